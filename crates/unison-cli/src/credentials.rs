@@ -0,0 +1,630 @@
+//! CLIの認証情報の保存
+//!
+//! プロファイルごとに接続先とトークンを保存します。トークンはOSのキーチェーン
+//! （macOSのキーチェーン、LinuxのSecret Service（`secret-tool`）、WindowsのCredential Manager）に保存し、
+//! 使えない環境では所有者のみ読み書きできる設定ディレクトリのファイルに保存します。
+//! 接続先などの秘密でない情報は`profiles.json`に保存します。
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// キーチェーンに登録するサービス名
+const SERVICE: &str = "unison-cli";
+const PROFILES_FILE: &str = "profiles.json";
+const CREDENTIALS_FILE: &str = "credentials.json";
+
+/// 設定ディレクトリを指定する環境変数
+pub const CONFIG_DIR_ENV: &str = "UNISON_CONFIG_DIR";
+
+#[derive(Debug, Error)]
+pub enum CredentialError {
+    #[error("Failed to access {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Invalid format in {path}: {source}")]
+    Format {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Keychain error: {0}")]
+    Keychain(String),
+    #[error("No configuration directory found (set {CONFIG_DIR_ENV})")]
+    NoConfigDir,
+}
+
+/// トークンの保存先
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Storage {
+    /// OSのキーチェーン
+    Keychain,
+    /// 設定ディレクトリのファイル
+    File,
+}
+
+impl Storage {
+    /// キーチェーンが使えればキーチェーン、使えなければファイル
+    pub fn preferred() -> Self {
+        if Keychain.available() {
+            Self::Keychain
+        } else {
+            Self::File
+        }
+    }
+}
+
+/// トークンを保存する場所の抽象
+pub trait SecretStore {
+    fn available(&self) -> bool;
+    fn get(&self, profile: &str) -> Result<Option<String>, CredentialError>;
+    fn set(&self, profile: &str, secret: &str) -> Result<(), CredentialError>;
+    /// 削除した場合は`true`
+    fn delete(&self, profile: &str) -> Result<bool, CredentialError>;
+}
+
+/// プロファイル（接続先とトークンの保存先）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub endpoint: String,
+    pub storage: Storage,
+}
+
+/// プロファイルとトークンの保存
+pub struct CredentialStore {
+    dir: PathBuf,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl CredentialStore {
+    /// 既定の設定ディレクトリを開く
+    pub fn open() -> Result<Self, CredentialError> {
+        Self::open_in(config_dir()?)
+    }
+
+    pub fn open_in(dir: impl Into<PathBuf>) -> Result<Self, CredentialError> {
+        let dir = dir.into();
+        let profiles = read_json(&dir.join(PROFILES_FILE))?.unwrap_or_default();
+        Ok(Self { dir, profiles })
+    }
+
+    /// プロファイルの接続先とトークンを保存（同じ名前のプロファイルは置き換える）
+    pub fn login(
+        &mut self,
+        name: &str,
+        endpoint: &str,
+        token: &str,
+        storage: Storage,
+    ) -> Result<(), CredentialError> {
+        let backend = self.backend(storage);
+        if !backend.available() {
+            return Err(CredentialError::Keychain(
+                "no keychain is available on this system".into(),
+            ));
+        }
+        backend.set(name, token)?;
+        if let Some(previous) = self.profiles.get(name)
+            && previous.storage != storage
+        {
+            // 保存先を変えた場合は以前のトークンを残さない
+            self.backend(previous.storage).delete(name)?;
+        }
+        self.profiles.insert(
+            name.to_string(),
+            Profile {
+                endpoint: endpoint.to_string(),
+                storage,
+            },
+        );
+        self.save()
+    }
+
+    /// プロファイルとトークンを削除（存在しない場合は`false`）
+    pub fn logout(&mut self, name: &str) -> Result<bool, CredentialError> {
+        let Some(profile) = self.profiles.remove(name) else {
+            return Ok(false);
+        };
+        self.backend(profile.storage).delete(name)?;
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn profiles(&self) -> impl Iterator<Item = (&str, &Profile)> {
+        self.profiles
+            .iter()
+            .map(|(name, profile)| (name.as_str(), profile))
+    }
+
+    /// プロファイルのトークン（プロファイルまたはトークンがない場合は`None`）
+    pub fn token(&self, name: &str) -> Result<Option<String>, CredentialError> {
+        match self.profiles.get(name) {
+            Some(profile) => self.backend(profile.storage).get(name),
+            None => Ok(None),
+        }
+    }
+
+    fn backend(&self, storage: Storage) -> Box<dyn SecretStore> {
+        match storage {
+            Storage::Keychain => Box::new(Keychain),
+            Storage::File => Box::new(FileSecrets {
+                path: self.dir.join(CREDENTIALS_FILE),
+            }),
+        }
+    }
+
+    fn save(&self) -> Result<(), CredentialError> {
+        write_json(&self.dir.join(PROFILES_FILE), &self.profiles)
+    }
+}
+
+/// OSのキーチェーン
+pub struct Keychain;
+
+impl SecretStore for Keychain {
+    fn available(&self) -> bool {
+        platform::available()
+    }
+
+    fn get(&self, profile: &str) -> Result<Option<String>, CredentialError> {
+        platform::get(profile)
+    }
+
+    fn set(&self, profile: &str, secret: &str) -> Result<(), CredentialError> {
+        platform::set(profile, secret)
+    }
+
+    fn delete(&self, profile: &str) -> Result<bool, CredentialError> {
+        platform::delete(profile)
+    }
+}
+
+/// 所有者のみ読み書きできるJSONファイル
+pub struct FileSecrets {
+    path: PathBuf,
+}
+
+impl FileSecrets {
+    fn load(&self) -> Result<BTreeMap<String, String>, CredentialError> {
+        Ok(read_json(&self.path)?.unwrap_or_default())
+    }
+}
+
+impl SecretStore for FileSecrets {
+    fn available(&self) -> bool {
+        true
+    }
+
+    fn get(&self, profile: &str) -> Result<Option<String>, CredentialError> {
+        Ok(self.load()?.remove(profile))
+    }
+
+    fn set(&self, profile: &str, secret: &str) -> Result<(), CredentialError> {
+        let mut secrets = self.load()?;
+        secrets.insert(profile.to_string(), secret.to_string());
+        write_json(&self.path, &secrets)
+    }
+
+    fn delete(&self, profile: &str) -> Result<bool, CredentialError> {
+        let mut secrets = self.load()?;
+        if secrets.remove(profile).is_none() {
+            return Ok(false);
+        }
+        write_json(&self.path, &secrets)?;
+        Ok(true)
+    }
+}
+
+/// 設定ディレクトリ（`UNISON_CONFIG_DIR`、`XDG_CONFIG_HOME/unison`、`~/.config/unison`、Windowsでは`%APPDATA%\unison`）
+pub fn config_dir() -> Result<PathBuf, CredentialError> {
+    let env = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    if let Some(dir) = env(CONFIG_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        env("APPDATA").map(PathBuf::from)
+    } else {
+        env("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("unison"))
+        .ok_or(CredentialError::NoConfigDir)
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> CredentialError + '_ {
+    move |source| CredentialError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, CredentialError> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(io_error(path)(e)),
+    };
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|source| CredentialError::Format {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// 所有者のみ読み書きできる権限で書き込む
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), CredentialError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(io_error(dir))?;
+    }
+    let data = serde_json::to_vec_pretty(value).expect("credentials are serializable");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // 以前から存在するファイルの権限も絞る
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .map_err(io_error(path))?;
+        }
+    }
+    let mut file = options.open(path).map_err(io_error(path))?;
+    io::Write::write_all(&mut file, &data).map_err(io_error(path))
+}
+
+/// 端末から入力を表示せずに1行読み込む
+pub fn read_hidden_line() -> io::Result<String> {
+    let _echo = platform::EchoGuard::disable();
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line)
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{CredentialError, SERVICE};
+    use security_framework::passwords;
+
+    pub use super::unix::EchoGuard;
+
+    /// `errSecItemNotFound`
+    const ITEM_NOT_FOUND: i32 = -25300;
+
+    pub fn available() -> bool {
+        true
+    }
+
+    pub fn get(profile: &str) -> Result<Option<String>, CredentialError> {
+        match passwords::get_generic_password(SERVICE, profile) {
+            Ok(secret) => String::from_utf8(secret)
+                .map(Some)
+                .map_err(|e| CredentialError::Keychain(e.to_string())),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(None),
+            Err(e) => Err(CredentialError::Keychain(e.to_string())),
+        }
+    }
+
+    pub fn set(profile: &str, secret: &str) -> Result<(), CredentialError> {
+        passwords::set_generic_password(SERVICE, profile, secret.as_bytes())
+            .map_err(|e| CredentialError::Keychain(e.to_string()))
+    }
+
+    pub fn delete(profile: &str) -> Result<bool, CredentialError> {
+        match passwords::delete_generic_password(SERVICE, profile) {
+            Ok(()) => Ok(true),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(false),
+            Err(e) => Err(CredentialError::Keychain(e.to_string())),
+        }
+    }
+}
+
+/// libsecretの`secret-tool`でSecret Service（GNOME Keyring・KWalletなど）に保存する
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use super::{CredentialError, SERVICE};
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    pub use super::unix::EchoGuard;
+
+    fn secret_tool(args: &[&str], input: Option<&str>) -> std::io::Result<Output> {
+        let mut child = Command::new("secret-tool")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // 秘密はコマンドライン引数ではなく標準入力で渡す
+        if let Some(mut stdin) = child.stdin.take()
+            && let Some(input) = input
+        {
+            stdin.write_all(input.as_bytes())?;
+        }
+        child.wait_with_output()
+    }
+
+    fn lookup(profile: &str) -> std::io::Result<Output> {
+        secret_tool(&["lookup", "service", SERVICE, "profile", profile], None)
+    }
+
+    fn failure(output: &Output) -> CredentialError {
+        CredentialError::Keychain(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+
+    pub fn available() -> bool {
+        // 見つからない場合は何も出力せずに失敗し、Secret Serviceに接続できない場合はエラーを出力する
+        lookup("").is_ok_and(|output| output.status.success() || output.stderr.is_empty())
+    }
+
+    pub fn get(profile: &str) -> Result<Option<String>, CredentialError> {
+        let output = lookup(profile).map_err(|e| CredentialError::Keychain(e.to_string()))?;
+        if output.status.success() {
+            return String::from_utf8(output.stdout)
+                .map(|secret| Some(secret.trim_end_matches('\n').to_string()))
+                .map_err(|e| CredentialError::Keychain(e.to_string()));
+        }
+        if output.stderr.is_empty() {
+            Ok(None)
+        } else {
+            Err(failure(&output))
+        }
+    }
+
+    pub fn set(profile: &str, secret: &str) -> Result<(), CredentialError> {
+        let label = format!("Unison CLI ({})", profile);
+        let output = secret_tool(
+            &[
+                "store", "--label", &label, "service", SERVICE, "profile", profile,
+            ],
+            Some(secret),
+        )
+        .map_err(|e| CredentialError::Keychain(e.to_string()))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(failure(&output))
+        }
+    }
+
+    pub fn delete(profile: &str) -> Result<bool, CredentialError> {
+        if get(profile)?.is_none() {
+            return Ok(false);
+        }
+        let output = secret_tool(&["clear", "service", SERVICE, "profile", profile], None)
+            .map_err(|e| CredentialError::Keychain(e.to_string()))?;
+        if output.status.success() {
+            Ok(true)
+        } else {
+            Err(failure(&output))
+        }
+    }
+}
+
+/// Credential Managerの汎用資格情報として保存する
+#[cfg(windows)]
+mod platform {
+    use super::{CredentialError, SERVICE};
+    use windows_sys::Win32::Foundation::{ERROR_NOT_FOUND, GetLastError};
+    use windows_sys::Win32::Security::Credentials::{
+        CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC, CREDENTIALW, CredDeleteW, CredFree,
+        CredReadW, CredWriteW,
+    };
+    use windows_sys::Win32::System::Console::{
+        CONSOLE_MODE, ENABLE_ECHO_INPUT, GetConsoleMode, GetStdHandle, STD_INPUT_HANDLE,
+        SetConsoleMode,
+    };
+
+    fn target(profile: &str) -> Vec<u16> {
+        format!("{}:{}", SERVICE, profile)
+            .encode_utf16()
+            .chain(Some(0))
+            .collect()
+    }
+
+    fn last_error(function: &str) -> CredentialError {
+        CredentialError::Keychain(format!("{} failed ({})", function, unsafe {
+            GetLastError()
+        }))
+    }
+
+    pub fn available() -> bool {
+        true
+    }
+
+    pub fn get(profile: &str) -> Result<Option<String>, CredentialError> {
+        let target = target(profile);
+        let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
+        if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+            if unsafe { GetLastError() } == ERROR_NOT_FOUND {
+                return Ok(None);
+            }
+            return Err(last_error("CredReadW"));
+        }
+        let secret = unsafe {
+            std::slice::from_raw_parts(
+                (*credential).CredentialBlob,
+                (*credential).CredentialBlobSize as usize,
+            )
+        }
+        .to_vec();
+        unsafe { CredFree(credential as *const _) };
+        String::from_utf8(secret)
+            .map(Some)
+            .map_err(|e| CredentialError::Keychain(e.to_string()))
+    }
+
+    pub fn set(profile: &str, secret: &str) -> Result<(), CredentialError> {
+        let mut target = target(profile);
+        let mut blob = secret.as_bytes().to_vec();
+        let credential = CREDENTIALW {
+            Type: CRED_TYPE_GENERIC,
+            TargetName: target.as_mut_ptr(),
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            ..Default::default()
+        };
+        if unsafe { CredWriteW(&credential, 0) } == 0 {
+            return Err(last_error("CredWriteW"));
+        }
+        Ok(())
+    }
+
+    pub fn delete(profile: &str) -> Result<bool, CredentialError> {
+        let target = target(profile);
+        if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+            if unsafe { GetLastError() } == ERROR_NOT_FOUND {
+                return Ok(false);
+            }
+            return Err(last_error("CredDeleteW"));
+        }
+        Ok(true)
+    }
+
+    /// コンソールの入力のエコーを止め、破棄時に元に戻す
+    pub struct EchoGuard(Option<CONSOLE_MODE>);
+
+    impl EchoGuard {
+        pub fn disable() -> Self {
+            let mut mode: CONSOLE_MODE = 0;
+            unsafe {
+                let handle = GetStdHandle(STD_INPUT_HANDLE);
+                if GetConsoleMode(handle, &mut mode) == 0
+                    || SetConsoleMode(handle, mode & !ENABLE_ECHO_INPUT) == 0
+                {
+                    return Self(None);
+                }
+            }
+            Self(Some(mode))
+        }
+    }
+
+    impl Drop for EchoGuard {
+        fn drop(&mut self) {
+            if let Some(mode) = self.0 {
+                unsafe { SetConsoleMode(GetStdHandle(STD_INPUT_HANDLE), mode) };
+            }
+        }
+    }
+}
+
+/// キーチェーンのない環境（ファイルのみ）
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use super::CredentialError;
+
+    pub fn available() -> bool {
+        false
+    }
+
+    pub fn get(_profile: &str) -> Result<Option<String>, CredentialError> {
+        Ok(None)
+    }
+
+    pub fn set(_profile: &str, _secret: &str) -> Result<(), CredentialError> {
+        Err(CredentialError::Keychain("not supported".into()))
+    }
+
+    pub fn delete(_profile: &str) -> Result<bool, CredentialError> {
+        Ok(false)
+    }
+
+    pub struct EchoGuard;
+
+    impl EchoGuard {
+        pub fn disable() -> Self {
+            Self
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::process::{Command, Stdio};
+
+    /// 端末の入力のエコーを`stty`で止め、破棄時に元に戻す
+    pub struct EchoGuard(bool);
+
+    impl EchoGuard {
+        pub fn disable() -> Self {
+            Self(stty("-echo"))
+        }
+    }
+
+    impl Drop for EchoGuard {
+        fn drop(&mut self) {
+            if self.0 {
+                stty("echo");
+            }
+        }
+    }
+
+    fn stty(arg: &str) -> bool {
+        Command::new("stty")
+            .arg(arg)
+            .stdin(Stdio::inherit())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_storage_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = CredentialStore::open_in(dir.path()).unwrap();
+        store
+            .login("default", "[::1]:8080", "secret-1", Storage::File)
+            .unwrap();
+        store
+            .login(
+                "staging",
+                "staging.example.com:443",
+                "secret-2",
+                Storage::File,
+            )
+            .unwrap();
+
+        // 開き直しても同じ内容を読み込める
+        let mut store = CredentialStore::open_in(dir.path()).unwrap();
+        let names: Vec<_> = store.profiles().map(|(name, _)| name).collect();
+        assert_eq!(names, ["default", "staging"]);
+        assert_eq!(store.token("staging").unwrap().as_deref(), Some("secret-2"));
+
+        // 秘密は接続先と別のファイルに保存する
+        let profiles = std::fs::read_to_string(dir.path().join(PROFILES_FILE)).unwrap();
+        assert!(profiles.contains("staging.example.com") && !profiles.contains("secret"));
+
+        store
+            .login("default", "[::1]:9090", "secret-3", Storage::File)
+            .unwrap();
+        assert_eq!(store.token("default").unwrap().as_deref(), Some("secret-3"));
+        assert!(store.logout("default").unwrap());
+        assert!(!store.logout("default").unwrap());
+        assert_eq!(store.token("default").unwrap(), None);
+        assert_eq!(store.token("staging").unwrap().as_deref(), Some("secret-2"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_credentials_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CREDENTIALS_FILE);
+        std::fs::write(&path, "{}").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let secrets = FileSecrets { path: path.clone() };
+        secrets.set("default", "secret").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(secrets.get("default").unwrap().as_deref(), Some("secret"));
+    }
+}
@@ -1,3 +1,208 @@
-fn main() {
-    println!("Hello, world!");
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use credentials::{CredentialStore, Storage};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use unison::codegen::{GraphFormat, SchemaGraph};
+use unison::parser::SchemaParser;
+
+mod credentials;
+
+/// Unison Protocolのコマンドラインツール
+#[derive(Parser)]
+#[command(name = "unison", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// KDLスキーマを標準のスタイルに整形
+    Fmt {
+        /// 整形するスキーマファイル
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// ファイルを書き換えず、整形が必要なファイルがあれば失敗する
+        #[arg(long)]
+        check: bool,
+    },
+    /// サービス・メソッド・メッセージ型の依存関係グラフを出力
+    Graph {
+        /// 対象のスキーマファイル
+        file: PathBuf,
+
+        /// 出力形式（mermaid / dot）
+        #[arg(long, default_value = "mermaid")]
+        format: GraphFormat,
+
+        /// 出力先（省略時は標準出力）
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// 接続先のトークンを保存
+    ///
+    /// トークンは標準入力（端末の場合は表示しないプロンプト）から読み込み、
+    /// OSのキーチェーン（使えない場合は設定ディレクトリのファイル）に保存します。
+    Login {
+        /// 接続先のアドレス
+        endpoint: String,
+
+        /// 保存するプロファイル名
+        #[arg(long, default_value = "default")]
+        profile: String,
+
+        /// キーチェーンを使わず設定ディレクトリのファイルに保存
+        #[arg(long)]
+        file: bool,
+    },
+    /// 保存したトークンを削除
+    Logout {
+        /// 削除するプロファイル名
+        #[arg(long, default_value = "default")]
+        profile: String,
+    },
+    /// 保存したプロファイルの一覧
+    Profiles,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Fmt { files, check } => fmt(&files, check),
+        Command::Graph {
+            file,
+            format,
+            output,
+        } => graph(&file, format, output.as_deref()),
+        Command::Login {
+            endpoint,
+            profile,
+            file,
+        } => login(&endpoint, &profile, file),
+        Command::Logout { profile } => logout(&profile),
+        Command::Profiles => profiles(),
+    }
+}
+
+fn login(endpoint: &str, profile: &str, file: bool) -> Result<()> {
+    let token = read_token()?;
+    let storage = if file {
+        Storage::File
+    } else {
+        Storage::preferred()
+    };
+    let mut store = CredentialStore::open()?;
+    store.login(profile, endpoint, &token, storage)?;
+    println!(
+        "Saved token for {} ({}) in the {}",
+        profile,
+        endpoint,
+        storage_name(storage)
+    );
+    Ok(())
+}
+
+/// トークンを標準入力から読み込む（端末の場合は入力を表示しない）
+fn read_token() -> Result<String> {
+    let stdin = std::io::stdin();
+    let token = if stdin.is_terminal() {
+        eprint!("Token: ");
+        std::io::stderr().flush()?;
+        let line = credentials::read_hidden_line()?;
+        eprintln!();
+        line
+    } else {
+        std::io::read_to_string(stdin)?
+    };
+    let token = token.trim();
+    if token.is_empty() {
+        anyhow::bail!("No token was given");
+    }
+    Ok(token.to_string())
+}
+
+fn logout(profile: &str) -> Result<()> {
+    let mut store = CredentialStore::open()?;
+    if !store.logout(profile)? {
+        anyhow::bail!("Profile {} is not logged in", profile);
+    }
+    println!("Removed token for {}", profile);
+    Ok(())
+}
+
+fn profiles() -> Result<()> {
+    let store = CredentialStore::open()?;
+    for (name, profile) in store.profiles() {
+        // トークン自体は表示しない
+        let token = match store.token(name) {
+            Ok(Some(_)) => "token stored",
+            Ok(None) => "token missing",
+            Err(_) => "token unavailable",
+        };
+        println!(
+            "{}\t{}\t{} ({})",
+            name,
+            profile.endpoint,
+            storage_name(profile.storage),
+            token
+        );
+    }
+    Ok(())
+}
+
+fn storage_name(storage: Storage) -> &'static str {
+    match storage {
+        Storage::Keychain => "keychain",
+        Storage::File => "credentials file",
+    }
+}
+
+fn graph(file: &Path, format: GraphFormat, output: Option<&Path>) -> Result<()> {
+    let source = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let schema = SchemaParser::new()
+        .parse(&source)
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+    let graph = SchemaGraph::from_schema(&schema);
+
+    let unused = graph.unused_types();
+    if !unused.is_empty() {
+        eprintln!("Unused types: {}", unused.join(", "));
+    }
+
+    let rendered = graph.render(format);
+    match output {
+        Some(path) => std::fs::write(path, rendered)
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn fmt(files: &[PathBuf], check: bool) -> Result<()> {
+    let mut unformatted = Vec::new();
+    for path in files {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let formatted = unison::parser::format_schema(&source)
+            .with_context(|| format!("Failed to format {}", path.display()))?;
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            unformatted.push(path.display().to_string());
+        } else {
+            std::fs::write(path, formatted)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("Formatted {}", path.display());
+        }
+    }
+
+    if !unformatted.is_empty() {
+        anyhow::bail!("Schema files need formatting: {}", unformatted.join(", "));
+    }
+    Ok(())
 }
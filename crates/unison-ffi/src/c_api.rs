@@ -0,0 +1,330 @@
+//! C ABI
+//!
+//! uniffiを使わない環境（C/C++・JNIの手書きバインディング等）向けの関数群です。
+//! 文字列はすべてNUL終端のUTF-8で、ペイロードとレスポンスはJSON文字列です。
+//!
+//! - 戻り値が`int32_t`の関数は成功で`0`、失敗で負のエラーコードを返します。
+//!   失敗時のメッセージは同じスレッドで[`unison_last_error`]から取得できます。
+//! - ライブラリが返した文字列は[`unison_string_free`]で解放します。
+//! - コールバックはライブラリ内部のスレッドから呼ばれます。
+//!   `user_data`はスレッドをまたいで使える必要があります。
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::ptr;
+use std::sync::Arc;
+
+use crate::client::{EventListener, FfiClient, StreamHandle, StreamListener};
+use crate::error::FfiError;
+
+/// クライアントのハンドル
+pub struct UnisonClientHandle {
+    client: Arc<FfiClient>,
+}
+
+/// ストリーミング呼び出しのハンドル
+pub struct UnisonStreamHandle {
+    handle: Arc<StreamHandle>,
+}
+
+/// イベントのコールバック（`user_data`, トピック, ペイロードのJSON）
+pub type UnisonEventCallback =
+    extern "C" fn(user_data: *mut c_void, topic: *const c_char, payload_json: *const c_char);
+
+/// ストリームのコールバック（`user_data`, 要素のJSON, エラーメッセージ）
+///
+/// 要素ごとに`item_json`付きで呼ばれ、終了時に`item_json`がNULLで1回呼ばれます。
+/// エラーで終了した場合は`error`にメッセージが、正常に終了した場合はNULLが渡されます。
+pub type UnisonStreamCallback =
+    extern "C" fn(user_data: *mut c_void, item_json: *const c_char, error: *const c_char);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: &FfiError) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(to_cstring(&error.to_string())));
+}
+
+/// 結果をエラーコードに変換し、失敗時はメッセージを記録
+fn report<T>(result: Result<T, FfiError>) -> Result<T, i32> {
+    result.map_err(|error| {
+        set_last_error(&error);
+        error.code()
+    })
+}
+
+/// 内部NULを含む文字列はその手前までを渡す
+fn to_cstring(value: &str) -> CString {
+    let end = value.find('\0').unwrap_or(value.len());
+    CString::new(&value[..end]).unwrap_or_default()
+}
+
+/// # Safety
+/// `value`はNULLか有効なNUL終端文字列であること
+unsafe fn read_str(value: *const c_char, name: &str) -> Result<String, FfiError> {
+    if value.is_null() {
+        return Err(FfiError::invalid_argument(format!("{} is null", name)));
+    }
+    unsafe { CStr::from_ptr(value) }
+        .to_str()
+        .map(str::to_string)
+        .map_err(|_| FfiError::invalid_argument(format!("{} is not valid UTF-8", name)))
+}
+
+/// # Safety
+/// `client`はNULLか[`unison_client_new`]が返した解放前のハンドルであること
+unsafe fn client_ref<'a>(client: *const UnisonClientHandle) -> Result<&'a FfiClient, FfiError> {
+    unsafe { client.as_ref() }
+        .map(|handle| handle.client.as_ref())
+        .ok_or_else(|| FfiError::invalid_argument("client is null"))
+}
+
+/// 呼び出し元の`user_data`とコールバックをスレッド間で受け渡す
+struct Callback<F> {
+    function: F,
+    user_data: *mut c_void,
+}
+
+// SAFETY: user_dataをスレッドをまたいで使えることは呼び出し元が保証する
+unsafe impl<F: Send> Send for Callback<F> {}
+unsafe impl<F: Sync> Sync for Callback<F> {}
+
+impl EventListener for Callback<UnisonEventCallback> {
+    fn on_event(&self, topic: String, payload_json: String) {
+        let topic = to_cstring(&topic);
+        let payload = to_cstring(&payload_json);
+        (self.function)(self.user_data, topic.as_ptr(), payload.as_ptr());
+    }
+}
+
+impl StreamListener for Callback<UnisonStreamCallback> {
+    fn on_item(&self, item_json: String) {
+        let item = to_cstring(&item_json);
+        (self.function)(self.user_data, item.as_ptr(), ptr::null());
+    }
+
+    fn on_error(&self, error: FfiError) {
+        let error = to_cstring(&error.to_string());
+        (self.function)(self.user_data, ptr::null(), error.as_ptr());
+    }
+
+    fn on_end(&self) {
+        (self.function)(self.user_data, ptr::null(), ptr::null());
+    }
+}
+
+/// 直前に失敗した呼び出しのエラーメッセージ（同じスレッドのみ、なければNULL）
+///
+/// 返した文字列は次に同じスレッドで呼び出しが失敗するまで有効で、解放は不要です。
+#[unsafe(no_mangle)]
+pub extern "C" fn unison_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// ライブラリが返した文字列を解放
+///
+/// # Safety
+/// `value`はNULLかこのライブラリが返した解放前の文字列であること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(unsafe { CString::from_raw(value) });
+    }
+}
+
+/// クライアントを作成（失敗時はNULL）
+#[unsafe(no_mangle)]
+pub extern "C" fn unison_client_new() -> *mut UnisonClientHandle {
+    match report(FfiClient::new()) {
+        Ok(client) => Box::into_raw(Box::new(UnisonClientHandle { client })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// クライアントを切断して解放
+///
+/// # Safety
+/// `client`はNULLか[`unison_client_new`]が返した解放前のハンドルであること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_client_free(client: *mut UnisonClientHandle) {
+    if !client.is_null() {
+        let handle = unsafe { Box::from_raw(client) };
+        let _ = handle.client.disconnect();
+    }
+}
+
+/// サーバーへ接続
+///
+/// # Safety
+/// `client`は有効なハンドル、`url`は有効なNUL終端文字列であること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_client_connect(
+    client: *const UnisonClientHandle,
+    url: *const c_char,
+) -> i32 {
+    let result = (|| unsafe { client_ref(client)?.connect(read_str(url, "url")?) })();
+    report(result).map_or_else(|code| code, |()| 0)
+}
+
+/// メソッドを呼び出し、レスポンスのJSONを`out_json`に書き込む
+///
+/// `out_json`の文字列は[`unison_string_free`]で解放します。
+///
+/// # Safety
+/// `client`は有効なハンドル、`method`・`payload_json`は有効なNUL終端文字列、
+/// `out_json`は書き込み可能なポインタであること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_client_call(
+    client: *const UnisonClientHandle,
+    method: *const c_char,
+    payload_json: *const c_char,
+    out_json: *mut *mut c_char,
+) -> i32 {
+    if out_json.is_null() {
+        return report::<()>(Err(FfiError::invalid_argument("out_json is null"))).unwrap_err();
+    }
+    let result = (|| unsafe {
+        client_ref(client)?.call(
+            read_str(method, "method")?,
+            read_str(payload_json, "payload_json")?,
+        )
+    })();
+    match report(result) {
+        Ok(response) => {
+            unsafe { *out_json = to_cstring(&response).into_raw() };
+            0
+        }
+        Err(code) => code,
+    }
+}
+
+/// トピックを購読し、購読IDを`out_subscription_id`に書き込む
+///
+/// # Safety
+/// `client`は有効なハンドル、`topic`は有効なNUL終端文字列、
+/// `out_subscription_id`は書き込み可能なポインタであること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_client_subscribe(
+    client: *const UnisonClientHandle,
+    topic: *const c_char,
+    callback: UnisonEventCallback,
+    user_data: *mut c_void,
+    out_subscription_id: *mut u64,
+) -> i32 {
+    let listener = Arc::new(Callback {
+        function: callback,
+        user_data,
+    });
+    let result =
+        (|| unsafe { client_ref(client)?.subscribe(read_str(topic, "topic")?, listener) })();
+    match report(result) {
+        Ok(id) => {
+            if let Some(out) = unsafe { out_subscription_id.as_mut() } {
+                *out = id;
+            }
+            0
+        }
+        Err(code) => code,
+    }
+}
+
+/// 購読を解除
+///
+/// # Safety
+/// `client`は有効なハンドルであること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_client_unsubscribe(
+    client: *const UnisonClientHandle,
+    subscription_id: u64,
+) -> i32 {
+    let result = unsafe { client_ref(client) }.and_then(|c| c.unsubscribe(subscription_id));
+    report(result).map_or_else(|code| code, |()| 0)
+}
+
+/// ストリーミング呼び出しを開始（失敗時はNULL）
+///
+/// 返したハンドルは[`unison_stream_free`]で解放します。
+///
+/// # Safety
+/// `client`は有効なハンドル、`method`・`payload_json`は有効なNUL終端文字列であること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_client_stream(
+    client: *const UnisonClientHandle,
+    method: *const c_char,
+    payload_json: *const c_char,
+    callback: UnisonStreamCallback,
+    user_data: *mut c_void,
+) -> *mut UnisonStreamHandle {
+    let listener = Arc::new(Callback {
+        function: callback,
+        user_data,
+    });
+    let result = (|| unsafe {
+        client_ref(client)?.stream(
+            read_str(method, "method")?,
+            read_str(payload_json, "payload_json")?,
+            listener,
+        )
+    })();
+    match report(result) {
+        Ok(handle) => Box::into_raw(Box::new(UnisonStreamHandle { handle })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// ストリームの受信を中止してハンドルを解放
+///
+/// # Safety
+/// `stream`はNULLか[`unison_client_stream`]が返した解放前のハンドルであること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unison_stream_free(stream: *mut UnisonStreamHandle) {
+    if !stream.is_null() {
+        unsafe { Box::from_raw(stream) }.handle.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(unison_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_invalid_arguments_set_last_error() {
+        let client = unison_client_new();
+        assert!(!client.is_null());
+
+        let mut out = ptr::null_mut();
+        let code = unsafe { unison_client_call(client, ptr::null(), c"{}".as_ptr(), &mut out) };
+        assert_eq!(code, -1);
+        assert!(out.is_null());
+        assert!(last_error().contains("method is null"));
+
+        let invalid = [0xffu8, 0];
+        let code = unsafe { unison_client_connect(client, invalid.as_ptr().cast()) };
+        assert_eq!(code, -1);
+        assert!(last_error().contains("not valid UTF-8"));
+
+        let code = unsafe { unison_client_unsubscribe(ptr::null(), 1) };
+        assert_eq!(code, -1);
+        assert!(last_error().contains("client is null"));
+
+        unsafe { unison_client_free(client) };
+    }
+
+    #[test]
+    fn test_strings_round_trip() {
+        let value = to_cstring("a\0b").into_raw();
+        assert_eq!(unsafe { CStr::from_ptr(value) }.to_str().unwrap(), "a");
+        unsafe {
+            unison_string_free(value);
+            unison_string_free(ptr::null_mut());
+        }
+    }
+}
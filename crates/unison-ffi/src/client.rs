@@ -0,0 +1,336 @@
+//! バインディングから使うクライアント
+//!
+//! [`ProtocolClient`]を専用のtokioランタイムと組み合わせ、
+//! 非同期ランタイムを持たない呼び出し側（Kotlin/Swift/C）から同期的に使えるようにします。
+//! イベントとストリームの各要素はリスナーのコールバックでランタイムのスレッドから通知されます。
+
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::warn;
+use unison::network::{ProtocolClientTrait, ProtocolMessage, SubscriptionId, TopicPattern};
+use unison::{ProtocolClient, UnisonClient};
+
+use crate::error::FfiError;
+
+/// 購読したトピックのイベントを受け取るリスナー
+#[uniffi::export(with_foreign)]
+pub trait EventListener: Send + Sync {
+    /// イベントを受信（`payload_json`はJSON文字列）
+    fn on_event(&self, topic: String, payload_json: String);
+}
+
+/// ストリーミング呼び出しの結果を受け取るリスナー
+///
+/// 終了時は`on_end`か`on_error`のどちらか一方が1回だけ呼ばれます。
+#[uniffi::export(with_foreign)]
+pub trait StreamListener: Send + Sync {
+    /// ストリームの要素を受信（`item_json`はJSON文字列）
+    fn on_item(&self, item_json: String);
+    /// ストリームがエラーで終了
+    fn on_error(&self, error: FfiError);
+    /// ストリームが正常に終了
+    fn on_end(&self);
+}
+
+/// 購読したトピックのパターンとリスナー
+struct Subscription {
+    pattern: TopicPattern,
+    listener: Arc<dyn EventListener>,
+}
+
+/// 購読中のトピックとリスナー
+#[derive(Default)]
+struct Subscriptions {
+    entries: Mutex<HashMap<SubscriptionId, Subscription>>,
+}
+
+impl Subscriptions {
+    fn insert(&self, id: SubscriptionId, pattern: TopicPattern, listener: Arc<dyn EventListener>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(id, Subscription { pattern, listener });
+    }
+
+    fn remove(&self, id: SubscriptionId) -> bool {
+        self.entries.lock().unwrap().remove(&id).is_some()
+    }
+
+    /// イベントのトピックに一致するリスナーへ通知
+    fn dispatch(&self, event: &ProtocolMessage) {
+        let listeners: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|subscription| subscription.pattern.matches(&event.method))
+            .map(|subscription| Arc::clone(&subscription.listener))
+            .collect();
+        if listeners.is_empty() {
+            return;
+        }
+        let payload = match event.payload_as_value() {
+            Ok(payload) => payload.to_string(),
+            Err(e) => {
+                warn!(
+                    "Dropping event with invalid payload on {}: {}",
+                    event.method, e
+                );
+                return;
+            }
+        };
+        for listener in listeners {
+            listener.on_event(event.method.clone(), payload.clone());
+        }
+    }
+}
+
+/// 実行中のストリーミング呼び出し
+#[derive(uniffi::Object)]
+pub struct StreamHandle {
+    task: JoinHandle<()>,
+}
+
+#[uniffi::export]
+impl StreamHandle {
+    /// ストリームの受信を中止（以降リスナーは呼ばれません）
+    pub fn cancel(&self) {
+        self.task.abort();
+    }
+
+    /// ストリームが終了したか
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+/// モバイルアプリ等に組み込むためのUnisonクライアント
+#[derive(uniffi::Object)]
+pub struct FfiClient {
+    runtime: Runtime,
+    inner: Arc<RwLock<ProtocolClient>>,
+    subscriptions: Arc<Subscriptions>,
+    /// 受信したイベントをリスナーへ振り分けるタスク
+    event_pump: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[uniffi::export]
+impl FfiClient {
+    /// 専用のランタイムを持つクライアントを作成
+    #[uniffi::constructor]
+    pub fn new() -> Result<Arc<Self>, FfiError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("unison-ffi")
+            .enable_all()
+            .build()
+            .map_err(|e| FfiError::Connection {
+                message: format!("Failed to start runtime: {}", e),
+            })?;
+        let client = ProtocolClient::new_default()?;
+        Ok(Arc::new(Self {
+            runtime,
+            inner: Arc::new(RwLock::new(client)),
+            subscriptions: Arc::default(),
+            event_pump: Mutex::new(None),
+        }))
+    }
+
+    /// サーバーへ接続
+    pub fn connect(&self, url: String) -> Result<(), FfiError> {
+        self.stop_event_pump();
+        let result = self
+            .runtime
+            .block_on(async { UnisonClient::connect(&mut *self.inner.write().await, &url).await });
+        self.start_event_pump();
+        Ok(result?)
+    }
+
+    /// サーバーから切断
+    pub fn disconnect(&self) -> Result<(), FfiError> {
+        self.stop_event_pump();
+        self.runtime
+            .block_on(async { UnisonClient::disconnect(&mut *self.inner.write().await).await })?;
+        Ok(())
+    }
+
+    /// 接続中か
+    pub fn is_connected(&self) -> bool {
+        self.runtime
+            .block_on(async { self.inner.read().await.is_connected().await })
+    }
+
+    /// JSON文字列のペイロードでメソッドを呼び出し、レスポンスをJSON文字列で返す
+    pub fn call(&self, method: String, payload_json: String) -> Result<String, FfiError> {
+        let payload = parse_payload(&payload_json)?;
+        let response = self.runtime.block_on(async {
+            UnisonClient::call(&*self.inner.read().await, &method, payload).await
+        })?;
+        Ok(response.to_string())
+    }
+
+    /// トピックを購読し、一致するイベントを`listener`へ通知
+    ///
+    /// `topic`にはワイルドカード（`*`・`#`）を含むパターンを指定できます。
+    pub fn subscribe(
+        &self,
+        topic: String,
+        listener: Arc<dyn EventListener>,
+    ) -> Result<u64, FfiError> {
+        let pattern =
+            TopicPattern::parse(&topic).map_err(|e| FfiError::invalid_argument(e.to_string()))?;
+        let id = self
+            .runtime
+            .block_on(async { self.inner.read().await.subscribe(&topic, None).await })?;
+        self.subscriptions.insert(id, pattern, listener);
+        Ok(id)
+    }
+
+    /// 購読を解除
+    pub fn unsubscribe(&self, subscription_id: u64) -> Result<(), FfiError> {
+        if !self.subscriptions.remove(subscription_id) {
+            return Err(FfiError::invalid_argument(format!(
+                "Unknown subscription: {}",
+                subscription_id
+            )));
+        }
+        self.runtime
+            .block_on(async { self.inner.read().await.unsubscribe(subscription_id).await })?;
+        Ok(())
+    }
+
+    /// ストリーミング呼び出しを開始し、各要素を`listener`へ通知
+    pub fn stream(
+        &self,
+        method: String,
+        payload_json: String,
+        listener: Arc<dyn StreamListener>,
+    ) -> Result<Arc<StreamHandle>, FfiError> {
+        let payload = parse_payload(&payload_json)?;
+        let mut stream = self.runtime.block_on(async {
+            ProtocolClientTrait::stream::<Value, Value>(&*self.inner.read().await, &method, payload)
+                .await
+        })?;
+        let task = self.runtime.spawn(async move {
+            while let Some(item) = stream.next().await {
+                match item {
+                    Ok(item) => listener.on_item(item.to_string()),
+                    Err(e) => {
+                        listener.on_error(e.into());
+                        return;
+                    }
+                }
+            }
+            listener.on_end();
+        });
+        Ok(Arc::new(StreamHandle { task }))
+    }
+}
+
+impl FfiClient {
+    fn start_event_pump(&self) {
+        let inner = Arc::clone(&self.inner);
+        let subscriptions = Arc::clone(&self.subscriptions);
+        let task = self.runtime.spawn(async move {
+            let client = inner.read().await;
+            while let Ok(event) = client.receive_event().await {
+                subscriptions.dispatch(&event);
+            }
+        });
+        if let Some(previous) = self.event_pump.lock().unwrap().replace(task) {
+            previous.abort();
+        }
+    }
+
+    /// イベントの振り分けを停止（接続の変更前に読み取りロックを解放するため）
+    fn stop_event_pump(&self) {
+        if let Some(task) = self.event_pump.lock().unwrap().take() {
+            task.abort();
+            let _ = self.runtime.block_on(task);
+        }
+    }
+}
+
+impl Drop for FfiClient {
+    fn drop(&mut self) {
+        if let Some(task) = self.event_pump.get_mut().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+fn parse_payload(payload_json: &str) -> Result<Value, FfiError> {
+    if payload_json.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(payload_json)
+        .map_err(|e| FfiError::invalid_argument(format!("Invalid JSON payload: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unison::network::MessageType;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<(String, String)>>,
+    }
+
+    impl EventListener for Recorder {
+        fn on_event(&self, topic: String, payload_json: String) {
+            self.events.lock().unwrap().push((topic, payload_json));
+        }
+    }
+
+    #[test]
+    fn test_dispatch_matches_topic_patterns() {
+        let subscriptions = Subscriptions::default();
+        let sensors = Arc::new(Recorder::default());
+        let alerts = Arc::new(Recorder::default());
+        subscriptions.insert(
+            1,
+            TopicPattern::parse("sensors.*").unwrap(),
+            sensors.clone(),
+        );
+        subscriptions.insert(2, TopicPattern::parse("alerts").unwrap(), alerts.clone());
+
+        let event = ProtocolMessage::new_with_json(
+            0,
+            "sensors.temp".into(),
+            MessageType::Event,
+            serde_json::json!({ "value": 21 }),
+        )
+        .unwrap();
+        subscriptions.dispatch(&event);
+        assert_eq!(
+            *sensors.events.lock().unwrap(),
+            vec![("sensors.temp".to_string(), r#"{"value":21}"#.to_string())]
+        );
+        assert!(alerts.events.lock().unwrap().is_empty());
+
+        assert!(subscriptions.remove(1));
+        subscriptions.dispatch(&event);
+        assert_eq!(sensors.events.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_call_requires_connection_and_valid_json() {
+        let client = FfiClient::new().unwrap();
+        assert!(!client.is_connected());
+        assert!(matches!(
+            client.call("echo".into(), "{not json".into()),
+            Err(FfiError::InvalidArgument { .. })
+        ));
+        assert!(client.call("echo".into(), "{}".into()).is_err());
+        assert!(matches!(
+            client.unsubscribe(42),
+            Err(FfiError::InvalidArgument { .. })
+        ));
+    }
+}
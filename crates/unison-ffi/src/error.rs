@@ -0,0 +1,73 @@
+//! バインディング境界で返すエラー
+
+use thiserror::Error;
+use unison::network::NetworkError;
+
+/// FFI呼び出しのエラー
+///
+/// uniffiではKotlin/Swiftの例外に、C ABIでは[`FfiError::code`]の戻り値と
+/// `unison_last_error`のメッセージに変換されます。
+#[derive(Debug, Error, uniffi::Error)]
+pub enum FfiError {
+    /// 引数が不正（NULL・不正なUTF-8・JSONとして解釈できない等）
+    #[error("Invalid argument: {message}")]
+    InvalidArgument { message: String },
+
+    /// サーバーへ接続していない
+    #[error("Not connected")]
+    NotConnected,
+
+    /// 接続の確立・維持に失敗
+    #[error("Connection error: {message}")]
+    Connection { message: String },
+
+    /// サーバーが呼び出しをエラーで応答した、または応答を解釈できない
+    #[error("Call failed: {message}")]
+    Call { message: String },
+}
+
+impl FfiError {
+    pub(crate) fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::InvalidArgument {
+            message: message.into(),
+        }
+    }
+
+    /// C ABIで返すエラーコード（成功は`0`）
+    pub fn code(&self) -> i32 {
+        match self {
+            FfiError::InvalidArgument { .. } => -1,
+            FfiError::NotConnected => -2,
+            FfiError::Connection { .. } => -3,
+            FfiError::Call { .. } => -4,
+        }
+    }
+}
+
+impl From<NetworkError> for FfiError {
+    fn from(error: NetworkError) -> Self {
+        match error {
+            NetworkError::NotConnected => FfiError::NotConnected,
+            NetworkError::Connection(_)
+            | NetworkError::Quic(_)
+            | NetworkError::Timeout
+            | NetworkError::UnsupportedTransport(_) => FfiError::Connection {
+                message: error.to_string(),
+            },
+            _ => FfiError::Call {
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+impl From<anyhow::Error> for FfiError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<NetworkError>() {
+            Ok(error) => error.into(),
+            Err(error) => FfiError::Call {
+                message: format!("{:#}", error),
+            },
+        }
+    }
+}
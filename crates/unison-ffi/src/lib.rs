@@ -0,0 +1,19 @@
+//! # Unison FFI
+//!
+//! モバイルアプリ（Android/iOS）等からUnisonプロトコルのクライアントを直接組み込むためのバインディング
+//!
+//! - uniffi: [`FfiClient`]・[`EventListener`]・[`StreamListener`]からKotlin/Swiftのバインディングを生成
+//! - C ABI: [`c_api`]の`unison_*`関数（`cdylib`・`staticlib`としてリンク）
+//!
+//! どちらも接続・呼び出し・購読・ストリーミングを提供し、ペイロードはJSON文字列で受け渡します。
+//! 非同期処理はクライアントごとの専用ランタイムで実行するため、呼び出し側にランタイムは不要です。
+//! 呼び出しは完了までブロックするので、UIスレッド以外から呼び出してください。
+
+pub mod c_api;
+pub mod client;
+pub mod error;
+
+pub use client::{EventListener, FfiClient, StreamHandle, StreamListener};
+pub use error::FfiError;
+
+uniffi::setup_scaffolding!();
@@ -0,0 +1,32 @@
+//! P2Pネットワーク層のエラー型
+
+use thiserror::Error;
+
+/// P2P通信で発生するエラー
+#[derive(Error, Debug)]
+pub enum P2pError {
+    #[error("QUIC error: {0}")]
+    Quic(String),
+    #[error("Rendezvous error: {0}")]
+    Rendezvous(String),
+    #[error("Peer not found: {peer_id}")]
+    PeerNotFound { peer_id: String },
+    #[error("Hole punching failed for peer: {peer_id}")]
+    HolePunchFailed { peer_id: String },
+    #[error("Relay error: {0}")]
+    Relay(String),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("No available network path")]
+    NoAvailablePath,
+    #[error("Frame error: {0}")]
+    Frame(String),
+    #[error("Timeout error")]
+    Timeout,
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
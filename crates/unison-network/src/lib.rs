@@ -1,3 +1,26 @@
+//! Unison Protocol P2Pネットワーク層
+//!
+//! NAT配下のクライアント同士が直接Unison接続を確立するための機能と、
+//! 直接接続できない場合のリレーフォールバック、複数インターフェースを束ねる
+//! マルチパス接続を提供します。
+
+pub mod error;
+pub mod multipath;
+pub mod nat;
+pub mod relay;
+
+pub use error::P2pError;
+pub use multipath::{
+    MultipathConfig, MultipathConnection, PathState, PathStats, SchedulerPolicy, select_path,
+};
+pub use nat::{
+    Candidate, CandidateKind, HolePunchConfig, HolePuncher, P2pEndpoint, PeerId, PeerIdentity,
+    PunchRole, RendezvousClient, RendezvousMessage, RendezvousServer,
+};
+pub use relay::{
+    PeerConnection, RelayChannel, RelayClient, RelayControl, RelayServer, connect_with_fallback,
+};
+
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
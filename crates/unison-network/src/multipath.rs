@@ -0,0 +1,705 @@
+//! マルチパス接続（実験的）
+//!
+//! 複数のローカルネットワークインターフェースからそれぞれQUIC接続を張り、
+//! 1つの論理的なUnison接続として束ねます。
+//!
+//! - パスごとにRTTや連続失敗回数を追跡し、状態（Active / Degraded / Failed）を管理
+//! - [`SchedulerPolicy::Failover`] は優先度順で最初の健全なパスのみを使用
+//! - [`SchedulerPolicy::Aggregate`] は処理中リクエスト数とRTTから負荷の低いパスへ分散し、
+//!   複数インターフェースの帯域を合算
+//!
+//! 送信前に失敗したリクエストは別のパスで再送されます。送信後の失敗は、
+//! [`MultipathConnection::call_idempotent`]で送った冪等なリクエストのみ再送します。
+//!
+//! サーバーの証明書は[`MultipathConfig::with_tls`]で指定した[`TlsConfig`]で検証します。
+//! 指定しない場合は[`QuicClient`]と同様に検証しません（開発用）。
+
+use quinn::{Connection, Endpoint};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use unison::network::quic::QuicClient;
+use unison::network::{
+    ProtocolMessage, StreamIdAllocator, StreamIdConfig, StreamInitiator, TlsConfig, read_frame,
+    write_frame,
+};
+
+use crate::error::P2pError;
+
+/// パスのスケジューリングポリシー
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchedulerPolicy {
+    /// 優先度順で最初の健全なパスを使用し、障害時のみ次のパスへ切り替える
+    #[default]
+    Failover,
+    /// 健全なパス全体に負荷を分散して帯域を合算する
+    Aggregate,
+}
+
+/// パスの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathState {
+    /// 正常
+    Active,
+    /// 直近で失敗が発生（しきい値未満）
+    Degraded,
+    /// 使用不可（再接続待ち）
+    Failed,
+}
+
+/// マルチパス接続の設定
+#[derive(Debug, Clone)]
+pub struct MultipathConfig {
+    /// 各パスでバインドするローカルアドレス（先頭ほど優先度が高い）
+    pub local_addrs: Vec<SocketAddr>,
+    /// スケジューリングポリシー
+    pub policy: SchedulerPolicy,
+    /// ヘルスチェックと再接続の間隔
+    pub health_check_interval: Duration,
+    /// パスをFailedとみなす連続失敗回数
+    pub failure_threshold: u32,
+    /// 全パスで使うTLSの設定（`None`の場合はサーバーの証明書を検証しない）
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for MultipathConfig {
+    fn default() -> Self {
+        Self {
+            local_addrs: vec!["[::]:0".parse().unwrap()],
+            policy: SchedulerPolicy::default(),
+            health_check_interval: Duration::from_secs(5),
+            failure_threshold: 3,
+            tls: None,
+        }
+    }
+}
+
+impl MultipathConfig {
+    pub fn new(local_addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            local_addrs,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_policy(mut self, policy: SchedulerPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
+
+    pub fn with_failure_threshold(mut self, threshold: u32) -> Self {
+        self.failure_threshold = threshold;
+        self
+    }
+
+    /// サーバーの証明書の検証方法・クライアント証明書・サーバー名を指定
+    ///
+    /// サーバー名を指定した場合は、[`MultipathConnection::connect`]に渡した名前より優先します。
+    pub fn with_tls(mut self, tls: impl Into<TlsConfig>) -> Self {
+        self.tls = Some(tls.into());
+        self
+    }
+}
+
+/// パスごとの統計情報
+#[derive(Debug, Clone)]
+pub struct PathStats {
+    pub local_addr: SocketAddr,
+    pub state: PathState,
+    pub rtt: Duration,
+    pub in_flight: usize,
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub consecutive_failures: u32,
+}
+
+impl PathStats {
+    fn new(local_addr: SocketAddr) -> Self {
+        Self {
+            local_addr,
+            state: PathState::Failed,
+            rtt: Duration::ZERO,
+            in_flight: 0,
+            messages_sent: 0,
+            bytes_sent: 0,
+            consecutive_failures: 0,
+        }
+    }
+
+    fn record_success(&mut self, bytes: usize) {
+        self.messages_sent += 1;
+        self.bytes_sent += bytes as u64;
+        self.consecutive_failures = 0;
+        self.state = PathState::Active;
+    }
+
+    fn record_failure(&mut self, failure_threshold: u32) {
+        self.consecutive_failures += 1;
+        self.state = if self.consecutive_failures >= failure_threshold {
+            PathState::Failed
+        } else {
+            PathState::Degraded
+        };
+    }
+}
+
+/// ポリシーに従って使用するパスのインデックスを選択
+///
+/// `excluded`に含まれるパスとFailedのパスは選択されません。
+pub fn select_path(
+    policy: SchedulerPolicy,
+    paths: &[PathStats],
+    excluded: &[usize],
+) -> Option<usize> {
+    let candidates = paths
+        .iter()
+        .enumerate()
+        .filter(|(i, p)| p.state != PathState::Failed && !excluded.contains(i));
+
+    match policy {
+        // Activeを優先し、同じ状態なら設定順
+        SchedulerPolicy::Failover => candidates
+            .min_by_key(|(i, p)| (p.state != PathState::Active, *i))
+            .map(|(i, _)| i),
+        // 処理中リクエスト数で重み付けしたRTTが最小のパス
+        SchedulerPolicy::Aggregate => candidates
+            .min_by_key(|(i, p)| {
+                let rtt = p.rtt.max(Duration::from_micros(1)).as_micros();
+                (
+                    p.state != PathState::Active,
+                    rtt * (p.in_flight as u128 + 1),
+                    *i,
+                )
+            })
+            .map(|(i, _)| i),
+    }
+}
+
+/// パスの処理中リクエストとして数える（Futureが破棄された場合も戻す）
+struct InFlight<'a> {
+    stats: &'a Mutex<PathStats>,
+}
+
+impl<'a> InFlight<'a> {
+    fn new(stats: &'a Mutex<PathStats>) -> Self {
+        stats.lock().unwrap().in_flight += 1;
+        Self { stats }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.stats.lock().unwrap().in_flight -= 1;
+    }
+}
+
+/// パス上でのリクエストの失敗
+enum RequestFailure {
+    /// リクエストを送信する前に失敗（別のパスで再送しても重複しない）
+    NotSent(P2pError),
+    /// リクエストの送信後に失敗（サーバーが処理した可能性がある）
+    Sent(P2pError),
+}
+
+/// パス上の接続と、その接続で開始するストリームのIDの割り当て
+#[derive(Clone)]
+struct PathSession {
+    connection: Connection,
+    stream_ids: Arc<StreamIdAllocator>,
+}
+
+/// 1本のネットワークパス
+struct Path {
+    endpoint: Endpoint,
+    session: Mutex<Option<PathSession>>,
+    stats: Mutex<PathStats>,
+}
+
+impl Path {
+    fn session(&self) -> Option<PathSession> {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|s| s.connection.close_reason().is_none())
+            .cloned()
+    }
+
+    fn connection(&self) -> Option<Connection> {
+        self.session().map(|s| s.connection)
+    }
+}
+
+/// 複数パスを束ねた論理的なUnison接続
+pub struct MultipathConnection {
+    server_addr: SocketAddr,
+    server_name: String,
+    config: MultipathConfig,
+    paths: Arc<Vec<Path>>,
+    monitor: JoinHandle<()>,
+}
+
+impl MultipathConnection {
+    /// 設定された各ローカルアドレスからサーバーへ接続
+    ///
+    /// 少なくとも1つのパスが確立できれば成功します。
+    pub async fn connect(
+        server_addr: SocketAddr,
+        server_name: &str,
+        config: MultipathConfig,
+    ) -> Result<Self, P2pError> {
+        if config.local_addrs.is_empty() {
+            return Err(P2pError::NoAvailablePath);
+        }
+
+        let server_name = config
+            .tls
+            .as_ref()
+            .and_then(TlsConfig::server_name)
+            .unwrap_or(server_name)
+            .to_string();
+        let client_config = match &config.tls {
+            Some(tls) => QuicClient::configure_client_with_tls(tls),
+            None => QuicClient::configure_client().await,
+        }
+        .map_err(|e| P2pError::Quic(e.to_string()))?;
+
+        let mut paths = Vec::with_capacity(config.local_addrs.len());
+        for local_addr in &config.local_addrs {
+            let mut endpoint = Endpoint::client(*local_addr)?;
+            endpoint.set_default_client_config(client_config.clone());
+            let bound_addr = endpoint.local_addr()?;
+
+            let path = Path {
+                endpoint,
+                session: Mutex::new(None),
+                stats: Mutex::new(PathStats::new(bound_addr)),
+            };
+            match connect_path(&path, server_addr, &server_name).await {
+                Ok(()) => info!("🛤️ Path {} -> {} established", bound_addr, server_addr),
+                Err(e) => warn!("Path {} -> {} failed: {}", bound_addr, server_addr, e),
+            }
+            paths.push(path);
+        }
+
+        let paths = Arc::new(paths);
+        let established = paths
+            .iter()
+            .any(|p| p.stats.lock().unwrap().state == PathState::Active);
+        if !established {
+            return Err(P2pError::NoAvailablePath);
+        }
+
+        let monitor = tokio::spawn(health_monitor(
+            Arc::clone(&paths),
+            server_addr,
+            server_name.clone(),
+            config.health_check_interval,
+        ));
+
+        Ok(Self {
+            server_addr,
+            server_name,
+            config,
+            paths,
+            monitor,
+        })
+    }
+
+    /// 接続先サーバーのアドレス
+    pub fn server_addr(&self) -> SocketAddr {
+        self.server_addr
+    }
+
+    /// 接続先サーバー名
+    pub fn server_name(&self) -> &str {
+        &self.server_name
+    }
+
+    /// 現在のスケジューリングポリシー
+    pub fn policy(&self) -> SchedulerPolicy {
+        self.config.policy
+    }
+
+    /// 全パスの統計情報
+    pub fn path_stats(&self) -> Vec<PathStats> {
+        self.paths
+            .iter()
+            .map(|p| p.stats.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// 使用可能なパスが1つ以上あるか
+    pub fn is_connected(&self) -> bool {
+        self.paths.iter().any(|p| p.connection().is_some())
+    }
+
+    /// リクエストを送信してレスポンスを受信
+    ///
+    /// 選択したパスで送信前に失敗した場合は、残りのパスで再送します。
+    /// 送信後に失敗した場合は、二重に処理されないよう再送せずにエラーを返します。
+    pub async fn call(&self, message: ProtocolMessage) -> Result<ProtocolMessage, P2pError> {
+        self.call_on_paths(message, false).await
+    }
+
+    /// 冪等なリクエストを送信してレスポンスを受信
+    ///
+    /// 送信後に失敗した場合も、残りのパスで再送します。
+    pub async fn call_idempotent(
+        &self,
+        message: ProtocolMessage,
+    ) -> Result<ProtocolMessage, P2pError> {
+        self.call_on_paths(message, true).await
+    }
+
+    async fn call_on_paths(
+        &self,
+        message: ProtocolMessage,
+        idempotent: bool,
+    ) -> Result<ProtocolMessage, P2pError> {
+        let frame_len = message
+            .clone()
+            .into_frame()
+            .map_err(|e| P2pError::Frame(e.to_string()))?
+            .to_bytes()
+            .len();
+
+        let mut tried = Vec::new();
+        let mut last_error = P2pError::NoAvailablePath;
+
+        loop {
+            let index = {
+                let snapshot = self.path_stats();
+                match select_path(self.config.policy, &snapshot, &tried) {
+                    Some(index) => index,
+                    None => return Err(last_error),
+                }
+            };
+            tried.push(index);
+
+            let path = &self.paths[index];
+            let Some(session) = path.session() else {
+                path.stats
+                    .lock()
+                    .unwrap()
+                    .record_failure(self.config.failure_threshold);
+                continue;
+            };
+
+            let result = {
+                let _in_flight = InFlight::new(&path.stats);
+                request_on(&session, &message).await
+            };
+            let mut stats = path.stats.lock().unwrap();
+
+            match result {
+                Ok(response) => {
+                    stats.record_success(frame_len);
+                    return Ok(response);
+                }
+                Err(RequestFailure::NotSent(e)) => {
+                    debug!("Request on path {} failed: {}", stats.local_addr, e);
+                    stats.record_failure(self.config.failure_threshold);
+                    last_error = e;
+                }
+                Err(RequestFailure::Sent(e)) => {
+                    debug!("Request on path {} failed: {}", stats.local_addr, e);
+                    stats.record_failure(self.config.failure_threshold);
+                    if !idempotent {
+                        return Err(e);
+                    }
+                    last_error = e;
+                }
+            }
+        }
+    }
+
+    /// 全パスを閉じる
+    pub fn close(&self) {
+        self.monitor.abort();
+        for path in self.paths.iter() {
+            if let Some(session) = path.session.lock().unwrap().take() {
+                session
+                    .connection
+                    .close(quinn::VarInt::from_u32(0), b"multipath close");
+            }
+            path.stats.lock().unwrap().state = PathState::Failed;
+        }
+    }
+}
+
+impl Drop for MultipathConnection {
+    fn drop(&mut self) {
+        self.monitor.abort();
+    }
+}
+
+async fn connect_path(
+    path: &Path,
+    server_addr: SocketAddr,
+    server_name: &str,
+) -> Result<(), P2pError> {
+    let connection = path
+        .endpoint
+        .connect(server_addr, server_name)
+        .map_err(|e| P2pError::Quic(e.to_string()))?
+        .await
+        .map_err(|e| P2pError::Quic(e.to_string()))?;
+
+    {
+        let mut stats = path.stats.lock().unwrap();
+        stats.rtt = connection.rtt();
+        stats.state = PathState::Active;
+        stats.consecutive_failures = 0;
+    }
+    // 接続ごとにエポックを決め直す（サーバーは接続ごとにIDを検証する）
+    let stream_ids = Arc::new(StreamIdConfig::default().allocator(StreamInitiator::Client));
+    *path.session.lock().unwrap() = Some(PathSession {
+        connection,
+        stream_ids,
+    });
+    Ok(())
+}
+
+async fn request_on(
+    session: &PathSession,
+    message: &ProtocolMessage,
+) -> Result<ProtocolMessage, RequestFailure> {
+    // サーバーはパスの接続ごとにIDを検証するため、パスの割り当てでIDを付け直す
+    let mut request = message.clone();
+    request.id = session
+        .stream_ids
+        .allocate()
+        .map_err(|e| RequestFailure::NotSent(P2pError::Quic(e.to_string())))?;
+
+    let (mut send, recv) = session
+        .connection
+        .open_bi()
+        .await
+        .map_err(|e| RequestFailure::NotSent(P2pError::Quic(e.to_string())))?;
+
+    // ストリームへ書き込み始めた後は、サーバーへ届いたものとして扱う
+    write_frame(&mut send, &request)
+        .await
+        .map_err(|e| RequestFailure::Sent(P2pError::Quic(e.to_string())))?;
+    send.finish()
+        .map_err(|e| RequestFailure::Sent(P2pError::Quic(e.to_string())))?;
+
+    let mut response: ProtocolMessage = read_frame(&mut BufReader::new(recv))
+        .await
+        .map_err(|e| RequestFailure::Sent(P2pError::Frame(e.to_string())))?
+        .ok_or_else(|| {
+            RequestFailure::Sent(P2pError::Frame(
+                "Stream closed without a response".to_string(),
+            ))
+        })?;
+    // 呼び出し側には元のIDで返す
+    response.id = message.id;
+    Ok(response)
+}
+
+/// 各パスのRTTを更新し、切断されたパスを再接続する
+async fn health_monitor(
+    paths: Arc<Vec<Path>>,
+    server_addr: SocketAddr,
+    server_name: String,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        for path in paths.iter() {
+            match path.connection() {
+                Some(connection) => {
+                    let mut stats = path.stats.lock().unwrap();
+                    stats.rtt = connection.rtt();
+                    // 接続が生きていれば再び試行対象に戻す
+                    if stats.state == PathState::Failed {
+                        stats.state = PathState::Degraded;
+                    }
+                }
+                None => {
+                    path.stats.lock().unwrap().state = PathState::Failed;
+                    if let Err(e) = connect_path(path, server_addr, &server_name).await {
+                        debug!("Path reconnection failed: {}", e);
+                    } else {
+                        info!(
+                            "🛤️ Path {} recovered",
+                            path.stats.lock().unwrap().local_addr
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(state: PathState, rtt_ms: u64, in_flight: usize) -> PathStats {
+        PathStats {
+            state,
+            rtt: Duration::from_millis(rtt_ms),
+            in_flight,
+            ..PathStats::new("[::1]:0".parse().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_failover_prefers_first_active_path() {
+        let paths = vec![
+            stats(PathState::Failed, 10, 0),
+            stats(PathState::Degraded, 10, 0),
+            stats(PathState::Active, 50, 3),
+        ];
+        assert_eq!(select_path(SchedulerPolicy::Failover, &paths, &[]), Some(2));
+        assert_eq!(
+            select_path(SchedulerPolicy::Failover, &paths, &[2]),
+            Some(1)
+        );
+        assert_eq!(
+            select_path(SchedulerPolicy::Failover, &paths, &[1, 2]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_aggregate_balances_by_load() {
+        let paths = vec![
+            stats(PathState::Active, 10, 4),
+            stats(PathState::Active, 20, 0),
+        ];
+        assert_eq!(
+            select_path(SchedulerPolicy::Aggregate, &paths, &[]),
+            Some(1)
+        );
+
+        let paths = vec![
+            stats(PathState::Active, 10, 0),
+            stats(PathState::Active, 20, 0),
+        ];
+        assert_eq!(
+            select_path(SchedulerPolicy::Aggregate, &paths, &[]),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_failure_threshold() {
+        let mut path = stats(PathState::Active, 10, 0);
+        path.record_failure(2);
+        assert_eq!(path.state, PathState::Degraded);
+        path.record_failure(2);
+        assert_eq!(path.state, PathState::Failed);
+        path.record_success(128);
+        assert_eq!(path.state, PathState::Active);
+        assert_eq!(path.consecutive_failures, 0);
+        assert_eq!(path.bytes_sent, 128);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_is_released_when_request_is_dropped() {
+        let path = Mutex::new(stats(PathState::Active, 10, 0));
+        let request = async {
+            let _in_flight = InFlight::new(&path);
+            std::future::pending::<()>().await
+        };
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), request)
+                .await
+                .is_err()
+        );
+        assert_eq!(path.lock().unwrap().in_flight, 0);
+    }
+
+    async fn spawn_echo_server(tls: Option<unison::network::TlsConfig>) -> SocketAddr {
+        use serde_json::Value;
+        use unison::network::{NetworkError, ProtocolServer, quic::QuicServer};
+
+        let server = ProtocolServer::new().with_call_handler("echo", |payload: Value| async move {
+            Ok::<_, NetworkError>(payload)
+        });
+        let mut quic_server = QuicServer::new(Arc::new(server));
+        if let Some(tls) = tls {
+            quic_server = quic_server.with_tls(tls);
+        }
+        quic_server.bind("[::1]:0").await.unwrap();
+        let addr = quic_server.local_addr().unwrap();
+        tokio::spawn(async move { quic_server.start().await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_call_over_length_prefixed_frames() {
+        use serde_json::json;
+        use unison::network::MessageType;
+
+        let addr = spawn_echo_server(None).await;
+        let config = MultipathConfig::new(vec!["[::1]:0".parse().unwrap()]);
+        let connection = MultipathConnection::connect(addr, "localhost", config)
+            .await
+            .unwrap();
+        for id in 1..=3 {
+            let request = ProtocolMessage::new_with_json(
+                id,
+                "echo".to_string(),
+                MessageType::Request,
+                json!({ "n": id }),
+            )
+            .unwrap();
+            let response = connection.call(request).await.unwrap();
+            assert_eq!(response.id, id);
+            assert_eq!(response.payload_as_value().unwrap(), json!({ "n": id }));
+        }
+
+        let stats = &connection.path_stats()[0];
+        assert_eq!(stats.messages_sent, 3);
+        assert_eq!(stats.in_flight, 0);
+        connection.close();
+    }
+
+    #[tokio::test]
+    async fn test_paths_verify_server_with_tls_config() {
+        use rustls::pki_types::PrivateKeyDer;
+        use unison::network::{CertificatePin, ClientTlsConfig};
+
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert = generated.cert.der().clone();
+        let key = PrivateKeyDer::try_from(generated.key_pair.serialize_der()).unwrap();
+        let server_tls = TlsConfig::new()
+            .with_identity(vec![cert.clone()], key)
+            .unwrap();
+        let addr = spawn_echo_server(Some(server_tls)).await;
+
+        let config = MultipathConfig::new(vec!["[::1]:0".parse().unwrap()]).with_tls(
+            ClientTlsConfig::pinned([CertificatePin::certificate(&cert)]),
+        );
+        let connection = MultipathConnection::connect(addr, "localhost", config)
+            .await
+            .unwrap();
+        connection.close();
+
+        // 別の証明書のピンでは経路を確立できない
+        let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let config = MultipathConfig::new(vec!["[::1]:0".parse().unwrap()]).with_tls(
+            ClientTlsConfig::pinned([CertificatePin::certificate(other.cert.der())]),
+        );
+        assert!(matches!(
+            MultipathConnection::connect(addr, "localhost", config).await,
+            Err(P2pError::NoAvailablePath)
+        ));
+    }
+}
@@ -0,0 +1,1215 @@
+//! NATトラバーサル（ホールパンチング）ヘルパー
+//!
+//! P2Pモードで、NAT配下にある2つのクライアントが公開ランデブーサーバーの仲介により
+//! 直接Unison接続を確立するための仕組みを提供します。
+//!
+//! 1. 各ピアは[`P2pEndpoint`]を作成し、同じUDPソケットから[`RendezvousClient`]で
+//!    ランデブーサーバーに登録します。サーバーは観測した公開アドレス
+//!    （server-reflexive候補）を返します
+//! 2. 接続したい側が[`RendezvousClient::request_peer`]を呼ぶと、サーバーは双方に
+//!    相手の候補アドレスを通知します
+//! 3. 双方が[`HolePuncher`]で同時オープンを行い、QUICの接続確立パケットで
+//!    NATのマッピングを開けてから直接接続を確立します
+//!
+//! 同時オープンでは双方向に接続が成立し得るため、ピアIDの大小で
+//! 接続を張る側（[`PunchRole::Initiator`]）と受ける側（[`PunchRole::Responder`]）を決めます。
+//!
+//! 各ピアは[`PeerIdentity`]（自己署名の証明書と秘密鍵）を持ち、直接接続では双方が証明書を提示します。
+//! [`HolePuncher::punch`]には相手の[`PeerIdentity::pin`]を渡し、一致しない相手との接続は確立しません。
+//! ピンはランデブーサーバーを経由せず、アプリケーションの信頼できる経路で交換してください。
+//!
+//! ピアIDは登録した接続に結び付けられ、他の接続からは同じIDで登録できません。
+//! 再接続したクライアントが登録を引き継ぐには、
+//! [`RendezvousClient::connect_with_credential`]で同じ資格情報を添えて登録します。
+
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use quinn::{ClientConfig, Connection, Endpoint};
+use ring::digest;
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair};
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{DigitallySignedStruct, DistinguishedName, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use unison::network::quic::{QuicClient, QuicServer};
+use unison::network::{CertificatePin, TlsConfig, Verification};
+
+use crate::error::P2pError;
+
+/// ランデブーメッセージの最大サイズ（64KB）
+const MAX_RENDEZVOUS_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// QUIC接続時に使用するサーバー名（開発用証明書のSAN）
+pub(crate) const P2P_SERVER_NAME: &str = "localhost";
+
+/// ピア識別子
+pub type PeerId = String;
+
+/// 候補アドレスの種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateKind {
+    /// ローカルインターフェースのアドレス
+    Host,
+    /// ランデブーサーバーから観測された公開アドレス
+    ServerReflexive,
+}
+
+/// 接続候補アドレス
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Candidate {
+    pub addr: SocketAddr,
+    pub kind: CandidateKind,
+}
+
+impl Candidate {
+    /// ホスト候補を作成
+    pub fn host(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            kind: CandidateKind::Host,
+        }
+    }
+
+    /// server-reflexive候補を作成
+    pub fn server_reflexive(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            kind: CandidateKind::ServerReflexive,
+        }
+    }
+
+    /// ICEに倣った候補の優先度（大きいほど優先）
+    ///
+    /// 同一ネットワーク内で直接届くホスト候補を優先し、IPv6をIPv4より優先します。
+    pub fn priority(&self) -> u32 {
+        let type_preference: u32 = match self.kind {
+            CandidateKind::Host => 126,
+            CandidateKind::ServerReflexive => 100,
+        };
+        let local_preference: u32 = if self.addr.is_ipv6() { 65535 } else { 32767 };
+        (type_preference << 24) | (local_preference << 8)
+    }
+}
+
+/// 優先度の高い順に候補を並べ替え、重複を除去
+pub fn sort_candidates(candidates: &mut Vec<Candidate>) {
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.priority()));
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|c| seen.insert(c.addr));
+}
+
+/// ランデブーサーバーとピア間でやり取りするメッセージ
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RendezvousMessage {
+    /// ピアの登録要求
+    Register {
+        peer_id: PeerId,
+        local_addrs: Vec<SocketAddr>,
+        /// 再接続時に登録を引き継ぐための資格情報
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        credential: Option<String>,
+    },
+    /// 登録完了（サーバーから観測された公開アドレスを含む）
+    Registered { observed_addr: SocketAddr },
+    /// 指定ピアへの接続仲介要求
+    ConnectRequest { target: PeerId },
+    /// 相手ピアの候補アドレス通知
+    PeerCandidates {
+        peer_id: PeerId,
+        candidates: Vec<Candidate>,
+    },
+    /// 指定ピアが登録されていない
+    NotFound { peer_id: PeerId },
+    /// エラー応答
+    Error { message: String },
+}
+
+impl RendezvousMessage {
+    fn to_bytes(&self) -> Result<Vec<u8>, P2pError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self, P2pError> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// ホールパンチングにおける役割
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PunchRole {
+    /// 自ら接続を確立する側
+    Initiator,
+    /// パンチング用パケットを送りつつ、相手からの接続を受け入れる側
+    Responder,
+}
+
+impl PunchRole {
+    /// ピアIDの大小から役割を決定（双方で同じ結論になる）
+    pub fn for_peers(local: &str, remote: &str) -> Self {
+        if local < remote {
+            Self::Initiator
+        } else {
+            Self::Responder
+        }
+    }
+}
+
+/// P-256公開鍵（非圧縮形式）のSubjectPublicKeyInfoの接頭辞
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+/// P2P接続で自身を証明する自己署名の証明書と秘密鍵（ECDSA P-256）
+#[derive(Clone)]
+pub struct PeerIdentity {
+    certificate: CertificateDer<'static>,
+    private_key: Arc<Vec<u8>>,
+    signing_key: Arc<EcdsaKeyPair>,
+}
+
+impl PeerIdentity {
+    /// 新しい鍵で証明書を生成
+    pub fn generate() -> Result<Self, P2pError> {
+        let generated = rcgen::generate_simple_self_signed(vec![P2P_SERVER_NAME.to_string()])
+            .map_err(|e| P2pError::Tls(e.to_string()))?;
+        Self::from_der(
+            generated.cert.der().clone(),
+            generated.key_pair.serialize_der(),
+        )
+    }
+
+    /// 証明書（DER）とPKCS#8の秘密鍵から作成
+    pub fn from_der(
+        certificate: CertificateDer<'static>,
+        private_key: Vec<u8>,
+    ) -> Result<Self, P2pError> {
+        let signing_key = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING,
+            &private_key,
+            &SystemRandom::new(),
+        )
+        .map_err(|e| P2pError::Tls(format!("Unsupported peer key: {}", e)))?;
+        let identity = Self {
+            certificate,
+            private_key: Arc::new(private_key),
+            signing_key: Arc::new(signing_key),
+        };
+        if !identity.pin().matches(&identity.certificate) {
+            return Err(P2pError::Tls(
+                "Certificate does not match the private key".to_string(),
+            ));
+        }
+        Ok(identity)
+    }
+
+    pub fn certificate(&self) -> &CertificateDer<'static> {
+        &self.certificate
+    }
+
+    /// 相手に渡して検証してもらう公開鍵のピン
+    pub fn pin(&self) -> CertificatePin {
+        CertificatePin::PublicKey(public_key_hash(self.public_key()))
+    }
+
+    /// 公開鍵（非圧縮形式のP-256の点）
+    pub(crate) fn public_key(&self) -> &[u8] {
+        self.signing_key.public_key().as_ref()
+    }
+
+    /// 秘密鍵で署名（ASN.1 DER形式）
+    pub(crate) fn sign(&self, message: &[u8]) -> Result<Vec<u8>, P2pError> {
+        self.signing_key
+            .sign(&SystemRandom::new(), message)
+            .map(|signature| signature.as_ref().to_vec())
+            .map_err(|_| P2pError::Encryption("Failed to sign".to_string()))
+    }
+
+    /// 相手の証明書をピンで検証し、自身の証明書を提示するクライアント設定
+    fn client_config(&self, remote: &CertificatePin) -> Result<ClientConfig, P2pError> {
+        let key = PrivateKeyDer::try_from(self.private_key.to_vec())
+            .map_err(|e| P2pError::Tls(e.to_string()))?;
+        let tls = TlsConfig::new()
+            .with_identity(vec![self.certificate.clone()], key)
+            .map_err(|e| P2pError::Tls(e.to_string()))?
+            .with_verification(Verification::Pinned)
+            .with_pinned_certificate(*remote);
+        let mut client_config = QuicClient::configure_client_with_tls(&tls)
+            .map_err(|e| P2pError::Tls(e.to_string()))?;
+        client_config.transport_config(Arc::new(p2p_transport_config()));
+        Ok(client_config)
+    }
+
+    /// 自身の証明書を提示し、相手にも証明書を要求するサーバー設定
+    fn server_config(&self) -> Result<quinn::ServerConfig, P2pError> {
+        let key = PrivateKeyDer::try_from(self.private_key.to_vec())
+            .map_err(|e| P2pError::Tls(e.to_string()))?;
+        let crypto = rustls::ServerConfig::builder()
+            .with_client_cert_verifier(Arc::new(PeerCertVerifier::new()))
+            .with_single_cert(vec![self.certificate.clone()], key)
+            .map_err(|e| P2pError::Tls(e.to_string()))?;
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(crypto)
+            .map_err(|e| P2pError::Tls(e.to_string()))?;
+        Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+    }
+}
+
+impl std::fmt::Debug for PeerIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PeerIdentity")
+            .field("pin", &self.pin().to_string())
+            .finish()
+    }
+}
+
+fn public_key_hash(public_key: &[u8]) -> [u8; 32] {
+    let mut spki = P256_SPKI_PREFIX.to_vec();
+    spki.extend_from_slice(public_key);
+    digest::digest(&digest::SHA256, &spki)
+        .as_ref()
+        .try_into()
+        .expect("SHA-256 digest is 32 bytes")
+}
+
+/// 公開鍵（非圧縮形式のP-256の点）がピンと一致するか
+///
+/// 証明書を受け取らない経路で使うため、公開鍵のピン（[`CertificatePin::PublicKey`]）のみ照合できます。
+pub(crate) fn pin_matches_public_key(pin: &CertificatePin, public_key: &[u8]) -> bool {
+    match pin {
+        CertificatePin::PublicKey(hash) => public_key_hash(public_key) == *hash,
+        CertificatePin::Certificate(_) => false,
+    }
+}
+
+/// 接続してきたピアに証明書を要求する検証器
+///
+/// 誰が接続してよいかは接続を受け入れた後にピンで照合するため、ここでは
+/// 証明書の提示とハンドシェイクの署名（秘密鍵を持っていること）のみを検証します。
+#[derive(Debug)]
+struct PeerCertVerifier {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl PeerCertVerifier {
+    fn new() -> Self {
+        Self {
+            algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ClientCertVerifier for PeerCertVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// 接続相手が提示した証明書がピンと一致するか
+fn peer_matches(connection: &Connection, pin: &CertificatePin) -> bool {
+    connection
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+        .is_some_and(|certs| certs.first().is_some_and(|cert| pin.matches(cert)))
+}
+
+/// P2P用QUICエンドポイントの作成
+///
+/// 同じUDPソケットでランデブー通信とホールパンチングを行う必要があるため、
+/// サーバー設定とクライアント設定の両方を持つエンドポイントを作成します。
+/// 直接接続を受ける際は`identity`の証明書を提示し、相手にも証明書を要求します。
+/// 既定のクライアント設定はランデブー・リレーサーバーへの接続に使います。
+pub struct P2pEndpoint;
+
+impl P2pEndpoint {
+    pub async fn bind(addr: SocketAddr, identity: &PeerIdentity) -> Result<Endpoint, P2pError> {
+        let transport = Arc::new(p2p_transport_config());
+        let mut server_config = identity.server_config()?;
+        server_config.transport_config(Arc::clone(&transport));
+        let mut client_config = QuicClient::configure_client()
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        client_config.transport_config(transport);
+
+        let mut endpoint = Endpoint::server(server_config, addr)?;
+        endpoint.set_default_client_config(client_config);
+        Ok(endpoint)
+    }
+}
+
+/// P2P用のトランスポート設定
+///
+/// ランデブーサーバーからの仲介通知は単方向ストリームで届くため、
+/// 受信側で単方向ストリームを許可します。
+fn p2p_transport_config() -> quinn::TransportConfig {
+    let mut transport_config = quinn::TransportConfig::default();
+    transport_config.max_idle_timeout(Some(Duration::from_secs(60).try_into().unwrap()));
+    transport_config.keep_alive_interval(Some(Duration::from_secs(10)));
+    transport_config.max_concurrent_uni_streams(100u32.into());
+    transport_config.max_concurrent_bidi_streams(1000u32.into());
+    transport_config.initial_rtt(Duration::from_millis(100));
+    transport_config
+}
+
+/// 登録済みピアの情報
+struct RegisteredPeer {
+    connection: Connection,
+    candidates: Vec<Candidate>,
+    /// 登録時の資格情報のハッシュ
+    credential: Option<Vec<u8>>,
+}
+
+impl RegisteredPeer {
+    /// 別の接続からの登録で置き換えてよいか（資格情報が一致する場合のみ）
+    fn accepts_reregister(&self, credential: Option<&[u8]>) -> bool {
+        self.credential.is_some() && self.credential.as_deref() == credential
+    }
+}
+
+/// 資格情報のハッシュ（平文のまま保持しない）
+pub(crate) fn credential_hash(credential: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, credential.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+/// 公開ランデブーサーバー
+///
+/// ピアの登録を受け付け、接続要求に応じて双方へ相手の候補アドレスを通知します。
+pub struct RendezvousServer {
+    endpoint: Endpoint,
+    peers: Arc<RwLock<HashMap<PeerId, RegisteredPeer>>>,
+}
+
+impl RendezvousServer {
+    /// 指定アドレスでランデブーサーバーをバインド
+    pub async fn bind(addr: SocketAddr) -> Result<Self, P2pError> {
+        let server_config = QuicServer::configure_server()
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        let endpoint = Endpoint::server(server_config, addr)?;
+        info!("🤝 Rendezvous server bound to {}", addr);
+
+        Ok(Self {
+            endpoint,
+            peers: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// バインドされたローカルアドレス
+    pub fn local_addr(&self) -> Result<SocketAddr, P2pError> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// 登録済みピアの一覧
+    pub async fn registered_peers(&self) -> Vec<PeerId> {
+        self.peers.read().await.keys().cloned().collect()
+    }
+
+    /// 接続の受け付けを開始（エンドポイントが閉じられるまで戻りません）
+    pub async fn run(&self) -> Result<(), P2pError> {
+        while let Some(incoming) = self.endpoint.accept().await {
+            let peers = Arc::clone(&self.peers);
+            tokio::spawn(async move {
+                match incoming.await {
+                    Ok(connection) => handle_rendezvous_connection(connection, peers).await,
+                    Err(e) => warn!("Failed to accept rendezvous connection: {}", e),
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// サーバーを停止
+    pub fn close(&self) {
+        self.endpoint
+            .close(quinn::VarInt::from_u32(0), b"rendezvous shutdown");
+    }
+}
+
+async fn handle_rendezvous_connection(
+    connection: Connection,
+    peers: Arc<RwLock<HashMap<PeerId, RegisteredPeer>>>,
+) {
+    let observed_addr = connection.remote_address();
+    let mut registered_as: Option<PeerId> = None;
+
+    // ストリーム単位のエラーでは接続の処理を終えず、切断時の登録解除を必ず行う
+    loop {
+        let (mut send, mut recv) = match connection.accept_bi().await {
+            Ok(streams) => streams,
+            Err(_) => break,
+        };
+
+        let response = match recv.read_to_end(MAX_RENDEZVOUS_MESSAGE_SIZE).await {
+            Ok(data) => {
+                handle_rendezvous_message(&data, &connection, &peers, &mut registered_as).await
+            }
+            Err(e) => RendezvousMessage::Error {
+                message: e.to_string(),
+            },
+        };
+
+        let written = match response.to_bytes() {
+            Ok(bytes) => send.write_all(&bytes).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match written {
+            Ok(()) => {
+                let _ = send.finish();
+            }
+            Err(e) => debug!(
+                "Failed to write rendezvous response to {}: {}",
+                observed_addr, e
+            ),
+        }
+    }
+
+    // 切断されたピアを登録解除（同じIDで再登録されている場合は残す）
+    if let Some(peer_id) = registered_as {
+        let mut peers = peers.write().await;
+        if peers
+            .get(&peer_id)
+            .is_some_and(|p| p.connection.stable_id() == connection.stable_id())
+        {
+            peers.remove(&peer_id);
+            debug!("Peer '{}' unregistered", peer_id);
+        }
+    }
+}
+
+/// 1つのランデブー要求を処理し、応答を返す
+async fn handle_rendezvous_message(
+    data: &[u8],
+    connection: &Connection,
+    peers: &RwLock<HashMap<PeerId, RegisteredPeer>>,
+    registered_as: &mut Option<PeerId>,
+) -> RendezvousMessage {
+    let observed_addr = connection.remote_address();
+    match RendezvousMessage::from_bytes(data) {
+        Ok(RendezvousMessage::Register {
+            peer_id,
+            local_addrs,
+            credential,
+        }) => {
+            let credential = credential.as_deref().map(credential_hash);
+            let mut candidates: Vec<Candidate> =
+                local_addrs.into_iter().map(Candidate::host).collect();
+            candidates.push(Candidate::server_reflexive(observed_addr));
+            sort_candidates(&mut candidates);
+
+            let mut peers = peers.write().await;
+            let accepted = match peers.get(&peer_id) {
+                // 同じ接続からの登録し直しは候補の更新
+                Some(existing) => {
+                    existing.connection.stable_id() == connection.stable_id()
+                        || existing.accepts_reregister(credential.as_deref())
+                }
+                None => true,
+            };
+            if !accepted {
+                warn!(
+                    "Rejected registration of '{}' from {}",
+                    peer_id, observed_addr
+                );
+                return RendezvousMessage::Error {
+                    message: format!("Peer already registered: {}", peer_id),
+                };
+            }
+
+            if let Some(existing) = peers.get(&peer_id)
+                && existing.connection.stable_id() != connection.stable_id()
+            {
+                // 再接続したクライアントの登録で古い接続を置き換える
+                existing
+                    .connection
+                    .close(quinn::VarInt::from_u32(0), b"rendezvous peer re-registered");
+            }
+            // 別のIDで登録し直した場合は以前の登録を解除
+            if let Some(previous) = registered_as.take().filter(|id| *id != peer_id) {
+                peers.remove(&previous);
+            }
+            debug!("Peer '{}' registered from {}", peer_id, observed_addr);
+            peers.insert(
+                peer_id.clone(),
+                RegisteredPeer {
+                    connection: connection.clone(),
+                    candidates,
+                    credential,
+                },
+            );
+            *registered_as = Some(peer_id);
+            RendezvousMessage::Registered { observed_addr }
+        }
+        Ok(RendezvousMessage::ConnectRequest { target }) => match registered_as {
+            Some(from) => introduce_peers(peers, from, &target).await,
+            None => RendezvousMessage::Error {
+                message: "Peer is not registered".to_string(),
+            },
+        },
+        Ok(other) => RendezvousMessage::Error {
+            message: format!("Unexpected rendezvous message: {:?}", other),
+        },
+        Err(e) => RendezvousMessage::Error {
+            message: e.to_string(),
+        },
+    }
+}
+
+/// 要求元へ接続先の候補を返し、接続先へ要求元の候補を通知
+async fn introduce_peers(
+    peers: &RwLock<HashMap<PeerId, RegisteredPeer>>,
+    from: &str,
+    target: &str,
+) -> RendezvousMessage {
+    let peers = peers.read().await;
+    let Some(source) = peers.get(from) else {
+        return RendezvousMessage::Error {
+            message: "Peer is not registered".to_string(),
+        };
+    };
+    let Some(destination) = peers.get(target) else {
+        return RendezvousMessage::NotFound {
+            peer_id: target.to_string(),
+        };
+    };
+
+    let notification = RendezvousMessage::PeerCandidates {
+        peer_id: from.to_string(),
+        candidates: source.candidates.clone(),
+    };
+    let target_connection = destination.connection.clone();
+    tokio::spawn(async move {
+        if let Err(e) = send_notification(&target_connection, &notification).await {
+            warn!("Failed to notify peer: {}", e);
+        }
+    });
+
+    RendezvousMessage::PeerCandidates {
+        peer_id: target.to_string(),
+        candidates: destination.candidates.clone(),
+    }
+}
+
+async fn send_notification(
+    connection: &Connection,
+    message: &RendezvousMessage,
+) -> Result<(), P2pError> {
+    let mut send = connection
+        .open_uni()
+        .await
+        .map_err(|e| P2pError::Quic(e.to_string()))?;
+    send.write_all(&message.to_bytes()?)
+        .await
+        .map_err(|e| P2pError::Quic(e.to_string()))?;
+    send.finish().map_err(|e| P2pError::Quic(e.to_string()))?;
+    Ok(())
+}
+
+/// ランデブーサーバーのクライアント
+pub struct RendezvousClient {
+    peer_id: PeerId,
+    connection: Connection,
+    observed_addr: Option<SocketAddr>,
+    credential: Option<String>,
+}
+
+impl RendezvousClient {
+    /// P2Pエンドポイントからランデブーサーバーへ接続
+    pub async fn connect(
+        endpoint: &Endpoint,
+        server_addr: SocketAddr,
+        peer_id: impl Into<PeerId>,
+    ) -> Result<Self, P2pError> {
+        let connection = endpoint
+            .connect(server_addr, P2P_SERVER_NAME)
+            .map_err(|e| P2pError::Quic(e.to_string()))?
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+
+        Ok(Self {
+            peer_id: peer_id.into(),
+            connection,
+            observed_addr: None,
+            credential: None,
+        })
+    }
+
+    /// 資格情報を添えて登録するクライアントとして接続
+    ///
+    /// 同じ資格情報で接続し直すと、古い接続の登録を置き換えます。
+    pub async fn connect_with_credential(
+        endpoint: &Endpoint,
+        server_addr: SocketAddr,
+        peer_id: impl Into<PeerId>,
+        credential: impl Into<String>,
+    ) -> Result<Self, P2pError> {
+        let mut client = Self::connect(endpoint, server_addr, peer_id).await?;
+        client.credential = Some(credential.into());
+        Ok(client)
+    }
+
+    /// 自身のピアID
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    /// サーバーから観測された公開アドレス（登録後に利用可能）
+    pub fn observed_addr(&self) -> Option<SocketAddr> {
+        self.observed_addr
+    }
+
+    /// ローカル候補アドレスと共にピアを登録し、観測された公開アドレスを返す
+    pub async fn register(&mut self, local_addrs: Vec<SocketAddr>) -> Result<SocketAddr, P2pError> {
+        let response = self
+            .request(&RendezvousMessage::Register {
+                peer_id: self.peer_id.clone(),
+                local_addrs,
+                credential: self.credential.clone(),
+            })
+            .await?;
+
+        match response {
+            RendezvousMessage::Registered { observed_addr } => {
+                info!(
+                    "🤝 Registered as '{}' (observed address: {})",
+                    self.peer_id, observed_addr
+                );
+                self.observed_addr = Some(observed_addr);
+                Ok(observed_addr)
+            }
+            other => Err(unexpected_response(other)),
+        }
+    }
+
+    /// 指定ピアへの接続仲介を要求し、相手の候補アドレスを取得
+    pub async fn request_peer(&self, target: &str) -> Result<Vec<Candidate>, P2pError> {
+        let response = self
+            .request(&RendezvousMessage::ConnectRequest {
+                target: target.to_string(),
+            })
+            .await?;
+
+        match response {
+            RendezvousMessage::PeerCandidates { candidates, .. } => Ok(candidates),
+            RendezvousMessage::NotFound { peer_id } => Err(P2pError::PeerNotFound { peer_id }),
+            other => Err(unexpected_response(other)),
+        }
+    }
+
+    /// 他のピアからの接続仲介通知を待機
+    pub async fn next_introduction(&self) -> Result<(PeerId, Vec<Candidate>), P2pError> {
+        let mut recv = self
+            .connection
+            .accept_uni()
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        let data = recv
+            .read_to_end(MAX_RENDEZVOUS_MESSAGE_SIZE)
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+
+        match RendezvousMessage::from_bytes(&data)? {
+            RendezvousMessage::PeerCandidates {
+                peer_id,
+                candidates,
+            } => Ok((peer_id, candidates)),
+            other => Err(unexpected_response(other)),
+        }
+    }
+
+    async fn request(&self, message: &RendezvousMessage) -> Result<RendezvousMessage, P2pError> {
+        let (mut send, mut recv) = self
+            .connection
+            .open_bi()
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        send.write_all(&message.to_bytes()?)
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        send.finish().map_err(|e| P2pError::Quic(e.to_string()))?;
+
+        let data = recv
+            .read_to_end(MAX_RENDEZVOUS_MESSAGE_SIZE)
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        RendezvousMessage::from_bytes(&data)
+    }
+}
+
+fn unexpected_response(message: RendezvousMessage) -> P2pError {
+    match message {
+        RendezvousMessage::Error { message } => P2pError::Rendezvous(message),
+        other => P2pError::Rendezvous(format!("Unexpected response: {:?}", other)),
+    }
+}
+
+/// ホールパンチングの設定
+#[derive(Debug, Clone)]
+pub struct HolePunchConfig {
+    /// 1回の接続試行のタイムアウト
+    pub attempt_timeout: Duration,
+    /// 再試行の間隔（NATマッピングを開くためのパケットを繰り返し送る）
+    pub retry_interval: Duration,
+    /// ホールパンチング全体のタイムアウト
+    pub total_timeout: Duration,
+}
+
+impl Default for HolePunchConfig {
+    fn default() -> Self {
+        Self {
+            attempt_timeout: Duration::from_secs(2),
+            retry_interval: Duration::from_millis(500),
+            total_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// QUICの同時オープンによるホールパンチング
+pub struct HolePuncher {
+    endpoint: Endpoint,
+    identity: PeerIdentity,
+    config: HolePunchConfig,
+}
+
+impl HolePuncher {
+    /// `identity`は`endpoint`を作成した[`P2pEndpoint::bind`]に渡したものと同じにします
+    pub fn new(endpoint: Endpoint, identity: PeerIdentity) -> Self {
+        Self::with_config(endpoint, identity, HolePunchConfig::default())
+    }
+
+    pub fn with_config(
+        endpoint: Endpoint,
+        identity: PeerIdentity,
+        config: HolePunchConfig,
+    ) -> Self {
+        Self {
+            endpoint,
+            identity,
+            config,
+        }
+    }
+
+    /// 相手の候補アドレスに対してホールパンチングを行い、直接接続を確立
+    ///
+    /// 相手の証明書が`pin`と一致しない接続は採用しません。
+    pub async fn punch(
+        &self,
+        peer_id: &str,
+        candidates: &[Candidate],
+        role: PunchRole,
+        pin: &CertificatePin,
+    ) -> Result<Connection, P2pError> {
+        let mut candidates = candidates.to_vec();
+        sort_candidates(&mut candidates);
+        if candidates.is_empty() {
+            return Err(P2pError::HolePunchFailed {
+                peer_id: peer_id.to_string(),
+            });
+        }
+        let client_config = self.identity.client_config(pin)?;
+
+        info!(
+            "🕳️ Hole punching to '{}' as {:?} ({} candidates)",
+            peer_id,
+            role,
+            candidates.len()
+        );
+
+        let result = tokio::time::timeout(
+            self.config.total_timeout,
+            self.punch_loop(&candidates, role, &client_config, pin),
+        )
+        .await;
+
+        match result {
+            Ok(Some(connection)) => {
+                info!(
+                    "🕳️ Direct connection to '{}' established via {}",
+                    peer_id,
+                    connection.remote_address()
+                );
+                Ok(connection)
+            }
+            Ok(None) | Err(_) => Err(P2pError::HolePunchFailed {
+                peer_id: peer_id.to_string(),
+            }),
+        }
+    }
+
+    async fn punch_loop(
+        &self,
+        candidates: &[Candidate],
+        role: PunchRole,
+        client_config: &ClientConfig,
+        pin: &CertificatePin,
+    ) -> Option<Connection> {
+        let mut attempts = FuturesUnordered::new();
+        let mut retry = tokio::time::interval(self.config.retry_interval);
+
+        loop {
+            tokio::select! {
+                _ = retry.tick() => {
+                    // 全候補へ接続試行を送り、NATマッピングを開く
+                    for candidate in candidates {
+                        attempts.push(self.attempt(client_config, candidate.addr));
+                    }
+                }
+                Some(result) = attempts.next(), if !attempts.is_empty() => {
+                    if let Some(connection) = result {
+                        match role {
+                            PunchRole::Initiator => return Some(connection),
+                            PunchRole::Responder => {
+                                // 受ける側では自発的な接続は採用しない
+                                connection.close(quinn::VarInt::from_u32(0), b"punch probe");
+                            }
+                        }
+                    }
+                }
+                incoming = self.endpoint.accept(), if role == PunchRole::Responder => {
+                    let incoming = incoming?;
+                    let remote = incoming.remote_address();
+                    if !candidates.iter().any(|c| c.addr == remote) {
+                        debug!("Ignoring connection from unexpected address {}", remote);
+                        incoming.ignore();
+                        continue;
+                    }
+                    match incoming.await {
+                        Ok(connection) if peer_matches(&connection, pin) => return Some(connection),
+                        Ok(connection) => {
+                            warn!("Rejected punch connection from {}: certificate mismatch", remote);
+                            connection.close(quinn::VarInt::from_u32(0), b"peer certificate mismatch");
+                        }
+                        Err(e) => debug!("Incoming punch connection failed: {}", e),
+                    }
+                }
+            }
+        }
+    }
+
+    async fn attempt(&self, client_config: &ClientConfig, addr: SocketAddr) -> Option<Connection> {
+        let connecting =
+            match self
+                .endpoint
+                .connect_with(client_config.clone(), addr, P2P_SERVER_NAME)
+            {
+                Ok(connecting) => connecting,
+                Err(e) => {
+                    debug!("Punch attempt to {} failed: {}", addr, e);
+                    return None;
+                }
+            };
+
+        match tokio::time::timeout(self.config.attempt_timeout, connecting).await {
+            Ok(Ok(connection)) => Some(connection),
+            Ok(Err(e)) => {
+                debug!("Punch attempt to {} failed: {}", addr, e);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_priority() {
+        let host_v6 = Candidate::host("[::1]:5000".parse().unwrap());
+        let host_v4 = Candidate::host("127.0.0.1:5000".parse().unwrap());
+        let srflx = Candidate::server_reflexive("[2001:db8::1]:6000".parse().unwrap());
+
+        assert!(host_v6.priority() > host_v4.priority());
+        assert!(host_v4.priority() > srflx.priority());
+    }
+
+    #[test]
+    fn test_sort_candidates_dedup() {
+        let addr: SocketAddr = "[::1]:5000".parse().unwrap();
+        let mut candidates = vec![
+            Candidate::server_reflexive("[2001:db8::1]:6000".parse().unwrap()),
+            Candidate::host(addr),
+            Candidate::server_reflexive(addr),
+        ];
+        sort_candidates(&mut candidates);
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0], Candidate::host(addr));
+    }
+
+    #[test]
+    fn test_punch_role_is_symmetric() {
+        assert_eq!(PunchRole::for_peers("alice", "bob"), PunchRole::Initiator);
+        assert_eq!(PunchRole::for_peers("bob", "alice"), PunchRole::Responder);
+    }
+
+    #[test]
+    fn test_rendezvous_message_roundtrip() {
+        let message = RendezvousMessage::PeerCandidates {
+            peer_id: "alice".to_string(),
+            candidates: vec![Candidate::host("[::1]:5000".parse().unwrap())],
+        };
+        let bytes = message.to_bytes().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(json["type"], "peer_candidates");
+
+        let restored = RendezvousMessage::from_bytes(&bytes).unwrap();
+        assert_eq!(restored, message);
+    }
+
+    async fn start_rendezvous() -> (Arc<RendezvousServer>, SocketAddr) {
+        let server = Arc::new(
+            RendezvousServer::bind("127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap(),
+        );
+        let addr = server.local_addr().unwrap();
+        tokio::spawn({
+            let server = Arc::clone(&server);
+            async move { server.run().await }
+        });
+        (server, addr)
+    }
+
+    async fn bind_peer() -> (PeerIdentity, Endpoint) {
+        let identity = PeerIdentity::generate().unwrap();
+        let endpoint = P2pEndpoint::bind("127.0.0.1:0".parse().unwrap(), &identity)
+            .await
+            .unwrap();
+        (identity, endpoint)
+    }
+
+    #[test]
+    fn test_peer_identity_pin() {
+        let identity = PeerIdentity::generate().unwrap();
+        assert!(identity.pin().matches(identity.certificate()));
+
+        let other = PeerIdentity::generate().unwrap();
+        assert!(!identity.pin().matches(other.certificate()));
+        assert!(
+            PeerIdentity::from_der(other.certificate().clone(), identity.private_key.to_vec())
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_other_connection() {
+        let (server, addr) = start_rendezvous().await;
+        let (_, endpoint) = bind_peer().await;
+
+        let mut alice =
+            RendezvousClient::connect_with_credential(&endpoint, addr, "alice", "secret")
+                .await
+                .unwrap();
+        alice.register(Vec::new()).await.unwrap();
+        // 同じ接続からの登録し直しは受け付ける
+        alice.register(Vec::new()).await.unwrap();
+
+        // 資格情報のない・一致しない別接続は同じIDで登録できない
+        let mut spoof = RendezvousClient::connect(&endpoint, addr, "alice")
+            .await
+            .unwrap();
+        assert!(spoof.register(Vec::new()).await.is_err());
+        let mut spoof =
+            RendezvousClient::connect_with_credential(&endpoint, addr, "alice", "guess")
+                .await
+                .unwrap();
+        assert!(spoof.register(Vec::new()).await.is_err());
+
+        let mut fresh =
+            RendezvousClient::connect_with_credential(&endpoint, addr, "alice", "secret")
+                .await
+                .unwrap();
+        fresh.register(Vec::new()).await.unwrap();
+        assert_eq!(server.registered_peers().await, vec!["alice".to_string()]);
+        server.close();
+    }
+
+    #[tokio::test]
+    async fn test_bad_stream_keeps_connection_and_unregisters_on_close() {
+        let (server, addr) = start_rendezvous().await;
+        let (_, endpoint) = bind_peer().await;
+
+        let mut alice = RendezvousClient::connect(&endpoint, addr, "alice")
+            .await
+            .unwrap();
+        alice.register(Vec::new()).await.unwrap();
+
+        // 上限を超えるメッセージにはエラーを返し、接続の処理は続ける
+        let (mut send, mut recv) = alice.connection.open_bi().await.unwrap();
+        send.write_all(&vec![b' '; MAX_RENDEZVOUS_MESSAGE_SIZE + 1])
+            .await
+            .unwrap();
+        send.finish().unwrap();
+        let response = recv.read_to_end(MAX_RENDEZVOUS_MESSAGE_SIZE).await.unwrap();
+        assert!(matches!(
+            RendezvousMessage::from_bytes(&response).unwrap(),
+            RendezvousMessage::Error { .. }
+        ));
+        assert!(matches!(
+            alice.request_peer("bob").await,
+            Err(P2pError::PeerNotFound { peer_id }) if peer_id == "bob"
+        ));
+
+        alice.connection.close(quinn::VarInt::from_u32(0), b"done");
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !server.registered_peers().await.is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        server.close();
+    }
+
+    /// 2つのピアをランデブーサーバーに登録し、互いの候補アドレスを交換する
+    async fn introduce(
+        addr: SocketAddr,
+        alice_endpoint: &Endpoint,
+        bob_endpoint: &Endpoint,
+    ) -> (Vec<Candidate>, Vec<Candidate>) {
+        let mut alice = RendezvousClient::connect(alice_endpoint, addr, "alice")
+            .await
+            .unwrap();
+        alice
+            .register(vec![alice_endpoint.local_addr().unwrap()])
+            .await
+            .unwrap();
+        let mut bob = RendezvousClient::connect(bob_endpoint, addr, "bob")
+            .await
+            .unwrap();
+        bob.register(vec![bob_endpoint.local_addr().unwrap()])
+            .await
+            .unwrap();
+
+        let bob_candidates = alice.request_peer("bob").await.unwrap();
+        let (introduced, alice_candidates) = bob.next_introduction().await.unwrap();
+        assert_eq!(introduced, "alice");
+        (alice_candidates, bob_candidates)
+    }
+
+    #[tokio::test]
+    async fn test_hole_punch_through_rendezvous() {
+        let (server, addr) = start_rendezvous().await;
+        let (alice_identity, alice_endpoint) = bind_peer().await;
+        let (bob_identity, bob_endpoint) = bind_peer().await;
+        let (alice_candidates, bob_candidates) =
+            introduce(addr, &alice_endpoint, &bob_endpoint).await;
+
+        let alice_role = PunchRole::for_peers("alice", "bob");
+        let bob_role = PunchRole::for_peers("bob", "alice");
+        assert_eq!(alice_role, PunchRole::Initiator);
+        assert_eq!(bob_role, PunchRole::Responder);
+
+        let alice_puncher = HolePuncher::new(alice_endpoint.clone(), alice_identity.clone());
+        let bob_puncher = HolePuncher::new(bob_endpoint.clone(), bob_identity.clone());
+        let (alice_connection, bob_connection) = tokio::join!(
+            alice_puncher.punch("bob", &bob_candidates, alice_role, &bob_identity.pin()),
+            bob_puncher.punch("alice", &alice_candidates, bob_role, &alice_identity.pin()),
+        );
+        let alice_connection = alice_connection.unwrap();
+        let bob_connection = bob_connection.unwrap();
+        assert_eq!(
+            alice_connection.remote_address(),
+            bob_endpoint.local_addr().unwrap()
+        );
+        assert_eq!(
+            bob_connection.remote_address(),
+            alice_endpoint.local_addr().unwrap()
+        );
+
+        // 確立した直接接続でデータを送れる
+        let mut send = alice_connection.open_uni().await.unwrap();
+        send.write_all(b"hello").await.unwrap();
+        send.finish().unwrap();
+        let mut recv = bob_connection.accept_uni().await.unwrap();
+        assert_eq!(recv.read_to_end(64).await.unwrap(), b"hello");
+        server.close();
+    }
+
+    #[tokio::test]
+    async fn test_hole_punch_rejects_unexpected_certificate() {
+        let (server, addr) = start_rendezvous().await;
+        let (alice_identity, alice_endpoint) = bind_peer().await;
+        let (bob_identity, bob_endpoint) = bind_peer().await;
+        let (alice_candidates, bob_candidates) =
+            introduce(addr, &alice_endpoint, &bob_endpoint).await;
+
+        // 相手のピンとして別の証明書を渡すと、どちらの役割でも接続を採用しない
+        let impostor = PeerIdentity::generate().unwrap();
+        let config = HolePunchConfig {
+            attempt_timeout: Duration::from_millis(500),
+            retry_interval: Duration::from_millis(200),
+            total_timeout: Duration::from_secs(2),
+        };
+        let alice_puncher =
+            HolePuncher::with_config(alice_endpoint.clone(), alice_identity, config.clone());
+        let bob_puncher = HolePuncher::with_config(bob_endpoint.clone(), bob_identity, config);
+        let (alice_connection, bob_connection) = tokio::join!(
+            alice_puncher.punch(
+                "bob",
+                &bob_candidates,
+                PunchRole::Initiator,
+                &impostor.pin()
+            ),
+            bob_puncher.punch(
+                "alice",
+                &alice_candidates,
+                PunchRole::Responder,
+                &impostor.pin()
+            ),
+        );
+        assert!(matches!(
+            alice_connection,
+            Err(P2pError::HolePunchFailed { .. })
+        ));
+        assert!(matches!(
+            bob_connection,
+            Err(P2pError::HolePunchFailed { .. })
+        ));
+        server.close();
+    }
+}
@@ -0,0 +1,1180 @@
+//! リレーフォールバック（TURN方式）
+//!
+//! ホールパンチングで直接接続を確立できない場合に、公開リレーサーバー経由で
+//! ピア間の通信を中継します。
+//!
+//! - 各ピアは[`RelayClient`]でリレーサーバーに1本の双方向ストリームを張り、
+//!   長さ付きフレームで宛先ピアIDとペイロードを送ります
+//! - リレーサーバーはフレームの宛先を送信元ピアIDに書き換えて転送するだけで、
+//!   ペイロードの内容には関与しません
+//! - ペイロードはピア間でX25519鍵交換を行い、ChaCha20-Poly1305で
+//!   エンドツーエンド暗号化されるため、リレーサーバーは内容を読めません
+//! - 鍵交換の公開鍵には各ピアの[`PeerIdentity`]で署名し、相手の署名を
+//!   [`PeerIdentity::pin`]で検証します。ピンが一致しない相手とはチャネルを確立しないため、
+//!   リレーサーバーが鍵をすり替えても中間者になれません
+//!
+//! 登録時に資格情報を添えたピアIDは、同じ資格情報を示した新しい接続で登録し直せます
+//! （再接続したクライアントが、古い接続のタイムアウトを待たずに登録できる）。
+
+use quinn::{Connection, Endpoint, RecvStream, SendStream};
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_ASN1, UnparsedPublicKey as SignaturePublicKey};
+use ring::{digest, hkdf};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{RwLock, mpsc};
+use tracing::{debug, info, warn};
+use unison::network::CertificatePin;
+use unison::network::quic::QuicServer;
+
+use crate::error::P2pError;
+use crate::nat::{
+    Candidate, HolePuncher, P2P_SERVER_NAME, PeerId, PeerIdentity, PunchRole, credential_hash,
+    pin_matches_public_key,
+};
+
+/// リレーフレームの最大サイズ（1MB）
+const MAX_RELAY_FRAME_SIZE: usize = 1024 * 1024;
+
+/// リレーサーバーがピアごとに保持する転送キューの長さ
+const RELAY_QUEUE_SIZE: usize = 256;
+
+/// チャネル未確立のピアごとに保持するフレーム数の上限
+const MAX_PENDING_FRAMES: usize = 32;
+
+/// チャネル未確立のピアから保持するフレームの合計サイズの上限（4MB）
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+
+/// エンドツーエンド鍵交換のタイムアウト
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 暗号化ペイロードの種別タグ
+const TAG_HANDSHAKE: u8 = 0x01;
+const TAG_DATA: u8 = 0x02;
+
+/// X25519公開鍵の長さ
+const PUBLIC_KEY_LEN: usize = 32;
+
+/// ピアの識別用公開鍵（非圧縮形式のP-256の点）の長さ
+const IDENTITY_KEY_LEN: usize = 65;
+
+/// ハンドシェイクの署名対象の接頭辞
+const HANDSHAKE_CONTEXT: &[u8] = b"unison relay v2 handshake";
+
+/// リレーサーバーとクライアント間の制御メッセージ
+///
+/// 宛先（送信元）ピアIDが空のフレームで送受信されます。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RelayControl {
+    /// リレーへのピア登録要求
+    ///
+    /// `credential`を添えた登録は、同じ資格情報による新しい登録で置き換えられます。
+    Bind {
+        peer_id: PeerId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        credential: Option<String>,
+    },
+    /// 登録完了
+    Bound,
+    /// エラー通知
+    Error { message: String },
+}
+
+/// フレームを書き込む
+///
+/// フォーマット: `[u32 全体長][u16 ピアID長][ピアID][ペイロード]`（ビッグエンディアン）
+async fn write_frame(send: &mut SendStream, peer_id: &str, payload: &[u8]) -> Result<(), P2pError> {
+    let peer_bytes = peer_id.as_bytes();
+    let body_len = 2 + peer_bytes.len() + payload.len();
+    if body_len > MAX_RELAY_FRAME_SIZE || peer_bytes.len() > u16::MAX as usize {
+        return Err(P2pError::Relay(format!(
+            "Relay frame too large: {} bytes",
+            body_len
+        )));
+    }
+
+    let mut buf = Vec::with_capacity(4 + body_len);
+    buf.extend_from_slice(&(body_len as u32).to_be_bytes());
+    buf.extend_from_slice(&(peer_bytes.len() as u16).to_be_bytes());
+    buf.extend_from_slice(peer_bytes);
+    buf.extend_from_slice(payload);
+
+    send.write_all(&buf)
+        .await
+        .map_err(|e| P2pError::Quic(e.to_string()))
+}
+
+/// フレームを読み込む（フレームの境界でストリームが正常に終了した場合は`None`）
+async fn read_frame(recv: &mut RecvStream) -> Result<Option<(PeerId, Vec<u8>)>, P2pError> {
+    let mut len_buf = [0u8; 4];
+    match recv.read_exact(&mut len_buf).await {
+        Ok(()) => {}
+        Err(quinn::ReadExactError::FinishedEarly(0)) => return Ok(None),
+        Err(e) => return Err(P2pError::Quic(e.to_string())),
+    }
+    let body_len = u32::from_be_bytes(len_buf) as usize;
+    if !(2..=MAX_RELAY_FRAME_SIZE).contains(&body_len) {
+        return Err(P2pError::Relay(format!(
+            "Invalid relay frame length: {}",
+            body_len
+        )));
+    }
+
+    let mut body = vec![0u8; body_len];
+    recv.read_exact(&mut body)
+        .await
+        .map_err(|e| P2pError::Quic(e.to_string()))?;
+
+    let peer_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+    if 2 + peer_len > body_len {
+        return Err(P2pError::Relay("Invalid relay frame peer id".to_string()));
+    }
+    let peer_id = String::from_utf8(body[2..2 + peer_len].to_vec())
+        .map_err(|e| P2pError::Relay(e.to_string()))?;
+    let payload = body.split_off(2 + peer_len);
+
+    Ok(Some((peer_id, payload)))
+}
+
+async fn write_control(send: &mut SendStream, control: &RelayControl) -> Result<(), P2pError> {
+    write_frame(send, "", &serde_json::to_vec(control)?).await
+}
+
+/// ピアごとの転送キュー
+type RelayQueue = mpsc::Sender<(PeerId, Vec<u8>)>;
+
+/// リレーに登録済みのピア
+struct RelayPeer {
+    queue: RelayQueue,
+    connection: Connection,
+    /// 登録時の資格情報のハッシュ（資格情報なしの登録は置き換えられない）
+    credential: Option<Vec<u8>>,
+}
+
+impl RelayPeer {
+    /// 同じ資格情報を示した登録で置き換えられるか
+    fn accepts_rebind(&self, credential: Option<&[u8]>) -> bool {
+        self.credential.is_some() && self.credential.as_deref() == credential
+    }
+}
+
+/// 公開リレーサーバー
+///
+/// 登録済みピア間でフレームを中継します。ペイロードは暗号化されたまま転送されます。
+pub struct RelayServer {
+    endpoint: Endpoint,
+    peers: Arc<RwLock<HashMap<PeerId, RelayPeer>>>,
+}
+
+impl RelayServer {
+    /// 指定アドレスでリレーサーバーをバインド
+    pub async fn bind(addr: SocketAddr) -> Result<Self, P2pError> {
+        let server_config = QuicServer::configure_server()
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        let endpoint = Endpoint::server(server_config, addr)?;
+        info!("📡 Relay server bound to {}", addr);
+
+        Ok(Self {
+            endpoint,
+            peers: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// バインドされたローカルアドレス
+    pub fn local_addr(&self) -> Result<SocketAddr, P2pError> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// 接続中のピア一覧
+    pub async fn connected_peers(&self) -> Vec<PeerId> {
+        self.peers.read().await.keys().cloned().collect()
+    }
+
+    /// 接続の受け付けを開始（エンドポイントが閉じられるまで戻りません）
+    pub async fn run(&self) -> Result<(), P2pError> {
+        while let Some(incoming) = self.endpoint.accept().await {
+            let peers = Arc::clone(&self.peers);
+            tokio::spawn(async move {
+                match incoming.await {
+                    Ok(connection) => {
+                        if let Err(e) = handle_relay_connection(connection, peers).await {
+                            warn!("Relay connection error: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to accept relay connection: {}", e),
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// サーバーを停止
+    pub fn close(&self) {
+        self.endpoint
+            .close(quinn::VarInt::from_u32(0), b"relay shutdown");
+    }
+}
+
+async fn handle_relay_connection(
+    connection: Connection,
+    peers: Arc<RwLock<HashMap<PeerId, RelayPeer>>>,
+) -> Result<(), P2pError> {
+    let (mut send, mut recv) = connection
+        .accept_bi()
+        .await
+        .map_err(|e| P2pError::Quic(e.to_string()))?;
+
+    // 最初のフレームは登録要求
+    let (peer_id, credential) = match read_frame(&mut recv).await? {
+        Some((target, payload)) if target.is_empty() => {
+            match serde_json::from_slice::<RelayControl>(&payload)? {
+                RelayControl::Bind {
+                    peer_id,
+                    credential,
+                } => (peer_id, credential.as_deref().map(credential_hash)),
+                other => {
+                    return Err(P2pError::Relay(format!(
+                        "Unexpected relay control: {:?}",
+                        other
+                    )));
+                }
+            }
+        }
+        _ => return Err(P2pError::Relay("Relay bind required".to_string())),
+    };
+
+    let (queue, mut outgoing) = mpsc::channel::<(PeerId, Vec<u8>)>(RELAY_QUEUE_SIZE);
+    // 登録の可否はロック内で決め、クライアントへの応答はロックを解放してから書き込む
+    let bound = {
+        let mut peers = peers.write().await;
+        let accepted = peers
+            .get(&peer_id)
+            .is_none_or(|existing| existing.accepts_rebind(credential.as_deref()));
+        if accepted {
+            let stale = peers.insert(
+                peer_id.clone(),
+                RelayPeer {
+                    queue: queue.clone(),
+                    connection: connection.clone(),
+                    credential,
+                },
+            );
+            if let Some(stale) = stale {
+                // 再接続したクライアントの登録で古い接続を置き換える
+                stale
+                    .connection
+                    .close(quinn::VarInt::from_u32(0), b"relay peer rebound");
+                debug!("Peer '{}' rebound to relay", peer_id);
+            }
+        }
+        accepted
+    };
+    if !bound {
+        write_control(
+            &mut send,
+            &RelayControl::Error {
+                message: format!("Peer already bound: {}", peer_id),
+            },
+        )
+        .await?;
+        let _ = send.finish();
+        return Ok(());
+    }
+    write_control(&mut send, &RelayControl::Bound).await?;
+    debug!("Peer '{}' bound to relay", peer_id);
+
+    // 転送キューからクライアントへの書き込み
+    let writer = tokio::spawn(async move {
+        while let Some((source, payload)) = outgoing.recv().await {
+            if write_frame(&mut send, &source, &payload).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = relay_frames(&peer_id, &mut recv, &peers, &queue).await;
+
+    {
+        let mut peers = peers.write().await;
+        if peers
+            .get(&peer_id)
+            .is_some_and(|p| p.queue.same_channel(&queue))
+        {
+            peers.remove(&peer_id);
+        }
+    }
+    writer.abort();
+    debug!("Peer '{}' unbound from relay", peer_id);
+
+    result
+}
+
+/// クライアントから受信したフレームを宛先ピアへ転送
+async fn relay_frames(
+    peer_id: &str,
+    recv: &mut RecvStream,
+    peers: &RwLock<HashMap<PeerId, RelayPeer>>,
+    own_queue: &RelayQueue,
+) -> Result<(), P2pError> {
+    while let Some((target, payload)) = read_frame(recv).await? {
+        if target.is_empty() {
+            continue;
+        }
+
+        let destination = peers.read().await.get(&target).map(|p| p.queue.clone());
+        let delivered = match destination {
+            Some(queue) => queue.send((peer_id.to_string(), payload)).await.is_ok(),
+            None => false,
+        };
+
+        if !delivered {
+            let control = RelayControl::Error {
+                message: format!("Peer not found: {}", target),
+            };
+            let _ = own_queue
+                .send((String::new(), serde_json::to_vec(&control)?))
+                .await;
+        }
+    }
+    Ok(())
+}
+
+/// クライアント側のルーティングテーブル
+#[derive(Default)]
+struct RelayRouting {
+    /// チャネル確立済みのピア
+    channels: HashMap<PeerId, mpsc::UnboundedSender<Vec<u8>>>,
+    /// チャネル未確立のピアから届いたフレーム
+    pending: HashMap<PeerId, Vec<Vec<u8>>>,
+    /// `pending`に保持しているフレームの合計サイズ
+    pending_bytes: usize,
+}
+
+impl RelayRouting {
+    /// チャネル未確立のピアから届いたフレームを保持
+    ///
+    /// 上限を超えるフレームは破棄します。そのピアの最初のフレームであれば`true`を返します。
+    fn buffer(&mut self, source: &str, payload: Vec<u8>) -> bool {
+        let frames = self.pending.get(source).map_or(0, Vec::len);
+        if frames >= MAX_PENDING_FRAMES || self.pending_bytes + payload.len() > MAX_PENDING_BYTES {
+            warn!(
+                "Dropping relay frame from '{}': pending buffer full",
+                source
+            );
+            return false;
+        }
+        self.pending_bytes += payload.len();
+        self.pending
+            .entry(source.to_string())
+            .or_default()
+            .push(payload);
+        frames == 0
+    }
+
+    /// 保持していたフレームを取り出す
+    fn take_pending(&mut self, source: &str) -> Vec<Vec<u8>> {
+        let frames = self.pending.remove(source).unwrap_or_default();
+        self.pending_bytes -= frames.iter().map(Vec::len).sum::<usize>();
+        frames
+    }
+}
+
+/// リレーサーバーのクライアント
+pub struct RelayClient {
+    peer_id: PeerId,
+    identity: PeerIdentity,
+    connection: Connection,
+    sender: Arc<tokio::sync::Mutex<SendStream>>,
+    routing: Arc<Mutex<RelayRouting>>,
+    arrivals: tokio::sync::Mutex<mpsc::UnboundedReceiver<PeerId>>,
+}
+
+impl RelayClient {
+    /// リレーサーバーへ接続し、ピアIDを登録
+    ///
+    /// `identity`はチャネルの鍵交換で自身を証明するために使います。
+    pub async fn connect(
+        endpoint: &Endpoint,
+        relay_addr: SocketAddr,
+        peer_id: impl Into<PeerId>,
+        identity: PeerIdentity,
+    ) -> Result<Self, P2pError> {
+        Self::bind(endpoint, relay_addr, peer_id.into(), identity, None).await
+    }
+
+    /// 資格情報を添えてピアIDを登録
+    ///
+    /// 同じ資格情報で接続し直すと、古い接続の登録を置き換えます。
+    pub async fn connect_with_credential(
+        endpoint: &Endpoint,
+        relay_addr: SocketAddr,
+        peer_id: impl Into<PeerId>,
+        identity: PeerIdentity,
+        credential: impl Into<String>,
+    ) -> Result<Self, P2pError> {
+        Self::bind(
+            endpoint,
+            relay_addr,
+            peer_id.into(),
+            identity,
+            Some(credential.into()),
+        )
+        .await
+    }
+
+    async fn bind(
+        endpoint: &Endpoint,
+        relay_addr: SocketAddr,
+        peer_id: PeerId,
+        identity: PeerIdentity,
+        credential: Option<String>,
+    ) -> Result<Self, P2pError> {
+        let connection = endpoint
+            .connect(relay_addr, P2P_SERVER_NAME)
+            .map_err(|e| P2pError::Quic(e.to_string()))?
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+
+        let (mut send, mut recv) = connection
+            .open_bi()
+            .await
+            .map_err(|e| P2pError::Quic(e.to_string()))?;
+        write_control(
+            &mut send,
+            &RelayControl::Bind {
+                peer_id: peer_id.clone(),
+                credential,
+            },
+        )
+        .await?;
+
+        match read_frame(&mut recv).await? {
+            Some((source, payload)) if source.is_empty() => {
+                match serde_json::from_slice::<RelayControl>(&payload)? {
+                    RelayControl::Bound => {}
+                    RelayControl::Error { message } => return Err(P2pError::Relay(message)),
+                    other => {
+                        return Err(P2pError::Relay(format!(
+                            "Unexpected relay control: {:?}",
+                            other
+                        )));
+                    }
+                }
+            }
+            _ => return Err(P2pError::Relay("Relay bind failed".to_string())),
+        }
+        info!("📡 Bound to relay {} as '{}'", relay_addr, peer_id);
+
+        let routing = Arc::new(Mutex::new(RelayRouting::default()));
+        let (arrivals_tx, arrivals_rx) = mpsc::unbounded_channel();
+        tokio::spawn(receive_loop(recv, Arc::clone(&routing), arrivals_tx));
+
+        Ok(Self {
+            peer_id,
+            identity,
+            connection,
+            sender: Arc::new(tokio::sync::Mutex::new(send)),
+            routing,
+            arrivals: tokio::sync::Mutex::new(arrivals_rx),
+        })
+    }
+
+    /// 自身のピアID
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    /// 指定ピアとの暗号化チャネルを開く
+    ///
+    /// 双方が同時に呼び出しても、一方が[`Self::accept_channel`]で受けても確立できます。
+    /// 相手の鍵交換の署名が`pin`と一致しない場合は確立しません。
+    pub async fn open_channel(
+        &self,
+        remote: &str,
+        pin: &CertificatePin,
+    ) -> Result<RelayChannel, P2pError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        {
+            let mut routing = self.routing.lock().unwrap();
+            for frame in routing.take_pending(remote) {
+                let _ = tx.send(frame);
+            }
+            routing.channels.insert(remote.to_string(), tx);
+        }
+
+        let mut channel = RelayChannel {
+            local: self.peer_id.clone(),
+            remote: remote.to_string(),
+            identity: self.identity.clone(),
+            remote_pin: *pin,
+            sender: Arc::clone(&self.sender),
+            receiver: rx,
+            cipher: None,
+        };
+
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, channel.handshake()).await {
+            Ok(Ok(())) => {
+                info!("📡 Relayed channel to '{}' established", remote);
+                Ok(channel)
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(P2pError::Timeout),
+        }
+    }
+
+    /// 他のピアから開かれたチャネルを受け入れる
+    ///
+    /// `trusted`に含まれるピアからのチャネルのみ受け入れ、そのピンで相手を検証します。
+    /// 含まれないピアから届いたフレームは破棄します。
+    pub async fn accept_channel(
+        &self,
+        trusted: &HashMap<PeerId, CertificatePin>,
+    ) -> Result<RelayChannel, P2pError> {
+        loop {
+            let remote = self
+                .arrivals
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or_else(|| P2pError::Relay("Relay connection closed".to_string()))?;
+
+            let Some(pin) = trusted.get(&remote) else {
+                warn!("Ignoring relayed channel from untrusted peer '{}'", remote);
+                self.routing.lock().unwrap().take_pending(&remote);
+                continue;
+            };
+            let already_open = self.routing.lock().unwrap().channels.contains_key(&remote);
+            if !already_open {
+                return self.open_channel(&remote, pin).await;
+            }
+        }
+    }
+
+    /// リレーとの接続を閉じる
+    pub fn close(&self) {
+        self.connection
+            .close(quinn::VarInt::from_u32(0), b"relay client closed");
+    }
+}
+
+/// リレーから届いたフレームを各チャネルへ振り分け
+async fn receive_loop(
+    mut recv: RecvStream,
+    routing: Arc<Mutex<RelayRouting>>,
+    arrivals: mpsc::UnboundedSender<PeerId>,
+) {
+    loop {
+        let (source, payload) = match read_frame(&mut recv).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Relay receive error: {}", e);
+                break;
+            }
+        };
+
+        if source.is_empty() {
+            if let Ok(RelayControl::Error { message }) = serde_json::from_slice(&payload) {
+                warn!("Relay reported error: {}", message);
+            }
+            continue;
+        }
+
+        let mut routing = routing.lock().unwrap();
+        let payload = match routing.channels.get(&source) {
+            Some(channel) => match channel.send(payload) {
+                Ok(()) => continue,
+                Err(mpsc::error::SendError(payload)) => {
+                    routing.channels.remove(&source);
+                    payload
+                }
+            },
+            None => payload,
+        };
+
+        if routing.buffer(&source, payload) {
+            let _ = arrivals.send(source);
+        }
+    }
+
+    // 受信側を閉じて待機中のチャネルへ終端を伝える
+    routing.lock().unwrap().channels.clear();
+}
+
+/// リレー経由のエンドツーエンド暗号化チャネル
+pub struct RelayChannel {
+    local: PeerId,
+    remote: PeerId,
+    identity: PeerIdentity,
+    /// 相手の識別用公開鍵のピン
+    remote_pin: CertificatePin,
+    sender: Arc<tokio::sync::Mutex<SendStream>>,
+    receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+    cipher: Option<E2eCipher>,
+}
+
+impl RelayChannel {
+    /// 相手のピアID
+    pub fn remote_peer(&self) -> &str {
+        &self.remote
+    }
+
+    /// セッション鍵のフィンガープリント（双方で同じ値になる）
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.cipher.as_ref().map(|c| c.fingerprint.as_str())
+    }
+
+    /// データを暗号化して送信
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), P2pError> {
+        let cipher = self
+            .cipher
+            .as_mut()
+            .ok_or_else(|| P2pError::Encryption("Handshake not completed".to_string()))?;
+        let frame = cipher.seal(data)?;
+        self.send_raw(&frame).await
+    }
+
+    /// 受信したデータを復号して返す
+    pub async fn recv(&mut self) -> Result<Vec<u8>, P2pError> {
+        loop {
+            let frame = self.recv_raw().await?;
+            if frame.first() == Some(&TAG_HANDSHAKE) {
+                // 再送されたハンドシェイクは無視
+                continue;
+            }
+            let cipher = self
+                .cipher
+                .as_mut()
+                .ok_or_else(|| P2pError::Encryption("Handshake not completed".to_string()))?;
+            return cipher.open(&frame);
+        }
+    }
+
+    /// 鍵交換
+    ///
+    /// ハンドシェイク: `[TAG_HANDSHAKE][X25519公開鍵][識別用公開鍵][署名]`
+    /// 署名は送信元・宛先のピアIDとX25519公開鍵に対するもので、識別用公開鍵が
+    /// 相手のピンと一致し、署名を検証できた場合のみ鍵を合意します。
+    async fn handshake(&mut self) -> Result<(), P2pError> {
+        let rng = SystemRandom::new();
+        let private_key = EphemeralPrivateKey::generate(&X25519, &rng)
+            .map_err(|_| P2pError::Encryption("Failed to generate key".to_string()))?;
+        let public_key = private_key
+            .compute_public_key()
+            .map_err(|_| P2pError::Encryption("Failed to compute public key".to_string()))?;
+
+        let signature = self.identity.sign(&handshake_transcript(
+            &self.local,
+            &self.remote,
+            public_key.as_ref(),
+        ))?;
+        let mut hello = Vec::with_capacity(1 + PUBLIC_KEY_LEN + IDENTITY_KEY_LEN + signature.len());
+        hello.push(TAG_HANDSHAKE);
+        hello.extend_from_slice(public_key.as_ref());
+        hello.extend_from_slice(self.identity.public_key());
+        hello.extend_from_slice(&signature);
+        self.send_raw(&hello).await?;
+
+        let remote_public = loop {
+            let frame = self.recv_raw().await?;
+            if frame.len() > 1 + PUBLIC_KEY_LEN + IDENTITY_KEY_LEN && frame[0] == TAG_HANDSHAKE {
+                break self.verify_hello(&frame[1..])?;
+            }
+            debug!("Dropping relay frame received before handshake");
+        };
+
+        let cipher = agreement::agree_ephemeral(
+            private_key,
+            &UnparsedPublicKey::new(&X25519, &remote_public),
+            |shared_secret| {
+                E2eCipher::derive(
+                    &self.local,
+                    &self.remote,
+                    public_key.as_ref(),
+                    &remote_public,
+                    shared_secret,
+                )
+            },
+        )
+        .map_err(|_| P2pError::Encryption("Key agreement failed".to_string()))??;
+
+        self.cipher = Some(cipher);
+        Ok(())
+    }
+
+    /// 相手のハンドシェイクを検証し、X25519公開鍵を返す
+    fn verify_hello(&self, hello: &[u8]) -> Result<Vec<u8>, P2pError> {
+        let (ephemeral, rest) = hello.split_at(PUBLIC_KEY_LEN);
+        let (identity, signature) = rest.split_at(IDENTITY_KEY_LEN);
+        if !pin_matches_public_key(&self.remote_pin, identity) {
+            return Err(P2pError::Encryption(format!(
+                "Unexpected identity for peer '{}'",
+                self.remote
+            )));
+        }
+        SignaturePublicKey::new(&ECDSA_P256_SHA256_ASN1, identity)
+            .verify(
+                &handshake_transcript(&self.remote, &self.local, ephemeral),
+                signature,
+            )
+            .map_err(|_| {
+                P2pError::Encryption(format!(
+                    "Invalid handshake signature from '{}'",
+                    self.remote
+                ))
+            })?;
+        Ok(ephemeral.to_vec())
+    }
+
+    async fn send_raw(&self, payload: &[u8]) -> Result<(), P2pError> {
+        let mut sender = self.sender.lock().await;
+        write_frame(&mut sender, &self.remote, payload).await
+    }
+
+    async fn recv_raw(&mut self) -> Result<Vec<u8>, P2pError> {
+        self.receiver
+            .recv()
+            .await
+            .ok_or_else(|| P2pError::Relay("Relay connection closed".to_string()))
+    }
+}
+
+/// ハンドシェイクの署名対象（送信元・宛先のピアIDとX25519公開鍵）
+fn handshake_transcript(from: &str, to: &str, ephemeral: &[u8]) -> Vec<u8> {
+    let mut transcript = HANDSHAKE_CONTEXT.to_vec();
+    for id in [from, to] {
+        transcript.extend_from_slice(&(id.len() as u16).to_be_bytes());
+        transcript.extend_from_slice(id.as_bytes());
+    }
+    transcript.extend_from_slice(ephemeral);
+    transcript
+}
+
+/// 方向ごとの鍵を持つエンドツーエンド暗号
+///
+/// フレーム: `[TAG_DATA][u64 カウンタ][暗号文 + 認証タグ]`
+/// リレーとの間は単一ストリームで順序が保証されるため、カウンタの連続性で
+/// リプレイや欠落を検出します。
+struct E2eCipher {
+    sealing: LessSafeKey,
+    opening: LessSafeKey,
+    send_counter: u64,
+    recv_counter: u64,
+    fingerprint: String,
+}
+
+impl E2eCipher {
+    fn derive(
+        local: &str,
+        remote: &str,
+        local_public: &[u8],
+        remote_public: &[u8],
+        shared_secret: &[u8],
+    ) -> Result<Self, P2pError> {
+        // 双方で同じソルトになるよう公開鍵をソートして連結
+        let mut salt = Vec::with_capacity(local_public.len() + remote_public.len());
+        if local_public <= remote_public {
+            salt.extend_from_slice(local_public);
+            salt.extend_from_slice(remote_public);
+        } else {
+            salt.extend_from_slice(remote_public);
+            salt.extend_from_slice(local_public);
+        }
+
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &salt).extract(shared_secret);
+        let direction_key = |from: &str, to: &str| -> Result<LessSafeKey, P2pError> {
+            let label = format!("unison relay v1 {} -> {}", from, to);
+            let info = [label.as_bytes()];
+            let okm = prk
+                .expand(&info, &CHACHA20_POLY1305)
+                .map_err(|_| P2pError::Encryption("Key derivation failed".to_string()))?;
+            Ok(LessSafeKey::new(UnboundKey::from(okm)))
+        };
+
+        let fingerprint = digest::digest(&digest::SHA256, &salt)
+            .as_ref()
+            .iter()
+            .take(8)
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        Ok(Self {
+            sealing: direction_key(local, remote)?,
+            opening: direction_key(remote, local)?,
+            send_counter: 0,
+            recv_counter: 0,
+            fingerprint,
+        })
+    }
+
+    fn nonce(counter: u64) -> Nonce {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[NONCE_LEN - 8..].copy_from_slice(&counter.to_be_bytes());
+        Nonce::assume_unique_for_key(nonce)
+    }
+
+    fn seal(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, P2pError> {
+        let counter = self.send_counter;
+        self.send_counter = counter
+            .checked_add(1)
+            .ok_or_else(|| P2pError::Encryption("Nonce exhausted".to_string()))?;
+
+        let mut buf = plaintext.to_vec();
+        self.sealing
+            .seal_in_place_append_tag(Self::nonce(counter), Aad::empty(), &mut buf)
+            .map_err(|_| P2pError::Encryption("Encryption failed".to_string()))?;
+
+        let mut frame = Vec::with_capacity(9 + buf.len());
+        frame.push(TAG_DATA);
+        frame.extend_from_slice(&counter.to_be_bytes());
+        frame.extend_from_slice(&buf);
+        Ok(frame)
+    }
+
+    fn open(&mut self, frame: &[u8]) -> Result<Vec<u8>, P2pError> {
+        if frame.len() < 9 || frame[0] != TAG_DATA {
+            return Err(P2pError::Encryption(
+                "Malformed encrypted frame".to_string(),
+            ));
+        }
+        let counter = u64::from_be_bytes(frame[1..9].try_into().unwrap());
+        if counter != self.recv_counter {
+            return Err(P2pError::Encryption(format!(
+                "Unexpected frame counter: expected {}, got {}",
+                self.recv_counter, counter
+            )));
+        }
+
+        let mut buf = frame[9..].to_vec();
+        let plaintext_len = self
+            .opening
+            .open_in_place(Self::nonce(counter), Aad::empty(), &mut buf)
+            .map_err(|_| P2pError::Encryption("Decryption failed".to_string()))?
+            .len();
+        buf.truncate(plaintext_len);
+        self.recv_counter += 1;
+        Ok(buf)
+    }
+}
+
+/// ピアとの接続（直接またはリレー経由）
+pub enum PeerConnection {
+    /// ホールパンチングで確立した直接接続
+    Direct(Connection),
+    /// リレー経由の暗号化チャネル
+    Relayed(Box<RelayChannel>),
+}
+
+impl PeerConnection {
+    pub fn is_relayed(&self) -> bool {
+        matches!(self, Self::Relayed(_))
+    }
+}
+
+/// ホールパンチングを試み、失敗した場合はリレーにフォールバックして接続
+///
+/// 直接接続では相手の証明書を、リレー経由では鍵交換の署名を`pin`で検証します。
+pub async fn connect_with_fallback(
+    puncher: &HolePuncher,
+    relay: &RelayClient,
+    peer_id: &str,
+    candidates: &[Candidate],
+    role: PunchRole,
+    pin: &CertificatePin,
+) -> Result<PeerConnection, P2pError> {
+    match puncher.punch(peer_id, candidates, role, pin).await {
+        Ok(connection) => Ok(PeerConnection::Direct(connection)),
+        Err(P2pError::HolePunchFailed { .. }) => {
+            warn!(
+                "Direct connection to '{}' failed, falling back to relay",
+                peer_id
+            );
+            Ok(PeerConnection::Relayed(Box::new(
+                relay.open_channel(peer_id, pin).await?,
+            )))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher_pair() -> (E2eCipher, E2eCipher) {
+        let rng = SystemRandom::new();
+        let alice_key = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+        let bob_key = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+        let alice_public = alice_key.compute_public_key().unwrap().as_ref().to_vec();
+        let bob_public = bob_key.compute_public_key().unwrap().as_ref().to_vec();
+
+        let alice = agreement::agree_ephemeral(
+            alice_key,
+            &UnparsedPublicKey::new(&X25519, &bob_public),
+            |secret| E2eCipher::derive("alice", "bob", &alice_public, &bob_public, secret),
+        )
+        .unwrap()
+        .unwrap();
+        let bob = agreement::agree_ephemeral(
+            bob_key,
+            &UnparsedPublicKey::new(&X25519, &alice_public),
+            |secret| E2eCipher::derive("bob", "alice", &bob_public, &alice_public, secret),
+        )
+        .unwrap()
+        .unwrap();
+        (alice, bob)
+    }
+
+    #[test]
+    fn test_e2e_roundtrip() {
+        let (mut alice, mut bob) = cipher_pair();
+        assert_eq!(alice.fingerprint, bob.fingerprint);
+
+        let frame = alice.seal(b"hello bob").unwrap();
+        assert!(!frame.windows(9).any(|w| w == b"hello bob"));
+        assert_eq!(bob.open(&frame).unwrap(), b"hello bob");
+
+        let reply = bob.seal(b"hello alice").unwrap();
+        assert_eq!(alice.open(&reply).unwrap(), b"hello alice");
+    }
+
+    #[test]
+    fn test_e2e_rejects_replay_and_tampering() {
+        let (mut alice, mut bob) = cipher_pair();
+
+        let frame = alice.seal(b"payload").unwrap();
+        bob.open(&frame).unwrap();
+        assert!(bob.open(&frame).is_err());
+
+        let mut tampered = alice.seal(b"payload").unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xff;
+        assert!(bob.open(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_e2e_direction_keys_differ() {
+        let (mut alice, _bob) = cipher_pair();
+        // 自分宛てに送ったフレームは別方向の鍵では復号できない
+        let frame = alice.seal(b"loop").unwrap();
+        assert!(alice.open(&frame).is_err());
+    }
+
+    #[test]
+    fn test_pending_frames_are_bounded() {
+        let mut routing = RelayRouting::default();
+        assert!(routing.buffer("alice", vec![0; 8]));
+        for _ in 1..MAX_PENDING_FRAMES + 10 {
+            assert!(!routing.buffer("alice", vec![0; 8]));
+        }
+        assert_eq!(routing.pending["alice"].len(), MAX_PENDING_FRAMES);
+
+        // 合計サイズの上限を超えるフレームは他のピアからでも破棄する
+        let large = vec![0; MAX_RELAY_FRAME_SIZE];
+        for _ in 0..8 {
+            routing.buffer("bob", large.clone());
+        }
+        assert!(routing.pending_bytes <= MAX_PENDING_BYTES);
+        assert!(!routing.buffer("carol", large));
+        assert!(!routing.pending.contains_key("carol"));
+
+        assert_eq!(routing.take_pending("alice").len(), MAX_PENDING_FRAMES);
+        assert!(routing.buffer("carol", vec![0; 8]));
+    }
+
+    async fn start_relay() -> (Arc<RelayServer>, SocketAddr) {
+        let server = Arc::new(
+            RelayServer::bind("127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap(),
+        );
+        let relay_addr = server.local_addr().unwrap();
+        tokio::spawn({
+            let server = Arc::clone(&server);
+            async move { server.run().await }
+        });
+        (server, relay_addr)
+    }
+
+    async fn bind_peer() -> (PeerIdentity, Endpoint) {
+        let identity = PeerIdentity::generate().unwrap();
+        let endpoint = crate::nat::P2pEndpoint::bind("127.0.0.1:0".parse().unwrap(), &identity)
+            .await
+            .unwrap();
+        (identity, endpoint)
+    }
+
+    #[tokio::test]
+    async fn test_rebind_with_same_credential_replaces_stale_binding() {
+        let (server, relay_addr) = start_relay().await;
+        let (identity, endpoint) = bind_peer().await;
+        let connect = |peer_id: &'static str| {
+            RelayClient::connect(&endpoint, relay_addr, peer_id, identity.clone())
+        };
+        let connect_with = |peer_id: &'static str, credential: &'static str| {
+            RelayClient::connect_with_credential(
+                &endpoint,
+                relay_addr,
+                peer_id,
+                identity.clone(),
+                credential,
+            )
+        };
+
+        let _stale = connect_with("alice", "secret").await.unwrap();
+
+        // 資格情報が一致しない登録は拒否する
+        assert!(connect("alice").await.is_err());
+        assert!(connect_with("alice", "guess").await.is_err());
+
+        let _fresh = connect_with("alice", "secret").await.unwrap();
+        assert_eq!(server.connected_peers().await, vec!["alice".to_string()]);
+
+        // 資格情報なしの登録は置き換えられない
+        let _bob = connect("bob").await.unwrap();
+        assert!(connect("bob").await.is_err());
+        server.close();
+    }
+
+    #[tokio::test]
+    async fn test_relayed_round_trip() {
+        let (server, relay_addr) = start_relay().await;
+        let (alice_identity, alice_endpoint) = bind_peer().await;
+        let (bob_identity, bob_endpoint) = bind_peer().await;
+        let alice =
+            RelayClient::connect(&alice_endpoint, relay_addr, "alice", alice_identity.clone())
+                .await
+                .unwrap();
+        let bob = RelayClient::connect(&bob_endpoint, relay_addr, "bob", bob_identity.clone())
+            .await
+            .unwrap();
+
+        let trusted = HashMap::from([("alice".to_string(), alice_identity.pin())]);
+        let (alice_channel, bob_channel) = tokio::join!(
+            alice.open_channel("bob", &bob_identity.pin()),
+            bob.accept_channel(&trusted),
+        );
+        let mut alice_channel = alice_channel.unwrap();
+        let mut bob_channel = bob_channel.unwrap();
+        assert_eq!(bob_channel.remote_peer(), "alice");
+        assert_eq!(alice_channel.fingerprint(), bob_channel.fingerprint());
+
+        for n in 0..3u8 {
+            alice_channel.send(&[n; 16]).await.unwrap();
+        }
+        for n in 0..3u8 {
+            assert_eq!(bob_channel.recv().await.unwrap(), vec![n; 16]);
+        }
+        bob_channel.send(b"pong").await.unwrap();
+        assert_eq!(alice_channel.recv().await.unwrap(), b"pong");
+        server.close();
+    }
+
+    #[tokio::test]
+    async fn test_relayed_channel_rejects_unexpected_identity() {
+        let (server, relay_addr) = start_relay().await;
+        let (alice_identity, alice_endpoint) = bind_peer().await;
+        let (bob_identity, bob_endpoint) = bind_peer().await;
+        let alice =
+            RelayClient::connect(&alice_endpoint, relay_addr, "alice", alice_identity.clone())
+                .await
+                .unwrap();
+        let bob = RelayClient::connect(&bob_endpoint, relay_addr, "bob", bob_identity)
+            .await
+            .unwrap();
+
+        // 相手のピンと異なる鍵で署名されたハンドシェイクではチャネルを確立しない
+        let impostor = PeerIdentity::generate().unwrap();
+        let trusted = HashMap::from([("alice".to_string(), alice_identity.pin())]);
+        let (alice_channel, _) = tokio::join!(
+            alice.open_channel("bob", &impostor.pin()),
+            bob.accept_channel(&trusted),
+        );
+        assert!(matches!(alice_channel, Err(P2pError::Encryption(_))));
+
+        // 信頼していないピアからのチャネルは受け入れない
+        let carol = RelayClient::connect(&bob_endpoint, relay_addr, "carol", impostor.clone())
+            .await
+            .unwrap();
+        let result = tokio::time::timeout(Duration::from_millis(500), async {
+            tokio::join!(
+                carol.open_channel("alice", &alice_identity.pin()),
+                alice.accept_channel(&HashMap::new()),
+            )
+        })
+        .await;
+        assert!(result.is_err());
+        server.close();
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_fallback_uses_relay() {
+        let (server, relay_addr) = start_relay().await;
+        let (alice_identity, alice_endpoint) = bind_peer().await;
+        let (bob_identity, bob_endpoint) = bind_peer().await;
+        let alice =
+            RelayClient::connect(&alice_endpoint, relay_addr, "alice", alice_identity.clone())
+                .await
+                .unwrap();
+        let bob = RelayClient::connect(&bob_endpoint, relay_addr, "bob", bob_identity.clone())
+            .await
+            .unwrap();
+
+        // 応答しないUDPソケットを候補にして、ホールパンチングを失敗させる
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let candidates = [Candidate::host(silent.local_addr().unwrap())];
+        let puncher = HolePuncher::with_config(
+            alice_endpoint.clone(),
+            alice_identity.clone(),
+            crate::nat::HolePunchConfig {
+                attempt_timeout: Duration::from_millis(200),
+                retry_interval: Duration::from_millis(100),
+                total_timeout: Duration::from_millis(500),
+            },
+        );
+
+        let trusted = HashMap::from([("alice".to_string(), alice_identity.pin())]);
+        let (connection, bob_channel) = tokio::join!(
+            connect_with_fallback(
+                &puncher,
+                &alice,
+                "bob",
+                &candidates,
+                PunchRole::Initiator,
+                &bob_identity.pin(),
+            ),
+            bob.accept_channel(&trusted),
+        );
+        let PeerConnection::Relayed(mut alice_channel) = connection.unwrap() else {
+            panic!("expected a relayed connection");
+        };
+        let mut bob_channel = bob_channel.unwrap();
+
+        alice_channel.send(b"via relay").await.unwrap();
+        assert_eq!(bob_channel.recv().await.unwrap(), b"via relay");
+        server.close();
+    }
+
+    #[test]
+    fn test_relay_control_roundtrip() {
+        let control = RelayControl::Bind {
+            peer_id: "alice".to_string(),
+            credential: None,
+        };
+        let json = serde_json::to_string(&control).unwrap();
+        assert!(json.contains("\"type\":\"bind\""));
+        assert_eq!(
+            serde_json::from_str::<RelayControl>(&json).unwrap(),
+            control
+        );
+    }
+}
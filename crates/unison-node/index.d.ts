@@ -0,0 +1,26 @@
+export interface StreamResult {
+  done: boolean
+  value?: any
+}
+
+/** A streaming call in progress; iterate it with `for await`. */
+export declare class UnisonStream implements AsyncIterableIterator<any> {
+  /** Receives the next item. Rejects on a stream error, after which it is done. */
+  next(): Promise<StreamResult>
+  /** Stops receiving and closes the stream. */
+  return(): Promise<StreamResult>
+  [Symbol.asyncIterator](): UnisonStream
+}
+
+/** Client for a Unison server over QUIC. */
+export declare class UnisonClient {
+  constructor()
+  /** Connects to a server, e.g. `quic://127.0.0.1:8080`. */
+  connect(url: string): Promise<void>
+  disconnect(): Promise<void>
+  isConnected(): Promise<boolean>
+  /** Calls a method with a JSON-serializable payload. */
+  call(method: string, payload?: any): Promise<any>
+  /** Starts a streaming call. */
+  stream(method: string, payload?: any): Promise<UnisonStream>
+}
@@ -0,0 +1,30 @@
+'use strict'
+
+const { existsSync } = require('node:fs')
+const { join } = require('node:path')
+
+// `napi build --platform` names the addon after the platform and architecture
+function loadBinding() {
+  const candidates = [
+    `unison.${process.platform}-${process.arch}.node`,
+    `unison.${process.platform}-${process.arch}-gnu.node`,
+    `unison.${process.platform}-${process.arch}-msvc.node`,
+    'unison.node',
+  ]
+  for (const file of candidates) {
+    const path = join(__dirname, file)
+    if (existsSync(path)) {
+      return require(path)
+    }
+  }
+  throw new Error(`No Unison native addon found for ${process.platform}-${process.arch}`)
+}
+
+const { UnisonClient, UnisonStream } = loadBinding()
+
+// Streams follow the async iterator protocol: `next()` and `return()` are native
+UnisonStream.prototype[Symbol.asyncIterator] = function () {
+  return this
+}
+
+module.exports = { UnisonClient, UnisonStream }
@@ -0,0 +1,197 @@
+//! # Unison Node
+//!
+//! napi-rsによるNode.jsネイティブアドオン
+//!
+//! [`ProtocolClient`]をNode.jsへ公開し、WebSocketのブリッジを介さずにQUICトランスポートを使えるようにします。
+//!
+//! - `connect`・`call`・`disconnect`はPromiseを返します
+//! - `stream`は[`UnisonStream`]を返し、`index.js`で`for await`の非同期イテレーターになります
+//!
+//! ペイロードとレスポンスはJSONとして解釈できる任意のJavaScriptの値です。
+
+use futures_util::{Stream, StreamExt};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde_json::Value;
+use std::fmt::Display;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use unison::ProtocolClient;
+use unison::network::ProtocolClientTrait;
+
+type ItemStream = Pin<Box<dyn Stream<Item = anyhow::Result<Value>> + Send>>;
+
+fn to_js_error(error: impl Display) -> Error {
+    Error::from_reason(error.to_string())
+}
+
+/// Unisonサーバーへ接続するクライアント
+#[napi]
+pub struct UnisonClient {
+    inner: Arc<RwLock<ProtocolClient>>,
+}
+
+#[napi]
+impl UnisonClient {
+    #[napi(constructor)]
+    pub fn new() -> Result<Self> {
+        let client = ProtocolClient::new_default().map_err(|e| to_js_error(format!("{:#}", e)))?;
+        Ok(Self {
+            inner: Arc::new(RwLock::new(client)),
+        })
+    }
+
+    /// サーバーへ接続（例: `quic://127.0.0.1:8080`）
+    #[napi]
+    pub async fn connect(&self, url: String) -> Result<()> {
+        self.inner
+            .write()
+            .await
+            .connect(&url)
+            .await
+            .map_err(|e| to_js_error(format!("{:#}", e)))
+    }
+
+    /// サーバーから切断
+    #[napi]
+    pub async fn disconnect(&self) -> Result<()> {
+        self.inner
+            .write()
+            .await
+            .disconnect()
+            .await
+            .map_err(|e| to_js_error(format!("{:#}", e)))
+    }
+
+    /// 接続中か
+    #[napi]
+    pub async fn is_connected(&self) -> bool {
+        self.inner.read().await.is_connected().await
+    }
+
+    /// メソッドを呼び出してレスポンスを返す
+    #[napi]
+    pub async fn call(&self, method: String, payload: Option<Value>) -> Result<Value> {
+        unison::UnisonClient::call(
+            &*self.inner.read().await,
+            &method,
+            payload.unwrap_or(Value::Null),
+        )
+        .await
+        .map_err(to_js_error)
+    }
+
+    /// ストリーミング呼び出しを開始
+    #[napi]
+    pub async fn stream(&self, method: String, payload: Option<Value>) -> Result<UnisonStream> {
+        let stream = ProtocolClientTrait::stream::<Value, Value>(
+            &*self.inner.read().await,
+            &method,
+            payload.unwrap_or(Value::Null),
+        )
+        .await
+        .map_err(|e| to_js_error(format!("{:#}", e)))?;
+        Ok(UnisonStream {
+            inner: Arc::new(Mutex::new(Some(stream))),
+        })
+    }
+}
+
+/// ストリームから受信した結果（JavaScriptの`IteratorResult`と同じ形）
+#[napi(object)]
+#[derive(Debug)]
+pub struct StreamResult {
+    pub done: bool,
+    pub value: Option<Value>,
+}
+
+/// 受信中のストリーム
+///
+/// `next()`を`done`が`true`になるまで呼び出して要素を受信します。
+#[napi]
+pub struct UnisonStream {
+    inner: Arc<Mutex<Option<ItemStream>>>,
+}
+
+#[napi]
+impl UnisonStream {
+    /// 次の要素を受信
+    ///
+    /// エラーを受信するとPromiseが拒否され、以降は`done`を返します。
+    #[napi]
+    pub async fn next(&self) -> Result<StreamResult> {
+        let mut guard = self.inner.lock().await;
+        let Some(stream) = guard.as_mut() else {
+            return Ok(StreamResult {
+                done: true,
+                value: None,
+            });
+        };
+        match stream.next().await {
+            Some(Ok(value)) => Ok(StreamResult {
+                done: false,
+                value: Some(value),
+            }),
+            Some(Err(e)) => {
+                *guard = None;
+                Err(to_js_error(format!("{:#}", e)))
+            }
+            None => {
+                *guard = None;
+                Ok(StreamResult {
+                    done: true,
+                    value: None,
+                })
+            }
+        }
+    }
+
+    /// 受信を中止してストリームを閉じる
+    #[napi(js_name = "return")]
+    pub async fn close(&self) -> Result<StreamResult> {
+        self.inner.lock().await.take();
+        Ok(StreamResult {
+            done: true,
+            value: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_ends_after_error() {
+        let items: Vec<anyhow::Result<Value>> = vec![
+            Ok(serde_json::json!({ "n": 1 })),
+            Err(anyhow::anyhow!("boom")),
+            Ok(serde_json::json!({ "n": 2 })),
+        ];
+        let stream = UnisonStream {
+            inner: Arc::new(Mutex::new(Some(Box::pin(futures_util::stream::iter(
+                items,
+            ))))),
+        };
+
+        let first = stream.next().await.unwrap();
+        assert!(!first.done);
+        assert_eq!(first.value.unwrap()["n"], 1);
+        assert!(stream.next().await.unwrap_err().reason.contains("boom"));
+        assert!(stream.next().await.unwrap().done);
+    }
+
+    #[tokio::test]
+    async fn test_call_without_connection_rejects() {
+        let client = UnisonClient::new().unwrap();
+        assert!(!client.is_connected().await);
+        assert!(client.call("ping".into(), None).await.is_err());
+
+        let stream = UnisonStream {
+            inner: Arc::new(Mutex::new(Some(Box::pin(futures_util::stream::empty())))),
+        };
+        assert!(stream.close().await.unwrap().done);
+        assert!(stream.next().await.unwrap().done);
+    }
+}
@@ -47,12 +47,13 @@ fn bench_message_throughput(c: &mut Criterion) {
                         tokio::time::sleep(Duration::from_secs(3600)).await;
                     });
 
-                    tokio::time::sleep(Duration::from_millis(100)).await;
-
                     // クライアント接続
                     let quic_client = QuicClient::new().unwrap();
                     let mut client = ProtocolClient::new(quic_client);
-                    client.connect("127.0.0.1:8081").await.unwrap();
+                    client
+                        .wait_until_ready("127.0.0.1:8081", Duration::from_secs(5))
+                        .await
+                        .unwrap();
 
                     // バッチ送信
                     let payload = "x".repeat(payload_size);
@@ -101,12 +102,13 @@ fn bench_streaming_throughput(c: &mut Criterion) {
                     tokio::time::sleep(Duration::from_secs(3600)).await;
                 });
 
-                tokio::time::sleep(Duration::from_millis(100)).await;
-
                 // クライアント接続
                 let quic_client = QuicClient::new().unwrap();
                 let mut client = ProtocolClient::new(quic_client);
-                client.connect("127.0.0.1:8082").await.unwrap();
+                client
+                    .wait_until_ready("127.0.0.1:8082", Duration::from_secs(5))
+                    .await
+                    .unwrap();
 
                 // ストリーミング送信
                 let payload = "x".repeat(payload_size);
@@ -223,12 +225,13 @@ fn bench_burst_throughput(c: &mut Criterion) {
                     tokio::time::sleep(Duration::from_secs(3600)).await;
                 });
 
-                tokio::time::sleep(Duration::from_millis(100)).await;
-
                 // クライアント接続
                 let quic_client = QuicClient::new().unwrap();
                 let mut client = ProtocolClient::new(quic_client);
-                client.connect("127.0.0.1:8084").await.unwrap();
+                client
+                    .wait_until_ready("127.0.0.1:8084", Duration::from_secs(5))
+                    .await
+                    .unwrap();
 
                 // バースト送信
                 let start = std::time::Instant::now();
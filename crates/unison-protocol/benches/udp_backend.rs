@@ -0,0 +1,90 @@
+use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use unison::network::{
+    NetworkError, UdpBackend, UnisonClient, UnisonServer, quic::QuicClient, udp::io_uring_supported,
+};
+use unison::{ProtocolClient, ProtocolServer};
+
+/// メッセージペイロードサイズ
+const PAYLOAD_SIZES: &[usize] = &[64, 1024, 8192];
+
+/// 並列に発行する呼び出し数
+const CONCURRENT_CALLS: u64 = 100;
+
+/// 比較するUDPソケットの実装（io_uringを使えない環境では標準ソケットのみ）
+fn backends() -> Vec<(&'static str, UdpBackend, &'static str)> {
+    let mut backends = vec![("standard", UdpBackend::Standard, "[::1]:8090")];
+    if io_uring_supported() {
+        backends.push(("io_uring", UdpBackend::io_uring(), "[::1]:8091"));
+    } else {
+        eprintln!("io_uring is not available (build with --features io-uring on Linux)");
+    }
+    backends
+}
+
+/// 指定の実装でサーバーを起動し、同じ実装のクライアントで接続
+async fn connect(backend: &UdpBackend, addr: &'static str) -> ProtocolClient {
+    let server_backend = backend.clone();
+    tokio::spawn(async move {
+        let mut server = ProtocolServer::new()
+            .with_udp_backend(server_backend)
+            .with_call_handler(
+                "echo",
+                |payload| async move { Ok::<_, NetworkError>(payload) },
+            );
+        let _ = server.listen(addr).await;
+    });
+
+    let quic_client = QuicClient::new().unwrap().with_udp_backend(backend.clone());
+    let mut client = ProtocolClient::new(quic_client);
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await
+        .unwrap();
+    client
+}
+
+/// 1回の呼び出しの往復時間
+fn bench_round_trip(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("udp_round_trip");
+    for (name, backend, addr) in backends() {
+        let client = runtime.block_on(connect(&backend, addr));
+        for &payload_size in PAYLOAD_SIZES {
+            let payload = json!({ "data": "x".repeat(payload_size) });
+            group.throughput(Throughput::Bytes(payload_size as u64));
+            group.bench_function(format!("{}_{}_bytes", name, payload_size), |b| {
+                b.to_async(&runtime).iter(|| async {
+                    let response: Value = client.call("echo", payload.clone()).await.unwrap();
+                    black_box(response)
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+/// 並列の呼び出しのスループット
+fn bench_concurrent_calls(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("udp_concurrent_calls");
+    group.throughput(Throughput::Elements(CONCURRENT_CALLS));
+    for (name, backend, addr) in backends() {
+        let client = runtime.block_on(connect(&backend, addr));
+        group.bench_function(name, |b| {
+            b.to_async(&runtime).iter(|| async {
+                let calls = (0..CONCURRENT_CALLS)
+                    .map(|i| UnisonClient::call(&client, "echo", json!({ "id": i })));
+                black_box(futures_util::future::join_all(calls).await)
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_round_trip, bench_concurrent_calls);
+criterion_main!(benches);
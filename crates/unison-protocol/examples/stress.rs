@@ -0,0 +1,369 @@
+//! ソーク・負荷テスト
+//!
+//! 複数のクライアント接続から呼び出しとサーバーストリームを一定時間流し続け、
+//! スループット・レイテンシ・エラーの集計を出力します。性能に影響する変更のリリース前確認に使います。
+//!
+//! ```text
+//! # 同じプロセスでサーバーを起動して実行
+//! cargo run --release --example stress -- --connections 32 --rate 200 --duration 60
+//!
+//! # 起動済みのサーバーに対して実行（stress.echo / stress.stream を実装していること）
+//! cargo run --release --example stress -- --target "[::1]:8080" --payload-sizes 64,4096
+//!
+//! # 閾値を超えたら失敗（CI向け、集計はJSONで出力）
+//! cargo run --release --example stress -- --max-error-rate 0.001 --max-p99-ms 50 --json
+//! ```
+
+use anyhow::Result;
+use clap::Parser;
+use futures_util::StreamExt;
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolClientTrait, ProtocolServer, UnisonClient, UnisonServer,
+    UnisonServerExt,
+};
+
+const ECHO_METHOD: &str = "stress.echo";
+const STREAM_METHOD: &str = "stress.stream";
+
+#[derive(Debug, Clone, Parser)]
+#[command(about = "Unison Protocol soak/stress test")]
+struct Args {
+    /// 接続先（省略時は`--listen`で同じプロセスにサーバーを起動）
+    #[arg(long)]
+    target: Option<String>,
+    /// 同じプロセスで起動するサーバーのアドレス
+    #[arg(long, default_value = "[::1]:18600")]
+    listen: String,
+    /// クライアント接続の数
+    #[arg(long, default_value_t = 8)]
+    connections: usize,
+    /// 接続ごとの毎秒の呼び出し数（0の場合は応答を待ってすぐ次を送る）
+    #[arg(long, default_value_t = 100)]
+    rate: u64,
+    /// 実行時間（秒）
+    #[arg(long, default_value_t = 10)]
+    duration: u64,
+    /// 呼び出しのペイロードのバイト数（順に使う）
+    #[arg(long, value_delimiter = ',', default_value = "64,1024,16384")]
+    payload_sizes: Vec<usize>,
+    /// 接続ごとに並行して開くサーバーストリームの数
+    #[arg(long, default_value_t = 1)]
+    streams: usize,
+    /// ストリームごとのアイテム数
+    #[arg(long, default_value_t = 100)]
+    stream_items: u64,
+    /// エラー率がこれを超えたら失敗として終了する
+    #[arg(long)]
+    max_error_rate: Option<f64>,
+    /// 呼び出しのP99レイテンシ（ミリ秒）がこれを超えたら失敗として終了する
+    #[arg(long)]
+    max_p99_ms: Option<f64>,
+    /// 集計をJSONで出力
+    #[arg(long)]
+    json: bool,
+}
+
+/// 接続ごとの集計（終了後にまとめる）
+struct WorkerReport {
+    latency: Histogram<u64>,
+    calls: u64,
+    bytes: u64,
+    stream_items: u64,
+    streams: u64,
+    errors: BTreeMap<String, u64>,
+}
+
+impl WorkerReport {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            latency: Histogram::new_with_bounds(1, 60_000_000, 3)?,
+            calls: 0,
+            bytes: 0,
+            stream_items: 0,
+            streams: 0,
+            errors: BTreeMap::new(),
+        })
+    }
+
+    fn record_error(&mut self, error: impl std::fmt::Display) {
+        // 詳細（IDなど）を除いた先頭部分で分類する
+        let message = error.to_string();
+        let kind = message
+            .split(':')
+            .next()
+            .unwrap_or(&message)
+            .trim()
+            .to_string();
+        *self.errors.entry(kind).or_default() += 1;
+    }
+
+    fn merge(&mut self, other: WorkerReport) -> Result<()> {
+        self.latency.add(&other.latency)?;
+        self.calls += other.calls;
+        self.bytes += other.bytes;
+        self.stream_items += other.stream_items;
+        self.streams += other.streams;
+        for (kind, count) in other.errors {
+            *self.errors.entry(kind).or_default() += count;
+        }
+        Ok(())
+    }
+}
+
+/// 出力する集計
+#[derive(Debug, Serialize)]
+struct Summary {
+    connections: usize,
+    elapsed_secs: f64,
+    calls: u64,
+    calls_per_sec: f64,
+    payload_bytes_per_sec: f64,
+    streams: u64,
+    stream_items_per_sec: f64,
+    latency_us: BTreeMap<&'static str, u64>,
+    errors: u64,
+    error_rate: f64,
+    error_kinds: BTreeMap<String, u64>,
+}
+
+impl Summary {
+    fn new(args: &Args, report: &WorkerReport, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let errors: u64 = report.errors.values().sum();
+        let attempts = report.calls + report.streams + errors;
+        let latency = &report.latency;
+        Self {
+            connections: args.connections,
+            elapsed_secs: secs,
+            calls: report.calls,
+            calls_per_sec: report.calls as f64 / secs,
+            payload_bytes_per_sec: report.bytes as f64 / secs,
+            streams: report.streams,
+            stream_items_per_sec: report.stream_items as f64 / secs,
+            latency_us: BTreeMap::from([
+                ("p50", latency.value_at_quantile(0.5)),
+                ("p90", latency.value_at_quantile(0.9)),
+                ("p99", latency.value_at_quantile(0.99)),
+                ("p999", latency.value_at_quantile(0.999)),
+                ("max", latency.max()),
+            ]),
+            errors,
+            error_rate: if attempts == 0 {
+                0.0
+            } else {
+                errors as f64 / attempts as f64
+            },
+            error_kinds: report.errors.clone(),
+        }
+    }
+
+    fn print(&self) {
+        println!("connections      : {}", self.connections);
+        println!("elapsed          : {:.1} s", self.elapsed_secs);
+        println!(
+            "calls            : {} ({:.0} calls/s, {:.2} MiB/s)",
+            self.calls,
+            self.calls_per_sec,
+            self.payload_bytes_per_sec / (1024.0 * 1024.0)
+        );
+        println!(
+            "streams          : {} ({:.0} items/s)",
+            self.streams, self.stream_items_per_sec
+        );
+        println!(
+            "latency (us)     : p50={} p90={} p99={} p99.9={} max={}",
+            self.latency_us["p50"],
+            self.latency_us["p90"],
+            self.latency_us["p99"],
+            self.latency_us["p999"],
+            self.latency_us["max"]
+        );
+        println!(
+            "errors           : {} ({:.4}%)",
+            self.errors,
+            self.error_rate * 100.0
+        );
+        for (kind, count) in &self.error_kinds {
+            println!("  {:>8}  {}", count, kind);
+        }
+    }
+
+    /// 閾値を超えた項目
+    fn violations(&self, args: &Args) -> Vec<String> {
+        let mut violations = Vec::new();
+        if let Some(max) = args.max_error_rate
+            && self.error_rate > max
+        {
+            violations.push(format!("error rate {:.4} > {}", self.error_rate, max));
+        }
+        if let Some(max) = args.max_p99_ms {
+            let p99_ms = self.latency_us["p99"] as f64 / 1000.0;
+            if p99_ms > max {
+                violations.push(format!("p99 latency {:.2} ms > {} ms", p99_ms, max));
+            }
+        }
+        violations
+    }
+}
+
+fn build_server() -> ProtocolServer {
+    let mut server = ProtocolServer::new().with_call_handler(ECHO_METHOD, |payload| async move {
+        Ok::<_, NetworkError>(payload)
+    });
+    UnisonServerExt::register_stream_handler(&mut server, STREAM_METHOD, |payload: Value| {
+        let items = payload["items"].as_u64().unwrap_or(0);
+        let data = "x".repeat(payload["size"].as_u64().unwrap_or(0) as usize);
+        Box::pin(futures_util::stream::iter(
+            (0..items).map(move |i| Ok(json!({ "i": i, "data": data }))),
+        ))
+    });
+    server
+}
+
+/// 1接続分の呼び出しとストリームを期限まで流す
+async fn run_worker(args: Args, target: String, worker: usize, until: Instant) -> WorkerReport {
+    let mut report = WorkerReport::new().expect("histogram bounds are valid");
+    let mut client = match ProtocolClient::new_default() {
+        Ok(client) => client,
+        Err(e) => {
+            report.record_error(e);
+            return report;
+        }
+    };
+    if let Err(e) = UnisonClient::connect(&mut client, &target).await {
+        report.record_error(e);
+        return report;
+    }
+
+    let payloads: Vec<Value> = args
+        .payload_sizes
+        .iter()
+        .map(|size| json!({ "data": "x".repeat(*size) }))
+        .collect();
+    let calls = async {
+        let mut report = WorkerReport::new().expect("histogram bounds are valid");
+        let mut ticker = (args.rate > 0).then(|| {
+            let mut ticker = tokio::time::interval(Duration::from_secs(1) / args.rate as u32);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker
+        });
+        let mut index = worker;
+        while Instant::now() < until && !payloads.is_empty() {
+            if let Some(ticker) = &mut ticker {
+                ticker.tick().await;
+            }
+            let (payload, size) = (
+                &payloads[index % payloads.len()],
+                args.payload_sizes[index % payloads.len()],
+            );
+            index += 1;
+            let started = Instant::now();
+            match UnisonClient::call(&client, ECHO_METHOD, payload.clone()).await {
+                Ok(_) => {
+                    let micros = started.elapsed().as_micros() as u64;
+                    report.latency.saturating_record(micros.max(1));
+                    report.calls += 1;
+                    report.bytes += size as u64;
+                }
+                Err(e) => report.record_error(e),
+            }
+        }
+        report
+    };
+    let streams = futures_util::future::join_all((0..args.streams).map(|_| async {
+        let mut report = WorkerReport::new().expect("histogram bounds are valid");
+        let request = json!({ "items": args.stream_items, "size": args.payload_sizes.first() });
+        while Instant::now() < until {
+            let stream = ProtocolClientTrait::stream::<Value, Value>(
+                &client,
+                STREAM_METHOD,
+                request.clone(),
+            )
+            .await;
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    report.record_error(e);
+                    continue;
+                }
+            };
+            let mut failed = false;
+            while let Some(item) = stream.next().await {
+                match item {
+                    Ok(_) => report.stream_items += 1,
+                    Err(e) => {
+                        report.record_error(e);
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+            if !failed {
+                report.streams += 1;
+            }
+        }
+        report
+    }));
+
+    let (call_report, stream_reports) = tokio::join!(calls, streams);
+    for partial in std::iter::once(call_report).chain(stream_reports) {
+        if let Err(e) = report.merge(partial) {
+            report.record_error(e);
+        }
+    }
+    let _ = UnisonClient::disconnect(&mut client).await;
+    report
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    if !args.json {
+        tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::WARN)
+            .init();
+    }
+
+    let target = match &args.target {
+        Some(target) => target.clone(),
+        None => {
+            let mut server = build_server();
+            let listen = args.listen.clone();
+            tokio::spawn(async move { server.listen(&listen).await });
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            args.listen.clone()
+        }
+    };
+
+    let started = Instant::now();
+    let until = started + Duration::from_secs(args.duration);
+    let workers: Vec<_> = (0..args.connections)
+        .map(|worker| tokio::spawn(run_worker(args.clone(), target.clone(), worker, until)))
+        .collect();
+
+    let mut report = WorkerReport::new()?;
+    for worker in workers {
+        report.merge(worker.await?)?;
+    }
+    let summary = Summary::new(&args, &report, started.elapsed());
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        summary.print();
+    }
+    let violations = summary.violations(&args);
+    if !violations.is_empty() {
+        for violation in &violations {
+            eprintln!("threshold exceeded: {}", violation);
+        }
+        std::process::exit(1);
+    }
+    Ok(())
+}
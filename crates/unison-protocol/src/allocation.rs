@@ -0,0 +1,365 @@
+//! メモリ確保の計測
+//!
+//! 長時間動かすサーバーのメモリの増加を調べるため、確保・解放の回数とバイト数を
+//! サブシステム（パケット・QUIC・ハンドラー）ごとに集計します。
+//! 計測は任意で、アプリケーションが[`TrackingAllocator`]をグローバルアロケーターとして
+//! 登録した場合のみ有効になります（`stats_alloc`やjemallocの統計には依存しません）。
+//!
+//! ```rust,no_run
+//! use unison::allocation::TrackingAllocator;
+//!
+//! #[global_allocator]
+//! static GLOBAL: TrackingAllocator = TrackingAllocator::system();
+//! ```
+//!
+//! 確保・解放はその時点で実行中のサブシステムに計上されます。別のサブシステムで確保した
+//! メモリを解放した場合はそちらに計上されるため、サブシステムごとの差し引きは目安で、
+//! 全体の値のみ正確です。集計は[`snapshot`]、またはサーバーの組み込みメソッド
+//! [`ALLOC_STATS_METHOD`]（テナントに紐づく接続からは使用できません）で取得でき、
+//! ソークテストでは[`AllocStats::growth_since`]で基準時点からの増加を確認できます。
+
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+/// メモリ確保の集計を返す組み込みメソッド
+pub const ALLOC_STATS_METHOD: &str = "unison.admin.alloc";
+
+/// 確保を計上するサブシステム
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    /// 他のどれにも属さない処理
+    Other,
+    /// パケットのシリアライズ・デシリアライズ
+    Packet,
+    /// QUICの接続とストリームの処理
+    Quic,
+    /// 登録したハンドラーの実行
+    Handlers,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Other,
+        Subsystem::Packet,
+        Subsystem::Quic,
+        Subsystem::Handlers,
+    ];
+}
+
+thread_local! {
+    // アロケーターから参照するため、確保を伴わない定数で初期化する
+    static CURRENT: Cell<Subsystem> = const { Cell::new(Subsystem::Other) };
+}
+
+/// 実行中のサブシステム
+pub fn current_subsystem() -> Subsystem {
+    CURRENT.try_with(Cell::get).unwrap_or(Subsystem::Other)
+}
+
+/// ガードを破棄するまで、このスレッドの確保を`subsystem`に計上する
+///
+/// `await`をまたぐ場合はタスクが別のスレッドへ移るため、[`in_subsystem`]を使用してください。
+pub fn enter(subsystem: Subsystem) -> SubsystemGuard {
+    let previous = CURRENT.try_with(|current| current.replace(subsystem));
+    SubsystemGuard {
+        previous: previous.unwrap_or(Subsystem::Other),
+    }
+}
+
+/// [`enter`]のガード（破棄すると元のサブシステムに戻す）
+#[derive(Debug)]
+pub struct SubsystemGuard {
+    previous: Subsystem,
+}
+
+impl Drop for SubsystemGuard {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| current.set(self.previous));
+    }
+}
+
+/// Futureのポーリング中の確保を`subsystem`に計上する
+pub fn in_subsystem<F: Future>(subsystem: Subsystem, future: F) -> InSubsystem<F> {
+    InSubsystem { subsystem, future }
+}
+
+/// [`in_subsystem`]が返すFuture
+#[derive(Debug)]
+pub struct InSubsystem<F> {
+    subsystem: Subsystem,
+    future: F,
+}
+
+impl<F: Future> Future for InSubsystem<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let _guard = enter(self.subsystem);
+        // SAFETY: `future`はピン留めされたまま移動せず、ほかのフィールドはUnpin
+        let future = unsafe { self.map_unchecked_mut(|this| &mut this.future) };
+        future.poll(cx)
+    }
+}
+
+struct Counters {
+    allocations: AtomicU64,
+    deallocations: AtomicU64,
+    reallocations: AtomicU64,
+    allocated_bytes: AtomicU64,
+    freed_bytes: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            allocations: AtomicU64::new(0),
+            deallocations: AtomicU64::new(0),
+            reallocations: AtomicU64::new(0),
+            allocated_bytes: AtomicU64::new(0),
+            freed_bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn snapshot(&self) -> SubsystemAllocStats {
+        SubsystemAllocStats {
+            allocations: self.allocations.load(Ordering::Relaxed),
+            deallocations: self.deallocations.load(Ordering::Relaxed),
+            reallocations: self.reallocations.load(Ordering::Relaxed),
+            allocated_bytes: self.allocated_bytes.load(Ordering::Relaxed),
+            freed_bytes: self.freed_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static LIVE_BYTES: AtomicI64 = AtomicI64::new(0);
+static PEAK_BYTES: AtomicI64 = AtomicI64::new(0);
+static COUNTERS: [Counters; 4] = [
+    Counters::new(),
+    Counters::new(),
+    Counters::new(),
+    Counters::new(),
+];
+
+fn counters() -> &'static Counters {
+    &COUNTERS[current_subsystem() as usize]
+}
+
+fn record_alloc(size: usize) {
+    let counters = counters();
+    counters.allocations.fetch_add(1, Ordering::Relaxed);
+    counters
+        .allocated_bytes
+        .fetch_add(size as u64, Ordering::Relaxed);
+    grow(size as i64);
+}
+
+fn record_dealloc(size: usize) {
+    let counters = counters();
+    counters.deallocations.fetch_add(1, Ordering::Relaxed);
+    counters
+        .freed_bytes
+        .fetch_add(size as u64, Ordering::Relaxed);
+    LIVE_BYTES.fetch_sub(size as i64, Ordering::Relaxed);
+}
+
+fn record_realloc(old_size: usize, new_size: usize) {
+    let counters = counters();
+    counters.reallocations.fetch_add(1, Ordering::Relaxed);
+    if new_size >= old_size {
+        let delta = new_size - old_size;
+        counters
+            .allocated_bytes
+            .fetch_add(delta as u64, Ordering::Relaxed);
+        grow(delta as i64);
+    } else {
+        let delta = old_size - new_size;
+        counters
+            .freed_bytes
+            .fetch_add(delta as u64, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(delta as i64, Ordering::Relaxed);
+    }
+}
+
+fn grow(size: i64) {
+    INSTALLED.store(true, Ordering::Relaxed);
+    let live = LIVE_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_BYTES.fetch_max(live, Ordering::Relaxed);
+}
+
+/// 確保・解放を集計するアロケーター
+///
+/// `#[global_allocator]`として登録すると計測が有効になります。実際の確保は`inner`に委譲します。
+#[derive(Debug, Default)]
+pub struct TrackingAllocator<A = System> {
+    inner: A,
+}
+
+impl TrackingAllocator<System> {
+    /// システムアロケーターに委譲する
+    pub const fn system() -> Self {
+        Self { inner: System }
+    }
+}
+
+impl<A> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+// SAFETY: 確保はすべて`inner`に委譲し、集計はアトミック変数と確保を伴わない
+// スレッドローカル変数のみを使用する
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            record_realloc(layout.size(), new_size);
+        }
+        new_ptr
+    }
+}
+
+/// サブシステムごとの集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubsystemAllocStats {
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub reallocations: u64,
+    pub allocated_bytes: u64,
+    pub freed_bytes: u64,
+}
+
+impl SubsystemAllocStats {
+    /// 確保から解放を差し引いたバイト数
+    pub fn net_bytes(&self) -> i64 {
+        self.allocated_bytes as i64 - self.freed_bytes as i64
+    }
+}
+
+/// メモリ確保の集計
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllocStats {
+    /// [`TrackingAllocator`]が登録されているか（`false`の場合はすべて0）
+    pub enabled: bool,
+    /// 現在確保されているバイト数
+    pub live_bytes: i64,
+    /// 確保されていたバイト数の最大値
+    pub peak_bytes: i64,
+    pub subsystems: BTreeMap<Subsystem, SubsystemAllocStats>,
+}
+
+impl AllocStats {
+    /// サブシステムの集計
+    pub fn subsystem(&self, subsystem: Subsystem) -> SubsystemAllocStats {
+        self.subsystems.get(&subsystem).copied().unwrap_or_default()
+    }
+
+    /// `baseline`の時点から増えたバイト数（サブシステムごと）
+    pub fn growth_since(&self, baseline: &AllocStats) -> BTreeMap<Subsystem, i64> {
+        Subsystem::ALL
+            .into_iter()
+            .map(|subsystem| {
+                let growth = self.subsystem(subsystem).net_bytes()
+                    - baseline.subsystem(subsystem).net_bytes();
+                (subsystem, growth)
+            })
+            .collect()
+    }
+}
+
+/// [`TrackingAllocator`]が登録されているか
+pub fn is_enabled() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+/// 現在の集計
+pub fn snapshot() -> AllocStats {
+    AllocStats {
+        enabled: is_enabled(),
+        live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+        peak_bytes: PEAK_BYTES.load(Ordering::Relaxed),
+        subsystems: Subsystem::ALL
+            .into_iter()
+            .map(|subsystem| (subsystem, COUNTERS[subsystem as usize].snapshot()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subsystem_scopes() {
+        assert_eq!(current_subsystem(), Subsystem::Other);
+        {
+            let _packet = enter(Subsystem::Packet);
+            assert_eq!(current_subsystem(), Subsystem::Packet);
+            let inner = in_subsystem(Subsystem::Handlers, async {
+                tokio::task::yield_now().await;
+                current_subsystem()
+            })
+            .await;
+            assert_eq!(inner, Subsystem::Handlers);
+            assert_eq!(current_subsystem(), Subsystem::Packet);
+        }
+        assert_eq!(current_subsystem(), Subsystem::Other);
+    }
+
+    /// テストではグローバルアロケーターとして登録せず、直接呼び出して集計を確認する
+    #[test]
+    fn test_tracking_allocator_counts_by_subsystem() {
+        let allocator = TrackingAllocator::system();
+        let baseline = snapshot();
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let _guard = enter(Subsystem::Quic);
+        // `kept`は集計を確認するまで解放しない
+        let kept = unsafe {
+            let ptr = allocator.alloc(layout);
+            let ptr = allocator.realloc(ptr, layout, 128);
+            allocator.dealloc(ptr, Layout::from_size_align(128, 8).unwrap());
+            allocator.alloc_zeroed(layout)
+        };
+
+        let stats = snapshot();
+        assert!(stats.enabled);
+        let quic = stats.subsystem(Subsystem::Quic);
+        let before = baseline.subsystem(Subsystem::Quic);
+        assert_eq!(quic.allocations - before.allocations, 2);
+        assert_eq!(quic.reallocations - before.reallocations, 1);
+        assert_eq!(quic.deallocations - before.deallocations, 1);
+        assert_eq!(stats.growth_since(&baseline)[&Subsystem::Quic], 64);
+        assert_eq!(stats.growth_since(&baseline)[&Subsystem::Packet], 0);
+        assert!(stats.peak_bytes >= 128);
+        unsafe { allocator.dealloc(kept, layout) };
+    }
+}
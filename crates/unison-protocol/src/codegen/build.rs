@@ -0,0 +1,199 @@
+//! `build.rs`からスキーマのコードを生成するヘルパー
+//!
+//! 共有のKDLスキーマからRustのコードを`OUT_DIR`へ、TypeScriptのパッケージを
+//! 任意のディレクトリへ生成します。生成したRustのコードは[`include_schema!`](crate::include_schema)で取り込みます。
+//!
+//! ```no_run
+//! // build.rs
+//! use unison::codegen::{SchemaBuild, TypeScriptPackageOptions};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     SchemaBuild::new()
+//!         .with_schema("../schema/chat.kdl")
+//!         .with_typescript_package("../ts-client", TypeScriptPackageOptions::new("@example/chat"))
+//!         .run()
+//! }
+//! ```
+
+use super::{CodeGenerator, RustGenerator, TypeScriptGenerator, TypeScriptPackageOptions};
+use crate::parser::{SchemaParser, TypeRegistry};
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+
+/// [`include_schema!`](crate::include_schema)が読み込む既定のファイル名
+pub const DEFAULT_RUST_FILE: &str = "unison_schema.rs";
+
+/// スキーマからのコード生成の設定
+#[derive(Debug, Clone)]
+pub struct SchemaBuild {
+    schemas: Vec<PathBuf>,
+    rust_file: String,
+    out_dir: Option<PathBuf>,
+    typescript: Option<(PathBuf, TypeScriptPackageOptions)>,
+}
+
+impl Default for SchemaBuild {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchemaBuild {
+    pub fn new() -> Self {
+        Self {
+            schemas: Vec::new(),
+            rust_file: DEFAULT_RUST_FILE.to_string(),
+            out_dir: None,
+            typescript: None,
+        }
+    }
+
+    /// スキーマファイルを追加（複数指定した場合は指定した順に連結して1つのスキーマとして扱う）
+    pub fn with_schema(mut self, path: impl Into<PathBuf>) -> Self {
+        self.schemas.push(path.into());
+        self
+    }
+
+    /// 生成するRustのファイル名（`include_schema!("chat.rs")`で読み込む）
+    pub fn with_rust_file(mut self, name: impl Into<String>) -> Self {
+        self.rust_file = name.into();
+        self
+    }
+
+    /// Rustのコードの出力先（既定は`OUT_DIR`）
+    pub fn with_out_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(dir.into());
+        self
+    }
+
+    /// TypeScriptのパッケージを`dir`へ生成する
+    ///
+    /// 内容が変わったファイルだけを書き換えるため、生成物をリポジトリに含める場合も
+    /// 不要な差分や再ビルドは発生しません。
+    pub fn with_typescript_package(
+        mut self,
+        dir: impl Into<PathBuf>,
+        options: TypeScriptPackageOptions,
+    ) -> Self {
+        self.typescript = Some((dir.into(), options));
+        self
+    }
+
+    /// スキーマを読み込んでコードを生成する
+    ///
+    /// 各スキーマファイルには`cargo:rerun-if-changed`を出力するため、
+    /// スキーマを変更したときだけ再生成されます。
+    pub fn run(&self) -> Result<()> {
+        if self.schemas.is_empty() {
+            bail!("No schema files given to SchemaBuild");
+        }
+
+        let mut source = String::new();
+        for path in &self.schemas {
+            println!("cargo:rerun-if-changed={}", path.display());
+            let schema = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read schema {}", path.display()))?;
+            source.push_str(&schema);
+            source.push_str("\n\n");
+        }
+
+        let schema = SchemaParser::new()
+            .parse(&source)
+            .with_context(|| format!("Failed to parse {}", self.describe_schemas()))?;
+        let mut registry = TypeRegistry::new();
+        registry.update_from_typedefs(&schema.typedefs);
+
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::var_os("OUT_DIR").map(PathBuf::from).context(
+                "OUT_DIR is not set (run SchemaBuild from build.rs or use with_out_dir)",
+            )?,
+        };
+        let code = RustGenerator::new().generate(&schema, &registry)?;
+        let header = format!("// @generated by unison from {}\n", self.describe_schemas());
+        write_if_changed(&out_dir.join(&self.rust_file), &(header + &code))?;
+
+        if let Some((dir, options)) = &self.typescript {
+            let package =
+                TypeScriptGenerator::new().generate_package(&schema, &registry, options)?;
+            for file in &package.files {
+                write_if_changed(&dir.join(&file.path), &file.contents)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn describe_schemas(&self) -> String {
+        self.schemas
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// 内容が変わる場合だけ書き込む（変更がなければ更新日時も変わらない）
+fn write_if_changed(path: &Path, contents: &str) -> Result<()> {
+    if std::fs::read_to_string(path).is_ok_and(|current| current == contents) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// [`SchemaBuild`]が`OUT_DIR`に生成したRustのコードを取り込む
+///
+/// 引数を省略すると[`DEFAULT_RUST_FILE`]を読み込みます。
+#[macro_export]
+macro_rules! include_schema {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/unison_schema.rs"));
+    };
+    ($file:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/", $file));
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_schemas_are_reported() {
+        let error = SchemaBuild::new().run().unwrap_err();
+        assert!(error.to_string().contains("No schema files"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.kdl");
+        let error = SchemaBuild::new()
+            .with_schema(&missing)
+            .with_out_dir(dir.path())
+            .run()
+            .unwrap_err();
+        assert!(error.to_string().contains("missing.kdl"), "{error}");
+    }
+
+    #[test]
+    fn test_write_if_changed_keeps_unchanged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("src").join("types.ts");
+        write_if_changed(&path, "export {};\n").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        write_if_changed(&path, "export {};\n").unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            modified
+        );
+
+        write_if_changed(&path, "export type Id = string;\n").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "export type Id = string;\n"
+        );
+    }
+}
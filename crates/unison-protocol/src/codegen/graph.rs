@@ -0,0 +1,479 @@
+//! スキーマの依存関係グラフの出力
+//!
+//! サービス・メソッド・メッセージ型の参照関係をMermaidまたはGraphviz（DOT）形式で
+//! 出力します。アーキテクチャレビューでの全体像の確認に使います。
+//!
+//! - サービス → メソッド・ストリーム → リクエスト・レスポンスで参照する型
+//! - メッセージ → フィールドで参照する型（型どうしの相互参照）
+//! - どのメソッドからも到達しない型は未使用として強調
+//! - 複数のサービスから参照される型は共有として強調
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use super::CodeGenerator;
+use crate::parser::{Field, MethodMessage, ParsedSchema, TypeRegistry};
+
+/// グラフの出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    #[default]
+    Mermaid,
+    Dot,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mermaid" => Ok(Self::Mermaid),
+            "dot" | "graphviz" => Ok(Self::Dot),
+            other => Err(format!(
+                "Unknown graph format '{}' (expected mermaid or dot)",
+                other
+            )),
+        }
+    }
+}
+
+/// ノードの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Service,
+    Method,
+    Stream,
+    ClientStream,
+    Message,
+    Enum,
+    TypeDef,
+}
+
+impl NodeKind {
+    fn is_type(self) -> bool {
+        matches!(self, Self::Message | Self::Enum | Self::TypeDef)
+    }
+}
+
+/// グラフのノード
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    pub id: String,
+    pub label: String,
+    pub kind: NodeKind,
+    /// どのメソッド・ストリームからも到達しない型
+    pub unused: bool,
+    /// 複数のサービスから参照される型
+    pub shared: bool,
+}
+
+/// グラフの辺（参照元 → 参照先）
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub label: Option<String>,
+}
+
+/// スキーマの依存関係グラフ
+#[derive(Debug, Clone, Default)]
+pub struct SchemaGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl SchemaGraph {
+    /// スキーマからグラフを構築
+    pub fn from_schema(schema: &ParsedSchema) -> Self {
+        let mut graph = Self::default();
+        let protocol_messages = schema.protocol.iter().flat_map(|p| &p.messages);
+        let protocol_enums = schema.protocol.iter().flat_map(|p| &p.enums);
+
+        // 型ノード（名前 → フィールド）
+        let mut type_fields: HashMap<&str, &[Field]> = HashMap::new();
+        for message in schema.messages.iter().chain(protocol_messages) {
+            type_fields.insert(&message.name, &message.fields);
+            graph.add_node(type_id(&message.name), &message.name, NodeKind::Message);
+        }
+        for enum_def in schema.enums.iter().chain(protocol_enums) {
+            graph.add_node(type_id(&enum_def.name), &enum_def.name, NodeKind::Enum);
+        }
+        for typedef in &schema.typedefs {
+            graph.add_node(type_id(&typedef.name), &typedef.name, NodeKind::TypeDef);
+        }
+        let known: HashSet<String> = graph.nodes.iter().map(|n| n.label.clone()).collect();
+
+        let mut edges = BTreeSet::new();
+        // メソッドが直接参照する型（型名, サービス名）
+        let mut roots: Vec<(String, &str)> = Vec::new();
+
+        for service in schema.protocol.iter().flat_map(|p| &p.services) {
+            let service_id = format!("svc_{}", sanitize(&service.name));
+            graph.add_node(service_id.clone(), &service.name, NodeKind::Service);
+
+            let methods = service
+                .methods
+                .iter()
+                .map(|m| (&m.name, NodeKind::Method, &m.request, &m.response));
+            let streams = service
+                .streams
+                .iter()
+                .map(|s| (&s.name, NodeKind::Stream, &s.request, &s.response));
+            let client_streams = service
+                .client_streams
+                .iter()
+                .map(|s| (&s.name, NodeKind::ClientStream, &s.request, &s.response));
+            for (name, kind, request, response) in methods.chain(streams).chain(client_streams) {
+                let method_id = format!("{}_{}", service_id, sanitize(name));
+                graph.add_node(method_id.clone(), name, kind);
+                edges.insert(edge(&service_id, &method_id, None));
+
+                for (label, message) in [("request", request), ("response", response)] {
+                    for referenced in referenced_types(message.as_ref(), &known) {
+                        edges.insert(edge(&method_id, &type_id(&referenced), Some(label)));
+                        roots.push((referenced, &service.name));
+                    }
+                }
+            }
+        }
+
+        // メッセージ間の相互参照
+        for (name, fields) in &type_fields {
+            for field in fields.iter() {
+                if let Some(referenced) = field_reference(field, &known) {
+                    edges.insert(edge(&type_id(name), &type_id(&referenced), None));
+                }
+            }
+        }
+
+        // 型ごとに、メソッドから直接・間接に参照しているサービスを集める
+        let mut referenced_by: HashMap<String, HashSet<&str>> = HashMap::new();
+        let mut queue: VecDeque<(String, &str)> = roots.into();
+        while let Some((name, service)) = queue.pop_front() {
+            if !referenced_by
+                .entry(name.clone())
+                .or_default()
+                .insert(service)
+            {
+                continue;
+            }
+            for field in type_fields.get(name.as_str()).copied().unwrap_or_default() {
+                if let Some(referenced) = field_reference(field, &known) {
+                    queue.push_back((referenced, service));
+                }
+            }
+        }
+
+        for node in graph.nodes.iter_mut().filter(|n| n.kind.is_type()) {
+            node.unused = !referenced_by.contains_key(&node.label);
+            node.shared = referenced_by
+                .get(&node.label)
+                .is_some_and(|services| services.len() > 1);
+        }
+        graph.edges = edges.into_iter().collect();
+        graph
+    }
+
+    /// どのメソッド・ストリームからも到達しない型の名前
+    pub fn unused_types(&self) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|n| n.unused)
+            .map(|n| n.label.as_str())
+            .collect()
+    }
+
+    /// 指定の形式で出力
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Mermaid => self.to_mermaid(),
+            GraphFormat::Dot => self.to_dot(),
+        }
+    }
+
+    /// Mermaidのフローチャートとして出力
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for node in &self.nodes {
+            let label = node.label.replace('"', "#quot;");
+            let shape = match node.kind {
+                NodeKind::Service => format!("[[\"{}\"]]", label),
+                NodeKind::Method => format!("(\"{}\")", label),
+                NodeKind::Stream => format!("([\"{} (stream)\"])", label),
+                NodeKind::ClientStream => format!("([\"{} (client stream)\"])", label),
+                NodeKind::Message => format!("[\"{}\"]", label),
+                NodeKind::Enum => format!("{{{{\"{}\"}}}}", label),
+                NodeKind::TypeDef => format!("[/\"{}\"/]", label),
+            };
+            let _ = writeln!(out, "    {}{}", node.id, shape);
+        }
+        for edge in &self.edges {
+            match &edge.label {
+                Some(label) => {
+                    let _ = writeln!(out, "    {} -->|{}| {}", edge.from, label, edge.to);
+                }
+                None => {
+                    let _ = writeln!(out, "    {} --> {}", edge.from, edge.to);
+                }
+            }
+        }
+
+        let unused: Vec<&str> = self.nodes_where(|n| n.unused);
+        let shared: Vec<&str> = self.nodes_where(|n| n.shared);
+        if !unused.is_empty() {
+            out.push_str("    classDef unused stroke-dasharray:5 5,fill:#f4f4f4,color:#999\n");
+            let _ = writeln!(out, "    class {} unused", unused.join(","));
+        }
+        if !shared.is_empty() {
+            out.push_str("    classDef shared stroke:#d97706,stroke-width:2px\n");
+            let _ = writeln!(out, "    class {} shared", shared.join(","));
+        }
+        out
+    }
+
+    /// Graphviz（DOT）として出力
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph schema {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Service => "box3d",
+                NodeKind::Method => "box",
+                NodeKind::Stream | NodeKind::ClientStream => "cds",
+                NodeKind::Message => "note",
+                NodeKind::Enum => "hexagon",
+                NodeKind::TypeDef => "parallelogram",
+            };
+            let mut attrs = vec![
+                format!("label=\"{}\"", node.label.replace('"', "\\\"")),
+                format!("shape={}", shape),
+            ];
+            if node.unused {
+                attrs.push("style=dashed".to_string());
+                attrs.push("color=gray".to_string());
+            }
+            if node.shared {
+                attrs.push("color=orange".to_string());
+                attrs.push("penwidth=2".to_string());
+            }
+            let _ = writeln!(out, "    {} [{}];", node.id, attrs.join(", "));
+        }
+        for edge in &self.edges {
+            match &edge.label {
+                Some(label) => {
+                    let _ = writeln!(
+                        out,
+                        "    {} -> {} [label=\"{}\"];",
+                        edge.from, edge.to, label
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "    {} -> {};", edge.from, edge.to);
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn add_node(&mut self, id: String, label: &str, kind: NodeKind) {
+        if self.nodes.iter().any(|n| n.id == id) {
+            return;
+        }
+        self.nodes.push(GraphNode {
+            id,
+            label: label.to_string(),
+            kind,
+            unused: false,
+            shared: false,
+        });
+    }
+
+    fn nodes_where(&self, predicate: impl Fn(&GraphNode) -> bool) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|n| predicate(n))
+            .map(|n| n.id.as_str())
+            .collect()
+    }
+}
+
+/// スキーマの依存関係グラフを出力するジェネレータ
+#[derive(Debug, Clone, Default)]
+pub struct GraphGenerator {
+    format: GraphFormat,
+}
+
+impl GraphGenerator {
+    pub fn new(format: GraphFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl CodeGenerator for GraphGenerator {
+    fn generate(&self, schema: &ParsedSchema, _type_registry: &TypeRegistry) -> Result<String> {
+        Ok(SchemaGraph::from_schema(schema).render(self.format))
+    }
+}
+
+fn referenced_types(message: Option<&MethodMessage>, known: &HashSet<String>) -> Vec<String> {
+    message
+        .iter()
+        .flat_map(|m| &m.fields)
+        .filter_map(|field| field_reference(field, known))
+        .collect()
+}
+
+/// フィールドが参照するスキーマ定義の型（組み込み型の場合は`None`）
+fn field_reference(field: &Field, known: &HashSet<String>) -> Option<String> {
+    let name = field.field_type_str.as_str();
+    known.contains(name).then(|| name.to_string())
+}
+
+fn type_id(name: &str) -> String {
+    format!("type_{}", sanitize(name))
+}
+
+fn edge(from: &str, to: &str, label: Option<&str>) -> GraphEdge {
+    GraphEdge {
+        from: from.to_string(),
+        to: to.to_string(),
+        label: label.map(str::to_string),
+    }
+}
+
+/// MermaidとDOTの両方で使える識別子へ変換
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Enum, Message, Method, Protocol, Service};
+
+    fn field(name: &str, ty: &str) -> Field {
+        Field {
+            name: name.into(),
+            field_type_str: ty.into(),
+            required: true,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        }
+    }
+
+    fn method(name: &str, request: &str) -> Method {
+        Method {
+            name: name.into(),
+            description: None,
+            timeout_ms: None,
+            idempotent: false,
+            paged: false,
+            encodings: Vec::new(),
+            request: Some(MethodMessage {
+                fields: vec![field("body", request)],
+            }),
+            response: None,
+        }
+    }
+
+    fn service(name: &str, methods: Vec<Method>) -> Service {
+        Service {
+            name: name.into(),
+            description: None,
+            methods,
+            streams: vec![],
+            client_streams: vec![],
+        }
+    }
+
+    fn schema() -> ParsedSchema {
+        let message = |name: &str, fields| Message {
+            name: name.into(),
+            description: None,
+            fields,
+        };
+        ParsedSchema {
+            protocol: Some(Protocol {
+                name: "chat".into(),
+                version: "1.0.0".into(),
+                namespace: None,
+                description: None,
+                services: vec![
+                    service("Chat", vec![method("send", "Message")]),
+                    service("Audit", vec![method("log", "User")]),
+                ],
+                messages: vec![
+                    message("Message", vec![field("author", "User")]),
+                    message("User", vec![field("role", "Role"), field("name", "string")]),
+                    message("Legacy", vec![field("id", "string")]),
+                ],
+                enums: vec![Enum {
+                    name: "Role".into(),
+                    values: vec!["admin".into()],
+                }],
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_graph_references_and_unused_types() {
+        let graph = SchemaGraph::from_schema(&schema());
+        assert_eq!(graph.unused_types(), vec!["Legacy"]);
+
+        let node = |label: &str| graph.nodes.iter().find(|n| n.label == label).unwrap();
+        // UserはChat（Message経由）とAuditの両方から参照される
+        assert!(node("User").shared);
+        assert!(node("Role").shared);
+        assert!(!node("Message").shared);
+
+        assert!(
+            graph
+                .edges
+                .contains(&edge("svc_Chat_send", "type_Message", Some("request")))
+        );
+        assert!(
+            graph
+                .edges
+                .contains(&edge("type_Message", "type_User", None))
+        );
+        // 組み込み型への辺は作らない
+        assert!(!graph.edges.iter().any(|e| e.to.contains("string")));
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let graph = SchemaGraph::from_schema(&schema());
+
+        let mermaid = graph.render(GraphFormat::Mermaid);
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("svc_Chat --> svc_Chat_send"));
+        assert!(mermaid.contains("svc_Chat_send -->|request| type_Message"));
+        assert!(mermaid.contains("class type_Legacy unused"));
+
+        let dot = graph.render(GraphFormat::Dot);
+        assert!(dot.starts_with("digraph schema {"));
+        assert!(dot.contains("type_Legacy [label=\"Legacy\", shape=note, style=dashed"));
+        assert!(dot.contains("svc_Chat_send -> type_Message [label=\"request\"];"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("dot".parse::<GraphFormat>(), Ok(GraphFormat::Dot));
+        assert_eq!("Graphviz".parse::<GraphFormat>(), Ok(GraphFormat::Dot));
+        assert_eq!("mermaid".parse::<GraphFormat>(), Ok(GraphFormat::Mermaid));
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! スキーマからのモックデータ生成
+//!
+//! メソッドのリクエスト・レスポンスのフィールドから、型と制約を満たす決定的なJSON値を
+//! 生成します。生成したテストコードやサンプルのペイロードに使用します。
+
+use serde_json::{Map, Value, json};
+
+use crate::parser::{Field, FieldType, Message, MethodMessage, ParsedSchema, TypeRegistry};
+
+/// 入れ子のメッセージを展開する最大の深さ（再帰的な型の無限展開を防ぐ）
+const MAX_DEPTH: usize = 4;
+
+/// スキーマの型定義を参照してモックデータを生成
+pub struct MockDataGenerator<'a> {
+    schema: &'a ParsedSchema,
+    type_registry: &'a TypeRegistry,
+}
+
+impl<'a> MockDataGenerator<'a> {
+    pub fn new(schema: &'a ParsedSchema, type_registry: &'a TypeRegistry) -> Self {
+        Self {
+            schema,
+            type_registry,
+        }
+    }
+
+    /// リクエスト・レスポンスのモック（定義がなければ`null`）
+    pub fn method_message(&self, message: Option<&MethodMessage>) -> Value {
+        match message {
+            Some(message) => self.fields(&message.fields, 0),
+            None => Value::Null,
+        }
+    }
+
+    /// 省略可能なフィールドも含めた全フィールドのモック
+    pub fn fields(&self, fields: &[Field], depth: usize) -> Value {
+        let object: Map<String, Value> = fields
+            .iter()
+            .map(|field| (field.name.clone(), self.field(field, depth)))
+            .collect();
+        Value::Object(object)
+    }
+
+    /// フィールドの型と制約を満たす値
+    pub fn field(&self, field: &Field, depth: usize) -> Value {
+        let constraints = field.constraints();
+        match field.field_type() {
+            FieldType::String => {
+                let mut value = format!("{}-example", field.name);
+                if let Some(max) = constraints.max_length {
+                    value.truncate(max);
+                }
+                if let Some(min) = constraints.min_length {
+                    while value.len() < min {
+                        value.push('x');
+                    }
+                }
+                Value::String(value)
+            }
+            FieldType::Int => {
+                let value = 1.max(constraints.min.unwrap_or(1));
+                json!(constraints.max.map_or(value, |max| value.min(max)))
+            }
+            field_type => self.value(&field_type, depth),
+        }
+    }
+
+    fn value(&self, field_type: &FieldType, depth: usize) -> Value {
+        match field_type {
+            FieldType::String => json!("example"),
+            FieldType::Int => json!(1),
+            FieldType::Float => json!(1.5),
+            FieldType::Bool => json!(true),
+            FieldType::Json | FieldType::Object | FieldType::Map(..) => json!({}),
+            FieldType::Array(inner) => json!([self.value(inner, depth)]),
+            FieldType::Enum(values) => values.first().map_or(Value::Null, |v| json!(v)),
+            FieldType::Custom(name) => self.custom(name, depth),
+        }
+    }
+
+    /// スキーマのメッセージ・列挙型・型定義を解決
+    fn custom(&self, name: &str, depth: usize) -> Value {
+        if let Some(message) = self.find_message(name) {
+            if depth >= MAX_DEPTH {
+                return json!({});
+            }
+            return self.fields(&message.fields, depth + 1);
+        }
+        if let Some(values) = self.find_enum(name) {
+            return values.first().map_or(Value::Null, |v| json!(v));
+        }
+        match self.type_registry.get_rust_type(name).as_deref() {
+            Some(ty) if ty.contains("DateTime") => json!("2024-01-01T00:00:00Z"),
+            Some(ty) if ty.contains("Uuid") => json!("00000000-0000-4000-8000-000000000000"),
+            _ => json!(format!("{}-example", name)),
+        }
+    }
+
+    fn find_message(&self, name: &str) -> Option<&'a Message> {
+        let protocol_messages = self.schema.protocol.iter().flat_map(|p| &p.messages);
+        self.schema
+            .messages
+            .iter()
+            .chain(protocol_messages)
+            .find(|m| m.name == name)
+    }
+
+    fn find_enum(&self, name: &str) -> Option<&'a [String]> {
+        let protocol_enums = self.schema.protocol.iter().flat_map(|p| &p.enums);
+        self.schema
+            .enums
+            .iter()
+            .chain(protocol_enums)
+            .find(|e| e.name == name)
+            .map(|e| e.values.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Enum;
+
+    fn field(name: &str, ty: &str) -> Field {
+        Field {
+            name: name.into(),
+            field_type_str: ty.into(),
+            required: true,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_mock_respects_constraints() {
+        let schema = ParsedSchema::default();
+        let registry = TypeRegistry::new();
+        let mock = MockDataGenerator::new(&schema, &registry);
+
+        let mut code = field("code", "string");
+        code.max_length = Some(3);
+        let mut count = field("count", "int");
+        count.min = Some(10);
+        count.max = Some(20);
+
+        let value = mock.fields(&[code, count, field("id", "uuid")], 0);
+        assert_eq!(value["code"], "cod");
+        assert_eq!(value["count"], 10);
+        assert_eq!(value["id"], "00000000-0000-4000-8000-000000000000");
+    }
+
+    #[test]
+    fn test_mock_resolves_schema_types() {
+        let schema = ParsedSchema {
+            messages: vec![Message {
+                name: "Node".into(),
+                description: None,
+                fields: vec![field("name", "string"), field("child", "Node")],
+            }],
+            enums: vec![Enum {
+                name: "Status".into(),
+                values: vec!["active".into(), "inactive".into()],
+            }],
+            ..Default::default()
+        };
+        let registry = TypeRegistry::new();
+        let mock = MockDataGenerator::new(&schema, &registry);
+
+        let value = mock.fields(&[field("status", "Status"), field("root", "Node")], 0);
+        assert_eq!(value["status"], "active");
+        assert_eq!(value["root"]["name"], "name-example");
+        // 再帰的な型は一定の深さで打ち切る
+        assert_eq!(value["root"]["child"]["child"]["child"]["child"], json!({}));
+    }
+}
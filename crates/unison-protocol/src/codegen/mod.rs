@@ -1,11 +1,21 @@
 use crate::parser::{ParsedSchema, TypeRegistry};
 use anyhow::Result;
 
+pub mod build;
+pub mod graph;
+pub mod mock;
 pub mod rust;
+pub mod rust_tests;
 pub mod typescript;
+pub mod typescript_package;
 
+pub use build::SchemaBuild;
+pub use graph::{GraphFormat, GraphGenerator, SchemaGraph};
+pub use mock::MockDataGenerator;
 pub use rust::RustGenerator;
+pub use rust_tests::RustTestGenerator;
 pub use typescript::TypeScriptGenerator;
+pub use typescript_package::{GeneratedFile, TypeScriptPackage, TypeScriptPackageOptions};
 
 /// コードジェネレータのトレイト
 pub trait CodeGenerator {
@@ -8,12 +8,31 @@ use convert_case::{Case, Casing};
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
-#[derive(Default)]
-pub struct RustGenerator;
+/// ビルダーを生成する省略可能フィールド数の既定値
+pub const DEFAULT_BUILDER_THRESHOLD: usize = 2;
+
+pub struct RustGenerator {
+    /// 省略可能なフィールドがこの数以上のメッセージにビルダーを生成（0で無効）
+    builder_threshold: usize,
+}
 
 impl RustGenerator {
     pub fn new() -> Self {
-        Self
+        Self {
+            builder_threshold: DEFAULT_BUILDER_THRESHOLD,
+        }
+    }
+
+    /// ビルダーを生成する省略可能フィールド数を指定（0でビルダーを生成しない）
+    pub fn with_builder_threshold(mut self, threshold: usize) -> Self {
+        self.builder_threshold = threshold;
+        self
+    }
+}
+
+impl Default for RustGenerator {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -50,12 +69,15 @@ impl RustGenerator {
         quote! {
             use serde::{Deserialize, Serialize};
             use anyhow::Result;
+            #[allow(unused_imports)]
             use chrono::{DateTime, Utc};
+            #[allow(unused_imports)]
             use uuid::Uuid;
+            #[allow(unused_imports)]
             use std::collections::HashMap;
 
             #[allow(unused_imports)]
-            use crate::network::{ProtocolClient, ProtocolServer};
+            use ::unison::network::ProtocolClientTrait;
         }
     }
 
@@ -77,6 +99,9 @@ impl RustGenerator {
             tokens.extend(self.generate_service(service, type_registry));
         }
 
+        // メソッド名の定数を生成
+        tokens.extend(self.generate_method_registry(&protocol.services));
+
         tokens
     }
 
@@ -118,11 +143,125 @@ impl RustGenerator {
             .map(|f| self.generate_field(f, type_registry))
             .collect();
 
+        let builder = self.generate_builder(message, type_registry);
+
         quote! {
             #[derive(Debug, Clone, Serialize, Deserialize)]
             pub struct #name {
                 #(#fields),*
             }
+
+            #builder
+        }
+    }
+
+    /// `Message::builder()`を生成
+    ///
+    /// 必須フィールドごとに型パラメータを持ち、未設定は`()`、設定済みはフィールドの型になります。
+    /// `build()`は全ての必須フィールドが設定済みの場合のみ呼び出せるため、
+    /// 必須フィールドの設定漏れはコンパイルエラーになります。
+    fn generate_builder(&self, message: &Message, type_registry: &TypeRegistry) -> TokenStream {
+        let optional_count = message.fields.iter().filter(|f| !f.required).count();
+        if self.builder_threshold == 0 || optional_count < self.builder_threshold {
+            return TokenStream::new();
+        }
+
+        let name = format_ident!("{}", message.name);
+        let builder = format_ident!("{}Builder", message.name);
+        let required: Vec<_> = message.fields.iter().filter(|f| f.required).collect();
+        let optional: Vec<_> = message.fields.iter().filter(|f| !f.required).collect();
+
+        let ident = |f: &Field| format_ident!("{}", f.name);
+        let param = |f: &Field| format_ident!("F{}", f.name.to_case(Case::Pascal));
+        let rust_type = |f: &Field| self.field_type_to_rust(&f.field_type(), type_registry);
+
+        let params: Vec<_> = required.iter().map(|f| param(f)).collect();
+        let required_idents: Vec<_> = required.iter().map(|f| ident(f)).collect();
+        let required_types: Vec<_> = required.iter().map(|f| rust_type(f)).collect();
+        let optional_idents: Vec<_> = optional.iter().map(|f| ident(f)).collect();
+        let optional_types: Vec<_> = optional.iter().map(|f| rust_type(f)).collect();
+        let all_idents: Vec<_> = message.fields.iter().map(ident).collect();
+        let unset: Vec<_> = required.iter().map(|_| quote! { () }).collect();
+
+        // 必須フィールドの設定で対応する型パラメータを`()`からフィールドの型へ進める
+        let required_setters = required.iter().enumerate().map(|(i, field)| {
+            let setter = ident(field);
+            let ty = rust_type(field);
+            let others: Vec<_> = params
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, p)| p)
+                .collect();
+            let before: Vec<_> = params
+                .iter()
+                .enumerate()
+                .map(|(j, p)| {
+                    if j == i {
+                        quote! { () }
+                    } else {
+                        quote! { #p }
+                    }
+                })
+                .collect();
+            let after: Vec<_> = params
+                .iter()
+                .enumerate()
+                .map(|(j, p)| {
+                    if j == i {
+                        quote! { #ty }
+                    } else {
+                        quote! { #p }
+                    }
+                })
+                .collect();
+            let moved: Vec<_> = all_idents.iter().filter(|f| **f != setter).collect();
+            quote! {
+                impl<#(#others),*> #builder<#(#before),*> {
+                    pub fn #setter(self, #setter: impl Into<#ty>) -> #builder<#(#after),*> {
+                        #builder {
+                            #setter: #setter.into(),
+                            #(#moved: self.#moved),*
+                        }
+                    }
+                }
+            }
+        });
+
+        quote! {
+            #[derive(Debug, Clone)]
+            pub struct #builder<#(#params),*> {
+                #(#required_idents: #params,)*
+                #(#optional_idents: Option<#optional_types>),*
+            }
+
+            impl #name {
+                pub fn builder() -> #builder<#(#unset),*> {
+                    #builder {
+                        #(#required_idents: (),)*
+                        #(#optional_idents: None),*
+                    }
+                }
+            }
+
+            #(#required_setters)*
+
+            impl<#(#params),*> #builder<#(#params),*> {
+                #(
+                    pub fn #optional_idents(mut self, #optional_idents: impl Into<#optional_types>) -> Self {
+                        self.#optional_idents = Some(#optional_idents.into());
+                        self
+                    }
+                )*
+            }
+
+            impl #builder<#(#required_types),*> {
+                pub fn build(self) -> #name {
+                    #name {
+                        #(#all_idents: self.#all_idents),*
+                    }
+                }
+            }
         }
     }
 
@@ -244,34 +383,69 @@ impl RustGenerator {
         let client_methods: Vec<_> = service
             .methods
             .iter()
-            .map(|m| self.generate_client_method(m, type_registry))
+            .map(|m| self.generate_client_method(service, m, type_registry))
             .collect();
 
         let client_streams: Vec<_> = service
             .streams
             .iter()
-            .map(|s| self.generate_client_stream(s, type_registry))
+            .map(|s| self.generate_client_stream(service, s, type_registry))
+            .collect();
+
+        let upload_streams: Vec<_> = service
+            .client_streams
+            .iter()
+            .map(|s| self.generate_service_client_stream(s, type_registry))
             .collect();
 
+        let client_uploads: Vec<_> = service
+            .client_streams
+            .iter()
+            .map(|s| self.generate_client_client_stream(service, s, type_registry))
+            .collect();
+
+        let method_types = service
+            .methods
+            .iter()
+            .map(|m| (&m.name, &m.request, &m.response))
+            .chain(
+                service
+                    .streams
+                    .iter()
+                    .chain(&service.client_streams)
+                    .map(|s| (&s.name, &s.request, &s.response)),
+            )
+            .map(|(name, request, response)| {
+                let request = self.generate_method_message(name, request, "Request", type_registry);
+                let response =
+                    self.generate_method_message(name, response, "Response", type_registry);
+                quote! { #request #response }
+            });
+
         quote! {
+            #(#method_types)*
+
             // サービストレイト
+            #[allow(async_fn_in_trait)]
             pub trait #service_name: Send + Sync {
                 #(#methods)*
                 #(#streams)*
+                #(#upload_streams)*
             }
 
             // クライアント実装
-            pub struct #client_name {
-                inner: Box<dyn ProtocolClient>,
+            pub struct #client_name<'a, C> {
+                inner: &'a C,
             }
 
-            impl #client_name {
-                pub fn new(client: Box<dyn ProtocolClient>) -> Self {
+            impl<'a, C: ProtocolClientTrait> #client_name<'a, C> {
+                pub fn new(client: &'a C) -> Self {
                     Self { inner: client }
                 }
 
                 #(#client_methods)*
                 #(#client_streams)*
+                #(#client_uploads)*
             }
         }
     }
@@ -282,8 +456,8 @@ impl RustGenerator {
         _type_registry: &TypeRegistry,
     ) -> TokenStream {
         let name = format_ident!("{}", method.name.to_case(Case::Snake));
-        let request_type = self.method_type_name(&method.request, "Request");
-        let response_type = self.method_type_name(&method.response, "Response");
+        let request_type = self.method_type_name(&method.name, &method.request, "Request");
+        let response_type = self.method_type_name(&method.name, &method.response, "Response");
 
         quote! {
             async fn #name(&self, request: #request_type) -> Result<#response_type>;
@@ -296,8 +470,8 @@ impl RustGenerator {
         _type_registry: &TypeRegistry,
     ) -> TokenStream {
         let name = format_ident!("{}", stream.name.to_case(Case::Snake));
-        let request_type = self.method_type_name(&stream.request, "Request");
-        let response_type = self.method_type_name(&stream.response, "Response");
+        let request_type = self.method_type_name(&stream.name, &stream.request, "Request");
+        let response_type = self.method_type_name(&stream.name, &stream.response, "Response");
 
         quote! {
             async fn #name(
@@ -309,68 +483,216 @@ impl RustGenerator {
 
     fn generate_client_method(
         &self,
+        service: &Service,
         method: &Method,
         _type_registry: &TypeRegistry,
     ) -> TokenStream {
         let name = format_ident!("{}", method.name.to_case(Case::Snake));
-        let request_type = self.method_type_name(&method.request, "Request");
-        let response_type = self.method_type_name(&method.response, "Response");
-        let method_name = &method.name;
+        let request_type = self.method_type_name(&method.name, &method.request, "Request");
+        let response_type = self.method_type_name(&method.name, &method.response, "Response");
+        let method_name = self.method_const_path(service, &method.name);
+        // 冪等なメソッドはクライアントの再試行の設定で接続断後も再送できる
+        let call = if method.idempotent {
+            format_ident!("call_idempotent")
+        } else {
+            format_ident!("call")
+        };
+
+        // エンコーディングを指定したメソッドはクライアントが対応するものから選んで送る
+        let unary = if !method.encodings.is_empty() {
+            let encodings = &method.encodings;
+            let idempotent = method.idempotent;
+            quote! {
+                pub async fn #name(&self, request: #request_type) -> Result<#response_type> {
+                    self.inner
+                        .call_negotiated(#method_name, request, &[#(#encodings),*], #idempotent)
+                        .await
+                }
+            }
+        } else {
+            quote! {
+                pub async fn #name(&self, request: #request_type) -> Result<#response_type> {
+                    self.inner.#call(#method_name, request).await
+                }
+            }
+        };
+        if !method.paged {
+            return unary;
+        }
 
+        // ページに分けて返すメソッドは、全ページを順に返すStreamの呼び出しも生成する
+        let paged_name = format_ident!("{}_paged", method.name.to_case(Case::Snake));
         quote! {
-            pub async fn #name(&self, request: #request_type) -> Result<#response_type> {
-                self.inner.call(#method_name, request).await
+            #unary
+
+            pub fn #paged_name(
+                &self,
+                request: #request_type
+            ) -> std::pin::Pin<Box<dyn futures_util::Stream<Item = Result<#response_type>> + Send + 'a>> {
+                self.inner.call_paged(#method_name, request)
             }
         }
     }
 
     fn generate_client_stream(
         &self,
+        service: &Service,
         stream: &Stream,
         _type_registry: &TypeRegistry,
     ) -> TokenStream {
         let name = format_ident!("{}", stream.name.to_case(Case::Snake));
-        let request_type = self.method_type_name(&stream.request, "Request");
-        let response_type = self.method_type_name(&stream.response, "Response");
-        let stream_name = &stream.name;
+        let request_type = self.method_type_name(&stream.name, &stream.request, "Request");
+        let response_type = self.method_type_name(&stream.name, &stream.response, "Response");
+        let stream_name = self.method_const_path(service, &stream.name);
 
         quote! {
             pub async fn #name(
                 &self,
                 request: #request_type
-            ) -> Result<Box<dyn futures_util::Stream<Item = Result<#response_type>> + Send + Unpin>> {
+            ) -> Result<std::pin::Pin<Box<dyn futures_util::Stream<Item = Result<#response_type>> + Send>>> {
                 self.inner.stream(#stream_name, request).await
             }
         }
     }
 
-    fn method_type_name(&self, message: &Option<MethodMessage>, suffix: &str) -> TokenStream {
-        if let Some(msg) = message {
-            // MethodMessage は常にインライン型を生成
-            let fields: Vec<_> = msg
-                .fields
+    /// クライアントストリームのサービスメソッド（リクエストの列を受け取り1つのレスポンスを返す）
+    fn generate_service_client_stream(
+        &self,
+        stream: &Stream,
+        _type_registry: &TypeRegistry,
+    ) -> TokenStream {
+        let name = format_ident!("{}", stream.name.to_case(Case::Snake));
+        let request_type = self.method_type_name(&stream.name, &stream.request, "Request");
+        let response_type = self.method_type_name(&stream.name, &stream.response, "Response");
+
+        quote! {
+            async fn #name(
+                &self,
+                requests: Box<dyn futures_util::Stream<Item = Result<#request_type>> + Send + Unpin>
+            ) -> Result<#response_type>;
+        }
+    }
+
+    /// クライアントストリームの呼び出し（リクエストの列を送り1つのレスポンスを受け取る）
+    fn generate_client_client_stream(
+        &self,
+        service: &Service,
+        stream: &Stream,
+        _type_registry: &TypeRegistry,
+    ) -> TokenStream {
+        let name = format_ident!("{}", stream.name.to_case(Case::Snake));
+        let request_type = self.method_type_name(&stream.name, &stream.request, "Request");
+        let response_type = self.method_type_name(&stream.name, &stream.response, "Response");
+        let stream_name = self.method_const_path(service, &stream.name);
+
+        quote! {
+            pub async fn #name<S>(&self, requests: S) -> Result<#response_type>
+            where
+                S: futures_util::Stream<Item = #request_type> + Send + 'static
+            {
+                self.inner.client_stream(#stream_name, requests).await
+            }
+        }
+    }
+
+    /// `methods`モジュールにサービスごとのメソッド名の定数と一覧を生成
+    ///
+    /// ハンドラーの登録やクライアントの呼び出しで定数を使うことで、メソッド名の誤記を防ぎます。
+    fn generate_method_registry(&self, services: &[Service]) -> TokenStream {
+        if services.is_empty() {
+            return TokenStream::new();
+        }
+
+        let modules = services.iter().map(|service| {
+            let module = format_ident!("{}", service.name.to_case(Case::Snake));
+            let names: Vec<&str> = service
+                .methods
                 .iter()
-                .map(|f| {
-                    let name = format_ident!("{}", f.name);
-                    let ty = self.field_type_to_rust(&f.field_type(), &TypeRegistry::new());
-                    quote! { pub #name: #ty }
-                })
+                .map(|m| m.name.as_str())
+                .chain(service.streams.iter().map(|s| s.name.as_str()))
+                .chain(service.client_streams.iter().map(|s| s.name.as_str()))
+                .collect();
+            let consts: Vec<_> = names
+                .iter()
+                .map(|name| format_ident!("{}", name.to_case(Case::UpperSnake)))
                 .collect();
-
             quote! {
-                {
-                    #[derive(Debug, Clone, Serialize, Deserialize)]
-                    struct #suffix {
-                        #(#fields),*
-                    }
-                    #suffix
+                pub mod #module {
+                    #(pub const #consts: &str = #names;)*
+
+                    /// サービスの全メソッド名
+                    pub const ALL: &[&str] = &[#(#consts),*];
+                }
+            }
+        });
+        let service_modules = services
+            .iter()
+            .map(|s| format_ident!("{}", s.name.to_case(Case::Snake)));
+
+        quote! {
+            /// スキーマで定義されたメソッド名
+            pub mod methods {
+                #(#modules)*
+
+                /// 全サービスのメソッド名
+                pub const ALL: &[&[&str]] = &[#(#service_modules::ALL),*];
+
+                /// スキーマで定義されたメソッド名か
+                pub fn is_valid(name: &str) -> bool {
+                    ALL.iter().any(|methods| methods.contains(&name))
                 }
             }
+        }
+    }
+
+    fn method_const_path(&self, service: &Service, name: &str) -> TokenStream {
+        let module = format_ident!("{}", service.name.to_case(Case::Snake));
+        let constant = format_ident!("{}", name.to_case(Case::UpperSnake));
+        quote! { methods::#module::#constant }
+    }
+
+    /// メソッドのリクエスト・レスポンスの型名（`ping`の`Request`なら`PingRequest`、定義がなければ`()`）
+    ///
+    /// TypeScriptジェネレーターと同じ名前を使います。
+    fn method_type_name(
+        &self,
+        method: &str,
+        message: &Option<MethodMessage>,
+        suffix: &str,
+    ) -> TokenStream {
+        if message.is_some() {
+            let ident = format_ident!("{}{}", method.to_case(Case::Pascal), suffix);
+            quote! { #ident }
         } else {
             quote! { () }
         }
     }
 
+    /// メソッドのリクエスト・レスポンスの構造体を生成
+    fn generate_method_message(
+        &self,
+        method: &str,
+        message: &Option<MethodMessage>,
+        suffix: &str,
+        type_registry: &TypeRegistry,
+    ) -> TokenStream {
+        let Some(message) = message else {
+            return TokenStream::new();
+        };
+        let name = format_ident!("{}{}", method.to_case(Case::Pascal), suffix);
+        let fields = message
+            .fields
+            .iter()
+            .map(|f| self.generate_field(f, type_registry));
+
+        quote! {
+            #[derive(Debug, Clone, Serialize, Deserialize)]
+            pub struct #name {
+                #(#fields),*
+            }
+        }
+    }
+
     fn format_code(&self, code: &str) -> String {
         // 基本的なフォーマット - 本番環境ではrustfmtを使用
         code.replace(" ;", ";")
@@ -380,3 +702,246 @@ impl RustGenerator {
             .replace(", ", ",\n    ")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, ty: &str, required: bool) -> Field {
+        Field {
+            name: name.into(),
+            field_type_str: ty.into(),
+            required,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        }
+    }
+
+    fn user() -> Message {
+        Message {
+            name: "User".into(),
+            description: None,
+            fields: vec![
+                field("id", "string", true),
+                field("name", "string", true),
+                field("email", "string", false),
+                field("age", "int", false),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_builder_for_messages_with_optional_fields() {
+        let code = RustGenerator::new()
+            .generate_message(&user(), &TypeRegistry::new())
+            .to_string();
+        let file: syn::File = syn::parse_str(&code).unwrap();
+        // 構造体・ビルダー・builder()・必須フィールド2つの設定・省略可能フィールドの設定・build()
+        assert_eq!(file.items.len(), 7);
+        assert!(code.contains("pub struct UserBuilder < FId , FName >"));
+        assert!(code.contains("pub fn builder () -> UserBuilder < () , () >"));
+        assert!(code.contains("impl UserBuilder < String , String > { pub fn build"));
+    }
+
+    #[test]
+    fn test_builder_threshold() {
+        let registry = TypeRegistry::new();
+        let code = RustGenerator::new()
+            .with_builder_threshold(3)
+            .generate_message(&user(), &registry)
+            .to_string();
+        assert!(!code.contains("UserBuilder"));
+
+        let code = RustGenerator::new()
+            .with_builder_threshold(0)
+            .generate_message(&user(), &registry)
+            .to_string();
+        assert!(!code.contains("UserBuilder"));
+    }
+
+    #[test]
+    fn test_method_registry() {
+        let method = |name: &str| Method {
+            name: name.into(),
+            description: None,
+            timeout_ms: None,
+            idempotent: false,
+            paged: false,
+            encodings: Vec::new(),
+            request: None,
+            response: None,
+        };
+        let schema = ParsedSchema {
+            protocol: Some(Protocol {
+                name: "ping_pong".into(),
+                version: "1.0.0".into(),
+                namespace: None,
+                description: None,
+                services: vec![Service {
+                    name: "PingPong".into(),
+                    description: None,
+                    methods: vec![method("ping"), method("getStatus")],
+                    streams: vec![],
+                    client_streams: vec![],
+                }],
+                messages: vec![],
+                enums: vec![],
+            }),
+            ..Default::default()
+        };
+        assert!(schema.has_method("getStatus"));
+        assert!(!schema.has_method("getstatus"));
+
+        let services = &schema.protocol.as_ref().unwrap().services;
+        let code = RustGenerator::new()
+            .generate_method_registry(services)
+            .to_string();
+        syn::parse_str::<syn::File>(&code).unwrap();
+        assert!(code.contains(r#"pub const GET_STATUS : & str = "getStatus""#));
+        assert!(code.contains("pub const ALL : & [& str] = & [PING , GET_STATUS]"));
+
+        // クライアントは定数でメソッドを呼び出す
+        let client = RustGenerator::new()
+            .generate_client_method(&services[0], &method("ping"), &TypeRegistry::new())
+            .to_string();
+        assert!(client.contains("call (methods :: ping_pong :: PING , request)"));
+    }
+
+    #[test]
+    fn test_client_stream_codegen() {
+        let service = Service {
+            name: "Files".into(),
+            description: None,
+            methods: vec![],
+            streams: vec![],
+            client_streams: vec![Stream {
+                name: "upload".into(),
+                request: None,
+                response: None,
+            }],
+        };
+        let schema = ParsedSchema {
+            protocol: Some(Protocol {
+                name: "files".into(),
+                version: "1.0.0".into(),
+                namespace: None,
+                description: None,
+                services: vec![service.clone()],
+                messages: vec![],
+                enums: vec![],
+            }),
+            ..Default::default()
+        };
+        assert!(schema.has_method("upload"));
+
+        let generator = RustGenerator::new();
+        let client = generator
+            .generate_client_client_stream(
+                &service,
+                &service.client_streams[0],
+                &TypeRegistry::new(),
+            )
+            .to_string();
+        syn::parse_str::<syn::ImplItemFn>(&client).unwrap();
+        assert!(client.contains("client_stream (methods :: files :: UPLOAD , requests)"));
+
+        let code = generator
+            .generate_service(&service, &TypeRegistry::new())
+            .to_string();
+        assert!(
+            code.contains("async fn upload (& self , requests : Box < dyn futures_util :: Stream")
+        );
+        let registry = generator
+            .generate_method_registry(std::slice::from_ref(&service))
+            .to_string();
+        assert!(registry.contains(r#"pub const UPLOAD : & str = "upload""#));
+    }
+
+    #[test]
+    fn test_service_codegen_with_named_messages() {
+        let message = |fields| Some(MethodMessage { fields });
+        let service = Service {
+            name: "Chat".into(),
+            description: None,
+            methods: vec![
+                Method {
+                    name: "sendMessage".into(),
+                    description: None,
+                    timeout_ms: None,
+                    idempotent: false,
+                    paged: false,
+                    encodings: Vec::new(),
+                    request: message(vec![field("text", "string", true)]),
+                    response: message(vec![field("id", "int", true)]),
+                },
+                Method {
+                    name: "getMessage".into(),
+                    description: None,
+                    timeout_ms: None,
+                    idempotent: true,
+                    paged: true,
+                    encodings: Vec::new(),
+                    request: message(vec![field("id", "int", true)]),
+                    response: message(vec![field("text", "string", true)]),
+                },
+                Method {
+                    name: "upload".into(),
+                    description: None,
+                    timeout_ms: None,
+                    idempotent: false,
+                    paged: false,
+                    encodings: vec!["msgpack".into(), "json".into()],
+                    request: None,
+                    response: None,
+                },
+            ],
+            streams: vec![Stream {
+                name: "history".into(),
+                request: None,
+                response: message(vec![field("text", "string", true)]),
+            }],
+            client_streams: vec![],
+        };
+        let code = RustGenerator::new()
+            .generate_service(&service, &TypeRegistry::new())
+            .to_string();
+        syn::parse_str::<syn::File>(&code).unwrap();
+        // TypeScriptと同じ名前の構造体を生成し、メソッドのシグネチャで使う
+        assert!(code.contains("pub struct SendMessageRequest { pub text : String }"));
+        assert!(code.contains("pub struct HistoryResponse"));
+        assert!(!code.contains("HistoryRequest"));
+        assert!(code.contains(
+            "async fn send_message (& self , request : SendMessageRequest) -> Result < SendMessageResponse >"
+        ));
+        // クライアントは任意のProtocolClientTraitの実装を借用する
+        assert!(code.contains(
+            "impl < 'a , C : ProtocolClientTrait > ChatClient < 'a , C > { pub fn new (client : & 'a C)"
+        ));
+        assert!(code.contains("async fn history (& self , request : ()) -> Result < std :: pin :: Pin < Box < dyn futures_util :: Stream < Item = Result < HistoryResponse >> + Send >> >"));
+        // 冪等なメソッドだけを再試行できる呼び出しで送る
+        assert!(code.contains("self . inner . call (methods :: chat :: SEND_MESSAGE , request)"));
+        assert!(
+            code.contains(
+                "self . inner . call_idempotent (methods :: chat :: GET_MESSAGE , request)"
+            )
+        );
+        // エンコーディングを指定したメソッドは交渉して送る
+        assert!(code.contains(
+            r#"self . inner . call_negotiated (methods :: chat :: UPLOAD , request , & ["msgpack" , "json"] , false)"#
+        ));
+        // ページに分けて返すメソッドは全ページを返すStreamの呼び出しも生成する
+        assert!(code.contains(
+            "pub fn get_message_paged (& self , request : GetMessageRequest) -> std :: pin :: Pin < Box < dyn futures_util :: Stream < Item = Result < GetMessageResponse >> + Send + 'a >>"
+        ));
+        assert!(
+            code.contains("self . inner . call_paged (methods :: chat :: GET_MESSAGE , request)")
+        );
+        assert!(!code.contains("send_message_paged"));
+    }
+}
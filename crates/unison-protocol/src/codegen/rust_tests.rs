@@ -0,0 +1,216 @@
+use super::CodeGenerator;
+use super::mock::MockDataGenerator;
+use crate::parser::{Method, ParsedSchema, Service, TypeRegistry};
+use anyhow::Result;
+use convert_case::{Case, Casing};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+/// サービスごとの結合テストの雛形を生成
+///
+/// メソッドごとに、モックデータのリクエストをサーバーへ送り、モックデータのレスポンスが
+/// 必須フィールドを含んで返ることを確認するラウンドトリップテストを生成します。
+/// スキーマの変更に対する最低限の動作確認として、そのままテストに含められます。
+pub struct RustTestGenerator {
+    /// 生成コードから参照するクレートのパス
+    crate_path: String,
+}
+
+impl RustTestGenerator {
+    pub fn new() -> Self {
+        Self {
+            crate_path: "unison".to_string(),
+        }
+    }
+
+    /// 生成コードから参照するクレートのパスを指定（クレート内で使う場合は`crate`）
+    pub fn with_crate_path(mut self, crate_path: impl Into<String>) -> Self {
+        self.crate_path = crate_path.into();
+        self
+    }
+}
+
+impl Default for RustTestGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeGenerator for RustTestGenerator {
+    fn generate(&self, schema: &ParsedSchema, type_registry: &TypeRegistry) -> Result<String> {
+        let mut tokens = TokenStream::new();
+        if let Some(protocol) = &schema.protocol {
+            let mock = MockDataGenerator::new(schema, type_registry);
+            for service in &protocol.services {
+                tokens.extend(self.generate_service_tests(service, &mock)?);
+            }
+        }
+        Ok(tokens.to_string())
+    }
+}
+
+impl RustTestGenerator {
+    fn generate_service_tests(
+        &self,
+        service: &Service,
+        mock: &MockDataGenerator<'_>,
+    ) -> Result<TokenStream> {
+        let module = format_ident!("{}_tests", service.name.to_case(Case::Snake));
+        let crate_path: syn::Path = syn::parse_str(&self.crate_path)
+            .map_err(|e| anyhow::anyhow!("Invalid crate path '{}': {}", self.crate_path, e))?;
+        let tests = service
+            .methods
+            .iter()
+            .map(|method| self.generate_method_test(method, mock))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(quote! {
+            #[cfg(test)]
+            mod #module {
+                use #crate_path::network::{NetworkError, ProtocolServer};
+                use serde_json::Value;
+
+                #(#tests)*
+            }
+        })
+    }
+
+    fn generate_method_test(
+        &self,
+        method: &Method,
+        mock: &MockDataGenerator<'_>,
+    ) -> Result<TokenStream> {
+        let test_name = format_ident!("{}_round_trip", method.name.to_case(Case::Snake));
+        let method_name = &method.name;
+        let request = serde_json::to_string(&mock.method_message(method.request.as_ref()))?;
+        let response = serde_json::to_string(&mock.method_message(method.response.as_ref()))?;
+        let required: Vec<&str> = method
+            .response
+            .iter()
+            .flat_map(|m| &m.fields)
+            .filter(|f| f.required)
+            .map(|f| f.name.as_str())
+            .collect();
+
+        Ok(quote! {
+            #[tokio::test]
+            async fn #test_name() {
+                let request: Value = serde_json::from_str(#request).unwrap();
+                let response: Value = serde_json::from_str(#response).unwrap();
+
+                let server = ProtocolServer::new();
+                let expected = request.clone();
+                server
+                    .register_call_handler(#method_name, move |payload: Value| {
+                        let expected = expected.clone();
+                        let response = response.clone();
+                        async move {
+                            assert_eq!(payload, expected);
+                            Ok::<_, NetworkError>(response)
+                        }
+                    })
+                    .await;
+
+                let reply = server
+                    .handle_call_response(#method_name, request)
+                    .await
+                    .into_result()
+                    .unwrap();
+                let required: &[&str] = &[#(#required),*];
+                for field in required {
+                    assert!(
+                        reply.get(field).is_some(),
+                        "missing required field '{}' in {} response",
+                        field,
+                        #method_name
+                    );
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Field, MethodMessage, Protocol};
+
+    fn schema() -> ParsedSchema {
+        let field = |name: &str, ty: &str, required: bool| Field {
+            name: name.into(),
+            field_type_str: ty.into(),
+            required,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        };
+        ParsedSchema {
+            protocol: Some(Protocol {
+                name: "ping_pong".into(),
+                version: "1.0.0".into(),
+                namespace: None,
+                description: None,
+                services: vec![Service {
+                    name: "PingPong".into(),
+                    description: None,
+                    methods: vec![Method {
+                        name: "sendPing".into(),
+                        description: None,
+                        timeout_ms: None,
+                        idempotent: false,
+                        paged: false,
+                        encodings: Vec::new(),
+                        request: Some(MethodMessage {
+                            fields: vec![field("message", "string", true)],
+                        }),
+                        response: Some(MethodMessage {
+                            fields: vec![
+                                field("reply", "string", true),
+                                field("count", "int", false),
+                            ],
+                        }),
+                    }],
+                    streams: vec![],
+                    client_streams: vec![],
+                }],
+                messages: vec![],
+                enums: vec![],
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_generates_round_trip_per_method() {
+        let code = RustTestGenerator::new()
+            .generate(&schema(), &TypeRegistry::new())
+            .unwrap();
+        // 生成コードはRustとして解析できる
+        let file: syn::File = syn::parse_str(&code).unwrap();
+        assert_eq!(file.items.len(), 1);
+
+        assert!(code.contains("mod ping_pong_tests"));
+        assert!(code.contains("async fn send_ping_round_trip"));
+        assert!(code.contains("use unison :: network"));
+        assert!(code.contains(r#"\"message\":\"message-example\""#));
+    }
+
+    #[test]
+    fn test_crate_path() {
+        let code = RustTestGenerator::new()
+            .with_crate_path("crate")
+            .generate(&schema(), &TypeRegistry::new())
+            .unwrap();
+        assert!(code.contains("use crate :: network"));
+
+        let error = RustTestGenerator::new()
+            .with_crate_path("not a path!")
+            .generate(&schema(), &TypeRegistry::new());
+        assert!(error.is_err());
+    }
+}
@@ -110,7 +110,12 @@ export type LanguageCode = string; // ISO 639-1 format
             .map(|f| self.generate_field(f, type_registry))
             .collect();
 
-        format!("export interface {} {{\n{}\n}}", name, fields.join("\n"))
+        format!(
+            "export interface {} {{\n{}\n}}{}",
+            name,
+            fields.join("\n"),
+            self.generate_validator_metadata(name, &message.fields)
+        )
     }
 
     fn generate_field(&self, field: &Field, type_registry: &TypeRegistry) -> String {
@@ -141,6 +146,10 @@ export type LanguageCode = string; // ISO 639-1 format
             comments.push(format!("@pattern {}", pattern));
         }
 
+        if let Some(validator) = &field.constraints().validator {
+            comments.push(format!("@validator {}", validator));
+        }
+
         if !comments.is_empty() {
             let comment = format!("  /** {} */\n", comments.join(" "));
             field_def = format!("{}{}", comment, field_def);
@@ -149,6 +158,25 @@ export type LanguageCode = string; // ISO 639-1 format
         field_def
     }
 
+    /// 独自の検証関数を指定したフィールドと検証関数の名前の対応（なければ空）
+    fn generate_validator_metadata(&self, name: &str, fields: &[Field]) -> String {
+        let entries: Vec<String> = fields
+            .iter()
+            .filter_map(|field| {
+                let validator = field.validator.as_ref()?;
+                Some(format!("  {}: '{}',", field.name, validator))
+            })
+            .collect();
+        if entries.is_empty() {
+            return String::new();
+        }
+        format!(
+            "\n\nexport const {}Validators = {{\n{}\n}} as const;",
+            name,
+            entries.join("\n")
+        )
+    }
+
     #[allow(clippy::only_used_in_recursion)]
     fn field_type_to_typescript(
         &self,
@@ -223,6 +251,10 @@ export type LanguageCode = string; // ISO 639-1 format
             code.push_str(&self.generate_service_stream(stream, type_registry));
         }
 
+        for stream in &service.client_streams {
+            code.push_str(&self.generate_service_client_stream(stream, type_registry));
+        }
+
         code.push_str("}\n\n");
 
         // クライアントクラスを生成
@@ -237,6 +269,10 @@ export type LanguageCode = string; // ISO 639-1 format
             code.push_str(&self.generate_client_stream(stream, type_registry));
         }
 
+        for stream in &service.client_streams {
+            code.push_str(&self.generate_client_client_stream(stream, type_registry));
+        }
+
         code.push_str("}\n");
 
         code
@@ -259,7 +295,7 @@ export type LanguageCode = string; // ISO 639-1 format
             }
         }
 
-        for stream in &service.streams {
+        for stream in service.streams.iter().chain(&service.client_streams) {
             if let Some(request) = &stream.request {
                 let type_name = format!("{}Request", stream.name.to_case(Case::Pascal));
                 code.push_str(&self.generate_inline_message(&type_name, request, type_registry));
@@ -288,7 +324,12 @@ export type LanguageCode = string; // ISO 639-1 format
             .map(|f| self.generate_field(f, type_registry))
             .collect();
 
-        format!("export interface {} {{\n{}\n}}", name, fields.join("\n"))
+        format!(
+            "export interface {} {{\n{}\n}}{}",
+            name,
+            fields.join("\n"),
+            self.generate_validator_metadata(name, &message.fields)
+        )
     }
 
     fn generate_service_method(&self, method: &Method, _type_registry: &TypeRegistry) -> String {
@@ -341,6 +382,39 @@ export type LanguageCode = string; // ISO 639-1 format
         )
     }
 
+    fn generate_service_client_stream(
+        &self,
+        stream: &Stream,
+        _type_registry: &TypeRegistry,
+    ) -> String {
+        let name = stream.name.to_case(Case::Camel);
+        let request_type = self.get_method_type_name(&stream.request, &stream.name, "Request");
+        let response_type = self.get_method_type_name(&stream.response, &stream.name, "Response");
+
+        format!(
+            "  {}(requests: AsyncIterable<{}>): Promise<{}>;\n",
+            name, request_type, response_type
+        )
+    }
+
+    fn generate_client_client_stream(
+        &self,
+        stream: &Stream,
+        _type_registry: &TypeRegistry,
+    ) -> String {
+        let name = stream.name.to_case(Case::Camel);
+        let request_type = self.get_method_type_name(&stream.request, &stream.name, "Request");
+        let response_type = self.get_method_type_name(&stream.response, &stream.name, "Response");
+
+        format!(
+            r#"  async {}(requests: AsyncIterable<{}> | Iterable<{}>): Promise<{}> {{
+    return this.transport.clientStream('{}', requests);
+  }}
+"#,
+            name, request_type, request_type, response_type, stream.name
+        )
+    }
+
     fn get_method_type_name(
         &self,
         message: &Option<MethodMessage>,
@@ -359,16 +433,25 @@ export type LanguageCode = string; // ISO 639-1 format
 // WebSocketトランスポートインターフェース（生成されたファイルに含まれる）
 impl TypeScriptGenerator {
     pub fn generate_transport_interface() -> String {
-        r#"// WebSocket Transport Interface
-export interface WebSocketTransport {
+        format!(
+            r#"// WebSocket Transport Interface
+export interface WebSocketTransport {{
   call<TRequest, TResponse>(method: string, request: TRequest): Promise<TResponse>;
   stream<TRequest, TResponse>(method: string, request: TRequest): AsyncIterableIterator<TResponse>;
+  clientStream<TRequest, TResponse>(method: string, requests: AsyncIterable<TRequest> | Iterable<TRequest>): Promise<TResponse>;
   connect(url: string): Promise<void>;
   disconnect(): Promise<void>;
   isConnected(): boolean;
-}
+}}
+
+{}"#,
+            Self::websocket_transport_impl()
+        )
+    }
 
-// Basic WebSocket transport implementation
+    /// WebSocketトランスポートの実装クラス
+    pub(super) fn websocket_transport_impl() -> &'static str {
+        r#"// Basic WebSocket transport implementation
 export class WebSocketTransportImpl implements WebSocketTransport {
   private ws: WebSocket | null = null;
   private requestId = 0;
@@ -462,21 +545,49 @@ export class WebSocketTransportImpl implements WebSocketTransport {
 
     this.ws!.send(JSON.stringify(message));
 
-    while (!done) {
+    // Drain items that arrived before the end of the stream, and yield items
+    // delivered straight to a waiting consumer
+    while (!done || queue.length > 0) {
       if (queue.length > 0) {
         yield queue.shift()!;
       } else {
-        await new Promise<IteratorResult<TResponse>>((r) => {
+        const result = await new Promise<IteratorResult<TResponse>>((r) => {
           resolve = r;
-        }).then((result) => {
-          if (!result.done) {
-            return result.value;
-          }
         });
+        if (!result.done) {
+          yield result.value;
+        }
       }
     }
   }
 
+  async clientStream<TRequest, TResponse>(method: string, requests: AsyncIterable<TRequest> | Iterable<TRequest>): Promise<TResponse> {
+    if (!this.isConnected()) {
+      throw new Error('WebSocket not connected');
+    }
+
+    const id = ++this.requestId;
+    const send = (type: string, payload: unknown) => {
+      this.ws!.send(JSON.stringify({ id, method, type, payload }));
+    };
+
+    const response = new Promise<TResponse>((resolve, reject) => {
+      this.pendingRequests.set(id, { resolve, reject });
+    });
+    send('client_stream', null);
+    try {
+      for await (const request of requests) {
+        send('stream_data', request);
+      }
+    } catch (error) {
+      this.pendingRequests.delete(id);
+      send('stream_error', { message: String(error) });
+      throw error;
+    }
+    send('stream_end', {});
+    return response;
+  }
+
   private handleMessage(event: MessageEvent): void {
     try {
       const data = JSON.parse(event.data);
@@ -491,6 +602,10 @@ export class WebSocketTransportImpl implements WebSocketTransport {
             handler.resolve(data.payload);
           }
         }
+      } else if (data.type === 'error' && this.pendingRequests.has(data.id)) {
+        const handler = this.pendingRequests.get(data.id)!;
+        this.pendingRequests.delete(data.id);
+        handler.reject(new Error(data.payload?.message ?? 'Unknown error'));
       } else if (data.type === 'stream_data' || data.type === 'stream_end' || data.type === 'error') {
         const handler = this.streamHandlers.get(data.id);
         if (handler) {
@@ -511,6 +626,6 @@ export class WebSocketTransportImpl implements WebSocketTransport {
     this.streamHandlers.clear();
   }
 }
-"#.to_string()
+"#
     }
 }
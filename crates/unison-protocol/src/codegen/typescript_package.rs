@@ -0,0 +1,529 @@
+//! 公開可能なTypeScriptパッケージの生成
+//!
+//! [`TypeScriptGenerator`]の出力を、Deno・Bun・ブラウザ・Node.jsでそのまま使える
+//! ESMパッケージ（`package.json`・`deno.json`・型定義・トランスポート実装）にまとめます。
+//! 生成するコードはWeb標準のAPI（`WebSocket`・`WebTransport`・Streams）のみを使い、
+//! Node.js固有のモジュールには依存しません。
+
+use anyhow::Result;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+use super::{CodeGenerator, TypeScriptGenerator};
+use crate::parser::{ParsedSchema, TypeRegistry};
+
+/// パッケージのメタデータ
+#[derive(Debug, Clone)]
+pub struct TypeScriptPackageOptions {
+    /// パッケージ名（例: `@acme/chat-client`）
+    pub name: String,
+    /// バージョン（未指定ならプロトコルのバージョン）
+    pub version: Option<String>,
+    pub description: Option<String>,
+}
+
+impl TypeScriptPackageOptions {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: None,
+            description: None,
+        }
+    }
+
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+}
+
+/// 生成したファイル（パッケージのルートからの相対パス）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// 生成したパッケージ
+#[derive(Debug, Clone)]
+pub struct TypeScriptPackage {
+    pub files: Vec<GeneratedFile>,
+}
+
+impl TypeScriptPackage {
+    /// 相対パスでファイルを取得
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<&GeneratedFile> {
+        self.files.iter().find(|file| file.path == path.as_ref())
+    }
+
+    /// ディレクトリへ書き出す（既存のファイルは上書き）
+    pub fn write_to(&self, dir: impl AsRef<Path>) -> std::io::Result<()> {
+        for file in &self.files {
+            let path = dir.as_ref().join(&file.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, &file.contents)?;
+        }
+        Ok(())
+    }
+}
+
+impl TypeScriptGenerator {
+    /// スキーマからESMパッケージを生成
+    ///
+    /// 単一の`.ts`ファイルを返す[`CodeGenerator::generate`]と異なり、
+    /// そのまま公開できるパッケージ一式を返します。
+    /// ソースの相対インポートは`.ts`拡張子付きのため、Deno・Bunはソースを直接読み込み、
+    /// `tsc`でビルドした`dist/`は`.js`に書き換えられたインポートでNode.jsやバンドラーから使えます。
+    pub fn generate_package(
+        &self,
+        schema: &ParsedSchema,
+        type_registry: &TypeRegistry,
+        options: &TypeScriptPackageOptions,
+    ) -> Result<TypeScriptPackage> {
+        let version = options
+            .version
+            .clone()
+            .or_else(|| schema.protocol.as_ref().map(|p| p.version.clone()))
+            .unwrap_or_else(|| "0.1.0".to_string());
+
+        let mut types = String::new();
+        let has_services = schema
+            .protocol
+            .as_ref()
+            .is_some_and(|protocol| !protocol.services.is_empty());
+        if has_services {
+            types.push_str("import type { WebSocketTransport } from './transport.ts';\n\n");
+        }
+        types.push_str(&self.generate(schema, type_registry)?);
+
+        let file = |path: &str, contents: String| GeneratedFile {
+            path: PathBuf::from(path),
+            contents,
+        };
+        Ok(TypeScriptPackage {
+            files: vec![
+                file("package.json", package_json(options, &version)),
+                file("deno.json", deno_json(options, &version)),
+                file("tsconfig.json", TSCONFIG.to_string()),
+                file("README.md", readme(options)),
+                file("src/index.ts", INDEX.to_string()),
+                file("src/types.ts", types),
+                file("src/transport.ts", transport()),
+            ],
+        })
+    }
+}
+
+fn pretty(value: serde_json::Value) -> String {
+    // serde_json::Valueの整形は失敗しない
+    let mut json = serde_json::to_string_pretty(&value).expect("JSON value serializes");
+    json.push('\n');
+    json
+}
+
+fn package_json(options: &TypeScriptPackageOptions, version: &str) -> String {
+    let mut package = json!({
+        "name": options.name,
+        "version": version,
+        "type": "module",
+        "main": "./dist/index.js",
+        "types": "./dist/index.d.ts",
+        "exports": {
+            ".": {
+                "types": "./dist/index.d.ts",
+                "bun": "./src/index.ts",
+                "import": "./dist/index.js"
+            }
+        },
+        "files": ["dist", "src"],
+        "sideEffects": false,
+        "scripts": {
+            "build": "tsc",
+            "prepublishOnly": "tsc"
+        },
+        "devDependencies": {
+            "typescript": "^5.7.0"
+        }
+    });
+    if let Some(description) = &options.description {
+        package["description"] = json!(description);
+    }
+    pretty(package)
+}
+
+fn deno_json(options: &TypeScriptPackageOptions, version: &str) -> String {
+    pretty(json!({
+        "name": options.name,
+        "version": version,
+        "exports": "./src/index.ts"
+    }))
+}
+
+fn readme(options: &TypeScriptPackageOptions) -> String {
+    format!(
+        r#"# {name}
+
+{description}
+
+Generated by Unison Protocol. Do not edit manually.
+
+## Usage
+
+```ts
+import {{ WebSocketTransportImpl }} from '{name}';
+
+const transport = new WebSocketTransportImpl();
+await transport.connect('ws://localhost:8080');
+```
+
+`WebTransportTransportImpl` connects over WebTransport (`https://` URLs) where the runtime supports it.
+
+- Deno / Bun: import `src/index.ts` directly
+- Node.js / bundlers: run `npm run build` and import the ESM output in `dist/`
+"#,
+        name = options.name,
+        description = options
+            .description
+            .as_deref()
+            .unwrap_or("Typed client for a Unison Protocol service."),
+    )
+}
+
+fn transport() -> String {
+    format!(
+        "{}\n{}\n{}",
+        TRANSPORT_HEADER,
+        TypeScriptGenerator::websocket_transport_impl(),
+        WEBTRANSPORT_IMPL
+    )
+}
+
+const TSCONFIG: &str = r#"{
+  "compilerOptions": {
+    "target": "ES2022",
+    "module": "ESNext",
+    "moduleResolution": "bundler",
+    "lib": ["ES2022", "DOM", "DOM.Iterable"],
+    "strict": true,
+    "declaration": true,
+    "rewriteRelativeImportExtensions": true,
+    "rootDir": "src",
+    "outDir": "dist",
+    "skipLibCheck": true
+  },
+  "include": ["src"]
+}
+"#;
+
+const INDEX: &str = r#"// Auto-generated package entry point
+// DO NOT EDIT MANUALLY
+
+export * from './types.ts';
+export * from './transport.ts';
+"#;
+
+const TRANSPORT_HEADER: &str = r#"// Auto-generated transports using only web-standard APIs
+// DO NOT EDIT MANUALLY
+
+export interface Transport {
+  call<TRequest, TResponse>(method: string, request: TRequest): Promise<TResponse>;
+  stream<TRequest, TResponse>(method: string, request: TRequest): AsyncIterableIterator<TResponse>;
+  clientStream<TRequest, TResponse>(method: string, requests: AsyncIterable<TRequest> | Iterable<TRequest>): Promise<TResponse>;
+  connect(url: string): Promise<void>;
+  disconnect(): Promise<void>;
+  isConnected(): boolean;
+}
+
+/** Transport accepted by the generated service clients */
+export type WebSocketTransport = Transport;
+
+/** Message exchanged with the server, one JSON object per message */
+export interface WireMessage {
+  id: number;
+  method: string;
+  type: string;
+  payload?: any;
+}
+
+/** Error returned by the server */
+export class UnisonError extends Error {
+  readonly code?: number;
+  readonly details?: unknown;
+
+  constructor(payload: any) {
+    super(typeof payload?.message === 'string' ? payload.message : String(payload));
+    this.name = 'UnisonError';
+    this.code = payload?.code;
+    this.details = payload?.details;
+  }
+}
+"#;
+
+const WEBTRANSPORT_IMPL: &str = r#"// WebTransport transport implementation (one bidirectional stream per call)
+export class WebTransportTransportImpl implements Transport {
+  private transport: WebTransport | null = null;
+  private requestId = 0;
+  private connected = false;
+
+  async connect(url: string): Promise<void> {
+    const transport = new WebTransport(url);
+    await transport.ready;
+    this.transport = transport;
+    this.connected = true;
+    transport.closed
+      .catch(() => undefined)
+      .finally(() => {
+        this.connected = false;
+      });
+  }
+
+  async disconnect(): Promise<void> {
+    this.transport?.close();
+    this.transport = null;
+    this.connected = false;
+  }
+
+  isConnected(): boolean {
+    return this.connected;
+  }
+
+  async call<TRequest, TResponse>(method: string, request: TRequest): Promise<TResponse> {
+    for await (const message of this.exchange(method, 'request', request)) {
+      if (message.type === 'response') {
+        return message.payload as TResponse;
+      }
+      if (message.type === 'error') {
+        throw new UnisonError(message.payload);
+      }
+    }
+    throw new Error(`No response received for ${method}`);
+  }
+
+  async *stream<TRequest, TResponse>(method: string, request: TRequest): AsyncIterableIterator<TResponse> {
+    for await (const message of this.exchange(method, 'stream', request)) {
+      if (message.type === 'stream_data') {
+        yield message.payload as TResponse;
+      } else if (message.type === 'stream_end') {
+        return;
+      } else if (message.type === 'error' || message.type === 'stream_error') {
+        throw new UnisonError(message.payload);
+      }
+    }
+  }
+
+  async clientStream<TRequest, TResponse>(method: string, requests: AsyncIterable<TRequest> | Iterable<TRequest>): Promise<TResponse> {
+    for await (const message of this.exchange(method, 'client_stream', null, requests)) {
+      if (message.type === 'response') {
+        return message.payload as TResponse;
+      }
+      if (message.type === 'error') {
+        throw new UnisonError(message.payload);
+      }
+    }
+    throw new Error(`No response received for ${method}`);
+  }
+
+  // Sends one message (followed by `requests` for client streams) on a new stream
+  // and reads newline-delimited JSON until it closes
+  private async *exchange(
+    method: string,
+    type: string,
+    payload: unknown,
+    requests?: AsyncIterable<unknown> | Iterable<unknown>,
+  ): AsyncGenerator<WireMessage> {
+    if (!this.transport) {
+      throw new Error('WebTransport not connected');
+    }
+
+    const stream = await this.transport.createBidirectionalStream();
+    const writer = stream.writable.getWriter();
+    const id = ++this.requestId;
+    const encoder = new TextEncoder();
+    const send = (type: string, payload: unknown) =>
+      writer.write(encoder.encode(JSON.stringify({ id, method, type, payload } as WireMessage) + '\n'));
+    await send(type, payload);
+    if (requests) {
+      try {
+        for await (const request of requests) {
+          await send('stream_data', request);
+        }
+      } catch (error) {
+        await send('stream_error', { message: String(error) });
+        await writer.close();
+        throw error;
+      }
+      await send('stream_end', {});
+    }
+    await writer.close();
+
+    const reader = stream.readable.pipeThrough(new TextDecoderStream()).getReader();
+    let buffer = '';
+    try {
+      while (true) {
+        const { done, value } = await reader.read();
+        if (value) {
+          buffer += value;
+        }
+        let newline: number;
+        while ((newline = buffer.indexOf('\n')) >= 0) {
+          const line = buffer.slice(0, newline).trim();
+          buffer = buffer.slice(newline + 1);
+          if (line) {
+            yield JSON.parse(line) as WireMessage;
+          }
+        }
+        if (done) {
+          break;
+        }
+      }
+      if (buffer.trim()) {
+        yield JSON.parse(buffer) as WireMessage;
+      }
+    } finally {
+      await reader.cancel().catch(() => undefined);
+    }
+  }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Field, Method, MethodMessage, Protocol, Service};
+
+    fn field(name: &str) -> Field {
+        Field {
+            name: name.into(),
+            field_type_str: "string".into(),
+            required: true,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        }
+    }
+
+    fn schema() -> ParsedSchema {
+        ParsedSchema {
+            protocol: Some(Protocol {
+                name: "chat".into(),
+                version: "1.2.0".into(),
+                namespace: Some("example.chat".into()),
+                description: None,
+                services: vec![Service {
+                    name: "Chat".into(),
+                    description: None,
+                    methods: vec![Method {
+                        name: "send".into(),
+                        description: None,
+                        timeout_ms: None,
+                        idempotent: false,
+                        paged: false,
+                        encodings: Vec::new(),
+                        request: Some(MethodMessage {
+                            fields: vec![field("text")],
+                        }),
+                        response: Some(MethodMessage {
+                            fields: vec![field("id")],
+                        }),
+                    }],
+                    streams: vec![],
+                    client_streams: vec![],
+                }],
+                messages: vec![],
+                enums: vec![],
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_package_layout_and_metadata() {
+        let options = TypeScriptPackageOptions::new("@acme/chat").with_description("Chat client");
+        let package = TypeScriptGenerator::new()
+            .generate_package(&schema(), &TypeRegistry::new(), &options)
+            .unwrap();
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&package.file("package.json").unwrap().contents).unwrap();
+        assert_eq!(manifest["name"], "@acme/chat");
+        assert_eq!(manifest["version"], "1.2.0");
+        assert_eq!(manifest["type"], "module");
+        assert_eq!(manifest["description"], "Chat client");
+
+        let deno: serde_json::Value =
+            serde_json::from_str(&package.file("deno.json").unwrap().contents).unwrap();
+        assert_eq!(deno["exports"], "./src/index.ts");
+
+        let types = &package.file("src/types.ts").unwrap().contents;
+        assert!(types.starts_with("import type { WebSocketTransport } from './transport.ts';"));
+        assert!(types.contains("export class ChatClient"));
+    }
+
+    #[test]
+    fn test_types_include_validator_names() {
+        let mut schema = schema();
+        let method = &mut schema.protocol.as_mut().unwrap().services[0].methods[0];
+        method.request.as_mut().unwrap().fields[0].validator = Some("profanity".into());
+        let types = TypeScriptGenerator::new()
+            .generate(&schema, &TypeRegistry::new())
+            .unwrap();
+        assert!(types.contains("/** @validator profanity */"));
+        assert!(
+            types.contains(
+                "export const SendRequestValidators = {\n  text: 'profanity',\n} as const;"
+            )
+        );
+        assert!(!types.contains("SendResponseValidators"));
+    }
+
+    #[test]
+    fn test_transport_avoids_node_only_apis() {
+        let options = TypeScriptPackageOptions::new("chat").with_version("2.0.0");
+        let package = TypeScriptGenerator::new()
+            .generate_package(&schema(), &TypeRegistry::new(), &options)
+            .unwrap();
+        assert!(
+            package
+                .file("package.json")
+                .unwrap()
+                .contents
+                .contains("\"2.0.0\"")
+        );
+
+        let transport = &package.file("src/transport.ts").unwrap().contents;
+        assert!(transport.contains("export class WebSocketTransportImpl"));
+        assert!(transport.contains("export class WebTransportTransportImpl"));
+        for module in ["require(", "from 'node:", "Buffer", "process."] {
+            assert!(!transport.contains(module), "{} found", module);
+        }
+        // ストリームの終了より前に届いたアイテムも読み切る
+        assert!(transport.contains("while (!done || queue.length > 0)"));
+    }
+
+    #[test]
+    fn test_write_to_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = TypeScriptGenerator::new()
+            .generate_package(
+                &schema(),
+                &TypeRegistry::new(),
+                &TypeScriptPackageOptions::new("chat"),
+            )
+            .unwrap();
+        package.write_to(dir.path()).unwrap();
+        assert!(dir.path().join("src/index.ts").is_file());
+        assert!(dir.path().join("tsconfig.json").is_file());
+    }
+}
@@ -0,0 +1,808 @@
+//! 設定ファイル（`unison.toml`）
+//!
+//! トランスポート・上限・TLS・ログの設定を再コンパイルせずにデプロイごとに調整できるよう、
+//! 次の順に重ねて読み込みます（後のものが優先）。
+//!
+//! 1. 設定ファイル（TOML）
+//! 2. 環境変数（`UNISON_`に続けてキーを`__`で区切ったもの。例: `UNISON_SERVER__LISTEN`）
+//! 3. プログラムからの上書き（[`ConfigLoader::with_override`]）
+//!
+//! 未知のキーや型の誤りは、どの設定元（ファイルと行・環境変数）のどのキーかを示すエラーになり、
+//! 綴りの近いキーがあれば候補も示します。
+//!
+//! ```toml
+//! [server]
+//! listen = "[::]:8080"
+//! handler_timeout = "30s"
+//! shutdown_grace = "10s"
+//!
+//! [server.tls]
+//! certificate = "certs/server.pem"
+//! private_key = "certs/server.key"
+//! client_roots = ["certs/ca.pem"]
+//! client_auth = "required"
+//! reload_interval = "1m"
+//!
+//! [server.limits]
+//! inbound_limit = 33554432
+//!
+//! [client]
+//! endpoint = "api.example.com:8080"
+//! locale = "ja"
+//!
+//! [client.tls]
+//! verification = "system_roots"
+//!
+//! [logging]
+//! level = "info,unison=debug"
+//! ```
+//!
+//! 期間は`"500ms"`・`"30s"`・`"5m"`・`"1h"`の形式、または整数（ミリ秒）で指定します。
+//! ファイルのパスは実行時のカレントディレクトリからの相対パスです。
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), unison::config::ConfigError> {
+//! use unison::ProtocolServer;
+//! use unison::config::ConfigLoader;
+//!
+//! let config = ConfigLoader::new()
+//!     .with_file("unison.toml")
+//!     .with_env()
+//!     .with_override("server.listen", "[::1]:9000")
+//!     .load()?;
+//! config.logging.init()?;
+//! let server = config.server.apply(ProtocolServer::new())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use toml::Value;
+use toml::value::Table;
+
+use crate::network::{
+    CertificatePin, ClientAuth, HeartbeatConfig, MemoryQuotaConfig, ProtocolClient, ProtocolServer,
+    QuicClient, TlsConfig, TlsError, UdpSocketConfig, Verification, WireFormat,
+};
+
+/// 既定の設定ファイル
+pub const DEFAULT_CONFIG_FILE: &str = "unison.toml";
+/// 設定ファイルのパスを指定する環境変数
+pub const CONFIG_FILE_ENV: &str = "UNISON_CONFIG";
+/// 設定を上書きする環境変数の接頭辞
+pub const ENV_PREFIX: &str = "UNISON";
+
+/// 設定の読み込みエラー
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid configuration in {origin}: {message}")]
+    Invalid { origin: String, message: String },
+    #[error("Invalid value for `{key}`: {message}")]
+    InvalidValue { key: String, message: String },
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+    #[error("Failed to create the client: {0}")]
+    Client(String),
+    #[error("Failed to initialize logging: {0}")]
+    Logging(String),
+}
+
+impl ConfigError {
+    fn invalid_value(key: &str, message: impl Into<String>) -> Self {
+        Self::InvalidValue {
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// 設定全体
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnisonConfig {
+    pub client: ClientConfig,
+    pub server: ServerConfig,
+    pub logging: LoggingConfig,
+}
+
+impl UnisonConfig {
+    /// 既定の設定元から読み込む
+    ///
+    /// `UNISON_CONFIG`で指定したファイル（指定しない場合はカレントディレクトリの`unison.toml`があれば）と
+    /// `UNISON_`で始まる環境変数を重ねます。
+    pub fn load() -> Result<Self, ConfigError> {
+        let loader = match std::env::var_os(CONFIG_FILE_ENV) {
+            Some(path) => ConfigLoader::new().with_file(path),
+            None => ConfigLoader::new().with_optional_file(DEFAULT_CONFIG_FILE),
+        };
+        loader.with_env().load()
+    }
+
+    /// TOMLの文字列のみから読み込む
+    pub fn from_toml_str(source: &str) -> Result<Self, ConfigError> {
+        ConfigLoader::new().with_toml_str(source).load()
+    }
+
+    /// 値の組み合わせを検証する（型と未知のキーは読み込み時に検証済み）
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(tls) = &self.client.tls {
+            tls.validate("client.tls")?;
+        }
+        for (key, heartbeat) in [
+            ("client.heartbeat", &self.client.heartbeat),
+            ("server.heartbeat", &self.server.heartbeat),
+        ] {
+            if let Some(heartbeat) = heartbeat
+                && (heartbeat.interval.is_zero() || heartbeat.miss_threshold == 0)
+            {
+                return Err(ConfigError::invalid_value(
+                    key,
+                    "interval and miss_threshold must be greater than zero",
+                ));
+            }
+        }
+        if self.server.listen.trim().is_empty() {
+            return Err(ConfigError::invalid_value(
+                "server.listen",
+                "address is empty",
+            ));
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.logging.level)
+            .map_err(|e| ConfigError::invalid_value("logging.level", e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// クライアントの設定
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// 接続先のアドレス
+    pub endpoint: Option<String>,
+    /// WebSocket・標準入出力でのワイヤー形式
+    pub wire_format: Option<WireFormat>,
+    /// エラーメッセージのロケール
+    pub locale: Option<String>,
+    pub tls: Option<ClientTlsSettings>,
+    pub heartbeat: Option<HeartbeatConfig>,
+    pub udp: UdpSocketConfig,
+}
+
+impl ClientConfig {
+    /// 設定を反映したクライアントを作成
+    pub fn build(&self) -> Result<ProtocolClient, ConfigError> {
+        let transport = QuicClient::new()
+            .map_err(|e| ConfigError::Client(format!("{:#}", e)))?
+            .with_udp_config(self.udp.clone());
+        let mut client = ProtocolClient::new(transport);
+        if let Some(tls) = &self.tls {
+            client = client.with_tls(tls.tls_config()?);
+        }
+        if let Some(heartbeat) = self.heartbeat {
+            client = client.with_heartbeat(heartbeat);
+        }
+        if let Some(format) = self.wire_format {
+            client = client.with_wire_format(format);
+        }
+        if let Some(locale) = &self.locale {
+            client = client.with_locale(locale.clone());
+        }
+        Ok(client)
+    }
+}
+
+/// クライアントのTLSの設定
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientTlsSettings {
+    /// サーバーの証明書の検証方法
+    pub verification: Verification,
+    /// 検証に使うルート証明書（PEMファイル）
+    pub roots: Vec<PathBuf>,
+    /// `verification = "pinned"`で受け入れる証明書（[`CertificatePin`]の文字列形式）
+    pub pins: Vec<String>,
+    /// 証明書の検証とSNIに使うサーバー名
+    pub server_name: Option<String>,
+    /// mTLSで提示するクライアント証明書（PEMファイル）
+    pub certificate: Option<PathBuf>,
+    /// クライアント証明書の秘密鍵
+    pub private_key: Option<PathBuf>,
+}
+
+impl ClientTlsSettings {
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        for pin in &self.pins {
+            pin.parse::<CertificatePin>()
+                .map_err(|e| ConfigError::invalid_value(&format!("{}.pins", key), e.to_string()))?;
+        }
+        if self.verification == Verification::Pinned && self.pins.is_empty() {
+            return Err(ConfigError::invalid_value(
+                &format!("{}.pins", key),
+                "pinned verification needs at least one pin",
+            ));
+        }
+        if self.certificate.is_some() != self.private_key.is_some() {
+            return Err(ConfigError::invalid_value(
+                key,
+                "certificate and private_key must be set together",
+            ));
+        }
+        Ok(())
+    }
+
+    /// ファイルを読み込んで[`TlsConfig`]を作成
+    pub fn tls_config(&self) -> Result<TlsConfig, ConfigError> {
+        self.validate("client.tls")?;
+        let mut tls = TlsConfig::new().with_verification(self.verification);
+        for path in &self.roots {
+            tls = tls.with_root_certificates_file(path)?;
+        }
+        for pin in &self.pins {
+            tls = tls.with_pinned_certificate(pin.parse()?);
+        }
+        if let Some(server_name) = &self.server_name {
+            tls = tls.with_server_name(server_name.clone());
+        }
+        if let (Some(certificate), Some(private_key)) = (&self.certificate, &self.private_key) {
+            tls = tls.with_identity_files(certificate, private_key)?;
+        }
+        Ok(tls)
+    }
+}
+
+/// サーバーの設定
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// 待ち受けるアドレス
+    pub listen: String,
+    /// WebSocket・標準入出力でのワイヤー形式
+    pub wire_format: Option<WireFormat>,
+    /// ハンドラーの実行期限の既定値
+    #[serde(with = "duration::option")]
+    pub handler_timeout: Option<Duration>,
+    /// 停止時に処理中のハンドラーの完了を待つ期間
+    #[serde(with = "duration::option")]
+    pub shutdown_grace: Option<Duration>,
+    pub tls: Option<ServerTlsSettings>,
+    pub heartbeat: Option<HeartbeatConfig>,
+    pub udp: UdpSocketConfig,
+    /// 接続ごとのメモリ上限
+    pub limits: MemoryQuotaConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: "[::1]:8080".to_string(),
+            wire_format: None,
+            handler_timeout: None,
+            shutdown_grace: None,
+            tls: None,
+            heartbeat: None,
+            udp: UdpSocketConfig::default(),
+            limits: MemoryQuotaConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// 設定をサーバーに反映する（ハンドラーの登録は別に行う）
+    pub fn apply(&self, server: ProtocolServer) -> Result<ProtocolServer, ConfigError> {
+        let mut server = server
+            .with_udp_config(self.udp.clone())
+            .with_memory_quota(self.limits.clone());
+        if let Some(timeout) = self.handler_timeout {
+            server = server.with_handler_timeout(Some(timeout));
+        }
+        if let Some(grace) = self.shutdown_grace {
+            server = server.with_shutdown_grace(grace);
+        }
+        if let Some(format) = self.wire_format {
+            server = server.with_wire_format(format);
+        }
+        if let Some(heartbeat) = self.heartbeat {
+            server = server.with_heartbeat(heartbeat);
+        }
+        if let Some(tls) = &self.tls {
+            server = server.with_tls(tls.tls_config()?);
+            if let Some(interval) = tls.reload_interval {
+                server = server.with_certificate_reload(interval);
+            }
+        }
+        Ok(server)
+    }
+}
+
+/// サーバーのTLSの設定
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerTlsSettings {
+    /// サーバー証明書（PEMファイル）
+    pub certificate: PathBuf,
+    /// 秘密鍵（PEMまたはDERファイル）
+    pub private_key: PathBuf,
+    /// クライアント証明書の検証に使うルート証明書（PEMファイル）
+    #[serde(default)]
+    pub client_roots: Vec<PathBuf>,
+    /// クライアント証明書の要求
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+    /// 証明書ファイルの更新を確認する間隔
+    #[serde(default, with = "duration::option")]
+    pub reload_interval: Option<Duration>,
+}
+
+impl ServerTlsSettings {
+    /// ファイルを読み込んで[`TlsConfig`]を作成
+    pub fn tls_config(&self) -> Result<TlsConfig, ConfigError> {
+        let mut tls = TlsConfig::new()
+            .with_identity_files(&self.certificate, &self.private_key)?
+            .with_client_auth(self.client_auth);
+        for path in &self.client_roots {
+            tls = tls.with_root_certificates_file(path)?;
+        }
+        Ok(tls)
+    }
+}
+
+/// ログの出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Full,
+    Compact,
+}
+
+/// ログの設定
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// 出力するレベル（`RUST_LOG`と同じ形式、例: `"info,unison=debug"`）
+    pub level: String,
+    pub format: LogFormat,
+    /// 色付きで出力する
+    pub ansi: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::default(),
+            ansi: true,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// この設定でグローバルのsubscriberを登録する
+    pub fn init(&self) -> Result<(), ConfigError> {
+        let filter = tracing_subscriber::EnvFilter::try_new(&self.level)
+            .map_err(|e| ConfigError::invalid_value("logging.level", e.to_string()))?;
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_ansi(self.ansi);
+        let result = match self.format {
+            LogFormat::Full => builder.try_init(),
+            LogFormat::Compact => builder.compact().try_init(),
+        };
+        result.map_err(|e| ConfigError::Logging(e.to_string()))
+    }
+}
+
+/// 設定元
+#[derive(Debug, Clone)]
+enum Source {
+    File {
+        path: PathBuf,
+        required: bool,
+    },
+    Toml(String),
+    Env {
+        prefix: String,
+        vars: Vec<(String, String)>,
+    },
+    Override {
+        key: String,
+        value: Value,
+    },
+}
+
+/// 設定元を重ねて[`UnisonConfig`]を読み込む
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLoader {
+    sources: Vec<Source>,
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 設定ファイルを重ねる（存在しない場合はエラー）
+    pub fn with_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.sources.push(Source::File {
+            path: path.as_ref().to_path_buf(),
+            required: true,
+        });
+        self
+    }
+
+    /// 設定ファイルがあれば重ねる
+    pub fn with_optional_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.sources.push(Source::File {
+            path: path.as_ref().to_path_buf(),
+            required: false,
+        });
+        self
+    }
+
+    /// TOMLの文字列を重ねる
+    pub fn with_toml_str(mut self, source: impl Into<String>) -> Self {
+        self.sources.push(Source::Toml(source.into()));
+        self
+    }
+
+    /// `UNISON_`で始まり`__`を含む環境変数を重ねる
+    pub fn with_env(self) -> Self {
+        self.with_env_vars(ENV_PREFIX, std::env::vars())
+    }
+
+    /// 指定した接頭辞の変数を環境変数と同じ規則で重ねる（`PREFIX_SECTION__KEY`）
+    pub fn with_env_vars(
+        mut self,
+        prefix: impl Into<String>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        self.sources.push(Source::Env {
+            prefix: prefix.into(),
+            vars: vars.into_iter().collect(),
+        });
+        self
+    }
+
+    /// `.`区切りのキーの値を上書きする（例: `("server.listen", "[::1]:9000")`）
+    pub fn with_override(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.sources.push(Source::Override {
+            key: key.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// 設定元を順に重ねて読み込み、検証する
+    pub fn load(self) -> Result<UnisonConfig, ConfigError> {
+        let mut merged = Table::new();
+        for source in self.sources {
+            for (origin, layer) in source.layers()? {
+                // 設定元ごとに検証し、エラーの場所を特定できるようにする
+                Value::Table(layer.clone())
+                    .try_into::<UnisonConfig>()
+                    .map_err(|e| invalid(origin, &e))?;
+                merge(&mut merged, layer);
+            }
+        }
+        let config: UnisonConfig = Value::Table(merged)
+            .try_into()
+            .map_err(|e| invalid("merged configuration".to_string(), &e))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl Source {
+    /// 設定元の名前と値
+    fn layers(self) -> Result<Vec<(String, Table)>, ConfigError> {
+        match self {
+            Self::File { path, required } => {
+                let source = match std::fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                        return Ok(Vec::new());
+                    }
+                    Err(source) => return Err(ConfigError::Io { path, source }),
+                };
+                let origin = path.display().to_string();
+                // 行と列を含むエラーにするため、文字列から直接検証する
+                toml::from_str::<UnisonConfig>(&source).map_err(|e| invalid(origin.clone(), &e))?;
+                let table = toml::from_str(&source).map_err(|e| invalid(origin.clone(), &e))?;
+                Ok(vec![(origin, table)])
+            }
+            Self::Toml(source) => {
+                let origin = "TOML source".to_string();
+                toml::from_str::<UnisonConfig>(&source).map_err(|e| invalid(origin.clone(), &e))?;
+                let table = toml::from_str(&source).map_err(|e| invalid(origin.clone(), &e))?;
+                Ok(vec![(origin, table)])
+            }
+            Self::Env { prefix, vars } => {
+                let prefix = format!("{}_", prefix);
+                let mut layers = Vec::new();
+                for (name, raw) in vars {
+                    let Some(path) = name.strip_prefix(&prefix) else {
+                        continue;
+                    };
+                    if !path.contains("__") {
+                        continue;
+                    }
+                    let keys: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+                    layers.push((
+                        format!("environment variable {}", name),
+                        nested(&keys, parse_env_value(&raw)),
+                    ));
+                }
+                // 環境変数の順序は不定のため、名前順に重ねる
+                layers.sort_by(|a, b| a.0.cmp(&b.0));
+                Ok(layers)
+            }
+            Self::Override { key, value } => {
+                let keys: Vec<String> = key.split('.').map(str::to_string).collect();
+                Ok(vec![(format!("override `{}`", key), nested(&keys, value))])
+            }
+        }
+    }
+}
+
+/// 環境変数の値をTOMLの値として解釈する（数値・真偽値・配列など、解釈できない場合は文字列）
+fn parse_env_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn nested(keys: &[String], value: Value) -> Table {
+    let mut value = value;
+    for key in keys.iter().skip(1).rev() {
+        value = Value::Table(Table::from_iter([(key.clone(), value)]));
+    }
+    Table::from_iter([(keys[0].clone(), value)])
+}
+
+/// `layer`の値で`base`を上書きする（テーブルは再帰的に重ねる）
+fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn invalid(origin: String, error: &toml::de::Error) -> ConfigError {
+    let mut message = error.to_string();
+    if let Some(suggestion) = suggest_field(&message) {
+        message.push_str(&format!(" (did you mean `{}`?)", suggestion));
+    }
+    ConfigError::Invalid { origin, message }
+}
+
+/// 未知のキーのエラーから綴りの近いキーを探す
+fn suggest_field(message: &str) -> Option<String> {
+    let rest = message.strip_prefix("unknown field ")?;
+    let mut quoted = rest.split('`').skip(1).step_by(2);
+    let unknown = quoted.next()?;
+    quoted
+        .map(|candidate| (edit_distance(unknown, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// 期間の読み込み（`"500ms"`・`"30s"`・`"5m"`・`"1h"`、または整数のミリ秒）
+pub(crate) mod duration {
+    use serde::Deserializer;
+    use serde::de::{self, Visitor};
+    use std::fmt;
+    use std::time::Duration;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+
+    /// 期間の文字列を解釈する
+    pub fn parse(value: &str) -> Result<Duration, String> {
+        let value = value.trim();
+        let split = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration `{}`", value))?;
+        let seconds = match unit.trim() {
+            "ms" | "" => number / 1000.0,
+            "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => {
+                return Err(format!(
+                    "invalid duration unit in `{}` (use ms, s, m or h)",
+                    value
+                ));
+            }
+        };
+        Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+    }
+
+    struct DurationVisitor;
+
+    impl Visitor<'_> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a duration such as \"30s\" or milliseconds")
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Duration, E> {
+            u64::try_from(value)
+                .map(Duration::from_millis)
+                .map_err(|_| E::custom("duration must not be negative"))
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Duration, E> {
+            Ok(Duration::from_millis(value))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Duration, E> {
+            parse(value).map_err(E::custom)
+        }
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer};
+        use std::time::Duration;
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super")] Duration);
+            Ok(Some(Wrapper::deserialize(deserializer)?.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+[server]
+listen = "[::]:8080"
+handler_timeout = "30s"
+
+[server.heartbeat]
+interval = "5s"
+
+[server.limits]
+inbound_limit = 1048576
+
+[client]
+endpoint = "api.example.com:8080"
+
+[logging]
+level = "warn"
+"#;
+
+    #[test]
+    fn test_layers_override_in_order() {
+        let config = ConfigLoader::new()
+            .with_toml_str(FILE)
+            .with_env_vars(
+                "UNISON",
+                [
+                    (
+                        "UNISON_SERVER__LISTEN".to_string(),
+                        "[::1]:9000".to_string(),
+                    ),
+                    (
+                        "UNISON_SERVER__UDP__RECV_BUFFER_SIZE".into(),
+                        "65536".into(),
+                    ),
+                    ("UNISON_CONFIG_DIR".into(), "/ignored".into()),
+                    ("OTHER_SERVER__LISTEN".into(), "ignored".into()),
+                ],
+            )
+            .with_override("logging.level", "debug")
+            .load()
+            .unwrap();
+
+        assert_eq!(config.server.listen, "[::1]:9000");
+        assert_eq!(config.server.handler_timeout, Some(Duration::from_secs(30)));
+        assert_eq!(config.server.udp.recv_buffer_size, Some(65536));
+        // ファイルで指定していない項目は既定値
+        let heartbeat = config.server.heartbeat.unwrap();
+        assert_eq!(heartbeat.interval, Duration::from_secs(5));
+        assert_eq!(
+            heartbeat.miss_threshold,
+            HeartbeatConfig::default().miss_threshold
+        );
+        assert_eq!(config.server.limits.inbound_limit, 1048576);
+        assert_eq!(
+            config.server.limits.outbound_limit,
+            MemoryQuotaConfig::default().outbound_limit
+        );
+        assert_eq!(
+            config.client.endpoint.as_deref(),
+            Some("api.example.com:8080")
+        );
+        assert_eq!(config.logging.level, "debug");
+    }
+
+    #[test]
+    fn test_unknown_keys_report_origin_and_suggestion() {
+        let error = UnisonConfig::from_toml_str("[server]\nlisen = \"[::1]:8080\"\n").unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("TOML source"), "{}", message);
+        assert!(
+            message.contains("for key `server` at line 1"),
+            "{}",
+            message
+        );
+        assert!(message.contains("did you mean `listen`?"), "{}", message);
+
+        let error = ConfigLoader::new()
+            .with_env_vars(
+                "UNISON",
+                [("UNISON_SERVER__TLS__CLIENT_AUTH".into(), "sometimes".into())],
+            )
+            .load()
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("environment variable UNISON_SERVER__TLS__CLIENT_AUTH"),
+            "{}",
+            error
+        );
+
+        let error =
+            UnisonConfig::from_toml_str("[client.tls]\nverification = \"pinned\"\n").unwrap_err();
+        assert!(
+            matches!(&error, ConfigError::InvalidValue { key, .. } if key == "client.tls.pins")
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(
+            duration::parse("250ms").unwrap(),
+            Duration::from_millis(250)
+        );
+        assert_eq!(
+            duration::parse("1.5s").unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(duration::parse("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(duration::parse("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(duration::parse("100").unwrap(), Duration::from_millis(100));
+        assert!(duration::parse("5 days").is_err());
+        assert!(UnisonConfig::from_toml_str("[server]\nshutdown_grace = -1\n").is_err());
+    }
+}
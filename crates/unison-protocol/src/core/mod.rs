@@ -5,6 +5,7 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 /// Unisonプロトコルの標準メッセージフォーマット
@@ -44,7 +45,10 @@ pub struct UnisonResponse {
 }
 
 /// Structured error information for Unison protocol
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Handlers can return this error to send an application-specific `code` to the caller,
+/// which receives it as `NetworkError::Remote`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UnisonError {
     /// Error code identifier
     pub code: String,
@@ -70,6 +74,15 @@ pub struct HandshakeRequest {
     /// List of client-supported features
     #[serde(default)]
     pub supported_features: Vec<String>,
+    /// Hash of the client's schema (not checked when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_hash: Option<String>,
+    /// Methods defined by the client's schema (reported when the schemas differ)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schema_methods: Vec<String>,
+    /// Connection-level metadata such as the preferred locale
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
 }
 
 /// Handshake response from server
@@ -86,6 +99,12 @@ pub struct HandshakeResponse {
     /// Heartbeat interval in milliseconds
     #[serde(skip_serializing_if = "Option::is_none")]
     pub heartbeat_interval: Option<u64>,
+    /// Hash of the server's schema (not checked when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_hash: Option<String>,
+    /// Methods defined by the server's schema
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schema_methods: Vec<String>,
 }
 
 /// Ping request for connection health check
@@ -176,6 +195,14 @@ impl UnisonResponse {
     }
 }
 
+impl std::fmt::Display for UnisonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for UnisonError {}
+
 impl UnisonError {
     /// Create a new Unison error
     pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
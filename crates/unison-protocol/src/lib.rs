@@ -46,7 +46,9 @@
 //! プロトコル定義は、ビルドプロセス中に自動的に強く型付けされた
 //! 分散ノード実装コードにコンパイルされます。
 
+pub mod allocation;
 pub mod codegen;
+pub mod config;
 pub mod network;
 pub mod parser;
 
@@ -69,7 +71,7 @@ pub mod generated {
 }
 
 // preludeの型を内部で使用
-use codegen::{CodeGenerator, RustGenerator, TypeScriptGenerator};
+use codegen::{CodeGenerator, RustGenerator, RustTestGenerator, TypeScriptGenerator};
 use parser::{ParseError as UnisonParseError, ParsedSchema, SchemaParser};
 
 // よく使用されるトレイトとクライアント/サーバーの再エクスポート
@@ -113,6 +115,20 @@ impl UnisonProtocol {
         Ok(code)
     }
 
+    /// 読み込んだスキーマの各サービスのラウンドトリップテストを生成
+    pub fn generate_rust_tests(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let generator = RustTestGenerator::new();
+        let type_registry = crate::parser::TypeRegistry::new();
+        let mut code = String::new();
+
+        for schema in &self.schemas {
+            code.push_str(&generator.generate(schema, &type_registry)?);
+            code.push('\n');
+        }
+
+        Ok(code)
+    }
+
     /// 読み込んだスキーマからTypeScriptコードを生成
     pub fn generate_typescript_code(&self) -> Result<String, Box<dyn std::error::Error>> {
         let generator = TypeScriptGenerator::new();
@@ -133,8 +149,14 @@ impl UnisonProtocol {
     }
 
     /// 新しいUnisonサーバーを作成
+    ///
+    /// 読み込んだスキーマの各サービスには`<service>.ping`・`<service>.describe`が登録されます。
     pub fn create_server(&self) -> ProtocolServer {
-        ProtocolServer::new()
+        self.schemas
+            .iter()
+            .fold(ProtocolServer::new(), |server, schema| {
+                server.with_schema_services(schema)
+            })
     }
 }
 
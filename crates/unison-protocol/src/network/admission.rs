@@ -0,0 +1,374 @@
+//! 同時に実行するハンドラーの数の制限（アドミッション制御）
+//!
+//! 急増したリクエストでメモリを使い果たさないよう、サーバー全体とメソッドごとに
+//! 同時に実行するハンドラーの数を制限します。
+//!
+//! - 上限に達している間に届いたリクエストは、待ち行列の上限まで空きを待ちます
+//! - 待ち行列も埋まっている場合と、待ち時間の上限を過ぎた場合は`UNAVAILABLE`で拒否します
+//!   （負荷の切り捨て）
+//! - 処理中・待機中の数は[`AdmissionStats`]として
+//!   [`ProtocolServer::stats`](super::ProtocolServer::stats)の`admission`に含まれます
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// 同時に実行する数の上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConcurrencyLimit {
+    /// 同時に実行するハンドラーの最大数
+    pub max_in_flight: usize,
+    /// 空きを待つリクエストの最大数（0の場合は待たずに拒否する）
+    pub max_queued: usize,
+}
+
+impl ConcurrencyLimit {
+    /// 待ち行列なしの上限
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            max_queued: 0,
+        }
+    }
+
+    pub fn with_queue(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+}
+
+/// アドミッション制御の設定
+#[derive(Debug, Clone, Default)]
+pub struct AdmissionConfig {
+    /// サーバー全体の上限
+    pub global: Option<ConcurrencyLimit>,
+    /// メソッドごとの上限
+    pub methods: HashMap<String, ConcurrencyLimit>,
+    /// 待ち行列で空きを待つ時間の上限（`None`の場合は空くまで待つ）
+    pub queue_timeout: Option<Duration>,
+}
+
+impl AdmissionConfig {
+    pub fn with_global_limit(mut self, limit: ConcurrencyLimit) -> Self {
+        self.global = Some(limit);
+        self
+    }
+
+    pub fn with_method_limit(mut self, method: impl Into<String>, limit: ConcurrencyLimit) -> Self {
+        self.methods.insert(method.into(), limit);
+        self
+    }
+
+    pub fn with_queue_timeout(mut self, timeout: Duration) -> Self {
+        self.queue_timeout = Some(timeout);
+        self
+    }
+}
+
+/// 上限に達した範囲
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionScope {
+    /// サーバー全体の上限
+    Global,
+    /// メソッドごとの上限
+    Method(String),
+}
+
+impl fmt::Display for AdmissionScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Global => f.write_str("global"),
+            Self::Method(method) => write!(f, "method '{}'", method),
+        }
+    }
+}
+
+/// 拒否した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// 待ち行列が埋まっていた
+    QueueFull,
+    /// 待ち時間の上限を過ぎた
+    QueueTimeout,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull => f.write_str("queue_full"),
+            Self::QueueTimeout => f.write_str("queue_timeout"),
+        }
+    }
+}
+
+/// アドミッション制御による拒否
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Too many concurrent requests for {scope} ({reason})")]
+pub struct AdmissionRejected {
+    pub scope: AdmissionScope,
+    pub reason: RejectReason,
+}
+
+/// 処理中・待機中のリクエストの数
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InFlightStats {
+    /// 実行中のハンドラーの数
+    pub in_flight: u64,
+    /// 空きを待っているリクエストの数
+    pub queued: u64,
+    /// 拒否したリクエストの数
+    pub rejected: u64,
+    /// 同時に実行する数の上限（`None`の場合は無制限）
+    pub max_in_flight: Option<usize>,
+}
+
+/// サーバー全体とメソッドごとの処理中のリクエストの数
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdmissionStats {
+    pub global: InFlightStats,
+    /// 呼び出されたことのあるメソッドごとの内訳
+    pub methods: HashMap<String, InFlightStats>,
+}
+
+/// 1つの範囲の上限と処理中の数
+#[derive(Default)]
+struct Gate {
+    /// 上限（`None`の場合は数えるだけ）
+    limit: Option<(ConcurrencyLimit, Arc<Semaphore>)>,
+    in_flight: AtomicU64,
+    queued: AtomicUsize,
+    rejected: AtomicU64,
+}
+
+impl Gate {
+    fn new(limit: Option<ConcurrencyLimit>) -> Self {
+        Self {
+            limit: limit.map(|limit| (limit, Arc::new(Semaphore::new(limit.max_in_flight)))),
+            ..Self::default()
+        }
+    }
+
+    /// 空きがあれば枠を確保し、なければ待ち行列の上限まで待つ
+    async fn acquire(
+        &self,
+        queue_timeout: Option<Duration>,
+    ) -> Result<Option<OwnedSemaphorePermit>, RejectReason> {
+        let Some((limit, semaphore)) = &self.limit else {
+            return Ok(None);
+        };
+        if let Ok(permit) = Arc::clone(semaphore).try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= limit.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(self.reject(RejectReason::QueueFull));
+        }
+        // 待機中に取り消された場合も待ち行列から外す
+        let _queued = QueuedGuard(&self.queued);
+        let acquire = Arc::clone(semaphore).acquire_owned();
+        let permit = match queue_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, acquire).await {
+                Ok(permit) => permit,
+                Err(_) => return Err(self.reject(RejectReason::QueueTimeout)),
+            },
+            None => acquire.await,
+        };
+        // セマフォは閉じないため確保は失敗しない
+        Ok(permit.ok())
+    }
+
+    fn reject(&self, reason: RejectReason) -> RejectReason {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        reason
+    }
+
+    fn stats(&self) -> InFlightStats {
+        InFlightStats {
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst) as u64,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            max_in_flight: self.limit.as_ref().map(|(limit, _)| limit.max_in_flight),
+        }
+    }
+}
+
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 実行中のハンドラーの枠（破棄すると解放される）
+pub struct AdmissionPermit {
+    gates: [Arc<Gate>; 2],
+    _permits: [Option<OwnedSemaphorePermit>; 2],
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        for gate in &self.gates {
+            gate.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[derive(Default)]
+struct AdmissionInner {
+    config: AdmissionConfig,
+    global: Arc<Gate>,
+    methods: Mutex<HashMap<String, Arc<Gate>>>,
+}
+
+/// サーバー全体とメソッドごとのアドミッション制御
+#[derive(Clone, Default)]
+pub struct AdmissionController {
+    inner: Arc<AdmissionInner>,
+}
+
+impl AdmissionController {
+    pub fn new(config: AdmissionConfig) -> Self {
+        Self {
+            inner: Arc::new(AdmissionInner {
+                global: Arc::new(Gate::new(config.global)),
+                config,
+                methods: Mutex::default(),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &AdmissionConfig {
+        &self.inner.config
+    }
+
+    /// `method`のハンドラーを実行する枠を確保
+    ///
+    /// メソッドの枠を確保してからサーバー全体の枠を確保するため、メソッドの上限で
+    /// 待っている間はサーバー全体の枠を占有しません。
+    pub async fn admit(&self, method: &str) -> Result<AdmissionPermit, AdmissionRejected> {
+        let gate = self.method_gate(method);
+        let timeout = self.inner.config.queue_timeout;
+        let method_permit = gate
+            .acquire(timeout)
+            .await
+            .map_err(|reason| AdmissionRejected {
+                scope: AdmissionScope::Method(method.to_string()),
+                reason,
+            })?;
+        let global_permit =
+            self.inner
+                .global
+                .acquire(timeout)
+                .await
+                .map_err(|reason| AdmissionRejected {
+                    scope: AdmissionScope::Global,
+                    reason,
+                })?;
+        gate.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.inner.global.in_flight.fetch_add(1, Ordering::SeqCst);
+        Ok(AdmissionPermit {
+            gates: [gate, Arc::clone(&self.inner.global)],
+            _permits: [method_permit, global_permit],
+        })
+    }
+
+    /// 処理中・待機中のリクエストの数
+    pub fn stats(&self) -> AdmissionStats {
+        let methods = self
+            .inner
+            .methods
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(method, gate)| (method.clone(), gate.stats()))
+            .collect();
+        AdmissionStats {
+            global: self.inner.global.stats(),
+            methods,
+        }
+    }
+
+    fn method_gate(&self, method: &str) -> Arc<Gate> {
+        let mut methods = self.inner.methods.lock().unwrap();
+        if let Some(gate) = methods.get(method) {
+            return Arc::clone(gate);
+        }
+        let limit = self.inner.config.methods.get(method).copied();
+        let gate = Arc::new(Gate::new(limit));
+        methods.insert(method.to_string(), Arc::clone(&gate));
+        gate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_counts_in_flight_without_limits() {
+        let admission = AdmissionController::default();
+        let first = admission.admit("echo").await.unwrap();
+        let _second = admission.admit("echo").await.unwrap();
+        let stats = admission.stats();
+        assert_eq!(stats.global.in_flight, 2);
+        assert_eq!(stats.global.max_in_flight, None);
+        assert_eq!(stats.methods["echo"].in_flight, 2);
+
+        drop(first);
+        assert_eq!(admission.stats().methods["echo"].in_flight, 1);
+    }
+
+    #[tokio::test]
+    async fn test_queue_overflow_is_shed() {
+        let admission = AdmissionController::new(
+            AdmissionConfig::default()
+                .with_method_limit("upload", ConcurrencyLimit::new(1).with_queue(1)),
+        );
+        let running = admission.admit("upload").await.unwrap();
+
+        let queued = tokio::spawn({
+            let admission = admission.clone();
+            async move { admission.admit("upload").await.map(drop) }
+        });
+        tokio::task::yield_now().await;
+        while admission.stats().methods["upload"].queued == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let error = admission.admit("upload").await.err().unwrap();
+        assert_eq!(error.scope, AdmissionScope::Method("upload".into()));
+        assert_eq!(error.reason, RejectReason::QueueFull);
+        // 他のメソッドは影響を受けない
+        assert!(admission.admit("echo").await.is_ok());
+
+        drop(running);
+        queued.await.unwrap().unwrap();
+        let stats = admission.stats().methods["upload"].clone();
+        assert_eq!((stats.in_flight, stats.queued, stats.rejected), (0, 0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_global_queue_timeout() {
+        let admission = AdmissionController::new(
+            AdmissionConfig::default()
+                .with_global_limit(ConcurrencyLimit::new(1).with_queue(4))
+                .with_queue_timeout(Duration::from_millis(20)),
+        );
+        let _running = admission.admit("a").await.unwrap();
+        let error = admission.admit("b").await.err().unwrap();
+        assert_eq!(error.scope, AdmissionScope::Global);
+        assert_eq!(error.reason, RejectReason::QueueTimeout);
+
+        // 拒否したリクエストはメソッドの枠も解放する
+        let stats = admission.stats();
+        assert_eq!(stats.methods["b"].in_flight, 0);
+        assert_eq!(stats.global.queued, 0);
+        assert_eq!(stats.global.rejected, 1);
+    }
+}
@@ -0,0 +1,556 @@
+//! 静的ファイル（アセット）の配信
+//!
+//! [`AssetService`]はrust-embedで埋め込んだファイルやディスク上のファイルを、
+//! 通常のUnisonのエンドポイントから配信します。UIのリソースやスキーマを
+//! 同じ接続で取得できるため、別にHTTPサーバーを用意する必要がありません。
+//!
+//! 次のメソッドを登録します（`unison.assets`は[`AssetService::with_prefix`]で変更できます）。
+//!
+//! - `unison.assets.stat`（呼び出し）: `{"path"}`に対し[`AssetInfo`]を返す
+//! - `unison.assets.list`（呼び出し）: 配信できるパスの一覧を返す
+//! - `unison.assets.get`（ストリーム）: `{"path", "if_none_match", "offset"}`に対し、
+//!   最初に[`AssetInfo`]と`not_modified`を、続いて`{"offset", "data"}`（Base64）の
+//!   チャンクを送る。`if_none_match`がETagと一致した場合はチャンクを送らずに終わる
+//!
+//! ETagは内容のSHA-256で、レスポンスのメタデータ（[`ETAG_METADATA_KEY`]）と
+//! キャッシュ制御のヒントにも格納されます。
+//!
+//! ```rust,no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use unison::network::{AssetClient, AssetService, DirectoryAssets, ProtocolClient, ProtocolServer};
+//!
+//! // 埋め込む場合は`#[derive(RustEmbed)]`の型で`EmbeddedAssets::<Ui>::new()`
+//! let server = ProtocolServer::new().with_assets(AssetService::new(DirectoryAssets::new("ui/dist")));
+//!
+//! # let client = ProtocolClient::new_default()?;
+//! let assets = AssetClient::new(&client);
+//! let index = assets.fetch("index.html", None).await?.expect("no cached copy");
+//! // 2回目以降はETagを渡し、変更がなければ`None`が返る
+//! assert!(assets.fetch("index.html", Some(&index.info.etag)).await?.is_none());
+//! # Ok(())
+//! # }
+//! ```
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures_util::{Stream, StreamExt};
+use rust_embed::RustEmbed;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+use super::handler::{CacheControl, HandlerResponse};
+use super::{ProtocolClientTrait, ProtocolError};
+
+/// 既定のメソッド名の接頭辞
+pub const DEFAULT_ASSET_PREFIX: &str = "unison.assets";
+
+/// ETagを格納するメタデータのキー
+pub const ETAG_METADATA_KEY: &str = "etag";
+
+/// 1チャンクの既定のバイト数
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// アセットの取得に関するエラー
+#[derive(Error, Debug)]
+pub enum AssetError {
+    #[error("Asset not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid asset path: {0}")]
+    InvalidPath(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<AssetError> for ProtocolError {
+    fn from(error: AssetError) -> Self {
+        let code = match error {
+            AssetError::NotFound(_) => ProtocolError::NOT_FOUND,
+            AssetError::InvalidPath(_) => ProtocolError::INVALID_REQUEST,
+            AssetError::Io(_) => ProtocolError::INTERNAL,
+        };
+        ProtocolError::new(code, error.to_string())
+    }
+}
+
+/// アセットのメタデータ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetInfo {
+    pub path: String,
+    pub size: u64,
+    /// 内容のSHA-256（引用符付き）
+    pub etag: String,
+    pub content_type: String,
+}
+
+/// アセットの内容とメタデータ
+#[derive(Debug, Clone)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub data: Cow<'static, [u8]>,
+}
+
+impl Asset {
+    /// 内容からETagとContent-Typeを求めて作成
+    pub fn new(path: impl Into<String>, data: impl Into<Cow<'static, [u8]>>) -> Self {
+        let path = path.into();
+        let data = data.into();
+        let digest = ring::digest::digest(&ring::digest::SHA256, &data);
+        Self::with_digest(path, data, digest.as_ref())
+    }
+
+    fn with_digest(path: String, data: Cow<'static, [u8]>, digest: &[u8]) -> Self {
+        let etag = format!(
+            "\"{}\"",
+            digest
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>()
+        );
+        Self {
+            info: AssetInfo {
+                content_type: content_type(&path).to_string(),
+                size: data.len() as u64,
+                path,
+                etag,
+            },
+            data,
+        }
+    }
+}
+
+/// アセットの取得元
+pub trait AssetSource: Send + Sync + 'static {
+    /// 正規化済みのパス（`/`区切り、先頭の`/`なし）のアセットを読み込む
+    fn load(&self, path: &str) -> Result<Option<Asset>, AssetError>;
+
+    /// 配信できるパスの一覧
+    fn paths(&self) -> Result<Vec<String>, AssetError>;
+}
+
+/// rust-embedで埋め込んだアセット
+pub struct EmbeddedAssets<E>(PhantomData<fn() -> E>);
+
+impl<E: RustEmbed> EmbeddedAssets<E> {
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<E: RustEmbed> Default for EmbeddedAssets<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: RustEmbed + 'static> AssetSource for EmbeddedAssets<E> {
+    fn load(&self, path: &str) -> Result<Option<Asset>, AssetError> {
+        Ok(E::get(path).map(|file| {
+            // 埋め込み時に計算済みのハッシュを使う
+            let digest = file.metadata.sha256_hash();
+            Asset::with_digest(path.to_string(), file.data, &digest)
+        }))
+    }
+
+    fn paths(&self) -> Result<Vec<String>, AssetError> {
+        Ok(E::iter().map(Cow::into_owned).collect())
+    }
+}
+
+/// ディレクトリ配下のファイル（リクエストごとに読み込む）
+#[derive(Debug, Clone)]
+pub struct DirectoryAssets {
+    root: PathBuf,
+}
+
+impl DirectoryAssets {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn collect(dir: &Path, prefix: &str, paths: &mut Vec<String>) -> Result<(), AssetError> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = if prefix.is_empty() {
+                name
+            } else {
+                format!("{prefix}/{name}")
+            };
+            if entry.file_type()?.is_dir() {
+                Self::collect(&entry.path(), &path, paths)?;
+            } else {
+                paths.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AssetSource for DirectoryAssets {
+    fn load(&self, path: &str) -> Result<Option<Asset>, AssetError> {
+        let file = path
+            .split('/')
+            .fold(self.root.clone(), |dir, segment| dir.join(segment));
+        match std::fs::read(&file) {
+            Ok(data) => Ok(Some(Asset::new(path, data))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            // ディレクトリを指定された場合も存在しない扱いにする
+            Err(_) if file.is_dir() => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn paths(&self) -> Result<Vec<String>, AssetError> {
+        let mut paths = Vec::new();
+        Self::collect(&self.root, "", &mut paths)?;
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+/// メモリ上のアセット（実行時に生成したスキーマなど）
+#[derive(Debug, Clone, Default)]
+pub struct StaticAssets {
+    assets: BTreeMap<String, Asset>,
+}
+
+impl StaticAssets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_asset(mut self, path: &str, data: impl Into<Cow<'static, [u8]>>) -> Self {
+        let path = normalize_path(path).unwrap_or_else(|_| path.to_string());
+        self.assets.insert(path.clone(), Asset::new(path, data));
+        self
+    }
+}
+
+impl AssetSource for StaticAssets {
+    fn load(&self, path: &str) -> Result<Option<Asset>, AssetError> {
+        Ok(self.assets.get(path).cloned())
+    }
+
+    fn paths(&self) -> Result<Vec<String>, AssetError> {
+        Ok(self.assets.keys().cloned().collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetRequest {
+    path: String,
+    #[serde(default)]
+    if_none_match: Option<String>,
+    #[serde(default)]
+    offset: u64,
+}
+
+/// アセットを配信するメソッド群
+///
+/// [`ProtocolServer::with_assets`](super::ProtocolServer::with_assets)でサーバーに登録します。
+pub struct AssetService {
+    source: Arc<dyn AssetSource>,
+    prefix: String,
+    chunk_size: usize,
+    cache_control: Option<CacheControl>,
+}
+
+impl AssetService {
+    pub fn new(source: impl AssetSource) -> Self {
+        Self {
+            source: Arc::new(source),
+            prefix: DEFAULT_ASSET_PREFIX.to_string(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            cache_control: None,
+        }
+    }
+
+    /// メソッド名の接頭辞を指定（複数のアセットを別々に配信する場合など）
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// `stat`のレスポンスと`get`の最初のアイテムに付けるキャッシュ制御のヒント
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    /// `stat`・`list`・`get`のメソッド名
+    pub fn method(&self, name: &str) -> String {
+        format!("{}.{}", self.prefix, name)
+    }
+
+    fn load(&self, path: &str) -> Result<Asset, AssetError> {
+        let path = normalize_path(path)?;
+        self.source.load(&path)?.ok_or(AssetError::NotFound(path))
+    }
+
+    pub(crate) fn stat(&self, payload: Value) -> HandlerResponse {
+        let asset = match serde_json::from_value::<AssetRequest>(payload) {
+            Ok(request) => self.load(&request.path),
+            Err(e) => Err(AssetError::InvalidPath(e.to_string())),
+        };
+        match asset {
+            Ok(asset) => {
+                let mut response = HandlerResponse::ok(json!(asset.info))
+                    .with_metadata(ETAG_METADATA_KEY, asset.info.etag);
+                if let Some(cache_control) = self.cache_control {
+                    response = response.with_cache_control(cache_control);
+                }
+                response
+            }
+            Err(e) => HandlerResponse::error(e.into()),
+        }
+    }
+
+    pub(crate) fn list(&self) -> HandlerResponse {
+        match self.source.paths() {
+            Ok(paths) => HandlerResponse::ok(json!(paths)),
+            Err(e) => HandlerResponse::error(e.into()),
+        }
+    }
+
+    /// `get`のストリームを開く
+    pub(crate) fn open(
+        &self,
+        payload: Value,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<Value>> + Send + use<>> {
+        let request: AssetRequest =
+            serde_json::from_value(payload).map_err(|e| AssetError::InvalidPath(e.to_string()))?;
+        let asset = self.load(&request.path)?;
+        let not_modified = request.if_none_match.as_deref() == Some(asset.info.etag.as_str());
+
+        let mut head = json!(asset.info);
+        head["not_modified"] = json!(not_modified);
+        if let Some(cache_control) = self.cache_control {
+            head["cache_control"] = json!(cache_control.to_string());
+        }
+
+        let data = Arc::new(asset.data);
+        let size = data.len();
+        let chunk_size = self.chunk_size;
+        let start = if not_modified {
+            size
+        } else {
+            (request.offset as usize).min(size)
+        };
+        let chunks =
+            futures_util::stream::iter((start..size).step_by(chunk_size)).map(move |offset| {
+                let end = (offset + chunk_size).min(size);
+                Ok(json!({ "offset": offset, "data": BASE64.encode(&data[offset..end]) }))
+            });
+        Ok(futures_util::stream::once(async move { Ok(head) }).chain(chunks))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetHead {
+    #[serde(flatten)]
+    info: AssetInfo,
+    not_modified: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetChunk {
+    offset: u64,
+    data: String,
+}
+
+/// [`AssetService`]からアセットを取得するクライアント
+pub struct AssetClient<'a, C> {
+    client: &'a C,
+    prefix: String,
+}
+
+impl<'a, C: ProtocolClientTrait> AssetClient<'a, C> {
+    pub fn new(client: &'a C) -> Self {
+        Self {
+            client,
+            prefix: DEFAULT_ASSET_PREFIX.to_string(),
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub async fn stat(&self, path: &str) -> anyhow::Result<AssetInfo> {
+        self.client
+            .call(&format!("{}.stat", self.prefix), json!({ "path": path }))
+            .await
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<String>> {
+        self.client
+            .call(&format!("{}.list", self.prefix), Value::Null)
+            .await
+    }
+
+    /// アセットを取得する
+    ///
+    /// `if_none_match`に手元のETagを渡し、変更がなければ`None`を返します。
+    pub async fn fetch(
+        &self,
+        path: &str,
+        if_none_match: Option<&str>,
+    ) -> anyhow::Result<Option<Asset>> {
+        let mut stream = self
+            .client
+            .stream::<_, Value>(
+                &format!("{}.get", self.prefix),
+                json!({ "path": path, "if_none_match": if_none_match }),
+            )
+            .await?;
+        let head = stream
+            .next()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Asset stream ended before metadata"))??;
+        let head: AssetHead = serde_json::from_value(head)?;
+        if head.not_modified {
+            return Ok(None);
+        }
+
+        let mut data = Vec::with_capacity(head.info.size as usize);
+        while let Some(chunk) = stream.next().await {
+            let chunk: AssetChunk = serde_json::from_value(chunk?)?;
+            anyhow::ensure!(
+                chunk.offset == data.len() as u64,
+                "Unexpected asset chunk offset {} (expected {})",
+                chunk.offset,
+                data.len()
+            );
+            data.extend(BASE64.decode(chunk.data)?);
+        }
+        anyhow::ensure!(
+            data.len() as u64 == head.info.size,
+            "Asset {} is truncated ({} of {} bytes)",
+            head.info.path,
+            data.len(),
+            head.info.size
+        );
+        Ok(Some(Asset {
+            info: head.info,
+            data: Cow::Owned(data),
+        }))
+    }
+}
+
+/// `/`区切りのパスへ正規化し、親ディレクトリへの移動などを拒否する
+fn normalize_path(path: &str) -> Result<String, AssetError> {
+    let segments: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect();
+    if segments.is_empty()
+        || segments
+            .iter()
+            .any(|segment| *segment == ".." || segment.contains(':'))
+    {
+        return Err(AssetError::InvalidPath(path.to_string()));
+    }
+    Ok(segments.join("/"))
+}
+
+/// 拡張子から推定したContent-Type
+fn content_type(path: &str) -> &'static str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("kdl") => "text/x-kdl; charset=utf-8",
+        Some("txt" | "md") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("wasm") => "application/wasm",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path_rejects_traversal() {
+        assert_eq!(normalize_path("/ui/./index.html").unwrap(), "ui/index.html");
+        assert_eq!(normalize_path("schemas\\a.kdl").unwrap(), "schemas/a.kdl");
+        for path in ["", "/", "../secret", "ui/../../etc/passwd", "C:/windows"] {
+            assert!(normalize_path(path).is_err(), "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chunked_stream_and_not_modified() {
+        let service =
+            AssetService::new(StaticAssets::new().with_asset("app.js", b"0123456789".as_slice()))
+                .with_chunk_size(4);
+        let items: Vec<Value> = service
+            .open(json!({ "path": "/app.js", "offset": 2 }))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(items[0]["content_type"], "text/javascript; charset=utf-8");
+        assert_eq!(items[0]["not_modified"], false);
+        let offsets: Vec<_> = items[1..]
+            .iter()
+            .map(|item| item["offset"].clone())
+            .collect();
+        assert_eq!(offsets, vec![json!(2), json!(6)]);
+
+        let etag = items[0]["etag"].as_str().unwrap();
+        let items: Vec<Value> = service
+            .open(json!({ "path": "app.js", "if_none_match": etag }))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["not_modified"], true);
+    }
+
+    #[test]
+    fn test_directory_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("css")).unwrap();
+        std::fs::write(dir.path().join("index.html"), "<html>").unwrap();
+        std::fs::write(dir.path().join("css").join("site.css"), "body{}").unwrap();
+
+        let source = DirectoryAssets::new(dir.path());
+        assert_eq!(source.paths().unwrap(), vec!["css/site.css", "index.html"]);
+        let asset = source.load("css/site.css").unwrap().unwrap();
+        assert_eq!(asset.data.as_ref(), b"body{}");
+        assert_eq!(
+            asset.info.etag,
+            Asset::new("x", b"body{}".as_slice()).info.etag
+        );
+        assert!(source.load("css").unwrap().is_none());
+        assert!(source.load("missing.js").unwrap().is_none());
+    }
+}
@@ -0,0 +1,642 @@
+//! フロー制御付きブロードキャスト（ファンアウト）
+//!
+//! 数千の接続へブロードキャストする場合でも呼び出し元をブロックしないよう、
+//! 以下の構成で配信します。
+//!
+//! - 接続ごとに上限付きの送信キューを持ち、満杯の接続（遅いコンシューマー）への配信は破棄
+//! - 固定数のワーカープールがキューに溜まった接続を順に処理し、同時送信数を制限
+//! - 1接続を連続して処理する件数を制限し、他の接続が待たされ続けないようにする
+//! - ブロードキャストごとに[`BroadcastHandle`]で進捗と完了を取得可能
+//! - 接続ごとのメモリ上限（[`MemoryQuotaConfig`]）を超える送信キューへの配信は破棄
+
+use bytes::Bytes;
+use quinn::Connection;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::debug;
+
+use super::NetworkError;
+use super::quic::write_stream_frame;
+use super::quota::{ConnectionMemory, MemoryQuotaConfig, MemoryReservation, MemoryUsage};
+
+/// サーバー側の接続ID
+pub type ConnectionId = u64;
+
+/// 1ジョブで連続して処理する最大配信数
+const DRAIN_BATCH_SIZE: usize = 32;
+
+/// エンコード済みフレームの送信先
+pub trait MessageSink: Send + Sync {
+    /// フレームを送信
+    fn send_frame(
+        &self,
+        frame: Bytes,
+    ) -> Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send + '_>>;
+
+    /// 送信先が閉じているか
+    fn is_closed(&self) -> bool;
+}
+
+impl MessageSink for Connection {
+    fn send_frame(
+        &self,
+        frame: Bytes,
+    ) -> Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send + '_>> {
+        Box::pin(async move {
+            let (mut send_stream, _recv_stream) = self
+                .open_bi()
+                .await
+                .map_err(|e| NetworkError::Quic(e.to_string()))?;
+            write_stream_frame(&mut send_stream, &frame, true)
+                .await
+                .map_err(|e| NetworkError::Quic(e.to_string()))?;
+            send_stream
+                .finish()
+                .map_err(|e| NetworkError::Quic(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.close_reason().is_some()
+    }
+}
+
+/// ブロードキャストの設定
+#[derive(Debug, Clone)]
+pub struct BroadcastConfig {
+    /// ワーカー数（同時に送信処理を行う接続数の上限）
+    pub workers: usize,
+    /// 接続ごとの送信キューの上限
+    pub queue_capacity: usize,
+    /// 1フレームの送信タイムアウト
+    pub send_timeout: Duration,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self {
+            workers: 8,
+            queue_capacity: 1024,
+            send_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// ブロードキャストの進捗
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BroadcastProgress {
+    /// 配信対象の接続数
+    pub total: usize,
+    /// 送信に成功した数
+    pub delivered: usize,
+    /// 送信に失敗した数（タイムアウト・切断を含む）
+    pub failed: usize,
+    /// キュー満杯のため破棄した数
+    pub dropped: usize,
+}
+
+impl BroadcastProgress {
+    /// 処理済みの数
+    pub fn completed(&self) -> usize {
+        self.delivered + self.failed + self.dropped
+    }
+
+    /// すべての配信が処理済みか
+    pub fn is_complete(&self) -> bool {
+        self.completed() >= self.total
+    }
+}
+
+/// 配信結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeliveryOutcome {
+    Delivered,
+    Failed,
+    Dropped,
+}
+
+/// ブロードキャストごとの進捗を集計
+struct BroadcastTracker {
+    progress: watch::Sender<BroadcastProgress>,
+}
+
+impl BroadcastTracker {
+    fn record(&self, outcome: DeliveryOutcome) {
+        self.progress.send_modify(|p| match outcome {
+            DeliveryOutcome::Delivered => p.delivered += 1,
+            DeliveryOutcome::Failed => p.failed += 1,
+            DeliveryOutcome::Dropped => p.dropped += 1,
+        });
+    }
+}
+
+/// ブロードキャストの進捗と完了を取得するハンドル
+pub struct BroadcastHandle {
+    progress: watch::Receiver<BroadcastProgress>,
+}
+
+impl BroadcastHandle {
+    /// 現在の進捗
+    pub fn progress(&self) -> BroadcastProgress {
+        *self.progress.borrow()
+    }
+
+    /// すべての配信が処理済みか
+    pub fn is_complete(&self) -> bool {
+        self.progress().is_complete()
+    }
+
+    /// すべての配信が処理されるまで待機し、最終的な進捗を返す
+    pub async fn wait(mut self) -> BroadcastProgress {
+        if let Ok(progress) = self.progress.wait_for(|p| p.is_complete()).await {
+            return *progress;
+        }
+        // 全配信が破棄された後は最終値を返す
+        *self.progress.borrow()
+    }
+}
+
+/// 送信待ちの配信
+struct Delivery {
+    frame: Bytes,
+    tracker: Arc<BroadcastTracker>,
+    /// 送信キューのメモリ上限に対する確保（送信完了で解放）
+    reservation: MemoryReservation,
+}
+
+impl Delivery {
+    /// 配信結果を記録（送信キューのメモリは記録より先に解放）
+    fn finish(self, outcome: DeliveryOutcome) {
+        drop(self.reservation);
+        self.tracker.record(outcome);
+    }
+}
+
+/// 接続ごとの送信キュー
+struct ConnectionQueue {
+    id: ConnectionId,
+    sink: Arc<dyn MessageSink>,
+    memory: Arc<ConnectionMemory>,
+    pending: Mutex<VecDeque<Delivery>>,
+    /// ワーカーへ処理を依頼済みか
+    scheduled: AtomicBool,
+    /// 登録解除済みか
+    closed: AtomicBool,
+}
+
+impl ConnectionQueue {
+    /// 残っている配信をすべて失敗として扱う
+    fn fail_pending(&self) {
+        let pending: Vec<Delivery> = self.pending.lock().unwrap().drain(..).collect();
+        for delivery in pending {
+            delivery.finish(DeliveryOutcome::Failed);
+        }
+    }
+}
+
+type Job = Arc<ConnectionQueue>;
+
+struct RegistryInner {
+    config: BroadcastConfig,
+    memory_quota: MemoryQuotaConfig,
+    connections: RwLock<HashMap<ConnectionId, Arc<ConnectionQueue>>>,
+    next_id: AtomicU64,
+    jobs: OnceLock<mpsc::UnboundedSender<Job>>,
+}
+
+/// サーバーに接続中のクライアントを管理し、ブロードキャストを配信するレジストリ
+#[derive(Clone)]
+pub struct ConnectionRegistry {
+    inner: Arc<RegistryInner>,
+}
+
+impl ConnectionRegistry {
+    pub fn new(config: BroadcastConfig) -> Self {
+        Self::with_memory_quota(config, MemoryQuotaConfig::default())
+    }
+
+    /// 接続ごとのメモリ上限を指定して作成
+    pub fn with_memory_quota(config: BroadcastConfig, memory_quota: MemoryQuotaConfig) -> Self {
+        Self {
+            inner: Arc::new(RegistryInner {
+                config,
+                memory_quota,
+                connections: RwLock::new(HashMap::new()),
+                next_id: AtomicU64::new(1),
+                jobs: OnceLock::new(),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &BroadcastConfig {
+        &self.inner.config
+    }
+
+    pub fn memory_quota(&self) -> &MemoryQuotaConfig {
+        &self.inner.memory_quota
+    }
+
+    /// 接続のメモリ予算
+    pub fn memory(&self, id: ConnectionId) -> Option<Arc<ConnectionMemory>> {
+        self.inner
+            .connections
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(|queue| Arc::clone(&queue.memory))
+    }
+
+    /// 全接続のメモリ使用量の合計
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.inner
+            .connections
+            .read()
+            .unwrap()
+            .values()
+            .map(|queue| queue.memory.usage())
+            .fold(MemoryUsage::default(), |total, usage| total + usage)
+    }
+
+    /// 接続を登録してIDを返す
+    pub fn register(&self, sink: Arc<dyn MessageSink>) -> ConnectionId {
+        let id = self.inner.next_id.fetch_add(1, Ordering::SeqCst);
+        let queue = Arc::new(ConnectionQueue {
+            id,
+            sink,
+            memory: Arc::new(ConnectionMemory::new(&self.inner.memory_quota)),
+            pending: Mutex::new(VecDeque::new()),
+            scheduled: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+        });
+        self.inner.connections.write().unwrap().insert(id, queue);
+        id
+    }
+
+    /// 接続の登録を解除（未送信の配信は失敗扱い）
+    pub fn unregister(&self, id: ConnectionId) {
+        let removed = self.inner.connections.write().unwrap().remove(&id);
+        if let Some(queue) = removed {
+            queue.closed.store(true, Ordering::SeqCst);
+            queue.fail_pending();
+        }
+    }
+
+    /// 接続中のクライアント数
+    pub fn len(&self) -> usize {
+        self.inner.connections.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 接続中のクライアントID一覧
+    pub fn connection_ids(&self) -> Vec<ConnectionId> {
+        self.inner
+            .connections
+            .read()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    /// 全接続へフレームを配信（呼び出し元はブロックされません）
+    ///
+    /// Tokioランタイム内から呼び出す必要があります。
+    pub fn broadcast_frame(&self, frame: Bytes) -> BroadcastHandle {
+        let targets: Vec<Arc<ConnectionQueue>> = self
+            .inner
+            .connections
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        self.enqueue(targets, frame)
+    }
+
+    /// 指定した接続へフレームを配信
+    pub fn broadcast_frame_to(&self, ids: &[ConnectionId], frame: Bytes) -> BroadcastHandle {
+        let targets: Vec<Arc<ConnectionQueue>> = {
+            let connections = self.inner.connections.read().unwrap();
+            ids.iter()
+                .filter_map(|id| connections.get(id).cloned())
+                .collect()
+        };
+        self.enqueue(targets, frame)
+    }
+
+    fn enqueue(&self, targets: Vec<Arc<ConnectionQueue>>, frame: Bytes) -> BroadcastHandle {
+        let (progress_tx, progress_rx) = watch::channel(BroadcastProgress {
+            total: targets.len(),
+            ..Default::default()
+        });
+        let tracker = Arc::new(BroadcastTracker {
+            progress: progress_tx,
+        });
+
+        let jobs = self.jobs();
+        for queue in targets {
+            {
+                let mut pending = queue.pending.lock().unwrap();
+                if pending.len() >= self.inner.config.queue_capacity {
+                    debug!("Broadcast queue full for connection {}", queue.id);
+                    tracker.record(DeliveryOutcome::Dropped);
+                    continue;
+                }
+                let reservation = match queue.memory.outbound.try_reserve(frame.len()) {
+                    Ok(reservation) => reservation,
+                    Err(e) => {
+                        debug!("Broadcast to connection {} dropped: {}", queue.id, e);
+                        tracker.record(DeliveryOutcome::Dropped);
+                        continue;
+                    }
+                };
+                pending.push_back(Delivery {
+                    frame: frame.clone(),
+                    tracker: Arc::clone(&tracker),
+                    reservation,
+                });
+            }
+
+            if !queue.scheduled.swap(true, Ordering::SeqCst) {
+                let _ = jobs.send(queue);
+            }
+        }
+
+        BroadcastHandle {
+            progress: progress_rx,
+        }
+    }
+
+    /// ワーカープールを（初回のみ）起動してジョブキューを返す
+    ///
+    /// ワーカーはジョブキューの送信側を弱参照で持つため、レジストリが破棄されると
+    /// キューが閉じてワーカーも終了します。
+    fn jobs(&self) -> &mpsc::UnboundedSender<Job> {
+        self.inner.jobs.get_or_init(|| {
+            let (tx, rx) = mpsc::unbounded_channel::<Job>();
+            let rx = Arc::new(tokio::sync::Mutex::new(rx));
+            for _ in 0..self.inner.config.workers.max(1) {
+                let rx = Arc::clone(&rx);
+                let resubmit = tx.downgrade();
+                let send_timeout = self.inner.config.send_timeout;
+                tokio::spawn(async move {
+                    loop {
+                        let job = rx.lock().await.recv().await;
+                        match job {
+                            Some(queue) => drain_queue(queue, &resubmit, send_timeout).await,
+                            None => break,
+                        }
+                    }
+                });
+            }
+            tx
+        })
+    }
+}
+
+impl Default for ConnectionRegistry {
+    fn default() -> Self {
+        Self::new(BroadcastConfig::default())
+    }
+}
+
+/// 接続の送信キューを処理
+async fn drain_queue(
+    queue: Arc<ConnectionQueue>,
+    resubmit: &mpsc::WeakUnboundedSender<Job>,
+    send_timeout: Duration,
+) {
+    let mut processed = 0;
+    loop {
+        if processed >= DRAIN_BATCH_SIZE {
+            // 他の接続を待たせないよう、キューの末尾に回す（レジストリが破棄済みなら続けて処理する）
+            match resubmit.upgrade() {
+                Some(jobs) => {
+                    let _ = jobs.send(queue);
+                    return;
+                }
+                None => processed = 0,
+            }
+        }
+
+        let next = queue.pending.lock().unwrap().pop_front();
+        let Some(delivery) = next else {
+            queue.scheduled.store(false, Ordering::SeqCst);
+            // 解除直後に追加された配信を取りこぼさないよう再確認
+            let has_pending = !queue.pending.lock().unwrap().is_empty();
+            if has_pending && !queue.scheduled.swap(true, Ordering::SeqCst) {
+                continue;
+            }
+            return;
+        };
+        processed += 1;
+
+        if queue.closed.load(Ordering::SeqCst) || queue.sink.is_closed() {
+            delivery.finish(DeliveryOutcome::Failed);
+            continue;
+        }
+
+        let outcome =
+            match tokio::time::timeout(send_timeout, queue.sink.send_frame(delivery.frame.clone()))
+                .await
+            {
+                Ok(Ok(())) => DeliveryOutcome::Delivered,
+                Ok(Err(e)) => {
+                    debug!("Broadcast to connection {} failed: {}", queue.id, e);
+                    DeliveryOutcome::Failed
+                }
+                Err(_) => {
+                    debug!("Broadcast to connection {} timed out", queue.id);
+                    DeliveryOutcome::Failed
+                }
+            };
+        delivery.finish(outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// 送信回数を数えるテスト用シンク
+    struct CountingSink {
+        sent: AtomicUsize,
+        delay: Duration,
+        fail: bool,
+    }
+
+    impl CountingSink {
+        fn new(delay: Duration, fail: bool) -> Arc<Self> {
+            Arc::new(Self {
+                sent: AtomicUsize::new(0),
+                delay,
+                fail,
+            })
+        }
+    }
+
+    impl MessageSink for CountingSink {
+        fn send_frame(
+            &self,
+            _frame: Bytes,
+        ) -> Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send + '_>> {
+            Box::pin(async move {
+                tokio::time::sleep(self.delay).await;
+                if self.fail {
+                    return Err(NetworkError::Connection("closed".to_string()));
+                }
+                self.sent.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+        }
+
+        fn is_closed(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_to_all_connections() {
+        let registry = ConnectionRegistry::new(BroadcastConfig {
+            workers: 4,
+            ..Default::default()
+        });
+        let sinks: Vec<_> = (0..100)
+            .map(|_| CountingSink::new(Duration::ZERO, false))
+            .collect();
+        for sink in &sinks {
+            registry.register(sink.clone());
+        }
+
+        let progress = registry
+            .broadcast_frame(Bytes::from_static(b"event"))
+            .wait()
+            .await;
+        assert_eq!(progress.total, 100);
+        assert_eq!(progress.delivered, 100);
+        assert!(sinks.iter().all(|s| s.sent.load(Ordering::SeqCst) == 1));
+    }
+
+    #[tokio::test]
+    async fn test_workers_stop_when_registry_is_dropped() {
+        let registry = ConnectionRegistry::new(BroadcastConfig {
+            workers: 3,
+            ..Default::default()
+        });
+        registry.register(CountingSink::new(Duration::ZERO, false));
+        let progress = registry
+            .broadcast_frame(Bytes::from_static(b"event"))
+            .wait()
+            .await;
+        assert_eq!(progress.delivered, 1);
+        let jobs = registry.jobs().downgrade();
+        let metrics = tokio::runtime::Handle::current().metrics();
+        assert_eq!(metrics.num_alive_tasks(), 3);
+
+        drop(registry);
+        assert!(jobs.upgrade().is_none());
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while metrics.num_alive_tasks() > 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("broadcast workers should stop");
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_does_not_block_caller() {
+        let registry = ConnectionRegistry::new(BroadcastConfig {
+            workers: 1,
+            ..Default::default()
+        });
+        registry.register(CountingSink::new(Duration::from_millis(200), false));
+
+        let started = std::time::Instant::now();
+        let handle = registry.broadcast_frame(Bytes::from_static(b"event"));
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(!handle.is_complete());
+        assert_eq!(handle.wait().await.delivered, 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_drops_and_failures() {
+        let registry = ConnectionRegistry::new(BroadcastConfig {
+            workers: 2,
+            queue_capacity: 1,
+            send_timeout: Duration::from_secs(5),
+        });
+        let slow = registry.register(CountingSink::new(Duration::from_millis(50), false));
+        registry.register(CountingSink::new(Duration::ZERO, true));
+
+        let first = registry.broadcast_frame_to(&[slow], Bytes::from_static(b"1"));
+        // ワーカーが1件目の送信を開始するまで待つ
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let second = registry.broadcast_frame_to(&[slow], Bytes::from_static(b"2"));
+        let third = registry.broadcast_frame_to(&[slow], Bytes::from_static(b"3"));
+
+        let results = [first.wait().await, second.wait().await, third.wait().await];
+        assert_eq!(results.iter().map(|p| p.delivered).sum::<usize>(), 2);
+        assert_eq!(results.iter().map(|p| p.dropped).sum::<usize>(), 1);
+
+        let all = registry
+            .broadcast_frame(Bytes::from_static(b"4"))
+            .wait()
+            .await;
+        assert_eq!(all.total, 2);
+        assert_eq!(all.delivered, 1);
+        assert_eq!(all.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unregister_fails_pending() {
+        let registry = ConnectionRegistry::new(BroadcastConfig {
+            workers: 1,
+            ..Default::default()
+        });
+        let blocker = registry.register(CountingSink::new(Duration::from_millis(100), false));
+        let id = registry.register(CountingSink::new(Duration::ZERO, false));
+
+        // ワーカーが1つなので、blockerの処理中はidへの配信が待機する
+        let _busy = registry.broadcast_frame_to(&[blocker], Bytes::from_static(b"busy"));
+        let handle = registry.broadcast_frame_to(&[id], Bytes::from_static(b"event"));
+        registry.unregister(id);
+
+        let progress = handle.wait().await;
+        assert_eq!(progress.failed, 1);
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_outbound_memory_quota_drops_and_releases() {
+        let registry = ConnectionRegistry::with_memory_quota(
+            BroadcastConfig::default(),
+            MemoryQuotaConfig {
+                outbound_limit: 8,
+                ..Default::default()
+            },
+        );
+        let slow = registry.register(CountingSink::new(Duration::from_millis(50), false));
+
+        // 送信キューのバイト数が上限を超える配信は破棄される
+        let first = registry.broadcast_frame_to(&[slow], Bytes::from_static(b"123456"));
+        let second = registry.broadcast_frame_to(&[slow], Bytes::from_static(b"789"));
+        assert_eq!(second.wait().await.dropped, 1);
+        assert_eq!(registry.memory_usage().outbound_bytes, 6);
+        assert_eq!(registry.memory_usage().rejected, 1);
+
+        // 送信完了で解放される
+        assert_eq!(first.wait().await.delivered, 1);
+        assert_eq!(registry.memory(slow).unwrap().usage().outbound_bytes, 0);
+    }
+}
@@ -1,15 +1,55 @@
 use anyhow::{Context, Result};
-use futures_util::Stream;
+use futures_util::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
 use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
 
-use super::quic::QuicClient;
+use crate::core::{UnisonMessage, UnisonResponse};
+use crate::parser::ParsedSchema;
+
+use super::coalesce::{CoalesceConfig, CoalesceStats, RequestCoalescer};
+use super::connection_stats::ConnectionStats;
+use super::content::{CONTENT_TYPE_METADATA_KEY, PayloadEncoding, negotiate};
+use super::deadline::CallOptions;
+use super::encoding::{AdaptiveEncodingConfig, EncodingProfile};
+use super::envelope::{Request, Response};
+use super::exactly_once::{ExactlyOnceConfig, IDEMPOTENCY_KEY_METADATA_KEY};
+use super::failover::{EndpointSelector, FailoverConfig, FailoverError};
+use super::framing::WireFormat;
+use super::handshake::{Capabilities, HandshakeError, SchemaIdentity};
+use super::heartbeat::{HeartbeatConfig, LatencyStats};
+#[cfg(feature = "metrics")]
+use super::metrics::{Metrics, MetricsSnapshot, STATUS_OK};
+use super::offline::{OfflineQueue, OfflineQueueConfig, QueuedMessage, QueuedOutcome};
+use super::paging::{PAGE_CURSOR_METADATA_KEY, next_cursor};
+use super::presence::{
+    PRESENCE_QUERY_METHOD, PRESENCE_SET_METHOD, PresenceQueryRequest, PresenceSetRequest,
+    PresenceState, PresenceStatus, PresenceWatch, presence_topic,
+};
+use super::pubsub::{
+    ACK_METHOD, AckRequest, SUBSCRIBE_METHOD, SubscribeRequest, SubscribeResponse, SubscriptionId,
+    UNSUBSCRIBE_METHOD, UnsubscribeRequest,
+};
+use super::quic::{MessageStream, QuicClient};
+use super::reconnect::{
+    AbandonOnDrop, PendingStream, PendingStreams, ReconnectError, ReconnectPolicy,
+};
+use super::resume::{ResumableStream, ResumeToken};
+use super::retry::RetryPolicy;
 use super::service::Service;
+use super::shutdown::{InFlightGuard, ShutdownController};
+use super::state::{ConnectionState, StateEvent};
+use super::stdio::MessageClient;
+use super::stream_id::StreamIdConfig;
+use super::tls::TlsConfig;
+use super::websocket::{WebSocketClient, is_websocket_url};
 use super::{
-    MessageType, NetworkError, ProtocolClientTrait, ProtocolMessage, UnisonClient, UnisonClientExt,
+    MessageType, NetworkError, ProtocolClientTrait, ProtocolError, ProtocolMessage, UnisonClient,
+    UnisonClientExt,
 };
 
 // TransportWrapper removed - using QuicClient directly
@@ -18,6 +58,33 @@ use super::{
 pub struct ProtocolClient {
     transport: Arc<QuicClient>,
     services: Arc<RwLock<HashMap<String, crate::network::service::UnisonService>>>,
+    coalescer: Option<RequestCoalescer>,
+    offline_queue: Option<Arc<OfflineQueue>>,
+    failover: Option<Arc<EndpointSelector>>,
+    /// 接続断・ドレイン通知を監視して接続先を切り替えるタスク
+    failover_task: StdMutex<Option<tokio::task::JoinHandle<()>>>,
+    reconnect: Option<ReconnectPolicy>,
+    /// 接続断を監視して再接続するタスク
+    reconnect_task: StdMutex<Option<tokio::task::JoinHandle<()>>>,
+    /// 失敗した単項呼び出しの再試行
+    retry: Option<RetryPolicy>,
+    /// 冪等キーを付けて厳密に1回処理させるメソッド
+    exactly_once: HashSet<String>,
+    /// 再接続時に再開するストリーム
+    pending_streams: PendingStreams,
+    /// WebSocket・メモリトランスポートで接続した場合の接続（QUICの代わりに使う）
+    channel: Option<MessageClient>,
+    /// WebSocketで送るメッセージの形式
+    wire_format: WireFormat,
+    /// 永続購読名を自分のものとして示す資格情報
+    durable_credential: String,
+    /// 受信・監視タスクを実行するランタイム（`None`の場合は呼び出し元のランタイム）
+    runtime: Option<tokio::runtime::Handle>,
+    /// [`Self::shutdown`]の停止要求と、レスポンスを待っている呼び出し
+    shutdown: ShutdownController,
+    /// Prometheus互換のメトリクス
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
 }
 
 // Transport trait removed - using direct implementation on TransportWrapper
@@ -27,6 +94,22 @@ impl ProtocolClient {
         Self {
             transport: Arc::new(transport),
             services: Arc::new(RwLock::new(HashMap::new())),
+            coalescer: None,
+            offline_queue: None,
+            failover: None,
+            failover_task: StdMutex::new(None),
+            reconnect: None,
+            reconnect_task: StdMutex::new(None),
+            retry: None,
+            exactly_once: HashSet::new(),
+            pending_streams: PendingStreams::default(),
+            channel: None,
+            wire_format: WireFormat::default(),
+            durable_credential: uuid::Uuid::new_v4().to_string(),
+            runtime: None,
+            shutdown: ShutdownController::new(),
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new("unison_client"),
         }
     }
 
@@ -36,9 +119,244 @@ impl ProtocolClient {
         Ok(Self {
             transport: Arc::new(transport),
             services: Arc::new(RwLock::new(HashMap::new())),
+            coalescer: None,
+            offline_queue: None,
+            failover: None,
+            failover_task: StdMutex::new(None),
+            reconnect: None,
+            reconnect_task: StdMutex::new(None),
+            retry: None,
+            exactly_once: HashSet::new(),
+            pending_streams: PendingStreams::default(),
+            channel: None,
+            wire_format: WireFormat::default(),
+            durable_credential: uuid::Uuid::new_v4().to_string(),
+            runtime: None,
+            shutdown: ShutdownController::new(),
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new("unison_client"),
         })
     }
 
+    /// 接続断からの自動再接続を有効化
+    ///
+    /// [`Self::connect`]で接続した後に接続が失われると、ポリシーのバックオフで
+    /// 同じ接続先へ再接続し、受信中の再開可能なストリームを再開します。
+    /// 経過は[`Self::state_events`]・[`Self::on_state_change`]で確認できます。
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// WebSocketで送るメッセージの形式を指定
+    ///
+    /// 既定は[`WireFormat::Packet`]です。パケット層に対応していない古いサーバーへ
+    /// 接続する場合は[`WireFormat::Json`]を指定します。QUICは常にパケットを使います。
+    pub fn with_wire_format(mut self, format: WireFormat) -> Self {
+        self.wire_format = format;
+        self
+    }
+
+    /// 永続購読の資格情報を指定
+    ///
+    /// 永続名は初回の購読時の資格情報に結び付けられます。既定値はクライアントごとの
+    /// ランダムな値のため、プロセスを再起動して同じ永続名を引き継ぐ場合は固定の値を指定します。
+    pub fn with_durable_credential(mut self, credential: impl Into<String>) -> Self {
+        self.durable_credential = credential.into();
+        self
+    }
+
+    /// 受信タスク・監視タスクとQUICの接続を駆動するタスクを実行するランタイムを指定
+    ///
+    /// IO専用のランタイムを用意している場合などに使います。接続前に指定してください。
+    pub fn with_runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        match Arc::get_mut(&mut self.transport) {
+            Some(transport) => transport.set_runtime(runtime.clone()),
+            None => warn!("Transport is already shared, QUIC tasks keep the current runtime"),
+        }
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// 接続ごとにハートビートを送り、応答しなくなったサーバーとの接続を閉じる
+    ///
+    /// 接続が閉じると接続状態がIdleへ遷移し、再接続ポリシーがあれば再接続します。
+    /// 接続前に指定してください。
+    pub fn with_heartbeat(mut self, config: HeartbeatConfig) -> Self {
+        match Arc::get_mut(&mut self.transport) {
+            Some(transport) => transport.set_heartbeat(config),
+            None => warn!("Transport is already shared, heartbeat is not enabled"),
+        }
+        self
+    }
+
+    /// 接続ごとに往復時間を測り、回線が遅い間はより強く圧縮する
+    ///
+    /// 切り替えはサーバーと制御パケットで取り決め、リクエストとレスポンスの両方に適用されます。
+    /// QUIC以外の接続では何もしません。接続前に指定してください。
+    pub fn with_adaptive_encoding(mut self, config: AdaptiveEncodingConfig) -> Self {
+        match Arc::get_mut(&mut self.transport) {
+            Some(transport) => transport.set_adaptive_encoding(config),
+            None => warn!("Transport is already shared, adaptive encoding is not enabled"),
+        }
+        self
+    }
+
+    /// 双方向ストリームのIDの割り当て方を指定
+    ///
+    /// サーバーの[`ProtocolServer::with_stream_ids`](super::ProtocolServer::with_stream_ids)と
+    /// 同じ方式を指定してください。QUIC以外の接続では何もしません。接続前に指定してください。
+    pub fn with_stream_ids(mut self, config: StreamIdConfig) -> Self {
+        match Arc::get_mut(&mut self.transport) {
+            Some(transport) => transport.set_stream_ids(config),
+            None => warn!("Transport is already shared, stream ID config is not applied"),
+        }
+        self
+    }
+
+    /// ハンドシェイクでサーバーと照合するスキーマのハッシュを指定
+    ///
+    /// サーバーも指定していて値が異なる場合、接続は[`HandshakeError::SchemaMismatch`]で失敗します。
+    /// 接続前に指定してください。
+    pub fn with_schema_hash(self, hash: impl Into<String>) -> Self {
+        self.with_schema(SchemaIdentity::from_fingerprint(hash))
+    }
+
+    /// 読み込んだスキーマの指紋をハンドシェイクでサーバーと照合
+    ///
+    /// 指紋が異なる場合、接続は[`HandshakeError::SchemaMismatch`]で失敗し、
+    /// このクライアントのスキーマにあってサーバーにないメソッドが`missing_methods`に入ります。
+    /// 接続前に指定してください。
+    pub fn with_schema_fingerprint(self, schema: &ParsedSchema) -> Self {
+        self.with_schema(SchemaIdentity::from_schema(schema))
+    }
+
+    fn with_schema(mut self, schema: SchemaIdentity) -> Self {
+        match Arc::get_mut(&mut self.transport) {
+            Some(transport) => transport.set_schema(schema),
+            None => warn!("Transport is already shared, schema hash is not sent"),
+        }
+        self
+    }
+
+    /// ハンドシェイクで接続単位のメタデータを送る（QUICのみ、接続前に指定）
+    pub fn with_handshake_metadata(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        match Arc::get_mut(&mut self.transport) {
+            Some(transport) => transport.set_handshake_metadata(key, value),
+            None => warn!("Transport is already shared, handshake metadata is not sent"),
+        }
+        self
+    }
+
+    /// サーバーの証明書の検証方法・クライアント証明書（mTLS）・サーバー名を指定（QUICのみ、接続前に指定）
+    pub fn with_tls(mut self, tls: impl Into<TlsConfig>) -> Self {
+        match Arc::get_mut(&mut self.transport) {
+            Some(transport) => transport.set_tls(tls.into()),
+            None => warn!("Transport is already shared, TLS config is not applied"),
+        }
+        self
+    }
+
+    /// エラーメッセージのロケールをハンドシェイクでサーバーに伝える
+    ///
+    /// サーバーが[`ErrorCatalog`](super::ErrorCatalog)を設定していれば、
+    /// エラーの`message`がこのロケールで返ります（コードは変わりません）。
+    pub fn with_locale(self, locale: impl Into<String>) -> Self {
+        self.with_handshake_metadata(super::LOCALE_METADATA_KEY, locale)
+    }
+
+    /// 現在の接続のハンドシェイクで取り決めた内容（QUIC以外の接続や未接続の場合は`None`）
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        match self.channel {
+            Some(_) => None,
+            None => self.transport.capabilities(),
+        }
+    }
+
+    /// 現在の接続で使っている圧縮のプロファイル（QUIC以外の接続では`None`）
+    pub fn encoding(&self) -> Option<EncodingProfile> {
+        match self.channel {
+            Some(_) => None,
+            None => Some(self.transport.encoding()),
+        }
+    }
+
+    /// 現在の接続の往復時間・送受信量・開いたストリームの数など
+    ///
+    /// QUIC以外の接続や未接続の場合は`None`を返します。
+    pub fn connection_stats(&self) -> Option<ConnectionStats> {
+        match self.channel {
+            Some(_) => None,
+            None => self.transport.connection_stats(),
+        }
+    }
+
+    /// 受信・監視タスクを実行するランタイム
+    pub fn runtime(&self) -> Option<&tokio::runtime::Handle> {
+        self.runtime.as_ref()
+    }
+
+    /// 指定のランタイム（なければ呼び出し元のランタイム）でタスクを起動
+    fn spawn_background<F>(&self, future: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.runtime {
+            Some(runtime) => runtime.spawn(future),
+            None => tokio::spawn(future),
+        }
+    }
+
+    /// 失敗した単項呼び出しの自動再試行を有効化
+    ///
+    /// サーバーが`UNAVAILABLE`・`RATE_LIMITED`で拒否した呼び出しは常に、接続断・タイムアウトで
+    /// 失敗した呼び出しは冪等なメソッドに限り、ポリシーのバックオフで再送します。
+    /// 合流した呼び出しは1回の送信として再試行されます。
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// 指定したメソッドの呼び出しに冪等キーを付け、サーバーに厳密に1回処理させる
+    ///
+    /// サーバーにも同じメソッドを[`ProtocolServer::with_exactly_once`](super::ProtocolServer::with_exactly_once)で
+    /// 指定します。呼び出しは冪等な呼び出しとして扱われ、[`Self::with_retry_policy`]の再試行では
+    /// 同じ冪等キーで再送されます。
+    pub fn with_exactly_once(mut self, config: ExactlyOnceConfig) -> Self {
+        self.exactly_once = config.methods;
+        self
+    }
+
+    /// 同一リクエストの合流を有効化
+    ///
+    /// 同じメソッド・同じペイロードの同時呼び出しは1回の送信にまとめられ、
+    /// レスポンスが共有されます。
+    pub fn with_coalescing(mut self, config: CoalesceConfig) -> Self {
+        self.coalescer = Some(RequestCoalescer::new(config));
+        self
+    }
+
+    /// 切断中の送信をキューに保持するオフラインキューを有効化
+    pub fn with_offline_queue(mut self, config: OfflineQueueConfig) -> Self {
+        self.offline_queue = Some(Arc::new(OfflineQueue::new(config)));
+        self
+    }
+
+    /// オフラインキューに保持中のメッセージ数
+    pub fn queued_len(&self) -> usize {
+        self.offline_queue.as_ref().map_or(0, |queue| queue.len())
+    }
+
+    /// リクエスト合流の統計（無効な場合は`None`）
+    pub fn coalesce_stats(&self) -> Option<CoalesceStats> {
+        self.coalescer.as_ref().map(RequestCoalescer::stats)
+    }
+
     /// Register a Service instance with the client
     pub async fn register_service(&self, service: crate::network::service::UnisonService) {
         let service_name = service.service_name().to_string();
@@ -69,71 +387,903 @@ impl ProtocolClient {
         }
     }
 
+    /// 型付きのリクエストを送信し、型付きのレスポンスを待機
+    ///
+    /// リクエストのメタデータと期限をサーバーへ送り、レスポンスのメタデータを含めて返します。
+    /// 期限を過ぎると`Timeout`を返します。
+    pub async fn request<Req, Res>(
+        &self,
+        request: Request<Req>,
+    ) -> Result<Response<Res>, NetworkError>
+    where
+        Req: Serialize,
+        Res: DeserializeOwned,
+    {
+        let _call = self.begin_call()?;
+        let deadline = request.deadline;
+        let message = request.to_message(generate_request_id())?;
+        let exchange = async {
+            match &self.channel {
+                Some(channel) => channel.request(message).await,
+                None => self
+                    .transport
+                    .request_with_deadline(message, deadline)
+                    .await
+                    .map_err(request_error),
+            }
+        };
+        let response = match deadline {
+            Some(deadline) => {
+                let remaining =
+                    super::deadline::remaining(deadline).ok_or(NetworkError::Timeout)?;
+                tokio::time::timeout(remaining, exchange)
+                    .await
+                    .map_err(|_| NetworkError::Timeout)??
+            }
+            None => exchange.await?,
+        };
+
+        if response.msg_type == MessageType::Error {
+            return Err(response_error(&response));
+        }
+        Response::from_message(&response)
+    }
+
+    /// コアの[`UnisonMessage`]を送信し、[`UnisonResponse`]を待機
+    ///
+    /// メッセージは他の呼び出しと同じくパケットで送られ、元のメッセージIDは
+    /// [`MESSAGE_ID_METADATA_KEY`](super::MESSAGE_ID_METADATA_KEY)のメタデータで
+    /// ハンドラーへ渡ります。ハンドラーのエラー（[`NetworkError::Remote`]）は
+    /// `success: false`のレスポンスになります。
+    pub async fn send_message(
+        &self,
+        message: UnisonMessage,
+    ) -> Result<UnisonResponse, NetworkError> {
+        let id = message.id.clone();
+        match self
+            .request::<serde_json::Value, serde_json::Value>(Request::from(message))
+            .await
+        {
+            Ok(response) => Ok(UnisonResponse::success(id, response.body)),
+            Err(NetworkError::Remote(error)) => Ok(UnisonResponse::error(id, error.message)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 期限などのオプションを指定して呼び出す
+    ///
+    /// 期限はサーバーへ伝わり、期限切れのリクエストはハンドラーを実行せずに拒否されます。
+    /// 期限までにレスポンスがなければ待機を打ち切り、いずれの場合も
+    /// `NetworkError::Timeout`を返します。
+    ///
+    /// WebSocket・メモリトランスポートで接続している場合、期限はクライアント側でのみ適用されます。
+    /// メタデータはどのトランスポートでもサーバーへ送られます。
+    ///
+    /// [`Self::with_retry_policy`]を設定している場合は期限までの間で再試行し、
+    /// [`CallOptions::idempotent`]を指定した呼び出しは冪等なメソッドとして扱います。
+    pub async fn call_with_options(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+        options: CallOptions,
+    ) -> Result<serde_json::Value, NetworkError> {
+        let _call = self.begin_call()?;
+        let options = self.exactly_once_options(method, options);
+        let deadline = options.effective_deadline(std::time::SystemTime::now());
+        #[cfg(feature = "metrics")]
+        let (started, sent) = (std::time::Instant::now(), json_len(&payload));
+        let result = match &self.retry {
+            Some(policy) => {
+                policy
+                    .run(method, options.idempotent, deadline, || {
+                        self.send_with_options(method, payload.clone(), &options, deadline)
+                    })
+                    .await
+            }
+            None => {
+                self.send_with_options(method, payload, &options, deadline)
+                    .await
+            }
+        };
+        #[cfg(feature = "metrics")]
+        self.record_metrics(method, started, sent, result.as_ref().map_err(Some));
+        result
+    }
+
+    /// 厳密に1回処理させるメソッドの呼び出しに冪等キーを付け、冪等な呼び出しとして扱う
+    ///
+    /// 呼び出し元が冪等キーを指定している場合はそのキーを使います。
+    fn exactly_once_options(&self, method: &str, mut options: CallOptions) -> CallOptions {
+        if self.exactly_once.contains(method) {
+            options
+                .metadata
+                .entry(IDEMPOTENCY_KEY_METADATA_KEY.to_string())
+                .or_insert_with(|| uuid::Uuid::new_v4().to_string());
+            options.idempotent = true;
+        }
+        options
+    }
+
+    /// [`Self::call_with_options`]の1回分の送信
+    async fn send_with_options(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+        options: &CallOptions,
+        deadline: Option<std::time::SystemTime>,
+    ) -> Result<serde_json::Value, NetworkError> {
+        if let Some(channel) = &self.channel {
+            let exchange = async {
+                if options.metadata.is_empty() && options.encoding == PayloadEncoding::Json {
+                    return channel.call(method, payload).await;
+                }
+                let message = options_message(method, &payload, options)?;
+                let response = channel.request(message).await?;
+                if response.msg_type == MessageType::Error {
+                    return Err(response_error(&response));
+                }
+                response.payload_as_value()
+            };
+            return match deadline {
+                Some(deadline) => {
+                    let remaining =
+                        super::deadline::remaining(deadline).ok_or(NetworkError::Timeout)?;
+                    tokio::time::timeout(remaining, exchange)
+                        .await
+                        .map_err(|_| NetworkError::Timeout)?
+                }
+                None => exchange.await,
+            };
+        }
+        let message = options_message(method, &payload, options)?;
+
+        self.with_failover(method, || {
+            request_message(&self.transport, message.clone(), deadline)
+        })
+        .await
+        .map_err(request_error)
+    }
+
+    /// メタデータ（認証トークン・トレースID・ロケールなど）を付けて呼び出す
+    ///
+    /// ハンドラーは[`request_metadata`](super::request_metadata)でメタデータを参照できます。
+    pub async fn call_with_metadata(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+        metadata: HashMap<String, String>,
+    ) -> Result<serde_json::Value, NetworkError> {
+        let options = CallOptions {
+            metadata,
+            ..CallOptions::default()
+        };
+        self.call_with_options(method, payload, options).await
+    }
+
+    /// サーバーへ接続
+    ///
+    /// `ws://`・`wss://`のURLはWebSocketで、`memory://`のURLは同じプロセス内の
+    /// サーバーへ、それ以外はQUICで接続します。
+    /// QUIC以外の接続では自動再接続・オフラインキューは使われません。
     pub async fn connect(&mut self, url: &str) -> Result<()> {
-        // Arc::get_mutを使用してmutableアクセス
-        Arc::get_mut(&mut self.transport)
-            .ok_or_else(|| anyhow::anyhow!("Failed to get mutable transport"))?
-            .connect(url)
+        self.reset_shutdown();
+        if is_channel_url(url) {
+            self.connect_channel(url).await?;
+            return Ok(());
+        }
+        self.channel = None;
+        self.transport.connect(url).await?;
+        self.start_reconnect();
+
+        // 切断中にキューへ入れたメッセージを送信
+        self.flush_offline_queue().await;
+        Ok(())
+    }
+
+    /// WebSocket・メモリトランスポートで接続
+    async fn connect_channel(&mut self, url: &str) -> Result<(), NetworkError> {
+        self.stop_background_tasks().await;
+        // 送受信タスクを指定のランタイムで起動するため、接続もそのランタイムで行う
+        let url = url.to_string();
+        let format = self.wire_format;
+        let channel = self
+            .spawn_background(async move {
+                if is_websocket_url(&url) {
+                    Ok(WebSocketClient::connect_with_wire_format(&url, format)
+                        .await?
+                        .into_inner())
+                } else {
+                    super::memory::connect(&url)
+                }
+            })
             .await
+            .map_err(|e| NetworkError::Connection(format!("Connect task failed: {}", e)))??;
+        self.channel = Some(channel);
+        Ok(())
+    }
+
+    /// 再接続ポリシーが指定されていれば接続断の監視を開始
+    fn start_reconnect(&self) {
+        let Some(policy) = self.reconnect.clone() else {
+            return;
+        };
+        let task = self.spawn_background(run_reconnect(
+            Arc::clone(&self.transport),
+            policy,
+            self.offline_queue.clone(),
+            self.pending_streams.clone(),
+        ));
+        if let Some(previous) = self.reconnect_task.lock().unwrap().replace(task) {
+            previous.abort();
+        }
+    }
+
+    /// 接続断の監視タスクを中断し、終了するまで待機
+    async fn stop_background_tasks(&self) {
+        for slot in [&self.failover_task, &self.reconnect_task] {
+            let task = slot.lock().unwrap().take();
+            if let Some(task) = task {
+                task.abort();
+                let _ = task.await;
+            }
+        }
+    }
+
+    /// 優先順の接続先リストで接続し、以降のフェイルオーバーを有効化
+    ///
+    /// プライマリから順に接続を試み、最初に成功した接続先を使います。
+    /// 接続断やサーバーからのドレイン通知を受けると、次の接続先へ自動的に切り替えます。
+    pub async fn connect_with_failover(&mut self, config: FailoverConfig) -> Result<()> {
+        if config.endpoints.is_empty() && config.discovery.is_none() {
+            return Err(FailoverError::NoEndpoints.into());
+        }
+        self.reset_shutdown();
+        let selector = Arc::new(EndpointSelector::new(config));
+        self.failover = Some(Arc::clone(&selector));
+
+        failover_connect(&self.transport, &selector, true).await?;
+        self.flush_offline_queue().await;
+
+        let task = self.spawn_background(run_failover(
+            Arc::clone(&self.transport),
+            selector,
+            self.offline_queue.clone(),
+        ));
+        if let Some(previous) = self.failover_task.lock().unwrap().replace(task) {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    /// 複数の接続先に優先順で接続し、接続・呼び出しの失敗時に次の接続先へ切り替える
+    ///
+    /// [`FailoverConfig::from_endpoints`]での[`Self::connect_with_failover`]と同じです。
+    /// 順序の決め方や接続先の検出を指定する場合は`connect_with_failover`を使います。
+    pub async fn connect_multi<S: AsRef<str>>(&mut self, endpoints: &[S]) -> Result<()> {
+        self.connect_with_failover(FailoverConfig::from_endpoints(
+            endpoints
+                .iter()
+                .map(|endpoint| endpoint.as_ref().to_string()),
+        ))
+        .await
+    }
+
+    /// 現在接続しているフェイルオーバー先（無効な場合は`None`）
+    pub fn current_endpoint(&self) -> Option<String> {
+        self.failover
+            .as_ref()
+            .and_then(|selector| selector.current())
+    }
+
+    /// 呼び出しを送信し、切断中ならオフラインキューに入れる
+    ///
+    /// 結果（レスポンス・エラー・期限切れ）は`on_complete`に通知されます。
+    /// オフラインキューが無効な場合、切断中はエラーを返します。
+    pub async fn call_or_queue<F>(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+        on_complete: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(QueuedOutcome) + Send + 'static,
+    {
+        self.send_or_queue(QueuedMessage::new(
+            method,
+            payload,
+            Some(Box::new(on_complete)),
+        ))
+        .await
+    }
+
+    /// 結果を必要としない通知を送信し、切断中ならオフラインキューに入れる
+    pub async fn notify_or_queue(&self, method: &str, payload: serde_json::Value) -> Result<()> {
+        self.send_or_queue(QueuedMessage::new(method, payload, None))
+            .await
+    }
+
+    async fn send_or_queue(&self, message: QueuedMessage) -> Result<()> {
+        let _call = self.begin_call()?;
+        if self.transport.is_connected().await {
+            let result =
+                send_request(&self.transport, &message.method, message.payload.clone()).await;
+            message.complete(match result {
+                Ok(value) => QueuedOutcome::Delivered(value),
+                Err(e) => QueuedOutcome::Failed(e.to_string()),
+            });
+            return Ok(());
+        }
+
+        let queue = self
+            .offline_queue
+            .as_ref()
+            .ok_or(NetworkError::NotConnected)?;
+        queue.push(message)?;
+        Ok(())
+    }
+
+    /// オフラインキューのメッセージを発行順に送信し、送信した件数を返す
+    ///
+    /// 送信中に再び切断された場合は、未送信のメッセージをキューに残して中断します。
+    pub async fn flush_offline_queue(&self) -> usize {
+        match &self.offline_queue {
+            Some(queue) => flush_queue(&self.transport, queue).await,
+            None => 0,
+        }
+    }
+
+    /// 新しい呼び出しの受け付けを止め、レスポンスを待っている呼び出しの完了を
+    /// 最大`timeout`待ってから接続を閉じる
+    ///
+    /// 停止を要求した後の呼び出しは`NetworkError::ShuttingDown`で失敗します。
+    /// ストリーミング呼び出しは開始までを待ち、開始後のストリームの受信は待ちません。
+    /// 期限までに完了しなかった呼び出しの数を返します（それらは接続を閉じたことで失敗します）。
+    /// 再び[`Self::connect`]すると呼び出しを受け付けます。
+    pub async fn shutdown(&self, timeout: std::time::Duration) -> Result<usize> {
+        self.shutdown.trigger();
+        let abandoned = if self.shutdown.wait_idle(timeout).await {
+            0
+        } else {
+            self.shutdown.in_flight()
+        };
+        if abandoned > 0 {
+            warn!("Closing client with {} calls still pending", abandoned);
+        }
+
+        self.stop_background_tasks().await;
+        match &self.channel {
+            Some(channel) => channel.close(),
+            None => self.transport.disconnect().await?,
+        }
+        Ok(abandoned)
+    }
+
+    /// サーバーとの往復時間を`samples`回計測し、統計を返す
+    ///
+    /// ハートビートと同じping/pongの制御パケットを使うため、サーバーのハンドラーは実行されません。
+    /// 回線品質に合わせてコーデックやビットレートを切り替える場合などに使います。
+    /// QUIC以外の接続では`UnsupportedTransport`を返します。
+    pub async fn measure_latency(&self, samples: usize) -> Result<LatencyStats, NetworkError> {
+        let _call = self.begin_call()?;
+        if self.channel.is_some() {
+            return Err(NetworkError::UnsupportedTransport(
+                "latency probing requires QUIC".to_string(),
+            ));
+        }
+        if samples == 0 {
+            return Err(NetworkError::Protocol(
+                "At least one sample is required".to_string(),
+            ));
+        }
+        if !self.transport.is_connected().await {
+            return Err(NetworkError::NotConnected);
+        }
+        self.transport
+            .measure_latency(samples)
+            .await
+            .map_err(|e| NetworkError::Quic(format!("{:#}", e)))
+    }
+
+    /// サーバーが呼び出しを受け付けられるようになるまで待機
+    ///
+    /// 接続（ハンドシェイクを含む）とpingによるヘルスチェックを、成功するまで間隔を空けて
+    /// 繰り返します。テストやベンチマークで、サーバーの起動を`sleep`で待つ代わりに使います。
+    /// `timeout`までに準備できなければ`Timeout`を、スキーマの不一致などハンドシェイクで
+    /// 互換性がないと分かった場合は再試行せずに`Handshake`を返します。
+    pub async fn wait_until_ready(
+        &mut self,
+        url: &str,
+        timeout: std::time::Duration,
+    ) -> Result<(), NetworkError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut delay = READY_RETRY_INITIAL_DELAY;
+        loop {
+            match tokio::time::timeout_at(deadline, self.check_ready(url)).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(e @ NetworkError::Handshake(_))) => return Err(e),
+                Ok(Err(e)) => debug!("Server at {} is not ready yet: {}", url, e),
+                Err(_) => return Err(NetworkError::Timeout),
+            }
+            if tokio::time::Instant::now() + delay >= deadline {
+                return Err(NetworkError::Timeout);
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(READY_RETRY_MAX_DELAY);
+        }
+    }
+
+    /// 未接続なら接続し、pingに応答するか確認（QUIC以外の接続は接続状態のみ確認）
+    async fn check_ready(&mut self, url: &str) -> Result<(), NetworkError> {
+        if !UnisonClient::is_connected(self) {
+            UnisonClient::connect(self, url).await?;
+        }
+        match self.measure_latency(1).await {
+            Ok(_) => Ok(()),
+            Err(NetworkError::UnsupportedTransport(_)) if UnisonClient::is_connected(self) => {
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 停止中でなければ、呼び出しをレスポンスを待っているものとして記録
+    fn begin_call(&self) -> Result<InFlightGuard, NetworkError> {
+        // 停止要求と入れ違いにならないよう、記録してから停止中か確認する
+        let call = self.shutdown.track();
+        if self.shutdown.is_stopping() {
+            return Err(NetworkError::ShuttingDown);
+        }
+        Ok(call)
+    }
+
+    /// 停止後に接続し直す場合、呼び出しの受け付けを再開する
+    fn reset_shutdown(&mut self) {
+        if self.shutdown.is_stopping() {
+            self.shutdown = ShutdownController::new();
+        }
     }
 
     pub async fn disconnect(&mut self) -> Result<()> {
-        Arc::get_mut(&mut self.transport)
-            .ok_or_else(|| anyhow::anyhow!("Failed to get mutable transport"))?
-            .disconnect()
+        self.stop_background_tasks().await;
+        if self.channel.take().is_some() {
+            return Ok(());
+        }
+        self.transport.disconnect().await
+    }
+
+    /// Prometheus互換のメトリクス（接続しているか・レスポンスを待っている呼び出しの数を含む）
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            active_connections: u64::from(UnisonClient::is_connected(self)),
+            in_flight: self.shutdown.in_flight() as u64,
+            ..self.metrics.snapshot()
+        }
+    }
+
+    /// 呼び出しの結果・処理時間・ペイロードのバイト数を記録
+    #[cfg(feature = "metrics")]
+    fn record_metrics(
+        &self,
+        method: &str,
+        started: std::time::Instant,
+        sent: u64,
+        result: Result<&serde_json::Value, Option<&NetworkError>>,
+    ) {
+        let status = match result {
+            Ok(_) => STATUS_OK.to_string(),
+            Err(Some(NetworkError::Remote(error))) => error.code.clone(),
+            Err(Some(NetworkError::Timeout)) => ProtocolError::TIMEOUT.to_string(),
+            Err(Some(NetworkError::ShuttingDown)) => ProtocolError::UNAVAILABLE.to_string(),
+            Err(_) => "error".to_string(),
+        };
+        self.metrics
+            .record_request(method, &status, started.elapsed());
+        let received = result.map_or(0, json_len);
+        self.metrics.record_bytes(method, sent, received);
+    }
+
+    /// 型付きの単項呼び出し（[`ProtocolClientTrait::call`]・[`ProtocolClientTrait::call_idempotent`]）
+    async fn call_typed<TRequest, TResponse>(
+        &self,
+        method: &str,
+        request: TRequest,
+        idempotent: bool,
+    ) -> Result<TResponse>
+    where
+        TRequest: Serialize,
+        TResponse: for<'de> Deserialize<'de>,
+    {
+        // 冪等キーを付けて送るため、同じペイロードの呼び出しとも合流させない
+        if self.exactly_once.contains(method) {
+            let payload = serde_json::to_value(request)?;
+            let response = self
+                .call_with_options(method, payload, CallOptions::default())
+                .await?;
+            return serde_json::from_value(response).context("Failed to deserialize response");
+        }
+
+        let _call = self.begin_call()?;
+        let payload = serde_json::to_value(request)?;
+        #[cfg(feature = "metrics")]
+        let (started, sent) = (std::time::Instant::now(), json_len(&payload));
+        let result = match &self.coalescer {
+            Some(coalescer) => {
+                coalescer
+                    .call(method, payload, |payload| {
+                        self.send_with_retry(method, payload, idempotent)
+                    })
+                    .await
+            }
+            None => self.send_with_retry(method, payload, idempotent).await,
+        };
+        #[cfg(feature = "metrics")]
+        self.record_metrics(
+            method,
+            started,
+            sent,
+            result
+                .as_ref()
+                .map_err(|e| e.downcast_ref::<NetworkError>()),
+        );
+        let payload_value = result?;
+
+        let result: TResponse =
+            serde_json::from_value(payload_value).context("Failed to deserialize response")?;
+
+        Ok(result)
+    }
+
+    /// リクエストを送信し、失敗した場合は再試行のポリシーに従って再送
+    async fn send_with_retry(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+        idempotent: bool,
+    ) -> Result<serde_json::Value> {
+        match &self.retry {
+            Some(policy) => {
+                policy
+                    .run(method, idempotent, None, || {
+                        self.send_with_failover(method, payload.clone())
+                    })
+                    .await
+            }
+            None => self.send_with_failover(method, payload).await,
+        }
+    }
+
+    /// リクエストを送信し、失敗した場合は接続先を切り替えて再送
+    async fn send_with_failover(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        if let Some(channel) = &self.channel {
+            return Ok(channel.call(method, payload).await?);
+        }
+        self.with_failover(method, || {
+            send_request(&self.transport, method, payload.clone())
+        })
+        .await
+    }
+
+    /// `send`で送信し、失敗した場合はフェイルオーバーの設定に従って切り替え・再送する
+    ///
+    /// 接続断で失敗した呼び出しは冪等なメソッドに限り、サーバーが`UNAVAILABLE`で
+    /// 拒否した呼び出しは常に、切り替え後に再送します。
+    async fn with_failover<F, Fut>(&self, method: &str, send: F) -> Result<serde_json::Value>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<serde_json::Value>>,
+    {
+        let Some(selector) = &self.failover else {
+            return send().await;
+        };
+        let mut switches = selector.switches();
+        let result = send().await;
+        let Some(rejected) = result.as_ref().err().and_then(failover_cause) else {
+            return result;
+        };
+        let retry = rejected || selector.config().is_retryable(method);
+        let wait = selector.config().retry_wait;
+
+        // 呼び出し中に切り替わっておらず接続も保たれている場合は、切り替えを要求して完了を待つ
+        // （切断された場合の切り替えは`run_failover`が行う）
+        let switched = switches.has_changed().unwrap_or(false);
+        if !switched && self.transport.is_connected().await {
+            selector.request_failover();
+            if !retry {
+                return result;
+            }
+            if !matches!(
+                tokio::time::timeout(wait, switches.changed()).await,
+                Ok(Ok(()))
+            ) {
+                return result;
+            }
+        } else if !retry {
+            return result;
+        }
+        match tokio::time::timeout(wait, self.transport.wait_for_state(ConnectionState::Ready))
             .await
+        {
+            Ok(()) => send().await,
+            Err(_) => result,
+        }
     }
 
     pub async fn is_connected(&self) -> bool {
+        if let Some(channel) = &self.channel {
+            return channel.is_open();
+        }
         self.transport.is_connected().await
     }
-}
 
-impl ProtocolClientTrait for ProtocolClient {
-    async fn call<TRequest, TResponse>(&self, method: &str, request: TRequest) -> Result<TResponse>
+    /// 最後に接続したURLへ再接続し、オフラインキューを送信
+    pub async fn reconnect(&self) -> Result<()> {
+        self.transport.reconnect().await?;
+        self.flush_offline_queue().await;
+        Ok(())
+    }
+
+    /// 現在の接続状態
+    pub fn state(&self) -> ConnectionState {
+        self.transport.state()
+    }
+
+    /// 接続状態が遷移するたびにコールバックを呼び出す
+    ///
+    /// 返されたハンドルを中断すると通知を停止します。
+    pub fn on_state_change<F>(&self, callback: F) -> tokio::task::JoinHandle<()>
     where
-        TRequest: Serialize + Send + Sync,
-        TResponse: for<'de> Deserialize<'de>,
+        F: Fn(&StateEvent) + Send + 'static,
     {
-        // Generate a unique request ID
-        let request_id = generate_request_id();
+        let mut events = self.state_events();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                callback(&event);
+            }
+        })
+    }
 
-        // Create the protocol message
+    /// 接続状態の遷移イベントを購読
+    ///
+    /// UIへの接続状態の反映などに使えます。購読開始以降の遷移のみを受け取ります。
+    pub fn state_events(&self) -> Pin<Box<dyn Stream<Item = StateEvent> + Send>> {
+        self.transport.state_events()
+    }
+
+    /// サーバーからのプッシュ通知（ブロードキャスト等）を受信
+    pub async fn receive_event(&self) -> Result<ProtocolMessage> {
+        if let Some(channel) = &self.channel {
+            return Ok(channel
+                .next_event()
+                .await
+                .ok_or(NetworkError::NotConnected)?);
+        }
+        self.transport.receive_event().await
+    }
+
+    /// トピックを購読
+    ///
+    /// `filter`を指定するとサーバー側で評価され、一致したイベントのみが
+    /// [`Self::receive_event`]に届きます。
+    pub async fn subscribe(&self, topic: &str, filter: Option<&str>) -> Result<SubscriptionId> {
+        let request = SubscribeRequest {
+            topic: topic.to_string(),
+            filter: filter.map(str::to_string),
+            durable: None,
+            credential: None,
+        };
+        let response: SubscribeResponse =
+            ProtocolClientTrait::call(self, SUBSCRIBE_METHOD, request).await?;
+        Ok(response.subscription_id)
+    }
+
+    /// 永続名を付けてトピックを購読
+    ///
+    /// 同じ永続名で再購読すると、確認応答していないイベントがサーバーから再送されます。
+    /// 受信したイベントは`method`（トピック）と`id`（オフセット）を[`Self::ack`]へ渡して確認します。
+    pub async fn subscribe_durable(
+        &self,
+        name: &str,
+        topic: &str,
+        filter: Option<&str>,
+    ) -> Result<SubscriptionId> {
+        let request = SubscribeRequest {
+            topic: topic.to_string(),
+            filter: filter.map(str::to_string),
+            durable: Some(name.to_string()),
+            credential: Some(self.durable_credential.clone()),
+        };
+        let response: SubscribeResponse =
+            ProtocolClientTrait::call(self, SUBSCRIBE_METHOD, request).await?;
+        Ok(response.subscription_id)
+    }
+
+    /// 永続購読のイベントを確認応答
+    pub async fn ack(&self, name: &str, topic: &str, offset: u64) -> Result<()> {
+        let request = AckRequest {
+            durable: name.to_string(),
+            topic: topic.to_string(),
+            offset,
+        };
+        let _: serde_json::Value = ProtocolClientTrait::call(self, ACK_METHOD, request).await?;
+        Ok(())
+    }
+
+    /// 購読を解除
+    pub async fn unsubscribe(&self, subscription_id: SubscriptionId) -> Result<()> {
+        let _: serde_json::Value = ProtocolClientTrait::call(
+            self,
+            UNSUBSCRIBE_METHOD,
+            UnsubscribeRequest { subscription_id },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// 自身のプレゼンスを宣言
+    ///
+    /// サーバーのタイムアウトより短い間隔で呼び直すことでオンライン状態が維持されます。
+    pub async fn set_presence(
+        &self,
+        id: &str,
+        status: PresenceStatus,
+        data: serde_json::Value,
+    ) -> Result<()> {
+        let request = PresenceSetRequest {
+            id: id.to_string(),
+            status,
+            data,
+        };
+        let _: serde_json::Value =
+            ProtocolClientTrait::call(self, PRESENCE_SET_METHOD, request).await?;
+        Ok(())
+    }
+
+    /// プレゼンスを照会（`ids`省略時はオンラインの全ID）
+    pub async fn query_presence(&self, ids: Option<&[&str]>) -> Result<Vec<PresenceState>> {
+        let request = PresenceQueryRequest {
+            ids: ids.map(|ids| ids.iter().map(|id| id.to_string()).collect()),
+        };
+        ProtocolClientTrait::call(self, PRESENCE_QUERY_METHOD, request).await
+    }
+
+    /// ロスターのプレゼンス変化の監視を開始
+    ///
+    /// 各IDのプレゼンストピックを購読してから現在の状態を取得するため、
+    /// 取得後の変化は[`Self::receive_event`]で漏れなく受け取れます。
+    pub async fn watch_presence(&self, ids: &[&str]) -> Result<PresenceWatch> {
+        let mut subscriptions = Vec::with_capacity(ids.len());
+        for id in ids {
+            subscriptions.push(self.subscribe(&presence_topic(id), None).await?);
+        }
+        let roster = self.query_presence(Some(ids)).await?;
+        Ok(PresenceWatch {
+            subscriptions,
+            roster,
+        })
+    }
+
+    /// 再開可能なストリーミングRPC呼び出しを開始
+    ///
+    /// 返されるストリームは受信したレジュームトークンを保持し、
+    /// 切断後は[`Self::resume_stream`]で続きから受信できます。
+    pub async fn stream_resumable<TRequest, TResponse>(
+        &self,
+        method: &str,
+        request: TRequest,
+    ) -> Result<ResumableStream<TResponse>>
+    where
+        TRequest: Serialize + Send + Sync,
+        TResponse: for<'de> Deserialize<'de> + Send + 'static,
+    {
+        let call = self.begin_call()?;
         let message = ProtocolMessage::new_with_json(
-            request_id,
+            generate_request_id(),
             method.to_string(),
-            MessageType::Request,
+            MessageType::Stream,
             serde_json::to_value(request)?,
         )?;
+        let messages = self.transport.request_stream(message).await?;
+        drop(call);
 
-        // Send the request
-        self.transport.send(message).await?;
+        let token_slot = Arc::new(StdMutex::new(None));
+        let pending = self.pending_streams.track(Arc::clone(&token_slot));
+        let stream = receive_stream(messages, Some(pending), self.reconnect.is_some());
+        Ok(ResumableStream::new(stream, token_slot))
+    }
 
-        // Wait for the response
-        // In a real implementation, this would use a proper request/response correlation mechanism
-        let response = self.transport.receive().await?;
+    /// レジュームトークンを使って中断したストリームを再開
+    pub async fn resume_stream<TResponse>(
+        &self,
+        token: ResumeToken,
+    ) -> Result<ResumableStream<TResponse>>
+    where
+        TResponse: for<'de> Deserialize<'de> + Send + 'static,
+    {
+        let messages = send_resume(&self.transport, &token).await?;
 
-        if response.msg_type == MessageType::Error {
-            let payload_value = response
-                .payload_as_value()
-                .context("Failed to parse error payload")?;
-            return Err(anyhow::anyhow!(
-                "Protocol error: {}",
-                payload_value
-                    .get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown error")
-            ));
-        }
+        let token_slot = Arc::new(StdMutex::new(Some(token)));
+        let pending = self.pending_streams.track(Arc::clone(&token_slot));
+        let stream = receive_stream(messages, Some(pending), self.reconnect.is_some());
+        Ok(ResumableStream::new(stream, token_slot))
+    }
+}
 
-        // Deserialize the response
-        let payload_value = response
-            .payload_as_value()
-            .context("Failed to parse response payload")?;
-        let result: TResponse =
-            serde_json::from_value(payload_value).context("Failed to deserialize response")?;
+impl ProtocolClientTrait for ProtocolClient {
+    async fn call<TRequest, TResponse>(&self, method: &str, request: TRequest) -> Result<TResponse>
+    where
+        TRequest: Serialize + Send + Sync,
+        TResponse: for<'de> Deserialize<'de>,
+    {
+        self.call_typed(method, request, false).await
+    }
 
-        Ok(result)
+    async fn call_idempotent<TRequest, TResponse>(
+        &self,
+        method: &str,
+        request: TRequest,
+    ) -> Result<TResponse>
+    where
+        TRequest: Serialize + Send + Sync,
+        TResponse: for<'de> Deserialize<'de>,
+    {
+        self.call_typed(method, request, true).await
+    }
+
+    async fn call_negotiated<TRequest, TResponse>(
+        &self,
+        method: &str,
+        request: TRequest,
+        accepted: &[&str],
+        idempotent: bool,
+    ) -> Result<TResponse>
+    where
+        TRequest: Serialize + Send + Sync,
+        TResponse: for<'de> Deserialize<'de>,
+    {
+        let encoding = negotiate(&PayloadEncoding::ALL, accepted).ok_or_else(|| {
+            anyhow::anyhow!("No supported payload encoding for {method}: {accepted:?}")
+        })?;
+        if encoding == PayloadEncoding::Json {
+            return self.call_typed(method, request, idempotent).await;
+        }
+        let options = CallOptions::default()
+            .with_encoding(encoding)
+            .with_idempotent(idempotent);
+        let payload = self
+            .call_with_options(method, serde_json::to_value(request)?, options)
+            .await?;
+        serde_json::from_value(payload).context("Failed to deserialize response")
+    }
+
+    fn call_paged<'a, TRequest, TResponse>(
+        &'a self,
+        method: &'a str,
+        request: TRequest,
+    ) -> Pin<Box<dyn Stream<Item = Result<TResponse>> + Send + 'a>>
+    where
+        TRequest: Serialize + Clone + Send + Sync + 'a,
+        TResponse: for<'de> Deserialize<'de> + Send + 'a,
+    {
+        Box::pin(async_stream::try_stream! {
+            let mut cursor: Option<String> = None;
+            loop {
+                let mut page = Request::new(method, request.clone());
+                if let Some(cursor) = cursor.take() {
+                    page = page.with_metadata(PAGE_CURSOR_METADATA_KEY, cursor);
+                }
+                let response: Response<TResponse> = self.request(page).await?;
+                cursor = next_cursor(&response.metadata);
+                yield response.body;
+                if cursor.is_none() {
+                    break;
+                }
+            }
+        })
     }
 
     async fn stream<TRequest, TResponse>(
@@ -145,6 +1295,14 @@ impl ProtocolClientTrait for ProtocolClient {
         TRequest: Serialize + Send + Sync,
         TResponse: for<'de> Deserialize<'de> + Send + 'static,
     {
+        let _call = self.begin_call()?;
+        if let Some(channel) = &self.channel {
+            let stream = channel.stream(method, serde_json::to_value(request)?)?;
+            return Ok(Box::pin(stream.map(|item| {
+                serde_json::from_value(item?).context("Failed to deserialize stream item")
+            })));
+        }
+
         // Generate a unique request ID
         let request_id = generate_request_id();
 
@@ -156,51 +1314,509 @@ impl ProtocolClientTrait for ProtocolClient {
             serde_json::to_value(request)?,
         )?;
 
-        // Send the stream request
-        self.transport.send(message).await?;
+        // 同じストリームで届くメッセージを受信
+        let messages = self.transport.request_stream(message).await?;
 
-        // Create a stream that receives messages
-        let transport = Arc::clone(&self.transport);
-        let stream = async_stream::stream! {
-            loop {
-                match transport.receive().await {
-                    Ok(msg) => {
-                        match msg.msg_type {
-                            MessageType::StreamData => {
-                                match msg.payload_as_value() {
-                                    Ok(payload_value) => {
-                                        match serde_json::from_value::<TResponse>(payload_value) {
-                                            Ok(data) => yield Ok(data),
-                                            Err(e) => yield Err(anyhow::anyhow!("Deserialization error: {}", e)),
-                                        }
-                                    }
-                                    Err(e) => yield Err(anyhow::anyhow!("Failed to parse payload: {}", e)),
+        Ok(receive_stream(messages, None, false))
+    }
+
+    async fn client_stream<TRequest, TResponse, S>(
+        &self,
+        method: &str,
+        requests: S,
+    ) -> Result<TResponse>
+    where
+        TRequest: Serialize + Send,
+        TResponse: for<'de> Deserialize<'de>,
+        S: Stream<Item = TRequest> + Send + 'static,
+    {
+        let _call = self.begin_call()?;
+        let requests =
+            requests.map(|request| serde_json::to_value(request).map_err(NetworkError::from));
+        let payload_value = match &self.channel {
+            Some(channel) => channel.client_stream(method, requests).await?,
+            None => {
+                let message = ProtocolMessage::new_with_json(
+                    generate_request_id(),
+                    method.to_string(),
+                    MessageType::ClientStream,
+                    serde_json::Value::Null,
+                )?;
+                let response = self
+                    .transport
+                    .request_client_stream(message, requests)
+                    .await?;
+                if response.msg_type == MessageType::Error {
+                    return Err(response_error(&response).into());
+                }
+                response.payload_as_value()?
+            }
+        };
+
+        serde_json::from_value(payload_value).context("Failed to deserialize response")
+    }
+}
+
+impl Drop for ProtocolClient {
+    fn drop(&mut self) {
+        // 監視タスクはトランスポートを共有しているため、切断せずに破棄された場合も止める
+        for slot in [&mut self.failover_task, &mut self.reconnect_task] {
+            if let Some(task) = slot.get_mut().unwrap().take() {
+                task.abort();
+            }
+        }
+    }
+}
+
+/// リクエストを送信してレスポンスのペイロードを受信
+/// オフラインキューのメッセージを発行順に送信し、送信した件数を返す
+async fn flush_queue(transport: &QuicClient, queue: &OfflineQueue) -> usize {
+    let mut sent = 0;
+    while let Some(message) = queue.pop() {
+        if !transport.is_connected().await {
+            queue.push_front(message);
+            break;
+        }
+        match send_request(transport, &message.method, message.payload.clone()).await {
+            Ok(value) => message.complete(QueuedOutcome::Delivered(value)),
+            Err(_) if !transport.is_connected().await => {
+                queue.push_front(message);
+                break;
+            }
+            Err(e) => message.complete(QueuedOutcome::Failed(e.to_string())),
+        }
+        sent += 1;
+    }
+    sent
+}
+
+/// レジュームトークンでストリームの再開を要求
+async fn send_resume(transport: &QuicClient, token: &ResumeToken) -> Result<MessageStream> {
+    let message = ProtocolMessage::new_with_json(
+        generate_request_id(),
+        token.method.clone(),
+        MessageType::StreamResume,
+        serde_json::to_value(token)?,
+    )?;
+    transport.request_stream(message).await
+}
+
+/// 接続断を監視し、バックオフしながら最後の接続先へ再接続する
+async fn run_reconnect(
+    transport: Arc<QuicClient>,
+    policy: ReconnectPolicy,
+    queue: Option<Arc<OfflineQueue>>,
+    pending: PendingStreams,
+) {
+    // 監視を終えると再開できないため、再開を待つストリームを終わらせる
+    let _abandon = AbandonOnDrop(pending.clone());
+    let mut events = transport.state_events();
+    while let Some(event) = events.next().await {
+        // 明示的な切断で監視を終了
+        if event.current == ConnectionState::Closed {
+            break;
+        }
+        if event.previous != ConnectionState::Ready || event.current != ConnectionState::Idle {
+            continue;
+        }
+        warn!(
+            "Connection lost ({}), reconnecting",
+            event.reason.as_deref().unwrap_or("unknown reason")
+        );
+
+        if let Err(e) = reconnect_with_backoff(&transport, &policy).await {
+            error!("{}", e);
+            break;
+        }
+        for (token, stream) in pending.resumable() {
+            match send_resume(&transport, &token).await {
+                Ok(messages) => stream.resume(messages),
+                Err(e) => {
+                    warn!("Failed to resume stream {}: {:#}", token.stream_id, e);
+                    stream.abandon();
+                }
+            }
+        }
+        if let Some(queue) = &queue {
+            flush_queue(&transport, queue).await;
+        }
+    }
+}
+
+async fn reconnect_with_backoff(
+    transport: &QuicClient,
+    policy: &ReconnectPolicy,
+) -> std::result::Result<(), ReconnectError> {
+    let mut attempt = 0;
+    let mut last_error = String::new();
+    while policy.allows(attempt) {
+        tokio::time::sleep(policy.delay(attempt)).await;
+        attempt += 1;
+        match transport.reconnect().await {
+            Ok(()) => {
+                info!("Reconnected after {} attempt(s)", attempt);
+                return Ok(());
+            }
+            Err(e) => {
+                warn!("Reconnect attempt {} failed: {:#}", attempt, e);
+                last_error = format!("{:#}", e);
+            }
+        }
+    }
+    Err(ReconnectError::Exhausted {
+        attempts: attempt,
+        last_error,
+    })
+}
+
+/// 接続先の候補を順に試す
+///
+/// 初回接続ではプライマリから、切り替え時は現在の次の接続先から試します。
+async fn failover_connect(
+    transport: &QuicClient,
+    selector: &EndpointSelector,
+    initial: bool,
+) -> Result<()> {
+    selector.refresh().await;
+    let candidates = if initial {
+        selector.initial_candidates()
+    } else {
+        selector.failover_candidates()
+    };
+    if candidates.is_empty() {
+        return Err(FailoverError::NoEndpoints.into());
+    }
+
+    let attempts = candidates.len();
+    let mut last_error = String::new();
+    for (index, endpoint) in candidates {
+        let result = if initial {
+            transport.connect(&endpoint).await
+        } else {
+            transport.reconnect_to(&endpoint).await
+        };
+        match result {
+            Ok(()) => {
+                selector.mark_connected(index);
+                info!("Connected to endpoint {}", endpoint);
+                return Ok(());
+            }
+            Err(e) => {
+                warn!("Failed to connect to endpoint {}: {:#}", endpoint, e);
+                last_error = format!("{:#}", e);
+            }
+        }
+    }
+    Err(FailoverError::Exhausted {
+        attempts,
+        last_error,
+    }
+    .into())
+}
+
+/// 呼び出しの失敗が接続先の切り替えを要するか
+///
+/// サーバーが処理せずに拒否した（`UNAVAILABLE`）場合は`Some(true)`、
+/// 接続のエラーの場合は`Some(false)`を返します。
+fn failover_cause(error: &anyhow::Error) -> Option<bool> {
+    match error.downcast_ref::<NetworkError>() {
+        Some(NetworkError::Remote(remote))
+            if remote.code == ProtocolError::UNAVAILABLE.to_string() =>
+        {
+            Some(true)
+        }
+        Some(NetworkError::Connection(_) | NetworkError::Quic(_) | NetworkError::NotConnected) => {
+            Some(false)
+        }
+        Some(_) => None,
+        // QUICのストリームの読み書きのエラーなど
+        None => Some(false),
+    }
+}
+
+/// 接続断・ドレイン通知・呼び出しの失敗を監視し、次の接続先へ切り替える
+///
+/// [`FailoverConfig::refresh_interval`]が指定された場合は接続先のリストを定期的に取得し直し、
+/// 現在の接続先がリストから消えた場合も切り替えます。
+async fn run_failover(
+    transport: Arc<QuicClient>,
+    selector: Arc<EndpointSelector>,
+    queue: Option<Arc<OfflineQueue>>,
+) {
+    let mut events = transport.state_events();
+    let mut refresh = selector.config().refresh_interval.map(|interval| {
+        let mut refresh = tokio::time::interval(interval);
+        refresh.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        refresh.reset();
+        refresh
+    });
+    loop {
+        tokio::select! {
+            _ = async {
+                match &mut refresh {
+                    Some(refresh) => refresh.tick().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                if selector.refresh().await {
+                    continue;
+                }
+                info!(
+                    "Endpoint {} is no longer discovered, failing over",
+                    selector.current().unwrap_or_default()
+                );
+            }
+            _ = selector.failover_requested() => {
+                warn!(
+                    "Calls to {} are failing, failing over",
+                    selector.current().unwrap_or_default()
+                );
+            }
+            event = events.next() => match event {
+                // 明示的な切断でフェイルオーバーを終了
+                None => break,
+                Some(event) if event.current == ConnectionState::Closed => break,
+                Some(event)
+                    if event.previous == ConnectionState::Ready
+                        && event.current == ConnectionState::Idle =>
+                {
+                    warn!(
+                        "Connection to {} lost, failing over",
+                        selector.current().unwrap_or_default()
+                    );
+                }
+                Some(_) => continue,
+            },
+            _ = transport.drained() => {
+                info!(
+                    "Endpoint {} is draining, failing over",
+                    selector.current().unwrap_or_default()
+                );
+            }
+        }
+
+        match failover_connect(&transport, &selector, false).await {
+            Ok(()) => {
+                if let Some(queue) = &queue {
+                    flush_queue(&transport, queue).await;
+                }
+            }
+            Err(e) => error!("Failover failed: {}", e),
+        }
+    }
+}
+
+/// メッセージを送信してレスポンスのペイロードを受け取る
+async fn request_message(
+    transport: &QuicClient,
+    message: ProtocolMessage,
+    deadline: Option<std::time::SystemTime>,
+) -> Result<serde_json::Value> {
+    let response = transport.request_with_deadline(message, deadline).await?;
+    if response.msg_type == MessageType::Error {
+        return Err(response_error(&response).into());
+    }
+    Ok(response.payload_as_value()?)
+}
+
+async fn send_request(
+    transport: &QuicClient,
+    method: &str,
+    payload: serde_json::Value,
+) -> Result<serde_json::Value> {
+    // Generate a unique request ID
+    let request_id = generate_request_id();
+
+    // Create the protocol message
+    let message = ProtocolMessage::new_with_json(
+        request_id,
+        method.to_string(),
+        MessageType::Request,
+        payload,
+    )?;
+
+    // リクエストごとのストリームでレスポンスを受信
+    let response = transport.request(message).await?;
+
+    if response.msg_type == MessageType::Error {
+        return Err(response_error(&response).into());
+    }
+
+    // Deserialize the response
+    response
+        .payload_as_value()
+        .context("Failed to parse response payload")
+}
+
+/// ストリームメッセージを受信してレスポンス型のストリームに変換
+///
+/// `pending`が指定された場合、受信したレジュームトークンを保存します。`auto_resume`が`true`の
+/// 場合は、`StreamEnd`の前に接続が失われると再接続後に再開したストリームから受信を続けます。
+fn receive_stream<TResponse>(
+    mut messages: MessageStream,
+    pending: Option<Arc<PendingStream>>,
+    auto_resume: bool,
+) -> Pin<Box<dyn Stream<Item = Result<TResponse>> + Send>>
+where
+    TResponse: for<'de> Deserialize<'de> + Send + 'static,
+{
+    let stream = async_stream::stream! {
+        loop {
+            let lost = match messages.next().await {
+                Some(Ok(msg)) => {
+                    match msg.msg_type {
+                        MessageType::StreamData => {
+                            // 受信済みの件数をトークンに反映（次のトークンを待たずに続きから再開できる）
+                            if let Some(p) = pending.as_ref() {
+                                if let Some(token) = p.token.lock().unwrap().as_mut() {
+                                    token.sequence += 1;
                                 }
                             }
-                            MessageType::StreamEnd => {
-                                break;
+                            match msg.payload_as_value() {
+                                Ok(payload_value) => {
+                                    match serde_json::from_value::<TResponse>(payload_value) {
+                                        Ok(data) => yield Ok(data),
+                                        Err(e) => yield Err(anyhow::anyhow!("Deserialization error: {}", e)),
+                                    }
+                                }
+                                Err(e) => yield Err(anyhow::anyhow!("Failed to parse payload: {}", e)),
                             }
-                            MessageType::Error => {
-                                let error_msg = msg.payload_as_value()
-                                    .ok()
-                                    .and_then(|v| v.get("message").and_then(|m| m.as_str()).map(|s| s.to_string()))
-                                    .unwrap_or_else(|| "Unknown error".to_string());
-                                yield Err(anyhow::anyhow!("Stream error: {}", error_msg));
-                                break;
+                        }
+                        MessageType::StreamResumeToken => {
+                            if let Some(slot) = pending.as_ref().map(|p| &p.token) {
+                                if let Ok(token) = msg
+                                    .payload_as_value()
+                                    .and_then(|v| Ok(serde_json::from_value::<ResumeToken>(v)?))
+                                {
+                                    *slot.lock().unwrap() = Some(token);
+                                }
                             }
-                            _ => {}
                         }
+                        MessageType::StreamEnd => {
+                            break;
+                        }
+                        MessageType::Error | MessageType::StreamError => {
+                            yield Err(response_error(&msg).into());
+                            break;
+                        }
+                        _ => {}
                     }
-                    Err(e) => {
-                        yield Err(e);
-                        break;
-                    }
+                    continue;
+                }
+                Some(Err(e)) => e,
+                None => anyhow::anyhow!("Stream closed before StreamEnd"),
+            };
+
+            // 再開できる場合は再接続後のストリームを待つ
+            let resumable = auto_resume
+                && pending
+                    .as_ref()
+                    .is_some_and(|p| p.token.lock().unwrap().is_some());
+            let resumed = match pending.as_ref() {
+                Some(p) if resumable => p.next_resumed().await,
+                _ => None,
+            };
+            match resumed {
+                Some(resumed) => messages = resumed,
+                None => {
+                    yield Err(lost);
+                    break;
                 }
             }
-        };
+        }
+        // 受信を終えたストリームは再接続時に再開しない
+        drop(pending);
+    };
+
+    Box::pin(stream)
+}
 
-        Ok(Box::pin(stream))
+/// エラーレスポンスを`NetworkError`に変換（実行期限の超過は`Timeout`）
+pub(crate) fn response_error(response: &ProtocolMessage) -> NetworkError {
+    let payload_value = match response.payload_as_value() {
+        Ok(value) => value,
+        Err(e) => return NetworkError::Protocol(format!("Failed to parse error payload: {}", e)),
+    };
+    let code = payload_value
+        .get("code")
+        .and_then(|v| v.as_i64())
+        .map_or(ProtocolError::INTERNAL, |code| code as i32);
+    if code == ProtocolError::DEADLINE_EXCEEDED {
+        return NetworkError::Timeout;
     }
+    let message = payload_value
+        .get("message")
+        .and_then(|v| v.as_str())
+        .unwrap_or("Unknown error");
+    let mut error = ProtocolError::new(code, message);
+    error.details = payload_value
+        .get("details")
+        .filter(|v| !v.is_null())
+        .cloned();
+    error.error_code = payload_value
+        .get("error_code")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    NetworkError::Remote(error.into())
+}
+
+/// QUICのリクエストのエラーを変換
+fn request_error(error: anyhow::Error) -> NetworkError {
+    match error.downcast::<NetworkError>() {
+        Ok(error) => error,
+        Err(error) => NetworkError::Protocol(error.to_string()),
+    }
+}
+
+/// [`ProtocolClient::wait_until_ready`]の再試行の最初の間隔（失敗するたびに2倍）
+const READY_RETRY_INITIAL_DELAY: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// [`ProtocolClient::wait_until_ready`]の再試行の最大の間隔
+const READY_RETRY_MAX_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// QUICの接続エラーを変換（ハンドシェイクの失敗はそのまま返す）
+fn connect_error(error: anyhow::Error) -> NetworkError {
+    match error.downcast::<HandshakeError>() {
+        Ok(error) => NetworkError::Handshake(error),
+        Err(error) => NetworkError::Connection(error.to_string()),
+    }
+}
+
+/// QUICの代わりにメッセージのチャネルで接続するURLか
+/// オプションのエンコーディングとメタデータを付けたリクエストのメッセージ
+fn options_message(
+    method: &str,
+    payload: &serde_json::Value,
+    options: &CallOptions,
+) -> Result<ProtocolMessage, NetworkError> {
+    let message = ProtocolMessage::new_encoded(
+        generate_request_id(),
+        method.to_string(),
+        MessageType::Request,
+        payload,
+        options.encoding,
+    )?;
+    if options.metadata.is_empty() {
+        return Ok(message);
+    }
+    let mut metadata = options.metadata.clone();
+    if options.encoding != PayloadEncoding::Json {
+        metadata.insert(
+            CONTENT_TYPE_METADATA_KEY.to_string(),
+            options.encoding.to_string(),
+        );
+    }
+    message.with_metadata(&metadata)
+}
+
+/// ペイロードをJSONにしたときのバイト数
+#[cfg(feature = "metrics")]
+fn json_len(value: &serde_json::Value) -> u64 {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len() as u64)
+}
+
+fn is_channel_url(url: &str) -> bool {
+    is_websocket_url(url) || super::memory::is_memory_url(url)
 }
 
 fn generate_request_id() -> u64 {
@@ -211,65 +1827,41 @@ fn generate_request_id() -> u64 {
 
 impl UnisonClient for ProtocolClient {
     async fn connect(&mut self, url: &str) -> Result<(), NetworkError> {
-        Arc::get_mut(&mut self.transport)
-            .ok_or_else(|| NetworkError::Connection("Failed to get mutable transport".to_string()))?
-            .connect(url)
-            .await
-            .map_err(|e| NetworkError::Connection(e.to_string()))
+        self.reset_shutdown();
+        if is_channel_url(url) {
+            return self.connect_channel(url).await;
+        }
+        self.channel = None;
+        self.transport.connect(url).await.map_err(connect_error)?;
+        self.start_reconnect();
+        Ok(())
     }
 
     async fn call(
-        &mut self,
+        &self,
         method: &str,
         payload: serde_json::Value,
     ) -> Result<serde_json::Value, NetworkError> {
-        let request_id = generate_request_id();
-
-        let message = ProtocolMessage::new_with_json(
-            request_id,
-            method.to_string(),
-            MessageType::Request,
-            payload,
-        )?;
-
-        self.transport
-            .send(message)
+        self.call_with_options(method, payload, CallOptions::default())
             .await
-            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
-
-        let response = self
-            .transport
-            .receive()
-            .await
-            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
-
-        if response.msg_type == MessageType::Error {
-            let payload_value = response.payload_as_value().map_err(|e| {
-                NetworkError::Protocol(format!("Failed to parse error payload: {}", e))
-            })?;
-            return Err(NetworkError::Protocol(
-                payload_value
-                    .get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown error")
-                    .to_string(),
-            ));
-        }
-
-        response.payload_as_value()
     }
 
     async fn disconnect(&mut self) -> Result<(), NetworkError> {
-        Arc::get_mut(&mut self.transport)
-            .ok_or_else(|| NetworkError::Connection("Failed to get mutable transport".to_string()))?
+        self.stop_background_tasks().await;
+        if self.channel.take().is_some() {
+            return Ok(());
+        }
+        self.transport
             .disconnect()
             .await
             .map_err(|e| NetworkError::Connection(e.to_string()))
     }
 
     fn is_connected(&self) -> bool {
-        // これはトレイトで同期的である必要があります
-        false // 今のところ簡略化
+        if let Some(channel) = &self.channel {
+            return channel.is_open();
+        }
+        self.transport.state() == ConnectionState::Ready
     }
 }
 
@@ -279,21 +1871,22 @@ impl UnisonClientExt for ProtocolClient {
     async fn start_system_stream(
         &mut self,
         method: &str,
-        _payload: serde_json::Value,
+        payload: serde_json::Value,
     ) -> Result<crate::network::quic::UnisonStream, NetworkError> {
-        // use super::quic::UnisonStream;
-        use super::StreamHandle;
-
-        // Create a real QUIC bidirectional stream
-        let _handle = StreamHandle {
-            stream_id: generate_request_id(),
-            method: method.to_string(),
-            created_at: std::time::SystemTime::now(),
-        };
-
-        // QUICクライアントの接続を取得してUnisonStreamを作成
-        // 現在の実装では直接アクセスできないため、エラーを返す
-        Err(NetworkError::NotConnected)
+        let _call = self.begin_call()?;
+        // 双方向ストリームはQUICのストリームをそのまま使うため、他のトランスポートでは使えない
+        if self.channel.is_some() {
+            return Err(NetworkError::UnsupportedTransport(
+                "system streams require QUIC".to_string(),
+            ));
+        }
+        if !self.is_connected().await {
+            return Err(NetworkError::NotConnected);
+        }
+        self.transport
+            .start_system_stream(method, payload)
+            .await
+            .map_err(|e| NetworkError::Quic(format!("{:#}", e)))
     }
 
     async fn list_system_streams(&self) -> Result<Vec<super::StreamHandle>, NetworkError> {
@@ -0,0 +1,284 @@
+//! クライアント側のリクエスト合流（コアレッシング）
+//!
+//! 同じメソッド・同じペイロードの呼び出しが短い時間窓の中で同時に発生した場合、
+//! 最初の呼び出しだけを実際に送信し、後続の呼び出しはそのレスポンスを共有します。
+//! UIから同じ照会が重複して発行されるようなクライアントでサーバー負荷を抑えられます。
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// コアレッシングの設定
+#[derive(Debug, Clone)]
+pub struct CoalesceConfig {
+    /// 実行中のリクエストに合流できる、開始からの時間窓
+    pub window: Duration,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(50),
+        }
+    }
+}
+
+/// コアレッシングの統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoalesceStats {
+    /// 実際に送信されたリクエスト数
+    pub executed: u64,
+    /// 実行中のリクエストに合流した呼び出し数
+    pub coalesced: u64,
+}
+
+type CoalesceKey = (String, u64);
+type Waiter = oneshot::Sender<Result<Value, String>>;
+
+/// 呼び出しの役割
+enum Role {
+    /// 実際に送信し、結果を待機者へ配る
+    Leader(CoalesceKey),
+    /// 実行中の呼び出しに合流する
+    Follower(oneshot::Receiver<Result<Value, String>>),
+    /// 合流せず単独で実行する
+    Independent,
+}
+
+struct InFlight {
+    payload: String,
+    started_at: Instant,
+    waiters: Vec<Waiter>,
+}
+
+/// 同一リクエストを合流させるコアレッサー
+#[derive(Clone, Default)]
+pub struct RequestCoalescer {
+    config: CoalesceConfig,
+    inflight: Arc<Mutex<HashMap<CoalesceKey, InFlight>>>,
+    executed: Arc<AtomicU64>,
+    coalesced: Arc<AtomicU64>,
+}
+
+impl RequestCoalescer {
+    pub fn new(config: CoalesceConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &CoalesceConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> CoalesceStats {
+        CoalesceStats {
+            executed: self.executed.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 呼び出しを実行、または実行中の同一呼び出しに合流
+    ///
+    /// `execute`は合流先がない場合にのみ呼ばれます。
+    /// 合流した呼び出しには同じレスポンスが返り、エラーはメッセージとして共有されます。
+    pub async fn call<F, Fut>(&self, method: &str, payload: Value, execute: F) -> Result<Value>
+    where
+        F: FnOnce(Value) -> Fut,
+        Fut: Future<Output = Result<Value>>,
+    {
+        let serialized = serde_json::to_string(&payload)?;
+        let key = (method.to_string(), hash_payload(&serialized));
+
+        let role = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get_mut(&key) {
+                Some(entry)
+                    if entry.payload == serialized
+                        && entry.started_at.elapsed() <= self.config.window =>
+                {
+                    let (tx, rx) = oneshot::channel();
+                    entry.waiters.push(tx);
+                    Role::Follower(rx)
+                }
+                // 時間窓を過ぎた呼び出しや、ハッシュが衝突した別ペイロードには合流しない
+                Some(_) => Role::Independent,
+                None => {
+                    inflight.insert(
+                        key.clone(),
+                        InFlight {
+                            payload: serialized,
+                            started_at: Instant::now(),
+                            waiters: Vec::new(),
+                        },
+                    );
+                    Role::Leader(key)
+                }
+            }
+        };
+
+        match role {
+            Role::Follower(rx) => {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                match rx.await {
+                    Ok(result) => result.map_err(|e| anyhow::anyhow!(e)),
+                    Err(_) => Err(anyhow::anyhow!(
+                        "Coalesced request for {} was cancelled",
+                        method
+                    )),
+                }
+            }
+            Role::Leader(key) => self.execute(Some(key), payload, execute).await,
+            Role::Independent => self.execute(None, payload, execute).await,
+        }
+    }
+
+    async fn execute<F, Fut>(
+        &self,
+        key: Option<CoalesceKey>,
+        payload: Value,
+        execute: F,
+    ) -> Result<Value>
+    where
+        F: FnOnce(Value) -> Fut,
+        Fut: Future<Output = Result<Value>>,
+    {
+        self.executed.fetch_add(1, Ordering::Relaxed);
+        // 実行側がキャンセルされてもエントリが残らないようにする
+        let guard = key.map(|key| InFlightGuard {
+            inflight: Arc::clone(&self.inflight),
+            key,
+            completed: false,
+        });
+
+        let result = execute(payload).await;
+
+        if let Some(waiters) = guard.and_then(|g| g.take()) {
+            let shared = result.as_ref().map(Clone::clone).map_err(|e| e.to_string());
+            for waiter in waiters {
+                let _ = waiter.send(shared.clone());
+            }
+        }
+        result
+    }
+}
+
+/// 実行中エントリを確実に削除するガード
+struct InFlightGuard {
+    inflight: Arc<Mutex<HashMap<CoalesceKey, InFlight>>>,
+    key: CoalesceKey,
+    completed: bool,
+}
+
+impl InFlightGuard {
+    /// エントリを削除して待機者を取り出す
+    fn take(mut self) -> Option<Vec<Waiter>> {
+        self.completed = true;
+        self.inflight
+            .lock()
+            .unwrap()
+            .remove(&self.key)
+            .map(|entry| entry.waiters)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        if let Ok(mut inflight) = self.inflight.lock() {
+            // 待機者の送信側を破棄すると、合流した呼び出しにはキャンセルとして伝わる
+            inflight.remove(&self.key);
+        }
+    }
+}
+
+fn hash_payload(serialized: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serialized.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn test_identical_concurrent_calls_share_one_request() {
+        let coalescer = RequestCoalescer::new(CoalesceConfig::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let coalescer = coalescer.clone();
+                let calls = Arc::clone(&calls);
+                tokio::spawn(async move {
+                    coalescer
+                        .call("get_user", json!({"id": 1}), |payload| async move {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(30)).await;
+                            Ok(json!({"id": payload["id"], "name": "alice"}))
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap()["name"], "alice");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            coalescer.stats(),
+            CoalesceStats {
+                executed: 1,
+                coalesced: 4
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_different_payloads_are_not_coalesced() {
+        let coalescer = RequestCoalescer::default();
+        let (a, b) = tokio::join!(
+            coalescer.call("get_user", json!({"id": 1}), |p| async move { Ok(p) }),
+            coalescer.call("get_user", json!({"id": 2}), |p| async move { Ok(p) }),
+        );
+        assert_eq!(a.unwrap()["id"], 1);
+        assert_eq!(b.unwrap()["id"], 2);
+        assert_eq!(coalescer.stats().executed, 2);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_shared_and_window_expires() {
+        let coalescer = RequestCoalescer::new(CoalesceConfig {
+            window: Duration::ZERO,
+        });
+        let leader = coalescer.call("fail", Value::Null, |_| async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Err::<Value, _>(anyhow::anyhow!("boom"))
+        });
+        let late = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            // 時間窓を過ぎているため合流せず自身で実行する
+            coalescer
+                .call("fail", Value::Null, |_| async { Ok(json!("fresh")) })
+                .await
+        };
+        let (leader, late) = tokio::join!(leader, late);
+        assert_eq!(leader.unwrap_err().to_string(), "boom");
+        assert_eq!(late.unwrap(), "fresh");
+        assert_eq!(coalescer.stats().coalesced, 0);
+    }
+}
@@ -0,0 +1,215 @@
+//! 接続・ストリームごとの統計
+//!
+//! QUIC接続の往復時間・送受信したバイト数とパケット数・再送の数を[`ConnectionStats`]として、
+//! 双方向ストリームで送受信したメッセージとバイト数を[`SystemStreamStats`]として取得できます。
+//!
+//! - クライアント: [`ProtocolClient::connection_stats`](super::ProtocolClient::connection_stats)
+//! - サーバー: [`ProtocolServer::connection_stats`](super::ProtocolServer::connection_stats)
+//! - ストリーム: [`UnisonStream::stats`](super::UnisonStream::stats)
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::heartbeat::LatencyStats;
+
+/// ジッターの計算に使う直近の往復時間の数
+const MAX_RTT_SAMPLES: usize = 64;
+
+/// 1つの接続の統計
+///
+/// 経過時間以外はQUIC接続のみ取得でき、それ以外の接続では0です。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionStats {
+    /// 往復時間の推定値
+    pub rtt_ms: f64,
+    /// 受信したUDPデータグラムのバイト数
+    pub bytes_in: u64,
+    /// 送信したUDPデータグラムのバイト数
+    pub bytes_out: u64,
+    pub packets_in: u64,
+    pub packets_out: u64,
+    /// 失われて再送したパケットの数
+    pub retransmits: u64,
+    /// 接続で開いたQUICストリームの数（相手が開いたものを含み、制御パケットのものは除く）
+    pub streams_opened: u64,
+    pub uptime_seconds: u64,
+}
+
+impl ConnectionStats {
+    /// 送信したパケットのうち失われた割合
+    pub fn packet_loss_rate(&self) -> f64 {
+        if self.packets_out == 0 {
+            0.0
+        } else {
+            self.retransmits as f64 / self.packets_out as f64
+        }
+    }
+}
+
+struct MeterInner {
+    opened_at: Instant,
+    quic: Option<quinn::Connection>,
+    streams_opened: AtomicU64,
+    /// 統計を取得するたびに記録した往復時間
+    rtts: Mutex<VecDeque<Duration>>,
+}
+
+/// 1つの接続の統計の記録
+#[derive(Clone)]
+pub(crate) struct ConnectionMeter {
+    inner: Arc<MeterInner>,
+}
+
+impl ConnectionMeter {
+    /// 接続の記録を開始（QUIC以外の接続では`quic`を`None`にする）
+    pub(crate) fn new(quic: Option<quinn::Connection>) -> Self {
+        Self {
+            inner: Arc::new(MeterInner {
+                opened_at: Instant::now(),
+                quic,
+                streams_opened: AtomicU64::new(0),
+                rtts: Mutex::default(),
+            }),
+        }
+    }
+
+    /// ストリームを開いたことを記録
+    pub(crate) fn record_stream(&self) {
+        self.inner.streams_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 現在の統計
+    pub(crate) fn stats(&self) -> ConnectionStats {
+        let mut stats = ConnectionStats {
+            streams_opened: self.inner.streams_opened.load(Ordering::Relaxed),
+            uptime_seconds: self.inner.opened_at.elapsed().as_secs(),
+            ..ConnectionStats::default()
+        };
+        if let Some(connection) = &self.inner.quic {
+            let quic = connection.stats();
+            self.record_rtt(quic.path.rtt);
+            stats.rtt_ms = millis(quic.path.rtt);
+            stats.bytes_in = quic.udp_rx.bytes;
+            stats.bytes_out = quic.udp_tx.bytes;
+            stats.packets_in = quic.udp_rx.datagrams;
+            stats.packets_out = quic.path.sent_packets;
+            stats.retransmits = quic.path.lost_packets;
+        }
+        stats
+    }
+
+    /// 記録した往復時間の連続する差の平均
+    pub(crate) fn jitter_ms(&self) -> f64 {
+        let rtts = self.inner.rtts.lock().unwrap();
+        let samples: Vec<Duration> = rtts.iter().copied().collect();
+        LatencyStats::from_samples(&samples).map_or(0.0, |stats| millis(stats.jitter))
+    }
+
+    fn record_rtt(&self, rtt: Duration) {
+        let mut rtts = self.inner.rtts.lock().unwrap();
+        if rtts.len() == MAX_RTT_SAMPLES {
+            rtts.pop_front();
+        }
+        rtts.push_back(rtt);
+    }
+}
+
+/// 1本の双方向ストリームの統計
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemStreamStats {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    /// 送信したフレームのバイト数（書き込み待ちを含む）
+    pub bytes_sent: u64,
+    /// 受信したフレームのバイト数
+    pub bytes_received: u64,
+    /// 書き込み待ちのバイト数
+    pub buffered_bytes: u64,
+    /// ストリームを開いてからの時間
+    pub age_ms: u64,
+}
+
+/// ストリームの送受信の記録
+#[derive(Debug, Default)]
+pub(crate) struct StreamCounters {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl StreamCounters {
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self, buffered_bytes: usize, age: Duration) -> SystemStreamStats {
+        SystemStreamStats {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            buffered_bytes: buffered_bytes as u64,
+            age_ms: age.as_millis() as u64,
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_without_quic_counts_streams_only() {
+        let meter = ConnectionMeter::new(None);
+        meter.record_stream();
+        meter.record_stream();
+        let stats = meter.stats();
+        assert_eq!(stats.streams_opened, 2);
+        assert_eq!((stats.bytes_in, stats.packets_out), (0, 0));
+        assert_eq!(stats.packet_loss_rate(), 0.0);
+        assert_eq!(meter.jitter_ms(), 0.0);
+    }
+
+    #[test]
+    fn test_jitter_from_recorded_rtts() {
+        let meter = ConnectionMeter::new(None);
+        for ms in [10, 14, 12] {
+            meter.record_rtt(Duration::from_millis(ms));
+        }
+        assert!((meter.jitter_ms() - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_packet_loss_rate_and_stream_counters() {
+        let stats = ConnectionStats {
+            packets_out: 200,
+            retransmits: 5,
+            ..ConnectionStats::default()
+        };
+        assert!((stats.packet_loss_rate() - 0.025).abs() < 1e-9);
+
+        let counters = StreamCounters::default();
+        counters.record_sent(10);
+        counters.record_sent(20);
+        counters.record_received(7);
+        let stats = counters.stats(4, Duration::from_millis(1500));
+        assert_eq!((stats.messages_sent, stats.bytes_sent), (2, 30));
+        assert_eq!((stats.messages_received, stats.bytes_received), (1, 7));
+        assert_eq!((stats.buffered_bytes, stats.age_ms), (4, 1500));
+    }
+}
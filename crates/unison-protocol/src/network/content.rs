@@ -0,0 +1,220 @@
+//! メソッドごとのペイロードのエンコーディングの選択
+//!
+//! スキーマでメソッドが受け付けるエンコーディングを優先順に指定できます。
+//!
+//! ```kdl
+//! method "upload" {
+//!     encodings "msgpack" "json"
+//! }
+//! ```
+//!
+//! - リクエストのエンコーディングはメタデータ[`CONTENT_TYPE_METADATA_KEY`]で伝えます（省略時は`json`）
+//! - サーバーはメソッドが受け付けないエンコーディングのリクエストを`UNSUPPORTED_ENCODING`で拒否し、
+//!   リクエストと同じエンコーディングでレスポンスを返します
+//! - 生成したクライアントは、メソッドが受け付けるもののうち自身が対応する最も優先度の高い
+//!   エンコーディングを選びます（[`negotiate`]）
+//!
+//! [`ProtocolMessage`](super::ProtocolMessage)のペイロードは文字列のため、`msgpack`・`bytes`は
+//! Base64で格納します。ハンドラーにはエンコーディングに関係なく`serde_json::Value`が渡され、
+//! `bytes`のペイロードはBase64の文字列として扱います。
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::parser::Method;
+
+/// リクエスト・レスポンスのエンコーディングを格納するメタデータのキー
+pub const CONTENT_TYPE_METADATA_KEY: &str = "content-type";
+
+/// ペイロードのエンコーディング
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadEncoding {
+    /// JSONのテキスト
+    #[default]
+    Json,
+    /// MessagePack（Base64）
+    Msgpack,
+    /// 任意のバイト列（Base64、ハンドラーにはBase64の文字列として渡す）
+    Bytes,
+}
+
+impl PayloadEncoding {
+    /// このクレートが対応するエンコーディング
+    pub const ALL: [PayloadEncoding; 3] = [Self::Json, Self::Msgpack, Self::Bytes];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Msgpack => "msgpack",
+            Self::Bytes => "bytes",
+        }
+    }
+
+    /// 値をペイロードの文字列へ変換
+    pub fn encode(self, value: &Value) -> Result<String, ContentError> {
+        match self {
+            Self::Json => serde_json::to_string(value).map_err(|e| self.encode_error(e)),
+            Self::Msgpack => rmp_serde::to_vec_named(value)
+                .map(|bytes| STANDARD.encode(bytes))
+                .map_err(|e| self.encode_error(e)),
+            Self::Bytes => match value {
+                Value::String(data) if STANDARD.decode(data).is_ok() => Ok(data.clone()),
+                _ => Err(self.encode_error("payload must be a base64 string")),
+            },
+        }
+    }
+
+    /// ペイロードの文字列を値へ変換
+    pub fn decode(self, payload: &str) -> Result<Value, ContentError> {
+        match self {
+            Self::Json => serde_json::from_str(payload).map_err(|e| self.decode_error(e)),
+            Self::Msgpack => {
+                let bytes = STANDARD.decode(payload).map_err(|e| self.decode_error(e))?;
+                rmp_serde::from_slice(&bytes).map_err(|e| self.decode_error(e))
+            }
+            Self::Bytes => match STANDARD.decode(payload) {
+                Ok(_) => Ok(Value::String(payload.to_string())),
+                Err(e) => Err(self.decode_error(e)),
+            },
+        }
+    }
+
+    fn encode_error(self, error: impl fmt::Display) -> ContentError {
+        ContentError::Encode {
+            encoding: self,
+            reason: error.to_string(),
+        }
+    }
+
+    fn decode_error(self, error: impl fmt::Display) -> ContentError {
+        ContentError::Decode {
+            encoding: self,
+            reason: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PayloadEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PayloadEncoding {
+    type Err = ContentError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|encoding| encoding.name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| ContentError::Unknown(name.to_string()))
+    }
+}
+
+/// エンコーディングのエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ContentError {
+    #[error("Unknown payload encoding: {0}")]
+    Unknown(String),
+
+    #[error("Failed to encode payload as {encoding}: {reason}")]
+    Encode {
+        encoding: PayloadEncoding,
+        reason: String,
+    },
+
+    #[error("Failed to decode {encoding} payload: {reason}")]
+    Decode {
+        encoding: PayloadEncoding,
+        reason: String,
+    },
+
+    #[error("Method '{method}' does not accept {encoding} payloads (accepts: {accepted})")]
+    NotAccepted {
+        method: String,
+        encoding: PayloadEncoding,
+        accepted: String,
+    },
+}
+
+/// スキーマのメソッドが受け付けるエンコーディング（優先順、指定がなければ`json`のみ）
+pub fn method_encodings(method: &Method) -> Result<Vec<PayloadEncoding>, ContentError> {
+    if method.encodings.is_empty() {
+        return Ok(vec![PayloadEncoding::Json]);
+    }
+    method.encodings.iter().map(|name| name.parse()).collect()
+}
+
+/// メソッドが受け付けるエンコーディング`accepted`（優先順）のうち、`supported`に含まれる最初のもの
+///
+/// `accepted`が空の場合は`json`、知らない名前は無視します。
+pub fn negotiate(supported: &[PayloadEncoding], accepted: &[&str]) -> Option<PayloadEncoding> {
+    if accepted.is_empty() {
+        return supported
+            .contains(&PayloadEncoding::Json)
+            .then_some(PayloadEncoding::Json);
+    }
+    accepted
+        .iter()
+        .filter_map(|name| name.parse().ok())
+        .find(|encoding| supported.contains(encoding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip_each_encoding() {
+        let value = json!({ "name": "alice", "scores": [1, 2, 3], "active": true });
+        for encoding in [PayloadEncoding::Json, PayloadEncoding::Msgpack] {
+            let payload = encoding.encode(&value).unwrap();
+            assert_eq!(encoding.decode(&payload).unwrap(), value, "{encoding}");
+        }
+
+        let bytes = json!(STANDARD.encode([0u8, 159, 146, 150]));
+        let payload = PayloadEncoding::Bytes.encode(&bytes).unwrap();
+        assert_eq!(PayloadEncoding::Bytes.decode(&payload).unwrap(), bytes);
+        assert!(PayloadEncoding::Bytes.encode(&value).is_err());
+        assert!(PayloadEncoding::Msgpack.decode("{}").is_err());
+    }
+
+    #[test]
+    fn test_negotiate_prefers_method_order() {
+        let all = PayloadEncoding::ALL;
+        assert_eq!(
+            negotiate(&all, &["msgpack", "json"]),
+            Some(PayloadEncoding::Msgpack)
+        );
+        // 知らないエンコーディングは飛ばす
+        assert_eq!(
+            negotiate(&[PayloadEncoding::Json], &["cbor", "msgpack", "json"]),
+            Some(PayloadEncoding::Json)
+        );
+        assert_eq!(negotiate(&[PayloadEncoding::Json], &["bytes"]), None);
+        assert_eq!(negotiate(&all, &[]), Some(PayloadEncoding::Json));
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(
+            "MsgPack".parse::<PayloadEncoding>().unwrap(),
+            PayloadEncoding::Msgpack
+        );
+        assert_eq!(
+            "cbor".parse::<PayloadEncoding>().unwrap_err(),
+            ContentError::Unknown("cbor".into())
+        );
+        assert_eq!(
+            serde_json::to_value(PayloadEncoding::Bytes).unwrap(),
+            json!("bytes")
+        );
+    }
+}
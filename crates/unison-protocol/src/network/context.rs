@@ -0,0 +1,116 @@
+//! ハンドラーに渡す呼び出しの情報
+//!
+//! [`RequestContext`]は処理中のリクエストの接続・ストリーム・取り決めた機能・メタデータ・期限を
+//! まとめたものです。[`ProtocolServer::register_context_handler`](super::ProtocolServer::register_context_handler)
+//! で登録したハンドラーはペイロードと一緒に受け取ります。`Fn(Value)`のハンドラーは従来どおり使え、
+//! 必要な場合は[`RequestContext::current`]で同じ情報を取得できます。
+//!
+//! 接続の情報はトランスポートによって異なります。
+//!
+//! - QUIC: 相手のアドレス・ストリームID・ハンドシェイクで取り決めた機能
+//! - WebSocket: 相手のアドレス
+//! - 標準入出力・名前付きパイプ・メモリ: 接続IDのみ
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use super::broadcast::ConnectionId;
+use super::envelope::current_head;
+
+/// 処理中のリクエストの情報
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    pub method: String,
+    /// メッセージID
+    pub id: u64,
+    /// リクエストを受信した接続（サーバー内部から直接呼び出した場合は`None`）
+    pub connection_id: Option<ConnectionId>,
+    /// 相手のアドレス
+    pub remote_address: Option<SocketAddr>,
+    /// リクエストを受信したQUICのストリームID
+    pub stream_id: Option<u64>,
+    /// ハンドシェイクで双方が対応している機能
+    pub features: Vec<String>,
+    pub metadata: HashMap<String, String>,
+    /// 呼び出し元の期限
+    pub deadline: Option<SystemTime>,
+}
+
+impl RequestContext {
+    /// 処理中のリクエストの情報（処理中でなければメソッド名以外は空）
+    pub fn current(method: &str) -> Self {
+        let mut context = Self {
+            method: method.to_string(),
+            ..Self::default()
+        };
+        if let Some(head) = current_head() {
+            context.id = head.id;
+            context.connection_id = head.connection_id;
+            context.remote_address = head.remote_address;
+            context.stream_id = head.stream_id;
+            context.features = head.features;
+            context.metadata = head.metadata;
+            context.deadline = head.deadline;
+        }
+        context
+    }
+
+    /// メタデータの値
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// 双方が対応している機能か
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// 期限までの残り時間（期限がない、または過ぎていれば`None`）
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.and_then(super::deadline::remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::envelope::{RequestHead, with_request_head};
+    use crate::network::{MessageType, ProtocolMessage};
+
+    #[tokio::test]
+    async fn test_current_context_from_request_head() {
+        let message = ProtocolMessage::new_with_json(
+            5,
+            "greet".into(),
+            MessageType::Request,
+            serde_json::Value::Null,
+        )
+        .unwrap()
+        .with_metadata(&HashMap::from([("trace".into(), "t-1".into())]))
+        .unwrap();
+        let deadline = SystemTime::now() + Duration::from_secs(30);
+        let addr: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let head = RequestHead::from_message(&message, Some(deadline))
+            .unwrap()
+            .with_connection(7, Some(addr), vec!["compression".into()])
+            .with_stream_id(Some(12));
+
+        let context = with_request_head(head, async { RequestContext::current("greet") }).await;
+        assert_eq!((context.id, context.connection_id), (5, Some(7)));
+        assert_eq!(context.remote_address, Some(addr));
+        assert_eq!(context.stream_id, Some(12));
+        assert!(context.supports("compression"));
+        assert_eq!(context.metadata("trace"), Some("t-1"));
+        assert!(context.remaining().is_some());
+    }
+
+    #[test]
+    fn test_context_outside_request_is_empty() {
+        let context = RequestContext::current("greet");
+        assert_eq!(context.method, "greet");
+        assert_eq!(context.connection_id, None);
+        assert!(context.metadata.is_empty());
+        assert_eq!(context.remaining(), None);
+    }
+}
@@ -0,0 +1,171 @@
+//! 呼び出しごとの実行期限
+//!
+//! クライアントは[`CallOptions`]で指定した期限をパケットヘッダーの`DEADLINE`拡張
+//! （UNIXエポックからのミリ秒、u64リトルエンディアン）として送信します。
+//! クライアントは期限を過ぎるとレスポンスの待機を打ち切り、サーバーは期限切れの
+//! リクエストのハンドラーを実行せず`DEADLINE_EXCEEDED`を返します。
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::packet::{CompressionConfig, UnisonPacketBuilder, UnisonPacketHeader, extension_type};
+
+use super::content::PayloadEncoding;
+use super::{ProtocolFrame, ProtocolMessage};
+
+/// 呼び出しごとのオプション
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// 呼び出しからの待機時間
+    pub timeout: Option<Duration>,
+    /// 絶対時刻での期限
+    pub deadline: Option<SystemTime>,
+    /// 呼び出しと一緒に送るメタデータ（認証トークン・トレースIDなど）
+    pub metadata: HashMap<String, String>,
+    /// 冪等な呼び出し（[`RetryPolicy`](super::RetryPolicy)に従い、接続断・タイムアウト後も再送する）
+    pub idempotent: bool,
+    /// リクエストのペイロードのエンコーディング（メソッドが受け付けるものを指定する）
+    pub encoding: PayloadEncoding,
+}
+
+impl CallOptions {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: PayloadEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// `timeout`と`deadline`のうち早い方の期限
+    pub fn effective_deadline(&self, now: SystemTime) -> Option<SystemTime> {
+        let from_timeout = self.timeout.map(|timeout| now + timeout);
+        match (from_timeout, self.deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// 期限までの残り時間（期限を過ぎていれば`None`）
+pub fn remaining(deadline: SystemTime) -> Option<Duration> {
+    deadline
+        .duration_since(SystemTime::now())
+        .ok()
+        .filter(|remaining| !remaining.is_zero())
+}
+
+/// 期限をUNIXエポックからのミリ秒に変換
+pub fn deadline_millis(deadline: SystemTime) -> u64 {
+    deadline
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// ヘッダーの`DEADLINE`拡張から期限を取得
+pub fn header_deadline(header: &UnisonPacketHeader) -> Option<SystemTime> {
+    let bytes: [u8; 8] = header
+        .extension(extension_type::DEADLINE)?
+        .try_into()
+        .ok()?;
+    Some(UNIX_EPOCH + Duration::from_millis(u64::from_le_bytes(bytes)))
+}
+
+impl ProtocolMessage {
+    /// 期限を`DEADLINE`拡張に格納したフレームへ変換
+    ///
+    /// 拡張はv2ヘッダーでのみ送信できるため、期限がある場合はv2のフレームになります。
+    pub fn into_frame_with_deadline(
+        self,
+        deadline: Option<SystemTime>,
+    ) -> Result<ProtocolFrame, crate::packet::SerializationError> {
+        self.into_frame_with_compression(deadline, CompressionConfig::default())
+    }
+
+    /// 期限と圧縮設定を指定してフレームへ変換
+    pub(crate) fn into_frame_with_compression(
+        self,
+        deadline: Option<SystemTime>,
+        compression: CompressionConfig,
+    ) -> Result<ProtocolFrame, crate::packet::SerializationError> {
+        let builder = UnisonPacketBuilder::new().with_compression(compression);
+        let builder = match deadline {
+            Some(deadline) => builder.with_version(UnisonPacketHeader::V2).with_extension(
+                extension_type::DEADLINE,
+                deadline_millis(deadline).to_le_bytes(),
+            ),
+            None => builder,
+        };
+        builder.build(crate::packet::RkyvPayload::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+
+    #[test]
+    fn test_effective_deadline_takes_earliest() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let options = CallOptions::default();
+        assert_eq!(options.effective_deadline(now), None);
+
+        let options = options.with_timeout(Duration::from_secs(5));
+        assert_eq!(
+            options.effective_deadline(now),
+            Some(now + Duration::from_secs(5))
+        );
+
+        let options = options.with_deadline(now + Duration::from_secs(2));
+        assert_eq!(
+            options.effective_deadline(now),
+            Some(now + Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn test_deadline_round_trips_through_frame() {
+        let deadline = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let message =
+            ProtocolMessage::new_with_json(1, "echo".into(), MessageType::Request, "hi".into())
+                .unwrap();
+
+        let frame = message
+            .clone()
+            .into_frame_with_deadline(Some(deadline))
+            .unwrap();
+        let restored = ProtocolFrame::from_bytes(&frame.to_bytes()).unwrap();
+        assert_eq!(header_deadline(&restored.header().unwrap()), Some(deadline));
+        assert_eq!(
+            ProtocolMessage::from_frame(&restored).unwrap().method,
+            "echo"
+        );
+
+        let frame = message.into_frame_with_deadline(None).unwrap();
+        assert_eq!(header_deadline(&frame.header().unwrap()), None);
+    }
+
+    #[test]
+    fn test_remaining() {
+        assert_eq!(remaining(SystemTime::now() - Duration::from_secs(1)), None);
+        assert!(remaining(SystemTime::now() + Duration::from_secs(60)).is_some());
+    }
+}
@@ -0,0 +1,173 @@
+//! 大きなフレームの復元
+//!
+//! 数MBの圧縮されたフレームの展開とデシリアライズはCPUを長く占有するため、
+//! 非同期タスクの中で行うと同じワーカースレッドの他の接続の処理が止まります。
+//! 展開後の大きさ（ヘッダーの`payload_length`）が[`DecodeConfig::blocking_threshold`]以上の
+//! フレームは`spawn_blocking`でブロッキング用のスレッドに移して復元し、小さなフレームはその場で
+//! 復元します。圧縮率の高い小さなフレームも、展開後の大きさに応じてスレッドを移します。
+//! 展開後の大きさが[`DecodeConfig::max_decoded_size`]を超えるフレームは展開せずに拒否します。
+
+use bytes::Bytes;
+
+use super::{ProtocolFrame, ProtocolMessage};
+use crate::packet::{PacketConfig, PacketDeserializer, SerializationError};
+
+/// ブロッキング用のスレッドで復元するフレームの大きさの既定値
+pub const DEFAULT_BLOCKING_DECODE_THRESHOLD: usize = 256 * 1024;
+
+/// フレームの復元の設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeConfig {
+    /// 展開後のバイト数がこの値以上のフレームをブロッキング用のスレッドで復元する（`None`の場合は常にその場で復元）
+    pub blocking_threshold: Option<usize>,
+    /// 展開後のペイロードの最大バイト数
+    pub max_decoded_size: usize,
+}
+
+impl DecodeConfig {
+    /// 常にその場で復元する
+    pub fn inline() -> Self {
+        Self {
+            blocking_threshold: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_blocking_threshold(mut self, bytes: usize) -> Self {
+        self.blocking_threshold = Some(bytes);
+        self
+    }
+
+    pub fn with_max_decoded_size(mut self, bytes: usize) -> Self {
+        self.max_decoded_size = bytes;
+        self
+    }
+
+    /// `len`バイトのフレームをブロッキング用のスレッドで復元するか
+    pub fn offloads(&self, len: usize) -> bool {
+        self.blocking_threshold
+            .is_some_and(|threshold| len >= threshold)
+    }
+}
+
+impl Default for DecodeConfig {
+    fn default() -> Self {
+        Self {
+            blocking_threshold: Some(DEFAULT_BLOCKING_DECODE_THRESHOLD),
+            max_decoded_size: PacketConfig::default().max_payload_size,
+        }
+    }
+}
+
+/// フレームのバイト列からフレームとメッセージを復元
+pub async fn decode_frame(
+    bytes: Bytes,
+    config: DecodeConfig,
+) -> Result<(ProtocolFrame, ProtocolMessage), SerializationError> {
+    if !should_offload(&bytes, &config)? {
+        return decode(&bytes);
+    }
+    match tokio::task::spawn_blocking(move || decode(&bytes)).await {
+        Ok(result) => result,
+        // 復元中のパニックは呼び出し側へ伝える
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        // ランタイムの停止で取り消された
+        Err(e) => Err(SerializationError::DecompressionFailed(e.to_string())),
+    }
+}
+
+/// 展開後の大きさを検証し、ブロッキング用のスレッドで復元するかを返す
+fn should_offload(bytes: &[u8], config: &DecodeConfig) -> Result<bool, SerializationError> {
+    let (header, _) = PacketDeserializer::split_header(bytes)?;
+    let decoded_size = header.payload_length as usize;
+    if decoded_size > config.max_decoded_size {
+        return Err(SerializationError::PacketTooLarge {
+            size: decoded_size,
+            max_size: config.max_decoded_size,
+        });
+    }
+    Ok(config.offloads(decoded_size.max(bytes.len())))
+}
+
+fn decode(bytes: &Bytes) -> Result<(ProtocolFrame, ProtocolMessage), SerializationError> {
+    let frame = ProtocolFrame::from_bytes(bytes)?;
+    let message = ProtocolMessage::from_frame(&frame)?;
+    Ok((frame, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+
+    fn message(payload: &str) -> ProtocolMessage {
+        ProtocolMessage {
+            id: 7,
+            method: "upload".into(),
+            msg_type: MessageType::Request,
+            payload: payload.into(),
+            metadata: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_threshold_decides_offload() {
+        let config = DecodeConfig::default();
+        assert!(!config.offloads(DEFAULT_BLOCKING_DECODE_THRESHOLD - 1));
+        assert!(config.offloads(DEFAULT_BLOCKING_DECODE_THRESHOLD));
+        assert!(!DecodeConfig::inline().offloads(usize::MAX));
+        assert!(
+            DecodeConfig::inline()
+                .with_blocking_threshold(0)
+                .offloads(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_large_frames_decode_on_blocking_thread() {
+        // 圧縮される大きなペイロード
+        let payload = format!("\"{}\"", "a".repeat(4 * DEFAULT_BLOCKING_DECODE_THRESHOLD));
+        let bytes = message(&payload).into_frame().unwrap().to_bytes();
+
+        let config = DecodeConfig::inline().with_blocking_threshold(1);
+        let (frame, decoded) = decode_frame(bytes, config).await.unwrap();
+        assert_eq!(decoded.payload, payload);
+        assert_eq!(decoded.id, 7);
+        assert!(frame.header().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_offload_is_decided_by_decompressed_size() {
+        // 圧縮後はしきい値未満だが、展開後はしきい値を超えるフレーム
+        let payload = format!("\"{}\"", "a".repeat(4 * DEFAULT_BLOCKING_DECODE_THRESHOLD));
+        let bytes = message(&payload).into_frame().unwrap().to_bytes();
+        assert!(bytes.len() < DEFAULT_BLOCKING_DECODE_THRESHOLD);
+        assert!(should_offload(&bytes, &DecodeConfig::default()).unwrap());
+        assert!(!should_offload(&bytes, &DecodeConfig::inline()).unwrap());
+
+        let (_, decoded) = decode_frame(bytes.clone(), DecodeConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(decoded.payload, payload);
+
+        // 展開後の上限を超えるフレームは展開せずに拒否する
+        let config =
+            DecodeConfig::default().with_max_decoded_size(DEFAULT_BLOCKING_DECODE_THRESHOLD);
+        assert!(matches!(
+            decode_frame(bytes, config).await,
+            Err(SerializationError::PacketTooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_frames_fail_on_either_path() {
+        let bytes = Bytes::from_static(b"not a frame");
+        assert!(
+            decode_frame(bytes.clone(), DecodeConfig::inline())
+                .await
+                .is_err()
+        );
+        let config = DecodeConfig::inline().with_blocking_threshold(0);
+        assert!(decode_frame(bytes, config).await.is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! 接続先の検出（サービスディスカバリー）
+//!
+//! [`EndpointDiscovery`]はフェイルオーバーの接続先リストを動的に提供します。
+//! [`FailoverConfig::with_discovery`](super::FailoverConfig::with_discovery)で指定すると、
+//! 接続・切り替えのたびに（および指定した間隔で）リストを取得し直します。
+//!
+//! - 固定のリスト: [`StaticEndpoints`]
+//! - 任意の非同期関数（レジストリへの問い合わせなど）: [`FnDiscovery`]
+//! - DNSのSRVレコード: `SrvDiscovery`（`hickory-dns`フィーチャー）
+
+use std::future::Future;
+use std::pin::Pin;
+use thiserror::Error;
+
+/// 接続先の検出のエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryError {
+    #[error("Service discovery failed: {0}")]
+    Lookup(String),
+    #[error("No endpoints discovered for {0}")]
+    Empty(String),
+}
+
+pub type DiscoverFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<String>, DiscoveryError>> + Send + 'a>>;
+
+/// 接続先のリスト（優先順）を提供する
+pub trait EndpointDiscovery: Send + Sync {
+    fn discover(&self) -> DiscoverFuture<'_>;
+}
+
+/// 固定の接続先リスト
+#[derive(Debug, Clone, Default)]
+pub struct StaticEndpoints {
+    endpoints: Vec<String>,
+}
+
+impl StaticEndpoints {
+    pub fn new<I, S>(endpoints: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            endpoints: endpoints.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl EndpointDiscovery for StaticEndpoints {
+    fn discover(&self) -> DiscoverFuture<'_> {
+        Box::pin(async move { Ok(self.endpoints.clone()) })
+    }
+}
+
+/// 非同期関数で接続先を取得する
+pub struct FnDiscovery<F>(F);
+
+impl<F, Fut> FnDiscovery<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Vec<String>, DiscoveryError>> + Send + 'static,
+{
+    pub fn new(discover: F) -> Self {
+        Self(discover)
+    }
+}
+
+impl<F, Fut> EndpointDiscovery for FnDiscovery<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Vec<String>, DiscoveryError>> + Send + 'static,
+{
+    fn discover(&self) -> DiscoverFuture<'_> {
+        Box::pin((self.0)())
+    }
+}
+
+/// DNSのSRVレコード（`_unison._udp.example.com`など）から接続先を取得する
+///
+/// 優先度（priority）の小さい順、同じ優先度では重み（weight）の大きい順に並べます。
+#[cfg(feature = "hickory-dns")]
+pub struct SrvDiscovery {
+    resolver: hickory_resolver::TokioAsyncResolver,
+    name: String,
+}
+
+#[cfg(feature = "hickory-dns")]
+impl SrvDiscovery {
+    /// システムの設定（`/etc/resolv.conf`等）のリゾルバーで`name`を問い合わせる
+    pub fn from_system_conf(name: impl Into<String>) -> Result<Self, DiscoveryError> {
+        let resolver = hickory_resolver::TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(|e| DiscoveryError::Lookup(e.to_string()))?;
+        Ok(Self::new(resolver, name))
+    }
+
+    pub fn new(resolver: hickory_resolver::TokioAsyncResolver, name: impl Into<String>) -> Self {
+        Self {
+            resolver,
+            name: name.into(),
+        }
+    }
+}
+
+#[cfg(feature = "hickory-dns")]
+impl EndpointDiscovery for SrvDiscovery {
+    fn discover(&self) -> DiscoverFuture<'_> {
+        Box::pin(async move {
+            let lookup = self
+                .resolver
+                .srv_lookup(self.name.as_str())
+                .await
+                .map_err(|e| DiscoveryError::Lookup(format!("{}: {}", self.name, e)))?;
+            let mut records: Vec<_> = lookup.iter().collect();
+            records.sort_by_key(|srv| (srv.priority(), std::cmp::Reverse(srv.weight())));
+            let endpoints: Vec<String> = records
+                .into_iter()
+                .map(|srv| {
+                    let target = srv.target().to_utf8();
+                    format!("{}:{}", target.trim_end_matches('.'), srv.port())
+                })
+                .collect();
+            if endpoints.is_empty() {
+                return Err(DiscoveryError::Empty(self.name.clone()));
+            }
+            Ok(endpoints)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_static_and_fn_discovery() {
+        let discovery = StaticEndpoints::new(["[::1]:8080", "[::1]:8081"]);
+        assert_eq!(
+            discovery.discover().await.unwrap(),
+            vec!["[::1]:8080", "[::1]:8081"]
+        );
+
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&lookups);
+        let discovery = FnDiscovery::new(move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            async move { Ok(vec![format!("[::1]:{}", 9000 + n)]) }
+        });
+        assert_eq!(discovery.discover().await.unwrap(), vec!["[::1]:9000"]);
+        assert_eq!(discovery.discover().await.unwrap(), vec!["[::1]:9001"]);
+    }
+}
@@ -0,0 +1,229 @@
+//! ドレインの進捗と処理中のストリーム
+//!
+//! ノードを再起動してよいか判断できるよう、次の値を[`DrainStatus`]としてまとめます。
+//!
+//! - 処理中のリクエスト数と、停止要求からの進捗（[`DrainProgress`]）
+//! - 開いているストリーム（サーバー・クライアントストリーム）の数と経過時間の分布
+//! - 接続数
+//!
+//! [`ProtocolServer::drain_status`](super::ProtocolServer::drain_status)で取得するか、
+//! 組み込みメソッド[`DRAIN_STATUS_METHOD`]で監視用のクライアントから問い合わせます。
+//! このメソッドは停止中も応答します。
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::events::{ServerEvent, ServerEvents};
+use super::shutdown::DrainProgress;
+
+/// ドレインの状態を返す組み込みメソッド
+pub const DRAIN_STATUS_METHOD: &str = "unison.admin.drain";
+
+/// ストリームの経過時間の分布の区切り
+pub const STREAM_AGE_BUCKETS: [Duration; 5] = [
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(600),
+    Duration::from_secs(3600),
+];
+
+/// 経過時間の分布の1区間（累積）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StreamAgeBucket {
+    /// 区間の上限（ミリ秒、`None`は上限なし）
+    pub le_ms: Option<u64>,
+    /// 経過時間が上限以下のストリーム数
+    pub count: usize,
+}
+
+/// ドレインの状態
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DrainStatus {
+    /// 停止が要求されているか
+    pub stopping: bool,
+    /// 処理中のリクエスト数（ストリームを含む）
+    pub in_flight: usize,
+    pub active_connections: usize,
+    pub active_streams: usize,
+    /// メソッドごとの開いているストリーム数
+    pub streams_by_method: HashMap<String, usize>,
+    /// 最も古いストリームの経過時間（ミリ秒）
+    pub oldest_stream_ms: Option<u64>,
+    pub stream_ages: Vec<StreamAgeBucket>,
+    /// 停止要求からの進捗（停止を要求していない場合は`None`）
+    pub drain: Option<DrainProgress>,
+    /// 処理中のリクエストもストリームもなく、再起動しても切断される処理がない
+    pub safe_to_restart: bool,
+}
+
+impl DrainStatus {
+    pub(crate) fn new(
+        stopping: bool,
+        in_flight: usize,
+        active_connections: usize,
+        streams: &StreamTracker,
+        drain: Option<DrainProgress>,
+    ) -> Self {
+        let snapshot = streams.snapshot();
+        let mut streams_by_method = HashMap::new();
+        for (method, _) in &snapshot {
+            *streams_by_method.entry(method.clone()).or_insert(0) += 1;
+        }
+        let ages: Vec<Duration> = snapshot.iter().map(|(_, age)| *age).collect();
+        Self {
+            stopping,
+            in_flight,
+            active_connections,
+            active_streams: ages.len(),
+            streams_by_method,
+            oldest_stream_ms: ages.iter().max().map(|age| age.as_millis() as u64),
+            stream_ages: age_histogram(&ages),
+            drain,
+            safe_to_restart: in_flight == 0 && ages.is_empty(),
+        }
+    }
+}
+
+/// 経過時間の累積分布（最後の区間は上限なし）
+fn age_histogram(ages: &[Duration]) -> Vec<StreamAgeBucket> {
+    let bounded = STREAM_AGE_BUCKETS.iter().map(|bound| StreamAgeBucket {
+        le_ms: Some(bound.as_millis() as u64),
+        count: ages.iter().filter(|age| *age <= bound).count(),
+    });
+    bounded
+        .chain(std::iter::once(StreamAgeBucket {
+            le_ms: None,
+            count: ages.len(),
+        }))
+        .collect()
+}
+
+/// 開いているストリームの記録
+#[derive(Clone, Default)]
+pub struct StreamTracker {
+    inner: Arc<TrackerInner>,
+}
+
+#[derive(Default)]
+struct TrackerInner {
+    next_id: AtomicU64,
+    streams: Mutex<HashMap<u64, (String, Instant)>>,
+    /// ストリームの開始・終了を発行する先
+    events: Option<ServerEvents>,
+}
+
+impl StreamTracker {
+    /// ストリームの開始・終了をイベントとして発行する記録
+    pub(crate) fn with_events(events: ServerEvents) -> Self {
+        Self {
+            inner: Arc::new(TrackerInner {
+                events: Some(events),
+                ..TrackerInner::default()
+            }),
+        }
+    }
+
+    /// ストリームを開いたことを記録（ガードの破棄で閉じたとみなす）
+    pub fn open(&self, method: &str) -> StreamGuard {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .streams
+            .lock()
+            .unwrap()
+            .insert(id, (method.to_string(), Instant::now()));
+        if let Some(events) = &self.inner.events {
+            events.emit(|timestamp_ms| ServerEvent::StreamOpened {
+                stream_id: id,
+                method: method.to_string(),
+                timestamp_ms,
+            });
+        }
+        StreamGuard {
+            inner: Arc::clone(&self.inner),
+            id,
+        }
+    }
+
+    /// 開いているストリームの数
+    pub fn active(&self) -> usize {
+        self.inner.streams.lock().unwrap().len()
+    }
+
+    /// 開いているストリームのメソッドと経過時間
+    pub fn snapshot(&self) -> Vec<(String, Duration)> {
+        let now = Instant::now();
+        self.inner
+            .streams
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(method, opened)| (method.clone(), now.duration_since(*opened)))
+            .collect()
+    }
+}
+
+/// 開いているストリームを表すガード
+pub struct StreamGuard {
+    inner: Arc<TrackerInner>,
+    id: u64,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let closed = self.inner.streams.lock().unwrap().remove(&self.id);
+        if let (Some(events), Some((method, opened))) = (&self.inner.events, closed) {
+            events.emit(|timestamp_ms| ServerEvent::StreamClosed {
+                stream_id: self.id,
+                method,
+                duration_ms: opened.elapsed().as_millis() as u64,
+                timestamp_ms,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_are_counted_until_guard_drops() {
+        let tracker = StreamTracker::default();
+        let first = tracker.open("feed");
+        let _second = tracker.open("feed");
+        let _upload = tracker.open("upload");
+        assert_eq!(tracker.active(), 3);
+
+        drop(first);
+        let status = DrainStatus::new(false, 2, 1, &tracker, None);
+        assert_eq!(status.active_streams, 2);
+        assert_eq!(status.streams_by_method["feed"], 1);
+        assert!(!status.safe_to_restart);
+    }
+
+    #[test]
+    fn test_age_histogram_is_cumulative() {
+        let ages = [
+            Duration::from_millis(500),
+            Duration::from_secs(30),
+            Duration::from_secs(7200),
+        ];
+        let buckets = age_histogram(&ages);
+        let counts: Vec<usize> = buckets.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![1, 1, 2, 2, 2, 3]);
+        assert_eq!(buckets[0].le_ms, Some(1000));
+        assert_eq!(buckets.last().unwrap().le_ms, None);
+    }
+
+    #[test]
+    fn test_idle_node_is_safe_to_restart() {
+        let status = DrainStatus::new(true, 0, 0, &StreamTracker::default(), None);
+        assert!(status.safe_to_restart);
+        assert_eq!(status.oldest_stream_ms, None);
+        assert_eq!(status.stream_ages.len(), STREAM_AGE_BUCKETS.len() + 1);
+    }
+}
@@ -0,0 +1,359 @@
+//! 回線品質に応じたペイロード圧縮の切り替え
+//!
+//! [`AdaptiveEncodingConfig`]を指定すると、クライアントは接続ごとに`interval`ごとのping
+//! （ハートビートと同じ経路）で往復時間を測り、回線が遅い間はより強く圧縮する
+//! [`EncodingProfile`]へ切り替えます。切り替えは[`ENCODING_METHOD`]の制御パケットでサーバーに伝え、
+//! サーバーが受け入れたプロファイルをその接続のリクエストとレスポンスの両方で使います。
+//!
+//! 閾値付近で切り替えを繰り返さないよう、遅いと判定する往復時間（`degrade_rtt`）と
+//! 速いと判定する往復時間（`recover_rtt`）の間を空け、同じ判定が`switch_after`回続いた場合に
+//! 1段階ずつ切り替えます。
+//!
+//! ペイロードは常にJSONで、切り替わるのはフレームの圧縮設定（閾値とzstdのレベル）です。
+//! 圧縮の有無はフレームヘッダーに記録されるため、受信側は相手のプロファイルに関係なく復元できます。
+
+use anyhow::{Context, Result};
+use quinn::{Connection, SendStream};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use super::heartbeat::{self, control_frame};
+use super::quic::{FrameReader, write_stream_frame};
+use super::{MessageType, ProtocolMessage};
+use crate::packet::CompressionConfig;
+
+/// 圧縮プロファイルを切り替える制御パケットのメソッド名
+pub const ENCODING_METHOD: &str = "unison.encoding";
+
+/// 接続で使う圧縮の強さ
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingProfile {
+    /// 既定の圧縮（2KB以上をレベル1で圧縮）
+    #[default]
+    Standard,
+    /// 1KB以上をレベル9で圧縮
+    Compact,
+    /// 256バイト以上をレベル19で圧縮（CPUより帯域を優先）
+    Minimal,
+}
+
+impl EncodingProfile {
+    /// このプロファイルで送信するフレームの圧縮設定
+    pub fn compression(self) -> CompressionConfig {
+        match self {
+            Self::Standard => CompressionConfig::default(),
+            Self::Compact => CompressionConfig::high_compression(),
+            Self::Minimal => CompressionConfig::custom(256, 19),
+        }
+    }
+
+    /// 1段階強く圧縮するプロファイル
+    fn stronger(self) -> Option<Self> {
+        match self {
+            Self::Standard => Some(Self::Compact),
+            Self::Compact => Some(Self::Minimal),
+            Self::Minimal => None,
+        }
+    }
+
+    /// 1段階弱く圧縮するプロファイル
+    fn weaker(self) -> Option<Self> {
+        match self {
+            Self::Standard => None,
+            Self::Compact => Some(Self::Standard),
+            Self::Minimal => Some(Self::Compact),
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Compact,
+            2 => Self::Minimal,
+            _ => Self::Standard,
+        }
+    }
+}
+
+/// 圧縮プロファイルの自動切り替えの設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveEncodingConfig {
+    /// 往復時間を測る間隔
+    pub interval: Duration,
+    /// この往復時間以上を遅いと判定する
+    pub degrade_rtt: Duration,
+    /// この往復時間以下を速いと判定する（`degrade_rtt`より小さくする）
+    pub recover_rtt: Duration,
+    /// 切り替えるまでに同じ判定が続く回数
+    pub switch_after: u32,
+}
+
+impl Default for AdaptiveEncodingConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            degrade_rtt: Duration::from_millis(200),
+            recover_rtt: Duration::from_millis(50),
+            switch_after: 3,
+        }
+    }
+}
+
+/// 往復時間の計測値からプロファイルを選ぶ（ヒステリシス付き）
+#[derive(Debug, Clone)]
+pub struct EncodingSelector {
+    config: AdaptiveEncodingConfig,
+    current: EncodingProfile,
+    slow_streak: u32,
+    fast_streak: u32,
+}
+
+impl EncodingSelector {
+    pub fn new(config: AdaptiveEncodingConfig) -> Self {
+        Self {
+            config,
+            current: EncodingProfile::default(),
+            slow_streak: 0,
+            fast_streak: 0,
+        }
+    }
+
+    /// 現在のプロファイル
+    pub fn current(&self) -> EncodingProfile {
+        self.current
+    }
+
+    /// 計測値を記録し、切り替える場合は新しいプロファイルを返す
+    pub fn observe(&mut self, rtt: Duration) -> Option<EncodingProfile> {
+        if rtt >= self.config.degrade_rtt {
+            self.slow_streak += 1;
+            self.fast_streak = 0;
+        } else if rtt <= self.config.recover_rtt {
+            self.fast_streak += 1;
+            self.slow_streak = 0;
+        } else {
+            self.slow_streak = 0;
+            self.fast_streak = 0;
+        }
+
+        let required = self.config.switch_after.max(1);
+        let next = if self.slow_streak >= required {
+            self.current.stronger()
+        } else if self.fast_streak >= required {
+            self.current.weaker()
+        } else {
+            None
+        }?;
+        self.reset(next);
+        Some(next)
+    }
+
+    /// プロファイルを設定し、判定の連続回数を数え直す
+    fn reset(&mut self, profile: EncodingProfile) {
+        self.current = profile;
+        self.slow_streak = 0;
+        self.fast_streak = 0;
+    }
+}
+
+/// 接続ごとに共有する現在のプロファイル
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionEncoding(Arc<AtomicU8>);
+
+impl ConnectionEncoding {
+    /// 現在のプロファイル
+    pub fn profile(&self) -> EncodingProfile {
+        EncodingProfile::from_u8(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set(&self, profile: EncodingProfile) {
+        self.0.store(profile as u8, Ordering::Relaxed);
+    }
+
+    /// 現在のプロファイルの圧縮設定
+    pub(crate) fn compression(&self) -> CompressionConfig {
+        self.profile().compression()
+    }
+}
+
+/// 切り替えの要求と応答のペイロード
+#[derive(Debug, Serialize, Deserialize)]
+struct EncodingChange {
+    profile: EncodingProfile,
+}
+
+/// 受信したメッセージがプロファイルの切り替えか
+pub(crate) fn is_encoding(message: &ProtocolMessage) -> bool {
+    message.msg_type == MessageType::Event && message.method == ENCODING_METHOD
+}
+
+fn encoding_message(id: u64, profile: EncodingProfile) -> Result<ProtocolMessage> {
+    let payload = serde_json::to_value(EncodingChange { profile })?;
+    Ok(ProtocolMessage::new_with_json(
+        id,
+        ENCODING_METHOD.to_string(),
+        MessageType::Event,
+        payload,
+    )?)
+}
+
+/// 切り替えの要求を受け入れ、同じストリームで受け入れたプロファイルを返す
+pub(crate) async fn answer(
+    mut send_stream: SendStream,
+    request: ProtocolMessage,
+    encoding: &ConnectionEncoding,
+) {
+    match serde_json::from_str::<EncodingChange>(&request.payload) {
+        Ok(change) => encoding.set(change.profile),
+        Err(e) => debug!("Ignoring invalid encoding change: {}", e),
+    }
+    let sent = match encoding_message(request.id, encoding.profile()).and_then(control_frame) {
+        Ok(frame) => write_stream_frame(&mut send_stream, &frame, true)
+            .await
+            .map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        debug!("Failed to answer encoding change: {:#}", e);
+    }
+    let _ = send_stream.finish();
+}
+
+/// プロファイルの切り替えを要求し、相手が受け入れたプロファイルを返す
+pub(crate) async fn negotiate(
+    connection: &Connection,
+    seq: u64,
+    profile: EncodingProfile,
+) -> Result<EncodingProfile> {
+    let (mut send_stream, recv_stream) = connection.open_bi().await?;
+    let frame = control_frame(encoding_message(seq, profile)?)?;
+    write_stream_frame(&mut send_stream, &frame, true).await?;
+    send_stream.finish()?;
+
+    let reply = FrameReader::new(recv_stream)
+        .next_message()
+        .await?
+        .context("Stream closed without an encoding reply")?;
+    if !is_encoding(&reply) || reply.id != seq {
+        anyhow::bail!("Unexpected encoding reply: {} {}", reply.method, reply.id);
+    }
+    Ok(serde_json::from_str::<EncodingChange>(&reply.payload)?.profile)
+}
+
+/// 接続が閉じるまで往復時間を測り、回線品質に合わせてプロファイルを切り替える
+pub(crate) async fn adapt(
+    connection: Connection,
+    config: AdaptiveEncodingConfig,
+    encoding: ConnectionEncoding,
+) {
+    let mut ticker = tokio::time::interval(config.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticker.tick().await;
+
+    let mut selector = EncodingSelector::new(config);
+    selector.reset(encoding.profile());
+    for seq in 1.. {
+        tokio::select! {
+            _ = connection.closed() => return,
+            _ = ticker.tick() => {}
+        }
+        // 応答がない場合の扱いはハートビートに任せ、計測できた値だけを使う
+        let rtt =
+            match tokio::time::timeout(config.interval, heartbeat::ping(&connection, seq)).await {
+                Ok(Ok(rtt)) => rtt,
+                Ok(Err(e)) => {
+                    debug!("Latency probe failed: {:#}", e);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+        let Some(profile) = selector.observe(rtt) else {
+            continue;
+        };
+        match negotiate(&connection, seq, profile).await {
+            Ok(accepted) => {
+                info!(
+                    "Switched encoding for {} to {:?} (rtt {:?})",
+                    connection.remote_address(),
+                    accepted,
+                    rtt
+                );
+                encoding.set(accepted);
+                selector.reset(accepted);
+            }
+            Err(e) => {
+                debug!("Failed to negotiate encoding: {:#}", e);
+                selector.reset(encoding.profile());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector() -> EncodingSelector {
+        EncodingSelector::new(AdaptiveEncodingConfig {
+            interval: Duration::from_millis(10),
+            degrade_rtt: Duration::from_millis(100),
+            recover_rtt: Duration::from_millis(20),
+            switch_after: 2,
+        })
+    }
+
+    const SLOW: Duration = Duration::from_millis(150);
+    const MIDDLE: Duration = Duration::from_millis(60);
+    const FAST: Duration = Duration::from_millis(5);
+
+    #[test]
+    fn test_switches_one_step_after_consecutive_samples() {
+        let mut selector = selector();
+        assert_eq!(selector.observe(SLOW), None);
+        assert_eq!(selector.observe(SLOW), Some(EncodingProfile::Compact));
+        assert_eq!(selector.observe(SLOW), None);
+        assert_eq!(selector.observe(SLOW), Some(EncodingProfile::Minimal));
+        // 最も強い圧縮より先はない
+        assert_eq!(selector.observe(SLOW), None);
+        assert_eq!(selector.observe(SLOW), None);
+
+        assert_eq!(selector.observe(FAST), None);
+        assert_eq!(selector.observe(FAST), Some(EncodingProfile::Compact));
+        assert_eq!(selector.current(), EncodingProfile::Compact);
+    }
+
+    #[test]
+    fn test_middle_band_does_not_flap() {
+        let mut selector = selector();
+        // 閾値の間の値や交互の値では切り替えない
+        for rtt in [SLOW, MIDDLE, SLOW, FAST, SLOW, MIDDLE, FAST, MIDDLE] {
+            assert_eq!(selector.observe(rtt), None);
+        }
+        assert_eq!(selector.current(), EncodingProfile::Standard);
+    }
+
+    #[test]
+    fn test_profiles_round_trip_through_shared_state() {
+        let encoding = ConnectionEncoding::default();
+        assert_eq!(encoding.profile(), EncodingProfile::Standard);
+        for profile in [EncodingProfile::Minimal, EncodingProfile::Compact] {
+            encoding.set(profile);
+            assert_eq!(encoding.clone().profile(), profile);
+        }
+        assert!(
+            EncodingProfile::Minimal.compression().threshold < encoding.compression().threshold
+        );
+        assert_eq!(
+            serde_json::to_value(EncodingChange {
+                profile: EncodingProfile::Compact
+            })
+            .unwrap(),
+            serde_json::json!({ "profile": "compact" })
+        );
+    }
+}
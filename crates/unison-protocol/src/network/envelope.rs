@@ -0,0 +1,353 @@
+//! 型付きのリクエスト・レスポンスの封筒
+//!
+//! [`Request<T>`]と[`Response<T>`]は、本体（`body`）とメッセージID・メタデータ・期限を
+//! まとめて扱う型です。ワイヤー上の[`ProtocolMessage`]や[`UnisonMessage`]との変換を持ち、
+//! 型付きの呼び出し（[`ProtocolClient::request`](super::ProtocolClient::request)）と
+//! ハンドラー（[`ProtocolServer::register_request_handler`](super::ProtocolServer::register_request_handler)）
+//! で同じ型を使います。
+//!
+//! サーバーは処理中のリクエストのID・メタデータ・期限をタスクローカルに保持し、
+//! 型付きハンドラーへ渡す[`Request<T>`]を組み立てます。`Value`を受け取るハンドラーは
+//! [`request_metadata`]でメタデータを参照できます。
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use super::broadcast::ConnectionId;
+use super::handler::HandlerResponse;
+use super::{MessageType, NetworkError, ProtocolError, ProtocolMessage};
+use crate::core::UnisonMessage;
+
+/// [`UnisonMessage`]から変換したリクエストで、元のメッセージIDを保持するメタデータのキー
+pub const MESSAGE_ID_METADATA_KEY: &str = "x-message-id";
+
+/// 型付きのリクエスト
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request<T> {
+    /// メッセージID（クライアントでは送信時に割り当てる）
+    pub id: u64,
+    pub method: String,
+    pub metadata: HashMap<String, String>,
+    /// 呼び出し元の期限
+    pub deadline: Option<SystemTime>,
+    pub body: T,
+}
+
+impl<T> Request<T> {
+    pub fn new(method: impl Into<String>, body: T) -> Self {
+        Self {
+            id: 0,
+            method: method.into(),
+            metadata: HashMap::new(),
+            deadline: None,
+            body,
+        }
+    }
+
+    /// メタデータを追加
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// 絶対時刻での期限を指定
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// 現在からの待機時間で期限を指定
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(SystemTime::now() + timeout)
+    }
+
+    /// メタデータの値
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// 本体を変換
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Request<U> {
+        Request {
+            id: self.id,
+            method: self.method,
+            metadata: self.metadata,
+            deadline: self.deadline,
+            body: f(self.body),
+        }
+    }
+
+    /// 本体を取り出す
+    pub fn into_body(self) -> T {
+        self.body
+    }
+}
+
+impl<T: Serialize> Request<T> {
+    /// ワイヤー形式のメッセージへ変換（期限はパケットヘッダーで送る）
+    pub fn to_message(&self, id: u64) -> Result<ProtocolMessage, NetworkError> {
+        ProtocolMessage::new_with_json(
+            id,
+            self.method.clone(),
+            MessageType::Request,
+            serde_json::to_value(&self.body)?,
+        )?
+        .with_metadata(&self.metadata)
+    }
+}
+
+impl<T: DeserializeOwned> Request<T> {
+    /// 受信したメッセージから復元
+    pub fn from_message(
+        message: &ProtocolMessage,
+        deadline: Option<SystemTime>,
+    ) -> Result<Self, NetworkError> {
+        Ok(Self {
+            id: message.id,
+            method: message.method.clone(),
+            metadata: message.metadata()?,
+            deadline,
+            body: serde_json::from_str(&message.payload)?,
+        })
+    }
+}
+
+impl From<UnisonMessage> for Request<Value> {
+    fn from(message: UnisonMessage) -> Self {
+        Request::new(message.method, message.payload)
+            .with_metadata(MESSAGE_ID_METADATA_KEY, message.id)
+    }
+}
+
+/// 型付きのレスポンス
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response<T> {
+    /// 応答したリクエストのメッセージID
+    pub id: u64,
+    pub metadata: HashMap<String, String>,
+    pub body: T,
+}
+
+impl<T> Response<T> {
+    pub fn new(body: T) -> Self {
+        Self {
+            id: 0,
+            metadata: HashMap::new(),
+            body,
+        }
+    }
+
+    /// メタデータを追加
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// メタデータの値
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// 本体を変換
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Response<U> {
+        Response {
+            id: self.id,
+            metadata: self.metadata,
+            body: f(self.body),
+        }
+    }
+
+    /// 本体を取り出す
+    pub fn into_body(self) -> T {
+        self.body
+    }
+}
+
+impl<T: Serialize> Response<T> {
+    /// ハンドラーのレスポンスへ変換（メタデータを引き継ぐ）
+    pub fn into_handler_response(self) -> HandlerResponse {
+        let mut response = match serde_json::to_value(self.body) {
+            Ok(body) => HandlerResponse::ok(body),
+            Err(e) => {
+                return HandlerResponse::error(ProtocolError::internal(format!(
+                    "Failed to serialize response: {}",
+                    e
+                )));
+            }
+        };
+        response.metadata.extend(self.metadata);
+        response
+    }
+}
+
+impl<T: DeserializeOwned> Response<T> {
+    /// 成功のレスポンスメッセージから復元（エラーのメッセージは呼び出し側で扱う）
+    pub fn from_message(message: &ProtocolMessage) -> Result<Self, NetworkError> {
+        Ok(Self {
+            id: message.id,
+            metadata: message.metadata()?,
+            body: serde_json::from_str(&message.payload)?,
+        })
+    }
+}
+
+/// 処理中のリクエストの本体以外の情報
+#[derive(Debug, Clone)]
+pub(crate) struct RequestHead {
+    pub(crate) id: u64,
+    pub(crate) metadata: HashMap<String, String>,
+    pub(crate) deadline: Option<SystemTime>,
+    pub(crate) connection_id: Option<ConnectionId>,
+    pub(crate) remote_address: Option<SocketAddr>,
+    pub(crate) stream_id: Option<u64>,
+    pub(crate) features: Vec<String>,
+}
+
+impl RequestHead {
+    pub(crate) fn from_message(
+        message: &ProtocolMessage,
+        deadline: Option<SystemTime>,
+    ) -> Result<Self, NetworkError> {
+        Ok(Self {
+            id: message.id,
+            metadata: message.metadata()?,
+            deadline,
+            connection_id: None,
+            remote_address: None,
+            stream_id: None,
+            features: Vec::new(),
+        })
+    }
+
+    /// リクエストを受信した接続の情報を設定
+    pub(crate) fn with_connection(
+        mut self,
+        connection_id: ConnectionId,
+        remote_address: Option<SocketAddr>,
+        features: Vec<String>,
+    ) -> Self {
+        self.connection_id = Some(connection_id);
+        self.remote_address = remote_address;
+        self.features = features;
+        self
+    }
+
+    pub(crate) fn with_stream_id(mut self, stream_id: Option<u64>) -> Self {
+        self.stream_id = stream_id;
+        self
+    }
+
+    pub(crate) fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_REQUEST: RequestHead;
+}
+
+/// 処理中のリクエストの情報を設定してFutureを実行
+pub(crate) async fn with_request_head<F: Future>(head: RequestHead, future: F) -> F::Output {
+    CURRENT_REQUEST.scope(head, future).await
+}
+
+/// 処理中のリクエストの情報
+pub(crate) fn current_head() -> Option<RequestHead> {
+    CURRENT_REQUEST.try_with(Clone::clone).ok()
+}
+
+/// 処理中のリクエストのメタデータ
+///
+/// ハンドラーの中で呼び出します。リクエストの処理中でなければ空になります。
+pub fn request_metadata() -> HashMap<String, String> {
+    CURRENT_REQUEST
+        .try_with(|head| head.metadata.clone())
+        .unwrap_or_default()
+}
+
+/// 本体と処理中のリクエストの情報から型付きのリクエストを組み立てる
+///
+/// サーバー内部から直接呼び出された場合など、情報がなければIDとメタデータは空になります。
+pub(crate) fn current_request<T>(method: &str, body: T) -> Request<T> {
+    let head = current_head();
+    let mut request = Request::new(method, body);
+    if let Some(head) = head {
+        request.id = head.id;
+        request.metadata = head.metadata;
+        request.deadline = head.deadline;
+    }
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Greet {
+        name: String,
+    }
+
+    #[test]
+    fn test_request_round_trips_through_message() {
+        let request = Request::new(
+            "greet",
+            Greet {
+                name: "unison".into(),
+            },
+        )
+        .with_metadata("tenant", "acme");
+        let message = request.to_message(42).unwrap();
+        assert_eq!(message.msg_type, MessageType::Request);
+
+        let deadline = SystemTime::now();
+        let restored = Request::<Greet>::from_message(&message, Some(deadline)).unwrap();
+        assert_eq!(restored.id, 42);
+        assert_eq!(restored.metadata("tenant"), Some("acme"));
+        assert_eq!(restored.deadline, Some(deadline));
+        assert_eq!(restored.body, request.body);
+    }
+
+    #[test]
+    fn test_response_keeps_metadata() {
+        let response = Response::new(Greet { name: "hi".into() }).with_metadata("x-cost", "3");
+        let message = response
+            .into_handler_response()
+            .into_message(7, "greet".into())
+            .unwrap();
+        let restored = Response::<Greet>::from_message(&message).unwrap();
+        assert_eq!(restored.id, 7);
+        assert_eq!(restored.metadata("x-cost"), Some("3"));
+        assert_eq!(restored.body.name, "hi");
+    }
+
+    #[tokio::test]
+    async fn test_current_request_uses_task_local_head() {
+        let message = Request::new("greet", Value::Null)
+            .with_metadata("tenant", "acme")
+            .to_message(9)
+            .unwrap();
+        let head = RequestHead::from_message(&message, None).unwrap();
+        let (request, metadata) = with_request_head(head, async {
+            (current_request("greet", 1), request_metadata())
+        })
+        .await;
+        assert_eq!((request.id, request.body), (9, 1));
+        assert_eq!(request.metadata("tenant"), Some("acme"));
+        assert_eq!(metadata, request.metadata);
+
+        // 情報がなければ空のまま
+        let request = current_request("greet", 2);
+        assert_eq!((request.id, request.metadata.len()), (0, 0));
+        assert!(request_metadata().is_empty());
+
+        let converted = Request::from(UnisonMessage::with_id("abc", "greet", Value::Null));
+        assert_eq!(converted.metadata(MESSAGE_ID_METADATA_KEY), Some("abc"));
+    }
+}
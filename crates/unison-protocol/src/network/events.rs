@@ -0,0 +1,181 @@
+//! サーバーの接続・ストリームのライフサイクルイベント
+//!
+//! [`ProtocolServer::events`](super::ProtocolServer::events)で、接続の開始・終了、
+//! ハンドシェイクの完了、ストリームの開始・終了、ハンドラーのパニックを[`ServerEvent`]として
+//! 購読できます。ログを解析せずにダッシュボードや監査ログを作るために使います。
+//!
+//! イベントは購読を始めた後に発生したものだけを受け取ります。処理が遅れて
+//! バッファ（[`SERVER_EVENT_CAPACITY`]件）を超えた古いイベントはスキップされます。
+
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+use super::broadcast::ConnectionId;
+
+/// イベントのバッファ数
+pub const SERVER_EVENT_CAPACITY: usize = 1024;
+
+/// 接続が終了した理由
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum CloseReason {
+    /// クライアントが切断した
+    ClientClosed,
+    /// サーバーが切断した（停止を含む）
+    ServerClosed,
+    /// 通信のエラー
+    Error(String),
+}
+
+/// サーバーのライフサイクルイベント
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerEvent {
+    ConnectionOpened {
+        connection_id: ConnectionId,
+        /// 相手のアドレス（QUIC・WebSocket以外の接続では`None`）
+        remote: Option<SocketAddr>,
+        timestamp_ms: u64,
+    },
+    ConnectionClosed {
+        connection_id: ConnectionId,
+        remote: Option<SocketAddr>,
+        reason: CloseReason,
+        timestamp_ms: u64,
+    },
+    HandshakeCompleted {
+        connection_id: ConnectionId,
+        session_id: String,
+        /// 相手のアプリケーション名
+        peer_name: String,
+        protocol_version: String,
+        timestamp_ms: u64,
+    },
+    StreamOpened {
+        /// サーバー内でストリームを識別する番号（`StreamClosed`と対応づける）
+        stream_id: u64,
+        method: String,
+        timestamp_ms: u64,
+    },
+    StreamClosed {
+        stream_id: u64,
+        method: String,
+        /// 開いていた時間（ミリ秒）
+        duration_ms: u64,
+        timestamp_ms: u64,
+    },
+    HandlerPanicked {
+        method: String,
+        /// サーバーのログとクライアントへのエラーに含まれる相関ID
+        correlation_id: String,
+        timestamp_ms: u64,
+    },
+    /// 稼働中にスキーマを読み込み直した（指紋が変わった場合のみ）
+    SchemaReloaded {
+        previous: Option<String>,
+        fingerprint: String,
+        /// 新しいスキーマから削除されたメソッド・ストリーム
+        removed_methods: Vec<String>,
+        timestamp_ms: u64,
+    },
+}
+
+impl ServerEvent {
+    /// イベントが発生した時刻（Unix時刻、ミリ秒）
+    pub fn timestamp_ms(&self) -> u64 {
+        match self {
+            ServerEvent::ConnectionOpened { timestamp_ms, .. }
+            | ServerEvent::ConnectionClosed { timestamp_ms, .. }
+            | ServerEvent::HandshakeCompleted { timestamp_ms, .. }
+            | ServerEvent::StreamOpened { timestamp_ms, .. }
+            | ServerEvent::StreamClosed { timestamp_ms, .. }
+            | ServerEvent::HandlerPanicked { timestamp_ms, .. }
+            | ServerEvent::SchemaReloaded { timestamp_ms, .. } => *timestamp_ms,
+        }
+    }
+}
+
+/// イベントの発行と購読
+#[derive(Clone)]
+pub(crate) struct ServerEvents {
+    sender: broadcast::Sender<ServerEvent>,
+}
+
+impl ServerEvents {
+    /// イベントを発行（購読者がいなければ何もしない）
+    pub(crate) fn emit(&self, event: impl FnOnce(u64) -> ServerEvent) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.sender.send(event(now_ms()));
+    }
+
+    /// 購読開始以降のイベントのストリーム
+    pub(crate) fn subscribe(&self) -> Pin<Box<dyn Stream<Item = ServerEvent> + Send>> {
+        let mut rx = self.sender.subscribe();
+        Box::pin(async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => yield event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+impl Default for ServerEvents {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(SERVER_EVENT_CAPACITY);
+        Self { sender }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_subscriber_receives_events_after_subscribing() {
+        let events = ServerEvents::default();
+        // 購読者がいない間のイベントは捨てられる
+        events.emit(|timestamp_ms| ServerEvent::HandlerPanicked {
+            method: "lost".into(),
+            correlation_id: "0".into(),
+            timestamp_ms,
+        });
+
+        let mut stream = events.subscribe();
+        events.emit(|timestamp_ms| ServerEvent::ConnectionClosed {
+            connection_id: 1,
+            remote: None,
+            reason: CloseReason::Error("reset".into()),
+            timestamp_ms,
+        });
+        let event = stream.next().await.unwrap();
+        assert!(event.timestamp_ms() > 0);
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "event": "connection_closed",
+                "connection_id": 1,
+                "remote": null,
+                "reason": { "kind": "error", "message": "reset" },
+                "timestamp_ms": event.timestamp_ms(),
+            })
+        );
+    }
+}
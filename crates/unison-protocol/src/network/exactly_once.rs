@@ -0,0 +1,538 @@
+//! 重要な制御メッセージの厳密に1回の処理（exactly-once）
+//!
+//! [`ExactlyOnceConfig`]で指定したメソッドは、再送・再接続・重複配信があっても
+//! ハンドラーを1回だけ実行し、すべての送信に同じ結果を返します。
+//!
+//! - クライアント（[`ProtocolClient::with_exactly_once`](super::ProtocolClient::with_exactly_once)）は
+//!   呼び出しごとに冪等キーを割り当てて[`IDEMPOTENCY_KEY_METADATA_KEY`]で送り、
+//!   レスポンス（ACK）を受け取るまで同じキーで再送します。呼び出しは冪等な呼び出しとして
+//!   [`RetryPolicy`](super::RetryPolicy)に従い、接続断・タイムアウト後も再送されます
+//! - サーバー（[`ProtocolServer::with_exactly_once`](super::ProtocolServer::with_exactly_once)）は
+//!   重複排除ウィンドウの間、キーごとに最初の実行結果を保持し、同じキーのリクエストには
+//!   ハンドラーを実行せずに保持した結果を返します（[`REPLAYED_METADATA_KEY`]を付ける）。
+//!   最初のリクエストの処理中に届いた重複は、その完了を待って同じ結果を受け取ります
+//!
+//! 冪等キーはテナントと認証された相手ごとに区別するため、他のクライアントのキーで結果を
+//! 受け取ることはできません。相手はmTLSのクライアント証明書、なければハンドシェイクで送った
+//! [`IDEMPOTENCY_CREDENTIAL_METADATA_KEY`]の値で区別し、どちらもなければ接続ごとに区別します。
+//! 再接続の前後で結果を共有するには、クライアント証明書か資格情報が必要です。
+//! 同じキーで異なるペイロードを送った場合は`INVALID_REQUEST`で拒否します。
+//! 処理中のキーでウィンドウが埋まっている場合、新しいキーは`UNAVAILABLE`で拒否します。
+//!
+//! 保証はウィンドウ（既定は[`DEFAULT_DEDUP_WINDOW`]）の間に限られるため、クライアントの再試行は
+//! ウィンドウより短い期間で終わるように設定します。サーバーがハンドラーを実行する前に拒否した
+//! レスポンス（停止中・レート制限・利用量の上限・アドミッション制御）は保持せず、
+//! 同じキーで再送すると改めて処理されます。ハンドラーが返したエラーはコードによらず保持します。
+//! 処理中に接続が切れてハンドラーが中断された場合も、次の送信で改めて実行されます。
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use super::ProtocolError;
+use super::handler::HandlerResponse;
+
+/// 冪等キーを送るメタデータのキー
+pub const IDEMPOTENCY_KEY_METADATA_KEY: &str = "idempotency-key";
+
+/// 再接続の前後で冪等キーを共有するための資格情報を送るハンドシェイクのメタデータのキー
+///
+/// 値はクライアントごとの秘密の文字列で、同じ値を送った接続どうしで実行結果を共有します。
+pub const IDEMPOTENCY_CREDENTIAL_METADATA_KEY: &str = "idempotency-credential";
+
+/// 保持した結果を返したレスポンスに付けるメタデータのキー（値は`true`）
+pub const REPLAYED_METADATA_KEY: &str = "idempotent-replayed";
+
+/// 重複排除ウィンドウの既定値
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(300);
+
+/// 保持する結果の数の上限の既定値
+pub const DEFAULT_DEDUP_CAPACITY: usize = 10_000;
+
+/// 厳密に1回処理するメソッドの設定
+#[derive(Debug, Clone)]
+pub struct ExactlyOnceConfig {
+    /// 厳密に1回処理するメソッド
+    pub methods: HashSet<String>,
+    /// 実行結果を保持する期間
+    pub window: Duration,
+    /// 保持する結果の数の上限（超えた場合は期限の近いものから破棄し、処理中のものだけで
+    /// 埋まっている場合は新しいキーを拒否する）
+    pub capacity: usize,
+}
+
+impl ExactlyOnceConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_method(mut self, method: impl Into<String>) -> Self {
+        self.methods.insert(method.into());
+        self
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// 厳密に1回処理するメソッドか
+    pub fn applies_to(&self, method: &str) -> bool {
+        self.methods.contains(method)
+    }
+}
+
+impl Default for ExactlyOnceConfig {
+    fn default() -> Self {
+        Self {
+            methods: HashSet::new(),
+            window: DEFAULT_DEDUP_WINDOW,
+            capacity: DEFAULT_DEDUP_CAPACITY,
+        }
+    }
+}
+
+/// 冪等キーを付けたリクエスト
+pub(crate) struct IdempotentRequest<'a> {
+    /// キーを共有する範囲（テナントと相手）
+    pub(crate) scope: String,
+    pub(crate) key: &'a str,
+    /// ペイロードのハッシュ（[`payload_hash`]）
+    pub(crate) payload_hash: u64,
+}
+
+/// 資格情報で区別する範囲（資格情報そのものは保持しない）
+pub(crate) fn credential_scope(credential: &str) -> String {
+    let hash = ring::digest::digest(&ring::digest::SHA256, credential.as_bytes());
+    let hex: String = hash
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("credential:{}", hex)
+}
+
+/// 同じキーのリクエストのペイロードが同じか確かめるためのハッシュ
+pub(crate) fn payload_hash(payload: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// メソッド・範囲・冪等キー
+type SlotKey = (String, String, String);
+
+struct Slot {
+    payload_hash: u64,
+    state: SlotState,
+}
+
+enum SlotState {
+    /// 最初のリクエストを処理中（完了すると結果が送られる）
+    Pending(watch::Receiver<Option<HandlerResponse>>),
+    Done {
+        response: HandlerResponse,
+        expires: Instant,
+    },
+}
+
+#[derive(Default)]
+struct Inner {
+    config: ExactlyOnceConfig,
+    /// メソッド・範囲・冪等キーごとの実行結果
+    slots: Mutex<HashMap<SlotKey, Slot>>,
+    replays: AtomicU64,
+}
+
+/// サーバーの重複排除ウィンドウ
+#[derive(Clone, Default)]
+pub struct DedupWindow {
+    inner: Arc<Inner>,
+}
+
+impl DedupWindow {
+    pub fn new(config: ExactlyOnceConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                ..Inner::default()
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &ExactlyOnceConfig {
+        &self.inner.config
+    }
+
+    /// 保持している結果（処理中を含む）の数
+    pub fn len(&self) -> usize {
+        self.inner.slots.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 保持した結果を返した回数
+    pub fn replays(&self) -> u64 {
+        self.inner.replays.load(Ordering::Relaxed)
+    }
+
+    /// 冪等キーごとに1回だけ`handler`を実行し、重複には同じ結果を返す
+    ///
+    /// 対象外のメソッドや冪等キーのないリクエストはそのまま実行します。
+    pub(crate) async fn run<F>(
+        &self,
+        method: &str,
+        request: Option<IdempotentRequest<'_>>,
+        handler: F,
+    ) -> HandlerResponse
+    where
+        F: Future<Output = HandlerResponse>,
+    {
+        let Some(request) = request.filter(|_| self.inner.config.applies_to(method)) else {
+            return handler.await;
+        };
+        let key = (method.to_string(), request.scope, request.key.to_string());
+        let execution = loop {
+            let mut pending = match self.claim(&key, request.payload_hash) {
+                Claim::Execute(execution) => break execution,
+                Claim::Recorded(response) => return self.replayed(response),
+                Claim::Pending(pending) => pending,
+                Claim::Rejected(error) => return HandlerResponse::error(error),
+            };
+            // 最初のリクエストが中断された場合は、改めて実行する
+            if let Ok(response) = pending.wait_for(Option::is_some).await {
+                let response = response.clone().expect("waited for a response");
+                return self.replayed(response);
+            }
+        };
+        let response = handler.await;
+        execution.complete(response.clone());
+        response
+    }
+
+    fn claim(&self, key: &SlotKey, payload_hash: u64) -> Claim<'_> {
+        let mut slots = self.inner.slots.lock().unwrap();
+        self.evict(&mut slots);
+        match slots.get(key) {
+            Some(slot) if slot.payload_hash != payload_hash => Claim::Rejected(
+                ProtocolError::new(
+                    ProtocolError::INVALID_REQUEST,
+                    "Idempotency key was reused with a different payload",
+                )
+                .with_details(serde_json::json!({ "reason": "payload_mismatch" })),
+            ),
+            Some(Slot {
+                state: SlotState::Done { response, .. },
+                ..
+            }) => Claim::Recorded(response.clone()),
+            Some(Slot {
+                state: SlotState::Pending(pending),
+                ..
+            }) => Claim::Pending(pending.clone()),
+            // 処理中のキーだけでウィンドウが埋まっている
+            None if slots.len() >= self.inner.config.capacity.max(1) => Claim::Rejected(
+                ProtocolError::new(
+                    ProtocolError::UNAVAILABLE,
+                    "Too many exactly-once requests are in progress",
+                )
+                .with_details(serde_json::json!({ "reason": "dedup_window_full" })),
+            ),
+            None => {
+                let (sender, receiver) = watch::channel(None);
+                slots.insert(
+                    key.clone(),
+                    Slot {
+                        payload_hash,
+                        state: SlotState::Pending(receiver),
+                    },
+                );
+                Claim::Execute(Execution {
+                    window: self,
+                    key: key.clone(),
+                    payload_hash,
+                    sender,
+                    completed: false,
+                })
+            }
+        }
+    }
+
+    /// 期限切れの結果を破棄し、上限を超えていれば期限の近いものから破棄する
+    fn evict(&self, slots: &mut HashMap<SlotKey, Slot>) {
+        let now = Instant::now();
+        slots.retain(
+            |_, slot| !matches!(slot.state, SlotState::Done { expires, .. } if expires <= now),
+        );
+        while slots.len() >= self.inner.config.capacity.max(1) {
+            let oldest = slots
+                .iter()
+                .filter_map(|(key, slot)| match slot.state {
+                    SlotState::Done { expires, .. } => Some((key.clone(), expires)),
+                    SlotState::Pending(_) => None,
+                })
+                .min_by_key(|(_, expires)| *expires);
+            match oldest {
+                Some((key, _)) => {
+                    slots.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn replayed(&self, mut response: HandlerResponse) -> HandlerResponse {
+        self.inner.replays.fetch_add(1, Ordering::Relaxed);
+        response
+            .metadata
+            .insert(REPLAYED_METADATA_KEY.to_string(), "true".to_string());
+        response
+    }
+}
+
+/// 冪等キーの状態の確認結果
+enum Claim<'a> {
+    /// 最初のリクエストとして実行を引き受けた
+    Execute(Execution<'a>),
+    /// 保持した結果
+    Recorded(HandlerResponse),
+    /// 処理中の結果の受信側
+    Pending(watch::Receiver<Option<HandlerResponse>>),
+    /// ペイロードの不一致・ウィンドウの不足で拒否した
+    Rejected(ProtocolError),
+}
+
+/// 引き受けた実行（完了せずに破棄された場合は、処理中の記録を取り消す）
+struct Execution<'a> {
+    window: &'a DedupWindow,
+    key: SlotKey,
+    payload_hash: u64,
+    sender: watch::Sender<Option<HandlerResponse>>,
+    completed: bool,
+}
+
+impl Execution<'_> {
+    fn complete(mut self, response: HandlerResponse) {
+        self.completed = true;
+        let mut slots = self.window.inner.slots.lock().unwrap();
+        if response.is_rejected() {
+            slots.remove(&self.key);
+        } else {
+            let expires = Instant::now() + self.window.inner.config.window;
+            slots.insert(
+                self.key.clone(),
+                Slot {
+                    payload_hash: self.payload_hash,
+                    state: SlotState::Done {
+                        response: response.clone(),
+                        expires,
+                    },
+                },
+            );
+        }
+        // 処理中に届いた重複へ同じ結果を返す
+        self.sender.send_replace(Some(response));
+    }
+}
+
+impl Drop for Execution<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.window.inner.slots.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::AtomicUsize;
+
+    fn window() -> DedupWindow {
+        DedupWindow::new(ExactlyOnceConfig::new().with_method("charge"))
+    }
+
+    fn keyed(key: &str) -> Option<IdempotentRequest<'_>> {
+        from("peer-a", key, "{}")
+    }
+
+    fn from<'a>(scope: &str, key: &'a str, payload: &str) -> Option<IdempotentRequest<'a>> {
+        Some(IdempotentRequest {
+            scope: scope.to_string(),
+            key,
+            payload_hash: payload_hash(payload),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_returns_recorded_response() {
+        let window = window();
+        let runs = AtomicUsize::new(0);
+        let handler = || async {
+            let run = runs.fetch_add(1, Ordering::SeqCst);
+            HandlerResponse::ok(json!({ "run": run }))
+        };
+
+        let first = window.run("charge", keyed("k1"), handler()).await;
+        let second = window.run("charge", keyed("k1"), handler()).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(second.outcome.unwrap(), first.outcome.unwrap());
+        assert_eq!(second.metadata[REPLAYED_METADATA_KEY], "true");
+        assert_eq!(window.replays(), 1);
+
+        // 別のキー・対象外のメソッド・キーのないリクエストは毎回実行する
+        window.run("charge", keyed("k2"), handler()).await;
+        window.run("refund", keyed("k1"), handler()).await;
+        window.run("charge", None, handler()).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_duplicates_wait_for_first_execution() {
+        let window = window();
+        let runs = AtomicUsize::new(0);
+        let handler = || async {
+            runs.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            HandlerResponse::ok(json!("done"))
+        };
+
+        let (first, second) = tokio::join!(
+            window.run("charge", keyed("k"), handler()),
+            window.run("charge", keyed("k"), handler()),
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(first.outcome.unwrap(), json!("done"));
+        assert_eq!(second.outcome.unwrap(), json!("done"));
+    }
+
+    #[tokio::test]
+    async fn test_rejections_and_cancelled_executions_are_not_recorded() {
+        let window = window();
+        let rejected = window
+            .run("charge", keyed("k"), async {
+                HandlerResponse::rejected(ProtocolError::new(ProtocolError::UNAVAILABLE, "busy"))
+            })
+            .await;
+        assert!(rejected.outcome.is_err());
+        assert!(window.is_empty());
+
+        // 完了前に破棄された実行は取り消され、次の送信で実行される
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(10),
+            window.run("charge", keyed("k"), std::future::pending()),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        assert!(window.is_empty());
+        let response = window
+            .run("charge", keyed("k"), async {
+                HandlerResponse::ok(json!(1))
+            })
+            .await;
+        assert_eq!(response.outcome.unwrap(), json!(1));
+        assert_eq!(window.len(), 1);
+
+        // ハンドラーが返したエラーは同じコードでも保持する
+        let failed = window
+            .run("charge", keyed("k2"), async {
+                HandlerResponse::error(ProtocolError::new(ProtocolError::UNAVAILABLE, "down"))
+            })
+            .await;
+        assert!(!failed.is_rejected());
+        assert_eq!(window.len(), 2);
+        let replayed = window
+            .run("charge", keyed("k2"), async {
+                HandlerResponse::ok(json!(1))
+            })
+            .await;
+        assert_eq!(replayed.outcome.unwrap_err().message, "down");
+    }
+
+    #[tokio::test]
+    async fn test_results_expire_after_window() {
+        let window = DedupWindow::new(
+            ExactlyOnceConfig::new()
+                .with_method("charge")
+                .with_window(Duration::from_millis(20)),
+        );
+        window
+            .run("charge", keyed("k"), async {
+                HandlerResponse::ok(json!(1))
+            })
+            .await;
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let response = window
+            .run("charge", keyed("k"), async {
+                HandlerResponse::ok(json!(2))
+            })
+            .await;
+        assert_eq!(response.outcome.unwrap(), json!(2));
+        assert_eq!(window.replays(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_scoped_and_bound_to_payload() {
+        let window = window();
+        let ok = |n| async move { HandlerResponse::ok(json!(n)) };
+        window.run("charge", from("a", "k", "{}"), ok(1)).await;
+
+        // 他の相手の同じキーは別のリクエストとして実行する
+        let other = window.run("charge", from("b", "k", "{}"), ok(2)).await;
+        assert_eq!(other.outcome.unwrap(), json!(2));
+
+        let mismatch = window
+            .run("charge", from("a", "k", r#"{"amount":9}"#), ok(3))
+            .await;
+        let error = mismatch.outcome.unwrap_err();
+        assert_eq!(error.code, ProtocolError::INVALID_REQUEST);
+        assert_eq!(error.details.unwrap()["reason"], "payload_mismatch");
+        assert_eq!(window.replays(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_window_full_of_pending_keys_rejects_new_keys() {
+        let window = DedupWindow::new(
+            ExactlyOnceConfig::new()
+                .with_method("charge")
+                .with_capacity(2),
+        );
+        let (release, wait) = watch::channel(false);
+        let pending = |key: &'static str| {
+            let mut wait = wait.clone();
+            window.run("charge", keyed(key), async move {
+                let _ = wait.wait_for(|released| *released).await;
+                HandlerResponse::ok(json!(key))
+            })
+        };
+        let (first, second) = (pending("k1"), pending("k2"));
+        let rejected = async {
+            tokio::task::yield_now().await;
+            let response = window
+                .run("charge", keyed("k3"), async {
+                    HandlerResponse::ok(json!(3))
+                })
+                .await;
+            assert_eq!(window.len(), 2);
+            release.send_replace(true);
+            response
+        };
+        let (first, second, rejected) = tokio::join!(first, second, rejected);
+        assert_eq!(first.outcome.unwrap(), json!("k1"));
+        assert_eq!(second.outcome.unwrap(), json!("k2"));
+        let error = rejected.outcome.unwrap_err();
+        assert_eq!(error.code, ProtocolError::UNAVAILABLE);
+        assert_eq!(error.details.unwrap()["reason"], "dedup_window_full");
+    }
+}
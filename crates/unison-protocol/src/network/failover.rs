@@ -0,0 +1,396 @@
+//! クライアントの複数サーバーへのフェイルオーバー
+//!
+//! 接続先を優先順のリスト（先頭がプライマリ、以降がフォールバック）で指定すると、
+//! 接続に失敗した場合やサーバーからドレイン通知（[`DRAIN_EVENT_METHOD`]）を受けた場合、
+//! 呼び出しが接続のエラーやサーバーの`UNAVAILABLE`で失敗した場合に
+//! 次の接続先へ自動的に切り替えます。
+//!
+//! 試す順序は[`BalanceStrategy`]で変更でき（優先順・ラウンドロビン・ランダム）、
+//! 接続先のリストは[`EndpointDiscovery`]で動的に提供できます。
+//!
+//! 切り替え中の送信はオフラインキューに保持され、切り替え後に送信されます。
+//! 接続断で失敗した呼び出しは、冪等と指定されたメソッドに限り新しい接続で再送します。
+//! `UNAVAILABLE`で拒否された呼び出しはサーバーで処理されていないため、常に再送します。
+
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{Notify, watch};
+use tracing::warn;
+
+use super::discovery::EndpointDiscovery;
+
+/// サーバーが接続先の切り替えを促すイベントのメソッド名
+pub const DRAIN_EVENT_METHOD: &str = "unison.drain";
+
+/// フェイルオーバーのエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FailoverError {
+    #[error("No endpoints configured")]
+    NoEndpoints,
+    #[error("All {attempts} connection attempts failed (last error: {last_error})")]
+    Exhausted { attempts: usize, last_error: String },
+}
+
+/// 接続先を試す順序の決め方
+pub trait BalanceStrategy: Send + Sync {
+    /// `endpoints`の添字を試す順に返す（`current`は切り替え元の接続先の添字）
+    fn order(&self, endpoints: &[String], current: Option<usize>) -> Vec<usize>;
+}
+
+/// 先頭から優先順に試す（切り替え時は現在の次から、現在の接続先は最後）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriorityBalance;
+
+impl BalanceStrategy for PriorityBalance {
+    fn order(&self, endpoints: &[String], current: Option<usize>) -> Vec<usize> {
+        let start = current.map_or(0, |current| current + 1);
+        (0..endpoints.len())
+            .map(|i| (start + i) % endpoints.len())
+            .collect()
+    }
+}
+
+/// 接続のたびに開始位置を1つずつずらす
+#[derive(Debug, Default)]
+pub struct RoundRobinBalance {
+    next: AtomicUsize,
+}
+
+impl BalanceStrategy for RoundRobinBalance {
+    fn order(&self, endpoints: &[String], current: Option<usize>) -> Vec<usize> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let order = (0..endpoints.len()).map(|i| (start + i) % endpoints.len());
+        last_if_current(order.collect(), current)
+    }
+}
+
+/// 毎回ランダムな順に試す（多数のクライアントの接続先を分散する）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomBalance;
+
+impl BalanceStrategy for RandomBalance {
+    fn order(&self, endpoints: &[String], current: Option<usize>) -> Vec<usize> {
+        // 呼び出しごとに異なる鍵のハッシュ値で並べ替える
+        let state = RandomState::new();
+        let mut order: Vec<usize> = (0..endpoints.len()).collect();
+        order.sort_by_cached_key(|&i| state.hash_one((i, &endpoints[i])));
+        last_if_current(order, current)
+    }
+}
+
+/// 切り替え元の接続先を最後に回す
+fn last_if_current(mut order: Vec<usize>, current: Option<usize>) -> Vec<usize> {
+    if let Some(position) = current.and_then(|current| order.iter().position(|&i| i == current)) {
+        let current = order.remove(position);
+        order.push(current);
+    }
+    order
+}
+
+/// フェイルオーバーの設定
+#[derive(Clone)]
+pub struct FailoverConfig {
+    /// 接続先（先頭がプライマリ、以降がフォールバック）
+    pub endpoints: Vec<String>,
+    /// 切り替え時に全接続先を試す周回数
+    pub max_rounds: usize,
+    /// 接続断で失敗した呼び出しを再送してよいメソッド（冪等なもの）
+    pub retryable_methods: HashSet<String>,
+    /// 再送の前に切り替え完了を待つ最大時間
+    pub retry_wait: Duration,
+    /// 接続先を試す順序
+    pub balance: Arc<dyn BalanceStrategy>,
+    /// 接続先のリストの提供元（指定した場合は`endpoints`より優先）
+    pub discovery: Option<Arc<dyn EndpointDiscovery>>,
+    /// 接続中に接続先のリストを取得し直す間隔
+    pub refresh_interval: Option<Duration>,
+}
+
+impl FailoverConfig {
+    /// プライマリの接続先を指定して作成
+    pub fn new(primary: impl Into<String>) -> Self {
+        Self {
+            endpoints: vec![primary.into()],
+            ..Default::default()
+        }
+    }
+
+    /// 優先順の接続先リストから作成
+    pub fn from_endpoints<I, S>(endpoints: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            endpoints: endpoints.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// フォールバックの接続先を末尾に追加
+    pub fn with_fallback(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoints.push(endpoint.into());
+        self
+    }
+
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// 接続断時に再送してよいメソッドを追加
+    pub fn with_retryable_method(mut self, method: impl Into<String>) -> Self {
+        self.retryable_methods.insert(method.into());
+        self
+    }
+
+    pub fn with_retry_wait(mut self, retry_wait: Duration) -> Self {
+        self.retry_wait = retry_wait;
+        self
+    }
+
+    pub fn with_balance(mut self, balance: impl BalanceStrategy + 'static) -> Self {
+        self.balance = Arc::new(balance);
+        self
+    }
+
+    /// 接続先のリストを接続・切り替えのたびに`discovery`から取得する
+    ///
+    /// 取得に失敗した場合は前回のリスト（初回は`endpoints`）を使います。
+    pub fn with_discovery(mut self, discovery: impl EndpointDiscovery + 'static) -> Self {
+        self.discovery = Some(Arc::new(discovery));
+        self
+    }
+
+    /// 接続中もリストを定期的に取得し直し、現在の接続先が消えたら切り替える
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = Some(interval);
+        self
+    }
+
+    /// 接続断で失敗した呼び出しを再送してよいか
+    pub fn is_retryable(&self, method: &str) -> bool {
+        self.retryable_methods.contains(method)
+    }
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            max_rounds: 1,
+            retryable_methods: HashSet::new(),
+            retry_wait: Duration::from_secs(10),
+            balance: Arc::new(PriorityBalance),
+            discovery: None,
+            refresh_interval: None,
+        }
+    }
+}
+
+impl fmt::Debug for FailoverConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FailoverConfig")
+            .field("endpoints", &self.endpoints)
+            .field("max_rounds", &self.max_rounds)
+            .field("retryable_methods", &self.retryable_methods)
+            .field("retry_wait", &self.retry_wait)
+            .field("discovery", &self.discovery.is_some())
+            .field("refresh_interval", &self.refresh_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 接続先の選択状態
+pub struct EndpointSelector {
+    config: FailoverConfig,
+    endpoints: RwLock<Vec<String>>,
+    current: Mutex<Option<String>>,
+    /// 接続先を切り替えた回数（切り替えの完了を待つために使う）
+    switches: watch::Sender<u64>,
+    failover_requested: Notify,
+}
+
+impl EndpointSelector {
+    pub fn new(config: FailoverConfig) -> Self {
+        Self {
+            endpoints: RwLock::new(config.endpoints.clone()),
+            config,
+            current: Mutex::new(None),
+            switches: watch::Sender::new(0),
+            failover_requested: Notify::new(),
+        }
+    }
+
+    pub fn config(&self) -> &FailoverConfig {
+        &self.config
+    }
+
+    /// 現在の接続先のリスト
+    pub fn endpoints(&self) -> Vec<String> {
+        self.endpoints.read().unwrap().clone()
+    }
+
+    /// 現在の接続先
+    pub fn current(&self) -> Option<String> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// 接続先のリストを取得し直す（[`FailoverConfig::discovery`]がない場合は何もしない）
+    ///
+    /// 現在の接続先がリストに残っているかを返します。
+    pub async fn refresh(&self) -> bool {
+        if let Some(discovery) = &self.config.discovery {
+            match discovery.discover().await {
+                Ok(endpoints) if !endpoints.is_empty() => {
+                    *self.endpoints.write().unwrap() = endpoints;
+                }
+                Ok(_) => warn!("Service discovery returned no endpoints, keeping the last list"),
+                Err(e) => warn!("{}, keeping the last list", e),
+            }
+        }
+        match self.current() {
+            Some(current) => self.endpoints.read().unwrap().contains(&current),
+            None => true,
+        }
+    }
+
+    /// 初回接続で試す順序
+    pub fn initial_candidates(&self) -> Vec<(usize, String)> {
+        self.candidates(None)
+    }
+
+    /// 切り替え時に試す順序（既定では現在の次から順に、現在の接続先は最後）
+    pub fn failover_candidates(&self) -> Vec<(usize, String)> {
+        // 未接続の場合はプライマリから切り替える
+        let index = match self.current() {
+            Some(current) => self
+                .endpoints
+                .read()
+                .unwrap()
+                .iter()
+                .position(|endpoint| *endpoint == current),
+            None => Some(0),
+        };
+        self.candidates(index)
+    }
+
+    /// 接続に成功した接続先を記録
+    pub fn mark_connected(&self, index: usize) {
+        let endpoint = self.endpoints.read().unwrap().get(index).cloned();
+        *self.current.lock().unwrap() = endpoint;
+        self.switches.send_modify(|switches| *switches += 1);
+    }
+
+    /// 接続先の切り替えを要求（呼び出しの失敗を検出した場合など）
+    pub fn request_failover(&self) {
+        self.failover_requested.notify_one();
+    }
+
+    pub(crate) async fn failover_requested(&self) {
+        self.failover_requested.notified().await;
+    }
+
+    /// 切り替えを待つための受信側（値は接続に成功した回数）
+    pub(crate) fn switches(&self) -> watch::Receiver<u64> {
+        self.switches.subscribe()
+    }
+
+    fn candidates(&self, current: Option<usize>) -> Vec<(usize, String)> {
+        let endpoints = self.endpoints.read().unwrap();
+        if endpoints.is_empty() {
+            return Vec::new();
+        }
+        let order = self.config.balance.order(&endpoints, current);
+        (0..self.config.max_rounds.max(1))
+            .flat_map(|_| order.iter().map(|&index| (index, endpoints[index].clone())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(candidates: Vec<(usize, String)>) -> Vec<String> {
+        candidates.into_iter().map(|(_, url)| url).collect()
+    }
+
+    #[test]
+    fn test_candidate_order() {
+        let selector = EndpointSelector::new(
+            FailoverConfig::new("[::1]:8080")
+                .with_fallback("[::1]:8081")
+                .with_fallback("[::1]:8082"),
+        );
+        assert_eq!(
+            urls(selector.initial_candidates()),
+            vec!["[::1]:8080", "[::1]:8081", "[::1]:8082"]
+        );
+
+        selector.mark_connected(1);
+        assert_eq!(selector.current().as_deref(), Some("[::1]:8081"));
+        // 現在の接続先は最後に試す
+        assert_eq!(
+            urls(selector.failover_candidates()),
+            vec!["[::1]:8082", "[::1]:8080", "[::1]:8081"]
+        );
+    }
+
+    #[test]
+    fn test_max_rounds_repeats_endpoints() {
+        let selector = EndpointSelector::new(
+            FailoverConfig::new("a")
+                .with_fallback("b")
+                .with_max_rounds(2)
+                .with_retryable_method("get_user"),
+        );
+        assert_eq!(
+            urls(selector.failover_candidates()),
+            vec!["b", "a", "b", "a"]
+        );
+        assert!(selector.config().is_retryable("get_user"));
+        assert!(!selector.config().is_retryable("create_user"));
+    }
+
+    #[test]
+    fn test_balance_strategies() {
+        let endpoints: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+        let round_robin = RoundRobinBalance::default();
+        assert_eq!(round_robin.order(&endpoints, None), vec![0, 1, 2]);
+        assert_eq!(round_robin.order(&endpoints, None), vec![1, 2, 0]);
+        // 切り替え元は最後
+        assert_eq!(round_robin.order(&endpoints, Some(2)), vec![0, 1, 2]);
+
+        let mut random = RandomBalance.order(&endpoints, Some(1));
+        assert_eq!(random.pop(), Some(1));
+        random.sort();
+        assert_eq!(random, vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_discovery_replaces_endpoints() {
+        use crate::network::discovery::StaticEndpoints;
+
+        let selector = EndpointSelector::new(
+            FailoverConfig::new("old").with_discovery(StaticEndpoints::new(["x", "y"])),
+        );
+        selector.mark_connected(0);
+        assert_eq!(selector.current().as_deref(), Some("old"));
+        // 現在の接続先がリストから消えた
+        assert!(!selector.refresh().await);
+        assert_eq!(selector.endpoints(), vec!["x", "y"]);
+        assert_eq!(urls(selector.failover_candidates()), vec!["x", "y"]);
+
+        let mut switches = selector.switches();
+        selector.mark_connected(1);
+        assert!(switches.has_changed().unwrap());
+        assert!(selector.refresh().await);
+    }
+}
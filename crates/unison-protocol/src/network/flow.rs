@@ -0,0 +1,223 @@
+//! ストリーム送信のフロー制御
+//!
+//! [`UnisonStream`](super::UnisonStream)の送信はストリームごとの送信タスクを経由し、
+//! 書き込み待ちのバイト数を[`SendWindow`]で数えます。受信側が遅くQUICのフロー制御で
+//! 書き込みが止まると、待ちのバイト数が上限（`high_watermark`）に達した時点で送信を止め、
+//! 下限（`low_watermark`）まで減ってから再開します。
+//!
+//! - `send`は再開まで待機します
+//! - `try_send`は待機せず[`NetworkError::WouldBlock`](super::NetworkError::WouldBlock)を返します
+//!   （リアルタイム用途で古いデータを捨てる場合など）
+
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// フロー制御の設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowControlConfig {
+    /// 書き込み待ちがこのバイト数に達すると送信を止める
+    pub high_watermark: usize,
+    /// 止めた送信を再開する書き込み待ちのバイト数（`high_watermark`を超える値は丸める）
+    pub low_watermark: usize,
+}
+
+impl Default for FlowControlConfig {
+    fn default() -> Self {
+        Self {
+            high_watermark: 1024 * 1024,
+            low_watermark: 256 * 1024,
+        }
+    }
+}
+
+/// 書き込み待ちのバイト数と送信の停止状態
+#[derive(Debug)]
+pub struct SendWindow {
+    state: Mutex<WindowState>,
+    changed: Notify,
+}
+
+#[derive(Debug)]
+struct WindowState {
+    config: FlowControlConfig,
+    buffered: usize,
+    /// 上限に達してから下限まで減るまでの間
+    paused: bool,
+    /// 送信タスクが終わり、これ以上書き込まれない
+    closed: bool,
+}
+
+/// 送信枠を確保できなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowError {
+    /// 書き込み待ちが上限に達している
+    Full,
+    /// ストリームへの書き込みが終わっている
+    Closed,
+}
+
+impl SendWindow {
+    pub fn new(config: FlowControlConfig) -> Self {
+        Self {
+            state: Mutex::new(WindowState {
+                config,
+                buffered: 0,
+                paused: false,
+                closed: false,
+            }),
+            changed: Notify::new(),
+        }
+    }
+
+    /// 上限と下限を変更
+    pub fn configure(&self, config: FlowControlConfig) {
+        let mut state = self.state.lock().unwrap();
+        state.config = config;
+        state.update_paused();
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    /// 書き込み待ちのバイト数
+    pub fn buffered(&self) -> usize {
+        self.state.lock().unwrap().buffered
+    }
+
+    /// 待機せずに`len`バイト分の送信枠を確保
+    pub fn try_acquire(&self, len: usize) -> Result<(), WindowError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(WindowError::Closed);
+        }
+        if state.paused {
+            return Err(WindowError::Full);
+        }
+        state.buffered += len;
+        state.update_paused();
+        Ok(())
+    }
+
+    /// 送信が再開されるまで待ってから`len`バイト分の送信枠を確保
+    pub async fn acquire(&self, len: usize) -> Result<(), WindowError> {
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            match self.try_acquire(len) {
+                Err(WindowError::Full) => changed.await,
+                result => return result,
+            }
+        }
+    }
+
+    /// 書き込み終えた`len`バイト分の送信枠を返す
+    pub fn release(&self, len: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.buffered = state.buffered.saturating_sub(len);
+        state.update_paused();
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    /// 書き込みが終わったことを記録し、待機中の送信を起こす
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_waiters();
+    }
+
+    /// 書き込み待ちがなくなる（または書き込みが終わる）まで待機
+    pub async fn flushed(&self) {
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            {
+                let state = self.state.lock().unwrap();
+                if state.buffered == 0 || state.closed {
+                    return;
+                }
+            }
+            changed.await;
+        }
+    }
+}
+
+impl Default for SendWindow {
+    fn default() -> Self {
+        Self::new(FlowControlConfig::default())
+    }
+}
+
+impl WindowState {
+    fn update_paused(&mut self) {
+        let high = self.config.high_watermark;
+        let low = self.config.low_watermark.min(high);
+        if self.buffered >= high {
+            self.paused = true;
+        } else if self.buffered <= low {
+            self.paused = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn window(high: usize, low: usize) -> SendWindow {
+        SendWindow::new(FlowControlConfig {
+            high_watermark: high,
+            low_watermark: low,
+        })
+    }
+
+    #[test]
+    fn test_pauses_at_high_and_resumes_at_low_watermark() {
+        let window = window(100, 40);
+        assert_eq!(window.try_acquire(60), Ok(()));
+        assert_eq!(window.try_acquire(60), Ok(()));
+        assert_eq!(window.try_acquire(1), Err(WindowError::Full));
+
+        // 上限を下回っても下限までは止めたまま
+        window.release(60);
+        assert_eq!(window.buffered(), 60);
+        assert_eq!(window.try_acquire(1), Err(WindowError::Full));
+
+        window.release(30);
+        assert_eq!(window.try_acquire(1), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_for_release() {
+        let window = Arc::new(window(10, 0));
+        window.try_acquire(10).unwrap();
+
+        let waiting = tokio::spawn({
+            let window = Arc::clone(&window);
+            async move { window.acquire(5).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        window.release(10);
+        assert_eq!(waiting.await.unwrap(), Ok(()));
+        assert_eq!(window.buffered(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_close_wakes_waiting_senders() {
+        let window = Arc::new(window(10, 0));
+        window.try_acquire(10).unwrap();
+        let waiting = tokio::spawn({
+            let window = Arc::clone(&window);
+            async move { window.acquire(1).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        window.close();
+        assert_eq!(waiting.await.unwrap(), Err(WindowError::Closed));
+        window.flushed().await;
+    }
+}
@@ -0,0 +1,220 @@
+//! バイトストリーム上のメッセージの区切り
+//!
+//! パイプや標準入出力のような1本のバイトストリームでは、各メッセージの境界を示す必要があります。
+//! QUICのストリームも同じ長さ接頭辞で区切り、1本のストリームで複数のメッセージを送受信します。
+//!
+//! - 長さ接頭辞: 4バイト（ビッグエンディアン）の長さに続けて[`ProtocolFrame`](super::ProtocolFrame)のバイト列
+//! - 行区切り: 1行に1つのJSON（[`stdio`](super::stdio)を参照）
+//!
+//! どちらを使うかは[`WireFormat`]で指定します。既定は[`ProtocolFrame`](super::ProtocolFrame)（`UnisonPacket`）で、
+//! 圧縮・チェックサム・優先度をすべてのトランスポートで使えます。JSONはパケット層に
+//! 対応していない古い相手との互換用です。
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::decode::{DecodeConfig, decode_frame};
+use super::stdio::{decode_line, encode_line};
+use super::{NetworkError, ProtocolMessage};
+
+/// 1フレームの最大サイズ
+pub const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+fn io_error(e: std::io::Error) -> NetworkError {
+    NetworkError::Connection(e.to_string())
+}
+
+/// 長さ接頭辞を付けてメッセージのフレームを書き込む
+pub async fn write_frame<W>(writer: &mut W, message: &ProtocolMessage) -> Result<(), NetworkError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let bytes = message.clone().into_frame()?.to_bytes();
+    if bytes.len() > MAX_FRAME_SIZE {
+        return Err(NetworkError::Protocol(format!(
+            "Frame too large: {} bytes (max {})",
+            bytes.len(),
+            MAX_FRAME_SIZE
+        )));
+    }
+    writer
+        .write_all(&(bytes.len() as u32).to_be_bytes())
+        .await
+        .map_err(io_error)?;
+    writer.write_all(&bytes).await.map_err(io_error)?;
+    writer.flush().await.map_err(io_error)
+}
+
+/// 長さ接頭辞付きのフレームを1件読み込む
+///
+/// フレームの境界で入力が閉じた場合は`None`を返します。
+pub async fn read_frame<R>(reader: &mut R) -> Result<Option<ProtocolMessage>, NetworkError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    // 境界での終了と途中での切断を区別する
+    if reader.fill_buf().await.map_err(io_error)?.is_empty() {
+        return Ok(None);
+    }
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length).await.map_err(io_error)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(NetworkError::Protocol(format!(
+            "Frame too large: {} bytes (max {})",
+            length, MAX_FRAME_SIZE
+        )));
+    }
+
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes).await.map_err(io_error)?;
+    let (_, message) = decode_frame(bytes.into(), DecodeConfig::default()).await?;
+    Ok(Some(message))
+}
+
+/// WebSocket・標準入出力でのメッセージのワイヤー形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WireFormat {
+    /// [`ProtocolFrame`](super::ProtocolFrame)（WebSocketではバイナリフレーム、標準入出力では長さ接頭辞付き）
+    #[default]
+    Packet,
+    /// 1メッセージ1つのJSON（WebSocketではテキストフレーム、標準入出力では1行）
+    Json,
+}
+
+impl From<WireFormat> for Framing {
+    fn from(format: WireFormat) -> Self {
+        match format {
+            WireFormat::Packet => Framing::LengthPrefixed,
+            WireFormat::Json => Framing::Lines,
+        }
+    }
+}
+
+/// バイトストリームでのメッセージの区切り方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    /// 1行に1つのJSON
+    Lines,
+    /// 長さ接頭辞付きのフレーム
+    LengthPrefixed,
+}
+
+impl Framing {
+    /// メッセージを1件読み込む（入力が閉じていれば`None`）
+    ///
+    /// 内容を解釈できないメッセージは`Serialization`・`FrameSerialization`のエラーになり、
+    /// 続けて次のメッセージを読み込めます。
+    pub(crate) async fn read<R>(
+        self,
+        reader: &mut R,
+    ) -> Result<Option<ProtocolMessage>, NetworkError>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        match self {
+            Framing::LengthPrefixed => read_frame(reader).await,
+            Framing::Lines => {
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).await.map_err(io_error)? == 0 {
+                        return Ok(None);
+                    }
+                    if !line.trim().is_empty() {
+                        return decode_line(&line).map(Some);
+                    }
+                }
+            }
+        }
+    }
+
+    /// メッセージを1件書き込む
+    pub(crate) async fn write<W>(
+        self,
+        writer: &mut W,
+        message: &ProtocolMessage,
+    ) -> Result<(), NetworkError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self {
+            Framing::LengthPrefixed => write_frame(writer, message).await,
+            Framing::Lines => {
+                let mut line = encode_line(message)?;
+                line.push('\n');
+                writer.write_all(line.as_bytes()).await.map_err(io_error)?;
+                writer.flush().await.map_err(io_error)
+            }
+        }
+    }
+}
+
+/// 読み飛ばして次のメッセージを読めるエラーか
+pub(crate) fn is_malformed(error: &NetworkError) -> bool {
+    matches!(
+        error,
+        NetworkError::Serialization(_) | NetworkError::FrameSerialization(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+    use tokio::io::BufReader;
+
+    fn message(id: u64) -> ProtocolMessage {
+        ProtocolMessage::new_with_json(
+            id,
+            "echo".into(),
+            MessageType::Request,
+            serde_json::json!({ "n": id }),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_frames_round_trip() {
+        let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+        for framing in [Framing::LengthPrefixed, Framing::Lines] {
+            framing.write(&mut writer, &message(1)).await.unwrap();
+            framing.write(&mut writer, &message(2)).await.unwrap();
+        }
+        drop(writer);
+
+        let mut reader = BufReader::new(reader);
+        for framing in [Framing::LengthPrefixed, Framing::Lines] {
+            for id in [1, 2] {
+                let read = framing.read(&mut reader).await.unwrap().unwrap();
+                assert_eq!(read.id, id);
+                assert_eq!(read.payload_as_value().unwrap()["n"], id);
+            }
+        }
+        assert!(read_frame(&mut reader).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_and_truncated_frames_rejected() {
+        let (mut writer, reader) = tokio::io::duplex(1024);
+        writer
+            .write_all(&((MAX_FRAME_SIZE + 1) as u32).to_be_bytes())
+            .await
+            .unwrap();
+        let mut reader = BufReader::new(reader);
+        assert!(matches!(
+            read_frame(&mut reader).await,
+            Err(NetworkError::Protocol(_))
+        ));
+
+        let (mut writer, reader) = tokio::io::duplex(1024);
+        writer.write_all(&16u32.to_be_bytes()).await.unwrap();
+        writer.write_all(b"short").await.unwrap();
+        drop(writer);
+        let mut reader = BufReader::new(reader);
+        assert!(matches!(
+            read_frame(&mut reader).await,
+            Err(NetworkError::Connection(_))
+        ));
+    }
+}
@@ -0,0 +1,421 @@
+//! サーバーハンドラーのレスポンス型
+//!
+//! ハンドラーは[`HandlerResponse`]を返します。成功時のペイロードか型付きのエラー
+//! （[`ProtocolError`]）に加え、レスポンスのメタデータとキャッシュ制御のヒントを
+//! 持たせることができ、[`HandlerResponse::into_message`]でワイヤー形式へ変換されます。
+//!
+//! - 成功: `Response`メッセージのペイロードに値を格納
+//! - エラー: `Error`メッセージのペイロードに`{"code", "message", "details"}`を格納
+//! - メタデータ・キャッシュ制御: メッセージの`metadata`に格納（キャッシュ制御は`cache-control`キー）
+//!
+//! ハンドラーのパニックは内部エラーに、実行期限の超過は`DEADLINE_EXCEEDED`に変換され、
+//! いずれも[`HandlerMetrics`]に記録されます。
+
+use futures_util::FutureExt;
+use serde_json::Value;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::{error, warn};
+
+use super::content::{CONTENT_TYPE_METADATA_KEY, PayloadEncoding};
+use super::{MessageType, NetworkError, ProtocolError, ProtocolMessage};
+
+/// キャッシュ制御ヒントを格納するメタデータのキー
+pub const CACHE_CONTROL_KEY: &str = "cache-control";
+
+/// ハンドラーの実行期限の既定値
+pub const DEFAULT_HANDLER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// ハンドラー登録時のオプション
+#[derive(Debug, Clone, Default)]
+pub struct HandlerOptions {
+    /// このハンドラーの実行期限（未指定の場合はサーバーの既定値）
+    pub timeout: Option<Duration>,
+}
+
+impl HandlerOptions {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// ハンドラー実行の異常を数えるメトリクス
+#[derive(Debug, Default)]
+pub struct HandlerMetrics {
+    panics: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl HandlerMetrics {
+    /// 捕捉したパニックの数
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// 実行期限を超過して中断したハンドラーの数
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_panic(&self) {
+        self.panics.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// レスポンスのキャッシュ制御ヒント
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheControl {
+    /// キャッシュしてはならない
+    NoStore,
+    /// 指定の期間キャッシュできる
+    MaxAge(Duration),
+    /// 内容が変化しないため無期限にキャッシュできる
+    Immutable,
+}
+
+impl CacheControl {
+    /// `cache-control`の値を解析
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "no-store" => Some(CacheControl::NoStore),
+            "immutable" => Some(CacheControl::Immutable),
+            other => other
+                .strip_prefix("max-age=")
+                .and_then(|secs| secs.parse::<u64>().ok())
+                .map(|secs| CacheControl::MaxAge(Duration::from_secs(secs))),
+        }
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheControl::NoStore => f.write_str("no-store"),
+            CacheControl::MaxAge(age) => write!(f, "max-age={}", age.as_secs()),
+            CacheControl::Immutable => f.write_str("immutable"),
+        }
+    }
+}
+
+/// ハンドラーのレスポンス
+#[derive(Debug, Clone)]
+pub struct HandlerResponse {
+    pub outcome: Result<Value, ProtocolError>,
+    pub metadata: HashMap<String, String>,
+    pub cache_control: Option<CacheControl>,
+    /// サーバーがハンドラーを実行する前に拒否したか
+    rejected: bool,
+}
+
+impl HandlerResponse {
+    /// 成功レスポンス
+    pub fn ok(payload: Value) -> Self {
+        Self::from_outcome(Ok(payload))
+    }
+
+    /// エラーレスポンス
+    pub fn error(error: ProtocolError) -> Self {
+        Self::from_outcome(Err(error))
+    }
+
+    /// サーバーがハンドラーを実行する前に拒否したレスポンス（停止中・レート制限・アドミッション制御など）
+    pub(crate) fn rejected(error: ProtocolError) -> Self {
+        Self {
+            rejected: true,
+            ..Self::error(error)
+        }
+    }
+
+    fn from_outcome(outcome: Result<Value, ProtocolError>) -> Self {
+        Self {
+            outcome,
+            metadata: HashMap::new(),
+            cache_control: None,
+            rejected: false,
+        }
+    }
+
+    /// レスポンスのメタデータを追加
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.outcome.is_ok()
+    }
+
+    /// ハンドラーを実行する前に拒否したレスポンスか（同じリクエストを再送すれば改めて処理される）
+    pub(crate) fn is_rejected(&self) -> bool {
+        self.rejected
+    }
+
+    /// メタデータを捨てて結果だけを取り出す
+    pub fn into_result(self) -> anyhow::Result<Value> {
+        self.outcome.map_err(|e| anyhow::anyhow!(e.message))
+    }
+
+    /// リクエストに対するワイヤー形式のメッセージへ変換
+    ///
+    /// メタデータの[`CONTENT_TYPE_METADATA_KEY`]でエンコーディングが指定されていれば、
+    /// 成功時のペイロードをそのエンコーディングで格納します（変換できない場合とエラーはJSON）。
+    pub fn into_message(self, id: u64, method: String) -> Result<ProtocolMessage, NetworkError> {
+        let mut metadata = self.metadata;
+        if let Some(cache_control) = self.cache_control {
+            metadata.insert(CACHE_CONTROL_KEY.to_string(), cache_control.to_string());
+        }
+        let encoding = metadata
+            .remove(CONTENT_TYPE_METADATA_KEY)
+            .and_then(|name| name.parse::<PayloadEncoding>().ok())
+            .unwrap_or_default();
+
+        let message = match self.outcome {
+            Ok(payload) if encoding != PayloadEncoding::Json => match encoding.encode(&payload) {
+                Ok(encoded) => {
+                    metadata.insert(CONTENT_TYPE_METADATA_KEY.to_string(), encoding.to_string());
+                    ProtocolMessage {
+                        id,
+                        method,
+                        msg_type: MessageType::Response,
+                        payload: encoded,
+                        metadata: String::new(),
+                    }
+                }
+                Err(e) => {
+                    warn!("Falling back to JSON for {} response: {}", method, e);
+                    ProtocolMessage::new_with_json(id, method, MessageType::Response, payload)?
+                }
+            },
+            Ok(payload) => {
+                ProtocolMessage::new_with_json(id, method, MessageType::Response, payload)?
+            }
+            Err(error) => ProtocolMessage::new_with_json(
+                id,
+                method,
+                MessageType::Error,
+                serde_json::to_value(error)?,
+            )?,
+        };
+        message.with_metadata(&metadata)
+    }
+}
+
+impl From<anyhow::Result<Value>> for HandlerResponse {
+    fn from(result: anyhow::Result<Value>) -> Self {
+        Self::from_outcome(result.map_err(|e| ProtocolError::internal(e.to_string())))
+    }
+}
+
+impl From<Result<Value, NetworkError>> for HandlerResponse {
+    fn from(result: Result<Value, NetworkError>) -> Self {
+        Self::from_outcome(result.map_err(ProtocolError::from))
+    }
+}
+
+impl From<Result<Value, ProtocolError>> for HandlerResponse {
+    fn from(outcome: Result<Value, ProtocolError>) -> Self {
+        Self::from_outcome(outcome)
+    }
+}
+
+/// ハンドラーの実行中に発生したパニックを捕捉し、内部エラーへ変換
+///
+/// パニックの内容はサーバー側のログにのみ相関IDと共に記録し、
+/// クライアントには相関IDだけを返します。パニックを捕捉した場合は相関IDを渡して`on_panic`が呼ばれます。
+pub(crate) async fn catch_handler_panic<Fut>(
+    method: &str,
+    handler: Fut,
+    on_panic: impl FnOnce(&str),
+) -> HandlerResponse
+where
+    Fut: Future<Output = HandlerResponse>,
+{
+    match AssertUnwindSafe(handler).catch_unwind().await {
+        Ok(response) => response,
+        Err(panic) => panic_response(method, panic.as_ref(), on_panic),
+    }
+}
+
+/// 実行期限を超えたハンドラーを中断し、`DEADLINE_EXCEEDED`を返す
+///
+/// 期限を超えた時点でハンドラーのFutureは破棄されます。
+pub(crate) async fn enforce_deadline<Fut>(
+    method: &str,
+    timeout: Option<Duration>,
+    handler: Fut,
+    metrics: &HandlerMetrics,
+) -> HandlerResponse
+where
+    Fut: Future<Output = HandlerResponse>,
+{
+    let Some(timeout) = timeout else {
+        return handler.await;
+    };
+
+    match tokio::time::timeout(timeout, handler).await {
+        Ok(response) => response,
+        Err(_) => {
+            metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "Handler for {} exceeded its deadline of {:?} and was aborted",
+                method, timeout
+            );
+            HandlerResponse::error(
+                ProtocolError::new(
+                    ProtocolError::DEADLINE_EXCEEDED,
+                    format!("Deadline exceeded after {:?}", timeout),
+                )
+                .with_details(serde_json::json!({ "timeout_ms": timeout.as_millis() as u64 })),
+            )
+        }
+    }
+}
+
+/// パニックを相関ID付きの内部エラーへ変換し、相関IDを渡して`on_panic`を呼ぶ
+pub(crate) fn panic_response(
+    method: &str,
+    panic: &(dyn Any + Send),
+    on_panic: impl FnOnce(&str),
+) -> HandlerResponse {
+    HandlerResponse::error(panic_error(method, panic, on_panic))
+}
+
+/// パニックの内容をログに記録し、クライアントへ返す相関ID付きの内部エラーを作る
+pub(crate) fn panic_error(
+    method: &str,
+    panic: &(dyn Any + Send),
+    on_panic: impl FnOnce(&str),
+) -> ProtocolError {
+    let correlation_id = uuid::Uuid::new_v4().to_string();
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    error!(
+        correlation_id = %correlation_id,
+        "Handler for {} panicked: {}",
+        method,
+        message
+    );
+    on_panic(&correlation_id);
+
+    ProtocolError::internal(format!(
+        "Internal error (correlation id: {})",
+        correlation_id
+    ))
+    .with_details(serde_json::json!({ "correlation_id": correlation_id }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_success_maps_metadata_and_cache_control() {
+        let message = HandlerResponse::ok(json!({"name": "alice"}))
+            .with_metadata("x-request-cost", "3")
+            .with_cache_control(CacheControl::MaxAge(Duration::from_secs(60)))
+            .into_message(7, "get_user".to_string())
+            .unwrap();
+
+        assert_eq!(message.msg_type, MessageType::Response);
+        assert_eq!(message.payload_as_value().unwrap()["name"], "alice");
+        let metadata = message.metadata().unwrap();
+        assert_eq!(metadata["x-request-cost"], "3");
+        assert_eq!(
+            CacheControl::parse(&metadata[CACHE_CONTROL_KEY]),
+            Some(CacheControl::MaxAge(Duration::from_secs(60)))
+        );
+    }
+
+    #[test]
+    fn test_typed_error_maps_to_error_message() {
+        let message = HandlerResponse::error(
+            ProtocolError::new(ProtocolError::NOT_FOUND, "user not found")
+                .with_details(json!({"id": 42})),
+        )
+        .into_message(7, "get_user".to_string())
+        .unwrap();
+
+        assert_eq!(message.msg_type, MessageType::Error);
+        let payload = message.payload_as_value().unwrap();
+        assert_eq!(payload["code"], ProtocolError::NOT_FOUND);
+        assert_eq!(payload["message"], "user not found");
+        assert_eq!(payload["details"]["id"], 42);
+        // メタデータがない場合は空のまま
+        assert!(message.metadata().unwrap().is_empty());
+
+        let response: HandlerResponse = Err::<Value, _>(anyhow::anyhow!("boom")).into();
+        assert_eq!(response.outcome.unwrap_err().code, ProtocolError::INTERNAL);
+    }
+
+    #[test]
+    fn test_unison_error_keeps_application_code() {
+        let error = crate::core::UnisonError::with_details(
+            "USER_NOT_FOUND",
+            "user not found",
+            json!({"id": 42}),
+        );
+        let response: HandlerResponse = Err::<Value, _>(NetworkError::Remote(error.clone())).into();
+        let message = response.into_message(7, "get_user".to_string()).unwrap();
+        let payload = message.payload_as_value().unwrap();
+        assert_eq!(payload["code"], ProtocolError::APPLICATION);
+        assert_eq!(payload["error_code"], "USER_NOT_FOUND");
+
+        let restored = crate::core::UnisonError::from(
+            serde_json::from_value::<ProtocolError>(payload).unwrap(),
+        );
+        assert_eq!(
+            (restored.code, restored.message, restored.details),
+            (error.code, error.message, error.details)
+        );
+
+        // 固有のコードがなければ数値のコードになる
+        let restored =
+            crate::core::UnisonError::from(ProtocolError::new(ProtocolError::NOT_FOUND, "missing"));
+        assert_eq!(restored.code, "404");
+    }
+
+    #[tokio::test]
+    async fn test_enforce_deadline_aborts_overrunning_handler() {
+        let metrics = HandlerMetrics::default();
+        let response = enforce_deadline(
+            "slow",
+            Some(Duration::from_millis(10)),
+            async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                HandlerResponse::ok(json!({}))
+            },
+            &metrics,
+        )
+        .await;
+        let error = response.outcome.unwrap_err();
+        assert_eq!(error.code, ProtocolError::DEADLINE_EXCEEDED);
+        assert_eq!(error.details.unwrap()["timeout_ms"], 10);
+        assert_eq!(metrics.timeouts(), 1);
+
+        // 期限内に完了したものはそのまま返す
+        let response = enforce_deadline(
+            "fast",
+            Some(Duration::from_secs(5)),
+            async { HandlerResponse::ok(json!({"ok": true})) },
+            &metrics,
+        )
+        .await;
+        assert!(response.is_ok());
+        assert_eq!(metrics.timeouts(), 1);
+    }
+}
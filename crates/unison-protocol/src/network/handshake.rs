@@ -0,0 +1,496 @@
+//! 接続直後のハンドシェイク
+//!
+//! QUICで接続すると、クライアントは最初に[`HANDSHAKE_METHOD`]の制御パケットで
+//! [`HandshakeRequest`]を送り、サーバーは同じストリームで[`HandshakeResponse`]を返します。
+//! 互いのプロトコルバージョン・スキーマのハッシュ・対応機能を交換し、
+//!
+//! - プロトコルバージョンのメジャー番号が異なる
+//! - 双方がスキーマの指紋（[`ParsedSchema::fingerprint`]）を指定していて、値が異なる
+//!
+//! 場合は接続を拒否します。サーバーは`ProtocolError::INCOMPATIBLE`のエラーを返し、
+//! クライアントは[`HANDSHAKE_CLOSE_CODE`]で接続を閉じて[`HandshakeError`]を返します。
+//! スキーマが異なる場合の[`HandshakeError::SchemaMismatch`]には、クライアントのスキーマにあって
+//! サーバーのスキーマにないメソッドが含まれます（古いスキーマでビルドしたクライアントが
+//! 実行時に`HandlerNotFound`を受け取る前に気付けるように）。
+//!
+//! クライアントはロケール（[`LOCALE_METADATA_KEY`]）などの接続単位のメタデータも送り、
+//! サーバーは[`Capabilities::metadata`]として保持します。
+//!
+//! 取り決めた内容は[`Capabilities`]として、クライアントでは
+//! [`ProtocolClient::capabilities`](super::ProtocolClient::capabilities)、サーバーでは
+//! [`ProtocolServer::capabilities`](super::ProtocolServer::capabilities)で参照できます。
+
+use anyhow::{Context, Result};
+use quinn::{Connection, SendStream};
+use std::collections::HashMap;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::debug;
+
+use super::heartbeat::control_frame;
+use super::i18n::LOCALE_METADATA_KEY;
+use super::quic::{FrameReader, write_stream_frame};
+use super::{MessageType, ProtocolError, ProtocolMessage};
+use crate::core::{HandshakeRequest, HandshakeResponse};
+use crate::parser::ParsedSchema;
+
+/// ハンドシェイクの制御パケットのメソッド名
+pub const HANDSHAKE_METHOD: &str = "unison.handshake";
+
+/// 互換性のない相手との接続を閉じるQUICのアプリケーションエラーコード
+pub const HANDSHAKE_CLOSE_CODE: u32 = 0x4853;
+
+/// このクレートが話すプロトコルのバージョン
+pub const PROTOCOL_VERSION: &str = "1.0.0";
+
+/// 応答を待つ期間
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 対応機能の名前
+pub mod feature {
+    /// 大きなペイロードのzstd圧縮
+    pub const COMPRESSION: &str = "compression";
+    /// 長さプレフィックス付きのバイナリフレーム（1つのストリームで複数のフレーム）
+    pub const BINARY_FRAMING: &str = "binary_framing";
+    /// 拡張領域を持つv2ヘッダー（期限など）
+    pub const HEADER_V2: &str = "header_v2";
+    /// ハートビートのping/pong
+    pub const HEARTBEAT: &str = "heartbeat";
+    /// 回線品質に応じた圧縮の切り替え
+    pub const ADAPTIVE_ENCODING: &str = "adaptive_encoding";
+    /// 双方向ストリーム（SystemStream）
+    pub const SYSTEM_STREAM: &str = "system_stream";
+
+    /// このクレートが対応している機能
+    pub const ALL: &[&str] = &[
+        COMPRESSION,
+        BINARY_FRAMING,
+        HEADER_V2,
+        HEARTBEAT,
+        ADAPTIVE_ENCODING,
+        SYSTEM_STREAM,
+    ];
+}
+
+/// ハンドシェイクが失敗した理由
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    #[error("Incompatible protocol version: local {local}, peer {peer}")]
+    IncompatibleVersion { local: String, peer: String },
+    /// クライアント（`expected`）とサーバー（`actual`）のスキーマの指紋が異なる
+    #[error(
+        "Schema mismatch: expected {expected}, actual {actual} (missing methods: {missing_methods:?})"
+    )]
+    SchemaMismatch {
+        expected: String,
+        actual: String,
+        /// クライアントのスキーマにあってサーバーのスキーマにないメソッド
+        missing_methods: Vec<String>,
+    },
+    /// サーバーが接続を拒否した
+    #[error("Handshake rejected by server: {0}")]
+    Rejected(ProtocolError),
+    #[error("Invalid handshake reply: {0}")]
+    InvalidReply(String),
+}
+
+impl From<HandshakeError> for ProtocolError {
+    fn from(error: HandshakeError) -> Self {
+        let details = match &error {
+            HandshakeError::IncompatibleVersion { local, peer } => {
+                serde_json::json!({ "reason": "version", "server": local, "client": peer })
+            }
+            HandshakeError::SchemaMismatch {
+                expected,
+                actual,
+                missing_methods,
+            } => serde_json::json!({
+                "reason": "schema",
+                "expected": expected,
+                "actual": actual,
+                "missing_methods": missing_methods,
+            }),
+            HandshakeError::Rejected(error) => return error.clone(),
+            HandshakeError::InvalidReply(_) => serde_json::json!({ "reason": "invalid" }),
+        };
+        ProtocolError::new(ProtocolError::INCOMPATIBLE, error.to_string()).with_details(details)
+    }
+}
+
+impl HandshakeError {
+    /// サーバーが返した拒否のエラーを、クライアントから見た理由に戻す
+    fn from_rejection(error: ProtocolError) -> Self {
+        let details = error.details.clone().unwrap_or_default();
+        let text = |key: &str| details[key].as_str().unwrap_or_default().to_string();
+        match details["reason"].as_str() {
+            Some("version") => Self::IncompatibleVersion {
+                local: text("client"),
+                peer: text("server"),
+            },
+            Some("schema") => Self::SchemaMismatch {
+                expected: text("expected"),
+                actual: text("actual"),
+                missing_methods: serde_json::from_value(details["missing_methods"].clone())
+                    .unwrap_or_default(),
+            },
+            _ => Self::Rejected(error),
+        }
+    }
+}
+
+/// ハンドシェイクで照合するスキーマ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaIdentity {
+    /// スキーマの指紋
+    pub fingerprint: String,
+    /// スキーマで定義されたメソッド・ストリームの名前（不一致時の診断に使う）
+    pub methods: Vec<String>,
+}
+
+impl SchemaIdentity {
+    /// 読み込んだスキーマの指紋とメソッド
+    pub fn from_schema(schema: &ParsedSchema) -> Self {
+        Self {
+            fingerprint: schema.fingerprint(),
+            methods: schema.method_names().map(str::to_string).collect(),
+        }
+    }
+
+    /// 指紋のみ（不一致時のメソッドの差分は分からない）
+    pub fn from_fingerprint(fingerprint: impl Into<String>) -> Self {
+        Self {
+            fingerprint: fingerprint.into(),
+            methods: Vec::new(),
+        }
+    }
+
+    fn from_wire(fingerprint: Option<String>, methods: Vec<String>) -> Option<Self> {
+        fingerprint.map(|fingerprint| Self {
+            fingerprint,
+            methods,
+        })
+    }
+}
+
+/// ハンドシェイクで取り決めた相手の情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// 相手のプロトコルバージョン
+    pub protocol_version: String,
+    /// 相手のアプリケーション名
+    pub peer_name: String,
+    /// 相手のアプリケーションのバージョン（クライアントのみ送る）
+    pub peer_version: Option<String>,
+    /// サーバーが割り当てたセッションID
+    pub session_id: String,
+    /// 双方が対応している機能
+    pub features: Vec<String>,
+    /// 相手のスキーマの指紋
+    pub schema_hash: Option<String>,
+    /// サーバーがハートビートを送る間隔
+    pub heartbeat_interval: Option<Duration>,
+    /// 相手が送った接続単位のメタデータ（クライアントのみ送る）
+    pub metadata: HashMap<String, String>,
+}
+
+impl Capabilities {
+    /// 双方が対応している機能か
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// 相手が希望するロケール
+    pub fn locale(&self) -> Option<&str> {
+        self.metadata.get(LOCALE_METADATA_KEY).map(String::as_str)
+    }
+}
+
+/// プロトコルバージョンのメジャー番号
+fn major(version: &str) -> &str {
+    version.split('.').next().unwrap_or(version)
+}
+
+/// 相手のプロトコルバージョンが自分と互換か
+fn check_version(peer_version: &str) -> Result<(), HandshakeError> {
+    if major(peer_version) != major(PROTOCOL_VERSION) {
+        return Err(HandshakeError::IncompatibleVersion {
+            local: PROTOCOL_VERSION.to_string(),
+            peer: peer_version.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// 双方がスキーマを指定している場合、指紋が一致するか
+fn check_schema(
+    client: Option<&SchemaIdentity>,
+    server: Option<&SchemaIdentity>,
+) -> Result<(), HandshakeError> {
+    let (Some(client), Some(server)) = (client, server) else {
+        return Ok(());
+    };
+    if client.fingerprint == server.fingerprint {
+        return Ok(());
+    }
+    Err(HandshakeError::SchemaMismatch {
+        expected: client.fingerprint.clone(),
+        actual: server.fingerprint.clone(),
+        missing_methods: client
+            .methods
+            .iter()
+            .filter(|method| !server.methods.contains(method))
+            .cloned()
+            .collect(),
+    })
+}
+
+/// 自分の対応機能のうち、相手も対応しているもの
+fn common_features(peer: &[String]) -> Vec<String> {
+    feature::ALL
+        .iter()
+        .filter(|feature| peer.iter().any(|p| p == *feature))
+        .map(|feature| feature.to_string())
+        .collect()
+}
+
+/// このクレートで送るハンドシェイクの要求
+pub(crate) fn client_request(schema: Option<&SchemaIdentity>) -> HandshakeRequest {
+    HandshakeRequest {
+        protocol_version: PROTOCOL_VERSION.to_string(),
+        client_name: env!("CARGO_PKG_NAME").to_string(),
+        client_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        supported_features: feature::ALL.iter().map(|f| f.to_string()).collect(),
+        schema_hash: schema.map(|schema| schema.fingerprint.clone()),
+        schema_methods: schema
+            .map(|schema| schema.methods.clone())
+            .unwrap_or_default(),
+        metadata: HashMap::new(),
+    }
+}
+
+/// クライアントの要求を検証し、応答とサーバー側から見た相手の情報を返す
+pub(crate) fn accept(
+    request: HandshakeRequest,
+    schema: Option<&SchemaIdentity>,
+    heartbeat_interval: Option<Duration>,
+) -> Result<(HandshakeResponse, Capabilities), HandshakeError> {
+    check_version(&request.protocol_version)?;
+    let client_schema =
+        SchemaIdentity::from_wire(request.schema_hash.clone(), request.schema_methods.clone());
+    check_schema(client_schema.as_ref(), schema)?;
+    let features = common_features(&request.supported_features);
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let response = HandshakeResponse {
+        server_version: PROTOCOL_VERSION.to_string(),
+        server_name: env!("CARGO_PKG_NAME").to_string(),
+        supported_features: feature::ALL.iter().map(|f| f.to_string()).collect(),
+        session_id: session_id.clone(),
+        heartbeat_interval: heartbeat_interval.map(|interval| interval.as_millis() as u64),
+        schema_hash: schema.map(|schema| schema.fingerprint.clone()),
+        schema_methods: schema
+            .map(|schema| schema.methods.clone())
+            .unwrap_or_default(),
+    };
+    let capabilities = Capabilities {
+        protocol_version: request.protocol_version,
+        peer_name: request.client_name,
+        peer_version: request.client_version,
+        session_id,
+        features,
+        schema_hash: request.schema_hash,
+        heartbeat_interval,
+        metadata: request.metadata,
+    };
+    Ok((response, capabilities))
+}
+
+/// サーバーの応答を検証し、クライアント側から見た相手の情報を返す
+pub(crate) fn complete(
+    response: HandshakeResponse,
+    schema: Option<&SchemaIdentity>,
+) -> Result<Capabilities, HandshakeError> {
+    check_version(&response.server_version)?;
+    let server_schema = SchemaIdentity::from_wire(
+        response.schema_hash.clone(),
+        response.schema_methods.clone(),
+    );
+    check_schema(schema, server_schema.as_ref())?;
+    Ok(Capabilities {
+        features: common_features(&response.supported_features),
+        protocol_version: response.server_version,
+        peer_name: response.server_name,
+        peer_version: None,
+        session_id: response.session_id,
+        schema_hash: response.schema_hash,
+        heartbeat_interval: response.heartbeat_interval.map(Duration::from_millis),
+        metadata: HashMap::new(),
+    })
+}
+
+/// 受信したメッセージがハンドシェイクか
+pub(crate) fn is_handshake(message: &ProtocolMessage) -> bool {
+    message.method == HANDSHAKE_METHOD
+        && matches!(message.msg_type, MessageType::Event | MessageType::Error)
+}
+
+/// ハンドシェイクの応答（またはエラー）を同じストリームで返す
+pub(crate) async fn answer(
+    mut send_stream: SendStream,
+    request: &ProtocolMessage,
+    reply: Result<HandshakeResponse, ProtocolError>,
+) {
+    let message = match reply {
+        Ok(response) => serde_json::to_value(response).map(|payload| (MessageType::Event, payload)),
+        Err(error) => serde_json::to_value(error).map(|payload| (MessageType::Error, payload)),
+    }
+    .map_err(Into::into)
+    .and_then(|(msg_type, payload)| {
+        ProtocolMessage::new_with_json(request.id, HANDSHAKE_METHOD.to_string(), msg_type, payload)
+    });
+    let sent = match message.map_err(anyhow::Error::from).and_then(control_frame) {
+        Ok(frame) => write_stream_frame(&mut send_stream, &frame, true)
+            .await
+            .map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        debug!("Failed to answer handshake: {:#}", e);
+    }
+    let _ = send_stream.finish();
+}
+
+/// ハンドシェイクを行い、互換性がなければ接続を閉じる
+///
+/// 互換性がない場合は[`HandshakeError`]を（`anyhow::Error`から取り出せる形で）返します。
+pub(crate) async fn perform(
+    connection: &Connection,
+    schema: Option<&SchemaIdentity>,
+    metadata: &HashMap<String, String>,
+) -> Result<Capabilities> {
+    let result = tokio::time::timeout(HANDSHAKE_TIMEOUT, exchange(connection, schema, metadata))
+        .await
+        .context("Handshake timed out")
+        .and_then(|result| result);
+    if result.is_err() {
+        connection.close(
+            quinn::VarInt::from_u32(HANDSHAKE_CLOSE_CODE),
+            b"handshake failed",
+        );
+    }
+    result
+}
+
+async fn exchange(
+    connection: &Connection,
+    schema: Option<&SchemaIdentity>,
+    metadata: &HashMap<String, String>,
+) -> Result<Capabilities> {
+    let (mut send_stream, recv_stream) = connection.open_bi().await?;
+    let mut request = client_request(schema);
+    request.metadata = metadata.clone();
+    let message = ProtocolMessage::new_with_json(
+        0,
+        HANDSHAKE_METHOD.to_string(),
+        MessageType::Event,
+        serde_json::to_value(request)?,
+    )?;
+    write_stream_frame(&mut send_stream, &control_frame(message)?, true).await?;
+    send_stream.finish()?;
+
+    let reply = FrameReader::new(recv_stream)
+        .next_message()
+        .await?
+        .context("Stream closed without a handshake reply")?;
+    let invalid = |e: &dyn std::fmt::Display| HandshakeError::InvalidReply(e.to_string());
+    if !is_handshake(&reply) {
+        return Err(invalid(&reply.method).into());
+    }
+    if reply.msg_type == MessageType::Error {
+        let error: ProtocolError = serde_json::from_str(&reply.payload).map_err(|e| invalid(&e))?;
+        return Err(HandshakeError::from_rejection(error).into());
+    }
+    let response: HandshakeResponse =
+        serde_json::from_str(&reply.payload).map_err(|e| invalid(&e))?;
+    Ok(complete(response, schema)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(fingerprint: &str, methods: &[&str]) -> SchemaIdentity {
+        SchemaIdentity {
+            fingerprint: fingerprint.into(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_accept_negotiates_common_features() {
+        let abc = SchemaIdentity::from_fingerprint("abc");
+        let mut request = client_request(Some(&abc));
+        request.supported_features = vec![
+            feature::HEARTBEAT.into(),
+            "quantum".into(),
+            feature::COMPRESSION.into(),
+        ];
+        let (response, server_side) =
+            accept(request, Some(&abc), Some(Duration::from_secs(15))).unwrap();
+        assert_eq!(server_side.features, ["compression", "heartbeat"]);
+        assert!(server_side.supports(feature::HEARTBEAT));
+        assert!(!server_side.supports(feature::SYSTEM_STREAM));
+        assert_eq!(server_side.peer_name, "unison");
+
+        let client_side = complete(response, Some(&abc)).unwrap();
+        assert_eq!(client_side.session_id, server_side.session_id);
+        assert_eq!(client_side.features.len(), feature::ALL.len());
+        assert_eq!(
+            client_side.heartbeat_interval,
+            Some(Duration::from_secs(15))
+        );
+    }
+
+    #[test]
+    fn test_rejects_incompatible_peers() {
+        let mut request = client_request(None);
+        request.protocol_version = "2.1.0".into();
+        let error = accept(request, None, None).unwrap_err();
+        assert_eq!(
+            error,
+            HandshakeError::IncompatibleVersion {
+                local: PROTOCOL_VERSION.into(),
+                peer: "2.1.0".into(),
+            }
+        );
+        let details = ProtocolError::from(error).details.unwrap();
+        assert_eq!(details["reason"], "version");
+
+        // マイナーバージョンの違いや指紋の片方だけの指定は受け入れる
+        let old = SchemaIdentity::from_fingerprint("old");
+        let mut request = client_request(Some(&old));
+        request.protocol_version = "1.4.2".into();
+        assert!(accept(request, None, None).is_ok());
+    }
+
+    #[test]
+    fn test_schema_mismatch_lists_missing_methods() {
+        let client = schema("old", &["greet", "legacy_ping", "farewell"]);
+        let server = schema("new", &["greet", "farewell", "status"]);
+        let error = accept(client_request(Some(&client)), Some(&server), None).unwrap_err();
+        let expected = HandshakeError::SchemaMismatch {
+            expected: "old".into(),
+            actual: "new".into(),
+            missing_methods: vec!["legacy_ping".into()],
+        };
+        assert_eq!(error, expected);
+
+        // サーバーの拒否はクライアントで同じ理由に戻る
+        let rejection = ProtocolError::from(error);
+        assert_eq!(rejection.code, ProtocolError::INCOMPATIBLE);
+        assert_eq!(HandshakeError::from_rejection(rejection), expected);
+
+        // クライアント側の照合でも同じ差分になる
+        let (response, _) = accept(client_request(None), Some(&server), None).unwrap();
+        assert_eq!(complete(response, Some(&client)), Err(expected));
+    }
+}
@@ -0,0 +1,172 @@
+//! Happy Eyeballs方式の接続レース（RFC 8305）
+//!
+//! ホスト名が複数のアドレスに解決された場合、優先するアドレスファミリーから交互に並べ、
+//! 一定の間隔をずらしながら接続を開始します。最初に成功した接続を採用し、
+//! 残りの試行は破棄します。IPv4/IPv6混在環境や、一部の経路でQUICが遮断されている
+//! 環境での接続待ち時間を短縮します。
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// 接続レースの設定
+#[derive(Debug, Clone)]
+pub struct HappyEyeballsConfig {
+    /// 次の接続試行を開始するまでの間隔（先行する試行が失敗した場合は即座に開始）
+    pub attempt_delay: Duration,
+    /// IPv6アドレスを優先する
+    pub prefer_ipv6: bool,
+}
+
+impl Default for HappyEyeballsConfig {
+    fn default() -> Self {
+        Self {
+            attempt_delay: Duration::from_millis(250),
+            prefer_ipv6: true,
+        }
+    }
+}
+
+/// 優先ファミリーから交互になるようにアドレスを並べ替える
+///
+/// 同じファミリー内の順序（リゾルバーの返した順）は保たれます。
+pub fn sort_addresses(addrs: Vec<SocketAddr>, prefer_ipv6: bool) -> Vec<SocketAddr> {
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == prefer_ipv6);
+
+    let mut sorted = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (first, second) => sorted.extend(first.into_iter().chain(second)),
+        }
+    }
+    sorted
+}
+
+/// 候補への接続をずらして開始し、最初に成功した結果を返す
+///
+/// 全ての候補が失敗した場合は最後のエラーを返します。
+pub async fn race<C, T, F, Fut>(
+    candidates: Vec<C>,
+    attempt_delay: Duration,
+    mut connect: F,
+) -> Result<(C, T)>
+where
+    C: Clone,
+    F: FnMut(C) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let total = candidates.len();
+    let mut pending = candidates.into_iter();
+    let mut start = |candidate: C| {
+        let attempt = connect(candidate.clone());
+        async move { (candidate, attempt.await) }
+    };
+
+    let mut attempts = FuturesUnordered::new();
+    match pending.next() {
+        Some(candidate) => attempts.push(start(candidate)),
+        None => return Err(anyhow::anyhow!("No connection candidates")),
+    }
+
+    let mut last_error = None;
+    loop {
+        let has_pending = pending.len() > 0;
+        tokio::select! {
+            finished = attempts.next() => match finished {
+                Some((candidate, Ok(value))) => return Ok((candidate, value)),
+                Some((_, Err(e))) => {
+                    last_error = Some(e);
+                    // 失敗した場合は待たずに次の候補を開始
+                    if let Some(candidate) = pending.next() {
+                        attempts.push(start(candidate));
+                    }
+                }
+                None => break,
+            },
+            _ = tokio::time::sleep(attempt_delay), if has_pending => {
+                if let Some(candidate) = pending.next() {
+                    attempts.push(start(candidate));
+                }
+            }
+        }
+    }
+
+    let last_error = last_error.unwrap_or_else(|| anyhow::anyhow!("No connection candidates"));
+    Err(last_error.context(format!("All {} connection attempts failed", total)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_sort_addresses_interleaves_families() {
+        let addrs: Vec<SocketAddr> = ["10.0.0.1:80", "10.0.0.2:80", "[::1]:80", "[::2]:80"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let sorted: Vec<String> = sort_addresses(addrs.clone(), true)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            sorted,
+            vec!["[::1]:80", "10.0.0.1:80", "[::2]:80", "10.0.0.2:80"]
+        );
+        assert_eq!(sort_addresses(addrs, false)[0].to_string(), "10.0.0.1:80");
+    }
+
+    #[tokio::test]
+    async fn test_race_prefers_first_success_and_staggers() {
+        let started = Instant::now();
+        // 1番目の候補は応答が遅く、2番目は遅延後に開始されて先に成功する
+        let (winner, value) = race(
+            vec![1u32, 2, 3],
+            Duration::from_millis(20),
+            |n| async move {
+                match n {
+                    1 => tokio::time::sleep(Duration::from_secs(5)).await,
+                    _ => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+                Ok(n * 10)
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!((winner, value), (2, 20));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_race_fails_fast_to_next_and_reports_last_error() {
+        let started = Instant::now();
+        let (winner, _) = race(vec!["a", "b"], Duration::from_secs(5), |c| async move {
+            if c == "a" {
+                Err(anyhow::anyhow!("refused"))
+            } else {
+                Ok(())
+            }
+        })
+        .await
+        .unwrap();
+        // 失敗した場合は遅延を待たずに次の候補を開始する
+        assert_eq!(winner, "b");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let error = race(vec!["a", "b"], Duration::from_millis(1), |c| async move {
+            Err::<(), _>(anyhow::anyhow!("refused {}", c))
+        })
+        .await
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("All 2 connection attempts failed"));
+    }
+}
@@ -0,0 +1,266 @@
+//! ハートビートによる相手の死活監視
+//!
+//! [`HeartbeatConfig`]を指定すると、クライアントとサーバーはそれぞれ接続ごとに監視タスクを起動し、
+//! `interval`ごとに新しい双方向ストリームで[`HEARTBEAT_METHOD`]の制御パケット（ping）を送ります。
+//! 受け取った側は同じストリームで同じIDのメッセージを返します（pong）。
+//!
+//! `interval`以内にpongが届かない回数が`miss_threshold`回続くと、相手が応答しなくなったとみなし、
+//! [`HEARTBEAT_CLOSE_CODE`]で接続を閉じます。クライアントでは接続状態がIdleへ遷移して
+//! 状態変化の通知や自動再接続が動き、その接続の[`UnisonStream`](super::UnisonStream)は
+//! 非アクティブになります。
+//!
+//! pingのペイロードは[`PingRequest`]、pongのペイロードは[`PongResponse`]です。
+//! 同じ経路の往復時間から[`LatencyStats`]を求められます
+//! （[`ProtocolClient::measure_latency`](super::ProtocolClient::measure_latency)）。
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use quinn::{Connection, SendStream};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use super::quic::{FrameReader, write_stream_frame};
+use super::{MessageType, ProtocolMessage};
+use crate::core::{PingRequest, PongResponse};
+use crate::packet::{PacketType, RkyvPayload, UnisonPacketBuilder};
+
+/// ハートビートの制御パケットのメソッド名
+pub const HEARTBEAT_METHOD: &str = "unison.heartbeat";
+
+/// 相手が応答しなくなった接続を閉じるQUICのアプリケーションエラーコード
+pub const HEARTBEAT_CLOSE_CODE: u32 = 0x4842;
+
+/// ハートビートの設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    /// pingを送る間隔（pongを待つ期間も同じ）
+    #[serde(with = "crate::config::duration")]
+    pub interval: Duration,
+    /// 接続を閉じるまでに許す、連続してpongが届かなかった回数
+    pub miss_threshold: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            miss_threshold: 3,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// 相手が応答しなくなってから接続を閉じるまでのおおよその時間
+    pub fn dead_after(&self) -> Duration {
+        self.interval * self.miss_threshold.max(1)
+    }
+}
+
+/// 往復時間の統計
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// 計測した回数
+    pub samples: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    /// 95パーセンタイル（最近傍順位）
+    pub p95: Duration,
+    pub max: Duration,
+    /// 連続する計測値の差の平均
+    pub jitter: Duration,
+}
+
+impl LatencyStats {
+    /// 計測値から統計を求める（計測値がなければ`None`）
+    pub fn from_samples(samples: &[Duration]) -> Option<Self> {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let (&min, &max) = (sorted.first()?, sorted.last()?);
+        let count = sorted.len() as u32;
+        let rank = (sorted.len() * 95).div_ceil(100).max(1);
+        let jitter = match samples.len() {
+            0 | 1 => Duration::ZERO,
+            n => {
+                let total: Duration = samples
+                    .windows(2)
+                    .map(|pair| pair[0].abs_diff(pair[1]))
+                    .sum();
+                total / (n as u32 - 1)
+            }
+        };
+        Some(Self {
+            samples: sorted.len(),
+            min,
+            avg: sorted.iter().sum::<Duration>() / count,
+            p95: sorted[rank - 1],
+            max,
+            jitter,
+        })
+    }
+}
+
+/// 受信したメッセージがハートビートか
+pub(crate) fn is_heartbeat(message: &ProtocolMessage) -> bool {
+    message.msg_type == MessageType::Event && message.method == HEARTBEAT_METHOD
+}
+
+/// 制御メッセージを制御パケットのバイト列にする
+pub(super) fn control_frame(message: ProtocolMessage) -> Result<bytes::Bytes> {
+    let frame = UnisonPacketBuilder::new()
+        .packet_type(PacketType::Control)
+        .build(RkyvPayload::new(message))?;
+    Ok(frame.to_bytes())
+}
+
+/// pingに対するpong（ペイロードが[`PingRequest`]でなければそのまま返す）
+fn pong_message(ping: ProtocolMessage) -> ProtocolMessage {
+    let Ok(request) = serde_json::from_str::<PingRequest>(&ping.payload) else {
+        return ping;
+    };
+    let pong = PongResponse {
+        timestamp: request.timestamp,
+        payload: request.payload,
+        server_time: Utc::now(),
+    };
+    serde_json::to_value(pong)
+        .map_err(Into::into)
+        .and_then(|payload| {
+            ProtocolMessage::new_with_json(ping.id, ping.method.clone(), ping.msg_type, payload)
+        })
+        .unwrap_or(ping)
+}
+
+/// 受信したpingに同じストリームでpongを返す
+pub(crate) async fn answer(mut send_stream: SendStream, ping: ProtocolMessage) {
+    let sent = match control_frame(pong_message(ping)) {
+        Ok(frame) => write_stream_frame(&mut send_stream, &frame, true)
+            .await
+            .map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        debug!("Failed to answer heartbeat: {:#}", e);
+    }
+    let _ = send_stream.finish();
+}
+
+/// pingを送り、pongを受け取るまで待機して往復時間を返す
+pub(crate) async fn ping(connection: &Connection, seq: u64) -> Result<Duration> {
+    let started = Instant::now();
+    let (mut send_stream, recv_stream) = connection.open_bi().await?;
+    let request = PingRequest {
+        timestamp: Utc::now(),
+        payload: None,
+    };
+    let message = ProtocolMessage::new_with_json(
+        seq,
+        HEARTBEAT_METHOD.to_string(),
+        MessageType::Event,
+        serde_json::to_value(request)?,
+    )?;
+    write_stream_frame(&mut send_stream, &control_frame(message)?, true).await?;
+    send_stream.finish()?;
+
+    let pong = FrameReader::new(recv_stream)
+        .next_message()
+        .await?
+        .context("Stream closed without a heartbeat reply")?;
+    if !is_heartbeat(&pong) || pong.id != seq {
+        anyhow::bail!("Unexpected heartbeat reply: {} {}", pong.method, pong.id);
+    }
+    Ok(started.elapsed())
+}
+
+/// 接続が閉じるまでpingを送り続け、相手が応答しなくなったら接続を閉じる
+pub(crate) async fn monitor(connection: Connection, config: HeartbeatConfig) {
+    let mut ticker = tokio::time::interval(config.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticker.tick().await;
+
+    let mut misses = 0;
+    for seq in 1.. {
+        tokio::select! {
+            _ = connection.closed() => return,
+            _ = ticker.tick() => {}
+        }
+        match tokio::time::timeout(config.interval, ping(&connection, seq)).await {
+            Ok(Ok(_)) => misses = 0,
+            Ok(Err(e)) => {
+                debug!("Heartbeat failed: {:#}", e);
+                misses += 1;
+            }
+            Err(_) => misses += 1,
+        }
+        if misses >= config.miss_threshold.max(1) {
+            warn!(
+                "Peer {} missed {} heartbeats, closing connection",
+                connection.remote_address(),
+                misses
+            );
+            connection.close(
+                quinn::VarInt::from_u32(HEARTBEAT_CLOSE_CODE),
+                b"heartbeat timeout",
+            );
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&v| Duration::from_millis(v)).collect()
+    }
+
+    #[test]
+    fn test_latency_stats_from_samples() {
+        let stats = LatencyStats::from_samples(&ms(&[10, 30, 20, 40])).unwrap();
+        assert_eq!(stats.samples, 4);
+        assert_eq!(stats.min, Duration::from_millis(10));
+        assert_eq!(stats.avg, Duration::from_millis(25));
+        assert_eq!(stats.p95, Duration::from_millis(40));
+        assert_eq!(stats.max, Duration::from_millis(40));
+        // |30-10| + |20-30| + |40-20| = 50 を3で割る
+        assert_eq!(stats.jitter, Duration::from_millis(50) / 3);
+
+        assert_eq!(LatencyStats::from_samples(&[]), None);
+        let single = LatencyStats::from_samples(&ms(&[5])).unwrap();
+        assert_eq!(
+            (single.p95, single.jitter),
+            (Duration::from_millis(5), Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_p95_uses_nearest_rank() {
+        let samples: Vec<u64> = (1..=100).collect();
+        let stats = LatencyStats::from_samples(&ms(&samples)).unwrap();
+        assert_eq!(stats.p95, Duration::from_millis(95));
+    }
+
+    #[test]
+    fn test_pong_echoes_ping_timestamp() {
+        let timestamp = Utc::now();
+        let request = PingRequest {
+            timestamp,
+            payload: Some("probe".into()),
+        };
+        let ping = ProtocolMessage::new_with_json(
+            7,
+            HEARTBEAT_METHOD.into(),
+            MessageType::Event,
+            serde_json::to_value(request).unwrap(),
+        )
+        .unwrap();
+
+        let pong = pong_message(ping);
+        assert!(is_heartbeat(&pong));
+        assert_eq!(pong.id, 7);
+        let response: PongResponse = serde_json::from_str(&pong.payload).unwrap();
+        assert_eq!(response.timestamp, timestamp);
+        assert_eq!(response.payload.as_deref(), Some("probe"));
+    }
+}
@@ -0,0 +1,296 @@
+//! 接続ごとの直近のフレームの記録
+//!
+//! [`FrameHistory`]は接続ごとに直近N件のリクエスト・レスポンスを、ペイロードを切り詰めて
+//! リングバッファに保持します。エラーが起きたときに直前のやり取りを確認するためのもので、
+//! 全体をキャプチャするより負荷を抑えられます。
+//!
+//! [`ProtocolServer::with_frame_history`](super::ProtocolServer::with_frame_history)で有効にすると、
+//! 呼び出しのリクエストとレスポンスを記録し、組み込みメソッド[`FRAME_HISTORY_METHOD`]で
+//! 取得できます（テナントに紐づく接続からは使用できません）。記録にはメタデータ（認証情報を
+//! 含みうる）が入るため、既定では呼び出した接続の記録のみを返します。他の接続の記録は
+//! [`ProtocolServer::with_frame_history_authorizer`](super::ProtocolServer::with_frame_history_authorizer)
+//! で許可した接続のみ取得できます。
+//! `log_on_error`が有効な場合、エラーを返したときにその接続の記録をログに出力します。
+//! 接続が閉じると記録は破棄されます。
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::broadcast::ConnectionId;
+use super::handler::HandlerResponse;
+use super::handshake::Capabilities;
+use super::{MessageType, ProtocolMessage};
+
+/// 記録したフレームを返す組み込みメソッド（`{"connection_id": n}`で接続を指定）
+pub const FRAME_HISTORY_METHOD: &str = "unison.admin.frames";
+
+/// すべての接続の記録の取得を許可するか判定する関数
+///
+/// 呼び出した接続のIDと、ハンドシェイクを終えていればその相手の情報を受け取ります。
+pub type FrameHistoryAuthorizer =
+    Arc<dyn Fn(ConnectionId, Option<&Capabilities>) -> bool + Send + Sync>;
+
+/// フレームの記録の設定
+#[derive(Debug, Clone)]
+pub struct FrameHistoryConfig {
+    /// 接続ごとに保持するフレームの数
+    pub capacity: usize,
+    /// 保持するペイロード・メタデータそれぞれの最大バイト数（超えた分は切り詰める）
+    pub max_payload_bytes: usize,
+    /// エラーを返したときに接続の記録をログに出力するか
+    pub log_on_error: bool,
+}
+
+impl Default for FrameHistoryConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 32,
+            max_payload_bytes: 256,
+            log_on_error: true,
+        }
+    }
+}
+
+/// フレームの向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameDirection {
+    Inbound,
+    Outbound,
+}
+
+/// 記録したフレーム
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub direction: FrameDirection,
+    /// 記録した時刻（UNIXエポックからのミリ秒）
+    pub timestamp_ms: u64,
+    pub id: u64,
+    pub method: String,
+    pub msg_type: MessageType,
+    /// 切り詰めたメタデータ（JSONオブジェクトの文字列）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub metadata: String,
+    /// メタデータを切り詰めたか
+    #[serde(default)]
+    pub metadata_truncated: bool,
+    /// 切り詰めたペイロード
+    pub payload: String,
+    /// 元のペイロードのバイト数
+    pub payload_len: usize,
+    pub truncated: bool,
+}
+
+/// 接続ごとの直近のフレームのリングバッファ
+#[derive(Debug, Clone, Default)]
+pub struct FrameHistory {
+    config: FrameHistoryConfig,
+    connections: Arc<Mutex<HashMap<ConnectionId, VecDeque<RecordedFrame>>>>,
+}
+
+impl FrameHistory {
+    pub fn new(config: FrameHistoryConfig) -> Self {
+        Self {
+            config,
+            connections: Arc::default(),
+        }
+    }
+
+    pub fn config(&self) -> &FrameHistoryConfig {
+        &self.config
+    }
+
+    /// 送受信したメッセージを記録
+    pub fn record(
+        &self,
+        connection_id: ConnectionId,
+        direction: FrameDirection,
+        message: &ProtocolMessage,
+    ) {
+        self.push(
+            connection_id,
+            direction,
+            message.id,
+            &message.method,
+            message.msg_type,
+            message.metadata.clone(),
+            &message.payload,
+        );
+    }
+
+    /// リクエストに対するハンドラーのレスポンスを送信として記録
+    pub fn record_response(
+        &self,
+        connection_id: ConnectionId,
+        request: &ProtocolMessage,
+        response: &HandlerResponse,
+    ) {
+        let (msg_type, payload) = match &response.outcome {
+            Ok(payload) => (MessageType::Response, payload.to_string()),
+            Err(error) => (
+                MessageType::Error,
+                serde_json::to_string(error).unwrap_or_default(),
+            ),
+        };
+        let metadata = if response.metadata.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(&response.metadata).unwrap_or_default()
+        };
+        self.push(
+            connection_id,
+            FrameDirection::Outbound,
+            request.id,
+            &request.method,
+            msg_type,
+            metadata,
+            &payload,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push(
+        &self,
+        connection_id: ConnectionId,
+        direction: FrameDirection,
+        id: u64,
+        method: &str,
+        msg_type: MessageType,
+        metadata: String,
+        payload: &str,
+    ) {
+        if self.config.capacity == 0 {
+            return;
+        }
+        let (kept, truncated) = truncate(payload, self.config.max_payload_bytes);
+        let (metadata, metadata_truncated) = truncate(&metadata, self.config.max_payload_bytes);
+        let frame = RecordedFrame {
+            direction,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            id,
+            method: method.to_string(),
+            msg_type,
+            metadata: metadata.to_string(),
+            metadata_truncated,
+            payload: kept.to_string(),
+            payload_len: payload.len(),
+            truncated,
+        };
+        let mut connections = self.connections.lock().unwrap();
+        let frames = connections.entry(connection_id).or_default();
+        if frames.len() == self.config.capacity {
+            frames.pop_front();
+        }
+        frames.push_back(frame);
+    }
+
+    /// 接続の記録（古い順）
+    pub fn frames(&self, connection_id: ConnectionId) -> Vec<RecordedFrame> {
+        self.connections
+            .lock()
+            .unwrap()
+            .get(&connection_id)
+            .map(|frames| frames.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// すべての接続の記録
+    pub fn dump(&self) -> HashMap<ConnectionId, Vec<RecordedFrame>> {
+        self.connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, frames)| (*id, frames.iter().cloned().collect()))
+            .collect()
+    }
+
+    /// 閉じた接続の記録を破棄
+    pub fn remove(&self, connection_id: ConnectionId) {
+        self.connections.lock().unwrap().remove(&connection_id);
+    }
+}
+
+/// 文字の境界で`max`バイト以下に切り詰める
+fn truncate(payload: &str, max: usize) -> (&str, bool) {
+    if payload.len() <= max {
+        return (payload, false);
+    }
+    let mut end = max;
+    while !payload.is_char_boundary(end) {
+        end -= 1;
+    }
+    (&payload[..end], true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::ProtocolError;
+    use serde_json::json;
+
+    fn request(id: u64, payload: serde_json::Value) -> ProtocolMessage {
+        ProtocolMessage::new_with_json(id, "echo".into(), MessageType::Request, payload).unwrap()
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_recent_frames() {
+        let history = FrameHistory::new(FrameHistoryConfig {
+            capacity: 3,
+            max_payload_bytes: 8,
+            log_on_error: false,
+        });
+        for id in 1..=4 {
+            history.record(1, FrameDirection::Inbound, &request(id, json!(id)));
+        }
+        history.record(2, FrameDirection::Inbound, &request(9, json!("あいうえお")));
+
+        let frames = history.frames(1);
+        let ids: Vec<u64> = frames.iter().map(|frame| frame.id).collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+
+        // 文字の途中では切らない
+        let frame = &history.frames(2)[0];
+        assert!(frame.truncated);
+        assert_eq!(frame.payload, "\"あい");
+        assert_eq!(frame.payload_len, "\"あいうえお\"".len());
+
+        history.remove(1);
+        assert!(history.frames(1).is_empty());
+        assert_eq!(history.dump().len(), 1);
+    }
+
+    #[test]
+    fn test_record_response_outcome() {
+        let history = FrameHistory::default();
+        let request = request(5, json!({}));
+        let error = ProtocolError::new(ProtocolError::NOT_FOUND, "missing");
+        history.record_response(1, &request, &HandlerResponse::error(error));
+
+        let frame = &history.frames(1)[0];
+        assert_eq!(frame.direction, FrameDirection::Outbound);
+        assert_eq!(frame.msg_type, MessageType::Error);
+        assert_eq!((frame.id, frame.method.as_str()), (5, "echo"));
+        assert!(frame.payload.contains("missing"));
+    }
+
+    #[test]
+    fn test_metadata_is_truncated() {
+        let history = FrameHistory::new(FrameHistoryConfig {
+            capacity: 2,
+            max_payload_bytes: 16,
+            log_on_error: false,
+        });
+        let mut message = request(1, json!({}));
+        message.metadata = json!({ "authorization": "x".repeat(1 << 20) }).to_string();
+        history.record(1, FrameDirection::Inbound, &message);
+
+        let frame = &history.frames(1)[0];
+        assert!(frame.metadata_truncated);
+        assert_eq!(frame.metadata.len(), 16);
+        assert!(!frame.truncated);
+    }
+}
@@ -0,0 +1,172 @@
+//! エラーメッセージのカタログ
+//!
+//! [`ErrorCatalog`]はエラーコードとロケールごとに人が読むためのメッセージを保持します。
+//! [`ProtocolServer::with_error_catalog`](super::ProtocolServer::with_error_catalog)で設定すると、
+//! サーバーは呼び出しのエラーの`message`を接続のロケールに合わせて置き換えます。
+//! 機械が判定に使う`code`・`error_code`・`details`は変更しません。
+//!
+//! ロケールはリクエストのメタデータ[`LOCALE_METADATA_KEY`]、なければハンドシェイクの
+//! メタデータ（[`ProtocolClient::with_locale`](super::ProtocolClient::with_locale)）から選びます。
+//! `ja-JP`のメッセージがなければ`ja`、それもなければカタログの既定のロケールを使い、
+//! どれもなければ元のメッセージのままにします。
+//!
+//! メッセージの`{name}`はエラーの`details`の同名の値で置き換えます。
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::ProtocolError;
+
+/// ロケールを指定するメタデータのキー（ハンドシェイク・リクエストで共通）
+pub const LOCALE_METADATA_KEY: &str = "locale";
+
+/// エラーコードとロケールごとのメッセージ
+#[derive(Debug, Clone, Default)]
+pub struct ErrorCatalog {
+    /// エラーコード → 正規化したロケール → メッセージ
+    messages: HashMap<String, HashMap<String, String>>,
+    default_locale: Option<String>,
+}
+
+impl ErrorCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// エラーコードのメッセージを追加
+    pub fn with_message(
+        mut self,
+        code: impl Into<String>,
+        locale: &str,
+        message: impl Into<String>,
+    ) -> Self {
+        self.insert(code, locale, message);
+        self
+    }
+
+    /// エラーコードのメッセージを追加（同じロケールのメッセージは置き換える）
+    pub fn insert(&mut self, code: impl Into<String>, locale: &str, message: impl Into<String>) {
+        self.messages
+            .entry(code.into())
+            .or_default()
+            .insert(normalize(locale), message.into());
+    }
+
+    /// ロケールの指定がない、または該当するメッセージがない場合に使うロケール
+    pub fn with_default_locale(mut self, locale: &str) -> Self {
+        self.default_locale = Some(normalize(locale));
+        self
+    }
+
+    /// エラーコードのメッセージ（置き換え前のテンプレート）
+    pub fn message(&self, code: &str, locale: Option<&str>) -> Option<&str> {
+        let messages = self.messages.get(code)?;
+        let requested = locale.map(normalize);
+        [requested.as_deref(), self.default_locale.as_deref()]
+            .into_iter()
+            .flatten()
+            .flat_map(|locale| [Some(locale), language(locale)])
+            .flatten()
+            .find_map(|locale| messages.get(locale))
+            .map(String::as_str)
+    }
+
+    /// エラーのメッセージをロケールに合わせて置き換える
+    ///
+    /// `error_code`があればそれを、なければ数値のコードをキーにします。
+    pub fn localize(&self, mut error: ProtocolError, locale: Option<&str>) -> ProtocolError {
+        let code = error
+            .error_code
+            .clone()
+            .unwrap_or_else(|| error.code.to_string());
+        if let Some(template) = self.message(&code, locale) {
+            error.message = render(template, error.details.as_ref());
+        }
+        error
+    }
+}
+
+/// 比較用にロケールを正規化（`ja_JP` → `ja-jp`）
+fn normalize(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+/// 地域などを除いた言語（`ja-jp` → `ja`）
+fn language(locale: &str) -> Option<&str> {
+    locale.split_once('-').map(|(language, _)| language)
+}
+
+/// `{name}`を`details`の値で置き換える（値がなければそのまま残す）
+fn render(template: &str, details: Option<&Value>) -> String {
+    let Some(details) = details.and_then(Value::as_object) else {
+        return template.to_string();
+    };
+    let mut message = template.to_string();
+    for (key, value) in details {
+        let placeholder = format!("{{{}}}", key);
+        if !message.contains(&placeholder) {
+            continue;
+        }
+        let value = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        message = message.replace(&placeholder, &value);
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::UnisonError;
+    use serde_json::json;
+
+    fn catalog() -> ErrorCatalog {
+        ErrorCatalog::new()
+            .with_message("USER_NOT_FOUND", "en", "User {id} was not found")
+            .with_message("USER_NOT_FOUND", "ja", "ユーザー{id}が見つかりません")
+            .with_message("404", "ja-JP", "メソッドが見つかりません")
+            .with_default_locale("en")
+    }
+
+    #[test]
+    fn test_locale_fallback() {
+        let catalog = catalog();
+        assert_eq!(
+            catalog.message("USER_NOT_FOUND", Some("ja_JP")),
+            Some("ユーザー{id}が見つかりません")
+        );
+        assert_eq!(
+            catalog.message("USER_NOT_FOUND", Some("fr")),
+            Some("User {id} was not found")
+        );
+        assert_eq!(
+            catalog.message("USER_NOT_FOUND", None),
+            Some("User {id} was not found")
+        );
+        // 言語だけの指定では地域付きのメッセージを選ばない
+        assert_eq!(catalog.message("404", Some("ja")), None);
+        assert_eq!(catalog.message("UNKNOWN", Some("ja")), None);
+    }
+
+    #[test]
+    fn test_localize_keeps_codes_and_details() {
+        let catalog = catalog();
+        let error = ProtocolError::from(UnisonError::with_details(
+            "USER_NOT_FOUND",
+            "no such user",
+            json!({ "id": 42 }),
+        ));
+        let localized = catalog.localize(error.clone(), Some("ja-JP"));
+        assert_eq!(localized.message, "ユーザー42が見つかりません");
+        assert_eq!(localized.code, error.code);
+        assert_eq!(localized.error_code, error.error_code);
+        assert_eq!(localized.details, error.details);
+
+        // 数値のコードでも引ける
+        let error = ProtocolError::new(ProtocolError::NOT_FOUND, "Method not found");
+        let localized = catalog.localize(error, Some("ja-JP"));
+        assert_eq!(localized.message, "メソッドが見つかりません");
+    }
+}
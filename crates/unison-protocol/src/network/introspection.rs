@@ -0,0 +1,107 @@
+//! スキーマで定義されたサービスの標準メソッド
+//!
+//! スキーマを読み込んだ[`UnisonProtocol`](crate::UnisonProtocol)から作成したサーバーには、
+//! サービスごとに次のメソッドが自動で登録されます。
+//!
+//! - `<service>.ping`: 疎通確認（プロトコルのバージョンとスキーマの指紋を返す）
+//! - `<service>.describe`: サービスのメソッド一覧とリクエスト・レスポンスのフィールド
+
+use serde_json::{Value, json};
+
+use crate::parser::{Method, MethodMessage, ParsedSchema, Service, Stream};
+
+/// 疎通確認メソッドの名前の末尾
+pub const PING_METHOD_SUFFIX: &str = "ping";
+
+/// サービス情報メソッドの名前の末尾
+pub const DESCRIBE_METHOD_SUFFIX: &str = "describe";
+
+/// サービスの疎通確認メソッドの名前
+pub fn ping_method(service: &str) -> String {
+    format!("{}.{}", service, PING_METHOD_SUFFIX)
+}
+
+/// サービスの情報メソッドの名前
+pub fn describe_method(service: &str) -> String {
+    format!("{}.{}", service, DESCRIBE_METHOD_SUFFIX)
+}
+
+/// スキーマの各サービスの標準メソッドとその応答
+///
+/// 応答はスキーマから決まるため、登録時に一度だけ作成します。
+pub fn schema_methods(schema: &ParsedSchema) -> Vec<(String, Value)> {
+    let Some(protocol) = &schema.protocol else {
+        return Vec::new();
+    };
+    let fingerprint = schema.fingerprint();
+
+    let mut methods = Vec::new();
+    for service in &protocol.services {
+        let method_names: Vec<&str> = service.methods.iter().map(|m| m.name.as_str()).collect();
+        methods.push((
+            ping_method(&service.name),
+            json!({
+                "service": service.name,
+                "protocol": protocol.name,
+                "version": protocol.version,
+                "methods": method_names,
+                "fingerprint": fingerprint,
+            }),
+        ));
+        methods.push((
+            describe_method(&service.name),
+            describe_service(&protocol.name, &protocol.version, service, &fingerprint),
+        ));
+    }
+    methods
+}
+
+fn describe_service(protocol: &str, version: &str, service: &Service, fingerprint: &str) -> Value {
+    json!({
+        "service": service.name,
+        "description": service.description,
+        "protocol": protocol,
+        "version": version,
+        "fingerprint": fingerprint,
+        "methods": service.methods.iter().map(describe_method_def).collect::<Vec<_>>(),
+        "streams": service.streams.iter().map(describe_stream).collect::<Vec<_>>(),
+        "client_streams": service.client_streams.iter().map(describe_stream).collect::<Vec<_>>(),
+    })
+}
+
+fn describe_method_def(method: &Method) -> Value {
+    json!({
+        "name": method.name,
+        "description": method.description,
+        "timeout_ms": method.timeout_ms,
+        "idempotent": method.idempotent,
+        "paged": method.paged,
+        "encodings": method.encodings,
+        "request": describe_fields(method.request.as_ref()),
+        "response": describe_fields(method.response.as_ref()),
+    })
+}
+
+fn describe_stream(stream: &Stream) -> Value {
+    json!({
+        "name": stream.name,
+        "request": describe_fields(stream.request.as_ref()),
+        "response": describe_fields(stream.response.as_ref()),
+    })
+}
+
+fn describe_fields(message: Option<&MethodMessage>) -> Value {
+    let fields = message.map_or(&[][..], |m| &m.fields);
+    Value::Array(
+        fields
+            .iter()
+            .map(|field| {
+                json!({
+                    "name": field.name,
+                    "type": field.field_type_str,
+                    "required": field.required,
+                })
+            })
+            .collect(),
+    )
+}
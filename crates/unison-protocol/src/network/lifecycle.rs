@@ -0,0 +1,631 @@
+//! サーバーに登録したサービスのライフサイクル
+//!
+//! ```text
+//! Starting ──▶ Running ──▶ Draining ──▶ Stopped
+//!    ▲  │         │                       ▲
+//!    │  └─────────┴──(終了・失敗)─────────┤ (再起動しない)
+//!    └────────────(再起動のポリシー)──────┘
+//! ```
+//!
+//! [`ProtocolServer::supervise_service`](super::ProtocolServer::supervise_service)で登録した
+//! サービスは、起動に失敗したりストリームが閉じたりすると[`RestartPolicy`]に従って
+//! バックオフ後に起動し直されます。停止を要求したサービスは`Draining`で処理中のリクエストを
+//! 待ってから終了通知を送り、`Stopped`になります。
+//!
+//! 状態の変化は[`ServiceEvent`]として購読でき、サーバーはトピック[`SERVICE_EVENT_TOPIC`]へ
+//! 発行します。組み込みメソッド[`SERVICES_METHOD`]で全サービスの状態を取得できます。
+
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{RwLock, broadcast, watch};
+use tracing::warn;
+
+use super::reconnect::ReconnectPolicy;
+use super::service::{Service, UnisonService};
+use super::{NetworkError, SystemStream};
+
+/// 全サービスの状態を取得する組み込みメソッド
+pub const SERVICES_METHOD: &str = "unison.admin.services";
+
+/// サービスの状態の変化を発行するトピック
+pub const SERVICE_EVENT_TOPIC: &str = "unison.admin.lifecycle";
+
+/// 状態イベントのバッファ数（遅い購読者はこれを超えた古いイベントを取りこぼす）
+const SERVICE_EVENT_CAPACITY: usize = 64;
+
+/// 健全性を確認する間隔の既定値
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// サービスの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceState {
+    /// 起動中（再起動のバックオフを待っている間を含む）
+    Starting,
+    /// リクエストを受け付けている
+    Running,
+    /// 停止を要求され、処理中のリクエストを待っている
+    Draining,
+    /// 停止済み
+    Stopped,
+}
+
+impl ServiceState {
+    /// 状態遷移が許可されているか
+    ///
+    /// `Starting`から`Starting`への遷移は、起動に失敗して再起動を待つことを表します。
+    pub fn can_transition_to(self, next: ServiceState) -> bool {
+        use ServiceState::*;
+        matches!(
+            (self, next),
+            (Starting, _)
+                | (Running, Starting | Draining | Stopped)
+                | (Draining, Stopped)
+                | (Stopped, Starting)
+        )
+    }
+}
+
+impl fmt::Display for ServiceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ServiceState::Starting => "starting",
+            ServiceState::Running => "running",
+            ServiceState::Draining => "draining",
+            ServiceState::Stopped => "stopped",
+        };
+        f.write_str(name)
+    }
+}
+
+/// サービスが終了したときに再起動するか
+#[derive(Debug, Clone, Default)]
+pub enum RestartPolicy {
+    /// 再起動しない
+    #[default]
+    Never,
+    /// 起動・健全性の確認に失敗した場合だけ、バックオフ後に再起動する
+    OnFailure(ReconnectPolicy),
+    /// ストリームが閉じて終了した場合も、バックオフ後に再起動する
+    Always(ReconnectPolicy),
+}
+
+impl RestartPolicy {
+    /// 既定のバックオフで失敗時に再起動する
+    pub fn on_failure() -> Self {
+        Self::OnFailure(ReconnectPolicy::default())
+    }
+
+    /// 既定のバックオフで常に再起動する
+    pub fn always() -> Self {
+        Self::Always(ReconnectPolicy::default())
+    }
+
+    /// `attempt`回目（0始まり）の再起動までの待機時間（再起動しない場合は`None`）
+    pub fn restart_delay(&self, failed: bool, attempt: u32) -> Option<Duration> {
+        let backoff = match self {
+            Self::Never => return None,
+            Self::OnFailure(_) if !failed => return None,
+            Self::OnFailure(backoff) | Self::Always(backoff) => backoff,
+        };
+        backoff.allows(attempt).then(|| backoff.delay(attempt))
+    }
+
+    /// 連続した再起動の回数を戻すまでに動き続ける時間
+    pub(crate) fn reset_after(&self) -> Duration {
+        match self {
+            Self::Never => Duration::ZERO,
+            Self::OnFailure(backoff) | Self::Always(backoff) => backoff.max_backoff,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Never => "never",
+            Self::OnFailure(_) => "on_failure",
+            Self::Always(_) => "always",
+        }
+    }
+}
+
+/// サービスの監督の設定
+#[derive(Debug, Clone)]
+pub struct SupervisionConfig {
+    pub restart: RestartPolicy,
+    /// 動作中のサービスのストリームと応答を確認する間隔
+    pub health_check_interval: Duration,
+}
+
+impl SupervisionConfig {
+    pub fn new(restart: RestartPolicy) -> Self {
+        Self {
+            restart,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_restart_policy(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    pub fn with_health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
+}
+
+impl Default for SupervisionConfig {
+    fn default() -> Self {
+        Self {
+            restart: RestartPolicy::Never,
+            health_check_interval: DEFAULT_HEALTH_CHECK_INTERVAL,
+        }
+    }
+}
+
+/// サービスの状態遷移イベント
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceEvent {
+    pub service: String,
+    /// 遷移前の状態（登録時は`None`）
+    pub previous: Option<ServiceState>,
+    pub current: ServiceState,
+    /// これまでに再起動した回数
+    pub restarts: u32,
+    /// 遷移の理由（起動の失敗のエラーメッセージ等）
+    pub reason: Option<String>,
+    /// 遷移した時刻（Unix時刻、ミリ秒）
+    pub timestamp_ms: u64,
+}
+
+/// サービスの現在の状態
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    pub service: String,
+    pub state: ServiceState,
+    /// 再起動のポリシー（`never`・`on_failure`・`always`）
+    pub restart_policy: String,
+    pub restarts: u32,
+    /// 直近の失敗の理由
+    pub last_error: Option<String>,
+}
+
+struct Entry {
+    state: watch::Sender<ServiceState>,
+    restart_policy: &'static str,
+    /// 監督タスクが停止の要求を処理するか
+    supervised: bool,
+    restarts: u32,
+    last_error: Option<String>,
+    /// 停止の要求
+    stop: watch::Sender<bool>,
+}
+
+struct Inner {
+    entries: Mutex<HashMap<String, Entry>>,
+    events: broadcast::Sender<ServiceEvent>,
+}
+
+/// サービスごとの状態・再起動の回数と、状態遷移の通知
+#[derive(Clone)]
+pub struct ServiceLifecycle {
+    inner: Arc<Inner>,
+}
+
+impl ServiceLifecycle {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(SERVICE_EVENT_CAPACITY);
+        Self {
+            inner: Arc::new(Inner {
+                entries: Mutex::new(HashMap::new()),
+                events,
+            }),
+        }
+    }
+
+    /// サービスを`Starting`で登録（登録済みの場合は停止の要求を取り消して`Starting`へ遷移）
+    ///
+    /// 停止の要求を受け取るレシーバーを返します。
+    pub(crate) fn register(
+        &self,
+        service: &str,
+        policy: &RestartPolicy,
+        supervised: bool,
+    ) -> watch::Receiver<bool> {
+        let mut entries = self.inner.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(service) {
+            entry.restart_policy = policy.name();
+            entry.supervised = supervised;
+            entry.stop.send_replace(false);
+            let stop = entry.stop.subscribe();
+            let previous = *entry.state.borrow();
+            if previous.can_transition_to(ServiceState::Starting) {
+                entry.state.send_replace(ServiceState::Starting);
+                self.notify(service, Some(previous), ServiceState::Starting, entry, None);
+            }
+            return stop;
+        }
+
+        let (state, _) = watch::channel(ServiceState::Starting);
+        let (stop, receiver) = watch::channel(false);
+        let entry = Entry {
+            state,
+            restart_policy: policy.name(),
+            supervised,
+            restarts: 0,
+            last_error: None,
+            stop,
+        };
+        self.notify(service, None, ServiceState::Starting, &entry, None);
+        entries.insert(service.to_string(), entry);
+        receiver
+    }
+
+    /// 状態を遷移させる
+    ///
+    /// 許可されていない遷移と、登録されていないサービスの遷移は無視され、`false`を返します。
+    pub fn transition(&self, service: &str, next: ServiceState, reason: Option<String>) -> bool {
+        let mut entries = self.inner.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(service) else {
+            return false;
+        };
+        let previous = *entry.state.borrow();
+        if !previous.can_transition_to(next) {
+            warn!("Ignoring invalid service state transition for {service}: {previous} -> {next}");
+            return false;
+        }
+        entry.state.send_replace(next);
+        self.notify(service, Some(previous), next, entry, reason);
+        true
+    }
+
+    /// 失敗・終了したサービスの再起動を記録し、`Starting`へ遷移させる
+    pub(crate) fn record_restart(&self, service: &str, failure: Option<String>, reason: String) {
+        if let Some(entry) = self.inner.entries.lock().unwrap().get_mut(service) {
+            entry.restarts += 1;
+            if failure.is_some() {
+                entry.last_error = failure;
+            }
+        }
+        self.transition(service, ServiceState::Starting, Some(reason));
+    }
+
+    /// 失敗の理由を記録
+    pub(crate) fn record_failure(&self, service: &str, error: String) {
+        if let Some(entry) = self.inner.entries.lock().unwrap().get_mut(service) {
+            entry.last_error = Some(error);
+        }
+    }
+
+    /// 停止を要求（登録されていない場合は`false`）
+    pub(crate) fn request_stop(&self, service: &str) -> bool {
+        match self.inner.entries.lock().unwrap().get(service) {
+            Some(entry) => {
+                entry.stop.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 監督下で起動したサービスか
+    pub(crate) fn is_supervised(&self, service: &str) -> bool {
+        self.inner
+            .entries
+            .lock()
+            .unwrap()
+            .get(service)
+            .is_some_and(|entry| entry.supervised)
+    }
+
+    pub fn state(&self, service: &str) -> Option<ServiceState> {
+        self.inner
+            .entries
+            .lock()
+            .unwrap()
+            .get(service)
+            .map(|entry| *entry.state.borrow())
+    }
+
+    /// 全サービスの状態（サービス名順）
+    pub fn statuses(&self) -> Vec<ServiceStatus> {
+        let mut statuses: Vec<_> = self
+            .inner
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(service, entry)| ServiceStatus {
+                service: service.clone(),
+                state: *entry.state.borrow(),
+                restart_policy: entry.restart_policy.to_string(),
+                restarts: entry.restarts,
+                last_error: entry.last_error.clone(),
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.service.cmp(&b.service));
+        statuses
+    }
+
+    /// 状態遷移イベントのストリーム
+    ///
+    /// 購読開始以降の遷移を受け取ります。処理が遅れて取りこぼしたイベントはスキップされます。
+    pub fn events(&self) -> Pin<Box<dyn Stream<Item = ServiceEvent> + Send>> {
+        let mut rx = self.inner.events.subscribe();
+        Box::pin(async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => yield event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// サービスが指定の状態になるまで待機（登録されていない場合はすぐに戻る）
+    pub async fn wait_for(&self, service: &str, target: ServiceState) {
+        let state = self
+            .inner
+            .entries
+            .lock()
+            .unwrap()
+            .get(service)
+            .map(|entry| entry.state.subscribe());
+        if let Some(mut state) = state {
+            let _ = state.wait_for(|state| *state == target).await;
+        }
+    }
+
+    fn notify(
+        &self,
+        service: &str,
+        previous: Option<ServiceState>,
+        current: ServiceState,
+        entry: &Entry,
+        reason: Option<String>,
+    ) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        // 購読者がいない場合の送信エラーは無視
+        let _ = self.inner.events.send(ServiceEvent {
+            service: service.to_string(),
+            previous,
+            current,
+            restarts: entry.restarts,
+            reason,
+            timestamp_ms,
+        });
+    }
+}
+
+impl Default for ServiceLifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// サーバーが保持するサービスのマップ
+pub(crate) type ServiceMap = Arc<RwLock<HashMap<String, UnisonService>>>;
+
+/// 監視を終えた理由
+enum Exit {
+    /// 停止を要求された
+    Stopped,
+    /// ストリームが閉じた
+    Completed,
+    /// 起動・健全性の確認に失敗した
+    Failed(String),
+}
+
+/// サービスを起動して監視し、終了したらポリシーに従って再起動する監督タスク
+///
+/// サービスは`service`の名前でマップに登録されます。
+pub(crate) async fn supervise<F, Fut>(
+    services: ServiceMap,
+    lifecycle: ServiceLifecycle,
+    service: String,
+    config: SupervisionConfig,
+    factory: F,
+    mut stop: watch::Receiver<bool>,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<UnisonService, NetworkError>>,
+{
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let exit = tokio::select! {
+            biased;
+            _ = stop.wait_for(|stop| *stop) => Exit::Stopped,
+            exit = run_once(&services, &lifecycle, &service, &config, &factory) => exit,
+        };
+        let (failed, reason) = match exit {
+            Exit::Stopped => {
+                drain_service(&services, &lifecycle, &service).await;
+                return;
+            }
+            Exit::Completed => (false, "stream closed".to_string()),
+            Exit::Failed(error) => {
+                lifecycle.record_failure(&service, error.clone());
+                (true, error)
+            }
+        };
+        // 終了したサービスのストリームを閉じる
+        let removed = services.write().await.remove(&service);
+        if let Some(mut removed) = removed {
+            let _ = removed.close().await;
+        }
+
+        // 十分に動き続けた場合は連続した再起動として数えない
+        if started.elapsed() >= config.restart.reset_after() {
+            attempt = 0;
+        }
+        let Some(delay) = config.restart.restart_delay(failed, attempt) else {
+            lifecycle.transition(&service, ServiceState::Stopped, Some(reason));
+            return;
+        };
+        lifecycle.record_restart(
+            &service,
+            failed.then(|| reason.clone()),
+            format!("restarting in {delay:?}: {reason}"),
+        );
+        tokio::select! {
+            biased;
+            _ = stop.wait_for(|stop| *stop) => {
+                lifecycle.transition(&service, ServiceState::Stopped, Some("stop requested".into()));
+                return;
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+        attempt += 1;
+    }
+}
+
+/// サービスを起動し、ストリームが閉じるか確認に失敗するまで監視（停止の要求は呼び出し側が待つ）
+async fn run_once<F, Fut>(
+    services: &ServiceMap,
+    lifecycle: &ServiceLifecycle,
+    service: &str,
+    config: &SupervisionConfig,
+    factory: &F,
+) -> Exit
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<UnisonService, NetworkError>>,
+{
+    let mut instance = match factory().await {
+        Ok(instance) => instance,
+        Err(e) => return Exit::Failed(e.to_string()),
+    };
+    if let Some(interval) = instance.get_config().heartbeat_interval {
+        if let Err(e) = instance.start_service_heartbeat(interval.as_secs()).await {
+            return Exit::Failed(e.to_string());
+        }
+    }
+    services.write().await.insert(service.to_string(), instance);
+    lifecycle.transition(service, ServiceState::Running, None);
+
+    loop {
+        tokio::time::sleep(config.health_check_interval).await;
+        let mut services = services.write().await;
+        let Some(instance) = services.get_mut(service) else {
+            return Exit::Completed;
+        };
+        if !instance.is_active() {
+            return Exit::Completed;
+        }
+        if let Err(e) = instance.service_ping().await {
+            return Exit::Failed(e.to_string());
+        }
+    }
+}
+
+/// 処理中のリクエストを待ってからサービスを外し、終了通知を送って`Stopped`にする
+pub(crate) async fn drain_service(
+    services: &ServiceMap,
+    lifecycle: &ServiceLifecycle,
+    service: &str,
+) {
+    lifecycle.transition(
+        service,
+        ServiceState::Draining,
+        Some("stop requested".into()),
+    );
+    // リクエストの処理は書き込みロックを保持するため、取得できた時点で処理中のものはない
+    let removed = services.write().await.remove(service);
+    if let Some(mut removed) = removed {
+        if let Err(e) = removed.shutdown().await {
+            warn!("Error shutting down service {service}: {e}");
+        }
+    }
+    lifecycle.transition(
+        service,
+        ServiceState::Stopped,
+        Some("stop requested".into()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ServiceState::*;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn test_restart_policy_decides_by_exit() {
+        let backoff = ReconnectPolicy::default()
+            .with_initial_backoff(Duration::from_millis(10))
+            .with_jitter(0.0)
+            .with_max_attempts(2);
+        assert_eq!(RestartPolicy::Never.restart_delay(true, 0), None);
+
+        let on_failure = RestartPolicy::OnFailure(backoff.clone());
+        assert_eq!(
+            on_failure.restart_delay(true, 0),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(on_failure.restart_delay(false, 0), None);
+        assert_eq!(on_failure.restart_delay(true, 2), None);
+
+        let always = RestartPolicy::Always(backoff);
+        assert_eq!(
+            always.restart_delay(false, 1),
+            Some(Duration::from_millis(20))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transitions_emit_events_and_statuses() {
+        let lifecycle = ServiceLifecycle::new();
+        let mut events = lifecycle.events();
+
+        let stop = lifecycle.register("chat", &RestartPolicy::on_failure(), true);
+        assert!(lifecycle.transition("chat", Running, None));
+        assert!(!lifecycle.transition("chat", Running, None));
+        lifecycle.record_restart("chat", Some("boom".into()), "Health check failed".into());
+        assert!(lifecycle.transition("chat", Running, None));
+        assert!(!lifecycle.transition("missing", Running, None));
+
+        let first = events.next().await.unwrap();
+        assert_eq!((first.previous, first.current), (None, Starting));
+        assert_eq!(events.next().await.unwrap().current, Running);
+        let restarted = events.next().await.unwrap();
+        assert_eq!(restarted.current, Starting);
+        assert_eq!(restarted.restarts, 1);
+        assert_eq!(restarted.reason.as_deref(), Some("Health check failed"));
+
+        assert!(lifecycle.request_stop("chat"));
+        assert!(*stop.borrow());
+        let statuses = lifecycle.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].state, Running);
+        assert_eq!(statuses[0].restart_policy, "on_failure");
+        assert_eq!(statuses[0].last_error.as_deref(), Some("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_register_again_restarts_stopped_service() {
+        let lifecycle = ServiceLifecycle::new();
+        let _ = lifecycle.register("chat", &RestartPolicy::Never, false);
+        assert!(!lifecycle.is_supervised("chat"));
+        assert!(lifecycle.transition("chat", Draining, None));
+        assert!(!lifecycle.transition("chat", Running, None));
+        assert!(lifecycle.transition("chat", Stopped, None));
+        lifecycle.wait_for("chat", Stopped).await;
+
+        let stop = lifecycle.register("chat", &RestartPolicy::always(), true);
+        assert!(lifecycle.is_supervised("chat"));
+        assert!(!*stop.borrow());
+        assert_eq!(lifecycle.state("chat"), Some(Starting));
+        assert_eq!(lifecycle.statuses()[0].restart_policy, "always");
+    }
+}
@@ -0,0 +1,437 @@
+//! LSP互換のJSON-RPCブリッジ
+//!
+//! [`stdio`](super::stdio)トランスポートと同じく標準入出力でサービスを提供しますが、
+//! メッセージをLanguage Server Protocolと同じ`Content-Length`ヘッダー付きの
+//! JSON-RPC 2.0で送受信します。LSPサーバーを起動するエディタやツールから、
+//! Unisonのサービスをそのまま利用できます。
+//!
+//! ```text
+//! Content-Length: 59\r\n
+//! \r\n
+//! {"jsonrpc":"2.0","id":1,"method":"echo","params":{"a":1}}
+//! ```
+//!
+//! - リクエストは同名のUnisonメソッドの呼び出しに対応し、結果を`result`で返します
+//! - 通知（`id`なし）も同名のメソッドを呼び出し、結果は破棄します
+//! - ブロードキャストなどのイベントは通知として送信します
+//! - `initialize`・`shutdown`・`exit`はハンドラーが登録されていなければブリッジが処理します
+
+use bytes::Bytes;
+use serde_json::{Value, json};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::{error, warn};
+
+use super::broadcast::{ConnectionId, MessageSink};
+use super::events::CloseReason;
+use super::server::ProtocolServer;
+use super::{NetworkError, ProtocolError, ProtocolFrame, ProtocolMessage};
+
+/// JSON-RPCのエラーコード
+pub mod error_code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// LSPで定義された、リクエストの処理に失敗したことを示すコード
+    pub const REQUEST_FAILED: i64 = -32803;
+}
+
+/// Content-Lengthヘッダー付きのメッセージを1件読み込む
+///
+/// 入力が閉じている場合は`None`を返します。
+pub async fn read_message<R>(reader: &mut R) -> Result<Option<Value>, NetworkError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut content_length = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        let read = reader
+            .read_line(&mut header)
+            .await
+            .map_err(|e| NetworkError::Connection(e.to_string()))?;
+        if read == 0 {
+            if content_length.is_none() {
+                return Ok(None);
+            }
+            return Err(NetworkError::Protocol("Unexpected end of headers".into()));
+        }
+
+        let line = header.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            // メッセージ間の空行は無視
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(NetworkError::Protocol(format!(
+                "Malformed header: {}",
+                line
+            )));
+        };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            let length = value.trim().parse::<usize>().map_err(|_| {
+                NetworkError::Protocol(format!("Invalid Content-Length: {}", value))
+            })?;
+            content_length = Some(length);
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or_default()];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|e| NetworkError::Connection(e.to_string()))?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Content-Lengthヘッダーを付けてメッセージを書き込む
+pub async fn write_message<W>(writer: &mut W, message: &Value) -> Result<(), NetworkError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let body = serde_json::to_vec(message)?;
+    let header = format!("Content-Length: {}\r\n\r\n", body.len());
+    let io = |e: std::io::Error| NetworkError::Connection(e.to_string());
+    writer.write_all(header.as_bytes()).await.map_err(io)?;
+    writer.write_all(&body).await.map_err(io)?;
+    writer.flush().await.map_err(io)
+}
+
+/// UnisonのエラーをJSON-RPCのエラーオブジェクトに変換
+///
+/// 元のコードと詳細は`data`に格納します。
+pub fn rpc_error(error: &ProtocolError) -> Value {
+    let code = match error.code {
+        ProtocolError::NOT_FOUND => error_code::METHOD_NOT_FOUND,
+        ProtocolError::INVALID_REQUEST => error_code::INVALID_PARAMS,
+        ProtocolError::INTERNAL => error_code::INTERNAL_ERROR,
+        _ => error_code::REQUEST_FAILED,
+    };
+    json!({
+        "code": code,
+        "message": error.message,
+        "data": { "code": error.code, "details": error.details },
+    })
+}
+
+fn response(id: Value, outcome: Result<Value, Value>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+fn plain_error(code: i64, message: impl Into<String>) -> Value {
+    json!({ "code": code, "message": message.into() })
+}
+
+/// イベントのフレームを通知として送る配信先
+struct NotificationSink {
+    tx: mpsc::UnboundedSender<Value>,
+}
+
+impl MessageSink for NotificationSink {
+    fn send_frame(
+        &self,
+        frame: Bytes,
+    ) -> Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send + '_>> {
+        Box::pin(async move {
+            let frame = ProtocolFrame::from_bytes(&frame)?;
+            let message = ProtocolMessage::from_frame(&frame)?;
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": message.method,
+                "params": message.payload_as_value()?,
+            });
+            self.tx
+                .send(notification)
+                .map_err(|_| NetworkError::NotConnected)
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// LSP互換のJSON-RPCでリクエストを処理するサーバー
+pub struct LspServer {
+    server: Arc<ProtocolServer>,
+}
+
+impl LspServer {
+    pub fn new(server: Arc<ProtocolServer>) -> Self {
+        Self { server }
+    }
+
+    /// 標準入力が閉じるか`exit`通知を受け取るまでリクエストを処理
+    pub async fn serve_stdio(&self) -> Result<(), NetworkError> {
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// 任意の入出力でリクエストを処理
+    pub async fn serve<R, W>(&self, reader: R, writer: W) -> Result<(), NetworkError>
+    where
+        R: AsyncRead + Unpin + Send,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+        let writer_task = tokio::spawn(async move {
+            let mut writer = writer;
+            while let Some(message) = rx.recv().await {
+                if let Err(e) = write_message(&mut writer, &message).await {
+                    error!("Failed to write LSP message: {}", e);
+                    break;
+                }
+            }
+        });
+        let connection_id =
+            self.server
+                .open_connection(Arc::new(NotificationSink { tx: tx.clone() }), None, None);
+
+        let mut reader = BufReader::new(reader);
+        let shutdown_requested = Arc::new(AtomicBool::new(false));
+        let stopped = self.server.shutdown_controller().stopped();
+        tokio::pin!(stopped);
+        let mut requests = JoinSet::new();
+        let result = loop {
+            let message = tokio::select! {
+                message = read_message(&mut reader) => message,
+                _ = &mut stopped => break Ok(()),
+            };
+            let message = match message {
+                Ok(Some(message)) => message,
+                Ok(None) => break Ok(()),
+                Err(NetworkError::Serialization(e)) => {
+                    warn!("Ignoring malformed LSP message: {}", e);
+                    let error = plain_error(error_code::PARSE_ERROR, e.to_string());
+                    let _ = tx.send(response(Value::Null, Err(error)));
+                    continue;
+                }
+                Err(e) => break Err(e),
+            };
+
+            let id = message.get("id").cloned();
+            let Some(method) = message.get("method").and_then(Value::as_str) else {
+                if let Some(id) = id {
+                    let error = plain_error(error_code::INVALID_REQUEST, "Missing method");
+                    let _ = tx.send(response(id, Err(error)));
+                }
+                continue;
+            };
+            if method == "exit" {
+                break Ok(());
+            }
+            let method = method.to_string();
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+            let server = Arc::clone(&self.server);
+            let tx = tx.clone();
+            let shutdown_requested = Arc::clone(&shutdown_requested);
+            let in_flight = server.shutdown_controller().track();
+            requests.spawn(async move {
+                let _in_flight = in_flight;
+                let outcome =
+                    dispatch(&server, connection_id, &method, params, &shutdown_requested).await;
+                if let Some(id) = id {
+                    let _ = tx.send(response(id, outcome));
+                }
+            });
+        };
+
+        while requests.join_next().await.is_some() {}
+        let reason = match &result {
+            Ok(()) if self.server.shutdown_controller().is_stopping() => CloseReason::ServerClosed,
+            Ok(()) => CloseReason::ClientClosed,
+            Err(e) => CloseReason::Error(e.to_string()),
+        };
+        self.server.connection_closed(connection_id, reason);
+        drop(tx);
+        let _ = writer_task.await;
+        result
+    }
+}
+
+/// 1件のリクエストを処理
+async fn dispatch(
+    server: &ProtocolServer,
+    connection_id: ConnectionId,
+    method: &str,
+    params: Value,
+    shutdown_requested: &AtomicBool,
+) -> Result<Value, Value> {
+    if shutdown_requested.load(Ordering::SeqCst) {
+        return Err(plain_error(
+            error_code::INVALID_REQUEST,
+            "Server is shutting down",
+        ));
+    }
+
+    let outcome = server
+        .handle_connection_request(connection_id, method, params)
+        .await
+        .outcome;
+    match outcome {
+        Ok(result) => Ok(result),
+        // ハンドラーのないライフサイクルメソッドはブリッジが応答
+        Err(e) if e.code == ProtocolError::NOT_FOUND && method == "initialize" => Ok(json!({
+            "capabilities": {},
+            "serverInfo": { "name": "unison", "version": env!("CARGO_PKG_VERSION") },
+        })),
+        Err(e) if e.code == ProtocolError::NOT_FOUND && method == "shutdown" => {
+            shutdown_requested.store(true, Ordering::SeqCst);
+            Ok(Value::Null)
+        }
+        Err(e) => Err(rpc_error(&e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+    use tokio::io::DuplexStream;
+
+    struct TestClient {
+        reader: BufReader<tokio::io::ReadHalf<DuplexStream>>,
+        writer: tokio::io::WriteHalf<DuplexStream>,
+    }
+
+    impl TestClient {
+        async fn send(&mut self, message: Value) {
+            write_message(&mut self.writer, &message).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> Value {
+            read_message(&mut self.reader).await.unwrap().unwrap()
+        }
+    }
+
+    fn connect(
+        server: Arc<ProtocolServer>,
+    ) -> (
+        TestClient,
+        tokio::task::JoinHandle<Result<(), NetworkError>>,
+    ) {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        let (client_read, client_write) = tokio::io::split(client_io);
+        let server = LspServer::new(server);
+        let serving = tokio::spawn(async move { server.serve(server_read, server_write).await });
+        let client = TestClient {
+            reader: BufReader::new(client_read),
+            writer: client_write,
+        };
+        (client, serving)
+    }
+
+    #[tokio::test]
+    async fn test_framing_round_trip() {
+        let (mut a, b) = tokio::io::duplex(1024);
+        let mut b = BufReader::new(b);
+        let message = json!({ "jsonrpc": "2.0", "id": 1, "method": "echo" });
+        write_message(&mut a, &message).await.unwrap();
+        // ヘッダーの大文字小文字やContent-Typeは区別しない
+        a.write_all(b"content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}")
+            .await
+            .unwrap();
+        drop(a);
+
+        assert_eq!(read_message(&mut b).await.unwrap(), Some(message));
+        assert_eq!(read_message(&mut b).await.unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut b).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_requests_and_lifecycle() {
+        let server = ProtocolServer::new();
+        server
+            .register_call_handler(
+                "echo",
+                |params| async move { Ok::<_, NetworkError>(params) },
+            )
+            .await;
+        let (mut client, serving) = connect(Arc::new(server));
+
+        client
+            .send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }))
+            .await;
+        let initialized = client.recv().await;
+        assert_eq!(initialized["id"], 1);
+        assert_eq!(initialized["result"]["serverInfo"]["name"], "unison");
+
+        client
+            .send(json!({ "jsonrpc": "2.0", "id": "a", "method": "echo", "params": { "x": 1 } }))
+            .await;
+        assert_eq!(
+            client.recv().await,
+            json!({ "jsonrpc": "2.0", "id": "a", "result": { "x": 1 } })
+        );
+
+        client
+            .send(json!({ "jsonrpc": "2.0", "id": 2, "method": "missing" }))
+            .await;
+        let missing = client.recv().await;
+        assert_eq!(missing["error"]["code"], error_code::METHOD_NOT_FOUND);
+        assert_eq!(missing["error"]["data"]["code"], ProtocolError::NOT_FOUND);
+
+        client
+            .send(json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }))
+            .await;
+        assert_eq!(client.recv().await["result"], Value::Null);
+        client
+            .send(json!({ "jsonrpc": "2.0", "id": 4, "method": "echo" }))
+            .await;
+        assert_eq!(
+            client.recv().await["error"]["code"],
+            error_code::INVALID_REQUEST
+        );
+
+        client
+            .send(json!({ "jsonrpc": "2.0", "method": "exit" }))
+            .await;
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_events_sent_as_notifications() {
+        let server = Arc::new(ProtocolServer::new());
+        let (mut client, _serving) = connect(Arc::clone(&server));
+        while server.connections().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let frame = ProtocolMessage::new_with_json(
+            0,
+            "window/logMessage".into(),
+            MessageType::Event,
+            json!({ "message": "hello" }),
+        )
+        .unwrap()
+        .into_frame()
+        .unwrap();
+        server
+            .connections()
+            .broadcast_frame(frame.to_bytes())
+            .wait()
+            .await;
+
+        let notification = client.recv().await;
+        assert_eq!(notification["method"], "window/logMessage");
+        assert_eq!(notification["params"]["message"], "hello");
+        assert!(notification.get("id").is_none());
+    }
+}
@@ -0,0 +1,332 @@
+//! プロセス内のメモリトランスポート
+//!
+//! `memory://<name>`のURLで、同じプロセス内の[`ProtocolClient`]と[`ProtocolServer`]を
+//! チャネルでつなぎます。ソケットや証明書を使わないため、ハンドラーや生成したクライアントの
+//! テストをポートの競合なく決定的に実行できます。
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use unison::network::{MemoryTransport, ProtocolServer, UnisonClient};
+//!
+//! let transport = MemoryTransport::new();
+//! let server = ProtocolServer::new().with_call_handler("echo", |payload| async move {
+//!     Ok::<_, unison::network::NetworkError>(payload)
+//! });
+//! transport.serve(server)?;
+//!
+//! let client = transport.connect().await?;
+//! let response = client.call("echo", serde_json::json!({ "text": "hi" })).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`UnisonServer::listen`](super::UnisonServer::listen)・
+//! [`ProtocolClient::connect`](super::ProtocolClient::connect)に`memory://`のURLを渡しても使えます。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use super::client::ProtocolClient;
+use super::server::ProtocolServer;
+use super::stdio::{MessageClient, serve_messages};
+use super::{NetworkError, ProtocolMessage};
+
+/// メモリトランスポートのURLスキーム
+pub const MEMORY_SCHEME: &str = "memory://";
+
+/// メモリトランスポートのURLか
+pub fn is_memory_url(url: &str) -> bool {
+    url.starts_with(MEMORY_SCHEME)
+}
+
+/// クライアントからサーバーへ渡す1つの接続
+struct MemoryConnection {
+    /// クライアントが送信したメッセージ
+    incoming: mpsc::UnboundedReceiver<ProtocolMessage>,
+    /// クライアントへ送るメッセージ
+    outgoing: mpsc::UnboundedSender<ProtocolMessage>,
+}
+
+/// 待ち受け中の名前と、接続を受け渡すチャネル
+static LISTENERS: LazyLock<Mutex<HashMap<String, mpsc::UnboundedSender<MemoryConnection>>>> =
+    LazyLock::new(Mutex::default);
+
+fn parse_memory_url(url: &str) -> Result<&str, NetworkError> {
+    match url.strip_prefix(MEMORY_SCHEME) {
+        Some(name) if !name.is_empty() => Ok(name),
+        Some(_) => Err(NetworkError::Connection(format!("Missing name in {}", url))),
+        None => Err(NetworkError::UnsupportedTransport(url.to_string())),
+    }
+}
+
+/// 名前で待ち受け中の接続の受け口（破棄で名前を解放）
+struct MemoryListener {
+    name: String,
+    registered: mpsc::UnboundedSender<MemoryConnection>,
+    connections: mpsc::UnboundedReceiver<MemoryConnection>,
+}
+
+impl MemoryListener {
+    fn bind(url: &str) -> Result<Self, NetworkError> {
+        let name = parse_memory_url(url)?;
+        let mut listeners = LISTENERS.lock().unwrap();
+        if listeners.get(name).is_some_and(|tx| !tx.is_closed()) {
+            return Err(NetworkError::Connection(format!(
+                "Memory address already in use: {}",
+                url
+            )));
+        }
+        let (registered, connections) = mpsc::unbounded_channel();
+        listeners.insert(name.to_string(), registered.clone());
+        Ok(Self {
+            name: name.to_string(),
+            registered,
+            connections,
+        })
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        let mut listeners = LISTENERS.lock().unwrap();
+        // 同じ名前で再び待ち受けている場合はそのままにする
+        if listeners
+            .get(&self.name)
+            .is_some_and(|tx| tx.same_channel(&self.registered))
+        {
+            listeners.remove(&self.name);
+        }
+    }
+}
+
+/// `memory://<name>`で待ち受け、停止が要求されるまで接続を処理
+pub(crate) async fn listen(server: Arc<ProtocolServer>, url: &str) -> Result<(), NetworkError> {
+    let listener = MemoryListener::bind(url)?;
+    accept(server, listener).await
+}
+
+async fn accept(
+    server: Arc<ProtocolServer>,
+    mut listener: MemoryListener,
+) -> Result<(), NetworkError> {
+    info!(
+        "🎵 Unison Protocol server listening on {}{} in memory",
+        MEMORY_SCHEME, listener.name
+    );
+
+    let stopped = server.shutdown_controller().stopped();
+    tokio::pin!(stopped);
+    let tasks = server.task_supervisor("memory connections");
+    loop {
+        let connection = tokio::select! {
+            connection = listener.connections.recv() => connection,
+            _ = &mut stopped => break,
+        };
+        // 受け口は自身が保持しているため、ここで閉じることはない
+        let Some(MemoryConnection { incoming, outgoing }) = connection else {
+            break;
+        };
+        let server = Arc::clone(&server);
+        tasks.spawn(async move {
+            let incoming = futures_util::stream::unfold(incoming, |mut incoming| async move {
+                incoming.recv().await.map(|message| (Ok(message), incoming))
+            });
+            if let Err(e) = serve_messages(&server, None, incoming, outgoing).await {
+                debug!("Memory connection closed: {}", e);
+            }
+        });
+    }
+
+    tasks.join(server.shutdown_grace()).await;
+    Ok(())
+}
+
+/// `memory://<name>`で待ち受けているサーバーへ接続
+pub(crate) fn connect(url: &str) -> Result<MessageClient, NetworkError> {
+    let name = parse_memory_url(url)?;
+    let listener = LISTENERS
+        .lock()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| NetworkError::Connection(format!("No memory listener at {}", url)))?;
+
+    let (to_server, incoming) = mpsc::unbounded_channel();
+    let (outgoing, mut from_server) = mpsc::unbounded_channel();
+    listener
+        .send(MemoryConnection { incoming, outgoing })
+        .map_err(|_| NetworkError::Connection(format!("No memory listener at {}", url)))?;
+
+    // 送信側を閉じるとサーバーへの入力も閉じるよう、転送するタスクを挟む
+    let (client_tx, mut client_rx) = mpsc::unbounded_channel::<ProtocolMessage>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(message) = client_rx.recv().await {
+            if to_server.send(message).is_err() {
+                return;
+            }
+        }
+    });
+    let incoming = futures_util::stream::poll_fn(move |cx| {
+        from_server.poll_recv(cx).map(|message| message.map(Ok))
+    });
+    Ok(MessageClient::from_parts(incoming, client_tx, writer_task))
+}
+
+/// 一意な名前でサーバーとクライアントをつなぐメモリトランスポート
+#[derive(Debug, Clone)]
+pub struct MemoryTransport {
+    url: String,
+}
+
+impl MemoryTransport {
+    /// プロセス内で一意な名前で作成
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self::named(&format!("unison-{}", NEXT.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    /// 名前を指定して作成
+    pub fn named(name: &str) -> Self {
+        Self {
+            url: format!("{}{}", MEMORY_SCHEME, name),
+        }
+    }
+
+    /// 接続に使う`memory://`のURL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// サーバーを起動
+    ///
+    /// 戻った時点で待ち受けが始まっているため、すぐに[`Self::connect`]できます。
+    /// 停止は[`ProtocolServer::shutdown_controller`]で要求します。
+    pub fn serve(
+        &self,
+        server: ProtocolServer,
+    ) -> Result<JoinHandle<Result<(), NetworkError>>, NetworkError> {
+        let listener = MemoryListener::bind(&self.url)?;
+        Ok(tokio::spawn(async move {
+            server
+                .run(|protocol_server| accept(protocol_server, listener))
+                .await
+        }))
+    }
+
+    /// 接続したクライアントを作成
+    pub async fn connect(&self) -> Result<ProtocolClient, NetworkError> {
+        let mut client =
+            ProtocolClient::new_default().map_err(|e| NetworkError::Connection(e.to_string()))?;
+        super::UnisonClient::connect(&mut client, &self.url).await?;
+        Ok(client)
+    }
+}
+
+impl Default for MemoryTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{MessageType, ProtocolClientTrait, UnisonClient};
+    use futures_util::StreamExt;
+    use serde_json::{Value, json};
+
+    fn echo_server() -> ProtocolServer {
+        ProtocolServer::new().with_call_handler("echo", |payload| async move {
+            Ok::<_, NetworkError>(payload)
+        })
+    }
+
+    #[test]
+    fn test_parse_memory_url() {
+        assert_eq!(parse_memory_url("memory://a").unwrap(), "a");
+        assert!(parse_memory_url("memory://").is_err());
+        assert!(matches!(
+            parse_memory_url("ws://a"),
+            Err(NetworkError::UnsupportedTransport(_))
+        ));
+        assert_ne!(MemoryTransport::new().url(), MemoryTransport::new().url());
+    }
+
+    #[tokio::test]
+    async fn test_call_stream_and_events_in_memory() {
+        let transport = MemoryTransport::new();
+        let server = echo_server();
+        server
+            .register_stream_handler("count", |payload| async move {
+                let n = payload["n"].as_u64().unwrap_or(0);
+                Ok(futures_util::stream::iter(
+                    (0..n).map(|i| Ok(json!({ "i": i }))),
+                ))
+            })
+            .await;
+        let shutdown = server.shutdown_controller().clone();
+        let registry = server.connections().clone();
+        let serving = transport.serve(server).unwrap();
+
+        let mut client = transport.connect().await.unwrap();
+        assert!(client.is_connected().await);
+        let response = UnisonClient::call(&client, "echo", json!({ "text": "hi" }))
+            .await
+            .unwrap();
+        assert_eq!(response["text"], "hi");
+        assert!(
+            UnisonClient::call(&client, "missing", Value::Null)
+                .await
+                .is_err()
+        );
+
+        let items: Vec<Value> = client
+            .stream::<Value, Value>("count", json!({ "n": 2 }))
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(items, vec![json!({ "i": 0 }), json!({ "i": 1 })]);
+
+        let event =
+            ProtocolMessage::new_with_json(0, "notice".into(), MessageType::Event, json!({}))
+                .unwrap()
+                .into_frame()
+                .unwrap();
+        registry.broadcast_frame(event.to_bytes()).wait().await;
+        assert_eq!(client.receive_event().await.unwrap().method, "notice");
+
+        UnisonClient::disconnect(&mut client).await.unwrap();
+        shutdown.trigger();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_name_is_released_after_stop() {
+        let transport = MemoryTransport::named("release-test");
+        let server = echo_server();
+        let shutdown = server.shutdown_controller().clone();
+        let serving = transport.serve(server).unwrap();
+        assert!(transport.serve(echo_server()).is_err());
+
+        shutdown.trigger();
+        serving.await.unwrap().unwrap();
+        assert!(transport.connect().await.is_err());
+
+        let server = echo_server();
+        let shutdown = server.shutdown_controller().clone();
+        let serving = transport.serve(server).unwrap();
+        let client = transport.connect().await.unwrap();
+        assert_eq!(
+            UnisonClient::call(&client, "echo", json!(1)).await.unwrap(),
+            json!(1)
+        );
+        shutdown.trigger();
+        serving.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,381 @@
+//! Prometheus互換のメトリクス（`metrics`フィーチャー）
+//!
+//! [`Metrics`]はメソッドと結果ごとの呼び出し数・処理時間のヒストグラム・送受信したバイト数・
+//! フレームの圧縮率を集計します。サーバーとクライアントがそれぞれ1つずつ持ち、
+//! [`MetricsSnapshot`]として取得できます。
+//!
+//! - サーバー: [`ProtocolServer::metrics`](super::ProtocolServer::metrics)（接続数・ストリーム数・
+//!   ハンドラーの待ち行列の長さを含む）
+//! - クライアント: [`ProtocolClient::metrics`](super::ProtocolClient::metrics)
+//!
+//! [`MetricsSnapshot::to_prometheus`]でPrometheusのテキスト形式に変換できるため、
+//! 任意のHTTPサーバーの`/metrics`から返せます。
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 処理時間のヒストグラムの区切り（秒）
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// 成功した呼び出しの結果のラベル（失敗はエラーコード）
+pub const STATUS_OK: &str = "ok";
+
+/// 処理時間のヒストグラム
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// 区切りごとの累積の件数（[`LATENCY_BUCKETS`]と同じ順序）
+    pub buckets: Vec<u64>,
+    /// 合計（秒）
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// フレームの圧縮の集計
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressionStats {
+    pub frames: u64,
+    /// 圧縮されたフレームの数
+    pub compressed_frames: u64,
+    /// 圧縮前のペイロードのバイト数
+    pub uncompressed_bytes: u64,
+    /// 送受信したペイロードのバイト数（圧縮されていればその大きさ）
+    pub wire_bytes: u64,
+}
+
+impl CompressionStats {
+    /// 送受信したバイト数と圧縮前のバイト数の比（フレームがなければ1.0）
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed_bytes == 0 {
+            return 1.0;
+        }
+        self.wire_bytes as f64 / self.uncompressed_bytes as f64
+    }
+}
+
+/// ある時点のメトリクス
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// メトリクス名の接頭辞（`unison_server`・`unison_client`）
+    pub prefix: String,
+    /// メソッド・結果（`ok`またはエラーコード）ごとの呼び出し数
+    pub requests: BTreeMap<String, BTreeMap<String, u64>>,
+    /// メソッドごとの処理時間
+    pub latency: BTreeMap<String, Histogram>,
+    /// メソッドごとの送信したペイロードのバイト数
+    pub bytes_sent: BTreeMap<String, u64>,
+    /// メソッドごとの受信したペイロードのバイト数
+    pub bytes_received: BTreeMap<String, u64>,
+    pub compression: CompressionStats,
+    pub active_connections: u64,
+    pub active_streams: u64,
+    /// 実行中のハンドラーの数
+    pub in_flight: u64,
+    /// 空きを待っているリクエストの数
+    pub queue_depth: u64,
+}
+
+impl MetricsSnapshot {
+    /// Prometheusのテキスト形式（バージョン0.0.4）に変換
+    pub fn to_prometheus(&self) -> String {
+        let mut out = TextWriter {
+            prefix: &self.prefix,
+            text: String::new(),
+        };
+
+        out.header("requests_total", "counter", "Calls by method and status");
+        for (method, statuses) in &self.requests {
+            for (status, count) in statuses {
+                let labels = format!(
+                    "method=\"{}\",status=\"{}\"",
+                    escape(method),
+                    escape(status)
+                );
+                out.sample("requests_total", &labels, *count as f64);
+            }
+        }
+
+        let duration = "request_duration_seconds";
+        out.header(duration, "histogram", "Call latency in seconds");
+        for (method, histogram) in &self.latency {
+            let method = escape(method);
+            let bounds = LATENCY_BUCKETS.iter().map(f64::to_string);
+            let counts = histogram.buckets.iter().map(|count| *count as f64);
+            let infinity = ("+Inf".to_string(), histogram.count as f64);
+            for (bound, count) in bounds.zip(counts).chain([infinity]) {
+                let labels = format!("method=\"{method}\",le=\"{bound}\"");
+                out.sample(&format!("{duration}_bucket"), &labels, count);
+            }
+            let labels = format!("method=\"{method}\"");
+            out.sample(&format!("{duration}_sum"), &labels, histogram.sum);
+            out.sample(
+                &format!("{duration}_count"),
+                &labels,
+                histogram.count as f64,
+            );
+        }
+
+        let bytes = [
+            ("bytes_sent_total", "Payload bytes sent", &self.bytes_sent),
+            (
+                "bytes_received_total",
+                "Payload bytes received",
+                &self.bytes_received,
+            ),
+        ];
+        for (name, help, bytes) in bytes {
+            out.header(name, "counter", help);
+            for (method, bytes) in bytes {
+                out.sample(
+                    name,
+                    &format!("method=\"{}\"", escape(method)),
+                    *bytes as f64,
+                );
+            }
+        }
+
+        let compression = &self.compression;
+        let counters = [
+            (
+                "frames_total",
+                "Frames sent and received",
+                compression.frames,
+            ),
+            (
+                "compressed_frames_total",
+                "Frames with a compressed payload",
+                compression.compressed_frames,
+            ),
+            (
+                "frame_uncompressed_bytes_total",
+                "Frame payload bytes before compression",
+                compression.uncompressed_bytes,
+            ),
+            (
+                "frame_wire_bytes_total",
+                "Frame payload bytes on the wire",
+                compression.wire_bytes,
+            ),
+        ];
+        for (name, help, value) in counters {
+            out.header(name, "counter", help);
+            out.sample(name, "", value as f64);
+        }
+
+        let gauges = [
+            (
+                "compression_ratio",
+                "Wire bytes divided by uncompressed bytes",
+                compression.ratio(),
+            ),
+            (
+                "active_connections",
+                "Open connections",
+                self.active_connections as f64,
+            ),
+            ("active_streams", "Open streams", self.active_streams as f64),
+            (
+                "handlers_in_flight",
+                "Handlers currently running",
+                self.in_flight as f64,
+            ),
+            (
+                "handler_queue_depth",
+                "Requests waiting for a handler slot",
+                self.queue_depth as f64,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            out.header(name, "gauge", help);
+            out.sample(name, "", value);
+        }
+        out.text
+    }
+}
+
+struct TextWriter<'a> {
+    prefix: &'a str,
+    text: String,
+}
+
+impl TextWriter<'_> {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let prefix = self.prefix;
+        let _ = writeln!(self.text, "# HELP {prefix}_{name} {help}");
+        let _ = writeln!(self.text, "# TYPE {prefix}_{name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &str, value: f64) {
+        let prefix = self.prefix;
+        if labels.is_empty() {
+            let _ = writeln!(self.text, "{prefix}_{name} {value}");
+        } else {
+            let _ = writeln!(self.text, "{prefix}_{name}{{{labels}}} {value}");
+        }
+    }
+}
+
+/// ラベルの値のエスケープ
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// 呼び出しとフレームのメトリクスの集計
+#[derive(Clone)]
+pub struct Metrics {
+    prefix: Arc<str>,
+    inner: Arc<Mutex<MetricsSnapshot>>,
+}
+
+impl Metrics {
+    /// `prefix`を接頭辞とするメトリクス（`unison_server`など）
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.into(),
+            inner: Arc::default(),
+        }
+    }
+
+    /// 呼び出しの結果と処理時間を記録
+    pub fn record_request(&self, method: &str, status: &str, latency: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner
+            .requests
+            .entry(method.to_string())
+            .or_default()
+            .entry(status.to_string())
+            .or_default() += 1;
+        inner
+            .latency
+            .entry(method.to_string())
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    /// 呼び出しで送受信したペイロードのバイト数を記録
+    pub fn record_bytes(&self, method: &str, sent: u64, received: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.bytes_sent.entry(method.to_string()).or_default() += sent;
+        *inner.bytes_received.entry(method.to_string()).or_default() += received;
+    }
+
+    /// フレームのペイロードの大きさを記録（`compressed_length`が0なら圧縮なし）
+    pub fn record_frame(&self, payload_length: u32, compressed_length: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        let compression = &mut inner.compression;
+        compression.frames += 1;
+        compression.uncompressed_bytes += u64::from(payload_length);
+        if compressed_length > 0 {
+            compression.compressed_frames += 1;
+            compression.wire_bytes += u64::from(compressed_length);
+        } else {
+            compression.wire_bytes += u64::from(payload_length);
+        }
+    }
+
+    /// 集計したメトリクス（接続数などのゲージは0）
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            prefix: self.prefix.to_string(),
+            ..self.inner.lock().unwrap().clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_and_latency_histogram() {
+        let metrics = Metrics::new("unison_server");
+        metrics.record_request("get", STATUS_OK, Duration::from_millis(3));
+        metrics.record_request("get", STATUS_OK, Duration::from_millis(300));
+        metrics.record_request("get", "404", Duration::from_millis(1));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.requests["get"][STATUS_OK], 2);
+        assert_eq!(snapshot.requests["get"]["404"], 1);
+        let latency = &snapshot.latency["get"];
+        assert_eq!(latency.count, 3);
+        // 0.001秒以下は1件、0.005秒以下は2件、0.5秒以下は3件
+        assert_eq!(latency.buckets[0], 1);
+        assert_eq!(latency.buckets[1], 2);
+        assert_eq!(latency.buckets[7], 3);
+        assert!((latency.sum - 0.304).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        let metrics = Metrics::new("unison_client");
+        assert_eq!(metrics.snapshot().compression.ratio(), 1.0);
+        metrics.record_frame(1000, 250);
+        metrics.record_frame(200, 0);
+
+        let compression = metrics.snapshot().compression;
+        assert_eq!((compression.frames, compression.compressed_frames), (2, 1));
+        assert_eq!(compression.uncompressed_bytes, 1200);
+        assert_eq!(compression.wire_bytes, 450);
+        assert!((compression.ratio() - 0.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let metrics = Metrics::new("unison_server");
+        metrics.record_request("chat.\"send\"", STATUS_OK, Duration::from_millis(20));
+        metrics.record_bytes("chat.\"send\"", 10, 20);
+        let snapshot = MetricsSnapshot {
+            active_connections: 2,
+            queue_depth: 1,
+            ..metrics.snapshot()
+        };
+
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("# TYPE unison_server_requests_total counter\n"));
+        assert!(text.contains(
+            "unison_server_requests_total{method=\"chat.\\\"send\\\"\",status=\"ok\"} 1\n"
+        ));
+        assert!(text.contains(
+            "unison_server_request_duration_seconds_bucket{method=\"chat.\\\"send\\\"\",le=\"0.025\"} 1\n"
+        ));
+        assert!(text.contains(
+            "unison_server_request_duration_seconds_bucket{method=\"chat.\\\"send\\\"\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(
+            text.contains("unison_server_bytes_received_total{method=\"chat.\\\"send\\\"\"} 20\n")
+        );
+        assert!(text.contains("# TYPE unison_server_active_connections gauge\n"));
+        assert!(text.contains("unison_server_active_connections 2\n"));
+        assert!(text.contains("unison_server_handler_queue_depth 1\n"));
+        assert!(text.contains("unison_server_compression_ratio 1\n"));
+    }
+}
@@ -2,22 +2,211 @@ use anyhow::Result;
 use futures_util::Stream;
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::pin::Pin;
 use thiserror::Error;
 
+use crate::core::UnisonError;
 use crate::packet::{RkyvPayload, SerializationError, UnisonPacket};
 
+pub mod admission;
+pub mod assets;
+pub mod broadcast;
 pub mod client;
+pub mod coalesce;
+pub mod connection_stats;
+pub mod content;
+pub mod context;
+pub mod deadline;
+pub mod decode;
+pub mod discovery;
+pub mod drain;
+pub mod encoding;
+pub mod envelope;
+pub mod events;
+pub mod exactly_once;
+pub mod failover;
+pub mod flow;
+pub mod framing;
+pub mod handler;
+pub mod handshake;
+pub mod happy_eyeballs;
+pub mod heartbeat;
+pub mod history;
+pub mod i18n;
+pub mod introspection;
+pub mod lifecycle;
+pub mod lsp;
+pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod offline;
+pub mod paging;
+pub mod pipe;
+pub mod pool;
+pub mod presence;
+pub mod proxy;
+pub mod pubsub;
 pub mod quic;
+pub mod quota;
+pub mod rate_limit;
+pub mod reconnect;
+pub mod resolver;
+pub mod resume;
+pub mod retry;
+pub mod schema_reload;
 pub mod server;
 pub mod service;
-
+pub mod shutdown;
+pub mod slo;
+pub mod state;
+pub mod stats;
+pub mod stdio;
+pub mod stream_id;
+pub mod supervisor;
+pub mod tenant;
+pub mod tls;
+#[cfg(feature = "tower")]
+pub mod tower_adapter;
+pub mod trace;
+pub mod udp;
+pub mod usage;
+pub mod websocket;
+
+pub use admission::{
+    AdmissionConfig, AdmissionController, AdmissionPermit, AdmissionRejected, AdmissionScope,
+    AdmissionStats, ConcurrencyLimit, InFlightStats, RejectReason,
+};
+pub use assets::{
+    Asset, AssetClient, AssetError, AssetInfo, AssetService, AssetSource, DEFAULT_ASSET_PREFIX,
+    DirectoryAssets, ETAG_METADATA_KEY, EmbeddedAssets, StaticAssets,
+};
+pub use broadcast::{
+    BroadcastConfig, BroadcastHandle, BroadcastProgress, ConnectionId, ConnectionRegistry,
+    MessageSink,
+};
 pub use client::ProtocolClient;
+pub use coalesce::{CoalesceConfig, CoalesceStats, RequestCoalescer};
+pub use connection_stats::{ConnectionStats, SystemStreamStats};
+pub use content::{
+    CONTENT_TYPE_METADATA_KEY, ContentError, PayloadEncoding, method_encodings, negotiate,
+};
+pub use context::RequestContext;
+pub use deadline::CallOptions;
+pub use decode::{DEFAULT_BLOCKING_DECODE_THRESHOLD, DecodeConfig, decode_frame};
+#[cfg(feature = "hickory-dns")]
+pub use discovery::SrvDiscovery;
+pub use discovery::{
+    DiscoverFuture, DiscoveryError, EndpointDiscovery, FnDiscovery, StaticEndpoints,
+};
+pub use drain::{
+    DRAIN_STATUS_METHOD, DrainStatus, STREAM_AGE_BUCKETS, StreamAgeBucket, StreamGuard,
+    StreamTracker,
+};
+pub use encoding::{
+    AdaptiveEncodingConfig, ConnectionEncoding, ENCODING_METHOD, EncodingProfile, EncodingSelector,
+};
+pub use envelope::{MESSAGE_ID_METADATA_KEY, Request, Response, request_metadata};
+pub use events::{CloseReason, SERVER_EVENT_CAPACITY, ServerEvent};
+pub use exactly_once::{
+    DEFAULT_DEDUP_CAPACITY, DEFAULT_DEDUP_WINDOW, DedupWindow, ExactlyOnceConfig,
+    IDEMPOTENCY_CREDENTIAL_METADATA_KEY, IDEMPOTENCY_KEY_METADATA_KEY, REPLAYED_METADATA_KEY,
+};
+pub use failover::{
+    BalanceStrategy, DRAIN_EVENT_METHOD, EndpointSelector, FailoverConfig, FailoverError,
+    PriorityBalance, RandomBalance, RoundRobinBalance,
+};
+pub use flow::{FlowControlConfig, SendWindow, WindowError};
+pub use framing::{WireFormat, read_frame, write_frame};
+pub use handler::{CacheControl, HandlerMetrics, HandlerOptions, HandlerResponse};
+pub use handshake::{
+    Capabilities, HANDSHAKE_CLOSE_CODE, HANDSHAKE_METHOD, HandshakeError, PROTOCOL_VERSION,
+    SchemaIdentity,
+};
+pub use happy_eyeballs::HappyEyeballsConfig;
+pub use heartbeat::{HEARTBEAT_CLOSE_CODE, HEARTBEAT_METHOD, HeartbeatConfig, LatencyStats};
+pub use history::{
+    FRAME_HISTORY_METHOD, FrameDirection, FrameHistory, FrameHistoryAuthorizer, FrameHistoryConfig,
+    RecordedFrame,
+};
+pub use i18n::{ErrorCatalog, LOCALE_METADATA_KEY};
+pub use lifecycle::{
+    DEFAULT_HEALTH_CHECK_INTERVAL, RestartPolicy, SERVICE_EVENT_TOPIC, SERVICES_METHOD,
+    ServiceEvent, ServiceLifecycle, ServiceState, ServiceStatus, SupervisionConfig,
+};
+pub use lsp::LspServer;
+pub use memory::{MEMORY_SCHEME, MemoryTransport};
+#[cfg(feature = "metrics")]
+pub use metrics::{
+    CompressionStats, Histogram, LATENCY_BUCKETS, Metrics, MetricsSnapshot, STATUS_OK,
+};
+pub use offline::{OfflineQueue, OfflineQueueConfig, OfflineQueueError, QueuedOutcome};
+pub use paging::{NEXT_PAGE_CURSOR_METADATA_KEY, PAGE_CURSOR_METADATA_KEY, Page, page_cursor};
+pub use pipe::{PIPE_SCHEME, PipeClient, PipeServer};
+pub use pool::{ClientPool, PoolConfig, PoolMemberStatus, PoolSelection};
+pub use presence::{
+    Presence, PresenceConfig, PresenceError, PresenceState, PresenceStatus, PresenceWatch,
+};
+pub use proxy::{ProxyConfig, ProxyCredentials, ProxyError, ProxyKind, Socks5UdpSocket};
+pub use pubsub::{
+    DurableConfig, Filter, FilterError, MemoryOffsetStore, OffsetStore, PatternStats, PubSub,
+    PubSubError, PubSubStats, RetentionPolicy, SubscribeRequest, Subscription, SubscriptionId,
+    TopicError, TopicPattern,
+};
 pub use quic::{QuicClient, QuicServer, UnisonStream};
-pub use server::ProtocolServer;
+pub use quota::{
+    ByteBudget, ConnectionMemory, MemoryQuotaConfig, MemoryReservation, MemoryUsage, QuotaError,
+};
+pub use rate_limit::{
+    RETRY_AFTER_METADATA_KEY, RateLimit, RateLimitConfig, RateLimitExceeded, RateLimitScope,
+    RateLimiter,
+};
+pub use reconnect::{ReconnectError, ReconnectPolicy};
+#[cfg(feature = "hickory-dns")]
+pub use resolver::HickoryResolver;
+pub use resolver::{
+    CachingResolver, DnsCacheConfig, Resolution, ResolveError, Resolver, SystemResolver,
+};
+pub use resume::{ResumableStream, ResumeConfig, ResumeToken, StreamEvent};
+pub use retry::{RetryClass, RetryPolicy};
+pub use schema_reload::{SCHEMA_CHANGED_EVENT_METHOD, SchemaChange, SchemaReloadError};
+pub use server::{ClientStreamRequests, ProtocolServer};
 pub use service::{
-    RealtimeService, Service, ServiceConfig, ServicePriority, ServiceStats, UnisonService,
+    LatencyInjection, RealtimeService, Service, ServiceConfig, ServicePriority, ServiceStats,
+    UnisonService,
+};
+pub use shutdown::{
+    DEFAULT_SHUTDOWN_GRACE, DrainProgress, GOAWAY_CLOSE_CODE, GOAWAY_EVENT_METHOD, InFlightGuard,
+    ShutdownController,
+};
+pub use slo::{LatencySlo, SLO_VIOLATION_TOPIC, SloStatus, SloTracker, SloViolation};
+pub use state::{ConnectionState, ConnectionStateMachine, StateEvent};
+pub use stats::{MethodStats, STATS_METHOD, StatsRecorder, StreamRecord, StreamStats};
+pub use stdio::{StdioClient, StdioServer, decode_line, encode_line};
+pub use stream_id::{
+    MAX_STREAM_SEQUENCE, StreamIdAllocator, StreamIdConfig, StreamIdError, StreamIdScheme,
+    StreamInitiator, stream_epoch,
+};
+pub use supervisor::TaskSupervisor;
+pub use tenant::{
+    TENANT_METADATA_KEY, TenantConfig, TenantError, TenantId, TenantRateLimit, TenantStats,
+    Tenants, current_tenant,
+};
+pub use tls::{
+    CertificatePin, CertificateReloader, ClientAuth, ClientTlsConfig, TlsConfig, TlsError,
+    Verification,
+};
+#[cfg(feature = "tower")]
+pub use tower_adapter::{ClientService, tower_error};
+pub use trace::{TRACEPARENT_METADATA_KEY, TraceContext};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use udp::IoUringUdpSocket;
+pub use udp::{IoUringConfig, UdpBackend, UdpSocketConfig};
+pub use usage::{
+    QUOTA_RESET_METADATA_KEY, QUOTA_USAGE_METHOD, QuotaExceeded, QuotaResource, QuotaWindow, Usage,
+    UsageConfig, UsageKey, UsageQuota, UsageTracker,
 };
+pub use websocket::{WS_SCHEME, WSS_SCHEME, WebSocketClient, WebSocketServer};
 
 /// Unison Protocolのネットワークエラー
 #[derive(Error, Debug)]
@@ -40,9 +229,34 @@ pub enum NetworkError {
     NotConnected,
     #[error("Unsupported transport: {0}")]
     UnsupportedTransport(String),
+    /// 送信の書き込み待ちが上限に達している（`try_send`）
+    #[error("Send would block")]
+    WouldBlock,
+    /// 接続直後のハンドシェイクで相手と互換性がなかった
+    #[error(transparent)]
+    Handshake(#[from] handshake::HandshakeError),
+    /// クライアントの停止中で、新しい呼び出しを受け付けない
+    #[error("Client is shutting down")]
+    ShuttingDown,
+    /// サーバーのハンドラーがエラーを返した（`code`で種類を区別できる）
+    #[error("Remote error: {0}")]
+    Remote(UnisonError),
+    /// ペイロードのエンコーディングに対応していない、または変換できなかった
+    #[error(transparent)]
+    Content(#[from] content::ContentError),
+}
+
+impl From<UnisonError> for NetworkError {
+    fn from(error: UnisonError) -> Self {
+        NetworkError::Remote(error)
+    }
 }
 
 /// プロトコルメッセージラッパー
+///
+/// rkyvでアーカイブされるワイヤー上の構造のため、フィールドの追加・削除は
+/// 以前のバージョンのピアと互換性のない変更になります（`metadata`の追加により
+/// 0.1.0-alpha3以前のピアとは通信できません）。
 #[derive(Debug, Clone, Serialize, Deserialize, Archive, RkyvSerialize, RkyvDeserialize)]
 #[archive(check_bytes)]
 pub struct ProtocolMessage {
@@ -51,6 +265,9 @@ pub struct ProtocolMessage {
     #[serde(rename = "type")]
     pub msg_type: MessageType,
     pub payload: String, // JSON文字列として保持してrkyv互換に
+    /// メタデータ（JSONオブジェクトの文字列。空文字列はメタデータなし）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub metadata: String,
 }
 
 /// フレームでラップされたプロトコルメッセージの型エイリアス
@@ -81,12 +298,73 @@ impl ProtocolMessage {
             method,
             msg_type,
             payload: serde_json::to_string(&payload)?,
+            metadata: String::new(),
         })
     }
 
-    /// payloadをserde_json::Valueとして取得
+    /// メタデータを設定
+    pub fn with_metadata(
+        mut self,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Self, NetworkError> {
+        self.metadata = if metadata.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(metadata)?
+        };
+        Ok(self)
+    }
+
+    /// メタデータを取得
+    pub fn metadata(&self) -> Result<HashMap<String, String>, NetworkError> {
+        if self.metadata.is_empty() {
+            return Ok(HashMap::new());
+        }
+        Ok(serde_json::from_str(&self.metadata)?)
+    }
+
+    /// 指定したエンコーディングでペイロードを格納したメッセージを作成
+    ///
+    /// JSON以外の場合はメタデータの[`CONTENT_TYPE_METADATA_KEY`]にエンコーディングを記録します。
+    pub fn new_encoded(
+        id: u64,
+        method: String,
+        msg_type: MessageType,
+        payload: &serde_json::Value,
+        encoding: PayloadEncoding,
+    ) -> Result<Self, NetworkError> {
+        let mut message = Self {
+            id,
+            method,
+            msg_type,
+            payload: encoding.encode(payload)?,
+            metadata: String::new(),
+        };
+        if encoding != PayloadEncoding::Json {
+            let metadata =
+                HashMap::from([(CONTENT_TYPE_METADATA_KEY.to_string(), encoding.to_string())]);
+            message = message.with_metadata(&metadata)?;
+        }
+        Ok(message)
+    }
+
+    /// ペイロードのエンコーディング（メタデータに記録がなければJSON）
+    pub fn encoding(&self) -> Result<PayloadEncoding, NetworkError> {
+        if self.metadata.is_empty() {
+            return Ok(PayloadEncoding::Json);
+        }
+        match self.metadata()?.get(CONTENT_TYPE_METADATA_KEY) {
+            Some(name) => Ok(name.parse()?),
+            None => Ok(PayloadEncoding::Json),
+        }
+    }
+
+    /// payloadをserde_json::Valueとして取得（エンコーディングに応じて変換）
     pub fn payload_as_value(&self) -> Result<serde_json::Value, NetworkError> {
-        Ok(serde_json::from_str(&self.payload)?)
+        match self.encoding()? {
+            PayloadEncoding::Json => Ok(serde_json::from_str(&self.payload)?),
+            encoding => Ok(encoding.decode(&self.payload)?),
+        }
     }
 }
 
@@ -112,19 +390,122 @@ pub enum MessageType {
     StreamData,
     StreamEnd,
     StreamError,
+    // ストリーム再開
+    StreamResume,
+    StreamResumeToken,
+    // サーバーからのプッシュ通知（ブロードキャスト等）
+    Event,
     // 双方向ストリーミング種別
     BidirectionalStream,
     StreamSend,
     StreamReceive,
     Error,
+    // クライアントからのストリーム（複数のリクエストに1つのレスポンスを返す）
+    ClientStream,
 }
 
 /// プロトコルエラー
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[error("{message} (code {code})")]
 pub struct ProtocolError {
     pub code: i32,
     pub message: String,
     pub details: Option<serde_json::Value>,
+    /// アプリケーション固有のエラーコード（[`UnisonError::code`]）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+impl ProtocolError {
+    pub const INVALID_REQUEST: i32 = 400;
+    /// 接続のテナントなどの権限で許可されていない
+    pub const PERMISSION_DENIED: i32 = 403;
+    pub const NOT_FOUND: i32 = 404;
+    /// ストリームIDが割り当ての規則に反するか、使用中のIDと衝突した（`details.reason`で理由を区別）
+    pub const STREAM_ID_CONFLICT: i32 = 409;
+    /// ハンドシェイクで互換性がないと判定した（`details.reason`で理由を区別）
+    pub const INCOMPATIBLE: i32 = 426;
+    /// ハンドラーがアプリケーション固有のエラー（[`UnisonError`]）を返した
+    pub const APPLICATION: i32 = 422;
+    /// メソッドがリクエストのペイロードのエンコーディングを受け付けない（`details.accepted`に一覧）
+    pub const UNSUPPORTED_ENCODING: i32 = 415;
+    pub const RATE_LIMITED: i32 = 429;
+    /// 期間内の利用量の上限を超えた（`details.resource`で対象を区別）
+    pub const QUOTA_EXCEEDED: i32 = Self::RATE_LIMITED;
+    /// 接続・メソッドごとのレート制限を超えた（`details.retry_after_ms`後に再試行できる）
+    pub const RESOURCE_EXHAUSTED: i32 = Self::RATE_LIMITED;
+    pub const INTERNAL: i32 = 500;
+    /// サーバーが停止中などで受け付けられない
+    pub const UNAVAILABLE: i32 = 503;
+    /// ハンドラーが実行期限内に完了しなかった
+    pub const DEADLINE_EXCEEDED: i32 = 504;
+    pub const TIMEOUT: i32 = Self::DEADLINE_EXCEEDED;
+
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+            error_code: None,
+        }
+    }
+
+    /// 内部エラー
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(Self::INTERNAL, message)
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// アプリケーション固有のエラーコードを指定
+    pub fn with_error_code(mut self, error_code: impl Into<String>) -> Self {
+        self.error_code = Some(error_code.into());
+        self
+    }
+}
+
+impl From<UnisonError> for ProtocolError {
+    fn from(error: UnisonError) -> Self {
+        Self {
+            code: Self::APPLICATION,
+            message: error.message,
+            details: error.details,
+            error_code: Some(error.code),
+        }
+    }
+}
+
+/// 呼び出し元へ届いたエラー（アプリケーション固有のコードがなければ数値のコードを使う）
+impl From<ProtocolError> for UnisonError {
+    fn from(error: ProtocolError) -> Self {
+        let code = error.error_code.unwrap_or_else(|| error.code.to_string());
+        let mut remote = UnisonError::new(code, error.message);
+        remote.details = error.details;
+        remote
+    }
+}
+
+impl From<NetworkError> for ProtocolError {
+    fn from(error: NetworkError) -> Self {
+        if let NetworkError::Remote(error) = error {
+            return error.into();
+        }
+        let code = match &error {
+            NetworkError::HandlerNotFound { .. } => Self::NOT_FOUND,
+            NetworkError::Serialization(_) => Self::INVALID_REQUEST,
+            NetworkError::Timeout => Self::TIMEOUT,
+            NetworkError::ShuttingDown => Self::UNAVAILABLE,
+            NetworkError::Content(ContentError::NotAccepted { .. } | ContentError::Unknown(_)) => {
+                Self::UNSUPPORTED_ENCODING
+            }
+            NetworkError::Content(ContentError::Decode { .. }) => Self::INVALID_REQUEST,
+            _ => Self::INTERNAL,
+        };
+        Self::new(code, error.to_string())
+    }
 }
 
 /// プロトコル呼び出し用クライアントトレイト (Rust 2024対応)
@@ -139,6 +520,65 @@ pub trait ProtocolClientTrait: Send + Sync {
         TRequest: Serialize + Send + Sync,
         TResponse: for<'de> Deserialize<'de>;
 
+    /// 冪等なメソッドの単項RPC呼び出し
+    ///
+    /// スキーマで`idempotent=#true`と指定されたメソッドの呼び出しに使います。
+    /// [`RetryPolicy`]を設定したクライアントは、接続断やタイムアウトで失敗した場合も再送します。
+    fn call_idempotent<TRequest, TResponse>(
+        &self,
+        method: &str,
+        request: TRequest,
+    ) -> impl std::future::Future<Output = Result<TResponse>> + Send
+    where
+        TRequest: Serialize + Send + Sync,
+        TResponse: for<'de> Deserialize<'de>,
+    {
+        self.call(method, request)
+    }
+
+    /// ペイロードのエンコーディングを選んで単項RPC呼び出し
+    ///
+    /// スキーマで`encodings`を指定したメソッドの呼び出しに使います。`accepted`はメソッドが
+    /// 受け付けるエンコーディング（優先順）で、クライアントが対応するうち最も優先度の高いものを使います。
+    /// 既定の実装はJSONで送信します。
+    fn call_negotiated<TRequest, TResponse>(
+        &self,
+        method: &str,
+        request: TRequest,
+        accepted: &[&str],
+        idempotent: bool,
+    ) -> impl std::future::Future<Output = Result<TResponse>> + Send
+    where
+        TRequest: Serialize + Send + Sync,
+        TResponse: for<'de> Deserialize<'de>,
+    {
+        let _ = accepted;
+        async move {
+            if idempotent {
+                self.call_idempotent(method, request).await
+            } else {
+                self.call(method, request).await
+            }
+        }
+    }
+
+    /// ページに分けて返すメソッドの全ページを順に受け取る
+    ///
+    /// スキーマで`paged=#true`と指定されたメソッドの呼び出しに使います。レスポンスのメタデータに
+    /// 次のページのカーソル（[`NEXT_PAGE_CURSOR_METADATA_KEY`]）がある間、カーソルを付けて
+    /// 同じリクエストを送り直します。既定の実装はメタデータを扱えないため、最初のページだけを返します。
+    fn call_paged<'a, TRequest, TResponse>(
+        &'a self,
+        method: &'a str,
+        request: TRequest,
+    ) -> Pin<Box<dyn Stream<Item = Result<TResponse>> + Send + 'a>>
+    where
+        TRequest: Serialize + Clone + Send + Sync + 'a,
+        TResponse: for<'de> Deserialize<'de> + Send + 'a,
+    {
+        Box::pin(futures_util::stream::once(self.call(method, request)))
+    }
+
     /// ストリーミングRPC呼び出しの開始
     fn stream<TRequest, TResponse>(
         &self,
@@ -150,6 +590,20 @@ pub trait ProtocolClientTrait: Send + Sync {
     where
         TRequest: Serialize + Send + Sync,
         TResponse: for<'de> Deserialize<'de> + Send + 'static;
+
+    /// クライアントストリーミングRPC呼び出し
+    ///
+    /// `requests`の各アイテムを順にサーバーへ送り、ストリームが終わった後に
+    /// サーバーが返す1つのレスポンスを受け取ります（アップロードなど）。
+    fn client_stream<TRequest, TResponse, S>(
+        &self,
+        method: &str,
+        requests: S,
+    ) -> impl std::future::Future<Output = Result<TResponse>> + Send
+    where
+        TRequest: Serialize + Send,
+        TResponse: for<'de> Deserialize<'de>,
+        S: Stream<Item = TRequest> + Send + 'static;
 }
 
 /// プロトコルリクエスト処理用サーバートレイト (Rust 2024対応)
@@ -180,8 +634,11 @@ pub trait UnisonClient: Send + Sync {
     ) -> impl std::future::Future<Output = Result<(), NetworkError>> + Send;
 
     /// リモートプロシージャ呼び出しの実行
+    ///
+    /// リクエストごとに独立したストリームを使うため、`Arc`で共有したクライアントから
+    /// 複数のタスクが同時に呼び出せます。
     fn call(
-        &mut self,
+        &self,
         method: &str,
         payload: serde_json::Value,
     ) -> impl std::future::Future<Output = Result<serde_json::Value, NetworkError>> + Send;
@@ -215,6 +672,14 @@ pub trait UnisonServerExt: UnisonServer {
     where
         F: Fn(serde_json::Value) -> Result<serde_json::Value, NetworkError> + Send + Sync + 'static;
 
+    /// 特定メソッド用の非同期ハンドラーの登録
+    ///
+    /// 登録は同期的に完了し、直後から（`listen`中でも）呼び出せます。
+    fn register_async_handler<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<serde_json::Value, NetworkError>> + Send + 'static;
+
     /// 特定メソッド用ストリームハンドラーの登録
     fn register_stream_handler<F>(&mut self, method: &str, handler: F)
     where
@@ -226,6 +691,15 @@ pub trait UnisonServerExt: UnisonServer {
             + Sync
             + 'static;
 
+    /// 特定メソッド用クライアントストリームハンドラーの登録
+    ///
+    /// ハンドラーはクライアントが送るリクエストのストリームを受け取り、
+    /// ストリームを読み終えた後（または途中で）1つのレスポンスを返します。
+    fn register_client_stream_handler<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(ClientStreamRequests) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<serde_json::Value, NetworkError>> + Send + 'static;
+
     /// 双方向ストリーミング用SystemStreamハンドラーの登録
     fn register_system_stream_handler<F>(&mut self, method: &str, handler: F)
     where
@@ -237,6 +711,19 @@ pub trait UnisonServerExt: UnisonServer {
             + Send
             + Sync
             + 'static;
+
+    /// 型付きハンドラーの登録
+    ///
+    /// リクエストをスキーマ（[`ProtocolServer::with_schema_validation`]）で検証してから
+    /// `Req`に変換し、ハンドラーが返した`Res`をJSONに変換して返します。
+    /// 検証や変換に失敗したリクエストは`INVALID_REQUEST`で拒否され、ハンドラーは呼ばれません。
+    fn register_typed_handler<Req, Res, E, F, Fut>(&mut self, method: &str, handler: F)
+    where
+        Req: serde::de::DeserializeOwned + Send + 'static,
+        Res: Serialize + Send + 'static,
+        E: Into<ProtocolError> + Send + 'static,
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<Res, E>> + Send + 'static;
 }
 
 /// SystemStream - QUIC用双方向ストリームトレイト (Rust 2024対応)
@@ -0,0 +1,237 @@
+//! クライアントのオフラインキュー
+//!
+//! 切断中に発行された通知・呼び出しを上限件数とTTL付きで保持し、
+//! 再接続後に発行順で送信します。各メッセージには送信成功・失敗・期限切れを
+//! 通知するコールバックを登録できます。モバイルなど接続が断続的なピア向けです。
+
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// オフラインキューの設定
+#[derive(Debug, Clone)]
+pub struct OfflineQueueConfig {
+    /// 保持する最大メッセージ数
+    pub capacity: usize,
+    /// キューに入ってから送信を諦めるまでの時間
+    pub ttl: Duration,
+}
+
+impl Default for OfflineQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+/// オフラインキューのエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum OfflineQueueError {
+    #[error("Offline queue is full (capacity {capacity})")]
+    Full { capacity: usize },
+}
+
+/// キューに入れたメッセージの結果
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueuedOutcome {
+    /// 送信され、レスポンスを受信した
+    Delivered(Value),
+    /// 送信したがエラーが返った
+    Failed(String),
+    /// TTL内に再接続できなかった
+    Expired,
+}
+
+/// 結果を受け取るコールバック
+pub type QueuedCallback = Box<dyn FnOnce(QueuedOutcome) + Send>;
+
+/// キュー内のメッセージ
+pub struct QueuedMessage {
+    pub method: String,
+    pub payload: Value,
+    enqueued_at: Instant,
+    callback: Option<QueuedCallback>,
+}
+
+impl QueuedMessage {
+    pub fn new(method: &str, payload: Value, callback: Option<QueuedCallback>) -> Self {
+        Self {
+            method: method.to_string(),
+            payload,
+            enqueued_at: Instant::now(),
+            callback,
+        }
+    }
+
+    /// キューに入ってからの経過時間
+    pub fn age(&self) -> Duration {
+        self.enqueued_at.elapsed()
+    }
+
+    /// 結果をコールバックへ通知
+    pub fn complete(mut self, outcome: QueuedOutcome) {
+        if let Some(callback) = self.callback.take() {
+            callback(outcome);
+        }
+    }
+}
+
+impl fmt::Debug for QueuedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueuedMessage")
+            .field("method", &self.method)
+            .field("payload", &self.payload)
+            .field("age", &self.age())
+            .finish()
+    }
+}
+
+/// 上限件数とTTLを持つFIFOキュー
+pub struct OfflineQueue {
+    config: OfflineQueueConfig,
+    messages: Mutex<VecDeque<QueuedMessage>>,
+}
+
+impl OfflineQueue {
+    pub fn new(config: OfflineQueueConfig) -> Self {
+        Self {
+            config,
+            messages: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn config(&self) -> &OfflineQueueConfig {
+        &self.config
+    }
+
+    /// メッセージを末尾に追加
+    ///
+    /// 期限切れのメッセージを先に取り除き、それでも満杯の場合はエラーを返します。
+    pub fn push(&self, message: QueuedMessage) -> Result<(), OfflineQueueError> {
+        self.expire();
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() >= self.config.capacity {
+            return Err(OfflineQueueError::Full {
+                capacity: self.config.capacity,
+            });
+        }
+        messages.push_back(message);
+        Ok(())
+    }
+
+    /// 送信に失敗したメッセージを先頭へ戻す
+    pub fn push_front(&self, message: QueuedMessage) {
+        self.messages.lock().unwrap().push_front(message);
+    }
+
+    /// 先頭から期限内のメッセージを取り出す
+    ///
+    /// 途中の期限切れメッセージには[`QueuedOutcome::Expired`]を通知します。
+    pub fn pop(&self) -> Option<QueuedMessage> {
+        loop {
+            let message = self.messages.lock().unwrap().pop_front()?;
+            if message.age() < self.config.ttl {
+                return Some(message);
+            }
+            message.complete(QueuedOutcome::Expired);
+        }
+    }
+
+    /// 期限切れのメッセージを取り除き、件数を返す
+    pub fn expire(&self) -> usize {
+        let expired: Vec<QueuedMessage> = {
+            let mut messages = self.messages.lock().unwrap();
+            let (live, expired) = messages
+                .drain(..)
+                .partition(|m: &QueuedMessage| m.age() < self.config.ttl);
+            *messages = live;
+            expired.into_iter().collect()
+        };
+
+        // コールバックはロック外で呼ぶ
+        let count = expired.len();
+        for message in expired {
+            message.complete(QueuedOutcome::Expired);
+        }
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for OfflineQueue {
+    fn default() -> Self {
+        Self::new(OfflineQueueConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn recorder() -> (Arc<Mutex<Vec<QueuedOutcome>>>, impl Fn() -> QueuedCallback) {
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+        let make = {
+            let outcomes = Arc::clone(&outcomes);
+            move || -> QueuedCallback {
+                let outcomes = Arc::clone(&outcomes);
+                Box::new(move |outcome| outcomes.lock().unwrap().push(outcome))
+            }
+        };
+        (outcomes, make)
+    }
+
+    #[test]
+    fn test_fifo_order_and_capacity() {
+        let queue = OfflineQueue::new(OfflineQueueConfig {
+            capacity: 2,
+            ..Default::default()
+        });
+        queue
+            .push(QueuedMessage::new("a", Value::Null, None))
+            .unwrap();
+        queue
+            .push(QueuedMessage::new("b", Value::Null, None))
+            .unwrap();
+        assert_eq!(
+            queue.push(QueuedMessage::new("c", Value::Null, None)),
+            Err(OfflineQueueError::Full { capacity: 2 })
+        );
+
+        let first = queue.pop().unwrap();
+        assert_eq!(first.method, "a");
+        // 送信失敗時は先頭に戻して順序を保つ
+        queue.push_front(first);
+        let order: Vec<String> = std::iter::from_fn(|| queue.pop())
+            .map(|m| m.method)
+            .collect();
+        assert_eq!(order, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_expired_messages_notify_callbacks() {
+        let queue = OfflineQueue::new(OfflineQueueConfig {
+            ttl: Duration::ZERO,
+            ..Default::default()
+        });
+        let (outcomes, callback) = recorder();
+        queue
+            .push(QueuedMessage::new("a", Value::Null, Some(callback())))
+            .unwrap();
+
+        assert!(queue.pop().is_none());
+        assert_eq!(*outcomes.lock().unwrap(), vec![QueuedOutcome::Expired]);
+    }
+}
@@ -0,0 +1,101 @@
+//! ページに分けたレスポンス（カーソルによるページング）
+//!
+//! 大きな一覧を返すメソッドは、1回のレスポンスで全件を返す代わりにページに分けて返します。
+//! カーソルはメタデータで受け渡すため、リクエスト・レスポンスの型にカーソルのフィールドは不要です。
+//!
+//! - クライアントは2ページ目以降のリクエストに、前のレスポンスのカーソルを
+//!   [`PAGE_CURSOR_METADATA_KEY`]で付けて送ります
+//! - サーバーは続きがあれば、レスポンスの[`NEXT_PAGE_CURSOR_METADATA_KEY`]に次のカーソルを付けます
+//!   （付けなければ最後のページ）。ハンドラーは[`page_cursor`]でカーソルを受け取り、
+//!   [`Page`]でレスポンスを返します
+//! - [`ProtocolClientTrait::call_paged`](super::ProtocolClientTrait::call_paged)は
+//!   全ページのレスポンスを順に返すStreamです。スキーマで`paged=#true`を指定したメソッドには、
+//!   これを使う`<メソッド名>_paged`のクライアントメソッドが生成されます
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::envelope::{Response, request_metadata};
+use super::handler::HandlerResponse;
+
+/// 前のページのレスポンスで受け取ったカーソルを送るメタデータのキー
+pub const PAGE_CURSOR_METADATA_KEY: &str = "page-cursor";
+
+/// 次のページのカーソルを返すメタデータのキー（最後のページには付けない）
+pub const NEXT_PAGE_CURSOR_METADATA_KEY: &str = "next-page-cursor";
+
+/// 処理中のリクエストのカーソル
+///
+/// ハンドラーの中で呼び出します。最初のページのリクエストでは`None`です。
+pub fn page_cursor() -> Option<String> {
+    request_metadata()
+        .remove(PAGE_CURSOR_METADATA_KEY)
+        .filter(|cursor| !cursor.is_empty())
+}
+
+/// 1ページ分のレスポンス
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: T,
+    /// 次のページのカーソル（最後のページは`None`）
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// 続きのあるページ
+    pub fn new(items: T, next_cursor: impl Into<String>) -> Self {
+        Self {
+            items,
+            next_cursor: Some(next_cursor.into()),
+        }
+    }
+
+    /// 最後のページ
+    pub fn last(items: T) -> Self {
+        Self {
+            items,
+            next_cursor: None,
+        }
+    }
+}
+
+impl<T> From<Page<T>> for Response<T> {
+    fn from(page: Page<T>) -> Self {
+        let response = Response::new(page.items);
+        match page.next_cursor {
+            Some(cursor) => response.with_metadata(NEXT_PAGE_CURSOR_METADATA_KEY, cursor),
+            None => response,
+        }
+    }
+}
+
+impl<T: Serialize> From<Page<T>> for HandlerResponse {
+    fn from(page: Page<T>) -> Self {
+        Response::from(page).into_handler_response()
+    }
+}
+
+/// レスポンスのメタデータの次のページのカーソル
+pub(crate) fn next_cursor(metadata: &HashMap<String, String>) -> Option<String> {
+    metadata
+        .get(NEXT_PAGE_CURSOR_METADATA_KEY)
+        .filter(|cursor| !cursor.is_empty())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_page_carries_next_cursor_in_metadata() {
+        let response = Response::from(Page::new(vec![1, 2], "2"));
+        assert_eq!(response.body, vec![1, 2]);
+        assert_eq!(next_cursor(&response.metadata).as_deref(), Some("2"));
+
+        let response = HandlerResponse::from(Page::last(json!([3])));
+        assert_eq!(response.outcome.unwrap(), json!([3]));
+        assert_eq!(next_cursor(&response.metadata), None);
+    }
+}
@@ -0,0 +1,302 @@
+//! ローカルIPC用のパイプトランスポート
+//!
+//! `pipe://<name>`のURLで、同じマシン上のプロセス間を1本のバイトストリームで接続します。
+//! メッセージは長さ接頭辞付きの[`ProtocolFrame`](super::ProtocolFrame)
+//! （[`framing`](super::framing)を参照）で送受信します。
+//!
+//! - Windows: 名前付きパイプ`\\.\pipe\<name>`
+//! - Unix: 一時ディレクトリのUnixドメインソケット`<name>.sock`
+//!
+//! [`UnisonServer::listen`](super::UnisonServer::listen)に`pipe://`のURLを渡すと、
+//! QUICの代わりにパイプで待ち受けます。
+
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::framing::Framing;
+use super::server::ProtocolServer;
+use super::stdio::{MessageClient, serve_connection};
+use super::{NetworkError, ProtocolMessage};
+
+/// パイプトランスポートのURLスキーム
+pub const PIPE_SCHEME: &str = "pipe://";
+
+/// `pipe://<name>`からパイプ名を取り出す
+///
+/// 名前は英数字と`-`・`_`・`.`のみ使用できます。
+pub fn parse_pipe_url(url: &str) -> Result<&str, NetworkError> {
+    let name = url
+        .strip_prefix(PIPE_SCHEME)
+        .ok_or_else(|| NetworkError::UnsupportedTransport(url.to_string()))?;
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(NetworkError::Connection(format!(
+            "Invalid pipe name: {:?}",
+            name
+        )));
+    }
+    Ok(name)
+}
+
+/// パイプ名に対応するOS上のパス
+pub fn pipe_path(name: &str) -> PathBuf {
+    #[cfg(windows)]
+    {
+        PathBuf::from(format!(r"\\.\pipe\{}", name))
+    }
+    #[cfg(not(windows))]
+    {
+        std::env::temp_dir().join(format!("{}.sock", name))
+    }
+}
+
+fn io_error(e: std::io::Error) -> NetworkError {
+    NetworkError::Connection(e.to_string())
+}
+
+/// パイプで接続を待ち受けるサーバー
+pub struct PipeServer {
+    server: Arc<ProtocolServer>,
+}
+
+impl PipeServer {
+    pub fn new(server: Arc<ProtocolServer>) -> Self {
+        Self { server }
+    }
+
+    /// `pipe://<name>`で待ち受け、停止が要求されるまで接続を処理
+    ///
+    /// 停止後は猶予期間まで処理中のリクエストの完了を待ってから戻ります。
+    pub async fn listen(&self, url: &str) -> Result<(), NetworkError> {
+        let path = pipe_path(parse_pipe_url(url)?);
+        let mut listener = sys::Listener::bind(&path).map_err(io_error)?;
+        info!("🎵 Unison Protocol server listening on {} via pipe", url);
+
+        let shutdown = self.server.shutdown_controller().clone();
+        let stopped = shutdown.stopped();
+        tokio::pin!(stopped);
+        let connections = self.server.task_supervisor("pipe connections");
+        loop {
+            let stream = tokio::select! {
+                stream = listener.accept() => stream,
+                _ = &mut stopped => break,
+            };
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("Failed to accept pipe connection: {}", e);
+                    continue;
+                }
+            };
+            let server = Arc::clone(&self.server);
+            connections.spawn(async move {
+                let (reader, writer) = tokio::io::split(stream);
+                if let Err(e) =
+                    serve_connection(&server, reader, writer, Framing::LengthPrefixed).await
+                {
+                    error!("Pipe connection error: {}", e);
+                }
+            });
+        }
+
+        drop(listener);
+        connections.join(self.server.shutdown_grace()).await;
+        Ok(())
+    }
+}
+
+/// パイプで接続するクライアント
+pub struct PipeClient {
+    inner: MessageClient,
+}
+
+impl PipeClient {
+    /// `pipe://<name>`で待ち受けているサーバーへ接続
+    pub async fn connect(url: &str) -> Result<Self, NetworkError> {
+        let path = pipe_path(parse_pipe_url(url)?);
+        let stream = sys::connect(&path).await.map_err(io_error)?;
+        let (reader, writer) = tokio::io::split(stream);
+        Ok(Self {
+            inner: MessageClient::new(reader, writer, Framing::LengthPrefixed),
+        })
+    }
+
+    /// リクエストを送信してレスポンスを待機
+    pub async fn call(&self, method: &str, payload: Value) -> Result<Value, NetworkError> {
+        self.inner.call(method, payload).await
+    }
+
+    /// レスポンス以外のメッセージ（イベント・ストリームデータ）を受信
+    ///
+    /// 接続が閉じると`None`を返します。
+    pub async fn next_event(&self) -> Option<ProtocolMessage> {
+        self.inner.next_event().await
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use tokio::net::{UnixListener, UnixStream};
+
+    pub(super) struct Listener {
+        inner: UnixListener,
+        path: PathBuf,
+    }
+
+    impl Listener {
+        pub(super) fn bind(path: &Path) -> io::Result<Self> {
+            if path.exists() {
+                // 接続できるソケットは使用中、できなければ前回のプロセスが残したもの
+                if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is already in use", path.display()),
+                    ));
+                }
+                std::fs::remove_file(path)?;
+            }
+            Ok(Self {
+                inner: UnixListener::bind(path)?,
+                path: path.to_path_buf(),
+            })
+        }
+
+        pub(super) async fn accept(&mut self) -> io::Result<UnixStream> {
+            self.inner.accept().await.map(|(stream, _)| stream)
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    pub(super) async fn connect(path: &Path) -> io::Result<UnixStream> {
+        UnixStream::connect(path).await
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+    use tokio::net::windows::named_pipe::{
+        ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions,
+    };
+
+    /// すべてのパイプインスタンスが使用中であることを示すエラーコード
+    const ERROR_PIPE_BUSY: i32 = 231;
+
+    pub(super) struct Listener {
+        path: PathBuf,
+        /// 次の接続を待つパイプインスタンス
+        next: NamedPipeServer,
+    }
+
+    impl Listener {
+        pub(super) fn bind(path: &Path) -> io::Result<Self> {
+            let next = ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(path)?;
+            Ok(Self {
+                path: path.to_path_buf(),
+                next,
+            })
+        }
+
+        pub(super) async fn accept(&mut self) -> io::Result<NamedPipeServer> {
+            self.next.connect().await?;
+            // 接続したインスタンスを返す前に次のインスタンスを作成する
+            let next = ServerOptions::new().create(&self.path)?;
+            Ok(std::mem::replace(&mut self.next, next))
+        }
+    }
+
+    pub(super) async fn connect(path: &Path) -> io::Result<NamedPipeClient> {
+        loop {
+            match ClientOptions::new().open(path) {
+                Ok(client) => return Ok(client),
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {}
+                Err(e) => return Err(e),
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pipe_url() {
+        assert_eq!(
+            parse_pipe_url("pipe://my-service_1.0").unwrap(),
+            "my-service_1.0"
+        );
+        assert!(matches!(
+            parse_pipe_url("quic://localhost:8080"),
+            Err(NetworkError::UnsupportedTransport(_))
+        ));
+        assert!(parse_pipe_url("pipe://").is_err());
+        assert!(parse_pipe_url("pipe://../etc/passwd").is_err());
+        assert!(parse_pipe_url("pipe://a/b").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_call_over_pipe() {
+        use crate::network::{MessageType, UnisonServer};
+
+        let url = format!("pipe://unison-test-{}", uuid::Uuid::new_v4());
+        let mut server = ProtocolServer::new().with_call_handler("echo", |payload| async move {
+            Ok::<_, NetworkError>(payload)
+        });
+        let shutdown = server.shutdown_controller().clone();
+        let registry = server.connections().clone();
+        let listen_url = url.clone();
+        let listening = tokio::spawn(async move { server.listen(&listen_url).await });
+
+        let client = loop {
+            match PipeClient::connect(&url).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        let response = client
+            .call("echo", serde_json::json!({ "text": "hi" }))
+            .await
+            .unwrap();
+        assert_eq!(response["text"], "hi");
+        assert!(matches!(
+            client.call("missing", Value::Null).await,
+            Err(NetworkError::Remote(e)) if e.code == "404"
+        ));
+
+        let event = ProtocolMessage::new_with_json(
+            0,
+            "notice".into(),
+            MessageType::Event,
+            serde_json::json!({}),
+        )
+        .unwrap()
+        .into_frame()
+        .unwrap();
+        registry.broadcast_frame(event.to_bytes()).wait().await;
+        assert_eq!(client.next_event().await.unwrap().method, "notice");
+
+        shutdown.trigger();
+        listening.await.unwrap().unwrap();
+        #[cfg(unix)]
+        assert!(!pipe_path(parse_pipe_url(&url).unwrap()).exists());
+    }
+}
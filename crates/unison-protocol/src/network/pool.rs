@@ -0,0 +1,466 @@
+//! 複数の上流サーバーへの接続プール
+//!
+//! [`ClientPool`]は1つ以上のサーバーにそれぞれ複数のQUIC接続を張り、呼び出しごとに
+//! ラウンドロビンまたは処理中の呼び出しが最も少ない接続を選びます。
+//! 一定間隔でハートビートと同じping（サーバーのハンドラーは実行されない）を送り、
+//! 応答しない接続は切り離して、次の確認で接続し直します。
+//!
+//! プールは[`UnisonClient`]を実装しているため、単一の[`ProtocolClient`]と同じように使えます。
+//!
+//! ```rust,no_run
+//! # async fn example() -> Result<(), unison::network::NetworkError> {
+//! use unison::network::{ClientPool, PoolConfig, PoolSelection, UnisonClient};
+//!
+//! let mut pool = ClientPool::new(
+//!     PoolConfig::default()
+//!         .with_connections_per_endpoint(4)
+//!         .with_selection(PoolSelection::LeastLoaded),
+//! );
+//! // 接続先はカンマ区切りで指定
+//! pool.connect("[::1]:8080,[::1]:8081").await?;
+//! let response = pool.call("ping", serde_json::Value::Null).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::{NetworkError, ProtocolClient, UnisonClient};
+
+/// 呼び出しに使う接続の選び方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PoolSelection {
+    /// 接続を順番に使う
+    #[default]
+    RoundRobin,
+    /// 処理中の呼び出しが最も少ない接続を使う
+    LeastLoaded,
+}
+
+/// 接続プールの設定
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// 接続先ごとに張る接続の数
+    pub connections_per_endpoint: usize,
+    pub selection: PoolSelection,
+    /// 接続の死活を確認する間隔（切り離した接続の再接続も同じ間隔で行う）
+    pub health_check_interval: Duration,
+    /// pingの応答を待つ時間
+    pub health_check_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            connections_per_endpoint: 1,
+            selection: PoolSelection::default(),
+            health_check_interval: Duration::from_secs(5),
+            health_check_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn with_connections_per_endpoint(mut self, connections: usize) -> Self {
+        self.connections_per_endpoint = connections.max(1);
+        self
+    }
+
+    pub fn with_selection(mut self, selection: PoolSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_health_check(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.health_check_interval = interval;
+        self.health_check_timeout = timeout;
+        self
+    }
+}
+
+/// プールの接続の状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolMemberStatus {
+    pub endpoint: String,
+    pub connected: bool,
+    /// 処理中の呼び出しの数
+    pub in_flight: usize,
+    /// この接続で完了した呼び出しの数
+    pub calls: u64,
+    /// 死活確認や呼び出しの失敗で切り離した回数
+    pub evictions: u64,
+}
+
+/// 接続を作成するコールバック（TLSなどの設定を反映したクライアントを返す）
+type ClientFactory = Arc<dyn Fn() -> Result<ProtocolClient, NetworkError> + Send + Sync>;
+
+/// プールの1つの接続
+struct Member {
+    endpoint: String,
+    client: RwLock<Option<Arc<ProtocolClient>>>,
+    in_flight: AtomicUsize,
+    calls: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Member {
+    fn new(endpoint: String) -> Self {
+        Self {
+            endpoint,
+            client: RwLock::new(None),
+            in_flight: AtomicUsize::new(0),
+            calls: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    fn client(&self) -> Option<Arc<ProtocolClient>> {
+        self.client.read().unwrap().clone()
+    }
+
+    /// 新しい接続を張る（失敗した場合は未接続のまま）
+    async fn connect(&self, factory: &ClientFactory) -> Result<(), NetworkError> {
+        let mut client = factory()?;
+        UnisonClient::connect(&mut client, &self.endpoint).await?;
+        *self.client.write().unwrap() = Some(Arc::new(client));
+        Ok(())
+    }
+
+    /// 接続を切り離す（処理中の呼び出しがなければ切断する）
+    async fn evict(&self, reason: &str) {
+        let Some(client) = self.client.write().unwrap().take() else {
+            return;
+        };
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            "Evicting pooled connection to {}: {}",
+            self.endpoint, reason
+        );
+        if let Ok(mut client) = Arc::try_unwrap(client) {
+            let _ = UnisonClient::disconnect(&mut client).await;
+        }
+    }
+
+    fn status(&self) -> PoolMemberStatus {
+        PoolMemberStatus {
+            endpoint: self.endpoint.clone(),
+            connected: self
+                .client()
+                .is_some_and(|client| UnisonClient::is_connected(&*client)),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            calls: self.calls.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 処理中の呼び出しを数える
+struct InFlight<'a>(&'a Member);
+
+impl<'a> InFlight<'a> {
+    fn new(member: &'a Member) -> Self {
+        member.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(member)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct Shared {
+    config: PoolConfig,
+    factory: ClientFactory,
+    members: RwLock<Vec<Arc<Member>>>,
+    next: AtomicUsize,
+}
+
+impl Shared {
+    fn members(&self) -> Vec<Arc<Member>> {
+        self.members.read().unwrap().clone()
+    }
+
+    /// 設定した方法で接続中の接続を選ぶ
+    fn select(&self) -> Option<(Arc<Member>, Arc<ProtocolClient>)> {
+        let candidates: Vec<_> = self
+            .members()
+            .into_iter()
+            .filter_map(|member| {
+                let client = member.client()?;
+                UnisonClient::is_connected(&*client).then_some((member, client))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+        let index = match self.config.selection {
+            PoolSelection::RoundRobin => start,
+            // 同じ数の場合も偏らないよう、ラウンドロビンの位置から探す
+            PoolSelection::LeastLoaded => (0..candidates.len())
+                .map(|offset| (start + offset) % candidates.len())
+                .min_by_key(|&i| candidates[i].0.in_flight.load(Ordering::Relaxed))
+                .unwrap_or(start),
+        };
+        candidates.into_iter().nth(index)
+    }
+
+    /// 接続中の接続にpingを送り、応答しないものを切り離し、未接続のものを接続し直す
+    async fn check_health(&self) {
+        let checks = self.members().into_iter().map(|member| async move {
+            match member.client() {
+                Some(client) => {
+                    let ping = tokio::time::timeout(
+                        self.config.health_check_timeout,
+                        client.measure_latency(1),
+                    )
+                    .await;
+                    match ping {
+                        Ok(Ok(_)) => {}
+                        // QUIC以外の接続はpingできないため、接続状態のみ確認する
+                        Ok(Err(NetworkError::UnsupportedTransport(_)))
+                            if UnisonClient::is_connected(&*client) => {}
+                        Ok(Err(e)) => member.evict(&e.to_string()).await,
+                        Err(_) => member.evict("health check timed out").await,
+                    }
+                }
+                None => {
+                    if let Err(e) = member.connect(&self.factory).await {
+                        debug!("Reconnecting to {} failed: {}", member.endpoint, e);
+                    }
+                }
+            }
+        });
+        futures_util::future::join_all(checks).await;
+    }
+}
+
+/// 複数の上流サーバーへの接続プール
+pub struct ClientPool {
+    shared: Arc<Shared>,
+    health_task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ClientPool {
+    /// 既定の設定（[`ProtocolClient::new_default`]）で接続を作成するプール
+    pub fn new(config: PoolConfig) -> Self {
+        Self::with_client_factory(config, || {
+            ProtocolClient::new_default().map_err(|e| NetworkError::Connection(format!("{:#}", e)))
+        })
+    }
+
+    /// 接続ごとに`factory`で作成したクライアントを使うプール（TLSやハートビートの設定など）
+    pub fn with_client_factory<F>(config: PoolConfig, factory: F) -> Self
+    where
+        F: Fn() -> Result<ProtocolClient, NetworkError> + Send + Sync + 'static,
+    {
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                factory: Arc::new(factory),
+                members: RwLock::new(Vec::new()),
+                next: AtomicUsize::new(0),
+            }),
+            health_task: Mutex::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.shared.config
+    }
+
+    /// 接続先ごとに設定した数の接続を張る
+    ///
+    /// 1つでも接続できれば成功とし、接続できなかったものは死活確認のたびに接続し直します。
+    pub async fn connect_endpoints<I, S>(&mut self, endpoints: I) -> Result<(), NetworkError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.close().await;
+        let per_endpoint = self.shared.config.connections_per_endpoint.max(1);
+        let members: Vec<_> = endpoints
+            .into_iter()
+            .map(Into::into)
+            .flat_map(|endpoint: String| {
+                (0..per_endpoint).map(move |_| Arc::new(Member::new(endpoint.clone())))
+            })
+            .collect();
+        if members.is_empty() {
+            return Err(NetworkError::Connection("No endpoints given".to_string()));
+        }
+        *self.shared.members.write().unwrap() = members.clone();
+
+        let factory = &self.shared.factory;
+        let results =
+            futures_util::future::join_all(members.iter().map(|member| member.connect(factory)))
+                .await;
+        let connected = results.iter().filter(|result| result.is_ok()).count();
+        if connected == 0 {
+            let last_error = results
+                .into_iter()
+                .filter_map(Result::err)
+                .last()
+                .map(|e| e.to_string())
+                .unwrap_or_default();
+            self.shared.members.write().unwrap().clear();
+            return Err(NetworkError::Connection(format!(
+                "All {} pooled connections failed (last error: {})",
+                members.len(),
+                last_error
+            )));
+        }
+        if connected < members.len() {
+            warn!(
+                "Connected {} of {} pooled connections, retrying the rest in the background",
+                connected,
+                members.len()
+            );
+        }
+        self.start_health_check();
+        Ok(())
+    }
+
+    /// 接続ごとの状態
+    pub fn status(&self) -> Vec<PoolMemberStatus> {
+        self.shared
+            .members()
+            .iter()
+            .map(|member| member.status())
+            .collect()
+    }
+
+    /// 今すぐ死活確認と再接続を行う
+    pub async fn check_health(&self) {
+        self.shared.check_health().await;
+    }
+
+    fn start_health_check(&self) {
+        let shared = Arc::clone(&self.shared);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(shared.config.health_check_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                shared.check_health().await;
+            }
+        });
+        if let Some(previous) = self.health_task.lock().unwrap().replace(task) {
+            previous.abort();
+        }
+    }
+
+    /// 死活確認を止めて全接続を切断する
+    async fn close(&self) {
+        if let Some(task) = self.health_task.lock().unwrap().take() {
+            task.abort();
+        }
+        let members = std::mem::take(&mut *self.shared.members.write().unwrap());
+        for member in members {
+            let client = member.client.write().unwrap().take();
+            if let Some(Ok(mut client)) = client.map(Arc::try_unwrap) {
+                let _ = UnisonClient::disconnect(&mut client).await;
+            }
+        }
+    }
+}
+
+impl UnisonClient for ClientPool {
+    /// カンマ区切りの接続先に接続する
+    async fn connect(&mut self, url: &str) -> Result<(), NetworkError> {
+        let endpoints: Vec<_> = url
+            .split(',')
+            .map(str::trim)
+            .filter(|endpoint| !endpoint.is_empty())
+            .collect();
+        self.connect_endpoints(endpoints).await
+    }
+
+    async fn call(&self, method: &str, payload: Value) -> Result<Value, NetworkError> {
+        // 送信前に切断を検出した場合のみ、他の接続で呼び出し直す
+        let attempts = self.shared.members.read().unwrap().len().max(1);
+        for _ in 0..attempts {
+            let (member, client) = self.shared.select().ok_or(NetworkError::NotConnected)?;
+            let result = {
+                let _in_flight = InFlight::new(&member);
+                client.call(method, payload.clone()).await
+            };
+            match result {
+                Err(NetworkError::NotConnected) => {
+                    member.evict("connection lost").await;
+                }
+                Err(e @ (NetworkError::Connection(_) | NetworkError::Quic(_))) => {
+                    member.evict(&e.to_string()).await;
+                    return Err(e);
+                }
+                result => {
+                    member.calls.fetch_add(1, Ordering::Relaxed);
+                    return result;
+                }
+            }
+        }
+        Err(NetworkError::NotConnected)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), NetworkError> {
+        self.close().await;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.shared.select().is_some()
+    }
+}
+
+impl Drop for ClientPool {
+    fn drop(&mut self) {
+        if let Some(task) = self.health_task.get_mut().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect_fails_without_endpoints() {
+        let mut pool = ClientPool::new(PoolConfig::default());
+        assert!(pool.connect(" , ").await.is_err());
+        assert!(!pool.is_connected());
+        assert!(matches!(
+            pool.call("ping", Value::Null).await,
+            Err(NetworkError::NotConnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_members_are_reported() {
+        let failures = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&failures);
+        let mut pool = ClientPool::with_client_factory(
+            PoolConfig::default().with_connections_per_endpoint(3),
+            move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Err(NetworkError::Connection("refused".into()))
+            },
+        );
+        let error = pool.connect("a:1,b:2").await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("All 6 pooled connections failed")
+        );
+        assert_eq!(failures.load(Ordering::SeqCst), 6);
+        assert!(pool.status().is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+//! プレゼンス（在席状態）管理
+//!
+//! クライアントは組み込みメソッド[`PRESENCE_SET_METHOD`]で自身のIDと状態
+//! （オンライン・離席・任意のペイロード）を宣言します。状態が変化するとサーバーは
+//! `presence.<id>`トピックへ[`PresenceState`]を発行するため、他のクライアントは
+//! Pub/Subの購読（`presence.#`や個別ID）でロスターの変化を受け取れます。
+//!
+//! 接続が切断された場合や、[`PresenceConfig::timeout`]の間更新がない場合、
+//! サーバーはそのIDをオフラインへ遷移させます。
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use super::broadcast::ConnectionId;
+use super::pubsub::SubscriptionId;
+use super::pubsub::topic::TOPIC_SEPARATOR;
+
+/// プレゼンス宣言用の組み込みメソッド名
+pub const PRESENCE_SET_METHOD: &str = "unison.presence.set";
+/// プレゼンス照会用の組み込みメソッド名
+pub const PRESENCE_QUERY_METHOD: &str = "unison.presence.query";
+/// プレゼンス変化を発行するトピックの接頭辞
+pub const PRESENCE_TOPIC_PREFIX: &str = "presence";
+
+/// プレゼンス変化を発行するトピック名
+pub fn presence_topic(id: &str) -> String {
+    format!("{}{}{}", PRESENCE_TOPIC_PREFIX, TOPIC_SEPARATOR, id)
+}
+
+/// プレゼンスのエラー
+#[derive(Error, Debug)]
+pub enum PresenceError {
+    #[error("Invalid presence id: '{0}'")]
+    InvalidId(String),
+    #[error("Offline status cannot be declared explicitly")]
+    OfflineNotAllowed,
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] serde_json::Error),
+}
+
+/// 在席状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Busy,
+    Offline,
+}
+
+/// IDごとのプレゼンス
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceState {
+    pub id: String,
+    pub status: PresenceStatus,
+    /// アプリケーション定義の任意データ（ステータスメッセージ等）
+    #[serde(default)]
+    pub data: Value,
+    /// 最終更新時刻（UNIXエポックからのミリ秒）
+    pub updated_at: u64,
+}
+
+/// プレゼンス宣言リクエスト
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceSetRequest {
+    pub id: String,
+    pub status: PresenceStatus,
+    #[serde(default)]
+    pub data: Value,
+}
+
+/// プレゼンス照会リクエスト
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresenceQueryRequest {
+    /// 照会するID（省略時はオフライン以外の全ID）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<String>>,
+}
+
+/// ロスター監視の開始結果
+#[derive(Debug, Clone)]
+pub struct PresenceWatch {
+    /// 各IDのプレゼンストピックの購読
+    pub subscriptions: Vec<SubscriptionId>,
+    /// 監視開始時点のロスター
+    pub roster: Vec<PresenceState>,
+}
+
+/// プレゼンスの設定
+#[derive(Debug, Clone)]
+pub struct PresenceConfig {
+    /// 更新がない場合にオフラインとみなすまでの時間
+    pub timeout: Duration,
+    /// タイムアウトを確認する間隔
+    pub sweep_interval: Duration,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            sweep_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+struct PresenceEntry {
+    state: PresenceState,
+    connection_id: Option<ConnectionId>,
+    last_seen: Instant,
+}
+
+/// プレゼンスのレジストリ
+///
+/// 各操作は状態が変化したIDの[`PresenceState`]を返し、発行は呼び出し側が行います。
+#[derive(Clone)]
+pub struct Presence {
+    config: PresenceConfig,
+    entries: Arc<Mutex<HashMap<String, PresenceEntry>>>,
+}
+
+impl Presence {
+    pub fn new(config: PresenceConfig) -> Self {
+        Self {
+            config,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn config(&self) -> &PresenceConfig {
+        &self.config
+    }
+
+    /// 接続のプレゼンスを宣言（更新も兼ねる）
+    ///
+    /// 状態またはデータが変化した場合のみ新しい状態を返します。
+    /// 別の接続が同じIDを宣言した場合は、後から宣言した接続が所有者になります。
+    pub fn set(
+        &self,
+        connection_id: ConnectionId,
+        id: &str,
+        status: PresenceStatus,
+        data: Value,
+    ) -> Result<Option<PresenceState>, PresenceError> {
+        if id.is_empty() || id.contains([TOPIC_SEPARATOR, '*', '#']) {
+            return Err(PresenceError::InvalidId(id.to_string()));
+        }
+        if status == PresenceStatus::Offline {
+            return Err(PresenceError::OfflineNotAllowed);
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let changed = entries
+            .get(id)
+            .is_none_or(|e| e.state.status != status || e.state.data != data);
+        let state = PresenceState {
+            id: id.to_string(),
+            status,
+            data,
+            updated_at: now_millis(),
+        };
+
+        let entry = entries
+            .entry(id.to_string())
+            .or_insert_with(|| PresenceEntry {
+                state: state.clone(),
+                connection_id: None,
+                last_seen: Instant::now(),
+            });
+        entry.connection_id = Some(connection_id);
+        entry.last_seen = Instant::now();
+        if changed {
+            entry.state = state.clone();
+            return Ok(Some(state));
+        }
+        Ok(None)
+    }
+
+    /// 接続の切断により所有するIDをオフラインへ遷移
+    pub fn disconnect(&self, connection_id: ConnectionId) -> Vec<PresenceState> {
+        let mut entries = self.entries.lock().unwrap();
+        entries
+            .values_mut()
+            .filter(|e| e.connection_id == Some(connection_id))
+            .map(go_offline)
+            .collect()
+    }
+
+    /// タイムアウトしたIDをオフラインへ遷移
+    pub fn expire(&self) -> Vec<PresenceState> {
+        let timeout = self.config.timeout;
+        let mut entries = self.entries.lock().unwrap();
+        entries
+            .values_mut()
+            .filter(|e| e.connection_id.is_some() && e.last_seen.elapsed() >= timeout)
+            .map(go_offline)
+            .collect()
+    }
+
+    /// 指定IDのプレゼンスを取得（未知のIDはオフライン扱い）
+    pub fn get(&self, ids: &[String]) -> Vec<PresenceState> {
+        let entries = self.entries.lock().unwrap();
+        ids.iter()
+            .map(|id| match entries.get(id) {
+                Some(entry) => entry.state.clone(),
+                None => PresenceState {
+                    id: id.clone(),
+                    status: PresenceStatus::Offline,
+                    data: Value::Null,
+                    updated_at: 0,
+                },
+            })
+            .collect()
+    }
+
+    /// オフライン以外の全プレゼンス
+    pub fn online(&self) -> Vec<PresenceState> {
+        let entries = self.entries.lock().unwrap();
+        let mut states: Vec<PresenceState> = entries
+            .values()
+            .filter(|e| e.state.status != PresenceStatus::Offline)
+            .map(|e| e.state.clone())
+            .collect();
+        states.sort_by(|a, b| a.id.cmp(&b.id));
+        states
+    }
+
+    /// 組み込みメソッドを処理
+    ///
+    /// プレゼンスのメソッドでなければ`None`を返します。
+    /// 戻り値の2番目は発行すべき状態変化です。
+    pub fn handle_request(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+        payload: Value,
+    ) -> Option<Result<(Value, Option<PresenceState>), PresenceError>> {
+        match method {
+            PRESENCE_SET_METHOD => Some(self.handle_set(connection_id, payload)),
+            PRESENCE_QUERY_METHOD => Some(self.handle_query(payload)),
+            _ => None,
+        }
+    }
+
+    fn handle_set(
+        &self,
+        connection_id: ConnectionId,
+        payload: Value,
+    ) -> Result<(Value, Option<PresenceState>), PresenceError> {
+        let request: PresenceSetRequest = serde_json::from_value(payload)?;
+        let change = self.set(connection_id, &request.id, request.status, request.data)?;
+        Ok((serde_json::json!({}), change))
+    }
+
+    fn handle_query(
+        &self,
+        payload: Value,
+    ) -> Result<(Value, Option<PresenceState>), PresenceError> {
+        let request: PresenceQueryRequest = serde_json::from_value(payload)?;
+        let states = match request.ids {
+            Some(ids) => self.get(&ids),
+            None => self.online(),
+        };
+        Ok((serde_json::to_value(states)?, None))
+    }
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Self::new(PresenceConfig::default())
+    }
+}
+
+fn go_offline(entry: &mut PresenceEntry) -> PresenceState {
+    entry.connection_id = None;
+    entry.state.status = PresenceStatus::Offline;
+    entry.state.updated_at = now_millis();
+    entry.state.clone()
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_set_reports_only_changes() {
+        let presence = Presence::default();
+        let change = presence
+            .set(1, "alice", PresenceStatus::Online, Value::Null)
+            .unwrap();
+        assert_eq!(change.unwrap().status, PresenceStatus::Online);
+
+        // 同じ状態での更新はハートビート扱い
+        assert!(
+            presence
+                .set(1, "alice", PresenceStatus::Online, Value::Null)
+                .unwrap()
+                .is_none()
+        );
+
+        let change = presence
+            .set(
+                1,
+                "alice",
+                PresenceStatus::Away,
+                json!({"message": "lunch"}),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.data["message"], "lunch");
+
+        assert!(matches!(
+            presence.set(1, "a.b", PresenceStatus::Online, Value::Null),
+            Err(PresenceError::InvalidId(_))
+        ));
+        assert!(matches!(
+            presence.set(1, "bob", PresenceStatus::Offline, Value::Null),
+            Err(PresenceError::OfflineNotAllowed)
+        ));
+    }
+
+    #[test]
+    fn test_disconnect_and_takeover() {
+        let presence = Presence::default();
+        presence
+            .set(1, "alice", PresenceStatus::Online, Value::Null)
+            .unwrap();
+        presence
+            .set(1, "bob", PresenceStatus::Online, Value::Null)
+            .unwrap();
+        // 別の接続がaliceを引き継ぐ
+        presence
+            .set(2, "alice", PresenceStatus::Online, Value::Null)
+            .unwrap();
+
+        let offline = presence.disconnect(1);
+        assert_eq!(offline.len(), 1);
+        assert_eq!(offline[0].id, "bob");
+        assert_eq!(offline[0].status, PresenceStatus::Offline);
+
+        let online: Vec<String> = presence.online().into_iter().map(|s| s.id).collect();
+        assert_eq!(online, vec!["alice"]);
+        assert_eq!(
+            presence.get(&["carol".to_string()])[0].status,
+            PresenceStatus::Offline
+        );
+    }
+
+    #[test]
+    fn test_expire_after_timeout() {
+        let presence = Presence::new(PresenceConfig {
+            timeout: Duration::ZERO,
+            ..Default::default()
+        });
+        presence
+            .set(1, "alice", PresenceStatus::Online, Value::Null)
+            .unwrap();
+
+        let expired = presence.expire();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].status, PresenceStatus::Offline);
+        // 一度オフラインになったIDは再度通知しない
+        assert!(presence.expire().is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+//! 永続購読（デュラブルサブスクリプション）
+//!
+//! 永続名を付けて購読すると、サーバーはトピックごとに確認応答（ack）済みの
+//! オフセットを記録します。切断中に発行されたメッセージはトピックの保持ログに残り、
+//! 同じ永続名で再購読した時点で未確認のものから順に再送されます。
+//!
+//! 永続名は最初に登録したクライアントの資格情報に結び付けられ、再購読には同じ資格情報が
+//! 必要です。切断されたまま[`DurableConfig::detached_ttl`]を過ぎた永続名は、
+//! オフセットとともに破棄されます。
+//!
+//! オフセットの保存先は[`OffsetStore`]で差し替えられます。
+
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::topic::TopicPattern;
+
+/// トピックの保持ポリシー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// 保持する最大メッセージ数
+    pub max_messages: usize,
+    /// 保持期間
+    pub max_age: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_messages: 10_000,
+            max_age: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// 永続購読の設定
+#[derive(Debug, Clone)]
+pub struct DurableConfig {
+    /// 個別指定のないトピックの保持ポリシー
+    pub default_retention: RetentionPolicy,
+    /// トピックパターンごとの保持ポリシー（先に登録したものが優先）
+    pub topic_retention: Vec<(TopicPattern, RetentionPolicy)>,
+    /// 切断された永続名を再購読まで保持する期間
+    pub detached_ttl: Duration,
+}
+
+impl Default for DurableConfig {
+    fn default() -> Self {
+        Self {
+            default_retention: RetentionPolicy::default(),
+            topic_retention: Vec::new(),
+            detached_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl DurableConfig {
+    pub fn with_default_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.default_retention = policy;
+        self
+    }
+
+    pub fn with_detached_ttl(mut self, ttl: Duration) -> Self {
+        self.detached_ttl = ttl;
+        self
+    }
+
+    /// トピックパターンに保持ポリシーを指定
+    pub fn with_topic_retention(mut self, pattern: TopicPattern, policy: RetentionPolicy) -> Self {
+        self.topic_retention.push((pattern, policy));
+        self
+    }
+
+    /// トピックに適用される保持ポリシー
+    pub fn retention_for(&self, topic: &str) -> RetentionPolicy {
+        self.topic_retention
+            .iter()
+            .find(|(pattern, _)| pattern.matches(topic))
+            .map(|(_, policy)| *policy)
+            .unwrap_or(self.default_retention)
+    }
+}
+
+/// 確認応答済みオフセットの保存先
+pub trait OffsetStore: Send + Sync {
+    /// 永続名とトピックの確認済みオフセットを取得
+    fn load(&self, name: &str, topic: &str) -> Option<u64>;
+    /// 確認済みオフセットを保存
+    fn commit(&self, name: &str, topic: &str, offset: u64);
+    /// 永続名の全オフセットを削除
+    fn remove(&self, name: &str);
+}
+
+/// メモリ上のオフセットストア
+#[derive(Default)]
+pub struct MemoryOffsetStore {
+    offsets: Mutex<HashMap<String, HashMap<String, u64>>>,
+}
+
+impl MemoryOffsetStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OffsetStore for MemoryOffsetStore {
+    fn load(&self, name: &str, topic: &str) -> Option<u64> {
+        self.offsets
+            .lock()
+            .unwrap()
+            .get(name)
+            .and_then(|topics| topics.get(topic))
+            .copied()
+    }
+
+    fn commit(&self, name: &str, topic: &str, offset: u64) {
+        let mut offsets = self.offsets.lock().unwrap();
+        let current = offsets
+            .entry(name.to_string())
+            .or_default()
+            .entry(topic.to_string())
+            .or_insert(offset);
+        *current = (*current).max(offset);
+    }
+
+    fn remove(&self, name: &str) {
+        self.offsets.lock().unwrap().remove(name);
+    }
+}
+
+/// 保持ログ内のメッセージ
+#[derive(Debug, Clone)]
+pub struct RetainedMessage {
+    pub topic: String,
+    pub offset: u64,
+    pub payload: Value,
+    pub published_at: Instant,
+}
+
+#[derive(Default)]
+struct TopicLog {
+    next_offset: u64,
+    messages: VecDeque<RetainedMessage>,
+}
+
+impl TopicLog {
+    fn prune(&mut self, policy: RetentionPolicy) {
+        while self.messages.len() > policy.max_messages {
+            self.messages.pop_front();
+        }
+        while self
+            .messages
+            .front()
+            .is_some_and(|m| m.published_at.elapsed() > policy.max_age)
+        {
+            self.messages.pop_front();
+        }
+    }
+}
+
+/// トピックごとの保持ログ
+pub(crate) struct MessageLog {
+    config: DurableConfig,
+    topics: Mutex<HashMap<String, TopicLog>>,
+}
+
+impl MessageLog {
+    pub(crate) fn new(config: DurableConfig) -> Self {
+        Self {
+            config,
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn config(&self) -> &DurableConfig {
+        &self.config
+    }
+
+    /// メッセージを追記し、割り当てたオフセットを返す
+    pub(crate) fn append(&self, topic: &str, payload: &Value) -> u64 {
+        let policy = self.config.retention_for(topic);
+        let mut topics = self.topics.lock().unwrap();
+        let log = topics.entry(topic.to_string()).or_default();
+
+        let offset = log.next_offset;
+        log.next_offset += 1;
+        log.messages.push_back(RetainedMessage {
+            topic: topic.to_string(),
+            offset,
+            payload: payload.clone(),
+            published_at: Instant::now(),
+        });
+        log.prune(policy);
+        offset
+    }
+
+    /// パターンに一致するトピックの保持メッセージを取得
+    ///
+    /// `start`はトピックごとの再送開始位置で、`None`の場合は`since`以降に
+    /// 発行されたメッセージのみを返します。
+    pub(crate) fn replay(
+        &self,
+        pattern: &TopicPattern,
+        since: Instant,
+        start: impl Fn(&str) -> Option<u64>,
+    ) -> Vec<RetainedMessage> {
+        let mut topics = self.topics.lock().unwrap();
+        let mut replay = Vec::new();
+
+        for (topic, log) in topics.iter_mut().filter(|(t, _)| pattern.matches(t)) {
+            log.prune(self.config.retention_for(topic));
+            let start = start(topic);
+            replay.extend(
+                log.messages
+                    .iter()
+                    .filter(|m| match start {
+                        Some(start) => m.offset >= start,
+                        None => m.published_at >= since,
+                    })
+                    .cloned(),
+            );
+        }
+
+        replay.sort_by_key(|m| m.published_at);
+        replay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_retention_per_topic() {
+        let config = DurableConfig::default().with_topic_retention(
+            TopicPattern::parse("metrics.#").unwrap(),
+            RetentionPolicy {
+                max_messages: 2,
+                ..Default::default()
+            },
+        );
+        assert_eq!(config.retention_for("metrics.cpu").max_messages, 2);
+        assert_eq!(config.retention_for("orders").max_messages, 10_000);
+
+        let log = MessageLog::new(config);
+        let since = Instant::now();
+        for n in 0..5 {
+            log.append("metrics.cpu", &json!(n));
+            log.append("orders", &json!(n));
+        }
+
+        let pattern = TopicPattern::parse("#").unwrap();
+        let replay = log.replay(&pattern, since, |_| None);
+        let metrics: Vec<u64> = replay
+            .iter()
+            .filter(|m| m.topic == "metrics.cpu")
+            .map(|m| m.offset)
+            .collect();
+        assert_eq!(metrics, vec![3, 4]);
+        assert_eq!(replay.iter().filter(|m| m.topic == "orders").count(), 5);
+
+        let replay = log.replay(&pattern, since, |topic| (topic == "orders").then_some(3));
+        assert_eq!(
+            replay
+                .iter()
+                .filter(|m| m.topic == "orders")
+                .map(|m| m.offset)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn test_memory_offset_store_keeps_highest() {
+        let store = MemoryOffsetStore::new();
+        store.commit("worker", "orders", 5);
+        store.commit("worker", "orders", 3);
+        assert_eq!(store.load("worker", "orders"), Some(5));
+        assert_eq!(store.load("worker", "other"), None);
+
+        store.remove("worker");
+        assert_eq!(store.load("worker", "orders"), None);
+    }
+}
@@ -0,0 +1,588 @@
+//! サブスクリプションフィルター式
+//!
+//! サブスクライバーがトピックに付与するフィルターを、サーバー側で評価するための
+//! 簡易DSLです。JSONPath風のパスとフィールド比較を論理演算で組み合わせます。
+//!
+//! ```text
+//! $.room == "kitchen" && $.temperature >= 30
+//! $.tags[0] != "debug" || !$.internal
+//! $.user.id            // 値が存在し、false/nullでなければ真
+//! ```
+//!
+//! 文法:
+//!
+//! ```text
+//! expr       := and ("||" and)*
+//! and        := unary ("&&" unary)*
+//! unary      := "!" unary | "(" expr ")" | comparison
+//! comparison := path (op literal)?
+//! op         := "==" | "!=" | ">" | ">=" | "<" | "<="
+//! path       := "$" ("." ident | "[" integer "]")*
+//! literal    := string | number | true | false | null
+//! ```
+
+use serde_json::Value;
+use std::fmt;
+use thiserror::Error;
+
+/// フィルター式の長さ（バイト数）の上限
+pub const MAX_FILTER_LENGTH: usize = 4096;
+
+/// フィルター式の構文木の深さの上限（`!`・括弧の入れ子と`&&`・`||`の連なり）
+///
+/// 解析と評価は再帰するため、クライアントが送った深い式でスタックが溢れないよう制限します。
+pub const MAX_FILTER_DEPTH: usize = 64;
+
+/// フィルター式の解析エラー
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum FilterError {
+    #[error("Unexpected character '{found}' at position {position}")]
+    UnexpectedChar { position: usize, found: char },
+    #[error("Unexpected token '{found}' at position {position}")]
+    UnexpectedToken { position: usize, found: String },
+    #[error("Unexpected end of filter expression")]
+    UnexpectedEnd,
+    #[error("Unterminated string literal at position {position}")]
+    UnterminatedString { position: usize },
+    #[error("Invalid number '{0}'")]
+    InvalidNumber(String),
+    #[error("Filter expression is too long ({length} bytes, max {max})")]
+    TooLong { length: usize, max: usize },
+    #[error("Filter expression is nested too deeply (max {max})")]
+    TooDeep { max: usize },
+}
+
+/// パスの要素
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+/// 比較演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// フィルター式の構文木
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    /// パスの値が存在し真とみなせるか
+    Truthy(Vec<PathSegment>),
+    /// パスの値とリテラルの比較
+    Compare {
+        path: Vec<PathSegment>,
+        op: CompareOp,
+        value: Value,
+    },
+    Not(Box<FilterExpr>),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+}
+
+/// 解析済みのフィルター
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    source: String,
+    expr: FilterExpr,
+}
+
+impl Filter {
+    /// フィルター式を解析
+    ///
+    /// [`MAX_FILTER_LENGTH`]より長い式や、[`MAX_FILTER_DEPTH`]より深い式はエラーを返します。
+    pub fn parse(source: &str) -> Result<Self, FilterError> {
+        if source.len() > MAX_FILTER_LENGTH {
+            return Err(FilterError::TooLong {
+                length: source.len(),
+                max: MAX_FILTER_LENGTH,
+            });
+        }
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.parse_or()?;
+        if let Some((position, token)) = parser.tokens.get(parser.pos) {
+            return Err(FilterError::UnexpectedToken {
+                position: *position,
+                found: token.to_string(),
+            });
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// 元のフィルター式
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 構文木
+    pub fn expr(&self) -> &FilterExpr {
+        &self.expr
+    }
+
+    /// ペイロードがフィルターに一致するか
+    pub fn matches(&self, payload: &Value) -> bool {
+        evaluate(&self.expr, payload)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn resolve<'a>(payload: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(payload, |value, segment| match segment {
+            PathSegment::Field(name) => value.get(name),
+            PathSegment::Index(index) => value.get(index),
+        })
+}
+
+fn evaluate(expr: &FilterExpr, payload: &Value) -> bool {
+    match expr {
+        FilterExpr::Truthy(path) => !matches!(
+            resolve(payload, path),
+            None | Some(Value::Null | Value::Bool(false))
+        ),
+        FilterExpr::Compare { path, op, value } => match resolve(payload, path) {
+            Some(actual) => compare(actual, *op, value),
+            // 存在しないフィールドは`!= 値`のみ真
+            None => *op == CompareOp::Ne,
+        },
+        FilterExpr::Not(inner) => !evaluate(inner, payload),
+        FilterExpr::And(lhs, rhs) => evaluate(lhs, payload) && evaluate(rhs, payload),
+        FilterExpr::Or(lhs, rhs) => evaluate(lhs, payload) || evaluate(rhs, payload),
+    }
+}
+
+fn compare(actual: &Value, op: CompareOp, expected: &Value) -> bool {
+    let ordering = match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .zip(b.as_f64())
+            .and_then(|(a, b)| a.partial_cmp(&b)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (a, b) => {
+            return match op {
+                CompareOp::Eq => a == b,
+                CompareOp::Ne => a != b,
+                _ => false,
+            };
+        }
+    };
+
+    let Some(ordering) = ordering else {
+        return op == CompareOp::Ne;
+    };
+    match op {
+        CompareOp::Eq => ordering.is_eq(),
+        CompareOp::Ne => ordering.is_ne(),
+        CompareOp::Gt => ordering.is_gt(),
+        CompareOp::Ge => ordering.is_ge(),
+        CompareOp::Lt => ordering.is_lt(),
+        CompareOp::Le => ordering.is_le(),
+    }
+}
+
+/// 字句
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Dollar,
+    Dot,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    Op(CompareOp),
+    Ident(String),
+    Str(String),
+    Number(serde_json::Number),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Dollar => f.write_str("$"),
+            Token::Dot => f.write_str("."),
+            Token::LBracket => f.write_str("["),
+            Token::RBracket => f.write_str("]"),
+            Token::LParen => f.write_str("("),
+            Token::RParen => f.write_str(")"),
+            Token::Not => f.write_str("!"),
+            Token::And => f.write_str("&&"),
+            Token::Or => f.write_str("||"),
+            Token::Op(op) => f.write_str(match op {
+                CompareOp::Eq => "==",
+                CompareOp::Ne => "!=",
+                CompareOp::Gt => ">",
+                CompareOp::Ge => ">=",
+                CompareOp::Lt => "<",
+                CompareOp::Le => "<=",
+            }),
+            Token::Ident(name) => f.write_str(name),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+
+        let (token, consumed) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('$', _) => (Token::Dollar, 1),
+            ('.', _) => (Token::Dot, 1),
+            ('[', _) => (Token::LBracket, 1),
+            (']', _) => (Token::RBracket, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(CompareOp::Eq), 2),
+            ('!', Some('=')) => (Token::Op(CompareOp::Ne), 2),
+            ('!', _) => (Token::Not, 1),
+            ('>', Some('=')) => (Token::Op(CompareOp::Ge), 2),
+            ('>', _) => (Token::Op(CompareOp::Gt), 1),
+            ('<', Some('=')) => (Token::Op(CompareOp::Le), 2),
+            ('<', _) => (Token::Op(CompareOp::Lt), 1),
+            ('"', _) => {
+                let mut value = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => return Err(FilterError::UnterminatedString { position }),
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => {
+                            let escaped = chars
+                                .get(j + 1)
+                                .ok_or(FilterError::UnterminatedString { position })?
+                                .1;
+                            value.push(match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                other => other,
+                            });
+                            j += 2;
+                        }
+                        Some((_, c)) => {
+                            value.push(*c);
+                            j += 1;
+                        }
+                    }
+                }
+                (Token::Str(value), j + 1 - i)
+            }
+            (c, _) if c.is_ascii_digit() || c == '-' => {
+                let mut j = i + 1;
+                while chars
+                    .get(j)
+                    .is_some_and(|(_, c)| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E'))
+                {
+                    j += 1;
+                }
+                let text: String = chars[i..j].iter().map(|(_, c)| c).collect();
+                let number: serde_json::Number = text
+                    .parse()
+                    .map_err(|_| FilterError::InvalidNumber(text.clone()))?;
+                (Token::Number(number), j - i)
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let mut j = i + 1;
+                while chars
+                    .get(j)
+                    .is_some_and(|(_, c)| c.is_alphanumeric() || matches!(c, '_' | '-'))
+                {
+                    j += 1;
+                }
+                let text: String = chars[i..j].iter().map(|(_, c)| c).collect();
+                (Token::Ident(text), j - i)
+            }
+            (found, _) => return Err(FilterError::UnexpectedChar { position, found }),
+        };
+
+        tokens.push((position, token));
+        i += consumed;
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// 解析中の構文木の深さ
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), FilterError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(FilterError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), FilterError> {
+        let (position, token) = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(FilterError::UnexpectedToken {
+                position,
+                found: token.to_string(),
+            })
+        }
+    }
+
+    /// 構文木を1段深くする
+    fn descend(&mut self) -> Result<(), FilterError> {
+        self.depth += 1;
+        if self.depth > MAX_FILTER_DEPTH {
+            return Err(FilterError::TooDeep {
+                max: MAX_FILTER_DEPTH,
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, FilterError> {
+        let mut expr = self.parse_and()?;
+        let depth = self.depth;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            // 左に連なる式も1段ずつ深くなる
+            self.descend()?;
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, FilterError> {
+        let mut expr = self.parse_unary()?;
+        let depth = self.depth;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            self.descend()?;
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<FilterExpr, FilterError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                self.descend()?;
+                let expr = FilterExpr::Not(Box::new(self.parse_unary()?));
+                self.depth -= 1;
+                Ok(expr)
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                self.descend()?;
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            _ => self.parse_comparison(),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<FilterExpr, FilterError> {
+        let path = self.parse_path()?;
+        let Some(Token::Op(op)) = self.peek().cloned() else {
+            return Ok(FilterExpr::Truthy(path));
+        };
+        self.pos += 1;
+
+        let (position, token) = self.next()?;
+        let value = match token {
+            Token::Str(s) => Value::String(s),
+            Token::Number(n) => Value::Number(n),
+            Token::Ident(ident) if ident == "true" => Value::Bool(true),
+            Token::Ident(ident) if ident == "false" => Value::Bool(false),
+            Token::Ident(ident) if ident == "null" => Value::Null,
+            other => {
+                return Err(FilterError::UnexpectedToken {
+                    position,
+                    found: other.to_string(),
+                });
+            }
+        };
+        Ok(FilterExpr::Compare { path, op, value })
+    }
+
+    fn parse_path(&mut self) -> Result<Vec<PathSegment>, FilterError> {
+        self.expect(Token::Dollar)?;
+        let mut path = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Dot) => {
+                    self.pos += 1;
+                    match self.next()? {
+                        (_, Token::Ident(name)) => path.push(PathSegment::Field(name)),
+                        (position, other) => {
+                            return Err(FilterError::UnexpectedToken {
+                                position,
+                                found: other.to_string(),
+                            });
+                        }
+                    }
+                }
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    match self.next()? {
+                        (_, Token::Number(n)) if n.as_u64().is_some() => {
+                            path.push(PathSegment::Index(n.as_u64().unwrap() as usize))
+                        }
+                        (_, Token::Str(name)) => path.push(PathSegment::Field(name)),
+                        (position, other) => {
+                            return Err(FilterError::UnexpectedToken {
+                                position,
+                                found: other.to_string(),
+                            });
+                        }
+                    }
+                    self.expect(Token::RBracket)?;
+                }
+                _ => return Ok(path),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn matches(filter: &str, payload: Value) -> bool {
+        Filter::parse(filter).unwrap().matches(&payload)
+    }
+
+    #[test]
+    fn test_field_equality() {
+        let event = json!({"room": "kitchen", "temperature": 31.5});
+        assert!(matches(r#"$.room == "kitchen""#, event.clone()));
+        assert!(!matches(r#"$.room == "garage""#, event.clone()));
+        assert!(matches(r#"$.room != "garage""#, event.clone()));
+        assert!(matches("$.temperature >= 30", event.clone()));
+        assert!(!matches("$.temperature < 30", event));
+    }
+
+    #[test]
+    fn test_logical_operators_and_precedence() {
+        let event = json!({"level": "warn", "code": 503, "internal": false});
+        assert!(matches(
+            r#"$.level == "error" || $.level == "warn" && $.code >= 500"#,
+            event.clone()
+        ));
+        assert!(!matches(
+            r#"($.level == "error" || $.level == "warn") && $.code < 500"#,
+            event.clone()
+        ));
+        assert!(matches("!$.internal", event));
+    }
+
+    #[test]
+    fn test_nested_paths_and_missing_fields() {
+        let event = json!({"user": {"id": 7, "tags": ["admin", "ops"]}});
+        assert!(matches("$.user.id == 7", event.clone()));
+        assert!(matches(r#"$.user.tags[1] == "ops""#, event.clone()));
+        assert!(matches(r#"$["user"].id"#, event.clone()));
+        assert!(!matches("$.user.missing", event.clone()));
+        assert!(!matches("$.user.missing == 1", event.clone()));
+        assert!(matches("$.user.missing != 1", event));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Filter::parse("$.a ==").unwrap_err(),
+            FilterError::UnexpectedEnd
+        );
+        assert!(matches!(
+            Filter::parse("room == 1").unwrap_err(),
+            FilterError::UnexpectedToken { position: 0, .. }
+        ));
+        assert!(matches!(
+            Filter::parse(r#"$.a == "open"#).unwrap_err(),
+            FilterError::UnterminatedString { .. }
+        ));
+        assert!(matches!(
+            Filter::parse("$.a == 1 $.b").unwrap_err(),
+            FilterError::UnexpectedToken { .. }
+        ));
+        assert!(matches!(
+            Filter::parse("$.a # 1").unwrap_err(),
+            FilterError::UnexpectedChar { found: '#', .. }
+        ));
+    }
+
+    #[test]
+    fn test_deep_filters_are_rejected() {
+        for open in ["!", "("] {
+            let deep = format!("{}$.a", open.repeat(100_000));
+            assert!(matches!(
+                Filter::parse(&deep).unwrap_err(),
+                FilterError::TooLong { .. }
+            ));
+
+            let nested = format!("{}$.a", open.repeat(MAX_FILTER_DEPTH + 1));
+            assert_eq!(
+                Filter::parse(&nested).unwrap_err(),
+                FilterError::TooDeep {
+                    max: MAX_FILTER_DEPTH
+                }
+            );
+        }
+        let chain = vec!["$.a"; MAX_FILTER_DEPTH + 2].join(" && ");
+        assert!(matches!(
+            Filter::parse(&chain).unwrap_err(),
+            FilterError::TooDeep { .. }
+        ));
+
+        // 上限の範囲の式は解析できる
+        let nested = format!("{}$.a", "!".repeat(MAX_FILTER_DEPTH));
+        assert!(Filter::parse(&nested).is_ok());
+        assert!(matches(&vec!["$.a"; 8].join(" && "), json!({ "a": 1 })));
+    }
+}
@@ -0,0 +1,842 @@
+//! トピックベースのPub/Sub
+//!
+//! クライアントは組み込みメソッド[`SUBSCRIBE_METHOD`]でトピックを購読し、
+//! サーバーは[`ProtocolServer::publish`](super::ProtocolServer::publish)で
+//! 購読中の接続にのみ`Event`メッセージを配信します。
+//!
+//! トピックは`.`区切りの階層名で、購読時には`*`・`#`のワイルドカードを使用できます
+//! （[`topic`]モジュール参照）。
+//!
+//! 購読にはフィルター式（[`filter`]モジュール参照）を付与でき、サーバー側で評価されるため
+//! 一致しないイベントはネットワークに送出されません。
+//!
+//! 永続名を付けた購読（[`durable`]モジュール参照）では、切断中のメッセージが
+//! 再購読時に再送されます。保持ログに記録されたイベントは`id`にトピック内のオフセットを持ち、
+//! クライアントは[`ACK_METHOD`]でそのオフセットを確認応答します。
+//! 永続名は最初に登録した資格情報でのみ再購読できます。
+
+pub mod durable;
+pub mod filter;
+pub mod topic;
+
+pub use durable::{
+    DurableConfig, MemoryOffsetStore, OffsetStore, RetainedMessage, RetentionPolicy,
+};
+pub use filter::{Filter, FilterError, MAX_FILTER_DEPTH, MAX_FILTER_LENGTH};
+pub use topic::{MAX_TOPIC_LENGTH, MAX_TOPIC_SEGMENTS, TopicError, TopicPattern, TopicTrie};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use super::broadcast::ConnectionId;
+use durable::MessageLog;
+use topic::validate_topic;
+
+/// 購読ID
+pub type SubscriptionId = u64;
+
+/// 購読用の組み込みメソッド名
+pub const SUBSCRIBE_METHOD: &str = "unison.subscribe";
+/// 購読解除用の組み込みメソッド名
+pub const UNSUBSCRIBE_METHOD: &str = "unison.unsubscribe";
+/// 永続購読の確認応答用の組み込みメソッド名
+pub const ACK_METHOD: &str = "unison.ack";
+
+/// Pub/Subのエラー
+#[derive(Error, Debug)]
+pub enum PubSubError {
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(#[from] FilterError),
+    #[error("Invalid topic: {0}")]
+    InvalidTopic(#[from] TopicError),
+    #[error("Subscription not found: {0}")]
+    SubscriptionNotFound(SubscriptionId),
+    #[error("Durable subscription '{0}' is already active on another connection")]
+    DurableNameInUse(String),
+    #[error("Durable subscription not found: {0}")]
+    DurableNotFound(String),
+    #[error("Durable subscription '{0}' requires a credential")]
+    DurableCredentialRequired(String),
+    #[error("Durable subscription '{0}' belongs to another client")]
+    DurableCredentialMismatch(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] serde_json::Error),
+}
+
+/// 購読リクエスト
+///
+/// `topic`にはワイルドカードを含むパターンを指定できます。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeRequest {
+    pub topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// 永続購読名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub durable: Option<String>,
+    /// 永続購読の資格情報（同じ永続名での再購読には同じ値が必要）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+/// 購読レスポンス
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeResponse {
+    pub subscription_id: SubscriptionId,
+}
+
+/// 購読解除リクエスト
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsubscribeRequest {
+    pub subscription_id: SubscriptionId,
+}
+
+/// 確認応答リクエスト
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AckRequest {
+    pub durable: String,
+    pub topic: String,
+    pub offset: u64,
+}
+
+/// 購読情報
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub id: SubscriptionId,
+    pub pattern: TopicPattern,
+    pub connection_id: ConnectionId,
+    pub filter: Option<Filter>,
+    /// 永続購読名
+    pub durable: Option<String>,
+}
+
+/// イベント発行の結果
+#[derive(Debug, Clone, Default)]
+pub struct Publication {
+    /// 保持ログに記録された場合のオフセット
+    pub offset: Option<u64>,
+    /// 配信先の接続
+    pub targets: Vec<ConnectionId>,
+}
+
+/// 組み込みメソッドの処理結果
+#[derive(Debug, Clone)]
+pub struct PubSubReply {
+    /// 呼び出し元へのレスポンス
+    pub response: Value,
+    /// 呼び出し元へ再送する保持メッセージ
+    pub replay: Vec<RetainedMessage>,
+}
+
+impl From<Value> for PubSubReply {
+    fn from(response: Value) -> Self {
+        Self {
+            response,
+            replay: Vec::new(),
+        }
+    }
+}
+
+/// Pub/Subの統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PubSubStats {
+    /// 有効な購読数
+    pub subscriptions: usize,
+    /// 発行されたイベント数
+    pub published: u64,
+    /// 配信対象となった接続数の累計
+    pub delivered: u64,
+    /// フィルターにより配信されなかった購読数の累計
+    pub filtered: u64,
+}
+
+/// 購読パターンごとの統計
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternStats {
+    pub pattern: String,
+    /// このパターンの有効な購読数
+    pub subscriptions: usize,
+    /// パターンに一致したイベント数の累計（購読ごと）
+    pub matched: u64,
+    /// パターンに一致したがフィルターで除外された数の累計
+    pub filtered: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    published: AtomicU64,
+    delivered: AtomicU64,
+    filtered: AtomicU64,
+}
+
+#[derive(Default)]
+struct PatternCounters {
+    subscriptions: usize,
+    matched: AtomicU64,
+    filtered: AtomicU64,
+}
+
+/// 永続購読の登録情報（切断中も保持される）
+struct DurableSubscriber {
+    pattern: TopicPattern,
+    connection_id: Option<ConnectionId>,
+    registered_at: Instant,
+    /// 登録したクライアントの資格情報のハッシュ
+    credential: Vec<u8>,
+    /// 切断された時刻
+    detached_at: Option<Instant>,
+}
+
+impl DurableSubscriber {
+    /// 切断されたまま`ttl`を過ぎたか
+    fn is_expired(&self, ttl: Duration) -> bool {
+        self.detached_at.is_some_and(|at| at.elapsed() >= ttl)
+    }
+}
+
+fn credential_hash(credential: &str) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, credential.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+#[derive(Default)]
+struct PubSubState {
+    subscriptions: HashMap<SubscriptionId, Subscription>,
+    trie: TopicTrie,
+    patterns: HashMap<String, PatternCounters>,
+    durables: HashMap<String, DurableSubscriber>,
+}
+
+impl PubSubState {
+    fn insert(&mut self, subscription: Subscription) {
+        self.trie.insert(&subscription.pattern, subscription.id);
+        self.patterns
+            .entry(subscription.pattern.as_str().to_string())
+            .or_default()
+            .subscriptions += 1;
+        self.subscriptions.insert(subscription.id, subscription);
+    }
+
+    fn remove(&mut self, id: SubscriptionId) -> Option<Subscription> {
+        let subscription = self.subscriptions.remove(&id)?;
+        self.trie.remove(&subscription.pattern, id);
+
+        let pattern = subscription.pattern.as_str();
+        if let Some(counters) = self.patterns.get_mut(pattern) {
+            counters.subscriptions -= 1;
+            if counters.subscriptions == 0 {
+                self.patterns.remove(pattern);
+            }
+        }
+        Some(subscription)
+    }
+}
+
+/// 購読を管理するブローカー
+#[derive(Clone)]
+pub struct PubSub {
+    state: Arc<RwLock<PubSubState>>,
+    next_id: Arc<AtomicU64>,
+    counters: Arc<Counters>,
+    log: Arc<MessageLog>,
+    offsets: Arc<dyn OffsetStore>,
+}
+
+impl Default for PubSub {
+    fn default() -> Self {
+        Self {
+            state: Arc::default(),
+            next_id: Arc::default(),
+            counters: Arc::default(),
+            log: Arc::new(MessageLog::new(DurableConfig::default())),
+            offsets: Arc::new(MemoryOffsetStore::new()),
+        }
+    }
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 永続購読の保持設定を指定
+    pub fn with_durable_config(mut self, config: DurableConfig) -> Self {
+        self.log = Arc::new(MessageLog::new(config));
+        self
+    }
+
+    /// 確認応答済みオフセットの保存先を指定
+    pub fn with_offset_store(mut self, store: Arc<dyn OffsetStore>) -> Self {
+        self.offsets = store;
+        self
+    }
+
+    /// トピックパターンを購読
+    ///
+    /// パターンには`*`（1階層）と`#`（0階層以上、末尾のみ）のワイルドカードを使用できます。
+    pub fn subscribe(
+        &self,
+        connection_id: ConnectionId,
+        pattern: &str,
+        filter: Option<Filter>,
+    ) -> Result<SubscriptionId, PubSubError> {
+        let pattern = TopicPattern::parse(pattern)?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.state.write().unwrap().insert(Subscription {
+            id,
+            pattern,
+            connection_id,
+            filter,
+            durable: None,
+        });
+        Ok(id)
+    }
+
+    /// 永続名を付けてトピックパターンを購読
+    ///
+    /// 同じ永続名での再購読では、確認応答済みオフセットより後の保持メッセージを
+    /// 再送対象として返します。初回登録時は再送しません。
+    /// 永続名は初回登録時の`credential`に結び付けられ、再購読には同じ値が必要です。
+    pub fn subscribe_durable(
+        &self,
+        connection_id: ConnectionId,
+        name: &str,
+        credential: &str,
+        pattern: &str,
+        filter: Option<Filter>,
+    ) -> Result<(SubscriptionId, Vec<RetainedMessage>), PubSubError> {
+        let pattern = TopicPattern::parse(pattern)?;
+        if credential.is_empty() {
+            return Err(PubSubError::DurableCredentialRequired(name.to_string()));
+        }
+        let credential = credential_hash(credential);
+
+        // 再送の取得と購読の登録を同じロック内で行い、その間の発行を取りこぼさない
+        let mut state = self.state.write().unwrap();
+        self.expire_durables(&mut state);
+        let registered_at = match state.durables.get(name) {
+            Some(durable) if durable.credential != credential => {
+                return Err(PubSubError::DurableCredentialMismatch(name.to_string()));
+            }
+            Some(durable) if durable.connection_id.is_some() => {
+                return Err(PubSubError::DurableNameInUse(name.to_string()));
+            }
+            Some(durable) => durable.registered_at,
+            None => Instant::now(),
+        };
+
+        let replay: Vec<RetainedMessage> = self
+            .log
+            .replay(&pattern, registered_at, |topic| {
+                self.offsets.load(name, topic).map(|offset| offset + 1)
+            })
+            .into_iter()
+            .filter(|m| filter.as_ref().is_none_or(|f| f.matches(&m.payload)))
+            .collect();
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        state.durables.insert(
+            name.to_string(),
+            DurableSubscriber {
+                pattern: pattern.clone(),
+                connection_id: Some(connection_id),
+                registered_at,
+                credential,
+                detached_at: None,
+            },
+        );
+        state.insert(Subscription {
+            id,
+            pattern,
+            connection_id,
+            filter,
+            durable: Some(name.to_string()),
+        });
+        Ok((id, replay))
+    }
+
+    /// 永続購読のオフセットを確認応答
+    pub fn ack(
+        &self,
+        connection_id: ConnectionId,
+        name: &str,
+        topic: &str,
+        offset: u64,
+    ) -> Result<(), PubSubError> {
+        let state = self.state.read().unwrap();
+        match state.durables.get(name) {
+            Some(durable) if durable.connection_id == Some(connection_id) => {
+                self.offsets.commit(name, topic, offset);
+                Ok(())
+            }
+            _ => Err(PubSubError::DurableNotFound(name.to_string())),
+        }
+    }
+
+    /// 購読を解除（他の接続の購読は解除できない）
+    pub fn unsubscribe(
+        &self,
+        connection_id: ConnectionId,
+        id: SubscriptionId,
+    ) -> Result<(), PubSubError> {
+        let mut state = self.state.write().unwrap();
+        match state.subscriptions.get(&id) {
+            Some(subscription) if subscription.connection_id == connection_id => {
+                // 明示的な解除では永続購読の登録とオフセットも破棄する
+                if let Some(name) = state.remove(id).and_then(|s| s.durable) {
+                    state.durables.remove(&name);
+                    self.offsets.remove(&name);
+                }
+                Ok(())
+            }
+            _ => Err(PubSubError::SubscriptionNotFound(id)),
+        }
+    }
+
+    /// 接続の全購読を削除し、削除数を返す
+    ///
+    /// 永続購読は登録を残したまま切断状態になり、再購読を待ちます。
+    pub fn remove_connection(&self, connection_id: ConnectionId) -> usize {
+        let mut state = self.state.write().unwrap();
+        let ids: Vec<SubscriptionId> = state
+            .subscriptions
+            .values()
+            .filter(|s| s.connection_id == connection_id)
+            .map(|s| s.id)
+            .collect();
+        for id in &ids {
+            if let Some(name) = state.remove(*id).and_then(|s| s.durable) {
+                if let Some(durable) = state.durables.get_mut(&name) {
+                    durable.connection_id = None;
+                    durable.detached_at = Some(Instant::now());
+                }
+            }
+        }
+        self.expire_durables(&mut state);
+        ids.len()
+    }
+
+    /// 切断されたまま保持期間を過ぎた永続名をオフセットとともに破棄
+    fn expire_durables(&self, state: &mut PubSubState) {
+        let ttl = self.log.config().detached_ttl;
+        state.durables.retain(|name, durable| {
+            let expired = durable.is_expired(ttl);
+            if expired {
+                self.offsets.remove(name);
+            }
+            !expired
+        });
+    }
+
+    /// 接続の購読一覧
+    pub fn subscriptions_for(&self, connection_id: ConnectionId) -> Vec<Subscription> {
+        self.state
+            .read()
+            .unwrap()
+            .subscriptions
+            .values()
+            .filter(|s| s.connection_id == connection_id)
+            .cloned()
+            .collect()
+    }
+
+    /// イベントを配信すべき接続を求める
+    ///
+    /// フィルターはここで評価され、同じ接続の複数購読が一致しても1回だけ配信されます。
+    pub fn matching_connections(
+        &self,
+        topic: &str,
+        payload: &Value,
+    ) -> Result<Vec<ConnectionId>, PubSubError> {
+        validate_topic(topic)?;
+        Ok(self.collect_targets(&self.state.read().unwrap(), topic, payload))
+    }
+
+    /// イベントを発行
+    ///
+    /// 永続購読（切断中を含む）のパターンに一致するトピックは保持ログに記録され、
+    /// 割り当てられたオフセットが返されます。
+    pub fn publish(&self, topic: &str, payload: &Value) -> Result<Publication, PubSubError> {
+        validate_topic(topic)?;
+
+        let state = self.state.read().unwrap();
+        let ttl = self.log.config().detached_ttl;
+        let offset = state
+            .durables
+            .values()
+            .any(|durable| !durable.is_expired(ttl) && durable.pattern.matches(topic))
+            .then(|| self.log.append(topic, payload));
+        let targets = self.collect_targets(&state, topic, payload);
+        Ok(Publication { offset, targets })
+    }
+
+    fn collect_targets(
+        &self,
+        state: &PubSubState,
+        topic: &str,
+        payload: &Value,
+    ) -> Vec<ConnectionId> {
+        let mut targets = Vec::new();
+        let mut filtered = 0;
+
+        for id in state.trie.matches(topic) {
+            let Some(subscription) = state.subscriptions.get(&id) else {
+                continue;
+            };
+            let counters = state.patterns.get(subscription.pattern.as_str());
+            if let Some(counters) = counters {
+                counters.matched.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let matched = subscription
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(payload));
+            if !matched {
+                filtered += 1;
+                if let Some(counters) = counters {
+                    counters.filtered.fetch_add(1, Ordering::Relaxed);
+                }
+            } else if !targets.contains(&subscription.connection_id) {
+                targets.push(subscription.connection_id);
+            }
+        }
+
+        self.counters.published.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .delivered
+            .fetch_add(targets.len() as u64, Ordering::Relaxed);
+        self.counters
+            .filtered
+            .fetch_add(filtered, Ordering::Relaxed);
+        targets
+    }
+
+    /// 統計を取得
+    pub fn stats(&self) -> PubSubStats {
+        PubSubStats {
+            subscriptions: self.state.read().unwrap().subscriptions.len(),
+            published: self.counters.published.load(Ordering::Relaxed),
+            delivered: self.counters.delivered.load(Ordering::Relaxed),
+            filtered: self.counters.filtered.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 購読パターンごとの統計を取得
+    pub fn pattern_stats(&self) -> Vec<PatternStats> {
+        let state = self.state.read().unwrap();
+        let mut stats: Vec<PatternStats> = state
+            .patterns
+            .iter()
+            .map(|(pattern, counters)| PatternStats {
+                pattern: pattern.clone(),
+                subscriptions: counters.subscriptions,
+                matched: counters.matched.load(Ordering::Relaxed),
+                filtered: counters.filtered.load(Ordering::Relaxed),
+            })
+            .collect();
+        stats.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+        stats
+    }
+
+    /// 組み込みメソッドを処理
+    ///
+    /// Pub/Subのメソッドでなければ`None`を返します。
+    pub fn handle_request(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+        payload: Value,
+    ) -> Option<Result<PubSubReply, PubSubError>> {
+        match method {
+            SUBSCRIBE_METHOD => Some(self.handle_subscribe(connection_id, payload)),
+            UNSUBSCRIBE_METHOD => Some(self.handle_unsubscribe(connection_id, payload)),
+            ACK_METHOD => Some(self.handle_ack(connection_id, payload)),
+            _ => None,
+        }
+    }
+
+    fn handle_subscribe(
+        &self,
+        connection_id: ConnectionId,
+        payload: Value,
+    ) -> Result<PubSubReply, PubSubError> {
+        let request: SubscribeRequest = serde_json::from_value(payload)?;
+        let filter = request.filter.as_deref().map(Filter::parse).transpose()?;
+        let (subscription_id, replay) = match &request.durable {
+            Some(name) => self.subscribe_durable(
+                connection_id,
+                name,
+                request.credential.as_deref().unwrap_or_default(),
+                &request.topic,
+                filter,
+            )?,
+            None => (
+                self.subscribe(connection_id, &request.topic, filter)?,
+                Vec::new(),
+            ),
+        };
+        Ok(PubSubReply {
+            response: serde_json::to_value(SubscribeResponse { subscription_id })?,
+            replay,
+        })
+    }
+
+    fn handle_unsubscribe(
+        &self,
+        connection_id: ConnectionId,
+        payload: Value,
+    ) -> Result<PubSubReply, PubSubError> {
+        let request: UnsubscribeRequest = serde_json::from_value(payload)?;
+        self.unsubscribe(connection_id, request.subscription_id)?;
+        Ok(serde_json::json!({}).into())
+    }
+
+    fn handle_ack(
+        &self,
+        connection_id: ConnectionId,
+        payload: Value,
+    ) -> Result<PubSubReply, PubSubError> {
+        let request: AckRequest = serde_json::from_value(payload)?;
+        self.ack(
+            connection_id,
+            &request.durable,
+            &request.topic,
+            request.offset,
+        )?;
+        Ok(serde_json::json!({}).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filtered_subscriptions() {
+        let pubsub = PubSub::new();
+        pubsub.subscribe(1, "sensors", None).unwrap();
+        pubsub
+            .subscribe(2, "sensors", Some(Filter::parse("$.temp > 30").unwrap()))
+            .unwrap();
+        pubsub.subscribe(3, "alerts", None).unwrap();
+
+        let mut targets = pubsub
+            .matching_connections("sensors", &json!({"temp": 20}))
+            .unwrap();
+        targets.sort();
+        assert_eq!(targets, vec![1]);
+
+        let mut targets = pubsub
+            .matching_connections("sensors", &json!({"temp": 35}))
+            .unwrap();
+        targets.sort();
+        assert_eq!(targets, vec![1, 2]);
+
+        let stats = pubsub.stats();
+        assert_eq!(stats.published, 2);
+        assert_eq!(stats.delivered, 3);
+        assert_eq!(stats.filtered, 1);
+    }
+
+    #[test]
+    fn test_builtin_methods() {
+        let pubsub = PubSub::new();
+        assert!(pubsub.handle_request(1, "other", json!({})).is_none());
+
+        let response = pubsub
+            .handle_request(
+                1,
+                SUBSCRIBE_METHOD,
+                json!({"topic": "chat", "filter": "$.room == \"lobby\""}),
+            )
+            .unwrap()
+            .unwrap();
+        let response: SubscribeResponse = serde_json::from_value(response.response).unwrap();
+
+        let invalid = pubsub.handle_request(
+            1,
+            SUBSCRIBE_METHOD,
+            json!({"topic": "chat", "filter": "room"}),
+        );
+        assert!(matches!(invalid, Some(Err(PubSubError::InvalidFilter(_)))));
+
+        // 他の接続の購読は解除できない
+        let payload = json!({"subscription_id": response.subscription_id});
+        assert!(matches!(
+            pubsub.handle_request(2, UNSUBSCRIBE_METHOD, payload.clone()),
+            Some(Err(PubSubError::SubscriptionNotFound(_)))
+        ));
+        assert!(matches!(
+            pubsub.handle_request(1, UNSUBSCRIBE_METHOD, payload),
+            Some(Ok(_))
+        ));
+        assert_eq!(pubsub.stats().subscriptions, 0);
+    }
+
+    #[test]
+    fn test_remove_connection() {
+        let pubsub = PubSub::new();
+        pubsub.subscribe(1, "a", None).unwrap();
+        pubsub.subscribe(1, "b", None).unwrap();
+        pubsub.subscribe(2, "a", None).unwrap();
+
+        assert_eq!(pubsub.remove_connection(1), 2);
+        assert!(pubsub.subscriptions_for(1).is_empty());
+        assert_eq!(
+            pubsub.matching_connections("a", &Value::Null).unwrap(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_wildcard_subscriptions_and_pattern_stats() {
+        let pubsub = PubSub::new();
+        pubsub.subscribe(1, "sensors.*.temp", None).unwrap();
+        pubsub.subscribe(2, "sensors.#", None).unwrap();
+        pubsub
+            .subscribe(
+                3,
+                "sensors.#",
+                Some(Filter::parse("$.value > 100").unwrap()),
+            )
+            .unwrap();
+        assert!(matches!(
+            pubsub.subscribe(4, "sensors.#.temp", None),
+            Err(PubSubError::InvalidTopic(_))
+        ));
+
+        let mut targets = pubsub
+            .matching_connections("sensors.room1.temp", &json!({"value": 21}))
+            .unwrap();
+        targets.sort();
+        assert_eq!(targets, vec![1, 2]);
+        assert_eq!(
+            pubsub
+                .matching_connections("sensors.room1.humidity", &json!({"value": 150}))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(matches!(
+            pubsub.matching_connections("sensors.*", &Value::Null),
+            Err(PubSubError::InvalidTopic(_))
+        ));
+
+        let stats = pubsub.pattern_stats();
+        assert_eq!(
+            stats,
+            vec![
+                PatternStats {
+                    pattern: "sensors.#".to_string(),
+                    subscriptions: 2,
+                    matched: 4,
+                    filtered: 1,
+                },
+                PatternStats {
+                    pattern: "sensors.*.temp".to_string(),
+                    subscriptions: 1,
+                    matched: 1,
+                    filtered: 0,
+                },
+            ]
+        );
+
+        pubsub.remove_connection(1);
+        assert_eq!(pubsub.pattern_stats().len(), 1);
+    }
+
+    #[test]
+    fn test_durable_replay_after_reconnect() {
+        let pubsub = PubSub::new();
+        let (_, replay) = pubsub
+            .subscribe_durable(1, "worker", "secret", "orders.#", None)
+            .unwrap();
+        assert!(replay.is_empty());
+
+        // 同じ永続名は同時に1接続のみ
+        assert!(matches!(
+            pubsub.subscribe_durable(2, "worker", "secret", "orders.#", None),
+            Err(PubSubError::DurableNameInUse(_))
+        ));
+
+        for n in 0..3 {
+            let publication = pubsub.publish("orders.created", &json!({"n": n})).unwrap();
+            assert_eq!(publication.offset, Some(n));
+            assert_eq!(publication.targets, vec![1]);
+        }
+        pubsub.ack(1, "worker", "orders.created", 0).unwrap();
+        assert!(matches!(
+            pubsub.ack(2, "worker", "orders.created", 1),
+            Err(PubSubError::DurableNotFound(_))
+        ));
+
+        // 切断中の発行も保持される
+        pubsub.remove_connection(1);
+        let publication = pubsub.publish("orders.created", &json!({"n": 3})).unwrap();
+        assert_eq!(publication.offset, Some(3));
+        assert!(publication.targets.is_empty());
+
+        let (subscription_id, replay) = pubsub
+            .subscribe_durable(2, "worker", "secret", "orders.#", None)
+            .unwrap();
+        let offsets: Vec<u64> = replay.iter().map(|m| m.offset).collect();
+        assert_eq!(offsets, vec![1, 2, 3]);
+
+        // 明示的な解除で永続購読は破棄され、以後は記録されない
+        pubsub.unsubscribe(2, subscription_id).unwrap();
+        let publication = pubsub.publish("orders.created", &json!({"n": 4})).unwrap();
+        assert_eq!(publication.offset, None);
+    }
+
+    #[test]
+    fn test_durable_requires_matching_credential() {
+        let pubsub = PubSub::new();
+        assert!(matches!(
+            pubsub.subscribe_durable(1, "worker", "", "orders.#", None),
+            Err(PubSubError::DurableCredentialRequired(_))
+        ));
+
+        pubsub
+            .subscribe_durable(1, "worker", "secret", "orders.#", None)
+            .unwrap();
+        pubsub.publish("orders.created", &json!({"n": 0})).unwrap();
+        pubsub.remove_connection(1);
+
+        // 切断中でも別の資格情報では引き継げない
+        assert!(matches!(
+            pubsub.subscribe_durable(2, "worker", "other", "orders.#", None),
+            Err(PubSubError::DurableCredentialMismatch(_))
+        ));
+        let (_, replay) = pubsub
+            .subscribe_durable(2, "worker", "secret", "orders.#", None)
+            .unwrap();
+        assert_eq!(replay.len(), 1);
+    }
+
+    #[test]
+    fn test_detached_durable_expires() {
+        let pubsub = PubSub::new()
+            .with_durable_config(DurableConfig::default().with_detached_ttl(Duration::ZERO));
+        pubsub
+            .subscribe_durable(1, "worker", "secret", "orders.#", None)
+            .unwrap();
+        pubsub.ack(1, "worker", "orders.created", 0).unwrap();
+        pubsub.remove_connection(1);
+
+        // 期限切れの永続名は記録対象にならず、別の資格情報で登録し直せる
+        let publication = pubsub.publish("orders.created", &json!({"n": 0})).unwrap();
+        assert_eq!(publication.offset, None);
+        let (_, replay) = pubsub
+            .subscribe_durable(2, "worker", "other", "orders.#", None)
+            .unwrap();
+        assert!(replay.is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+//! 階層トピックとワイルドカードマッチング
+//!
+//! トピックは`.`区切りの階層名（例: `sensors.room1.temp`）です。
+//! 購読パターンでは次のワイルドカードを使用できます。
+//!
+//! - `*` は任意の1階層に一致（`sensors.*.temp`）
+//! - `#` は0階層以上に一致し、パターンの末尾にのみ置けます（`sensors.#`）
+//!
+//! マッチングはトライ木で行うため、購読数ではなくトピックの階層数に比例したコストで済みます。
+
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+use super::SubscriptionId;
+
+/// トピックの区切り文字
+pub const TOPIC_SEPARATOR: char = '.';
+
+/// トピック・パターンの長さ（バイト数）の上限
+pub const MAX_TOPIC_LENGTH: usize = 1024;
+
+/// トピック・パターンの階層数の上限
+///
+/// マッチングとトライ木の走査は階層ごとに再帰するため、クライアントが送った深いパターンで
+/// スタックが溢れないよう制限します。
+pub const MAX_TOPIC_SEGMENTS: usize = 64;
+
+/// トピック・パターンのエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TopicError {
+    #[error("Topic must not be empty")]
+    Empty,
+    #[error("Topic '{0}' contains an empty segment")]
+    EmptySegment(String),
+    #[error("Multi-level wildcard '#' must be the last segment: '{0}'")]
+    MultiWildcardNotLast(String),
+    #[error("Wildcards are not allowed in published topics: '{0}'")]
+    WildcardInTopic(String),
+    #[error("Topic is too long ({length} bytes, max {max})")]
+    TooLong { length: usize, max: usize },
+    #[error("Topic has too many segments ({segments}, max {max})")]
+    TooManySegments { segments: usize, max: usize },
+}
+
+/// パターンの階層要素
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PatternSegment {
+    Literal(String),
+    /// `*`
+    Single,
+    /// `#`
+    Multi,
+}
+
+/// 購読パターン
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TopicPattern {
+    source: String,
+    segments: Vec<PatternSegment>,
+}
+
+impl TopicPattern {
+    /// パターンを解析
+    ///
+    /// [`MAX_TOPIC_LENGTH`]より長いパターンや、[`MAX_TOPIC_SEGMENTS`]より階層の多いパターンは
+    /// エラーを返します。
+    pub fn parse(pattern: &str) -> Result<Self, TopicError> {
+        if pattern.is_empty() {
+            return Err(TopicError::Empty);
+        }
+        if pattern.len() > MAX_TOPIC_LENGTH {
+            return Err(TopicError::TooLong {
+                length: pattern.len(),
+                max: MAX_TOPIC_LENGTH,
+            });
+        }
+
+        let parts: Vec<&str> = pattern.split(TOPIC_SEPARATOR).collect();
+        if parts.len() > MAX_TOPIC_SEGMENTS {
+            return Err(TopicError::TooManySegments {
+                segments: parts.len(),
+                max: MAX_TOPIC_SEGMENTS,
+            });
+        }
+        let mut segments = Vec::with_capacity(parts.len());
+        for (index, part) in parts.iter().enumerate() {
+            let segment = match *part {
+                "" => return Err(TopicError::EmptySegment(pattern.to_string())),
+                "*" => PatternSegment::Single,
+                "#" if index + 1 == parts.len() => PatternSegment::Multi,
+                "#" => return Err(TopicError::MultiWildcardNotLast(pattern.to_string())),
+                literal => PatternSegment::Literal(literal.to_string()),
+            };
+            segments.push(segment);
+        }
+
+        Ok(Self {
+            source: pattern.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn segments(&self) -> &[PatternSegment] {
+        &self.segments
+    }
+
+    /// ワイルドカードを含むか
+    pub fn has_wildcards(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| !matches!(s, PatternSegment::Literal(_)))
+    }
+
+    /// トピックがパターンに一致するか
+    pub fn matches(&self, topic: &str) -> bool {
+        let parts: Vec<&str> = topic.split(TOPIC_SEPARATOR).collect();
+        match_segments(&self.segments, &parts)
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn match_segments(pattern: &[PatternSegment], topic: &[&str]) -> bool {
+    match (pattern.split_first(), topic.split_first()) {
+        (Some((PatternSegment::Multi, _)), _) => true,
+        (None, None) => true,
+        (Some((PatternSegment::Single, rest)), Some((_, topic_rest))) => {
+            match_segments(rest, topic_rest)
+        }
+        (Some((PatternSegment::Literal(name), rest)), Some((part, topic_rest))) => {
+            name == part && match_segments(rest, topic_rest)
+        }
+        _ => false,
+    }
+}
+
+/// 発行先トピックを検証（ワイルドカード不可）
+pub fn validate_topic(topic: &str) -> Result<(), TopicError> {
+    if TopicPattern::parse(topic)?.has_wildcards() {
+        return Err(TopicError::WildcardInTopic(topic.to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct TrieNode {
+    children: HashMap<String, TrieNode>,
+    single: Option<Box<TrieNode>>,
+    /// このノードで終わるパターンの購読
+    exact: Vec<SubscriptionId>,
+    /// このノードに続く`#`の購読
+    multi: Vec<SubscriptionId>,
+}
+
+impl TrieNode {
+    fn is_empty(&self) -> bool {
+        self.children.is_empty()
+            && self.single.is_none()
+            && self.exact.is_empty()
+            && self.multi.is_empty()
+    }
+
+    fn collect(&self, segments: &[&str], out: &mut Vec<SubscriptionId>) {
+        out.extend_from_slice(&self.multi);
+
+        let Some((head, rest)) = segments.split_first() else {
+            out.extend_from_slice(&self.exact);
+            return;
+        };
+        if let Some(child) = self.children.get(*head) {
+            child.collect(rest, out);
+        }
+        if let Some(single) = &self.single {
+            single.collect(rest, out);
+        }
+    }
+
+    /// 購読を削除し、ノードが空になったかを返す
+    fn remove(&mut self, segments: &[PatternSegment], id: SubscriptionId) -> bool {
+        match segments.split_first() {
+            None => self.exact.retain(|s| *s != id),
+            Some((PatternSegment::Multi, _)) => self.multi.retain(|s| *s != id),
+            Some((PatternSegment::Single, rest)) => {
+                if let Some(single) = &mut self.single {
+                    if single.remove(rest, id) {
+                        self.single = None;
+                    }
+                }
+            }
+            Some((PatternSegment::Literal(name), rest)) => {
+                if let Some(child) = self.children.get_mut(name) {
+                    if child.remove(rest, id) {
+                        self.children.remove(name);
+                    }
+                }
+            }
+        }
+        self.is_empty()
+    }
+}
+
+/// パターンから購読IDを引くトライ木
+#[derive(Default)]
+pub struct TopicTrie {
+    root: TrieNode,
+}
+
+impl TopicTrie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// パターンに購読を追加
+    pub fn insert(&mut self, pattern: &TopicPattern, id: SubscriptionId) {
+        let mut node = &mut self.root;
+        for segment in &pattern.segments {
+            node = match segment {
+                PatternSegment::Literal(name) => node.children.entry(name.clone()).or_default(),
+                PatternSegment::Single => node.single.get_or_insert_with(Default::default),
+                PatternSegment::Multi => {
+                    node.multi.push(id);
+                    return;
+                }
+            };
+        }
+        node.exact.push(id);
+    }
+
+    /// パターンから購読を削除
+    pub fn remove(&mut self, pattern: &TopicPattern, id: SubscriptionId) {
+        self.root.remove(&pattern.segments, id);
+    }
+
+    /// トピックに一致する購読IDを返す
+    pub fn matches(&self, topic: &str) -> Vec<SubscriptionId> {
+        let segments: Vec<&str> = topic.split(TOPIC_SEPARATOR).collect();
+        let mut out = Vec::new();
+        self.root.collect(&segments, &mut out);
+        out
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_parsing() {
+        assert!(TopicPattern::parse("sensors.*.temp").is_ok());
+        assert!(TopicPattern::parse("sensors.#").is_ok());
+        assert_eq!(TopicPattern::parse(""), Err(TopicError::Empty));
+        assert!(matches!(
+            TopicPattern::parse("sensors..temp"),
+            Err(TopicError::EmptySegment(_))
+        ));
+        assert!(matches!(
+            TopicPattern::parse("sensors.#.temp"),
+            Err(TopicError::MultiWildcardNotLast(_))
+        ));
+        assert!(validate_topic("sensors.room1.temp").is_ok());
+        assert!(matches!(
+            validate_topic("sensors.*"),
+            Err(TopicError::WildcardInTopic(_))
+        ));
+    }
+
+    #[test]
+    fn test_long_and_deep_patterns_are_rejected() {
+        let deep = vec!["a"; MAX_TOPIC_SEGMENTS + 1].join(".");
+        assert_eq!(
+            TopicPattern::parse(&deep),
+            Err(TopicError::TooManySegments {
+                segments: MAX_TOPIC_SEGMENTS + 1,
+                max: MAX_TOPIC_SEGMENTS
+            })
+        );
+        assert!(matches!(
+            validate_topic(&"a.".repeat(100_000)),
+            Err(TopicError::TooLong { .. })
+        ));
+        assert!(matches!(
+            TopicPattern::parse(&"x".repeat(MAX_TOPIC_LENGTH + 1)),
+            Err(TopicError::TooLong { .. })
+        ));
+
+        let deepest = vec!["*"; MAX_TOPIC_SEGMENTS].join(".");
+        let pattern = TopicPattern::parse(&deepest).unwrap();
+        let mut trie = TopicTrie::new();
+        trie.insert(&pattern, 1);
+        assert_eq!(
+            trie.matches(&vec!["a"; MAX_TOPIC_SEGMENTS].join(".")),
+            vec![1]
+        );
+        trie.remove(&pattern, 1);
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn test_wildcard_matching() {
+        let single = TopicPattern::parse("sensors.*.temp").unwrap();
+        assert!(single.matches("sensors.room1.temp"));
+        assert!(!single.matches("sensors.room1.humidity"));
+        assert!(!single.matches("sensors.floor1.room1.temp"));
+
+        let multi = TopicPattern::parse("sensors.#").unwrap();
+        assert!(multi.matches("sensors"));
+        assert!(multi.matches("sensors.room1"));
+        assert!(multi.matches("sensors.floor1.room1.temp"));
+        assert!(!multi.matches("alerts.room1"));
+    }
+
+    #[test]
+    fn test_trie_insert_and_remove() {
+        let exact = TopicPattern::parse("sensors.room1.temp").unwrap();
+        let single = TopicPattern::parse("sensors.*.temp").unwrap();
+        let multi = TopicPattern::parse("#").unwrap();
+
+        let mut trie = TopicTrie::new();
+        trie.insert(&exact, 1);
+        trie.insert(&single, 2);
+        trie.insert(&multi, 3);
+
+        let mut ids = trie.matches("sensors.room1.temp");
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(trie.matches("alerts"), vec![3]);
+
+        trie.remove(&exact, 1);
+        trie.remove(&single, 2);
+        trie.remove(&multi, 3);
+        assert!(trie.is_empty());
+    }
+}
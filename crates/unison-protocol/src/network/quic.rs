@@ -1,29 +1,64 @@
 use anyhow::{Context, Result};
-use futures_util::StreamExt;
+use futures_util::{Stream, StreamExt};
 use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream, ServerConfig};
 use rust_embed::RustEmbed;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use rustls::{ClientConfig as RustlsClientConfig, ServerConfig as RustlsServerConfig};
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::{
     Arc,
-    atomic::{AtomicBool, AtomicU64, Ordering},
+    atomic::{AtomicBool, Ordering},
 };
-use std::time::SystemTime;
-use tokio::sync::{Mutex, RwLock, mpsc};
-use tracing::{error, info, warn};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{Mutex, Notify, RwLock, mpsc};
+use tracing::{Instrument, error, info, warn};
+
+use crate::allocation::{Subsystem, in_subsystem};
+use crate::packet::{CompressionConfig, SerializationError};
+use crate::parser::ParsedSchema;
 
 use super::{
-    MessageType, NetworkError, ProtocolFrame, ProtocolMessage, ProtocolServerTrait, StreamHandle,
-    SystemStream, server::ProtocolServer,
+    MessageType, NetworkError, ProtocolError, ProtocolFrame, ProtocolMessage, StreamHandle,
+    SystemStream,
+    broadcast::ConnectionId,
+    connection_stats::{ConnectionMeter, ConnectionStats, StreamCounters, SystemStreamStats},
+    deadline::header_deadline,
+    decode::{DecodeConfig, decode_frame},
+    encoding::{self, AdaptiveEncodingConfig, ConnectionEncoding, EncodingProfile},
+    events::CloseReason,
+    failover::DRAIN_EVENT_METHOD,
+    flow::{FlowControlConfig, SendWindow, WindowError},
+    framing::MAX_FRAME_SIZE,
+    handler::HandlerResponse,
+    handshake::{self, Capabilities, SchemaIdentity},
+    happy_eyeballs::{self, HappyEyeballsConfig},
+    heartbeat::{self, HeartbeatConfig, LatencyStats},
+    proxy::ProxyConfig,
+    quota::{ConnectionMemory, MemoryReservation},
+    resolver::{CachingResolver, DnsCacheConfig, Resolver},
+    resume::StreamEvent,
+    schema_reload::SCHEMA_CHANGED_EVENT_METHOD,
+    server::{ClientStreamRequests, ProtocolServer, client_stream_item, stream_error_payload},
+    shutdown::{GOAWAY_CLOSE_CODE, GOAWAY_EVENT_METHOD},
+    state::{ConnectionState, ConnectionStateMachine, StateEvent},
+    stream_id::{StreamIdAllocator, StreamIdConfig, StreamInitiator},
+    supervisor::TaskSupervisor,
+    tls::{FileWatcher, TlsConfig},
+    trace,
+    udp::{self, UdpBackend, UdpSocketConfig},
 };
 
 /// Default certificate file paths for assets/certs directory
 pub const DEFAULT_CERT_PATH: &str = "assets/certs/cert.pem";
 pub const DEFAULT_KEY_PATH: &str = "assets/certs/private_key.der";
 
-/// Maximum message size for QUIC streams (8MB)
-const MAX_MESSAGE_SIZE: usize = 8 * 1024 * 1024;
+/// Default server port when the address omits it
+const DEFAULT_PORT: u16 = 8080;
+
+/// メモリ上限の超過で受信を拒否したときのストリームのエラーコード
+const QUOTA_EXCEEDED_CODE: u32 = 0x51;
 
 /// Embedded certificates for development use
 #[derive(RustEmbed)]
@@ -39,22 +74,287 @@ pub struct QuicClient {
     connection: Arc<RwLock<Option<Connection>>>,
     rx: Arc<RwLock<Option<mpsc::UnboundedReceiver<ProtocolMessage>>>>,
     tx: mpsc::UnboundedSender<ProtocolMessage>,
-    /// レスポンス受信タスクのハンドルを管理
-    response_tasks: Arc<Mutex<Vec<tokio::task::JoinHandle<()>>>>,
+    /// サーバーからのプッシュ通知（Event）の受信チャネル
+    event_rx: Arc<RwLock<Option<mpsc::UnboundedReceiver<ProtocolMessage>>>>,
+    event_tx: mpsc::UnboundedSender<ProtocolMessage>,
+    /// レスポンス・サーバーからのストリームを受信するタスク（切断時に中断して待機）
+    tasks: TaskSupervisor,
+    /// 接続状態
+    state: ConnectionStateMachine,
+    /// 最後に接続したURL（再接続に使用）
+    last_url: Arc<RwLock<Option<String>>>,
+    /// サーバーからのドレイン通知
+    drain: Arc<Notify>,
+    /// 複数アドレスへの接続レースの設定
+    happy_eyeballs: HappyEyeballsConfig,
+    /// ホスト名のリゾルバー（キャッシュ付き）
+    resolver: CachingResolver,
+    /// 外向き通信に使うプロキシ
+    proxy: Option<ProxyConfig>,
+    /// エンドポイントが使うUDPソケットの実装
+    udp_backend: UdpBackend,
+    /// エンドポイントが使うUDPソケットのチューニング
+    udp_config: UdpSocketConfig,
+    /// 接続ごとのハートビート（`None`の場合は送らない）
+    heartbeat: Option<HeartbeatConfig>,
+    /// 回線品質に応じた圧縮の切り替え（`None`の場合は切り替えない）
+    adaptive_encoding: Option<AdaptiveEncodingConfig>,
+    /// 現在の接続で使う圧縮のプロファイル
+    encoding: ConnectionEncoding,
+    /// ハンドシェイクで送るスキーマ
+    schema: Option<SchemaIdentity>,
+    /// ハンドシェイクで送る接続単位のメタデータ
+    handshake_metadata: HashMap<String, String>,
+    /// 現在の接続のハンドシェイクで取り決めた内容
+    capabilities: Arc<std::sync::RwLock<Option<Capabilities>>>,
+    /// TLSの設定（`None`の場合はサーバーの証明書を検証しない）
+    tls: Option<TlsConfig>,
+    /// ストリームIDの割り当て方
+    stream_ids: StreamIdConfig,
+    /// 現在の接続で開始するストリームのID（接続ごとに作り直す）
+    stream_id_allocator: Arc<std::sync::RwLock<Arc<StreamIdAllocator>>>,
+    /// 現在の接続の統計の記録
+    meter: Arc<std::sync::RwLock<Option<ConnectionMeter>>>,
 }
 
 impl QuicClient {
     pub fn new() -> Result<Self> {
         let (tx, rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
         Ok(Self {
             endpoint: None,
             connection: Arc::new(RwLock::new(None)),
             rx: Arc::new(RwLock::new(Some(rx))),
             tx,
-            response_tasks: Arc::new(Mutex::new(Vec::new())),
+            event_rx: Arc::new(RwLock::new(Some(event_rx))),
+            event_tx,
+            tasks: TaskSupervisor::new("QUIC client"),
+            state: ConnectionStateMachine::new(),
+            last_url: Arc::new(RwLock::new(None)),
+            drain: Arc::new(Notify::new()),
+            happy_eyeballs: HappyEyeballsConfig::default(),
+            resolver: CachingResolver::default(),
+            proxy: None,
+            udp_backend: UdpBackend::default(),
+            udp_config: UdpSocketConfig::default(),
+            heartbeat: None,
+            adaptive_encoding: None,
+            encoding: ConnectionEncoding::default(),
+            schema: None,
+            handshake_metadata: HashMap::new(),
+            capabilities: Arc::default(),
+            tls: None,
+            stream_ids: StreamIdConfig::default(),
+            stream_id_allocator: Arc::new(std::sync::RwLock::new(Arc::new(
+                StreamIdConfig::default().allocator(StreamInitiator::Client),
+            ))),
+            meter: Arc::default(),
         })
     }
 
+    /// 接続ごとにハートビートを送り、応答しなくなったサーバーとの接続を閉じる
+    pub fn with_heartbeat(mut self, config: HeartbeatConfig) -> Self {
+        self.heartbeat = Some(config);
+        self
+    }
+
+    pub(crate) fn set_heartbeat(&mut self, config: HeartbeatConfig) {
+        self.heartbeat = Some(config);
+    }
+
+    /// 接続ごとに往復時間を測り、回線が遅い間はより強く圧縮する
+    pub fn with_adaptive_encoding(mut self, config: AdaptiveEncodingConfig) -> Self {
+        self.adaptive_encoding = Some(config);
+        self
+    }
+
+    pub(crate) fn set_adaptive_encoding(&mut self, config: AdaptiveEncodingConfig) {
+        self.adaptive_encoding = Some(config);
+    }
+
+    /// ストリームIDの割り当て方を指定（サーバーと同じ方式を指定してください）
+    pub fn with_stream_ids(mut self, config: StreamIdConfig) -> Self {
+        self.set_stream_ids(config);
+        self
+    }
+
+    pub(crate) fn set_stream_ids(&mut self, config: StreamIdConfig) {
+        self.stream_ids = config;
+        *self.stream_id_allocator.write().unwrap() =
+            Arc::new(config.allocator(StreamInitiator::Client));
+    }
+
+    /// 現在の接続の往復時間・送受信量・開いたストリームの数など（未接続の場合は`None`）
+    pub fn connection_stats(&self) -> Option<ConnectionStats> {
+        self.meter
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(ConnectionMeter::stats)
+    }
+
+    /// 現在の接続でストリームを開いたことを記録
+    fn record_stream(&self) {
+        if let Some(meter) = self.meter.read().unwrap().as_ref() {
+            meter.record_stream();
+        }
+    }
+
+    /// 現在の接続で使っている圧縮のプロファイル
+    pub fn encoding(&self) -> EncodingProfile {
+        self.encoding.profile()
+    }
+
+    /// ハンドシェイクで照合するスキーマのハッシュを指定
+    pub fn with_schema_hash(mut self, hash: impl Into<String>) -> Self {
+        self.schema = Some(SchemaIdentity::from_fingerprint(hash));
+        self
+    }
+
+    /// 読み込んだスキーマの指紋をハンドシェイクで照合
+    pub fn with_schema_fingerprint(mut self, schema: &ParsedSchema) -> Self {
+        self.schema = Some(SchemaIdentity::from_schema(schema));
+        self
+    }
+
+    pub(crate) fn set_schema(&mut self, schema: SchemaIdentity) {
+        self.schema = Some(schema);
+    }
+
+    /// ハンドシェイクで接続単位のメタデータ（ロケールなど）を送る
+    pub fn with_handshake_metadata(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.set_handshake_metadata(key, value);
+        self
+    }
+
+    pub(crate) fn set_handshake_metadata(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) {
+        self.handshake_metadata.insert(key.into(), value.into());
+    }
+
+    /// サーバーの証明書の検証方法・クライアント証明書・サーバー名を指定
+    ///
+    /// [`TlsConfig`]または検証方法を明示的に選んだ[`ClientTlsConfig`](super::ClientTlsConfig)を渡します。
+    /// 指定しない場合はサーバーの証明書を検証しません（開発用）。
+    pub fn with_tls(mut self, tls: impl Into<TlsConfig>) -> Self {
+        self.set_tls(tls.into());
+        self
+    }
+
+    pub(crate) fn set_tls(&mut self, tls: TlsConfig) {
+        self.tls = Some(tls);
+    }
+
+    /// 現在の接続のハンドシェイクで取り決めた内容（未接続の場合は`None`）
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.capabilities.read().unwrap().clone()
+    }
+
+    /// 複数アドレスへの接続レースの設定を指定
+    pub fn with_happy_eyeballs(mut self, config: HappyEyeballsConfig) -> Self {
+        self.happy_eyeballs = config;
+        self
+    }
+
+    /// ホスト名のリゾルバーを指定（hickory-dns等）
+    pub fn with_resolver(mut self, resolver: Arc<dyn Resolver>) -> Self {
+        self.resolver = CachingResolver::new(resolver, self.resolver.config().clone());
+        self
+    }
+
+    /// プロキシ経由で接続する
+    ///
+    /// QUICはUDPを使うため、SOCKS5プロキシ（`UDP ASSOCIATE`）のみ対応しています。
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// エンドポイントが使うUDPソケットの実装を指定
+    ///
+    /// プロキシを指定している場合は、プロキシのUDP中継が優先されます。
+    pub fn with_udp_backend(mut self, backend: UdpBackend) -> Self {
+        self.udp_backend = backend;
+        self
+    }
+
+    /// エンドポイントが使うUDPソケットの送受信バッファとGSOを指定
+    pub fn with_udp_config(mut self, config: UdpSocketConfig) -> Self {
+        self.udp_config = config;
+        self
+    }
+
+    /// 名前解決キャッシュの設定を指定
+    pub fn with_dns_cache_config(mut self, config: DnsCacheConfig) -> Self {
+        self.resolver = CachingResolver::new(self.resolver.inner(), config);
+        self
+    }
+
+    /// 受信タスクとQUICの接続を駆動するタスクを実行するランタイムを指定
+    ///
+    /// 指定しない場合は`connect`を呼び出したランタイムで実行されます。
+    pub fn with_runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        self.set_runtime(runtime);
+        self
+    }
+
+    pub(crate) fn set_runtime(&mut self, runtime: tokio::runtime::Handle) {
+        self.tasks.release();
+        self.tasks = TaskSupervisor::with_runtime("QUIC client", runtime);
+    }
+
+    /// 接続先のアドレス候補とTLSのサーバー名を解決
+    ///
+    /// IPv6アドレス・ポート番号・`localhost`の形式はそのまま使い、
+    /// それ以外はホスト名としてリゾルバーで解決します（IPv4アドレスも候補に含みます）。
+    async fn resolve_server_addresses(&self, addr: &str) -> Result<(Vec<SocketAddr>, String)> {
+        let parse_error = match Self::parse_server_address(addr) {
+            Ok(socket_addr) => return Ok((vec![socket_addr], "localhost".to_string())),
+            Err(e) => e,
+        };
+        let Some((host, port)) = Self::split_hostname(addr)? else {
+            return Err(parse_error);
+        };
+
+        let addrs = self.resolver.resolve(host, port).await?;
+        Ok((addrs, host.to_string()))
+    }
+
+    /// ホスト名形式のアドレスをホスト名とポートに分解
+    ///
+    /// IPアドレスのリテラルは解析結果（IPv4の拒否など）に従うため`None`を返します。
+    fn split_hostname(addr: &str) -> Result<Option<(&str, u16)>> {
+        let (host, port) = match addr.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .map_err(|_| anyhow::anyhow!("無効なポート番号: {}", port))?,
+            ),
+            None => (addr, DEFAULT_PORT),
+        };
+        if host.is_empty() || host.contains(':') || host.parse::<std::net::IpAddr>().is_ok() {
+            return Ok(None);
+        }
+        Ok(Some((host, port)))
+    }
+
+    /// 接続に失敗したホスト名のキャッシュを破棄し、次回の接続で再解決させる
+    fn invalidate_resolution(resolver: &CachingResolver, addr: &str) {
+        if Self::parse_server_address(addr).is_ok() {
+            return;
+        }
+        if let Ok(Some((host, port))) = Self::split_hostname(addr) {
+            resolver.invalidate(host, port);
+        }
+    }
+
     /// Configure client with custom TLS configuration
     pub async fn configure_client() -> Result<ClientConfig> {
         let client_crypto_config = RustlsClientConfig::builder()
@@ -62,6 +362,15 @@ impl QuicClient {
             .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
             .with_no_client_auth();
 
+        Self::client_config_from_crypto(client_crypto_config)
+    }
+
+    /// [`TlsConfig`]でサーバーの証明書を検証し、必要ならクライアント証明書を提示する設定
+    pub fn configure_client_with_tls(tls: &TlsConfig) -> Result<ClientConfig> {
+        Self::client_config_from_crypto(tls.client_crypto()?)
+    }
+
+    fn client_config_from_crypto(client_crypto_config: RustlsClientConfig) -> Result<ClientConfig> {
         let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(client_crypto_config)?;
         let mut client_config = ClientConfig::new(Arc::new(crypto));
 
@@ -88,6 +397,12 @@ impl QuicClient {
     // 双方向ストリームを使うため、start_receive_loopは不要になりました
 }
 
+impl Drop for QuicClient {
+    fn drop(&mut self) {
+        self.tasks.release();
+    }
+}
+
 impl QuicClient {
     /// IPv6専用でサーバーアドレスを解析
     fn parse_server_address(addr: &str) -> Result<SocketAddr> {
@@ -104,9 +419,6 @@ impl QuicClient {
             }
         }
 
-        // デフォルトポート
-        const DEFAULT_PORT: u16 = 8080;
-
         // IPv6アドレスとして解析を試みる（ポートなし）
         if addr.contains(':') && !addr.contains('[') && !addr.contains('.') {
             // IPv6アドレスにデフォルトポートを追加
@@ -160,53 +472,224 @@ impl QuicClient {
     }
 
     pub async fn send(&self, message: ProtocolMessage) -> Result<()> {
-        let connection_guard = self.connection.read().await;
-        if let Some(connection) = connection_guard.as_ref() {
-            // 双方向ストリームを開く
-            let (mut send_stream, mut recv_stream) = connection
-                .open_bi()
-                .await
-                .context("Failed to open bidirectional QUIC stream")?;
+        let recv_stream = self.write_request(message, None).await?;
+
+        // レスポンスを受信してチャンネルに送る
+        let tx = self.tx.clone();
+        self.tasks.spawn(async move {
+            match read_response(recv_stream).await {
+                Ok(response) => {
+                    let _ = tx.send(response);
+                }
+                Err(e) => {
+                    error!("Failed to read response: {:#}", e);
+                }
+            }
+        });
 
-            // リクエストをフレームに変換して送信
-            let frame = message.into_frame().context("Failed to create frame")?;
-            let frame_bytes = frame.to_bytes();
-            send_stream
-                .write_all(&frame_bytes)
-                .await
-                .context("Failed to write to QUIC stream")?;
-            send_stream
-                .finish()
-                .context("Failed to finish QUIC send stream")?;
+        Ok(())
+    }
 
-            // レスポンスを受信してチャンネルに送る
-            let tx = self.tx.clone();
-            let task = tokio::spawn(async move {
-                match recv_stream.read_to_end(MAX_MESSAGE_SIZE).await {
-                    Ok(data) => {
-                        // フレームからProtocolMessageを復元
-                        let frame_bytes = bytes::Bytes::from(data);
-                        if let Ok(frame) = ProtocolFrame::from_bytes(&frame_bytes) {
-                            if let Ok(response) = ProtocolMessage::from_frame(&frame) {
-                                let _ = tx.send(response);
-                            }
-                        }
+    /// リクエストを送信し、同じストリームでレスポンスを受信
+    ///
+    /// リクエストごとに双方向ストリームを開くため、レスポンスの対応付けに共有の
+    /// 受信チャネルを使わず、複数のタスクから同時に呼び出せます。
+    pub async fn request(&self, message: ProtocolMessage) -> Result<ProtocolMessage> {
+        self.request_with_deadline(message, None).await
+    }
+
+    /// 期限付きでリクエストを送信し、同じストリームでレスポンスを受信
+    ///
+    /// 期限はヘッダーの`DEADLINE`拡張でサーバーへ伝わります。期限を過ぎても
+    /// レスポンスがなければストリームを破棄して`NetworkError::Timeout`を返します。
+    /// 呼び出しは`unison.client`のスパンで記録され、トレースコンテキストがサーバーへ送られます。
+    pub async fn request_with_deadline(
+        &self,
+        mut message: ProtocolMessage,
+        deadline: Option<SystemTime>,
+    ) -> Result<ProtocolMessage> {
+        let span = trace::client_span(&mut message);
+        let call = async {
+            let recv_stream = self.write_request(message, deadline).await?;
+            read_response(recv_stream).await
+        };
+        let Some(deadline) = deadline else {
+            return call.instrument(span).await;
+        };
+        let remaining = super::deadline::remaining(deadline).ok_or(NetworkError::Timeout)?;
+        tokio::time::timeout(remaining, call)
+            .instrument(span)
+            .await
+            .map_err(|_| NetworkError::Timeout)?
+    }
+
+    /// 双方向ストリームを開いてリクエストを書き込み、受信側を返す
+    async fn write_request(
+        &self,
+        mut message: ProtocolMessage,
+        deadline: Option<SystemTime>,
+    ) -> Result<RecvStream> {
+        let (mut send_stream, recv_stream) = self.open_bi().await?;
+        message.id = self.allocate_stream_id()?;
+
+        // リクエストをフレームに変換して送信
+        let frame = message
+            .into_frame_with_compression(deadline, self.encoding.compression())
+            .context("Failed to create frame")?;
+        write_stream_frame(&mut send_stream, &frame.to_bytes(), true)
+            .await
+            .context("Failed to write to QUIC stream")?;
+        send_stream
+            .finish()
+            .context("Failed to finish QUIC send stream")?;
+
+        Ok(recv_stream)
+    }
+
+    /// 双方向ストリームを開く
+    async fn open_bi(&self) -> Result<(SendStream, RecvStream)> {
+        // ストリームを開く間だけ接続のロックを保持する
+        let connection = self
+            .connection
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("QUIC not connected"))?;
+
+        let streams = connection
+            .open_bi()
+            .await
+            .context("Failed to open bidirectional QUIC stream")?;
+        self.record_stream();
+        Ok(streams)
+    }
+
+    /// 接続のエポックで次のストリームIDを割り当てる
+    ///
+    /// リクエストはストリームごとに送るため、メッセージIDにはストリームIDを使います。
+    fn allocate_stream_id(&self) -> Result<u64> {
+        Ok(self.stream_id_allocator.read().unwrap().allocate()?)
+    }
+
+    /// ハートビートのping/pongを`samples`回往復させて往復時間の統計を求める
+    pub async fn measure_latency(&self, samples: usize) -> Result<LatencyStats> {
+        let connection = self
+            .connection
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("QUIC not connected"))?;
+        let mut rtts = Vec::with_capacity(samples);
+        for seq in 0..samples as u64 {
+            rtts.push(heartbeat::ping(&connection, seq).await?);
+        }
+        LatencyStats::from_samples(&rtts).context("At least one sample is required")
+    }
+
+    /// 双方向ストリーム（SystemStream）を開始
+    pub async fn start_system_stream(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+    ) -> Result<UnisonStream> {
+        let connection = self
+            .connection
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("QUIC not connected"))?;
+        let stream_id = self.allocate_stream_id()?;
+        let stream =
+            UnisonStream::open(method.to_string(), payload, Arc::new(connection), stream_id)
+                .await?;
+        self.record_stream();
+        Ok(stream)
+    }
+
+    /// クライアントストリームを送信し、同じストリームでレスポンスを受信
+    ///
+    /// `message`（`ClientStream`）に続けて`requests`の各アイテムを`StreamData`として送り、
+    /// 最後に`StreamEnd`を送ります。アイテムがエラーの場合は`StreamError`を送って中断し、
+    /// そのエラーを返します。サーバーがストリームを読み終える前に応答した場合
+    /// （未登録のメソッドなど）は、送信を打ち切ってその応答を返します。
+    pub async fn request_client_stream<S>(
+        &self,
+        message: ProtocolMessage,
+        requests: S,
+    ) -> Result<ProtocolMessage>
+    where
+        S: Stream<Item = Result<serde_json::Value, NetworkError>> + Send,
+    {
+        let (mut send_stream, recv_stream) = self.open_bi().await?;
+        let mut message = message;
+        message.id = self.allocate_stream_id()?;
+        let id = message.id;
+        let method = message.method.clone();
+
+        let send = async {
+            write_stream_frame(&mut send_stream, &message.into_frame()?.to_bytes(), true).await?;
+            let mut requests = std::pin::pin!(requests);
+            let mut failed = None;
+            let (msg_type, payload) = loop {
+                match requests.next().await {
+                    Some(Ok(payload)) => {
+                        let item = ProtocolMessage::new_with_json(
+                            id,
+                            method.clone(),
+                            MessageType::StreamData,
+                            payload,
+                        )?;
+                        write_stream_frame(&mut send_stream, &item.into_frame()?.to_bytes(), true)
+                            .await?;
                     }
-                    Err(e) => {
-                        error!("Failed to read response: {}", e);
+                    Some(Err(e)) => {
+                        let payload = serde_json::json!({ "message": e.to_string() });
+                        failed = Some(e);
+                        break (MessageType::StreamError, payload);
                     }
+                    None => break (MessageType::StreamEnd, serde_json::json!({})),
                 }
-            });
-
-            // タスクハンドルを保存
-            self.response_tasks.lock().await.push(task);
-
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("QUIC not connected"))
+            };
+            let last = ProtocolMessage::new_with_json(id, method.clone(), msg_type, payload)?;
+            write_stream_frame(&mut send_stream, &last.into_frame()?.to_bytes(), true).await?;
+            send_stream
+                .finish()
+                .context("Failed to finish QUIC send stream")?;
+            anyhow::Ok(failed)
+        };
+        let response = read_response(recv_stream);
+        tokio::pin!(send, response);
+
+        tokio::select! {
+            response = &mut response => response,
+            sent = &mut send => match sent {
+                Ok(None) => response.await,
+                Ok(Some(e)) => Err(e.into()),
+                // サーバーが応答して受信を止めた場合は、書き込みの失敗より応答を優先する
+                Err(e) => response.await.map_err(|_| e),
+            }
         }
     }
 
+    /// ストリーム要求を送信し、同じストリームで届くメッセージを順に受信
+    ///
+    /// サーバーが送信を終えるか接続が失われると、返したストリームも終わります。
+    pub async fn request_stream(&self, message: ProtocolMessage) -> Result<MessageStream> {
+        let recv_stream = self.write_request(message, None).await?;
+        let reader = Some(FrameReader::new(recv_stream));
+        Ok(Box::pin(futures_util::stream::unfold(
+            reader,
+            |reader| async move {
+                let mut reader = reader?;
+                match reader.next_message().await {
+                    Ok(Some(message)) => Some((Ok(message), Some(reader))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(e), None)),
+                }
+            },
+        )))
+    }
+
     pub async fn receive(&self) -> Result<ProtocolMessage> {
         let mut rx_guard = self.rx.write().await;
         if let Some(rx) = rx_guard.as_mut() {
@@ -218,46 +701,204 @@ impl QuicClient {
         }
     }
 
-    pub async fn connect(&self, url: &str) -> Result<()> {
-        // Parse URL (IPv6 only)
-        let addr = Self::parse_server_address(url)?;
+    /// サーバーからのプッシュ通知（Event）を受信
+    pub async fn receive_event(&self) -> Result<ProtocolMessage> {
+        let mut rx_guard = self.event_rx.write().await;
+        if let Some(rx) = rx_guard.as_mut() {
+            rx.recv()
+                .await
+                .context("Failed to receive event from channel")
+        } else {
+            Err(anyhow::anyhow!("Event receiver not available"))
+        }
+    }
 
-        let client_config = Self::configure_client().await?;
+    pub async fn connect(&self, url: &str) -> Result<()> {
+        self.state.transition(ConnectionState::Connecting, None);
+        *self.last_url.write().await = Some(url.to_string());
+
+        let result = self
+            .establish(url)
+            .instrument(tracing::info_span!("unison.connect", url = %url))
+            .await;
+        if let Err(e) = &result {
+            Self::invalidate_resolution(&self.resolver, url);
+            self.state
+                .transition(ConnectionState::Idle, Some(format!("{:#}", e)));
+        }
+        result
+    }
 
-        // IPv6専用でバインド
-        let bind_addr: SocketAddr = "[::]:0".parse().unwrap();
+    async fn establish(&self, url: &str) -> Result<()> {
+        let (addrs, mut server_name) = self.resolve_server_addresses(url).await?;
+        let candidates = happy_eyeballs::sort_addresses(addrs, self.happy_eyeballs.prefer_ipv6);
 
-        let mut endpoint = Endpoint::client(bind_addr)?;
-        endpoint.set_default_client_config(client_config);
+        let client_config = match &self.tls {
+            Some(tls) => {
+                if let Some(name) = tls.server_name() {
+                    server_name = name.to_string();
+                }
+                Self::configure_client_with_tls(tls)?
+            }
+            None => Self::configure_client().await?,
+        };
 
-        let connection = endpoint
-            .connect(addr, "localhost")?
-            .await
-            .context("Failed to establish QUIC connection")?;
+        self.state.transition(ConnectionState::Handshaking, None);
+        // 複数のアドレス候補がある場合は接続をずらして開始し、最初に成功したものを採用
+        let (addr, connection) =
+            happy_eyeballs::race(candidates, self.happy_eyeballs.attempt_delay, |addr| {
+                connect_endpoint(
+                    addr,
+                    client_config.clone(),
+                    server_name.clone(),
+                    self.proxy.clone(),
+                    &self.udp_backend,
+                    &self.udp_config,
+                    self.tasks.runtime().cloned(),
+                )
+            })
+            .await?;
+
+        // 互換性のないサーバーとの接続はここで閉じられる
+        *self.capabilities.write().unwrap() = None;
+        let capabilities =
+            handshake::perform(&connection, self.schema.as_ref(), &self.handshake_metadata)
+                .instrument(tracing::debug_span!("unison.handshake"))
+                .await?;
+        info!(
+            "Connected to QUIC server at {} (session {})",
+            addr, capabilities.session_id
+        );
+        *self.capabilities.write().unwrap() = Some(capabilities);
+        // 再接続の前後でストリームIDが衝突しないよう、接続ごとにエポックを変える
+        *self.stream_id_allocator.write().unwrap() =
+            Arc::new(self.stream_ids.allocator(StreamInitiator::Client));
+        let meter = ConnectionMeter::new(Some(connection.clone()));
+        *self.meter.write().unwrap() = Some(meter.clone());
+
+        // サーバーから開始されたストリーム（ストリームデータ・イベント）を受信
+        self.tasks.spawn(receive_server_streams(
+            connection.clone(),
+            meter,
+            self.tx.clone(),
+            self.event_tx.clone(),
+            Arc::clone(&self.drain),
+            self.tasks.clone(),
+        ));
+        if let Some(config) = self.heartbeat {
+            self.tasks
+                .spawn(heartbeat::monitor(connection.clone(), config));
+        }
+        // 新しい接続は既定のプロファイルから始める
+        self.encoding.set(EncodingProfile::default());
+        if let Some(config) = self.adaptive_encoding {
+            self.tasks.spawn(encoding::adapt(
+                connection.clone(),
+                config,
+                self.encoding.clone(),
+            ));
+        }
 
-        info!("Connected to QUIC server at {} (IPv6)", addr);
+        // 接続が失われた場合はIdleへ遷移（明示的な切断時はタスクごと中断される）
+        let state = self.state.clone();
+        let monitored = connection.clone();
+        let resolver = self.resolver.clone();
+        let url = url.to_string();
+        self.tasks.spawn(async move {
+            let reason = monitored.closed().await;
+            Self::invalidate_resolution(&resolver, &url);
+            state.transition_if(
+                |current| current == ConnectionState::Ready,
+                ConnectionState::Idle,
+                Some(reason.to_string()),
+            );
+        });
 
         *self.connection.write().await = Some(connection);
+        self.state.transition(ConnectionState::Ready, None);
 
         Ok(())
     }
 
     pub async fn disconnect(&self) -> Result<()> {
-        // すべてのレスポンス受信タスクをキャンセル
-        let mut tasks = self.response_tasks.lock().await;
-        for task in tasks.drain(..) {
-            task.abort();
+        self.close_connection().await;
+        self.state.transition(
+            ConnectionState::Closed,
+            Some("client disconnect".to_string()),
+        );
+        Ok(())
+    }
+
+    /// 最後に接続したURLへ再接続
+    pub async fn reconnect(&self) -> Result<()> {
+        let url = self
+            .last_url
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("No previous connection to reconnect"))?;
+        self.reconnect_to(&url).await
+    }
+
+    /// 現在の接続を閉じて指定のURLへ再接続
+    ///
+    /// Ready（接続中）またはIdle（切断検知後）の状態からのみ再接続できます。
+    pub async fn reconnect_to(&self, url: &str) -> Result<()> {
+        if !self.state.transition(ConnectionState::Reconnecting, None) {
+            return Err(anyhow::anyhow!(
+                "Cannot reconnect while {}",
+                self.state.current()
+            ));
         }
 
+        self.close_connection().await;
+        self.connect(url).await
+    }
+
+    /// 最後に接続したURL
+    pub async fn last_url(&self) -> Option<String> {
+        self.last_url.read().await.clone()
+    }
+
+    /// サーバーからドレイン通知を受けるまで待機
+    ///
+    /// 待機していない間に受けた通知は1回分保持されます。
+    pub async fn drained(&self) {
+        self.drain.notified().await
+    }
+
+    /// 現在の接続状態
+    pub fn state(&self) -> ConnectionState {
+        self.state.current()
+    }
+
+    /// 接続状態の遷移イベントを購読
+    pub fn state_events(&self) -> Pin<Box<dyn Stream<Item = StateEvent> + Send>> {
+        self.state.events()
+    }
+
+    /// 指定の接続状態になるまで待機
+    pub async fn wait_for_state(&self, target: ConnectionState) {
+        self.state.wait_for(target).await
+    }
+
+    async fn close_connection(&self) {
+        // すべての受信タスクを中断し、終了を待つ
+        self.tasks.shutdown().await;
+
         // 接続をクローズ
+        *self.capabilities.write().unwrap() = None;
+        *self.meter.write().unwrap() = None;
         let mut connection_guard = self.connection.write().await;
         if let Some(connection) = connection_guard.take() {
             connection.close(quinn::VarInt::from_u32(0), b"client disconnect");
         }
-        Ok(())
     }
 
     pub async fn is_connected(&self) -> bool {
+        if self.state.current() != ConnectionState::Ready {
+            return false;
+        }
         let connection_guard = self.connection.read().await;
         if let Some(connection) = connection_guard.as_ref() {
             connection.close_reason().is_none()
@@ -267,20 +908,235 @@ impl QuicClient {
     }
 }
 
+/// アドレスファミリーに合わせたエンドポイントから1つのアドレスへ接続
+///
+/// プロキシが指定されている場合は、プロキシのUDP中継を使うエンドポイントから接続します。
+/// `runtime`を指定すると、エンドポイントと接続を駆動するタスクはそのランタイムで実行されます。
+async fn connect_endpoint(
+    addr: SocketAddr,
+    client_config: ClientConfig,
+    server_name: String,
+    proxy: Option<ProxyConfig>,
+    udp_backend: &UdpBackend,
+    udp_config: &UdpSocketConfig,
+    runtime: Option<tokio::runtime::Handle>,
+) -> Result<Connection> {
+    let socket = match &proxy {
+        Some(proxy) => Some(
+            proxy
+                .associate_udp()
+                .await
+                .with_context(|| format!("Failed to set up proxy {}", proxy.address))?,
+        ),
+        None => None,
+    };
+
+    let connecting = {
+        let _runtime = runtime.as_ref().map(tokio::runtime::Handle::enter);
+        let mut endpoint = match socket {
+            Some(socket) => Endpoint::new_with_abstract_socket(
+                quinn::EndpointConfig::default(),
+                None,
+                Arc::new(socket),
+                Arc::new(quinn::TokioRuntime),
+            )?,
+            None => {
+                let bind_addr: SocketAddr = if addr.is_ipv6() {
+                    "[::]:0".parse().unwrap()
+                } else {
+                    "0.0.0.0:0".parse().unwrap()
+                };
+                udp::bind_endpoint(udp_backend, udp_config, bind_addr, None)?
+            }
+        };
+        endpoint.set_default_client_config(client_config);
+        endpoint.connect(addr, &server_name)?
+    };
+
+    connecting
+        .await
+        .with_context(|| format!("Failed to establish QUIC connection to {}", addr))
+}
+
+/// サーバーから開始された双方向ストリームを受け付け、メッセージ種別ごとに振り分け
+async fn receive_server_streams(
+    connection: Connection,
+    meter: ConnectionMeter,
+    tx: mpsc::UnboundedSender<ProtocolMessage>,
+    event_tx: mpsc::UnboundedSender<ProtocolMessage>,
+    drain: Arc<Notify>,
+    tasks: TaskSupervisor,
+) {
+    while let Ok((send_stream, recv_stream)) = connection.accept_bi().await {
+        meter.record_stream();
+        let tx = tx.clone();
+        let event_tx = event_tx.clone();
+        let drain = Arc::clone(&drain);
+        tasks.spawn(async move {
+            // 1本のストリームで複数のメッセージが届くことがある
+            let mut reader = FrameReader::new(recv_stream);
+            let mut send_stream = Some(send_stream);
+            loop {
+                let frame_bytes = match reader.next().await {
+                    Ok(Some(frame_bytes)) => frame_bytes,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Failed to read server stream: {:#}", e);
+                        break;
+                    }
+                };
+                let message = reader.decode(frame_bytes).await.map(|(_, message)| message);
+                match message {
+                    Ok(message) if heartbeat::is_heartbeat(&message) => {
+                        if let Some(send_stream) = send_stream.take() {
+                            heartbeat::answer(send_stream, message).await;
+                        }
+                    }
+                    Ok(message)
+                        if message.msg_type == MessageType::Event
+                            && (message.method == DRAIN_EVENT_METHOD
+                                || message.method == GOAWAY_EVENT_METHOD) =>
+                    {
+                        info!("Server requested drain: {}", message.payload);
+                        drain.notify_one();
+                    }
+                    Ok(message)
+                        if message.msg_type == MessageType::Event
+                            && message.method == SCHEMA_CHANGED_EVENT_METHOD =>
+                    {
+                        info!("Server schema changed: {}", message.payload);
+                        let _ = event_tx.send(message);
+                    }
+                    Ok(message) if message.msg_type == MessageType::Event => {
+                        let _ = event_tx.send(message);
+                    }
+                    Ok(message) => {
+                        let _ = tx.send(message);
+                    }
+                    Err(e) => warn!("Failed to parse server message: {}", e),
+                }
+            }
+        });
+    }
+}
+
 /// QUICサーバー実装
 pub struct QuicServer {
     server: Arc<ProtocolServer>,
     endpoint: Option<Endpoint>,
+    /// 接続とリクエストの処理タスク（停止時に中断して待機）
+    tasks: TaskSupervisor,
+    /// エンドポイントが使うUDPソケットのチューニング
+    udp_config: UdpSocketConfig,
+    /// TLSの設定（`None`の場合は開発用の証明書を使う）
+    tls: Option<TlsConfig>,
+    /// 証明書ファイルの更新を確認する間隔（`None`の場合は確認しない）
+    certificate_reload: Option<Duration>,
+}
+
+impl Drop for QuicServer {
+    fn drop(&mut self) {
+        self.tasks.release();
+    }
 }
 
 impl QuicServer {
     pub fn new(server: Arc<ProtocolServer>) -> Self {
+        let tasks = match server.runtime() {
+            Some(runtime) => TaskSupervisor::with_runtime("QUIC server", runtime.clone()),
+            None => TaskSupervisor::new("QUIC server"),
+        };
         Self {
+            udp_config: server.udp_config().clone(),
+            tls: server.tls().cloned(),
+            certificate_reload: server.certificate_reload(),
             server,
             endpoint: None,
+            tasks,
         }
     }
 
+    /// 証明書とクライアント証明書の要求を指定
+    ///
+    /// 指定しない場合は[`ProtocolServer::with_tls`]の設定、それもなければ開発用の証明書を使います。
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// 証明書ファイルの更新を`interval`ごとに確認し、更新されていれば読み込み直す
+    ///
+    /// 指定しない場合は[`ProtocolServer::with_certificate_reload`]の設定を使います。
+    pub fn with_certificate_reload(mut self, interval: Duration) -> Self {
+        self.certificate_reload = Some(interval);
+        self
+    }
+
+    /// 証明書を読み込み直し、以降の新しい接続に使う（既存の接続は維持される）
+    ///
+    /// [`TlsConfig`]を指定している場合はそのファイルまたはコールバックから、
+    /// 指定していない場合は`assets/certs`・埋め込み・自己署名の順に読み込みます。
+    /// 読み込みに失敗した場合は以前の証明書を使い続けます。
+    pub async fn reload_certificates(&self) -> Result<()> {
+        let endpoint = self
+            .endpoint
+            .as_ref()
+            .context("Server not bound to an address")?;
+        reload_endpoint_certificates(endpoint, self.tls.as_ref()).await
+    }
+
+    /// 証明書の読み込み直しの要求（[`ProtocolServer::reload_certificates`]）と
+    /// ファイルの更新を待ち、エンドポイントの設定を入れ替える
+    fn spawn_certificate_reloader(&self, endpoint: Endpoint) {
+        let tls = self.tls.clone();
+        let interval = self.certificate_reload;
+        let server = Arc::clone(&self.server);
+        self.tasks.spawn(async move {
+            let files = match &tls {
+                Some(tls) => tls.identity_files(),
+                None => vec![DEFAULT_CERT_PATH.into(), DEFAULT_KEY_PATH.into()],
+            };
+            // コールバックで読み込む場合は更新を検出できないため、間隔ごとに読み込み直す
+            let poll_loader = tls.as_ref().is_some_and(TlsConfig::has_identity_loader);
+            let mut watcher = FileWatcher::new(files);
+            let reloader = server.certificate_reloader().clone();
+            let mut ticker = interval.map(|interval| {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                ticker
+            });
+            loop {
+                tokio::select! {
+                    _ = reloader.requested() => {}
+                    _ = async { ticker.as_mut().unwrap().tick().await }, if ticker.is_some() => {
+                        if !watcher.changed() && !poll_loader {
+                            continue;
+                        }
+                    }
+                }
+                // 停止処理中は新しい接続を受け付けないよう設定を外しているため戻さない
+                if server.shutdown_controller().is_stopping() {
+                    break;
+                }
+                match reload_endpoint_certificates(&endpoint, tls.as_ref()).await {
+                    Ok(()) => info!("🔐 Reloaded QUIC server certificates"),
+                    Err(e) => warn!(
+                        "Failed to reload certificates, keeping the current ones: {:#}",
+                        e
+                    ),
+                }
+            }
+        });
+    }
+
+    /// エンドポイントが使うUDPソケットの送受信バッファとGSOを指定
+    ///
+    /// 指定しない場合は[`ProtocolServer::with_udp_config`]の設定を使います。
+    pub fn with_udp_config(mut self, config: UdpSocketConfig) -> Self {
+        self.udp_config = config;
+        self
+    }
+
     /// QUIC/TLS 1.3用の自己署名証明書を生成（本番環境使用に最適化）
     pub fn generate_self_signed_cert()
     -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
@@ -377,6 +1233,15 @@ impl QuicServer {
             .with_single_cert(certs, private_key)
             .map_err(|e| anyhow::anyhow!("Failed to configure TLS: {}", e))?;
 
+        Self::server_config_from_crypto(rustls_server_config)
+    }
+
+    /// [`TlsConfig`]の証明書を使い、必要ならクライアント証明書を検証する設定
+    pub fn configure_server_with_tls(tls: &TlsConfig) -> Result<ServerConfig> {
+        Self::server_config_from_crypto(tls.server_crypto()?)
+    }
+
+    fn server_config_from_crypto(rustls_server_config: RustlsServerConfig) -> Result<ServerConfig> {
         let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(rustls_server_config)?;
         let mut server_config = ServerConfig::with_crypto(Arc::new(crypto));
 
@@ -405,14 +1270,33 @@ impl QuicServer {
         // IPv6を優先的に使用し、IPv4もサポート
         let socket_addr = Self::parse_socket_addr(addr)?;
 
-        let server_config = Self::configure_server().await?;
-        let endpoint = Endpoint::server(server_config, socket_addr)?;
+        let server_config = match &self.tls {
+            Some(tls) => Self::configure_server_with_tls(tls)?,
+            None => Self::configure_server().await?,
+        };
+        // 受け付けた接続を駆動するタスクもサーバーのランタイムで実行する
+        let _runtime = self.server.runtime().map(tokio::runtime::Handle::enter);
+        let endpoint = udp::bind_endpoint(
+            self.server.udp_backend(),
+            &self.udp_config,
+            socket_addr,
+            Some(server_config),
+        )?;
 
         info!("QUIC server bound to {} (IPv6)", socket_addr);
         self.endpoint = Some(endpoint);
         Ok(())
     }
 
+    /// バインドしたローカルアドレス（ポート0でバインドした場合に割り当てられたポートの確認用）
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        let endpoint = self
+            .endpoint
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("QUIC server is not bound"))?;
+        Ok(endpoint.local_addr()?)
+    }
+
     /// IPv6専用でソケットアドレスを解析
     fn parse_socket_addr(addr: &str) -> Result<SocketAddr> {
         // まず直接パースを試みる（IPv6のみ受け入れる）
@@ -483,242 +1367,239 @@ impl QuicServer {
             .context("Server not bound to an address")?;
 
         info!("QUIC server listening for connections");
-
-        while let Some(connecting) = endpoint.accept().await {
-            let connection = connecting.await?;
-            let remote_addr = connection.remote_address();
-            info!("New QUIC connection from: {}", remote_addr);
-
+        self.spawn_certificate_reloader(endpoint.clone());
+
+        let stopped = self.server.shutdown_controller().stopped();
+        tokio::pin!(stopped);
+        loop {
+            let connecting = tokio::select! {
+                connecting = endpoint.accept() => match connecting {
+                    Some(connecting) => connecting,
+                    None => break,
+                },
+                _ = &mut stopped => break,
+            };
             let server = Arc::clone(&self.server);
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(connection, server).await {
+            let tasks = self.tasks.clone();
+            self.tasks.spawn(in_subsystem(Subsystem::Quic, async move {
+                // TLSのハンドシェイクに失敗した接続（証明書の検証エラーなど）は受け付けを止めずに破棄する
+                let connection = match connecting.await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        warn!("QUIC handshake failed: {}", e);
+                        return;
+                    }
+                };
+                info!("New QUIC connection from: {}", connection.remote_address());
+                if let Err(e) = handle_connection(connection, server, tasks).await {
                     error!("Connection error: {}", e);
                 }
-            });
+            }));
         }
 
+        if self.server.shutdown_controller().is_stopping() {
+            self.shutdown(endpoint).await;
+        }
+        // 閉じた接続に残っている処理を中断し、すべての終了を待つ
+        self.tasks.shutdown().await;
         Ok(())
     }
+
+    /// 新しい接続を拒否し、処理中のハンドラーを猶予期間まで待ってから全接続を閉じる
+    async fn shutdown(&self, endpoint: &Endpoint) {
+        endpoint.set_server_config(None);
+
+        let grace = self.server.shutdown_grace();
+        let deadline = tokio::time::Instant::now() + grace;
+        match self.server.announce_goaway() {
+            Ok(handle) => {
+                let _ = tokio::time::timeout_at(deadline, handle.wait()).await;
+            }
+            Err(e) => warn!("Failed to announce shutdown: {}", e),
+        }
+
+        let shutdown = self.server.shutdown_controller();
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        if !shutdown.wait_idle(remaining).await {
+            warn!(
+                "{} request(s) still in flight after the {:?} grace period, closing connections",
+                shutdown.in_flight(),
+                grace
+            );
+        }
+
+        endpoint.close(
+            quinn::VarInt::from_u32(GOAWAY_CLOSE_CODE),
+            b"server shutdown",
+        );
+        endpoint.wait_idle().await;
+        info!("QUIC server shut down");
+    }
 }
 
-async fn handle_connection(connection: Connection, server: Arc<ProtocolServer>) -> Result<()> {
-    loop {
+async fn handle_connection(
+    connection: Connection,
+    server: Arc<ProtocolServer>,
+    tasks: TaskSupervisor,
+) -> Result<()> {
+    // ブロードキャスト配信先として登録
+    let connection_id = server.open_connection(
+        Arc::new(connection.clone()),
+        Some(connection.remote_address()),
+        Some(connection.clone()),
+    );
+    let memory = server.connections().memory(connection_id);
+    // クライアントが切り替えるまでは既定のプロファイルでレスポンスを送る
+    let encoding = ConnectionEncoding::default();
+    if let Some(config) = server.heartbeat() {
+        tasks.spawn(heartbeat::monitor(connection.clone(), config));
+    }
+
+    let reason = loop {
         let connection_clone = connection.clone();
         match connection.accept_bi().await {
-            Ok((mut send_stream, mut recv_stream)) => {
+            Ok((send_stream, recv_stream)) => {
                 let server = Arc::clone(&server);
                 let connection = connection_clone;
-
-                tokio::spawn(async move {
-                    match recv_stream.read_to_end(MAX_MESSAGE_SIZE).await {
-                        Ok(data) => {
+                let memory = memory.clone();
+                let encoding = encoding.clone();
+                // 停止時に完了を待つよう、応答を送り終えるまで処理中として記録
+                let in_flight = server.shutdown_controller().track();
+
+                tasks.spawn(in_subsystem(Subsystem::Quic, async move {
+                    let _in_flight = in_flight;
+                    let stream_id = quinn::VarInt::from(send_stream.id()).into_inner();
+                    let mut reader =
+                        FrameReader::new(recv_stream).with_decode_config(server.decode_config());
+                    // 確保したメモリはリクエストの処理が終わるまで保持する
+                    match read_with_quota(&mut reader, memory.as_deref()).await {
+                        Ok(None) => {}
+                        Ok(Some((frame_bytes, _reservation))) => {
                             // フレームからProtocolMessageを復元
-                            let frame_bytes = bytes::Bytes::from(data);
-                            let frame_result = ProtocolFrame::from_bytes(&frame_bytes);
                             let request_result =
-                                frame_result.and_then(|frame| ProtocolMessage::from_frame(&frame));
+                                reader.decode(frame_bytes).await.map(|(frame, message)| {
+                                    let header = frame.header().ok();
+                                    if let Some(header) = &header {
+                                        server.record_frame(header);
+                                    }
+                                    let deadline =
+                                        header.and_then(|header| header_deadline(&header));
+                                    (message, deadline)
+                                });
 
                             match request_result {
-                                Ok(request) => {
+                                Ok((request, deadline)) => {
+                                    // 規則に反するIDや使用中のIDと衝突したストリームは処理せずに拒否し、
+                                    // 受け付けたIDは応答を送り終えるまで使用中として記録する
+                                    let _claim = if is_control(&request) {
+                                        None
+                                    } else {
+                                        server.record_connection_stream(connection_id);
+                                        match server.claim_stream_id(connection_id, request.id) {
+                                            Ok(claim) => Some(claim),
+                                            Err(e) => {
+                                                reject_stream(
+                                                    &server,
+                                                    send_stream,
+                                                    request,
+                                                    e,
+                                                    !reader.is_legacy(),
+                                                    encoding.compression(),
+                                                )
+                                                .await;
+                                                return;
+                                            }
+                                        }
+                                    };
                                     // Process the message based on its type
                                     match request.msg_type {
+                                        _ if heartbeat::is_heartbeat(&request) => {
+                                            heartbeat::answer(send_stream, request).await;
+                                        }
+                                        _ if handshake::is_handshake(&request) => {
+                                            let reply =
+                                                server.accept_handshake(connection_id, &request);
+                                            handshake::answer(send_stream, &request, reply).await;
+                                        }
+                                        _ if encoding::is_encoding(&request) => {
+                                            encoding::answer(send_stream, request, &encoding).await;
+                                        }
                                         super::MessageType::Request => {
-                                            let payload_value = match request.payload_as_value() {
-                                                Ok(v) => v,
-                                                Err(e) => {
-                                                    error!(
-                                                        "Failed to parse request payload: {}",
-                                                        e
-                                                    );
-                                                    return;
-                                                }
-                                            };
-
-                                            let response = server
-                                                .handle_call(&request.method, payload_value)
-                                                .await;
-
-                                            let response_msg = match response {
-                                                Ok(payload) => {
-                                                    match ProtocolMessage::new_with_json(
-                                                        request.id,
-                                                        request.method,
-                                                        super::MessageType::Response,
-                                                        payload,
-                                                    ) {
-                                                        Ok(msg) => msg,
-                                                        Err(e) => {
-                                                            error!(
-                                                                "Failed to create response: {}",
-                                                                e
-                                                            );
-                                                            return;
-                                                        }
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    match ProtocolMessage::new_with_json(
-                                                        request.id,
-                                                        request.method,
-                                                        super::MessageType::Error,
-                                                        serde_json::json!({
-                                                            "message": e.to_string(),
-                                                        }),
-                                                    ) {
-                                                        Ok(msg) => msg,
-                                                        Err(e) => {
-                                                            error!(
-                                                                "Failed to create error response: {}",
-                                                                e
-                                                            );
-                                                            return;
-                                                        }
+                                            // メタデータのテナントに接続を紐づけてから処理
+                                            let response =
+                                                match server.bind_tenant(connection_id, &request) {
+                                                    Ok(_) => {
+                                                        server
+                                                            .handle_stream_request_message(
+                                                                connection_id,
+                                                                Some(stream_id),
+                                                                &request,
+                                                                deadline,
+                                                            )
+                                                            .await
                                                     }
-                                                }
-                                            };
-
-                                            // 双方向ストリームの送信側を使ってレスポンスをフレームとして送信
-                                            match response_msg.into_frame() {
-                                                Ok(frame) => {
-                                                    let frame_bytes = frame.to_bytes();
-                                                    if let Err(e) =
-                                                        send_stream.write_all(&frame_bytes).await
-                                                    {
-                                                        error!("Failed to send response: {}", e);
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    error!(
-                                                        "Failed to create response frame: {}",
-                                                        e
-                                                    );
-                                                }
-                                            }
-                                            let _ = send_stream.finish();
+                                                    Err(e) => HandlerResponse::error(e),
+                                                };
+                                            write_response(
+                                                &server,
+                                                send_stream,
+                                                response,
+                                                request,
+                                                !reader.is_legacy(),
+                                                encoding.compression(),
+                                            )
+                                            .await;
                                         }
-                                        super::MessageType::Stream => {
-                                            let payload_value = match request.payload_as_value() {
-                                                Ok(v) => v,
-                                                Err(e) => {
-                                                    error!(
-                                                        "Failed to parse stream request payload: {}",
-                                                        e
-                                                    );
-                                                    return;
-                                                }
-                                            };
-
-                                            match server
-                                                .handle_stream(&request.method, payload_value)
-                                                .await
-                                            {
-                                                Ok(mut stream) => {
-                                                    while let Some(item) = stream.next().await {
-                                                        let msg = match item {
-                                                            Ok(payload) => {
-                                                                match ProtocolMessage::new_with_json(
-                                                                    request.id,
-                                                                    request.method.clone(),
-                                                                    super::MessageType::StreamData,
-                                                                    payload,
-                                                                ) {
-                                                                    Ok(msg) => msg,
-                                                                    Err(e) => {
-                                                                        error!(
-                                                                            "Failed to create stream data message: {}",
-                                                                            e
-                                                                        );
-                                                                        break;
-                                                                    }
-                                                                }
-                                                            }
-                                                            Err(e) => {
-                                                                match ProtocolMessage::new_with_json(
-                                                                    request.id,
-                                                                    request.method.clone(),
-                                                                    super::MessageType::Error,
-                                                                    serde_json::json!({
-                                                                        "message": e.to_string(),
-                                                                    }),
-                                                                ) {
-                                                                    Ok(msg) => msg,
-                                                                    Err(e) => {
-                                                                        error!(
-                                                                            "Failed to create error message: {}",
-                                                                            e
-                                                                        );
-                                                                        break;
-                                                                    }
-                                                                }
-                                                            }
-                                                        };
-
-                                                        if let Err(e) =
-                                                            send_response(connection.clone(), msg)
-                                                                .await
-                                                        {
-                                                            error!(
-                                                                "Failed to send stream data: {}",
-                                                                e
-                                                            );
-                                                            break;
-                                                        }
+                                        super::MessageType::ClientStream => {
+                                            let length_prefixed = !reader.is_legacy();
+                                            let response =
+                                                match server.bind_tenant(connection_id, &request) {
+                                                    Ok(_) => {
+                                                        let requests =
+                                                            client_stream_requests(reader, memory);
+                                                        server
+                                                            .handle_client_stream(
+                                                                connection_id,
+                                                                &request.method,
+                                                                requests,
+                                                            )
+                                                            .await
                                                     }
-
-                                                    // Send stream end message
-                                                    let end_msg =
-                                                        match ProtocolMessage::new_with_json(
-                                                            request.id,
-                                                            request.method,
-                                                            super::MessageType::StreamEnd,
-                                                            serde_json::json!({}),
-                                                        ) {
-                                                            Ok(msg) => msg,
-                                                            Err(e) => {
-                                                                error!(
-                                                                    "Failed to create stream end message: {}",
-                                                                    e
-                                                                );
-                                                                return;
-                                                            }
-                                                        };
-
-                                                    if let Err(e) =
-                                                        send_response(connection, end_msg).await
-                                                    {
-                                                        error!("Failed to send stream end: {}", e);
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    let error_msg =
-                                                        match ProtocolMessage::new_with_json(
-                                                            request.id,
-                                                            request.method,
-                                                            super::MessageType::Error,
-                                                            serde_json::json!({
-                                                                "message": e.to_string(),
-                                                            }),
-                                                        ) {
-                                                            Ok(msg) => msg,
-                                                            Err(e) => {
-                                                                error!(
-                                                                    "Failed to create error message: {}",
-                                                                    e
-                                                                );
-                                                                return;
-                                                            }
-                                                        };
-
-                                                    if let Err(e) =
-                                                        send_response(connection, error_msg).await
-                                                    {
-                                                        error!(
-                                                            "Failed to send error response: {}",
-                                                            e
-                                                        );
-                                                    }
-                                                }
-                                            }
+                                                    Err(e) => HandlerResponse::error(e),
+                                                };
+                                            write_response(
+                                                &server,
+                                                send_stream,
+                                                response,
+                                                request,
+                                                length_prefixed,
+                                                encoding.compression(),
+                                            )
+                                            .await;
+                                        }
+                                        super::MessageType::BidirectionalStream => {
+                                            serve_system_stream(
+                                                &server,
+                                                connection_id,
+                                                request,
+                                                send_stream,
+                                                reader,
+                                                connection,
+                                            )
+                                            .await;
+                                        }
+                                        super::MessageType::Stream
+                                        | super::MessageType::StreamResume => {
+                                            let length_prefixed = !reader.is_legacy();
+                                            serve_stream(
+                                                &server,
+                                                connection_id,
+                                                request,
+                                                send_stream,
+                                                connection,
+                                                length_prefixed,
+                                            )
+                                            .await;
                                         }
                                         _ => {
                                             warn!(
@@ -734,36 +1615,532 @@ async fn handle_connection(connection: Connection, server: Arc<ProtocolServer>)
                             }
                         }
                         Err(e) => {
-                            error!("Failed to read from stream: {}", e);
+                            error!("Failed to read from stream: {:#}", e);
                         }
                     }
-                });
+                }));
             }
             Err(quinn::ConnectionError::ApplicationClosed(_)) => {
                 info!("Client disconnected");
-                break;
+                break CloseReason::ClientClosed;
+            }
+            Err(quinn::ConnectionError::LocallyClosed) => {
+                info!("Connection closed by server");
+                break CloseReason::ServerClosed;
             }
             Err(e) => {
                 error!("Failed to accept stream: {}", e);
-                break;
+                break CloseReason::Error(e.to_string());
+            }
+        }
+    };
+
+    server.connection_closed(connection_id, reason);
+    Ok(())
+}
+
+/// リクエストと同じストリームで、リクエストと同じ形式のレスポンスを送信して閉じる
+async fn write_response(
+    server: &ProtocolServer,
+    mut send_stream: SendStream,
+    response: HandlerResponse,
+    request: ProtocolMessage,
+    length_prefixed: bool,
+    compression: CompressionConfig,
+) {
+    let response_msg = match response.into_message(request.id, request.method) {
+        Ok(msg) => msg,
+        Err(e) => {
+            error!("Failed to create response: {}", e);
+            return;
+        }
+    };
+    match response_msg.into_frame_with_compression(None, compression) {
+        Ok(frame) => {
+            if let Ok(header) = frame.header() {
+                server.record_frame(&header);
+            }
+            if let Err(e) =
+                write_stream_frame(&mut send_stream, &frame.to_bytes(), length_prefixed).await
+            {
+                error!("Failed to send response: {}", e);
+            }
+        }
+        Err(e) => {
+            error!("Failed to create response frame: {}", e);
+        }
+    }
+    let _ = send_stream.finish();
+}
+
+/// クライアントストリームの残りのフレームを、ハンドラーへ渡すリクエストの列として読み込む
+///
+/// 各フレームは接続のメモリ上限の範囲で読み込み、変換し終えたら確保を解放します。
+fn client_stream_requests(
+    reader: FrameReader,
+    memory: Option<Arc<ConnectionMemory>>,
+) -> ClientStreamRequests {
+    Box::pin(futures_util::stream::unfold(
+        Some((reader, memory)),
+        |state| async move {
+            let (mut reader, memory) = state?;
+            let item = match read_with_quota(&mut reader, memory.as_deref()).await {
+                Ok(Some((frame_bytes, _reservation))) => reader
+                    .decode(frame_bytes)
+                    .await
+                    .map_err(NetworkError::from)
+                    .map_or_else(
+                        |e| Some(Err(e)),
+                        |(_, message)| client_stream_item(&message),
+                    ),
+                Ok(None) => Some(Err(NetworkError::Connection(
+                    "Client stream ended before StreamEnd".to_string(),
+                ))),
+                Err(e) => Some(Err(NetworkError::Connection(format!("{:#}", e)))),
+            };
+            let item = item?;
+            let next = item.is_ok().then_some((reader, memory));
+            Some((item, next))
+        },
+    ))
+}
+
+/// IDを受け付けられなかったストリームを、処理せずにエラーで閉じる
+///
+/// 双方向ストリームには`StreamError`、それ以外にはエラーのレスポンスを送ります。
+async fn reject_stream(
+    server: &ProtocolServer,
+    mut send_stream: SendStream,
+    request: ProtocolMessage,
+    error: ProtocolError,
+    length_prefixed: bool,
+    compression: CompressionConfig,
+) {
+    if request.msg_type != MessageType::BidirectionalStream {
+        let response = HandlerResponse::error(error);
+        write_response(
+            server,
+            send_stream,
+            response,
+            request,
+            length_prefixed,
+            compression,
+        )
+        .await;
+        return;
+    }
+    let last = ProtocolMessage::new_with_json(
+        request.id,
+        request.method,
+        MessageType::StreamError,
+        serde_json::json!({ "message": error.to_string() }),
+    )
+    .map_err(anyhow::Error::from)
+    .and_then(|message| Ok(message.into_frame()?.to_bytes()));
+    match last {
+        Ok(bytes) => {
+            if let Err(e) = write_stream_frame(&mut send_stream, &bytes, true).await {
+                warn!("Failed to reject stream: {:#}", e);
+            }
+        }
+        Err(e) => error!("Failed to create stream rejection: {:#}", e),
+    }
+    let _ = send_stream.finish();
+}
+
+/// ハートビート・ハンドシェイク・圧縮の切り替えの制御パケット
+fn is_control(message: &ProtocolMessage) -> bool {
+    heartbeat::is_heartbeat(message)
+        || handshake::is_handshake(message)
+        || encoding::is_encoding(message)
+}
+
+/// 双方向ストリームをハンドラーに渡し、ハンドラーが終わったらストリームを閉じる
+///
+/// ハンドラーがストリームを閉じずに終わった場合は、成功なら`StreamEnd`、
+/// 失敗なら`StreamError`を送ってから送信を終えます。
+async fn serve_system_stream(
+    server: &ProtocolServer,
+    connection_id: ConnectionId,
+    request: ProtocolMessage,
+    send_stream: SendStream,
+    reader: FrameReader,
+    connection: Connection,
+) {
+    let stream = UnisonStream::from_reader(
+        request.id,
+        request.method.clone(),
+        Arc::new(connection),
+        send_stream,
+        reader,
+    );
+    let send_half = Arc::clone(&stream.send_stream);
+    let window = Arc::clone(&stream.window);
+    let response = match (
+        server.bind_tenant(connection_id, &request),
+        request.payload_as_value(),
+    ) {
+        (Ok(_), Ok(payload)) => {
+            server
+                .handle_system_stream(connection_id, &request.method, payload, stream)
+                .await
+        }
+        (Err(e), _) => HandlerResponse::error(e),
+        (_, Err(e)) => HandlerResponse::error(ProtocolError::new(
+            ProtocolError::INVALID_REQUEST,
+            e.to_string(),
+        )),
+    };
+
+    // ハンドラーが送ったデータを書き込み終えてから終わりを送る
+    window.flushed().await;
+    let Some(mut send_stream) = send_half.lock().await.take() else {
+        return;
+    };
+    let (msg_type, payload) = match response.outcome {
+        Ok(_) => (MessageType::StreamEnd, serde_json::json!({})),
+        Err(e) => (
+            MessageType::StreamError,
+            serde_json::json!({ "message": e.to_string() }),
+        ),
+    };
+    let last = ProtocolMessage::new_with_json(request.id, request.method, msg_type, payload)
+        .map_err(anyhow::Error::from)
+        .and_then(|message| Ok(message.into_frame()?.to_bytes()));
+    match last {
+        Ok(bytes) => {
+            if let Err(e) = write_stream_frame(&mut send_stream, &bytes, true).await {
+                warn!("Failed to close system stream: {:#}", e);
+            }
+        }
+        Err(e) => error!("Failed to create system stream end: {:#}", e),
+    }
+    let _ = send_stream.finish();
+}
+
+/// 1つのストリーム要求に対して届くメッセージ
+pub(crate) type MessageStream = Pin<Box<dyn Stream<Item = Result<ProtocolMessage>> + Send>>;
+
+/// ストリーム要求を処理し、リクエストと同じストリームでイベントを送信
+///
+/// 各アイテムを`StreamData`として送信し、`StreamEnd`または`StreamError`を送信して閉じます。
+/// 長さ接頭辞に対応しない以前の形式のピアには、メッセージごとにサーバーから開いた
+/// ストリームで送信します。
+async fn serve_stream(
+    server: &ProtocolServer,
+    connection_id: ConnectionId,
+    request: ProtocolMessage,
+    mut send_stream: SendStream,
+    connection: Connection,
+    length_prefixed: bool,
+) {
+    // メタデータのテナントに接続を紐づけてから処理
+    let opened = match server.bind_tenant(connection_id, &request) {
+        Ok(_) => server.open_stream(connection_id, &request).await,
+        Err(e) => Err(e.into()),
+    };
+    let mut events = match opened {
+        Ok(events) => events,
+        Err(e) => {
+            let sent = send_stream_message(
+                &mut send_stream,
+                &connection,
+                length_prefixed,
+                &request,
+                super::MessageType::Error,
+                stream_error_payload(&e),
+            )
+            .await;
+            if let Err(e) = sent {
+                error!("Failed to send error response: {:#}", e);
             }
+            let _ = send_stream.finish();
+            return;
+        }
+    };
+
+    loop {
+        let (msg_type, payload) = match events.next().await {
+            Some(Ok(StreamEvent::Data(payload))) => (super::MessageType::StreamData, payload),
+            Some(Ok(StreamEvent::ResumeToken(token))) => (
+                super::MessageType::StreamResumeToken,
+                serde_json::to_value(token).unwrap_or_default(),
+            ),
+            Some(Err(e)) => (
+                super::MessageType::StreamError,
+                serde_json::json!({ "message": e.to_string() }),
+            ),
+            None => (super::MessageType::StreamEnd, serde_json::json!({})),
+        };
+        let last = matches!(
+            msg_type,
+            super::MessageType::StreamError | super::MessageType::StreamEnd
+        );
+        let sent = send_stream_message(
+            &mut send_stream,
+            &connection,
+            length_prefixed,
+            &request,
+            msg_type,
+            payload,
+        )
+        .await;
+        if let Err(e) = sent {
+            error!("Failed to send stream data: {:#}", e);
+            break;
+        }
+        if last {
+            break;
         }
     }
+    let _ = send_stream.finish();
+}
 
+/// ストリーム要求への1つのメッセージを送信
+async fn send_stream_message(
+    send_stream: &mut SendStream,
+    connection: &Connection,
+    length_prefixed: bool,
+    request: &ProtocolMessage,
+    msg_type: super::MessageType,
+    payload: serde_json::Value,
+) -> Result<()> {
+    let message =
+        ProtocolMessage::new_with_json(request.id, request.method.clone(), msg_type, payload)?;
+    if !length_prefixed {
+        return send_response(connection.clone(), message).await;
+    }
+    write_stream_frame(send_stream, &message.into_frame()?.to_bytes(), true).await?;
     Ok(())
 }
 
+/// 読み込み中のフレームの大きさ
+#[derive(Debug, Clone, Copy)]
+enum FrameStart {
+    /// 長さ接頭辞で示された大きさ
+    Length(usize),
+    /// 以前の形式（先頭の1バイトを読み込み済みで、ストリームの終端までがフレーム）
+    Legacy(u8),
+}
+
+/// QUICストリームの受信側から長さ接頭辞付きのフレームを順に読み込む
+///
+/// 形式は[`framing`](super::framing)と同じ（4バイトのビッグエンディアンの長さに続けてフレーム）で、
+/// 1本のストリームで複数のメッセージを送れます。フレームの大きさは上限未満のため長さの
+/// 先頭バイトは常に0になり、ヘッダーのバージョン（1以上）で始まるストリームは、
+/// ストリーム全体で1つのフレームを送る以前の形式として読み込みます。
+pub(crate) struct FrameReader {
+    stream: RecvStream,
+    /// 以前の形式のストリーム
+    legacy: bool,
+    /// ストリームの終端まで読み込んだ
+    finished: bool,
+    /// フレームの復元の設定
+    decode: DecodeConfig,
+}
+
+impl FrameReader {
+    pub(crate) fn new(stream: RecvStream) -> Self {
+        Self {
+            stream,
+            legacy: false,
+            finished: false,
+            decode: DecodeConfig::default(),
+        }
+    }
+
+    pub(crate) fn with_decode_config(mut self, decode: DecodeConfig) -> Self {
+        self.decode = decode;
+        self
+    }
+
+    /// 読み込んだフレームのバイト列を、大きさに応じてブロッキング用のスレッドで復元する
+    pub(crate) async fn decode(
+        &self,
+        frame_bytes: bytes::Bytes,
+    ) -> Result<(ProtocolFrame, ProtocolMessage), SerializationError> {
+        decode_frame(frame_bytes, self.decode).await
+    }
+
+    /// 以前の形式（長さ接頭辞なし）のストリームか
+    pub(crate) fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    /// 次のフレームの大きさを読み込む（フレームの境界でストリームが終わっていれば`None`）
+    async fn read_start(&mut self) -> Result<Option<FrameStart>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let mut first = [0u8; 1];
+        if self.stream.read(&mut first).await?.is_none() {
+            self.finished = true;
+            return Ok(None);
+        }
+        if first[0] != 0 {
+            self.legacy = true;
+            return Ok(Some(FrameStart::Legacy(first[0])));
+        }
+        let mut rest = [0u8; 3];
+        self.stream
+            .read_exact(&mut rest)
+            .await
+            .context("Stream ended inside a frame length")?;
+        let length = u32::from_be_bytes([first[0], rest[0], rest[1], rest[2]]) as usize;
+        Ok(Some(FrameStart::Length(length)))
+    }
+
+    /// フレームの本体を最大`limit`バイトまで読み込む
+    async fn read_body(&mut self, start: FrameStart, limit: usize) -> Result<bytes::Bytes> {
+        match start {
+            FrameStart::Length(length) => {
+                if length > limit {
+                    return Err(NetworkError::Protocol(format!(
+                        "Frame too large: {} bytes (max {})",
+                        length, limit
+                    ))
+                    .into());
+                }
+                let mut bytes = vec![0; length];
+                self.stream
+                    .read_exact(&mut bytes)
+                    .await
+                    .context("Stream ended inside a frame")?;
+                Ok(bytes.into())
+            }
+            FrameStart::Legacy(first) => {
+                self.finished = true;
+                let rest = self.stream.read_to_end(limit.saturating_sub(1)).await?;
+                let mut bytes = Vec::with_capacity(rest.len() + 1);
+                bytes.push(first);
+                bytes.extend_from_slice(&rest);
+                Ok(bytes.into())
+            }
+        }
+    }
+
+    /// 次のフレームのバイト列を読み込む（ストリームが終わっていれば`None`）
+    pub(crate) async fn next(&mut self) -> Result<Option<bytes::Bytes>> {
+        let Some(start) = self.read_start().await? else {
+            return Ok(None);
+        };
+        self.read_body(start, MAX_FRAME_SIZE).await.map(Some)
+    }
+
+    /// 次のメッセージを読み込む（ストリームが終わっていれば`None`）
+    pub(crate) async fn next_message(&mut self) -> Result<Option<ProtocolMessage>> {
+        let Some(frame_bytes) = self.next().await? else {
+            return Ok(None);
+        };
+        let (_, message) = self
+            .decode(frame_bytes)
+            .await
+            .context("Failed to parse frame")?;
+        Ok(Some(message))
+    }
+
+    /// 受信を停止し、送信側へエラーコードを伝える
+    pub(crate) fn stop(&mut self, code: u32) {
+        self.finished = true;
+        let _ = self.stream.stop(quinn::VarInt::from_u32(code));
+    }
+}
+
+/// フレームを書き込む
+///
+/// `length_prefixed`が`false`の場合は、以前の形式のピアへの応答として接頭辞を付けません。
+pub(crate) async fn write_stream_frame(
+    send_stream: &mut SendStream,
+    frame: &[u8],
+    length_prefixed: bool,
+) -> Result<(), quinn::WriteError> {
+    if length_prefixed {
+        send_stream
+            .write_all(&(frame.len() as u32).to_be_bytes())
+            .await?;
+    }
+    send_stream.write_all(frame).await
+}
+
+/// 受信ストリームからレスポンスのフレームを読み込む
+async fn read_response(recv_stream: RecvStream) -> Result<ProtocolMessage> {
+    FrameReader::new(recv_stream)
+        .next_message()
+        .await
+        .context("Failed to read response")?
+        .ok_or_else(|| anyhow::anyhow!("Stream closed without a response"))
+}
+
+/// 接続のメモリ上限の範囲で次のフレームを読み込む
+///
+/// フレームの大きさ分の空きを待って確保し、期限を過ぎた場合や空きを超えるフレームは
+/// ストリームを停止して拒否します。
+async fn read_with_quota(
+    reader: &mut FrameReader,
+    memory: Option<&ConnectionMemory>,
+) -> Result<Option<(bytes::Bytes, Option<MemoryReservation>)>> {
+    let Some(start) = reader.read_start().await? else {
+        return Ok(None);
+    };
+    let Some(memory) = memory else {
+        return Ok(Some((reader.read_body(start, MAX_FRAME_SIZE).await?, None)));
+    };
+
+    // 以前の形式は大きさが分からないため上限まで確保し、読み込み後に縮小する
+    let wanted = match start {
+        FrameStart::Length(length) => length,
+        FrameStart::Legacy(_) => MAX_FRAME_SIZE,
+    };
+    let mut reservation = match memory.reserve_inbound(wanted.min(MAX_FRAME_SIZE)).await {
+        Ok(reservation) => reservation,
+        Err(e) => {
+            reader.stop(QUOTA_EXCEEDED_CODE);
+            return Err(e.into());
+        }
+    };
+    match reader.read_body(start, reservation.bytes()).await {
+        Ok(bytes) => {
+            reservation.shrink_to(bytes.len());
+            Ok(Some((bytes, Some(reservation))))
+        }
+        Err(e) => {
+            let too_long = match start {
+                FrameStart::Length(length) => length > reservation.bytes(),
+                FrameStart::Legacy(_) => e
+                    .downcast_ref::<quinn::ReadToEndError>()
+                    .is_some_and(|e| matches!(e, quinn::ReadToEndError::TooLong)),
+            };
+            if too_long {
+                reader.stop(QUOTA_EXCEEDED_CODE);
+            }
+            Err(e)
+        }
+    }
+}
+
 async fn send_response(connection: Connection, message: ProtocolMessage) -> Result<()> {
     // 双方向ストリームを使用（レスポンスチャンネルは使わないが、プロトコルの一貫性のため）
     let (mut send_stream, _recv_stream) = connection.open_bi().await?;
     // ProtocolMessageをフレームに変換して送信
     let frame = message.into_frame()?;
-    let frame_bytes = frame.to_bytes();
-    send_stream.write_all(&frame_bytes).await?;
+    write_stream_frame(&mut send_stream, &frame.to_bytes(), true).await?;
     send_stream.finish()?;
     Ok(())
 }
 
+/// 証明書を読み込み直してエンドポイントのTLSの設定を入れ替える
+async fn reload_endpoint_certificates(endpoint: &Endpoint, tls: Option<&TlsConfig>) -> Result<()> {
+    let server_config = match tls {
+        Some(tls) => {
+            tls.reload_identity()?;
+            QuicServer::configure_server_with_tls(tls)?
+        }
+        None => QuicServer::configure_server().await?,
+    };
+    endpoint.set_server_config(Some(server_config));
+    Ok(())
+}
+
 /// 検証をスキップするカスタム証明書検証器（テスト専用）
 #[derive(Debug)]
 pub struct SkipServerVerification;
@@ -819,26 +2196,44 @@ impl rustls::client::danger::ServerCertVerifier for SkipServerVerification {
 }
 
 /// Unison Stream - QUIC双方向ストリーム実装
+///
+/// 送信はストリームごとの送信タスクが順に書き込み、書き込み待ちのバイト数は
+/// [`FlowControlConfig`]の上限と下限で制御されます（[`super::flow`]を参照）。
 pub struct UnisonStream {
     stream_id: u64,
     method: String,
-    #[allow(dead_code)]
     connection: Arc<Connection>,
     send_stream: Arc<Mutex<Option<SendStream>>>,
-    recv_stream: Arc<Mutex<Option<RecvStream>>>,
+    recv_stream: Arc<Mutex<Option<FrameReader>>>,
     is_active: Arc<AtomicBool>,
     handle: StreamHandle,
+    /// 送信タスクへ渡す書き込み待ちのフレーム
+    outbox: mpsc::UnboundedSender<bytes::Bytes>,
+    window: Arc<SendWindow>,
+    /// 送信タスクの書き込みが失敗した理由
+    write_error: Arc<std::sync::Mutex<Option<String>>>,
+    /// 送受信したメッセージとバイト数
+    counters: Arc<StreamCounters>,
 }
 
 impl UnisonStream {
+    /// 双方向ストリームを開く
+    ///
+    /// `stream_id`が`None`の場合は、プロセス内で共有する割り当て（クライアント側）からIDを選びます。
     pub async fn new(
         method: String,
         connection: Arc<Connection>,
         stream_id: Option<u64>,
     ) -> Result<Self> {
-        static STREAM_ID_COUNTER: AtomicU64 = AtomicU64::new(1);
+        static STREAM_IDS: std::sync::LazyLock<StreamIdAllocator> =
+            std::sync::LazyLock::new(|| {
+                StreamIdConfig::default().allocator(StreamInitiator::Client)
+            });
 
-        let id = stream_id.unwrap_or_else(|| STREAM_ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+        let id = match stream_id {
+            Some(id) => id,
+            None => STREAM_IDS.allocate()?,
+        };
 
         // Open bidirectional stream
         let (send_stream, recv_stream) = connection
@@ -846,21 +2241,42 @@ impl UnisonStream {
             .await
             .context("Failed to open bidirectional stream")?;
 
-        let handle = StreamHandle {
-            stream_id: id,
-            method: method.clone(),
-            created_at: SystemTime::now(),
-        };
-
-        Ok(Self {
-            stream_id: id,
+        Ok(Self::from_reader(
+            id,
             method,
             connection,
-            send_stream: Arc::new(Mutex::new(Some(send_stream))),
-            recv_stream: Arc::new(Mutex::new(Some(recv_stream))),
-            is_active: Arc::new(AtomicBool::new(true)),
-            handle,
-        })
+            send_stream,
+            FrameReader::new(recv_stream),
+        ))
+    }
+
+    /// 双方向ストリームを開き、最初のメッセージ（`BidirectionalStream`）を送信（クライアント側）
+    ///
+    /// サーバーは`method`に登録されたハンドラーに`payload`とストリームを渡します。
+    /// ハンドラーが見つからない場合や`stream_id`が衝突した場合などは、最初の`receive`が
+    /// エラーになります。
+    pub async fn open(
+        method: String,
+        payload: serde_json::Value,
+        connection: Arc<Connection>,
+        stream_id: u64,
+    ) -> Result<Self> {
+        let stream = Self::new(method, connection, Some(stream_id)).await?;
+        let message = ProtocolMessage::new_with_json(
+            stream.stream_id,
+            stream.method.clone(),
+            MessageType::BidirectionalStream,
+            payload,
+        )?;
+        let mut send_guard = stream.send_stream.lock().await;
+        let send_stream = send_guard
+            .as_mut()
+            .expect("newly opened stream has a send half");
+        write_stream_frame(send_stream, &message.into_frame()?.to_bytes(), true)
+            .await
+            .context("Failed to send initial stream message")?;
+        drop(send_guard);
+        Ok(stream)
     }
 
     /// 既存のストリームから作成（サーバー側）
@@ -870,53 +2286,155 @@ impl UnisonStream {
         connection: Arc<Connection>,
         send_stream: SendStream,
         recv_stream: RecvStream,
+    ) -> Self {
+        Self::from_reader(
+            stream_id,
+            method,
+            connection,
+            send_stream,
+            FrameReader::new(recv_stream),
+        )
+    }
+
+    /// 最初のメッセージを読み終えたストリームから作成（サーバー側）
+    fn from_reader(
+        stream_id: u64,
+        method: String,
+        connection: Arc<Connection>,
+        send_stream: SendStream,
+        reader: FrameReader,
     ) -> Self {
         let handle = StreamHandle {
             stream_id,
             method: method.clone(),
             created_at: SystemTime::now(),
         };
+        let send_stream = Arc::new(Mutex::new(Some(send_stream)));
+        let window = Arc::new(SendWindow::default());
+        let write_error = Arc::new(std::sync::Mutex::new(None));
+        let (outbox, frames) = mpsc::unbounded_channel();
+        tokio::spawn(write_stream_frames(
+            frames,
+            Arc::clone(&send_stream),
+            Arc::clone(&window),
+            Arc::clone(&write_error),
+        ));
 
         Self {
             stream_id,
             method,
             connection,
-            send_stream: Arc::new(Mutex::new(Some(send_stream))),
-            recv_stream: Arc::new(Mutex::new(Some(recv_stream))),
+            send_stream,
+            recv_stream: Arc::new(Mutex::new(Some(reader))),
             is_active: Arc::new(AtomicBool::new(true)),
             handle,
+            outbox,
+            window,
+            write_error,
+            counters: Arc::default(),
         }
     }
-}
 
-impl SystemStream for UnisonStream {
-    async fn send(&mut self, data: serde_json::Value) -> Result<(), NetworkError> {
+    /// フロー制御の上限と下限を変更したストリームを返す
+    pub fn with_flow_control(self, config: FlowControlConfig) -> Self {
+        self.window.configure(config);
+        self
+    }
+
+    /// 書き込み待ちのバイト数
+    pub fn buffered_bytes(&self) -> usize {
+        self.window.buffered()
+    }
+
+    /// 送受信したメッセージとバイト数、書き込み待ちのバイト数、開いてからの時間
+    pub fn stats(&self) -> SystemStreamStats {
+        let age = self.handle.created_at.elapsed().unwrap_or_default();
+        self.counters.stats(self.buffered_bytes(), age)
+    }
+
+    /// ストリームが使っているQUIC接続
+    pub(crate) fn quic_connection(&self) -> Connection {
+        (*self.connection).clone()
+    }
+
+    /// 待機せずに送信（書き込み待ちが上限に達している場合は`WouldBlock`）
+    ///
+    /// リアルタイム用途など、受信側が遅い間のデータを捨てたい場合に使います。
+    pub fn try_send(&self, data: serde_json::Value) -> Result<(), NetworkError> {
+        let frame = self.stream_frame(data)?;
+        match self.window.try_acquire(frame.len()) {
+            Ok(()) => self.enqueue(frame),
+            Err(WindowError::Full) => Err(NetworkError::WouldBlock),
+            Err(WindowError::Closed) => Err(self.closed_error()),
+        }
+    }
+
+    /// 送信する値を`StreamSend`のフレームに変換
+    fn stream_frame(&self, data: serde_json::Value) -> Result<bytes::Bytes, NetworkError> {
         if !self.is_active() {
             return Err(NetworkError::Connection("Stream is not active".to_string()));
         }
-
         let message = ProtocolMessage::new_with_json(
             self.stream_id,
             self.method.clone(),
             MessageType::StreamSend,
             data,
         )?;
+        Ok(message.into_frame()?.to_bytes())
+    }
 
-        // ProtocolMessageをフレームに変換
-        let frame = message.into_frame()?;
-        let frame_bytes = frame.to_bytes();
+    /// 送信枠を確保したフレームを送信タスクへ渡す
+    fn enqueue(&self, frame: bytes::Bytes) -> Result<(), NetworkError> {
+        let len = frame.len();
+        self.outbox.send(frame).map_err(|_| {
+            self.window.release(len);
+            self.closed_error()
+        })?;
+        self.counters.record_sent(len);
+        Ok(())
+    }
 
-        let mut send_guard = self.send_stream.lock().await;
-        if let Some(send_stream) = send_guard.as_mut() {
-            send_stream
-                .write_all(&frame_bytes)
+    /// 送信タスクが書き込みを終えた後の送信に返すエラー
+    fn closed_error(&self) -> NetworkError {
+        match self.write_error.lock().unwrap().clone() {
+            Some(e) => NetworkError::Quic(format!("Failed to send data: {}", e)),
+            None => NetworkError::Connection("Send stream is closed".to_string()),
+        }
+    }
+}
+
+/// ストリームの送信タスク：渡されたフレームを順に書き込み、書き込み終えた分の送信枠を返す
+///
+/// 書き込みに失敗すると理由を記録して終わり、以降の送信はエラーになります。
+async fn write_stream_frames(
+    mut frames: mpsc::UnboundedReceiver<bytes::Bytes>,
+    send_stream: Arc<Mutex<Option<SendStream>>>,
+    window: Arc<SendWindow>,
+    write_error: Arc<std::sync::Mutex<Option<String>>>,
+) {
+    while let Some(frame) = frames.recv().await {
+        let written = match send_stream.lock().await.as_mut() {
+            Some(send_stream) => write_stream_frame(send_stream, &frame, true)
                 .await
-                .map_err(|e| NetworkError::Quic(format!("Failed to send data: {}", e)))?;
-            Ok(())
-        } else {
-            Err(NetworkError::Connection(
-                "Send stream is closed".to_string(),
-            ))
+                .map_err(|e| e.to_string()),
+            None => Err("Send stream is closed".to_string()),
+        };
+        window.release(frame.len());
+        if let Err(e) = written {
+            *write_error.lock().unwrap() = Some(e);
+            break;
+        }
+    }
+    window.close();
+}
+
+impl SystemStream for UnisonStream {
+    async fn send(&mut self, data: serde_json::Value) -> Result<(), NetworkError> {
+        let frame = self.stream_frame(data)?;
+        // 書き込み待ちが上限に達している間は、下限まで減るのを待つ
+        match self.window.acquire(frame.len()).await {
+            Ok(()) => self.enqueue(frame),
+            Err(_) => Err(self.closed_error()),
         }
     }
 
@@ -926,43 +2444,34 @@ impl SystemStream for UnisonStream {
         }
 
         let mut recv_guard = self.recv_stream.lock().await;
-        if let Some(recv_stream) = recv_guard.as_mut() {
-            let data = recv_stream
-                .read_to_end(MAX_MESSAGE_SIZE)
-                .await // 8MB limit
-                .map_err(|e| NetworkError::Quic(format!("Failed to receive data: {}", e)))?;
-
-            if data.is_empty() {
+        if let Some(reader) = recv_guard.as_mut() {
+            // 次のフレームだけを読み込み、後続のメッセージはストリームに残す
+            let Some(frame_bytes) = reader
+                .next()
+                .await
+                .map_err(|e| NetworkError::Quic(format!("Failed to receive data: {:#}", e)))?
+            else {
                 self.is_active.store(false, Ordering::SeqCst);
                 return Err(NetworkError::Connection("Stream ended".to_string()));
-            }
+            };
 
             // BytesからフレームをデシリアライズしてProtocolMessageを復元
-            let frame_bytes = bytes::Bytes::from(data);
-            let frame = ProtocolFrame::from_bytes(&frame_bytes)?;
-            let message = ProtocolMessage::from_frame(&frame)?;
+            let len = frame_bytes.len();
+            let (_, message) = reader.decode(frame_bytes).await?;
 
             match message.msg_type {
-                MessageType::StreamReceive | MessageType::StreamData => message.payload_as_value(),
+                // 相手の`send`は`StreamSend`として届く
+                MessageType::StreamSend | MessageType::StreamReceive | MessageType::StreamData => {
+                    self.counters.record_received(len);
+                    message.payload_as_value()
+                }
                 MessageType::StreamEnd => {
                     self.is_active.store(false, Ordering::SeqCst);
                     Err(NetworkError::Connection("Stream ended by peer".to_string()))
                 }
                 MessageType::StreamError => {
                     self.is_active.store(false, Ordering::SeqCst);
-                    let error_msg = message
-                        .payload_as_value()
-                        .ok()
-                        .and_then(|v| {
-                            v.get("message")
-                                .and_then(|m| m.as_str())
-                                .map(|s| s.to_string())
-                        })
-                        .unwrap_or_else(|| "Unknown error".to_string());
-                    Err(NetworkError::Protocol(format!(
-                        "Stream error: {}",
-                        error_msg
-                    )))
+                    Err(super::client::response_error(&message))
                 }
                 _ => Err(NetworkError::Protocol(format!(
                     "Unexpected message type: {:?}",
@@ -977,11 +2486,14 @@ impl SystemStream for UnisonStream {
     }
 
     fn is_active(&self) -> bool {
-        self.is_active.load(Ordering::SeqCst)
+        // ハートビートの失敗などで接続が閉じた場合も非アクティブ
+        self.is_active.load(Ordering::SeqCst) && self.connection.close_reason().is_none()
     }
 
     async fn close(&mut self) -> Result<(), NetworkError> {
         self.is_active.store(false, Ordering::SeqCst);
+        // 送信済みのデータを書き込み終えてから送信を終える
+        self.window.flushed().await;
 
         // Close send stream
         if let Some(mut send_stream) = self.send_stream.lock().await.take() {
@@ -991,10 +2503,8 @@ impl SystemStream for UnisonStream {
         }
 
         // Close receive stream
-        if let Some(mut recv_stream) = self.recv_stream.lock().await.take() {
-            recv_stream.stop(quinn::VarInt::from_u32(0)).map_err(|e| {
-                NetworkError::Quic(format!("Failed to close receive stream: {}", e))
-            })?;
+        if let Some(mut reader) = self.recv_stream.lock().await.take() {
+            reader.stop(0);
         }
 
         info!(
@@ -1008,3 +2518,136 @@ impl SystemStream for UnisonStream {
         self.handle.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+    use crate::network::heartbeat::HEARTBEAT_CLOSE_CODE;
+    use serde_json::json;
+
+    /// ループバックでQUICの接続を張る（エンドポイントは接続を使い終わるまで保持する）
+    async fn connection_pair() -> (Endpoint, Endpoint, Connection, Connection) {
+        let server_config = QuicServer::configure_server().await.unwrap();
+        let server = Endpoint::server(server_config, "[::1]:0".parse().unwrap()).unwrap();
+        let mut client = Endpoint::client("[::]:0".parse().unwrap()).unwrap();
+        client.set_default_client_config(QuicClient::configure_client().await.unwrap());
+
+        let connecting = client
+            .connect(server.local_addr().unwrap(), "localhost")
+            .unwrap();
+        let accepting = async { server.accept().await.unwrap().await.unwrap() };
+        let (server_connection, connection) = tokio::join!(accepting, connecting);
+        (server, client, connection.unwrap(), server_connection)
+    }
+
+    fn frame_bytes(id: u64, payload: serde_json::Value) -> Vec<u8> {
+        ProtocolMessage::new_with_json(id, "echo".into(), MessageType::Request, payload)
+            .unwrap()
+            .into_frame()
+            .unwrap()
+            .to_bytes()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_multiple_frames_on_one_stream() {
+        let (_server, _client, connection, server_connection) = connection_pair().await;
+        let (mut send_stream, _) = connection.open_bi().await.unwrap();
+        let large = "x".repeat(1024 * 1024);
+        for (id, payload) in [(1, json!("a")), (2, json!(large)), (3, json!("c"))] {
+            write_stream_frame(&mut send_stream, &frame_bytes(id, payload), true)
+                .await
+                .unwrap();
+        }
+        send_stream.finish().unwrap();
+
+        let (_, recv_stream) = server_connection.accept_bi().await.unwrap();
+        let mut reader = FrameReader::new(recv_stream);
+        for id in 1..=3 {
+            let message = reader.next_message().await.unwrap().unwrap();
+            assert_eq!(message.id, id);
+        }
+        assert!(reader.next().await.unwrap().is_none());
+        assert!(!reader.is_legacy());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_stream_is_read_as_one_frame() {
+        let (_server, _client, connection, server_connection) = connection_pair().await;
+        let (mut send_stream, _) = connection.open_bi().await.unwrap();
+        write_stream_frame(&mut send_stream, &frame_bytes(7, json!({})), false)
+            .await
+            .unwrap();
+        send_stream.finish().unwrap();
+
+        let (_, recv_stream) = server_connection.accept_bi().await.unwrap();
+        let mut reader = FrameReader::new(recv_stream);
+        assert_eq!(reader.next_message().await.unwrap().unwrap().id, 7);
+        assert!(reader.is_legacy());
+        assert!(reader.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_is_rejected() {
+        let (_server, _client, connection, server_connection) = connection_pair().await;
+        let (mut send_stream, _) = connection.open_bi().await.unwrap();
+        send_stream
+            .write_all(&((MAX_FRAME_SIZE + 1) as u32).to_be_bytes())
+            .await
+            .unwrap();
+
+        let (_, recv_stream) = server_connection.accept_bi().await.unwrap();
+        let mut reader = FrameReader::new(recv_stream);
+        assert!(reader.next().await.is_err());
+    }
+
+    fn fast_heartbeat() -> HeartbeatConfig {
+        HeartbeatConfig {
+            interval: std::time::Duration::from_millis(50),
+            miss_threshold: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_closes_silent_peer() {
+        let (_server, _client, connection, server_connection) = connection_pair().await;
+        // 相手はストリームを受け付けず、pongを返さない
+        tokio::time::timeout(
+            std::time::Duration::from_secs(2),
+            heartbeat::monitor(connection.clone(), fast_heartbeat()),
+        )
+        .await
+        .unwrap();
+
+        assert!(connection.close_reason().is_some());
+        match server_connection.closed().await {
+            quinn::ConnectionError::ApplicationClosed(close) => {
+                assert_eq!(
+                    close.error_code,
+                    quinn::VarInt::from_u32(HEARTBEAT_CLOSE_CODE)
+                );
+            }
+            other => panic!("unexpected close: {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_keeps_answering_peer_open() {
+        let (_server, _client, connection, server_connection) = connection_pair().await;
+        tokio::spawn(async move {
+            while let Ok((send_stream, recv_stream)) = server_connection.accept_bi().await {
+                let ping = FrameReader::new(recv_stream).next_message().await;
+                if let Ok(Some(ping)) = ping {
+                    heartbeat::answer(send_stream, ping).await;
+                }
+            }
+        });
+
+        let monitor = heartbeat::monitor(connection.clone(), fast_heartbeat());
+        let still_running =
+            tokio::time::timeout(std::time::Duration::from_millis(400), monitor).await;
+        assert!(still_running.is_err());
+        assert!(connection.close_reason().is_none());
+    }
+}
@@ -0,0 +1,287 @@
+//! 接続ごとのメモリ使用量の計測と上限
+//!
+//! 遅いピアや悪意のあるピアがサーバーのメモリを使い尽くさないよう、接続ごとに
+//! バッファ中のバイト数を受信（リクエストのペイロード）と送信（ブロードキャストの
+//! 送信キュー）に分けて計測し、上限を超える新しい処理を待機または拒否します。
+//!
+//! - 受信: 空きができるまで[`MemoryQuotaConfig::park_timeout`]の間待機し、期限を過ぎたら拒否
+//! - 送信: 上限を超える配信は待機せずに破棄（遅いコンシューマーの扱いと同じ）
+//!
+//! 確保したバイト数は[`MemoryReservation`]の破棄時に解放されます。
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// メモリ上限の超過
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum QuotaError {
+    #[error("Memory quota exceeded: requested {requested} bytes, {available} of {limit} available")]
+    Exceeded {
+        requested: usize,
+        available: usize,
+        limit: usize,
+    },
+    #[error("Timed out after {0:?} waiting for memory quota")]
+    Timeout(Duration),
+}
+
+/// 接続ごとのメモリ上限の設定
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryQuotaConfig {
+    /// 受信中・処理中のペイロードの上限（バイト）
+    pub inbound_limit: usize,
+    /// 送信キューの上限（バイト）
+    pub outbound_limit: usize,
+    /// 受信の空きを待つ最大時間
+    #[serde(with = "crate::config::duration")]
+    pub park_timeout: Duration,
+}
+
+impl Default for MemoryQuotaConfig {
+    fn default() -> Self {
+        Self {
+            inbound_limit: 32 * 1024 * 1024,
+            outbound_limit: 16 * 1024 * 1024,
+            park_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// 上限付きのバイト数の予算
+#[derive(Debug)]
+pub struct ByteBudget {
+    limit: usize,
+    used: AtomicUsize,
+    rejected: AtomicU64,
+    released: Notify,
+}
+
+impl ByteBudget {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            used: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+            released: Notify::new(),
+        })
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// 使用中のバイト数
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+
+    pub fn available(&self) -> usize {
+        self.limit.saturating_sub(self.used())
+    }
+
+    /// 上限を超えたため拒否した数
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// 指定のバイト数を確保（空きがなければ待機せずに拒否）
+    pub fn try_reserve(self: &Arc<Self>, bytes: usize) -> Result<MemoryReservation, QuotaError> {
+        self.try_acquire(bytes).map_err(|available| {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            QuotaError::Exceeded {
+                requested: bytes,
+                available,
+                limit: self.limit,
+            }
+        })
+    }
+
+    /// 空きがあるまで待機し、最大`max_bytes`まで空きの範囲で確保
+    ///
+    /// 受信するペイロードのサイズが事前にわからない場合に、読み込みの上限として使います。
+    pub async fn reserve_up_to(
+        self: &Arc<Self>,
+        max_bytes: usize,
+        park_timeout: Duration,
+    ) -> Result<MemoryReservation, QuotaError> {
+        let wait = async {
+            loop {
+                // 解放の通知を取りこぼさないよう、空きの確認より先に登録する
+                let released = self.released.notified();
+                let available = self.available().min(max_bytes);
+                if available > 0 {
+                    if let Ok(reservation) = self.try_acquire(available) {
+                        return reservation;
+                    }
+                    continue;
+                }
+                released.await;
+            }
+        };
+
+        tokio::time::timeout(park_timeout, wait).await.map_err(|_| {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            QuotaError::Timeout(park_timeout)
+        })
+    }
+
+    fn try_acquire(self: &Arc<Self>, bytes: usize) -> Result<MemoryReservation, usize> {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes).filter(|total| *total <= self.limit)
+            })
+            .map(|_| MemoryReservation {
+                budget: Arc::clone(self),
+                bytes,
+            })
+            .map_err(|used| self.limit.saturating_sub(used))
+    }
+
+    fn release(&self, bytes: usize) {
+        if bytes > 0 {
+            self.used.fetch_sub(bytes, Ordering::AcqRel);
+            self.released.notify_waiters();
+        }
+    }
+}
+
+/// 確保したバイト数（破棄時に解放）
+#[derive(Debug)]
+pub struct MemoryReservation {
+    budget: Arc<ByteBudget>,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// 実際に使用したバイト数まで縮小し、余りを解放
+    pub fn shrink_to(&mut self, bytes: usize) {
+        if bytes < self.bytes {
+            self.budget.release(self.bytes - bytes);
+            self.bytes = bytes;
+        }
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// 接続のメモリ使用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub inbound_bytes: usize,
+    pub outbound_bytes: usize,
+    /// 上限を超えたため拒否・破棄した数
+    pub rejected: u64,
+}
+
+impl std::ops::Add for MemoryUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            inbound_bytes: self.inbound_bytes + other.inbound_bytes,
+            outbound_bytes: self.outbound_bytes + other.outbound_bytes,
+            rejected: self.rejected + other.rejected,
+        }
+    }
+}
+
+/// 1接続分の受信・送信の予算
+#[derive(Debug)]
+pub struct ConnectionMemory {
+    pub inbound: Arc<ByteBudget>,
+    pub outbound: Arc<ByteBudget>,
+    park_timeout: Duration,
+}
+
+impl ConnectionMemory {
+    pub fn new(config: &MemoryQuotaConfig) -> Self {
+        Self {
+            inbound: ByteBudget::new(config.inbound_limit),
+            outbound: ByteBudget::new(config.outbound_limit),
+            park_timeout: config.park_timeout,
+        }
+    }
+
+    /// 受信の空きを待って最大`max_bytes`まで確保
+    pub async fn reserve_inbound(&self, max_bytes: usize) -> Result<MemoryReservation, QuotaError> {
+        self.inbound
+            .reserve_up_to(max_bytes, self.park_timeout)
+            .await
+    }
+
+    pub fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            inbound_bytes: self.inbound.used(),
+            outbound_bytes: self.outbound.used(),
+            rejected: self.inbound.rejected() + self.outbound.rejected(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_and_release() {
+        let budget = ByteBudget::new(100);
+        let first = budget.try_reserve(60).unwrap();
+        assert_eq!(budget.available(), 40);
+
+        let error = budget.try_reserve(50).unwrap_err();
+        assert_eq!(
+            error,
+            QuotaError::Exceeded {
+                requested: 50,
+                available: 40,
+                limit: 100
+            }
+        );
+        assert_eq!(budget.rejected(), 1);
+
+        drop(first);
+        assert_eq!(budget.used(), 0);
+        assert!(budget.try_reserve(100).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reserve_up_to_parks_until_released() {
+        let budget = ByteBudget::new(100);
+        let mut held = budget.try_reserve(100).unwrap();
+
+        // 空きがない間は待機し、解放されると確保できる
+        let waiter = {
+            let budget = Arc::clone(&budget);
+            tokio::spawn(async move {
+                budget
+                    .reserve_up_to(1000, Duration::from_secs(5))
+                    .await
+                    .map(|r| r.bytes())
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+        held.shrink_to(30);
+        assert_eq!(waiter.await.unwrap().unwrap(), 70);
+
+        // 空かないまま期限を過ぎたら拒否する
+        let _rest = budget.try_reserve(budget.available()).unwrap();
+        let error = budget
+            .reserve_up_to(10, Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert_eq!(error, QuotaError::Timeout(Duration::from_millis(10)));
+    }
+}
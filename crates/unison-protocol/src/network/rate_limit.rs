@@ -0,0 +1,309 @@
+//! 接続・メソッドごとのレート制限
+//!
+//! 1つの接続が送りすぎたリクエストで他の接続の処理が滞らないよう、接続ごとに
+//! トークンバケットでリクエストを制限します。
+//!
+//! - 接続ごとの上限（全メソッドの合計）と、メソッドごとの上限（接続ごとに別のバケット）を指定できます
+//! - 上限を超えたリクエストはハンドラーを実行せずに`RESOURCE_EXHAUSTED`で拒否し、
+//!   再試行できるまでの時間をエラーの`details.retry_after_ms`と
+//!   メタデータ[`RETRY_AFTER_METADATA_KEY`]に設定します
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use super::broadcast::ConnectionId;
+
+/// 再試行できるまでの時間（ミリ秒）を格納するメタデータのキー
+pub const RETRY_AFTER_METADATA_KEY: &str = "retry-after-ms";
+
+/// トークンバケットの上限
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// 1秒あたりに補充されるリクエスト数
+    pub requests_per_second: f64,
+    /// 連続して受け付けられる最大リクエスト数
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            requests_per_second,
+            burst,
+        }
+    }
+
+    /// 1秒あたり`requests`件（同じ数まで連続して受け付ける）
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests as f64, requests)
+    }
+}
+
+/// トークンの残量と最終補充時刻
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// 満杯のバケット
+    pub(crate) fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            last: now,
+        }
+    }
+
+    /// 経過時間分のトークンを補充
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(limit.burst as f64);
+        self.last = now;
+    }
+
+    /// 補充した上で、トークンがなければ次のトークンが貯まるまでの時間を返す
+    fn check(&mut self, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            return Ok(());
+        }
+        if limit.requests_per_second > 0.0 {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / limit.requests_per_second,
+            ))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    /// トークンがあれば1つ消費し、なければ次のトークンが貯まるまでの時間を返す
+    pub(crate) fn try_acquire(&mut self, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        self.check(limit, now)?;
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// 上限を超えた範囲
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitScope {
+    /// 接続ごとの上限（全メソッドの合計）
+    Connection,
+    /// メソッドごとの上限
+    Method(String),
+}
+
+impl fmt::Display for RateLimitScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connection => f.write_str("connection"),
+            Self::Method(method) => write!(f, "method '{}'", method),
+        }
+    }
+}
+
+/// レート制限の超過
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Rate limit exceeded for {scope}, retry after {retry_after:?}")]
+pub struct RateLimitExceeded {
+    pub scope: RateLimitScope,
+    /// 再試行できるまでの時間
+    pub retry_after: Duration,
+}
+
+impl RateLimitExceeded {
+    /// 再試行できるまでの時間（ミリ秒、切り上げ）
+    pub fn retry_after_millis(&self) -> u64 {
+        let millis = self.retry_after.as_micros().div_ceil(1000);
+        millis.min(u64::MAX as u128) as u64
+    }
+}
+
+/// レート制限の設定
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitConfig {
+    /// 接続ごとの上限（全メソッドの合計）
+    pub connection: Option<RateLimit>,
+    /// メソッドごとの上限（接続ごとに適用）
+    pub methods: HashMap<String, RateLimit>,
+}
+
+impl RateLimitConfig {
+    pub fn with_connection_limit(mut self, limit: RateLimit) -> Self {
+        self.connection = Some(limit);
+        self
+    }
+
+    pub fn with_method_limit(mut self, method: impl Into<String>, limit: RateLimit) -> Self {
+        self.methods.insert(method.into(), limit);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connection.is_none() && self.methods.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct ConnectionBuckets {
+    connection: Option<TokenBucket>,
+    methods: HashMap<String, TokenBucket>,
+}
+
+#[derive(Default)]
+struct RateLimiterInner {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<ConnectionId, ConnectionBuckets>>,
+    rejected: AtomicU64,
+}
+
+/// 接続・メソッドごとのレート制限
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    inner: Arc<RateLimiterInner>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            inner: Arc::new(RateLimiterInner {
+                config,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.inner.config
+    }
+
+    /// 接続からの`method`の呼び出しを受け付けるか確認し、受け付ける場合はトークンを消費
+    ///
+    /// 接続ごとの上限とメソッドの上限の両方に空きがある場合だけ、両方のトークンを消費します。
+    pub fn check(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+    ) -> Result<(), RateLimitExceeded> {
+        let config = &self.inner.config;
+        let method_limit = config.methods.get(method).copied();
+        if config.connection.is_none() && method_limit.is_none() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.inner.buckets.lock().unwrap();
+        let buckets = buckets.entry(connection_id).or_default();
+        let mut connection = config.connection.map(|limit| {
+            let bucket = buckets
+                .connection
+                .get_or_insert_with(|| TokenBucket::full(&limit, now));
+            (limit, bucket)
+        });
+        let mut method_bucket = method_limit.map(|limit| {
+            let bucket = buckets
+                .methods
+                .entry(method.to_string())
+                .or_insert_with(|| TokenBucket::full(&limit, now));
+            (limit, bucket)
+        });
+
+        let rejected = |scope, retry_after| {
+            self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+            Err(RateLimitExceeded { scope, retry_after })
+        };
+        // 片方だけ消費しないよう、両方を確認してから消費する
+        if let Some((limit, bucket)) = &mut connection {
+            if let Err(retry_after) = bucket.check(limit, now) {
+                return rejected(RateLimitScope::Connection, retry_after);
+            }
+        }
+        if let Some((limit, bucket)) = &mut method_bucket {
+            if let Err(retry_after) = bucket.check(limit, now) {
+                return rejected(RateLimitScope::Method(method.to_string()), retry_after);
+            }
+        }
+        for (limit, bucket) in connection.into_iter().chain(method_bucket) {
+            let _ = bucket.try_acquire(&limit, now);
+        }
+        Ok(())
+    }
+
+    /// レート制限で拒否したリクエストの数
+    pub fn rejected(&self) -> u64 {
+        self.inner.rejected.load(Ordering::Relaxed)
+    }
+
+    /// 接続のバケットを破棄
+    pub fn remove_connection(&self, connection_id: ConnectionId) {
+        self.inner.buckets.lock().unwrap().remove(&connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_have_separate_buckets() {
+        let limiter = RateLimiter::new(
+            RateLimitConfig::default().with_connection_limit(RateLimit::new(1.0, 2)),
+        );
+        assert!(limiter.check(1, "echo").is_ok());
+        assert!(limiter.check(1, "other").is_ok());
+        let error = limiter.check(1, "echo").unwrap_err();
+        assert_eq!(error.scope, RateLimitScope::Connection);
+        assert!(error.retry_after > Duration::ZERO && error.retry_after <= Duration::from_secs(1));
+        assert!(error.retry_after_millis() >= 1);
+
+        // 他の接続は影響を受けない
+        assert!(limiter.check(2, "echo").is_ok());
+        assert_eq!(limiter.rejected(), 1);
+
+        limiter.remove_connection(1);
+        assert!(limiter.check(1, "echo").is_ok());
+    }
+
+    #[test]
+    fn test_method_limit_does_not_consume_connection_tokens() {
+        let limiter = RateLimiter::new(
+            RateLimitConfig::default()
+                .with_connection_limit(RateLimit::new(0.0, 3))
+                .with_method_limit("search", RateLimit::new(0.0, 1)),
+        );
+        assert!(limiter.check(1, "search").is_ok());
+        let error = limiter.check(1, "search").unwrap_err();
+        assert_eq!(error.scope, RateLimitScope::Method("search".into()));
+        assert_eq!(error.retry_after, Duration::MAX);
+
+        // 拒否したリクエストは接続のトークンを消費しない
+        assert!(limiter.check(1, "echo").is_ok());
+        assert!(limiter.check(1, "echo").is_ok());
+        assert_eq!(
+            limiter.check(1, "echo").unwrap_err().scope,
+            RateLimitScope::Connection
+        );
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let limit = RateLimit::per_second(10);
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(&limit, start);
+        for _ in 0..10 {
+            assert!(bucket.try_acquire(&limit, start).is_ok());
+        }
+        let retry_after = bucket.try_acquire(&limit, start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(100));
+        assert!(
+            bucket
+                .try_acquire(&limit, start + Duration::from_millis(100))
+                .is_ok()
+        );
+    }
+}
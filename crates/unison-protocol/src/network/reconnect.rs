@@ -0,0 +1,268 @@
+//! 接続断からの自動再接続
+//!
+//! [`ReconnectPolicy`]を指定したクライアントは、接続が失われると指数バックオフで
+//! 最後の接続先へ再接続します。再接続後は再開可能なストリームをレジュームトークンで
+//! 再開し、オフラインキューのメッセージを送信します。
+//!
+//! 再接続の経過は接続状態のイベント（`Ready → Idle → Reconnecting → ...`）として通知されます。
+
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use super::quic::MessageStream;
+use super::resume::ResumeToken;
+
+/// 再接続のエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReconnectError {
+    #[error("Gave up reconnecting after {attempts} attempts (last error: {last_error})")]
+    Exhausted { attempts: u32, last_error: String },
+}
+
+/// 再接続のバックオフ設定
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// 最初の再接続までの待機時間
+    pub initial_backoff: Duration,
+    /// 待機時間の上限
+    pub max_backoff: Duration,
+    /// 試行ごとに待機時間へ掛ける倍率
+    pub multiplier: f64,
+    /// 待機時間を短縮する割合の最大値（0.0〜1.0、再接続の集中を避ける）
+    pub jitter: f64,
+    /// 最大試行回数（`None`の場合は無制限）
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// `attempt`回目（0始まり）の試行を行ってよいか
+    pub fn allows(&self, attempt: u32) -> bool {
+        self.max_attempts.is_none_or(|max| attempt < max)
+    }
+
+    /// `attempt`回目の試行前のジッターを含まない待機時間
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        exponential_backoff(
+            self.initial_backoff,
+            self.max_backoff,
+            self.multiplier,
+            attempt,
+        )
+    }
+
+    /// `attempt`回目の試行前の待機時間（ジッターで短縮）
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff(attempt)
+            .mul_f64(1.0 - self.jitter * random_unit())
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+/// `initial`に`multiplier`を`attempt`回掛けた待機時間（`max`で頭打ち）
+pub(crate) fn exponential_backoff(
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    attempt: u32,
+) -> Duration {
+    let factor = multiplier
+        .max(1.0)
+        .powi(attempt.min(i32::MAX as u32) as i32);
+    let backoff = initial.as_secs_f64() * factor;
+    Duration::from_secs_f64(backoff.min(max.as_secs_f64()))
+}
+
+/// 0.0以上1.0未満の乱数（UUID v4の乱数部分を使用）
+pub(crate) fn random_unit() -> f64 {
+    let bits = uuid::Uuid::new_v4().as_u128() >> 80;
+    bits as f64 / (1u64 << 48) as f64
+}
+
+/// 受信中の再開可能なストリーム
+///
+/// ストリームの受信が終わるか破棄されると登録が外れます。
+pub(crate) struct PendingStream {
+    pub(crate) token: Arc<Mutex<Option<ResumeToken>>>,
+    /// 再接続後に再開したストリームの受け渡し（再開を諦めると閉じる）
+    resumed_tx: Mutex<Option<mpsc::UnboundedSender<MessageStream>>>,
+    resumed_rx: tokio::sync::Mutex<mpsc::UnboundedReceiver<MessageStream>>,
+}
+
+impl PendingStream {
+    /// 再開したストリームを受信側へ渡す
+    pub(crate) fn resume(&self, messages: MessageStream) {
+        if let Some(tx) = self.resumed_tx.lock().unwrap().as_ref() {
+            let _ = tx.send(messages);
+        }
+    }
+
+    /// 再開を諦め、待機中の受信側を終了させる
+    pub(crate) fn abandon(&self) {
+        self.resumed_tx.lock().unwrap().take();
+    }
+
+    /// 再開したストリームを待つ（再開を諦めた場合は`None`）
+    pub(crate) async fn next_resumed(&self) -> Option<MessageStream> {
+        self.resumed_rx.lock().await.recv().await
+    }
+}
+
+/// 再接続時に再開するストリームの一覧
+#[derive(Clone, Default)]
+pub(crate) struct PendingStreams {
+    streams: Arc<Mutex<Vec<Weak<PendingStream>>>>,
+}
+
+impl PendingStreams {
+    /// ストリームを登録（返した値を受信側が保持している間だけ有効）
+    pub(crate) fn track(&self, token: Arc<Mutex<Option<ResumeToken>>>) -> Arc<PendingStream> {
+        let (resumed_tx, resumed_rx) = mpsc::unbounded_channel();
+        let stream = Arc::new(PendingStream {
+            token,
+            resumed_tx: Mutex::new(Some(resumed_tx)),
+            resumed_rx: tokio::sync::Mutex::new(resumed_rx),
+        });
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|stream| stream.strong_count() > 0);
+        streams.push(Arc::downgrade(&stream));
+        stream
+    }
+
+    /// 受信中のストリームとそのレジュームトークン（トークン未受信のものは除く）
+    pub(crate) fn resumable(&self) -> Vec<(ResumeToken, Arc<PendingStream>)> {
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|stream| stream.strong_count() > 0);
+        streams
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter_map(|stream| {
+                let token = stream.token.lock().unwrap().clone()?;
+                Some((token, stream))
+            })
+            .collect()
+    }
+
+    /// 全てのストリームの再開を諦める
+    pub(crate) fn abandon_all(&self) {
+        let streams = self.streams.lock().unwrap();
+        for stream in streams.iter().filter_map(Weak::upgrade) {
+            stream.abandon();
+        }
+    }
+}
+
+/// 破棄時に全てのストリームの再開を諦める
+///
+/// 再接続の監視タスクが終了・中断したときに、再開を待つ受信側を終わらせるために保持します。
+pub(crate) struct AbandonOnDrop(pub(crate) PendingStreams);
+
+impl Drop for AbandonOnDrop {
+    fn drop(&mut self) {
+        self.0.abandon_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = ReconnectPolicy::new()
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_secs(1))
+            .with_jitter(0.5);
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+
+        for attempt in 0..5 {
+            let delay = policy.delay(attempt);
+            assert!(delay <= policy.backoff(attempt));
+            assert!(delay >= policy.backoff(attempt) / 2);
+        }
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        assert!(ReconnectPolicy::new().allows(1_000));
+        let policy = ReconnectPolicy::new().with_max_attempts(3);
+        assert!(policy.allows(2));
+        assert!(!policy.allows(3));
+    }
+
+    #[test]
+    fn test_pending_streams_drop_finished() {
+        let pending = PendingStreams::default();
+        let token = ResumeToken {
+            stream_id: "s1".into(),
+            method: "watch".into(),
+            sequence: 3,
+        };
+        let active = pending.track(Arc::new(Mutex::new(Some(token.clone()))));
+        let finished = pending.track(Arc::new(Mutex::new(None)));
+        let resumable = pending.resumable();
+        assert_eq!(resumable.len(), 1);
+        assert_eq!(resumable[0].0, token);
+        drop(resumable);
+
+        drop(active);
+        drop(finished);
+        assert!(pending.resumable().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_stream_stops_waiting() {
+        let pending = PendingStreams::default();
+        let stream = pending.track(Arc::new(Mutex::new(None)));
+
+        stream.resume(Box::pin(futures_util::stream::empty()));
+        assert!(stream.next_resumed().await.is_some());
+
+        pending.abandon_all();
+        assert!(stream.next_resumed().await.is_none());
+    }
+}
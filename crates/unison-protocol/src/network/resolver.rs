@@ -0,0 +1,282 @@
+//! ホスト名の解決とキャッシュ
+//!
+//! 接続先のホスト名は[`Resolver`]で解決します。標準ではOSのリゾルバー
+//! （[`SystemResolver`]）を使い、`hickory-dns`フィーチャーを有効にすると
+//! レコードのTTLを取得できる[`HickoryResolver`]も使えます。
+//!
+//! 解決結果は[`CachingResolver`]がTTLに従ってキャッシュし、
+//! 接続に失敗した場合はキャッシュを破棄して次回の接続で再解決します。
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// 名前解決のエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    #[error("Failed to resolve {host}: {message}")]
+    Lookup { host: String, message: String },
+    #[error("No addresses found for {0}")]
+    NoAddresses(String),
+}
+
+/// 名前解決の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub addrs: Vec<SocketAddr>,
+    /// レコードの有効期間（リゾルバーが提供しない場合は`None`）
+    pub ttl: Option<Duration>,
+}
+
+pub type ResolveFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Resolution, ResolveError>> + Send + 'a>>;
+
+/// 非同期のホスト名リゾルバー
+pub trait Resolver: Send + Sync {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a>;
+}
+
+/// OSのリゾルバー（`getaddrinfo`）を使う実装
+///
+/// TTLを取得できないため、キャッシュには[`DnsCacheConfig::default_ttl`]が使われます。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| ResolveError::Lookup {
+                    host: host.to_string(),
+                    message: e.to_string(),
+                })?
+                .collect();
+            Ok(Resolution { addrs, ttl: None })
+        })
+    }
+}
+
+/// hickory-dnsを使う実装（レコードのTTLに従ってキャッシュされます）
+#[cfg(feature = "hickory-dns")]
+pub struct HickoryResolver {
+    resolver: hickory_resolver::TokioAsyncResolver,
+}
+
+#[cfg(feature = "hickory-dns")]
+impl HickoryResolver {
+    /// システムの設定（`/etc/resolv.conf`等）から作成
+    pub fn from_system_conf() -> Result<Self, ResolveError> {
+        let resolver =
+            hickory_resolver::TokioAsyncResolver::tokio_from_system_conf().map_err(|e| {
+                ResolveError::Lookup {
+                    host: String::new(),
+                    message: e.to_string(),
+                }
+            })?;
+        Ok(Self { resolver })
+    }
+
+    pub fn new(resolver: hickory_resolver::TokioAsyncResolver) -> Self {
+        Self { resolver }
+    }
+}
+
+#[cfg(feature = "hickory-dns")]
+impl Resolver for HickoryResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let lookup = self
+                .resolver
+                .lookup_ip(host)
+                .await
+                .map_err(|e| ResolveError::Lookup {
+                    host: host.to_string(),
+                    message: e.to_string(),
+                })?;
+            let ttl = lookup
+                .valid_until()
+                .saturating_duration_since(Instant::now());
+            let addrs = lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect();
+            Ok(Resolution {
+                addrs,
+                ttl: Some(ttl),
+            })
+        })
+    }
+}
+
+/// 名前解決キャッシュの設定
+#[derive(Debug, Clone)]
+pub struct DnsCacheConfig {
+    /// リゾルバーがTTLを提供しない場合の有効期間
+    pub default_ttl: Duration,
+    /// 有効期間の下限
+    pub min_ttl: Duration,
+    /// 有効期間の上限
+    pub max_ttl: Duration,
+}
+
+impl Default for DnsCacheConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: Duration::from_secs(60),
+            min_ttl: Duration::from_secs(1),
+            max_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+struct CacheEntry {
+    addrs: Vec<SocketAddr>,
+    expires_at: Instant,
+}
+
+/// TTLに従って解決結果をキャッシュするリゾルバー
+#[derive(Clone)]
+pub struct CachingResolver {
+    inner: Arc<dyn Resolver>,
+    config: DnsCacheConfig,
+    cache: Arc<Mutex<HashMap<(String, u16), CacheEntry>>>,
+}
+
+impl CachingResolver {
+    pub fn new(inner: Arc<dyn Resolver>, config: DnsCacheConfig) -> Self {
+        Self {
+            inner,
+            config,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn config(&self) -> &DnsCacheConfig {
+        &self.config
+    }
+
+    /// キャッシュ元のリゾルバー
+    pub fn inner(&self) -> Arc<dyn Resolver> {
+        Arc::clone(&self.inner)
+    }
+
+    /// ホスト名を解決（有効なキャッシュがあればそれを返す）
+    pub async fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, ResolveError> {
+        let key = (host.to_ascii_lowercase(), port);
+        if let Some(entry) = self.cache.lock().unwrap().get(&key) {
+            if entry.expires_at > Instant::now() {
+                return Ok(entry.addrs.clone());
+            }
+        }
+
+        let resolution = self.inner.resolve(host, port).await?;
+        if resolution.addrs.is_empty() {
+            return Err(ResolveError::NoAddresses(host.to_string()));
+        }
+
+        let ttl = resolution
+            .ttl
+            .unwrap_or(self.config.default_ttl)
+            .clamp(self.config.min_ttl, self.config.max_ttl);
+        self.cache.lock().unwrap().insert(
+            key,
+            CacheEntry {
+                addrs: resolution.addrs.clone(),
+                expires_at: Instant::now() + ttl,
+            },
+        );
+        Ok(resolution.addrs)
+    }
+
+    /// キャッシュを破棄して次回の解決で再問い合わせさせる
+    pub fn invalidate(&self, host: &str, port: u16) {
+        self.cache
+            .lock()
+            .unwrap()
+            .remove(&(host.to_ascii_lowercase(), port));
+    }
+
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl Default for CachingResolver {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemResolver), DnsCacheConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 問い合わせ回数を数えるリゾルバー
+    struct CountingResolver {
+        lookups: AtomicUsize,
+        ttl: Option<Duration>,
+    }
+
+    impl Resolver for CountingResolver {
+        fn resolve<'a>(&'a self, _host: &'a str, port: u16) -> ResolveFuture<'a> {
+            let n = self.lookups.fetch_add(1, Ordering::SeqCst) as u16;
+            let ttl = self.ttl;
+            Box::pin(async move {
+                Ok(Resolution {
+                    addrs: vec![SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, n + 1], port))],
+                    ttl,
+                })
+            })
+        }
+    }
+
+    fn counting(ttl: Option<Duration>) -> Arc<CountingResolver> {
+        Arc::new(CountingResolver {
+            lookups: AtomicUsize::new(0),
+            ttl,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_cache_hits_and_invalidate() {
+        let inner = counting(Some(Duration::from_secs(30)));
+        let resolver = CachingResolver::new(inner.clone(), DnsCacheConfig::default());
+
+        let first = resolver.resolve("Example.com", 8080).await.unwrap();
+        let second = resolver.resolve("example.com", 8080).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+
+        // 接続失敗時の再解決
+        resolver.invalidate("example.com", 8080);
+        let third = resolver.resolve("example.com", 8080).await.unwrap();
+        assert_ne!(first, third);
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ttl_is_respected_and_clamped() {
+        let inner = counting(Some(Duration::ZERO));
+        let resolver = CachingResolver::new(
+            inner.clone(),
+            DnsCacheConfig {
+                min_ttl: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+        resolver.resolve("example.com", 8080).await.unwrap();
+        resolver.resolve("example.com", 8080).await.unwrap();
+        // TTLが0のため毎回問い合わせる
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 2);
+
+        let inner = counting(Some(Duration::ZERO));
+        let resolver = CachingResolver::new(inner.clone(), DnsCacheConfig::default());
+        resolver.resolve("example.com", 8080).await.unwrap();
+        resolver.resolve("example.com", 8080).await.unwrap();
+        // 下限（1秒）に切り上げられてキャッシュされる
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,329 @@
+//! サーバーストリームの再開（レジューム）サポート
+//!
+//! 再開可能なストリームでは、サーバーが一定件数ごとにレジュームトークン
+//! （ストリームIDと送信済みシーケンス番号）を発行します。
+//! 再接続したクライアントは最後に受け取ったトークンでストリームを開き直すことで、
+//! 最初からではなく中断した位置から受信を再開できます。
+
+use anyhow::Result;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// レジュームトークン
+///
+/// `sequence`はクライアントへ送信済みのアイテム数（次に再開するオフセット）です。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeToken {
+    pub stream_id: String,
+    pub method: String,
+    pub sequence: u64,
+}
+
+/// 再開可能ストリームの設定
+#[derive(Debug, Clone)]
+pub struct ResumeConfig {
+    /// トークンを発行する間隔（アイテム数）
+    pub token_interval: u64,
+    /// 最後の送信から再開を受け付ける期間
+    pub retention: Duration,
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        Self {
+            token_interval: 16,
+            retention: Duration::from_secs(300),
+        }
+    }
+}
+
+/// サーバーストリームから送出されるイベント
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// ストリームデータ
+    Data(Value),
+    /// レジュームトークンの発行
+    ResumeToken(ResumeToken),
+}
+
+/// 再開待ちストリームのエントリ
+struct ResumableEntry {
+    method: String,
+    payload: Value,
+    sequence: u64,
+    last_activity: Instant,
+}
+
+/// 再開可能なストリームの状態を保持するレジストリ
+///
+/// 再開時には元のリクエストペイロードをサーバー側から復元するため、
+/// クライアントがトークンを改ざんしても別のリクエストとして再開されることはありません。
+#[derive(Clone)]
+pub struct ResumeRegistry {
+    config: ResumeConfig,
+    entries: Arc<Mutex<HashMap<String, ResumableEntry>>>,
+}
+
+impl ResumeRegistry {
+    pub fn new(config: ResumeConfig) -> Self {
+        Self {
+            config,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn config(&self) -> &ResumeConfig {
+        &self.config
+    }
+
+    /// 新しいストリームを登録し、ストリームIDを返す
+    pub fn register(&self, method: &str, payload: Value) -> String {
+        let stream_id = uuid::Uuid::new_v4().to_string();
+        let mut entries = self.entries.lock().unwrap();
+        self.prune(&mut entries);
+        entries.insert(
+            stream_id.clone(),
+            ResumableEntry {
+                method: method.to_string(),
+                payload,
+                sequence: 0,
+                last_activity: Instant::now(),
+            },
+        );
+        stream_id
+    }
+
+    /// トークンを検証し、元のリクエストペイロードを返す
+    pub fn resume(&self, token: &ResumeToken) -> Result<Value> {
+        let mut entries = self.entries.lock().unwrap();
+        self.prune(&mut entries);
+
+        let entry = entries
+            .get_mut(&token.stream_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown or expired resume token"))?;
+        if entry.method != token.method {
+            return Err(anyhow::anyhow!(
+                "Resume token method mismatch: expected {}, got {}",
+                entry.method,
+                token.method
+            ));
+        }
+        if token.sequence > entry.sequence {
+            return Err(anyhow::anyhow!(
+                "Resume token sequence {} is ahead of the stream ({})",
+                token.sequence,
+                entry.sequence
+            ));
+        }
+
+        entry.sequence = token.sequence;
+        entry.last_activity = Instant::now();
+        Ok(entry.payload.clone())
+    }
+
+    /// 送信済みシーケンスを更新
+    fn advance(&self, stream_id: &str, sequence: u64) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(stream_id) {
+            entry.sequence = sequence;
+            entry.last_activity = Instant::now();
+        }
+    }
+
+    /// 完了したストリームを削除
+    fn complete(&self, stream_id: &str) {
+        self.entries.lock().unwrap().remove(stream_id);
+    }
+
+    /// 再開待ちストリーム数
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn prune(&self, entries: &mut HashMap<String, ResumableEntry>) {
+        let retention = self.config.retention;
+        entries.retain(|_, entry| entry.last_activity.elapsed() < retention);
+    }
+
+    /// ハンドラーのストリームにシーケンス管理とトークン発行を付与
+    ///
+    /// `offset`は再開位置で、ハンドラーのストリームはその位置から生成されている必要があります。
+    pub fn wrap(
+        &self,
+        stream_id: String,
+        method: String,
+        offset: u64,
+        inner: Pin<Box<dyn Stream<Item = Result<Value>> + Send>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>> {
+        let registry = self.clone();
+        let interval = self.config.token_interval.max(1);
+
+        let stream = async_stream::stream! {
+            let mut inner = inner;
+            let mut sequence = offset;
+
+            // 開始時点のトークンを発行（再開直後の切断にも備える）
+            yield Ok(StreamEvent::ResumeToken(ResumeToken {
+                stream_id: stream_id.clone(),
+                method: method.clone(),
+                sequence,
+            }));
+
+            while let Some(item) = inner.next().await {
+                match item {
+                    Ok(value) => {
+                        sequence += 1;
+                        registry.advance(&stream_id, sequence);
+                        yield Ok(StreamEvent::Data(value));
+
+                        if sequence % interval == 0 {
+                            yield Ok(StreamEvent::ResumeToken(ResumeToken {
+                                stream_id: stream_id.clone(),
+                                method: method.clone(),
+                                sequence,
+                            }));
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+
+            registry.complete(&stream_id);
+        };
+
+        Box::pin(stream)
+    }
+}
+
+impl Default for ResumeRegistry {
+    fn default() -> Self {
+        Self::new(ResumeConfig::default())
+    }
+}
+
+/// クライアント側の再開可能ストリーム
+///
+/// 受信したレジュームトークンを保持し、切断後に
+/// [`ProtocolClient::resume_stream`](super::ProtocolClient::resume_stream)へ渡せます。
+pub struct ResumableStream<T> {
+    inner: Pin<Box<dyn Stream<Item = Result<T>> + Send>>,
+    token: Arc<Mutex<Option<ResumeToken>>>,
+}
+
+impl<T> ResumableStream<T> {
+    pub(crate) fn new(
+        inner: Pin<Box<dyn Stream<Item = Result<T>> + Send>>,
+        token: Arc<Mutex<Option<ResumeToken>>>,
+    ) -> Self {
+        Self { inner, token }
+    }
+
+    /// 受信済みの位置までのレジュームトークン
+    ///
+    /// `sequence`はトークンの発行間隔に関係なく、受信したアイテムごとに進みます。
+    pub fn resume_token(&self) -> Option<ResumeToken> {
+        self.token.lock().unwrap().clone()
+    }
+}
+
+impl<T> Stream for ResumableStream<T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    fn numbers(from: u64, to: u64) -> Pin<Box<dyn Stream<Item = Result<Value>> + Send>> {
+        Box::pin(stream::iter((from..to).map(|n| Ok(serde_json::json!(n)))))
+    }
+
+    #[tokio::test]
+    async fn test_tokens_issued_at_interval() {
+        let registry = ResumeRegistry::new(ResumeConfig {
+            token_interval: 2,
+            ..Default::default()
+        });
+        let id = registry.register("count", serde_json::json!({"to": 5}));
+        let events: Vec<_> = registry
+            .wrap(id, "count".to_string(), 0, numbers(0, 5))
+            .collect()
+            .await;
+
+        let tokens: Vec<u64> = events
+            .iter()
+            .filter_map(|e| match e {
+                Ok(StreamEvent::ResumeToken(t)) => Some(t.sequence),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tokens, vec![0, 2, 4]);
+        // 完了したストリームは再開できない
+        assert!(registry.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resume_restores_payload() {
+        let registry = ResumeRegistry::default();
+        let payload = serde_json::json!({"to": 10});
+        let id = registry.register("count", payload.clone());
+
+        // 3件送信した時点で切断
+        let mut stream = registry.wrap(id.clone(), "count".to_string(), 0, numbers(0, 10));
+        for _ in 0..4 {
+            stream.next().await;
+        }
+        drop(stream);
+
+        let token = ResumeToken {
+            stream_id: id.clone(),
+            method: "count".to_string(),
+            sequence: 3,
+        };
+        assert_eq!(registry.resume(&token).unwrap(), payload);
+
+        let ahead = ResumeToken {
+            sequence: 4,
+            ..token.clone()
+        };
+        assert!(registry.resume(&ahead).is_err());
+
+        let wrong_method = ResumeToken {
+            method: "other".to_string(),
+            ..token
+        };
+        assert!(registry.resume(&wrong_method).is_err());
+    }
+
+    #[test]
+    fn test_expired_tokens_rejected() {
+        let registry = ResumeRegistry::new(ResumeConfig {
+            retention: Duration::ZERO,
+            ..Default::default()
+        });
+        let id = registry.register("count", Value::Null);
+        let token = ResumeToken {
+            stream_id: id,
+            method: "count".to_string(),
+            sequence: 0,
+        };
+        assert!(registry.resume(&token).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+//! 失敗した呼び出しの自動再試行
+//!
+//! [`RetryPolicy`]を指定したクライアントは、失敗した単項呼び出しを指数バックオフで再送します。
+//! サーバーが処理せずに拒否したエラー（`UNAVAILABLE`・`RATE_LIMITED`）はどのメソッドでも、
+//! 処理されたか分からないエラー（接続断・タイムアウト）は冪等なメソッドに限り再試行します。
+//!
+//! メソッドが冪等かは、スキーマの`idempotent=#true`（生成したクライアントは
+//! [`call_idempotent`](super::ProtocolClientTrait::call_idempotent)で呼び出す）、
+//! [`CallOptions::idempotent`](super::CallOptions::idempotent)、
+//! [`RetryPolicy::with_idempotent_method`]のいずれかで指定します。
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+use super::reconnect::{exponential_backoff, random_unit};
+use super::{NetworkError, ProtocolError};
+use crate::parser::ParsedSchema;
+
+/// 再試行するエラーの分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryClass {
+    /// 接続断・QUICのエラー（リクエストが処理されたか分からない）
+    Connection,
+    /// 応答待ちの期限切れ・サーバーの`DEADLINE_EXCEEDED`
+    Timeout,
+    /// サーバーが`UNAVAILABLE`で拒否した
+    Unavailable,
+    /// サーバーが`RATE_LIMITED`で拒否した
+    RateLimited,
+}
+
+impl RetryClass {
+    pub const ALL: [RetryClass; 4] = [
+        Self::Connection,
+        Self::Timeout,
+        Self::Unavailable,
+        Self::RateLimited,
+    ];
+
+    /// サーバーが処理せずに拒否したか（冪等でないメソッドも再送できる）
+    pub fn is_rejection(self) -> bool {
+        matches!(self, Self::Unavailable | Self::RateLimited)
+    }
+
+    /// エラーの分類（再試行の対象にならないエラーは`None`）
+    pub fn of(error: &NetworkError) -> Option<Self> {
+        match error {
+            NetworkError::Connection(_) | NetworkError::Quic(_) | NetworkError::NotConnected => {
+                Some(Self::Connection)
+            }
+            NetworkError::Timeout => Some(Self::Timeout),
+            NetworkError::Remote(remote) => match remote.code.parse::<i32>() {
+                Ok(ProtocolError::UNAVAILABLE) => Some(Self::Unavailable),
+                Ok(ProtocolError::RATE_LIMITED) => Some(Self::RateLimited),
+                Ok(ProtocolError::DEADLINE_EXCEEDED) => Some(Self::Timeout),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// 再試行の設定
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 最初の呼び出しを含む最大試行回数
+    pub max_attempts: u32,
+    /// 最初の再試行までの待機時間
+    pub initial_backoff: Duration,
+    /// 待機時間の上限
+    pub max_backoff: Duration,
+    /// 再試行ごとに待機時間へ掛ける倍率
+    pub multiplier: f64,
+    /// 待機時間を短縮する割合の最大値（0.0〜1.0）
+    pub jitter: f64,
+    /// 再試行するエラーの分類
+    pub retry_on: HashSet<RetryClass>,
+    /// 冪等として扱うメソッド
+    pub idempotent_methods: HashSet<String>,
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// 再試行するエラーの分類を置き換える
+    pub fn with_retry_on(mut self, classes: impl IntoIterator<Item = RetryClass>) -> Self {
+        self.retry_on = classes.into_iter().collect();
+        self
+    }
+
+    pub fn with_idempotent_method(mut self, method: impl Into<String>) -> Self {
+        self.idempotent_methods.insert(method.into());
+        self
+    }
+
+    /// スキーマで`idempotent=#true`と指定されたメソッドを冪等として扱う
+    pub fn with_schema(mut self, schema: &ParsedSchema) -> Self {
+        let methods = schema
+            .protocol
+            .iter()
+            .flat_map(|protocol| &protocol.services)
+            .flat_map(|service| &service.methods)
+            .filter(|method| method.idempotent)
+            .map(|method| method.name.clone());
+        self.idempotent_methods.extend(methods);
+        self
+    }
+
+    pub fn is_idempotent(&self, method: &str) -> bool {
+        self.idempotent_methods.contains(method)
+    }
+
+    /// `class`のエラーで失敗した`method`の呼び出しを再送してよいか
+    pub fn should_retry(&self, method: &str, idempotent: bool, class: RetryClass) -> bool {
+        self.retry_on.contains(&class)
+            && (class.is_rejection() || idempotent || self.is_idempotent(method))
+    }
+
+    /// `attempt`回目（1始まり）の再試行前のジッターを含まない待機時間
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        exponential_backoff(
+            self.initial_backoff,
+            self.max_backoff,
+            self.multiplier,
+            attempt.saturating_sub(1),
+        )
+    }
+
+    /// `attempt`回目の再試行前の待機時間（ジッターで短縮）
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff(attempt)
+            .mul_f64(1.0 - self.jitter * random_unit())
+    }
+
+    /// `send`を呼び出し、再試行できるエラーで失敗した場合は待機してから再送する
+    ///
+    /// 期限までに待機が終わらない場合は再送せずに最後のエラーを返します。
+    pub(crate) async fn run<T, E, F, Fut>(
+        &self,
+        method: &str,
+        idempotent: bool,
+        deadline: Option<SystemTime>,
+        send: F,
+    ) -> Result<T, E>
+    where
+        E: RetryableError,
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let error = match send().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            let Some(class) = error.retry_class() else {
+                return Err(error);
+            };
+            if attempt >= self.max_attempts || !self.should_retry(method, idempotent, class) {
+                return Err(error);
+            }
+            // サーバーが指定した待機時間より早くは再送しない
+            let delay = self
+                .delay(attempt)
+                .max(error.retry_after().unwrap_or_default());
+            if deadline.is_some_and(|deadline| {
+                super::deadline::remaining(deadline).is_none_or(|remaining| remaining <= delay)
+            }) {
+                return Err(error);
+            }
+            debug!(
+                "Retrying {} in {:?} after {:?} (attempt {} of {})",
+                method,
+                delay,
+                class,
+                attempt + 1,
+                self.max_attempts
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_on: RetryClass::ALL.into_iter().collect(),
+            idempotent_methods: HashSet::new(),
+        }
+    }
+}
+
+/// 再試行の判断に使うエラーの情報
+pub(crate) trait RetryableError {
+    fn retry_class(&self) -> Option<RetryClass>;
+
+    /// サーバーが指定した再試行までの待機時間
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl RetryableError for NetworkError {
+    fn retry_class(&self) -> Option<RetryClass> {
+        RetryClass::of(self)
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            NetworkError::Remote(remote) => remote
+                .details
+                .as_ref()
+                .and_then(|details| details["retry_after_ms"].as_u64())
+                .map(Duration::from_millis),
+            _ => None,
+        }
+    }
+}
+
+impl RetryableError for anyhow::Error {
+    fn retry_class(&self) -> Option<RetryClass> {
+        match self.downcast_ref::<NetworkError>() {
+            Some(error) => error.retry_class(),
+            // QUICのストリームの読み書きのエラーなど
+            None => Some(RetryClass::Connection),
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        self.downcast_ref::<NetworkError>()
+            .and_then(RetryableError::retry_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::UnisonError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn remote(code: i32) -> NetworkError {
+        NetworkError::Remote(UnisonError::new(code.to_string(), "rejected"))
+    }
+
+    #[test]
+    fn test_only_rejections_retry_non_idempotent_methods() {
+        let policy = RetryPolicy::new().with_idempotent_method("get_user");
+        assert_eq!(
+            RetryClass::of(&remote(ProtocolError::UNAVAILABLE)),
+            Some(RetryClass::Unavailable)
+        );
+        assert_eq!(RetryClass::of(&remote(ProtocolError::NOT_FOUND)), None);
+
+        assert!(policy.should_retry("create_user", false, RetryClass::Unavailable));
+        assert!(policy.should_retry("create_user", false, RetryClass::RateLimited));
+        assert!(!policy.should_retry("create_user", false, RetryClass::Connection));
+        assert!(policy.should_retry("create_user", true, RetryClass::Connection));
+        assert!(policy.should_retry("get_user", false, RetryClass::Timeout));
+
+        let policy = policy.with_retry_on([RetryClass::Connection]);
+        assert!(!policy.should_retry("create_user", false, RetryClass::Unavailable));
+        assert!(policy.should_retry("get_user", false, RetryClass::Connection));
+    }
+
+    #[test]
+    fn test_backoff_starts_at_initial_and_caps() {
+        let policy = RetryPolicy::new()
+            .with_initial_backoff(Duration::from_millis(50))
+            .with_max_backoff(Duration::from_millis(120))
+            .with_jitter(0.0);
+        assert_eq!(policy.delay(1), Duration::from_millis(50));
+        assert_eq!(policy.delay(2), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(120));
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_max_attempts_and_deadline() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(3)
+            .with_initial_backoff(Duration::from_millis(1))
+            .with_jitter(0.0);
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), NetworkError> = policy
+            .run("create_user", false, None, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(remote(ProtocolError::UNAVAILABLE))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // 期限までに待機が終わらない場合は再送しない
+        let attempts = AtomicU32::new(0);
+        let deadline = SystemTime::now() + Duration::from_millis(50);
+        let result: Result<(), NetworkError> = RetryPolicy::new()
+            .with_initial_backoff(Duration::from_secs(1))
+            .run("create_user", false, Some(deadline), || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(remote(ProtocolError::UNAVAILABLE))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
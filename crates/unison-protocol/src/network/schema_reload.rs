@@ -0,0 +1,161 @@
+//! 稼働中のサーバーのスキーマの読み込み直し
+//!
+//! [`ProtocolServer::reload_schema`](super::ProtocolServer::reload_schema)でKDLのスキーマを
+//! 読み込み直すと、サーバーは次の順に切り替えます。
+//!
+//! 1. スキーマを解析し、検証の規則・実行期限・エンコーディングを新しい定義に置き換える
+//! 2. ハンドシェイクで照合するスキーマを新しい指紋とメソッドに置き換える
+//! 3. 新しいスキーマから削除されたメソッドの呼び出しを`NOT_FOUND`で拒否する
+//! 4. 接続中のクライアントへ[`SCHEMA_CHANGED_EVENT_METHOD`]の制御パケットを送信
+//!
+//! 接続を閉じずにプロトコルを更新できるため、無停止でのスキーマの更新に使います。
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+use super::ProtocolError;
+use super::content::ContentError;
+use super::handshake::SchemaIdentity;
+use crate::parser::ValidationError;
+
+/// サーバーがスキーマの変更を通知するイベントのメソッド名
+pub const SCHEMA_CHANGED_EVENT_METHOD: &str = "unison.schema.changed";
+
+/// スキーマの読み込み直しによる変更（クライアントへの通知のペイロード）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaChange {
+    /// 読み込み直す前のスキーマの指紋（照合していなかった場合は`None`）
+    pub previous: Option<String>,
+    /// 新しいスキーマの指紋
+    pub fingerprint: String,
+    /// 新しいスキーマのプロトコルのバージョン
+    pub version: Option<String>,
+    /// 追加されたメソッド・ストリーム
+    pub added_methods: Vec<String>,
+    /// 削除されたメソッド・ストリーム（以降の呼び出しは拒否される）
+    pub removed_methods: Vec<String>,
+}
+
+impl SchemaChange {
+    /// 前後のスキーマの差分
+    ///
+    /// 前のスキーマのメソッドが分からない場合（指紋のみ）は、追加・削除とも空になります。
+    pub fn between(
+        previous: Option<&SchemaIdentity>,
+        current: &SchemaIdentity,
+        version: Option<String>,
+    ) -> Self {
+        let (added_methods, removed_methods) = match previous {
+            Some(previous) if !previous.methods.is_empty() => {
+                let before: BTreeSet<&String> = previous.methods.iter().collect();
+                let after: BTreeSet<&String> = current.methods.iter().collect();
+                (
+                    after.difference(&before).map(|m| m.to_string()).collect(),
+                    before.difference(&after).map(|m| m.to_string()).collect(),
+                )
+            }
+            _ => (Vec::new(), Vec::new()),
+        };
+        Self {
+            previous: previous.map(|schema| schema.fingerprint.clone()),
+            fingerprint: current.fingerprint.clone(),
+            version,
+            added_methods,
+            removed_methods,
+        }
+    }
+
+    /// 指紋が変わったか
+    pub fn is_changed(&self) -> bool {
+        self.previous.as_deref() != Some(self.fingerprint.as_str())
+    }
+}
+
+/// スキーマの読み込み直しのエラー（エラーの場合、サーバーのスキーマは変わらない）
+#[derive(Debug, Error)]
+pub enum SchemaReloadError {
+    #[error("Failed to parse schema: {0}")]
+    Parse(String),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error(transparent)]
+    Encoding(#[from] ContentError),
+}
+
+/// スキーマから削除されたメソッド
+#[derive(Clone, Default)]
+pub(crate) struct RetiredMethods {
+    methods: Arc<RwLock<HashSet<String>>>,
+}
+
+impl RetiredMethods {
+    /// 削除されたメソッドを記録し、再び追加されたメソッドの記録を外す
+    pub(crate) fn apply(&self, change: &SchemaChange) {
+        let mut methods = self.methods.write().unwrap();
+        for method in &change.added_methods {
+            methods.remove(method);
+        }
+        methods.extend(change.removed_methods.iter().cloned());
+    }
+
+    /// 削除されたメソッドであれば`NOT_FOUND`を返す
+    pub(crate) fn check(&self, method: &str) -> Result<(), ProtocolError> {
+        if !self.methods.read().unwrap().contains(method) {
+            return Ok(());
+        }
+        Err(ProtocolError::new(
+            ProtocolError::NOT_FOUND,
+            format!("Method was removed from the schema: {}", method),
+        )
+        .with_details(serde_json::json!({ "reason": "removed", "method": method })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(fingerprint: &str, methods: &[&str]) -> SchemaIdentity {
+        SchemaIdentity {
+            fingerprint: fingerprint.to_string(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_change_between_schemas() {
+        let before = identity("a", &["get", "list", "put"]);
+        let after = identity("b", &["get", "put", "watch"]);
+        let change = SchemaChange::between(Some(&before), &after, Some("2.0".to_string()));
+        assert_eq!(change.previous.as_deref(), Some("a"));
+        assert_eq!(change.added_methods, ["watch"]);
+        assert_eq!(change.removed_methods, ["list"]);
+        assert!(change.is_changed());
+
+        // 指紋のみの場合は差分が分からない
+        let change = SchemaChange::between(Some(&identity("a", &[])), &after, None);
+        assert!(change.added_methods.is_empty() && change.removed_methods.is_empty());
+
+        let change = SchemaChange::between(Some(&after), &after, None);
+        assert!(!change.is_changed());
+        assert!(SchemaChange::between(None, &after, None).is_changed());
+    }
+
+    #[test]
+    fn test_retired_methods_are_rejected_until_added_again() {
+        let retired = RetiredMethods::default();
+        let v1 = identity("a", &["get", "list"]);
+        let v2 = identity("b", &["get"]);
+        retired.apply(&SchemaChange::between(Some(&v1), &v2, None));
+
+        assert!(retired.check("get").is_ok());
+        let error = retired.check("list").unwrap_err();
+        assert_eq!(error.code, ProtocolError::NOT_FOUND);
+        assert_eq!(error.details.unwrap()["reason"], "removed");
+
+        retired.apply(&SchemaChange::between(Some(&v2), &v1, None));
+        assert!(retired.check("list").is_ok());
+    }
+}
@@ -1,19 +1,91 @@
 use anyhow::Result;
-use futures_util::Stream;
+use futures_util::{FutureExt, Stream, StreamExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
+use tracing::Instrument;
 
-use super::service::Service;
+use crate::allocation::{self, ALLOC_STATS_METHOD, Subsystem, in_subsystem};
+use crate::packet::{PacketType, RkyvPayload, UnisonPacketBuilder, UnisonPacketHeader};
+use crate::parser::{ParsedSchema, SchemaValidator, ValidationError};
+
+use super::admission::{AdmissionConfig, AdmissionController, AdmissionRejected};
+use super::assets::AssetService;
+use super::broadcast::{
+    BroadcastConfig, BroadcastHandle, ConnectionId, ConnectionRegistry, MessageSink,
+};
+use super::connection_stats::{ConnectionMeter, ConnectionStats};
+use super::content::{CONTENT_TYPE_METADATA_KEY, ContentError, PayloadEncoding, method_encodings};
+use super::context::RequestContext;
+use super::decode::DecodeConfig;
+use super::drain::{DRAIN_STATUS_METHOD, DrainStatus, StreamTracker};
+use super::envelope::{Request, RequestHead, Response, current_request, with_request_head};
+use super::events::{CloseReason, ServerEvent, ServerEvents};
+use super::exactly_once::{
+    DedupWindow, ExactlyOnceConfig, IDEMPOTENCY_CREDENTIAL_METADATA_KEY,
+    IDEMPOTENCY_KEY_METADATA_KEY, IdempotentRequest, credential_scope, payload_hash,
+};
+use super::failover::DRAIN_EVENT_METHOD;
+use super::framing::WireFormat;
+use super::handler::{
+    DEFAULT_HANDLER_TIMEOUT, HandlerMetrics, HandlerOptions, HandlerResponse, catch_handler_panic,
+    enforce_deadline, panic_error, panic_response,
+};
+use super::handshake::{self, Capabilities, SchemaIdentity};
+use super::heartbeat::HeartbeatConfig;
+use super::history::{
+    FRAME_HISTORY_METHOD, FrameDirection, FrameHistory, FrameHistoryAuthorizer, FrameHistoryConfig,
+};
+use super::i18n::{ErrorCatalog, LOCALE_METADATA_KEY};
+use super::introspection;
+use super::lifecycle::{
+    RestartPolicy, SERVICE_EVENT_TOPIC, SERVICES_METHOD, ServiceEvent, ServiceLifecycle,
+    ServiceState, SupervisionConfig, drain_service, supervise,
+};
+#[cfg(feature = "metrics")]
+use super::metrics::{Metrics, MetricsSnapshot};
+use super::presence::{
+    PRESENCE_QUERY_METHOD, PRESENCE_SET_METHOD, Presence, PresenceConfig, PresenceState,
+    presence_topic,
+};
+use super::pubsub::{PubSub, PubSubError};
+use super::quota::MemoryQuotaConfig;
+use super::rate_limit::{
+    RETRY_AFTER_METADATA_KEY, RateLimitConfig, RateLimitExceeded, RateLimiter,
+};
+use super::resume::{ResumeConfig, ResumeRegistry, ResumeToken, StreamEvent};
+use super::schema_reload::{
+    RetiredMethods, SCHEMA_CHANGED_EVENT_METHOD, SchemaChange, SchemaReloadError,
+};
+use super::service::{Service, ServiceStats};
+use super::shutdown::{DEFAULT_SHUTDOWN_GRACE, GOAWAY_EVENT_METHOD, ShutdownController};
+use super::slo::{LatencySlo, SLO_VIOLATION_TOPIC, SloTracker, SloViolation};
+use super::stats::{STATS_METHOD, StatsRecorder};
+use super::stream_id::{StreamIdClaim, StreamIdConfig, StreamIdRegistry, StreamInitiator};
+use super::supervisor::TaskSupervisor;
+use super::tenant::{TenantConfig, TenantError, TenantId, Tenants, with_tenant};
+use super::tls::{CertificatePin, CertificateReloader, TlsConfig};
+use super::trace::{self, with_trace};
+use super::udp::{UdpBackend, UdpSocketConfig};
+use super::usage::{
+    QUOTA_RESET_METADATA_KEY, QUOTA_USAGE_METHOD, QuotaExceeded, UsageConfig, UsageKey,
+    UsageTracker,
+};
 use super::{
-    MessageType, NetworkError, ProtocolMessage, ProtocolServerTrait, UnisonServer, UnisonServerExt,
+    MessageType, NetworkError, ProtocolError, ProtocolMessage, ProtocolServerTrait, UnisonServer,
+    UnisonServerExt,
 };
 
 /// サーバーハンドラー関数型
 type CallHandler = Arc<
-    dyn Fn(Value) -> Pin<Box<dyn futures_util::Future<Output = Result<Value>> + Send>>
+    dyn Fn(Value) -> Pin<Box<dyn futures_util::Future<Output = HandlerResponse> + Send>>
         + Send
         + Sync,
 >;
@@ -32,34 +104,1718 @@ type StreamHandler = Arc<
         + Sync,
 >;
 
-/// シンプルハンドラー用のUnisonハンドラー型
-type UnisonHandler =
-    Arc<dyn Fn(serde_json::Value) -> Result<serde_json::Value, NetworkError> + Send + Sync>;
+/// 再開可能ストリームハンドラー関数型（リクエストペイロードと再開オフセットを受け取る）
+type ResumableStreamHandler = Arc<
+    dyn Fn(
+            Value,
+            u64,
+        ) -> Pin<
+            Box<
+                dyn futures_util::Future<
+                        Output = Result<Pin<Box<dyn Stream<Item = Result<Value>> + Send>>>,
+                    > + Send,
+            >,
+        > + Send
+        + Sync,
+>;
+
+/// クライアントストリームで届くリクエストの列
+///
+/// クライアントが`StreamEnd`を送ると終わります。クライアントが`StreamError`を送った場合や
+/// 終わりを送る前に切断した場合は、エラーを1つ返して終わります。
+pub type ClientStreamRequests = Pin<Box<dyn Stream<Item = Result<Value, NetworkError>> + Send>>;
+
+/// クライアントストリームハンドラー関数型
+type ClientStreamHandler = Arc<
+    dyn Fn(
+            ClientStreamRequests,
+        ) -> Pin<Box<dyn futures_util::Future<Output = HandlerResponse> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// 双方向ストリーム（SystemStream）ハンドラー関数型
+type SystemStreamHandler = Arc<
+    dyn Fn(
+            Value,
+            crate::network::quic::UnisonStream,
+        ) -> Pin<Box<dyn futures_util::Future<Output = Result<(), NetworkError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// シンプルハンドラー用のUnisonハンドラー型
+type UnisonHandler =
+    Arc<dyn Fn(serde_json::Value) -> Result<serde_json::Value, NetworkError> + Send + Sync>;
+
+/// メソッド名からハンドラーへの対応表
+///
+/// 登録は同期的に行い、`listen`の前後を問わず登録直後から呼び出せます。
+/// ロックはハンドラーを取り出す間だけ保持し、`await`をまたいで保持しません。
+type HandlerMap<H> = Arc<std::sync::RwLock<HashMap<String, H>>>;
+
+/// プロトコルサーバー実装
+pub struct ProtocolServer {
+    call_handlers: HandlerMap<CallHandler>,
+    stream_handlers: HandlerMap<StreamHandler>,
+    client_stream_handlers: HandlerMap<ClientStreamHandler>,
+    system_stream_handlers: HandlerMap<SystemStreamHandler>,
+    resumable_handlers: HandlerMap<ResumableStreamHandler>,
+    resume_registry: ResumeRegistry,
+    unison_handlers: HandlerMap<UnisonHandler>,
+    services: Arc<RwLock<HashMap<String, crate::network::service::UnisonService>>>,
+    /// サービスごとの状態と再起動の監督
+    lifecycle: ServiceLifecycle,
+    connections: ConnectionRegistry,
+    pubsub: PubSub,
+    presence: Presence,
+    tenants: Tenants,
+    /// 接続・メソッドごとのレート制限
+    rate_limiter: RateLimiter,
+    /// 同時に実行するハンドラーの数の制限
+    admission: AdmissionController,
+    usage: UsageTracker,
+    /// メソッドごとのレイテンシSLO
+    slo: SloTracker,
+    /// 開いているストリーム
+    streams: StreamTracker,
+    /// 接続・ストリームのライフサイクルイベント
+    events: ServerEvents,
+    /// メソッド・ストリームごとの統計
+    stats: StatsRecorder,
+    /// ハンドラーの実行期限の既定値（`None`の場合は無制限）
+    handler_timeout: Option<Duration>,
+    /// メソッドごとの実行期限
+    method_timeouts: Arc<RwLock<HashMap<String, Duration>>>,
+    /// メソッドごとに受け付けるペイロードのエンコーディング（登録がなければJSONのみ）
+    method_encodings: Arc<RwLock<HashMap<String, Vec<PayloadEncoding>>>>,
+    handler_metrics: Arc<HandlerMetrics>,
+    running: Arc<RwLock<bool>>,
+    shutdown: ShutdownController,
+    /// 停止時に処理中のハンドラーの完了を待つ期間
+    shutdown_grace: Duration,
+    /// 受信したフレームの復元（大きなフレームはブロッキング用のスレッドで復元する）
+    decode: DecodeConfig,
+    /// Prometheus互換のメトリクス
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+    /// 型付きハンドラーがリクエストの検証に使うスキーマ
+    schema_validator: Arc<std::sync::RwLock<Option<Arc<SchemaValidator>>>>,
+    /// 接続とリクエストの処理タスクを実行するランタイム（`None`の場合は`listen`を呼び出したランタイム）
+    runtime: Option<tokio::runtime::Handle>,
+    /// QUICのエンドポイントが使うUDPソケットの実装
+    udp_backend: UdpBackend,
+    /// QUICのエンドポイントが使うUDPソケットのチューニング
+    udp_config: UdpSocketConfig,
+    /// QUICのTLSの設定（`None`の場合は開発用の証明書を使う）
+    tls: Option<TlsConfig>,
+    /// 証明書ファイルの更新を確認する間隔（`None`の場合は確認しない）
+    certificate_reload: Option<Duration>,
+    /// 証明書の読み込み直しの要求
+    certificate_reloader: CertificateReloader,
+    /// 接続ごとのハートビート（`None`の場合は送らない）
+    heartbeat: Option<HeartbeatConfig>,
+    /// WebSocket・標準入出力で送るメッセージの形式
+    wire_format: WireFormat,
+    /// ハンドシェイクで照合するスキーマ（`None`の場合は照合しない）
+    schema: Arc<std::sync::RwLock<Option<SchemaIdentity>>>,
+    /// スキーマの読み込み直しで削除されたメソッド
+    retired_methods: RetiredMethods,
+    /// ハンドシェイクを終えた接続ごとの相手の情報
+    capabilities: Arc<std::sync::RwLock<HashMap<ConnectionId, Capabilities>>>,
+    /// エラーメッセージをロケールに合わせて置き換えるカタログ
+    error_catalog: Option<Arc<ErrorCatalog>>,
+    /// 接続ごとの相手のアドレス（トランスポートが分かる場合のみ）
+    peer_addresses: Arc<std::sync::RwLock<HashMap<ConnectionId, SocketAddr>>>,
+    /// 接続ごとの相手のクライアント証明書（mTLSで検証された場合のみ）
+    peer_certificates: Arc<std::sync::RwLock<HashMap<ConnectionId, CertificatePin>>>,
+    /// 接続ごとの統計の記録
+    connection_meters: Arc<std::sync::RwLock<HashMap<ConnectionId, ConnectionMeter>>>,
+    /// 接続ごとの直近のフレーム（`None`の場合は記録しない）
+    frame_history: Option<FrameHistory>,
+    /// すべての接続の記録の取得を許可する接続の判定（`None`の場合は自分の接続の記録のみ）
+    frame_history_authorizer: Option<FrameHistoryAuthorizer>,
+    /// 厳密に1回処理するメソッドの重複排除ウィンドウ
+    dedup: DedupWindow,
+    /// 接続ごとにクライアントが開始したストリームのID
+    stream_ids: StreamIdRegistry,
+}
+
+impl ProtocolServer {
+    pub fn new() -> Self {
+        let events = ServerEvents::default();
+        Self {
+            call_handlers: HandlerMap::default(),
+            stream_handlers: HandlerMap::default(),
+            client_stream_handlers: HandlerMap::default(),
+            system_stream_handlers: HandlerMap::default(),
+            resumable_handlers: HandlerMap::default(),
+            resume_registry: ResumeRegistry::default(),
+            unison_handlers: HandlerMap::default(),
+            services: Arc::new(RwLock::new(HashMap::new())),
+            lifecycle: ServiceLifecycle::new(),
+            connections: ConnectionRegistry::default(),
+            pubsub: PubSub::default(),
+            presence: Presence::default(),
+            tenants: Tenants::default(),
+            rate_limiter: RateLimiter::default(),
+            admission: AdmissionController::default(),
+            usage: UsageTracker::default(),
+            slo: SloTracker::default(),
+            streams: StreamTracker::with_events(events.clone()),
+            events,
+            stats: StatsRecorder::default(),
+            handler_timeout: Some(DEFAULT_HANDLER_TIMEOUT),
+            method_timeouts: Arc::new(RwLock::new(HashMap::new())),
+            method_encodings: Arc::new(RwLock::new(HashMap::new())),
+            handler_metrics: Arc::new(HandlerMetrics::default()),
+            running: Arc::new(RwLock::new(false)),
+            shutdown: ShutdownController::new(),
+            shutdown_grace: DEFAULT_SHUTDOWN_GRACE,
+            decode: DecodeConfig::default(),
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new("unison_server"),
+            schema_validator: Arc::default(),
+            runtime: None,
+            udp_backend: UdpBackend::default(),
+            udp_config: UdpSocketConfig::default(),
+            tls: None,
+            certificate_reload: None,
+            certificate_reloader: CertificateReloader::default(),
+            heartbeat: None,
+            wire_format: WireFormat::default(),
+            schema: Arc::default(),
+            retired_methods: RetiredMethods::default(),
+            capabilities: Arc::default(),
+            error_catalog: None,
+            peer_addresses: Arc::default(),
+            peer_certificates: Arc::default(),
+            connection_meters: Arc::default(),
+            frame_history: None,
+            frame_history_authorizer: None,
+            dedup: DedupWindow::default(),
+            stream_ids: StreamIdRegistry::default(),
+        }
+    }
+
+    /// ブロードキャストの設定を指定
+    pub fn with_broadcast_config(mut self, config: BroadcastConfig) -> Self {
+        let memory_quota = self.connections.memory_quota().clone();
+        self.connections = ConnectionRegistry::with_memory_quota(config, memory_quota);
+        self
+    }
+
+    /// 接続ごとのメモリ上限を指定（受信中のペイロードと送信キュー）
+    pub fn with_memory_quota(mut self, memory_quota: MemoryQuotaConfig) -> Self {
+        let config = self.connections.config().clone();
+        self.connections = ConnectionRegistry::with_memory_quota(config, memory_quota);
+        self
+    }
+
+    /// 接続中のクライアントのレジストリ
+    pub fn connections(&self) -> &ConnectionRegistry {
+        &self.connections
+    }
+
+    /// 接続・ストリームのライフサイクルイベントのストリーム
+    ///
+    /// 購読開始以降のイベントを受け取ります。処理が遅れて取りこぼしたイベントはスキップされます。
+    pub fn events(&self) -> Pin<Box<dyn Stream<Item = ServerEvent> + Send>> {
+        self.events.subscribe()
+    }
+
+    /// 接続を登録し、`ConnectionOpened`を発行（`quic`はQUIC接続の統計の取得に使う）
+    pub(crate) fn open_connection(
+        &self,
+        sink: Arc<dyn MessageSink>,
+        remote: Option<SocketAddr>,
+        quic: Option<quinn::Connection>,
+    ) -> ConnectionId {
+        let connection_id = self.connections.register(sink);
+        if let Some(remote) = remote {
+            self.set_peer_address(connection_id, remote);
+        }
+        if let Some(pin) = quic.as_ref().and_then(client_certificate_pin) {
+            self.peer_certificates
+                .write()
+                .unwrap()
+                .insert(connection_id, pin);
+        }
+        self.connection_meters
+            .write()
+            .unwrap()
+            .insert(connection_id, ConnectionMeter::new(quic));
+        self.events
+            .emit(|timestamp_ms| ServerEvent::ConnectionOpened {
+                connection_id,
+                remote,
+                timestamp_ms,
+            });
+        connection_id
+    }
+
+    /// 接続中の全クライアントへイベントをブロードキャスト
+    ///
+    /// 配信はワーカープールで非同期に行われ、呼び出し元はブロックされません。
+    /// 返されるハンドルで進捗と完了を確認できます。
+    pub fn broadcast(&self, method: &str, payload: Value) -> Result<BroadcastHandle, NetworkError> {
+        let message =
+            ProtocolMessage::new_with_json(0, method.to_string(), MessageType::Event, payload)?;
+        let frame = message.into_frame()?.to_bytes();
+        Ok(self.connections.broadcast_frame(frame))
+    }
+
+    /// 接続中の全クライアントへドレインを通知
+    ///
+    /// フェイルオーバーを有効にしたクライアントは、通知を受けると次の接続先へ切り替えます。
+    /// メンテナンス前に接続を他のサーバーへ移すために使います。
+    pub fn announce_drain(&self, reason: &str) -> Result<BroadcastHandle, NetworkError> {
+        self.broadcast(DRAIN_EVENT_METHOD, serde_json::json!({ "reason": reason }))
+    }
+
+    /// 停止時に処理中のハンドラーの完了を待つ期間を指定
+    pub fn with_shutdown_grace(mut self, grace: Duration) -> Self {
+        self.shutdown_grace = grace;
+        self
+    }
+
+    /// 接続とリクエストの処理タスクを実行するランタイムを指定
+    ///
+    /// IO専用のランタイムを用意している場合などに使います。
+    /// `listen`の待ち受け自体は呼び出したタスクで実行されます。
+    pub fn with_runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// 接続とリクエストの処理タスクを実行するランタイム
+    pub fn runtime(&self) -> Option<&tokio::runtime::Handle> {
+        self.runtime.as_ref()
+    }
+
+    /// QUICのエンドポイントが使うUDPソケットの実装を指定
+    ///
+    /// 高スループットのLinuxサーバーでは[`UdpBackend::IoUring`]を選べます。
+    pub fn with_udp_backend(mut self, backend: UdpBackend) -> Self {
+        self.udp_backend = backend;
+        self
+    }
+
+    /// QUICのエンドポイントが使うUDPソケットの実装
+    pub fn udp_backend(&self) -> &UdpBackend {
+        &self.udp_backend
+    }
+
+    /// QUICのエンドポイントが使うUDPソケットの送受信バッファとGSOを指定
+    pub fn with_udp_config(mut self, config: UdpSocketConfig) -> Self {
+        self.udp_config = config;
+        self
+    }
+
+    /// QUICのエンドポイントが使うUDPソケットのチューニング
+    pub fn udp_config(&self) -> &UdpSocketConfig {
+        &self.udp_config
+    }
+
+    /// QUICの証明書とクライアント証明書（mTLS）の要求を指定
+    ///
+    /// 指定しない場合は`assets/certs`・埋め込み・自己署名の順に開発用の証明書を使います。
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// QUICのTLSの設定
+    pub fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
+    }
+
+    /// 証明書ファイルの更新を`interval`ごとに確認し、更新されていれば読み込み直す
+    ///
+    /// [`TlsConfig::with_identity_files`]のファイル（TLSを指定していない場合は`assets/certs`）を
+    /// 確認します。[`TlsConfig::with_identity_loader`]の場合は間隔ごとにコールバックで読み込み直します。
+    /// 新しい証明書は以降の接続に使われ、既存の接続は切断されません。
+    pub fn with_certificate_reload(mut self, interval: Duration) -> Self {
+        self.certificate_reload = Some(interval);
+        self
+    }
+
+    /// 証明書ファイルの更新を確認する間隔
+    pub fn certificate_reload(&self) -> Option<Duration> {
+        self.certificate_reload
+    }
+
+    /// QUICのエンドポイントに証明書を読み込み直させる（既存の接続は維持される）
+    ///
+    /// 読み込みは非同期に行われ、失敗した場合は警告を出力して以前の証明書を使い続けます。
+    pub fn reload_certificates(&self) {
+        self.certificate_reloader.reload();
+    }
+
+    /// `listen`で稼働中のサーバーに証明書の読み込み直しを要求するハンドル
+    pub fn certificate_reloader(&self) -> &CertificateReloader {
+        &self.certificate_reloader
+    }
+
+    /// 接続ごとにハートビートを送り、応答しなくなったクライアントの接続を閉じる
+    pub fn with_heartbeat(mut self, config: HeartbeatConfig) -> Self {
+        self.heartbeat = Some(config);
+        self
+    }
+
+    /// 接続ごとのハートビートの設定
+    pub fn heartbeat(&self) -> Option<HeartbeatConfig> {
+        self.heartbeat
+    }
+
+    /// WebSocket・標準入出力で送るメッセージの形式を指定
+    ///
+    /// 既定は[`WireFormat::Packet`]です。パケット層に対応していない古いクライアントへ
+    /// 応答する場合は[`WireFormat::Json`]を指定します。QUICは常にパケットを使います。
+    pub fn with_wire_format(mut self, format: WireFormat) -> Self {
+        self.wire_format = format;
+        self
+    }
+
+    /// WebSocket・標準入出力で送るメッセージの形式
+    pub fn wire_format(&self) -> WireFormat {
+        self.wire_format
+    }
+
+    /// ハンドシェイクで照合するスキーマのハッシュを指定
+    ///
+    /// クライアントも指定していて値が異なる場合、接続を拒否します。
+    pub fn with_schema_hash(self, hash: impl Into<String>) -> Self {
+        *self.schema.write().unwrap() = Some(SchemaIdentity::from_fingerprint(hash));
+        self
+    }
+
+    /// 読み込んだスキーマの指紋をハンドシェイクで照合
+    ///
+    /// クライアントのスキーマと指紋が異なる場合、クライアントにあってサーバーにない
+    /// メソッドを添えて接続を拒否します。
+    pub fn with_schema_fingerprint(self, schema: &ParsedSchema) -> Self {
+        *self.schema.write().unwrap() = Some(SchemaIdentity::from_schema(schema));
+        self
+    }
+
+    /// ハンドシェイクで照合するスキーマのハッシュ
+    pub fn schema_hash(&self) -> Option<String> {
+        self.schema
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|schema| schema.fingerprint.clone())
+    }
+
+    /// 呼び出しのエラーメッセージを接続のロケールに合わせて置き換える
+    ///
+    /// ロケールはリクエストのメタデータ[`LOCALE_METADATA_KEY`]、
+    /// なければハンドシェイクで受け取ったメタデータから選びます。
+    pub fn with_error_catalog(mut self, catalog: ErrorCatalog) -> Self {
+        self.error_catalog = Some(Arc::new(catalog));
+        self
+    }
+
+    /// エラーメッセージのカタログ
+    pub fn error_catalog(&self) -> Option<&ErrorCatalog> {
+        self.error_catalog.as_deref()
+    }
+
+    /// 接続ごとに直近のリクエスト・レスポンスを記録する
+    ///
+    /// 記録は組み込みメソッド[`FRAME_HISTORY_METHOD`]で取得できます。
+    /// 既定では呼び出した接続の記録のみを返します。
+    pub fn with_frame_history(mut self, config: FrameHistoryConfig) -> Self {
+        self.frame_history = Some(FrameHistory::new(config));
+        self
+    }
+
+    /// [`FRAME_HISTORY_METHOD`]ですべての接続の記録を取得できる接続を判定する関数を指定
+    ///
+    /// 関数が`false`を返した接続は自分の接続の記録のみ取得できます。
+    pub fn with_frame_history_authorizer<F>(mut self, authorizer: F) -> Self
+    where
+        F: Fn(ConnectionId, Option<&Capabilities>) -> bool + Send + Sync + 'static,
+    {
+        self.frame_history_authorizer = Some(Arc::new(authorizer));
+        self
+    }
+
+    /// 接続ごとの直近のフレームの記録
+    pub fn frame_history(&self) -> Option<&FrameHistory> {
+        self.frame_history.as_ref()
+    }
+
+    /// 指定したメソッドを冪等キーごとに厳密に1回処理する
+    ///
+    /// 同じ冪等キーのリクエストは、ウィンドウの間はハンドラーを実行せずに最初の結果を返します。
+    pub fn with_exactly_once(mut self, config: ExactlyOnceConfig) -> Self {
+        self.dedup = DedupWindow::new(config);
+        self
+    }
+
+    /// 厳密に1回処理するメソッドの重複排除ウィンドウ
+    pub fn dedup_window(&self) -> &DedupWindow {
+        &self.dedup
+    }
+
+    /// クライアントが開始したストリームのIDの割り当て方を指定
+    ///
+    /// 既定では偶奇とエポックを検証します。旧来の連番で割り当てるクライアントを受け付ける
+    /// 場合は[`StreamIdConfig::sequential`]を指定してください。衝突は常に検出します。
+    pub fn with_stream_ids(mut self, config: StreamIdConfig) -> Self {
+        self.stream_ids = StreamIdRegistry::new(config);
+        self
+    }
+
+    /// ストリームIDの設定
+    pub fn stream_id_config(&self) -> StreamIdConfig {
+        self.stream_ids.config()
+    }
+
+    /// クライアントが開始したストリームのIDを検証し、ストリームが終わるまで使用中として記録
+    pub(crate) fn claim_stream_id(
+        &self,
+        connection_id: ConnectionId,
+        stream_id: u64,
+    ) -> Result<StreamIdClaim, ProtocolError> {
+        self.stream_ids
+            .claim(connection_id, stream_id, StreamInitiator::Client)
+            .map_err(ProtocolError::from)
+    }
+
+    /// 接続のハンドシェイクで取り決めた相手の情報（ハンドシェイク前は`None`）
+    pub fn capabilities(&self, connection_id: ConnectionId) -> Option<Capabilities> {
+        self.capabilities
+            .read()
+            .unwrap()
+            .get(&connection_id)
+            .cloned()
+    }
+
+    /// 接続の相手のアドレス（QUIC・WebSocket以外の接続では`None`）
+    pub fn peer_address(&self, connection_id: ConnectionId) -> Option<SocketAddr> {
+        self.peer_addresses
+            .read()
+            .unwrap()
+            .get(&connection_id)
+            .copied()
+    }
+
+    /// 接続の相手がmTLSで提示したクライアント証明書の公開鍵のハッシュ（提示していなければ`None`）
+    pub fn peer_certificate(&self, connection_id: ConnectionId) -> Option<CertificatePin> {
+        self.peer_certificates
+            .read()
+            .unwrap()
+            .get(&connection_id)
+            .copied()
+    }
+
+    /// 接続の往復時間・送受信量・開いたストリームの数など（切断済みの接続では`None`）
+    pub fn connection_stats(&self, connection_id: ConnectionId) -> Option<ConnectionStats> {
+        let meters = self.connection_meters.read().unwrap();
+        meters.get(&connection_id).map(ConnectionMeter::stats)
+    }
+
+    /// 接続でストリームを開いたことを記録
+    pub(crate) fn record_connection_stream(&self, connection_id: ConnectionId) {
+        if let Some(meter) = self.connection_meters.read().unwrap().get(&connection_id) {
+            meter.record_stream();
+        }
+    }
+
+    pub(crate) fn set_peer_address(&self, connection_id: ConnectionId, address: SocketAddr) {
+        self.peer_addresses
+            .write()
+            .unwrap()
+            .insert(connection_id, address);
+    }
+
+    /// クライアントのハンドシェイクを検証し、受け入れた場合は相手の情報を記録
+    pub(crate) fn accept_handshake(
+        &self,
+        connection_id: ConnectionId,
+        request: &ProtocolMessage,
+    ) -> Result<crate::core::HandshakeResponse, ProtocolError> {
+        let request = serde_json::from_str(&request.payload).map_err(|e| {
+            ProtocolError::new(
+                ProtocolError::INVALID_REQUEST,
+                format!("Invalid handshake: {}", e),
+            )
+        })?;
+        let interval = self.heartbeat.map(|config| config.interval);
+        let schema = self.schema.read().unwrap().clone();
+        let (response, capabilities) = handshake::accept(request, schema.as_ref(), interval)
+            .inspect_err(|e| {
+                tracing::warn!(
+                    "Rejected handshake from connection {}: {}",
+                    connection_id,
+                    e
+                );
+            })?;
+        self.events
+            .emit(|timestamp_ms| ServerEvent::HandshakeCompleted {
+                connection_id,
+                session_id: capabilities.session_id.clone(),
+                peer_name: capabilities.peer_name.clone(),
+                protocol_version: capabilities.protocol_version.clone(),
+                timestamp_ms,
+            });
+        self.capabilities
+            .write()
+            .unwrap()
+            .insert(connection_id, capabilities);
+        Ok(response)
+    }
+
+    /// サーバーのランタイムでタスクを実行する監督を作成
+    pub(crate) fn task_supervisor(&self, name: &'static str) -> TaskSupervisor {
+        match &self.runtime {
+            Some(runtime) => TaskSupervisor::with_runtime(name, runtime.clone()),
+            None => TaskSupervisor::new(name),
+        }
+    }
+
+    /// 停止時に処理中のハンドラーの完了を待つ期間
+    pub fn shutdown_grace(&self) -> Duration {
+        self.shutdown_grace
+    }
+
+    /// 停止の要求と処理中のリクエスト数を共有するハンドル
+    ///
+    /// `listen`にサーバーを渡した後でも、このハンドルの`trigger`で停止できます。
+    pub fn shutdown_controller(&self) -> &ShutdownController {
+        &self.shutdown
+    }
+
+    /// 接続中の全クライアントへ停止を通知（制御パケット）
+    ///
+    /// 通知を受けたクライアントは新しいリクエストの送信をやめ、
+    /// フェイルオーバーが有効であれば次の接続先へ切り替えます。
+    pub fn announce_goaway(&self) -> Result<BroadcastHandle, NetworkError> {
+        let message = ProtocolMessage::new_with_json(
+            0,
+            GOAWAY_EVENT_METHOD.to_string(),
+            MessageType::Event,
+            serde_json::json!({ "grace_ms": self.shutdown_grace.as_millis() as u64 }),
+        )?;
+        let frame = UnisonPacketBuilder::new()
+            .packet_type(PacketType::Control)
+            .build(RkyvPayload::new(message))?;
+        Ok(self.connections.broadcast_frame(frame.to_bytes()))
+    }
+
+    /// Pub/Subのブローカーを指定（永続購読の保持設定やオフセットストアの差し替え用）
+    pub fn with_pubsub(mut self, pubsub: PubSub) -> Self {
+        self.pubsub = pubsub;
+        self
+    }
+
+    /// トピック購読のブローカー
+    pub fn pubsub(&self) -> &PubSub {
+        &self.pubsub
+    }
+
+    /// トピックにイベントを発行
+    ///
+    /// 購読パターンとフィルターの両方に一致した接続にのみ配信されます。
+    /// 発行先のトピックにはワイルドカードを指定できません。
+    /// 永続購読の対象トピックでは、イベントの`id`に保持ログのオフセットが設定されます。
+    pub fn publish(&self, topic: &str, payload: Value) -> Result<BroadcastHandle, NetworkError> {
+        self.publish_scoped(topic, topic, payload)
+    }
+
+    /// テナントの名前空間のトピックにイベントを発行
+    ///
+    /// 同じテナントに紐づく接続の購読にのみ配信され、イベントには元のトピック名が使われます。
+    pub fn publish_to_tenant(
+        &self,
+        tenant: &TenantId,
+        topic: &str,
+        payload: Value,
+    ) -> Result<BroadcastHandle, NetworkError> {
+        self.publish_scoped(&tenant.scope_topic(topic), topic, payload)
+    }
+
+    fn publish_scoped(
+        &self,
+        scoped_topic: &str,
+        topic: &str,
+        payload: Value,
+    ) -> Result<BroadcastHandle, NetworkError> {
+        let publication = self
+            .pubsub
+            .publish(scoped_topic, &payload)
+            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+        let message = ProtocolMessage::new_with_json(
+            publication.offset.unwrap_or(0),
+            topic.to_string(),
+            MessageType::Event,
+            payload,
+        )?;
+        let frame = message.into_frame()?.to_bytes();
+        Ok(self
+            .connections
+            .broadcast_frame_to(&publication.targets, frame))
+    }
+
+    /// 接続からのリクエストを処理
+    ///
+    /// 購読などの接続に紐づく組み込みメソッドを処理し、それ以外は通常のハンドラーへ渡します。
+    pub async fn handle_connection_call(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+        payload: Value,
+    ) -> Result<Value> {
+        self.handle_connection_request(connection_id, method, payload)
+            .await
+            .into_result()
+    }
+
+    /// マルチテナントの設定を指定
+    pub fn with_tenant_config(mut self, config: TenantConfig) -> Self {
+        self.tenants = Tenants::new(config);
+        self
+    }
+
+    /// 接続とテナントの紐づけ・テナントごとの集計
+    pub fn tenants(&self) -> &Tenants {
+        &self.tenants
+    }
+
+    /// 受信したフレームの復元の設定を指定
+    ///
+    /// [`DecodeConfig::blocking_threshold`]以上のフレームは、展開とデシリアライズを
+    /// ブロッキング用のスレッドで行い、他の接続の処理を止めないようにします。
+    pub fn with_decode_config(mut self, decode: DecodeConfig) -> Self {
+        self.decode = decode;
+        self
+    }
+
+    /// 受信したフレームの復元の設定
+    pub fn decode_config(&self) -> DecodeConfig {
+        self.decode
+    }
+
+    /// 接続・メソッドごとのレート制限を指定
+    ///
+    /// 上限を超えたリクエストはハンドラーを実行せずに`RESOURCE_EXHAUSTED`で拒否し、
+    /// 再試行できるまでの時間をメタデータ[`RETRY_AFTER_METADATA_KEY`]に設定します。
+    pub fn with_rate_limit_config(mut self, config: RateLimitConfig) -> Self {
+        self.rate_limiter = RateLimiter::new(config);
+        self
+    }
+
+    /// 接続・メソッドごとのレート制限
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// 同時に実行するハンドラーの数の上限を指定
+    ///
+    /// 上限と待ち行列の両方が埋まっている場合や待ち時間の上限を過ぎた場合は、
+    /// ハンドラーを実行せずに`UNAVAILABLE`で拒否します。組み込みメソッドは制限しません。
+    pub fn with_admission_config(mut self, config: AdmissionConfig) -> Self {
+        self.admission = AdmissionController::new(config);
+        self
+    }
+
+    /// 同時に実行するハンドラーの数の制限
+    pub fn admission(&self) -> &AdmissionController {
+        &self.admission
+    }
+
+    /// テナント・ピアごとの利用量の上限を指定
+    pub fn with_usage_config(mut self, config: UsageConfig) -> Self {
+        self.usage = UsageTracker::new(config);
+        self
+    }
+
+    /// テナント・ピアごとの利用量
+    pub fn usage(&self) -> &UsageTracker {
+        &self.usage
+    }
+
+    /// メソッドのレイテンシSLOを宣言
+    ///
+    /// 違反すると[`Self::on_slo_violation`]のコールバックを呼び出し、
+    /// [`SLO_VIOLATION_TOPIC`]へイベントを発行します。
+    pub fn with_latency_slo(self, method: &str, slo: LatencySlo) -> Self {
+        self.slo.set(method, slo);
+        self
+    }
+
+    /// SLO違反時に呼び出すコールバックを追加
+    pub fn on_slo_violation<F>(self, callback: F) -> Self
+    where
+        F: Fn(&SloViolation) + Send + Sync + 'static,
+    {
+        self.slo.on_violation(callback);
+        self
+    }
+
+    /// メソッドごとのレイテンシSLOの集計
+    pub fn slo(&self) -> &SloTracker {
+        &self.slo
+    }
+
+    /// 開いているストリームの記録
+    pub fn streams(&self) -> &StreamTracker {
+        &self.streams
+    }
+
+    /// 全体の集計とメソッド・ストリームごとの内訳
+    ///
+    /// 組み込みメソッド[`STATS_METHOD`]でも同じ内容を取得できます。
+    pub fn stats(&self) -> ServiceStats {
+        // 接続中の全接続のパケットロス率とジッター
+        let meters: Vec<ConnectionMeter> = self
+            .connection_meters
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        let connections: Vec<ConnectionStats> = meters.iter().map(ConnectionMeter::stats).collect();
+        let sent: u64 = connections.iter().map(|stats| stats.packets_out).sum();
+        let lost: u64 = connections.iter().map(|stats| stats.retransmits).sum();
+        ServiceStats {
+            packet_loss_rate: if sent == 0 {
+                0.0
+            } else {
+                lost as f64 / sent as f64
+            },
+            jitter_ms: if meters.is_empty() {
+                0.0
+            } else {
+                meters.iter().map(ConnectionMeter::jitter_ms).sum::<f64>() / meters.len() as f64
+            },
+            admission: Some(self.admission.stats()),
+            ..self.stats.snapshot()
+        }
+    }
+
+    /// メソッド・ストリームごとの統計の集計
+    pub fn stats_recorder(&self) -> &StatsRecorder {
+        &self.stats
+    }
+
+    fn record_call(&self, method: &str, elapsed: Duration, response: &HandlerResponse) {
+        self.stats.record_call(method, elapsed, response.is_ok());
+        #[cfg(feature = "metrics")]
+        match &response.outcome {
+            Ok(_) => self
+                .metrics
+                .record_request(method, super::metrics::STATUS_OK, elapsed),
+            Err(e) => self
+                .metrics
+                .record_request(method, &e.code.to_string(), elapsed),
+        }
+    }
+
+    /// 送受信したフレームの圧縮の有無と大きさを記録
+    pub(crate) fn record_frame(&self, header: &UnisonPacketHeader) {
+        #[cfg(feature = "metrics")]
+        self.metrics
+            .record_frame(header.payload_length, header.compressed_length);
+        #[cfg(not(feature = "metrics"))]
+        let _ = header;
+    }
+
+    /// Prometheus互換のメトリクス（接続数・ストリーム数・ハンドラーの待ち行列の長さを含む）
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> MetricsSnapshot {
+        let admission = self.admission.stats();
+        let queued: u64 = admission.methods.values().map(|stats| stats.queued).sum();
+        MetricsSnapshot {
+            active_connections: self.connections.len() as u64,
+            active_streams: self.streams.active() as u64,
+            in_flight: admission.global.in_flight,
+            queue_depth: admission.global.queued + queued,
+            ..self.metrics.snapshot()
+        }
+    }
+
+    /// 処理中のリクエスト・ストリームと停止要求からの進捗
+    ///
+    /// `safe_to_restart`が`true`であれば、再起動しても処理中の呼び出しは切断されません。
+    pub fn drain_status(&self) -> DrainStatus {
+        DrainStatus::new(
+            self.shutdown.is_stopping(),
+            self.shutdown.in_flight(),
+            self.connections.len(),
+            &self.streams,
+            self.shutdown.drain_progress(),
+        )
+    }
+
+    /// 組み込みメソッドでドレインの状態を返す
+    ///
+    /// サーバー全体の情報のため、テナントに紐づく接続からは使用できません。
+    fn handle_drain_status(&self, connection_id: ConnectionId) -> HandlerResponse {
+        if self.tenants.tenant_of(connection_id).is_some() {
+            return HandlerResponse::error(ProtocolError::new(
+                ProtocolError::PERMISSION_DENIED,
+                "Drain status is not available to tenant-scoped connections",
+            ));
+        }
+        match serde_json::to_value(self.drain_status()) {
+            Ok(status) => HandlerResponse::ok(status),
+            Err(e) => HandlerResponse::error(ProtocolError::internal(e.to_string())),
+        }
+    }
+
+    /// リクエストのメタデータから接続のテナントを決定
+    ///
+    /// 接続が固定されたテナントと異なるテナントが指定された場合は拒否します。
+    pub fn bind_tenant(
+        &self,
+        connection_id: ConnectionId,
+        request: &ProtocolMessage,
+    ) -> Result<Option<TenantId>, ProtocolError> {
+        let metadata = request.metadata().map_err(ProtocolError::from)?;
+        self.tenants
+            .bind(connection_id, &metadata)
+            .map_err(tenant_error)
+    }
+
+    /// 接続からのリクエストを処理し、メタデータを含むレスポンスを返す
+    ///
+    /// 接続がテナントに紐づいている場合は、テナントのレート制限を適用し、
+    /// ハンドラーを[`current_tenant`](super::tenant::current_tenant)が返すテナントで実行します。
+    /// 接続・メソッドごとのレート制限を超えた場合は、再試行までの時間をメタデータに含めて拒否します。
+    /// 利用量の上限を超えた場合は、リセット時刻をメタデータに含めて拒否します。
+    pub async fn handle_connection_request(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+        payload: Value,
+    ) -> HandlerResponse {
+        // ドレインの進捗を確認できるよう、停止中も応答する
+        if method == DRAIN_STATUS_METHOD {
+            return self.handle_drain_status(connection_id);
+        }
+        if self.shutdown.is_stopping() {
+            return HandlerResponse::rejected(ProtocolError::new(
+                ProtocolError::UNAVAILABLE,
+                "Server is shutting down",
+            ));
+        }
+
+        let tenant = self.tenants.tenant_of(connection_id);
+        if let Some(tenant) = &tenant {
+            if let Err(e) = self.tenants.check_rate(tenant) {
+                return HandlerResponse::rejected(tenant_error(e));
+            }
+        }
+        if let Err(e) = self.rate_limiter.check(connection_id, method) {
+            return rate_limit_error(e);
+        }
+        if let Err(e) = self.retired_methods.check(method) {
+            return HandlerResponse::error(e);
+        }
+
+        let usage_key = UsageKey::for_connection(tenant.as_ref(), connection_id);
+        if method != QUOTA_USAGE_METHOD {
+            // バイト数の上限がある場合のみペイロードのサイズを計算
+            let bytes = if self.usage.counts_bytes(&usage_key) {
+                serde_json::to_vec(&payload).map_or(0, |bytes| bytes.len() as u64)
+            } else {
+                0
+            };
+            if let Err(e) = self.usage.record(&usage_key, bytes) {
+                let reset_at_ms = e.reset_at_millis();
+                return HandlerResponse::rejected(quota_error(e))
+                    .with_metadata(QUOTA_RESET_METADATA_KEY, reset_at_ms.to_string());
+            }
+        }
+
+        let started = std::time::Instant::now();
+        let builtin = self.handle_builtin_request(connection_id, tenant.as_ref(), method, &payload);
+        let response = match builtin {
+            Some(Ok(response)) => HandlerResponse::ok(response),
+            Some(Err(e)) => HandlerResponse::error(e),
+            None => {
+                // 枠はハンドラーが終わるまで保持する
+                let _permit = match self.admission.admit(method).await {
+                    Ok(permit) => permit,
+                    Err(e) => return admission_error(e),
+                };
+                with_tenant(tenant.clone(), self.handle_call_response(method, payload))
+                    .instrument(tracing::debug_span!("unison.handler", rpc.method = %method))
+                    .await
+            }
+        };
+        let elapsed = started.elapsed();
+        self.record_call(method, elapsed, &response);
+        if let Some(violation) = self.slo.record(method, elapsed) {
+            self.publish_slo_violation(&violation);
+        }
+
+        if let Some(tenant) = &tenant {
+            self.tenants.record(tenant, response.is_ok());
+        }
+        response
+    }
+
+    /// クライアントが指定した期限の範囲で接続からのリクエストを処理
+    ///
+    /// 期限を過ぎたリクエストはハンドラーを実行せずに`DEADLINE_EXCEEDED`を返し、
+    /// 期限内であれば残り時間を実行期限としてハンドラーを実行します。
+    pub async fn handle_connection_request_with_deadline(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+        payload: Value,
+        deadline: Option<SystemTime>,
+    ) -> HandlerResponse {
+        let Some(deadline) = deadline else {
+            return self
+                .handle_connection_request(connection_id, method, payload)
+                .await;
+        };
+        match super::deadline::remaining(deadline) {
+            Some(remaining) => {
+                let request = self.handle_connection_request(connection_id, method, payload);
+                enforce_deadline(method, Some(remaining), request, &self.handler_metrics).await
+            }
+            None => {
+                tracing::debug!(
+                    "Skipping {}: the caller's deadline has already passed",
+                    method
+                );
+                HandlerResponse::rejected(
+                    ProtocolError::new(
+                        ProtocolError::DEADLINE_EXCEEDED,
+                        "Deadline exceeded before the handler was invoked",
+                    )
+                    .with_details(serde_json::json!({
+                        "deadline_ms": super::deadline::deadline_millis(deadline),
+                    })),
+                )
+            }
+        }
+    }
+
+    /// 接続から受信したリクエストのメッセージを処理
+    ///
+    /// [`handle_connection_request_with_deadline`](Self::handle_connection_request_with_deadline)と同じく
+    /// 処理し、型付きハンドラーにはメッセージID・メタデータ・期限を、
+    /// ハンドラーの[`RequestContext`]には接続の情報も渡します。
+    pub async fn handle_request_message(
+        &self,
+        connection_id: ConnectionId,
+        request: &ProtocolMessage,
+        deadline: Option<SystemTime>,
+    ) -> HandlerResponse {
+        self.handle_stream_request_message(connection_id, None, request, deadline)
+            .await
+    }
+
+    /// QUICのストリームで受信したリクエストのメッセージを処理
+    pub(crate) async fn handle_stream_request_message(
+        &self,
+        connection_id: ConnectionId,
+        stream_id: Option<u64>,
+        request: &ProtocolMessage,
+        deadline: Option<SystemTime>,
+    ) -> HandlerResponse {
+        if let Some(history) = &self.frame_history {
+            history.record(connection_id, FrameDirection::Inbound, request);
+        }
+        let (span, context) = trace::server_span(request);
+        let response = with_trace(
+            context,
+            self.handle_recorded_request(connection_id, stream_id, request, deadline),
+        )
+        .instrument(span)
+        .await;
+        if let Some(history) = &self.frame_history {
+            history.record_response(connection_id, request, &response);
+            if let Err(e) = &response.outcome
+                && history.config().log_on_error
+            {
+                let frames =
+                    serde_json::to_string(&history.frames(connection_id)).unwrap_or_default();
+                tracing::warn!(
+                    "Request {} ({}) on connection {} failed: {}; recent frames: {}",
+                    request.id,
+                    request.method,
+                    connection_id,
+                    e,
+                    frames
+                );
+            }
+        }
+        response
+    }
+
+    async fn handle_recorded_request(
+        &self,
+        connection_id: ConnectionId,
+        stream_id: Option<u64>,
+        request: &ProtocolMessage,
+        deadline: Option<SystemTime>,
+    ) -> HandlerResponse {
+        let invalid = |e: NetworkError| {
+            HandlerResponse::error(ProtocolError::new(
+                ProtocolError::INVALID_REQUEST,
+                e.to_string(),
+            ))
+        };
+        let encoding = match self.accepted_encoding(request).await {
+            Ok(encoding) => encoding,
+            Err(e) => return HandlerResponse::error(e),
+        };
+        let payload = match request.payload_as_value() {
+            Ok(payload) => payload,
+            Err(e) => return invalid(e),
+        };
+        let head = match RequestHead::from_message(request, deadline) {
+            Ok(head) => head,
+            Err(e) => return invalid(e),
+        };
+        let features = self
+            .capabilities(connection_id)
+            .map(|capabilities| capabilities.features)
+            .unwrap_or_default();
+        let head = head
+            .with_connection(connection_id, self.peer_address(connection_id), features)
+            .with_stream_id(stream_id);
+        let locale = self
+            .error_catalog
+            .as_ref()
+            .and_then(|_| self.request_locale(connection_id, &head));
+        let idempotency_key = head
+            .metadata(IDEMPOTENCY_KEY_METADATA_KEY)
+            .map(str::to_string);
+        let idempotent = idempotency_key.as_deref().map(|key| IdempotentRequest {
+            scope: self.dedup_scope(connection_id),
+            key,
+            payload_hash: payload_hash(&request.payload),
+        });
+        let response = self.dedup.run(
+            &request.method,
+            idempotent,
+            self.handle_connection_request_with_deadline(
+                connection_id,
+                &request.method,
+                payload,
+                deadline,
+            ),
+        );
+        let mut response = with_request_head(head, response).await;
+        if encoding != PayloadEncoding::Json {
+            response
+                .metadata
+                .insert(CONTENT_TYPE_METADATA_KEY.to_string(), encoding.to_string());
+        }
+        if let Some(catalog) = &self.error_catalog {
+            response.outcome = response
+                .outcome
+                .map_err(|e| catalog.localize(e, locale.as_deref()));
+        }
+        let bytes_out = response.outcome.as_ref().map_or(0, |payload| {
+            serde_json::to_vec(payload).map_or(0, |bytes| bytes.len() as u64)
+        });
+        self.stats
+            .record_bytes(&request.method, request.payload.len() as u64, bytes_out);
+        #[cfg(feature = "metrics")]
+        self.metrics
+            .record_bytes(&request.method, bytes_out, request.payload.len() as u64);
+        response
+    }
+
+    /// 冪等キーを共有する範囲（テナントと認証された相手）
+    ///
+    /// 相手はmTLSのクライアント証明書、なければハンドシェイクで送られた
+    /// [`IDEMPOTENCY_CREDENTIAL_METADATA_KEY`]の値で区別し、どちらもなければ接続ごとに区別します。
+    fn dedup_scope(&self, connection_id: ConnectionId) -> String {
+        let tenant = self.tenants.tenant_of(connection_id);
+        let credential = self.capabilities(connection_id).and_then(|capabilities| {
+            capabilities
+                .metadata
+                .get(IDEMPOTENCY_CREDENTIAL_METADATA_KEY)
+                .filter(|credential| !credential.is_empty())
+                .map(|credential| credential_scope(credential))
+        });
+        let peer = match (self.peer_certificate(connection_id), credential) {
+            (Some(pin), _) => format!("certificate:{}", pin),
+            (None, Some(credential)) => credential,
+            (None, None) => format!("connection:{}", connection_id),
+        };
+        format!("{}/{}", tenant.as_ref().map_or("", TenantId::as_str), peer)
+    }
+
+    /// 記録したフレームを接続IDごとに返す（`connection_id`を指定した場合はその接続のみ）
+    ///
+    /// 許可されていない接続には、呼び出した接続の記録のみを返します。
+    fn frame_history_response(
+        &self,
+        connection_id: ConnectionId,
+        payload: &Value,
+    ) -> Result<Value, ProtocolError> {
+        let Some(history) = &self.frame_history else {
+            return Err(ProtocolError::new(
+                ProtocolError::UNAVAILABLE,
+                "Frame history is not enabled",
+            ));
+        };
+        let requested = payload.get("connection_id").and_then(Value::as_u64);
+        let authorized = self
+            .frame_history_authorizer
+            .as_ref()
+            .is_some_and(|authorize| {
+                authorize(connection_id, self.capabilities(connection_id).as_ref())
+            });
+        let mut frames = if authorized {
+            history.dump()
+        } else {
+            if requested.is_some_and(|id| id != connection_id) {
+                return Err(ProtocolError::new(
+                    ProtocolError::PERMISSION_DENIED,
+                    "Frame history of other connections is not available to this connection",
+                ));
+            }
+            HashMap::from([(connection_id, history.frames(connection_id))])
+        };
+        if let Some(id) = requested {
+            frames.retain(|connection_id, _| *connection_id == id);
+        }
+        serde_json::to_value(frames).map_err(|e| ProtocolError::internal(e.to_string()))
+    }
+
+    /// リクエスト、なければ接続のハンドシェイクで指定されたロケール
+    fn request_locale(&self, connection_id: ConnectionId, head: &RequestHead) -> Option<String> {
+        match head.metadata(LOCALE_METADATA_KEY) {
+            Some(locale) => Some(locale.to_string()),
+            None => self
+                .capabilities(connection_id)
+                .and_then(|capabilities| capabilities.locale().map(str::to_string)),
+        }
+    }
+
+    /// 購読・プレゼンス・利用量などの組み込みメソッドを処理
+    ///
+    /// 組み込みメソッドでなければ`None`を返します。
+    fn handle_builtin_request(
+        &self,
+        connection_id: ConnectionId,
+        tenant: Option<&TenantId>,
+        method: &str,
+        payload: &Value,
+    ) -> Option<Result<Value, ProtocolError>> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            ProtocolError::new(ProtocolError::INVALID_REQUEST, e.to_string())
+        };
+
+        // テナントに紐づく接続のトピックはテナントの名前空間へ変換
+        let mut payload = payload.clone();
+        if let (Some(tenant), Some(Value::String(topic))) = (tenant, payload.get_mut("topic")) {
+            *topic = tenant.scope_topic(topic);
+        }
+
+        if let Some(result) = self
+            .pubsub
+            .handle_request(connection_id, method, payload.clone())
+        {
+            let reply = match result {
+                Ok(reply) => reply,
+                Err(e @ PubSubError::DurableCredentialMismatch(_)) => {
+                    return Some(Err(ProtocolError::new(
+                        ProtocolError::PERMISSION_DENIED,
+                        e.to_string(),
+                    )
+                    .with_details(
+                        serde_json::json!({ "reason": "durable_credential_mismatch" }),
+                    )));
+                }
+                Err(e) => return Some(Err(invalid(&e))),
+            };
+            // 永続購読の再送分を購読元の接続へ順に送る
+            for retained in reply.replay {
+                let topic = match tenant {
+                    Some(tenant) => tenant
+                        .unscope_topic(&retained.topic)
+                        .unwrap_or(&retained.topic)
+                        .to_string(),
+                    None => retained.topic,
+                };
+                let frame = ProtocolMessage::new_with_json(
+                    retained.offset,
+                    topic,
+                    MessageType::Event,
+                    retained.payload,
+                )
+                .and_then(|message| Ok(message.into_frame()?.to_bytes()));
+                match frame {
+                    Ok(frame) => {
+                        self.connections.broadcast_frame_to(&[connection_id], frame);
+                    }
+                    Err(e) => return Some(Err(ProtocolError::internal(e.to_string()))),
+                }
+            }
+            return Some(Ok(reply.response));
+        }
+        if method == STATS_METHOD {
+            // サーバー全体の情報のため、テナントに紐づく接続からは使用できない
+            if tenant.is_some() {
+                return Some(Err(ProtocolError::new(
+                    ProtocolError::PERMISSION_DENIED,
+                    "Stats are not available to tenant-scoped connections",
+                )));
+            }
+            return Some(serde_json::to_value(self.stats()).map_err(|e| invalid(&e)));
+        }
+        if method == FRAME_HISTORY_METHOD {
+            if tenant.is_some() {
+                return Some(Err(ProtocolError::new(
+                    ProtocolError::PERMISSION_DENIED,
+                    "Frame history is not available to tenant-scoped connections",
+                )));
+            }
+            return Some(self.frame_history_response(connection_id, &payload));
+        }
+        if method == SERVICES_METHOD {
+            if tenant.is_some() {
+                return Some(Err(ProtocolError::new(
+                    ProtocolError::PERMISSION_DENIED,
+                    "Service states are not available to tenant-scoped connections",
+                )));
+            }
+            return Some(serde_json::to_value(self.lifecycle.statuses()).map_err(|e| invalid(&e)));
+        }
+        if method == ALLOC_STATS_METHOD {
+            if tenant.is_some() {
+                return Some(Err(ProtocolError::new(
+                    ProtocolError::PERMISSION_DENIED,
+                    "Allocation stats are not available to tenant-scoped connections",
+                )));
+            }
+            return Some(serde_json::to_value(allocation::snapshot()).map_err(|e| invalid(&e)));
+        }
+        if method == QUOTA_USAGE_METHOD {
+            let key = UsageKey::for_connection(tenant, connection_id);
+            let usage = self.usage.usage(&key);
+            return Some(serde_json::to_value(usage).map_err(|e| invalid(&e)));
+        }
+        if tenant.is_some() && matches!(method, PRESENCE_SET_METHOD | PRESENCE_QUERY_METHOD) {
+            // プレゼンスはテナント間で共有されるため、テナントに紐づく接続からは使用できない
+            return Some(Err(ProtocolError::new(
+                ProtocolError::PERMISSION_DENIED,
+                "Presence is not available to tenant-scoped connections",
+            )));
+        }
+        if let Some(result) = self.presence.handle_request(connection_id, method, payload) {
+            let (response, change) = match result {
+                Ok(reply) => reply,
+                Err(e) => return Some(Err(invalid(&e))),
+            };
+            if let Some(state) = change {
+                self.publish_presence(state);
+            }
+            return Some(Ok(response));
+        }
+        None
+    }
+
+    /// ハンドラーのパニック・実行期限超過のメトリクス
+    pub fn handler_metrics(&self) -> &HandlerMetrics {
+        &self.handler_metrics
+    }
+
+    /// ハンドラーの実行期限の既定値を指定（`None`で無制限）
+    pub fn with_handler_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.handler_timeout = timeout;
+        self
+    }
+
+    /// メソッドの実行期限を指定（既定値より優先）
+    pub async fn set_method_timeout(&self, method: &str, timeout: Duration) {
+        self.method_timeouts
+            .write()
+            .await
+            .insert(method.to_string(), timeout);
+    }
+
+    /// スキーマの`timeout_ms`で指定されたメソッドの実行期限を適用
+    pub async fn apply_schema_timeouts(&self, schema: &crate::parser::ParsedSchema) {
+        let Some(protocol) = &schema.protocol else {
+            return;
+        };
+        for method in protocol.services.iter().flat_map(|s| &s.methods) {
+            if let Some(timeout) = method.timeout() {
+                self.set_method_timeout(&method.name, timeout).await;
+            }
+        }
+    }
+
+    /// メソッドが受け付けるペイロードのエンコーディングを優先順に指定
+    pub async fn set_method_encodings(&self, method: &str, encodings: Vec<PayloadEncoding>) {
+        self.method_encodings
+            .write()
+            .await
+            .insert(method.to_string(), encodings);
+    }
+
+    /// スキーマの`encodings`で指定されたメソッドのエンコーディングを適用
+    ///
+    /// 対応していないエンコーディングの名前があればエラーを返します。
+    pub async fn apply_schema_encodings(
+        &self,
+        schema: &crate::parser::ParsedSchema,
+    ) -> Result<(), ContentError> {
+        let Some(protocol) = &schema.protocol else {
+            return Ok(());
+        };
+        for method in protocol.services.iter().flat_map(|s| &s.methods) {
+            if !method.encodings.is_empty() {
+                let encodings = method_encodings(method)?;
+                self.set_method_encodings(&method.name, encodings).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// リクエストのエンコーディングをメソッドが受け付けるか確認
+    async fn accepted_encoding(
+        &self,
+        request: &ProtocolMessage,
+    ) -> Result<PayloadEncoding, ProtocolError> {
+        let encoding = request.encoding().map_err(ProtocolError::from)?;
+        let encodings = self.method_encodings.read().await;
+        let accepted = encodings
+            .get(&request.method)
+            .map_or(&[PayloadEncoding::Json][..], Vec::as_slice);
+        if accepted.contains(&encoding) {
+            return Ok(encoding);
+        }
+        let names: Vec<&str> = accepted.iter().map(|e| e.name()).collect();
+        let error = ContentError::NotAccepted {
+            method: request.method.clone(),
+            encoding,
+            accepted: names.join(", "),
+        };
+        Err(
+            ProtocolError::new(ProtocolError::UNSUPPORTED_ENCODING, error.to_string())
+                .with_details(serde_json::json!({ "encoding": encoding, "accepted": names })),
+        )
+    }
+
+    /// スキーマで定義された各サービスに`<service>.ping`・`<service>.describe`を登録
+    ///
+    /// 同じ名前のハンドラーが登録済みの場合はそちらを優先します。
+    pub fn with_schema_services(self, schema: &crate::parser::ParsedSchema) -> Self {
+        {
+            let mut handlers = self.call_handlers.write().unwrap();
+            for (method, response) in introspection::schema_methods(schema) {
+                handlers.entry(method).or_insert_with(|| {
+                    Arc::new(move |_payload: Value| {
+                        let response = HandlerResponse::ok(response.clone());
+                        Box::pin(async move { response })
+                            as Pin<Box<dyn futures_util::Future<Output = HandlerResponse> + Send>>
+                    })
+                });
+            }
+        }
+        self
+    }
+
+    /// アセットを配信するメソッド（`<prefix>.stat`・`<prefix>.list`・`<prefix>.get`）を登録
+    pub fn with_assets(self, assets: AssetService) -> Self {
+        let assets = Arc::new(assets);
+        let (stat, list, get) = (
+            Arc::clone(&assets),
+            Arc::clone(&assets),
+            Arc::clone(&assets),
+        );
+        let server = self
+            .with_call_handler(&assets.method("stat"), move |payload| {
+                let response = stat.stat(payload);
+                async move { response }
+            })
+            .with_call_handler(&assets.method("list"), move |_| {
+                let response = list.list();
+                async move { response }
+            });
+        server.insert_stream_handler(&assets.method("get"), move |payload| {
+            let stream = get.open(payload);
+            async move { stream }
+        });
+        server
+    }
+
+    /// 型付きハンドラーのリクエストをスキーマで検証
+    ///
+    /// スキーマの`pattern`が不正な場合や、`validator`を指定したフィールドがある場合は
+    /// エラーを返します。独自の検証関数を使う場合は[`Self::with_schema_validator`]を使用してください。
+    pub fn with_schema_validation(self, schema: &ParsedSchema) -> Result<Self, ValidationError> {
+        self.with_schema_validator(SchemaValidator::new(schema)?)
+    }
+
+    /// 検証関数を登録済みの検証器で型付きハンドラーのリクエストを検証
+    ///
+    /// スキーマで参照している検証関数が登録されていない場合はエラーを返します。
+    pub fn with_schema_validator(
+        self,
+        validator: SchemaValidator,
+    ) -> Result<Self, ValidationError> {
+        if let Some(name) = validator.missing_validators().into_iter().next() {
+            return Err(ValidationError::UnknownValidator { name });
+        }
+        *self.schema_validator.write().unwrap() = Some(Arc::new(validator));
+        Ok(self)
+    }
+
+    /// リクエストの検証に使うスキーマの検証器
+    pub fn schema_validator(&self) -> Option<Arc<SchemaValidator>> {
+        self.schema_validator.read().unwrap().clone()
+    }
+
+    /// 稼働中のサーバーのスキーマを読み込み直す
+    ///
+    /// KDLを解析し、検証の規則（検証を有効にしている場合）・実行期限・エンコーディングと
+    /// ハンドシェイクで照合するスキーマを置き換えます。削除されたメソッドの呼び出しは
+    /// ハンドラーが登録されていても`NOT_FOUND`で拒否し、指紋が変わった場合は接続中の
+    /// クライアントへ[`SCHEMA_CHANGED_EVENT_METHOD`]を送ります。
+    ///
+    /// 登録済みの検証関数は引き継ぎます。エラーの場合、スキーマは変わりません。
+    pub async fn reload_schema(&self, kdl: &str) -> Result<SchemaChange, SchemaReloadError> {
+        let schema = crate::parser::SchemaParser::new()
+            .parse(kdl)
+            .map_err(|e| SchemaReloadError::Parse(format!("{:#}", e)))?;
+
+        // 置き換える前に、新しいスキーマで失敗しうるものをすべて確認する
+        let validator = match self.schema_validator() {
+            Some(current) => {
+                let mut validator = SchemaValidator::new(&schema)?;
+                validator.inherit_validators(&current);
+                if let Some(name) = validator.missing_validators().into_iter().next() {
+                    return Err(ValidationError::UnknownValidator { name }.into());
+                }
+                Some(Arc::new(validator))
+            }
+            None => None,
+        };
+        let methods = schema.protocol.iter().flat_map(|p| &p.services);
+        for method in methods.flat_map(|s| &s.methods) {
+            method_encodings(method)?;
+        }
+
+        let current = SchemaIdentity::from_schema(&schema);
+        let version = schema.protocol.as_ref().map(|p| p.version.clone());
+        let change = {
+            let mut identity = self.schema.write().unwrap();
+            let change = SchemaChange::between(identity.as_ref(), &current, version);
+            *identity = Some(current);
+            change
+        };
+        if validator.is_some() {
+            *self.schema_validator.write().unwrap() = validator;
+        }
+        {
+            let mut timeouts = self.method_timeouts.write().await;
+            let mut encodings = self.method_encodings.write().await;
+            for method in &change.removed_methods {
+                timeouts.remove(method);
+                encodings.remove(method);
+            }
+        }
+        self.apply_schema_timeouts(&schema).await;
+        self.apply_schema_encodings(&schema).await?;
+        self.retired_methods.apply(&change);
+
+        if change.is_changed() {
+            tracing::info!(
+                "Reloaded schema {} (added: {:?}, removed: {:?})",
+                change.fingerprint,
+                change.added_methods,
+                change.removed_methods
+            );
+            self.events
+                .emit(|timestamp_ms| ServerEvent::SchemaReloaded {
+                    previous: change.previous.clone(),
+                    fingerprint: change.fingerprint.clone(),
+                    removed_methods: change.removed_methods.clone(),
+                    timestamp_ms,
+                });
+            let payload = serde_json::to_value(&change).unwrap_or_default();
+            if let Err(e) = self.broadcast(SCHEMA_CHANGED_EVENT_METHOD, payload) {
+                tracing::warn!("Failed to announce the schema change: {}", e);
+            }
+        }
+        Ok(change)
+    }
+
+    async fn method_timeout(&self, method: &str) -> Option<Duration> {
+        match self.method_timeouts.read().await.get(method) {
+            Some(timeout) => Some(*timeout),
+            None => self.handler_timeout,
+        }
+    }
+
+    /// 登録済みハンドラーでリクエストを処理し、メタデータを含むレスポンスを返す
+    ///
+    /// ハンドラーがパニックした場合は内部エラーとして返し、接続は維持されます。
+    /// 非同期ハンドラーが実行期限を超えた場合は中断して`DEADLINE_EXCEEDED`を返します。
+    pub async fn handle_call_response(&self, method: &str, payload: Value) -> HandlerResponse {
+        let on_panic = |correlation_id: &str| self.handler_panicked(method, correlation_id);
+
+        // まずunison_handlers（register_handlerで登録）を試行
+        let unison_handler = self.unison_handlers.read().unwrap().get(method).cloned();
+        if let Some(handler) = unison_handler {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let _alloc = allocation::enter(Subsystem::Handlers);
+                handler(payload)
+            }));
+            return match result {
+                Err(panic) => panic_response(method, panic.as_ref(), on_panic),
+                Ok(Ok(result)) => HandlerResponse::ok(result),
+                Ok(Err(e)) => {
+                    let mut error = ProtocolError::from(e);
+                    error.message = format!("Handler error: {}", error.message);
+                    HandlerResponse::error(error)
+                }
+            };
+        }
+        // call_handlersへフォールバック
+        let handler = self.call_handlers.read().unwrap().get(method).cloned();
+        match handler {
+            Some(handler) => {
+                // ハンドラーが同期的にパニックする場合も捕捉できるよう、呼び出しごとFutureに含める
+                let invocation = catch_handler_panic(
+                    method,
+                    in_subsystem(Subsystem::Handlers, async move { handler(payload).await }),
+                    on_panic,
+                );
+                let timeout = self.method_timeout(method).await;
+                enforce_deadline(method, timeout, invocation, &self.handler_metrics).await
+            }
+            None => HandlerResponse::error(ProtocolError::new(
+                ProtocolError::NOT_FOUND,
+                format!("Method not found: {}", method),
+            )),
+        }
+    }
+
+    /// ハンドラーのパニックを数え、`HandlerPanicked`を発行
+    fn handler_panicked(&self, method: &str, correlation_id: &str) {
+        report_panic(&self.handler_metrics, &self.events, method, correlation_id);
+    }
+
+    /// 接続の終了処理（`ConnectionClosed`を発行）
+    pub fn connection_closed(&self, connection_id: ConnectionId, reason: CloseReason) {
+        let remote = self.peer_address(connection_id);
+        self.events
+            .emit(|timestamp_ms| ServerEvent::ConnectionClosed {
+                connection_id,
+                remote,
+                reason,
+                timestamp_ms,
+            });
+        self.connections.unregister(connection_id);
+        self.pubsub.remove_connection(connection_id);
+        self.tenants.unbind(connection_id);
+        self.rate_limiter.remove_connection(connection_id);
+        self.usage.remove_peer(connection_id);
+        self.capabilities.write().unwrap().remove(&connection_id);
+        self.peer_addresses.write().unwrap().remove(&connection_id);
+        self.peer_certificates
+            .write()
+            .unwrap()
+            .remove(&connection_id);
+        self.connection_meters
+            .write()
+            .unwrap()
+            .remove(&connection_id);
+        self.stream_ids.remove_connection(connection_id);
+        if let Some(history) = &self.frame_history {
+            history.remove(connection_id);
+        }
+        for state in self.presence.disconnect(connection_id) {
+            self.publish_presence(state);
+        }
+    }
+
+    /// プレゼンスの設定を指定
+    pub fn with_presence_config(mut self, config: PresenceConfig) -> Self {
+        self.presence = Presence::new(config);
+        self
+    }
 
-/// プロトコルサーバー実装
-pub struct ProtocolServer {
-    call_handlers: Arc<RwLock<HashMap<String, CallHandler>>>,
-    stream_handlers: Arc<RwLock<HashMap<String, StreamHandler>>>,
-    unison_handlers: Arc<RwLock<HashMap<String, UnisonHandler>>>,
-    services: Arc<RwLock<HashMap<String, crate::network::service::UnisonService>>>,
-    running: Arc<RwLock<bool>>,
-}
+    /// プレゼンスのレジストリ
+    pub fn presence(&self) -> &Presence {
+        &self.presence
+    }
 
-impl ProtocolServer {
-    pub fn new() -> Self {
-        Self {
-            call_handlers: Arc::new(RwLock::new(HashMap::new())),
-            stream_handlers: Arc::new(RwLock::new(HashMap::new())),
-            unison_handlers: Arc::new(RwLock::new(HashMap::new())),
-            services: Arc::new(RwLock::new(HashMap::new())),
-            running: Arc::new(RwLock::new(false)),
+    /// タイムアウトしたプレゼンスをオフラインへ遷移させ、変化を発行
+    ///
+    /// [`UnisonServer::listen`]中は[`PresenceConfig::sweep_interval`]ごとに自動で呼ばれます。
+    pub fn sweep_presence(&self) -> usize {
+        let expired = self.presence.expire();
+        let count = expired.len();
+        for state in expired {
+            self.publish_presence(state);
+        }
+        count
+    }
+
+    fn publish_slo_violation(&self, violation: &SloViolation) {
+        let result = serde_json::to_value(violation)
+            .map_err(NetworkError::from)
+            .and_then(|payload| self.publish(SLO_VIOLATION_TOPIC, payload));
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to publish SLO violation for {}: {}",
+                violation.method,
+                e
+            );
+        }
+    }
+
+    fn publish_service_event(&self, event: &ServiceEvent) {
+        let result = serde_json::to_value(event)
+            .map_err(NetworkError::from)
+            .and_then(|payload| self.publish(SERVICE_EVENT_TOPIC, payload));
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to publish service event for {}: {}",
+                event.service,
+                e
+            );
         }
     }
 
+    fn publish_presence(&self, state: PresenceState) {
+        let topic = presence_topic(&state.id);
+        let result = serde_json::to_value(&state)
+            .map_err(NetworkError::from)
+            .and_then(|payload| self.publish(&topic, payload));
+        if let Err(e) = result {
+            tracing::warn!("Failed to publish presence change for {}: {}", state.id, e);
+        }
+    }
+
+    /// 再開可能ストリームの設定を指定
+    pub fn with_resume_config(mut self, config: ResumeConfig) -> Self {
+        self.resume_registry = ResumeRegistry::new(config);
+        self
+    }
+
     /// サーバーにサービスインスタンスを登録
+    ///
+    /// 監督せずに`Running`として登録します。終了したときに再起動する場合は
+    /// [`supervise_service`](Self::supervise_service)を使用してください。
     pub async fn register_service(&self, service: crate::network::service::UnisonService) {
         let service_name = service.service_name().to_string();
         let mut services = self.services.write().await;
+        let _ = self
+            .lifecycle
+            .register(&service_name, &RestartPolicy::Never, false);
+        self.lifecycle
+            .transition(&service_name, ServiceState::Running, None);
         services.insert(service_name, service);
     }
 
@@ -76,6 +1832,16 @@ impl ProtocolServer {
         method: &str,
         payload: serde_json::Value,
     ) -> Result<serde_json::Value> {
+        match self.lifecycle.state(service_name) {
+            None | Some(ServiceState::Running) => {}
+            Some(state) => {
+                return Err(anyhow::anyhow!(
+                    "Service {} is not running: {}",
+                    service_name,
+                    state
+                ));
+            }
+        }
         let mut services = self.services.write().await;
         if let Some(service) = services.get_mut(service_name) {
             service
@@ -88,22 +1854,226 @@ impl ProtocolServer {
     }
 
     /// 呼び出しハンドラーを登録
-    pub async fn register_call_handler<F, Fut>(&self, method: &str, handler: F)
+    ///
+    /// ハンドラーは[`HandlerResponse`]（または`Result<Value>`など変換可能な型）を返し、
+    /// 型付きのエラーやレスポンスのメタデータ、キャッシュ制御のヒントを返せます。
+    pub async fn register_call_handler<F, Fut, R>(&self, method: &str, handler: F)
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        self.register_call_handler_with_options(method, HandlerOptions::default(), handler)
+            .await;
+    }
+
+    /// 実行期限などのオプションを指定して呼び出しハンドラーを登録
+    pub async fn register_call_handler_with_options<F, Fut, R>(
+        &self,
+        method: &str,
+        options: HandlerOptions,
+        handler: F,
+    ) where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        if let Some(timeout) = options.timeout {
+            self.set_method_timeout(method, timeout).await;
+        }
+
+        self.insert_call_handler(method, handler);
+    }
+
+    /// 呼び出しハンドラーを登録したサーバーを返す（`listen`前の構築用）
+    ///
+    /// [`register_call_handler`](Self::register_call_handler)と同じく、ハンドラーは
+    /// `Result<Value, _>`や[`HandlerResponse`]を返す非同期関数です。
+    pub fn with_call_handler<F, Fut, R>(self, method: &str, handler: F) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        self.insert_call_handler(method, handler);
+        self
+    }
+
+    /// 非同期の呼び出しハンドラーをその場で登録
+    fn insert_call_handler<F, Fut, R>(&self, method: &str, handler: F)
     where
         F: Fn(Value) -> Fut + Send + Sync + 'static,
-        Fut: futures_util::Future<Output = Result<Value>> + Send + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        let handler: CallHandler = Arc::new(move |value: Value| {
+            let response = handler(value);
+            Box::pin(async move { response.await.into() })
+                as Pin<Box<dyn futures_util::Future<Output = HandlerResponse> + Send>>
+        });
+        self.call_handlers
+            .write()
+            .unwrap()
+            .insert(method.to_string(), handler);
+    }
+
+    /// 呼び出しの情報（[`RequestContext`]）とペイロードを受け取るハンドラーを登録
+    ///
+    /// 接続・ストリーム・取り決めた機能・メタデータ・期限を参照できます。
+    /// 戻り値は[`register_call_handler`](Self::register_call_handler)と同じです。
+    pub async fn register_context_handler<F, Fut, R>(&self, method: &str, handler: F)
+    where
+        F: Fn(RequestContext, Value) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        self.insert_context_handler(method, handler);
+    }
+
+    /// 呼び出しの情報を受け取るハンドラーを登録したサーバーを返す（`listen`前の構築用）
+    pub fn with_context_handler<F, Fut, R>(self, method: &str, handler: F) -> Self
+    where
+        F: Fn(RequestContext, Value) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        self.insert_context_handler(method, handler);
+        self
+    }
+
+    /// 処理中のリクエストの情報を渡す`Fn(Value)`のハンドラーとして登録
+    fn insert_context_handler<F, Fut, R>(&self, method: &str, handler: F)
+    where
+        F: Fn(RequestContext, Value) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        let method_name = method.to_string();
+        self.insert_call_handler(method, move |payload| {
+            handler(RequestContext::current(&method_name), payload)
+        });
+    }
+
+    /// 型付きのリクエストを受け取るハンドラーを登録
+    ///
+    /// ハンドラーは本体を`Req`に変換した[`Request`]（メッセージID・メタデータ・期限を含む）を受け取り、
+    /// [`Response`]のメタデータはレスポンスのメタデータとして返されます。
+    /// スキーマ検証が有効な場合は変換前に検証し、失敗すると`INVALID_REQUEST`を返します。
+    pub async fn register_request_handler<Req, Res, E, F, Fut>(&self, method: &str, handler: F)
+    where
+        Req: DeserializeOwned + Send + 'static,
+        Res: Serialize + Send + 'static,
+        E: Into<ProtocolError> + Send + 'static,
+        F: Fn(Request<Req>) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = Result<Response<Res>, E>> + Send + 'static,
+    {
+        self.insert_request_handler(method, handler);
+    }
+
+    /// 型付きのリクエストを受け取るハンドラーを登録したサーバーを返す（`listen`前の構築用）
+    pub fn with_request_handler<Req, Res, E, F, Fut>(self, method: &str, handler: F) -> Self
+    where
+        Req: DeserializeOwned + Send + 'static,
+        Res: Serialize + Send + 'static,
+        E: Into<ProtocolError> + Send + 'static,
+        F: Fn(Request<Req>) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = Result<Response<Res>, E>> + Send + 'static,
+    {
+        self.insert_request_handler(method, handler);
+        self
+    }
+
+    /// `tower::Service`をハンドラーとして登録したサーバーを返す（`tower`フィーチャー）
+    ///
+    /// towerのミドルウェアを重ねたサービスを、型付きハンドラーと同じように呼び出します。
+    /// サービスが返したエラーは[`tower_error`](super::tower_adapter::tower_error)で変換します。
+    #[cfg(feature = "tower")]
+    pub fn with_tower_service<S, Req, Res>(self, method: &str, service: S) -> Self
+    where
+        S: tower::Service<Request<Req>, Response = Response<Res>> + Clone + Send + 'static,
+        S::Error: Into<tower::BoxError>,
+        S::Future: Send + 'static,
+        Req: DeserializeOwned + Send + 'static,
+        Res: Serialize + Send + 'static,
+    {
+        self.insert_request_handler(method, super::tower_adapter::request_handler(service));
+        self
+    }
+
+    /// `tower::Service`をハンドラーとして登録（`tower`フィーチャー）
+    #[cfg(feature = "tower")]
+    pub async fn register_tower_service<S, Req, Res>(&self, method: &str, service: S)
+    where
+        S: tower::Service<Request<Req>, Response = Response<Res>> + Clone + Send + 'static,
+        S::Error: Into<tower::BoxError>,
+        S::Future: Send + 'static,
+        Req: DeserializeOwned + Send + 'static,
+        Res: Serialize + Send + 'static,
+    {
+        self.insert_request_handler(method, super::tower_adapter::request_handler(service));
+    }
+
+    fn insert_request_handler<Req, Res, E, F, Fut>(&self, method: &str, handler: F)
+    where
+        Req: DeserializeOwned + Send + 'static,
+        Res: Serialize + Send + 'static,
+        E: Into<ProtocolError> + Send + 'static,
+        F: Fn(Request<Req>) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = Result<Response<Res>, E>> + Send + 'static,
     {
-        let handler = Arc::new(move |value: Value| {
-            Box::pin(handler(value))
-                as Pin<Box<dyn futures_util::Future<Output = Result<Value>> + Send>>
+        let handler = Arc::new(handler);
+        let validator = Arc::clone(&self.schema_validator);
+        let method_name = method.to_string();
+        let call_handler: CallHandler = Arc::new(move |payload: Value| {
+            let handler = Arc::clone(&handler);
+            let validator = validator.read().unwrap().clone();
+            let method = method_name.clone();
+            Box::pin(async move {
+                if let Some(validator) = validator
+                    && let Err(e) = validator.validate_request(&method, &payload)
+                {
+                    return HandlerResponse::error(
+                        ProtocolError::new(ProtocolError::INVALID_REQUEST, e.to_string())
+                            .with_details(e.details()),
+                    );
+                }
+                let body: Req = match serde_json::from_value(payload) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        return HandlerResponse::error(ProtocolError::new(
+                            ProtocolError::INVALID_REQUEST,
+                            format!("Invalid request: {}", e),
+                        ));
+                    }
+                };
+                match handler(current_request(&method, body)).await {
+                    Ok(response) => response.into_handler_response(),
+                    Err(e) => HandlerResponse::error(e.into()),
+                }
+            }) as Pin<Box<dyn futures_util::Future<Output = HandlerResponse> + Send>>
         });
 
-        let mut handlers = self.call_handlers.write().await;
-        handlers.insert(method.to_string(), handler);
+        self.call_handlers
+            .write()
+            .unwrap()
+            .insert(method.to_string(), call_handler);
     }
 
     /// ストリームハンドラーを登録
+    ///
+    /// ハンドラーが返すストリームの各アイテムは`StreamData`としてクライアントへ送信され、
+    /// 終了時に`StreamEnd`、エラー時に`StreamError`を送信してストリームを閉じます。
     pub async fn register_stream_handler<F, Fut, S>(&self, method: &str, handler: F)
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = Result<S>> + Send + 'static,
+        S: Stream<Item = Result<Value>> + Send + 'static,
+    {
+        self.insert_stream_handler(method, handler);
+    }
+
+    /// ストリームハンドラーをその場で登録
+    fn insert_stream_handler<F, Fut, S>(&self, method: &str, handler: F)
     where
         F: Fn(Value) -> Fut + Send + Sync + 'static,
         Fut: futures_util::Future<Output = Result<S>> + Send + 'static,
@@ -125,37 +2095,455 @@ impl ProtocolServer {
                 >
         });
 
-        let mut handlers = self.stream_handlers.write().await;
-        handlers.insert(method.to_string(), wrapped_handler);
+        self.stream_handlers
+            .write()
+            .unwrap()
+            .insert(method.to_string(), wrapped_handler);
+    }
+
+    /// クライアントストリームハンドラーを登録
+    ///
+    /// ハンドラーはクライアントが送るリクエストの列（[`ClientStreamRequests`]）を受け取り、
+    /// 1つのレスポンスを返します。呼び出しハンドラーと同じく[`HandlerResponse`]に
+    /// 変換できる型を返せます。
+    pub async fn register_client_stream_handler<F, Fut, R>(&self, method: &str, handler: F)
+    where
+        F: Fn(ClientStreamRequests) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        self.insert_client_stream_handler(method, handler);
+    }
+
+    /// クライアントストリームハンドラーを登録したサーバーを返す（`listen`前の構築用）
+    pub fn with_client_stream_handler<F, Fut, R>(self, method: &str, handler: F) -> Self
+    where
+        F: Fn(ClientStreamRequests) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        self.insert_client_stream_handler(method, handler);
+        self
+    }
+
+    /// クライアントストリームハンドラーをその場で登録
+    fn insert_client_stream_handler<F, Fut, R>(&self, method: &str, handler: F)
+    where
+        F: Fn(ClientStreamRequests) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = R> + Send + 'static,
+        R: Into<HandlerResponse>,
+    {
+        let handler: ClientStreamHandler = Arc::new(move |requests| {
+            let response = handler(requests);
+            Box::pin(async move { response.await.into() })
+                as Pin<Box<dyn futures_util::Future<Output = HandlerResponse> + Send>>
+        });
+        self.client_stream_handlers
+            .write()
+            .unwrap()
+            .insert(method.to_string(), handler);
+    }
+
+    /// 接続からのクライアントストリームを処理
+    ///
+    /// 停止中の拒否、レート制限、実行期限、パニックの捕捉とSLOの集計は
+    /// 単項の呼び出しと同じく適用します。
+    pub async fn handle_client_stream(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+        requests: ClientStreamRequests,
+    ) -> HandlerResponse {
+        if self.shutdown.is_stopping() {
+            return HandlerResponse::error(ProtocolError::new(
+                ProtocolError::UNAVAILABLE,
+                "Server is shutting down",
+            ));
+        }
+
+        let tenant = self.tenants.tenant_of(connection_id);
+        if let Some(tenant) = &tenant {
+            if let Err(e) = self.tenants.check_rate(tenant) {
+                return HandlerResponse::error(tenant_error(e));
+            }
+        }
+        if let Err(e) = self.rate_limiter.check(connection_id, method) {
+            return rate_limit_error(e);
+        }
+        if let Err(e) = self.retired_methods.check(method) {
+            return HandlerResponse::error(e);
+        }
+
+        let handler = self
+            .client_stream_handlers
+            .read()
+            .unwrap()
+            .get(method)
+            .cloned();
+        let Some(handler) = handler else {
+            return HandlerResponse::error(ProtocolError::new(
+                ProtocolError::NOT_FOUND,
+                format!("Client stream method not found: {}", method),
+            ));
+        };
+        let _permit = match self.admission.admit(method).await {
+            Ok(permit) => permit,
+            Err(e) => return admission_error(e),
+        };
+
+        let _open = self.streams.open(method);
+        let mut record = self.stats.open_stream(method);
+        let started = std::time::Instant::now();
+        let on_panic = |correlation_id: &str| self.handler_panicked(method, correlation_id);
+        let invocation =
+            catch_handler_panic(method, async move { handler(requests).await }, on_panic);
+        let timeout = self.method_timeout(method).await;
+        let response = with_tenant(
+            tenant.clone(),
+            enforce_deadline(method, timeout, invocation, &self.handler_metrics),
+        )
+        .await;
+        let elapsed = started.elapsed();
+        self.record_call(method, elapsed, &response);
+        if !response.is_ok() {
+            record.record_error();
+        }
+        if let Some(violation) = self.slo.record(method, elapsed) {
+            self.publish_slo_violation(&violation);
+        }
+
+        if let Some(tenant) = &tenant {
+            self.tenants.record(tenant, response.is_ok());
+        }
+        response
+    }
+
+    /// 双方向ストリームハンドラーを登録したサーバーを返す（`listen`前の構築用）
+    pub fn with_system_stream_handler<F>(self, method: &str, handler: F) -> Self
+    where
+        F: Fn(
+                Value,
+                crate::network::quic::UnisonStream,
+            )
+                -> Pin<Box<dyn futures_util::Future<Output = Result<(), NetworkError>> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.insert_system_stream_handler(method, handler);
+        self
+    }
+
+    /// 双方向ストリームハンドラーをその場で登録
+    fn insert_system_stream_handler<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(
+                Value,
+                crate::network::quic::UnisonStream,
+            )
+                -> Pin<Box<dyn futures_util::Future<Output = Result<(), NetworkError>> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.system_stream_handlers
+            .write()
+            .unwrap()
+            .insert(method.to_string(), Arc::new(handler));
+    }
+
+    /// 接続からの双方向ストリームを処理
+    ///
+    /// 最初のメッセージのペイロードと開いたストリームをハンドラーに渡し、ハンドラーが
+    /// 終わるまで待ちます。双方向ストリームは長く開いたままになるため実行期限は適用せず、
+    /// 停止中の拒否、レート制限、アドミッション制御とパニックの捕捉だけを適用します。
+    pub async fn handle_system_stream(
+        &self,
+        connection_id: ConnectionId,
+        method: &str,
+        payload: Value,
+        stream: crate::network::quic::UnisonStream,
+    ) -> HandlerResponse {
+        if self.shutdown.is_stopping() {
+            return HandlerResponse::error(ProtocolError::new(
+                ProtocolError::UNAVAILABLE,
+                "Server is shutting down",
+            ));
+        }
+
+        let tenant = self.tenants.tenant_of(connection_id);
+        if let Some(tenant) = &tenant {
+            if let Err(e) = self.tenants.check_rate(tenant) {
+                return HandlerResponse::error(tenant_error(e));
+            }
+        }
+        if let Err(e) = self.rate_limiter.check(connection_id, method) {
+            return rate_limit_error(e);
+        }
+        if let Err(e) = self.retired_methods.check(method) {
+            return HandlerResponse::error(e);
+        }
+
+        let handler = self
+            .system_stream_handlers
+            .read()
+            .unwrap()
+            .get(method)
+            .cloned();
+        let Some(handler) = handler else {
+            return HandlerResponse::error(ProtocolError::new(
+                ProtocolError::NOT_FOUND,
+                format!("System stream method not found: {}", method),
+            ));
+        };
+        // 枠はハンドラーが終わるまで保持する
+        let _permit = match self.admission.admit(method).await {
+            Ok(permit) => permit,
+            Err(e) => return admission_error(e),
+        };
+
+        let _open = self.streams.open(method);
+        let mut record = self.stats.open_stream(method);
+        let on_panic = |correlation_id: &str| self.handler_panicked(method, correlation_id);
+        let invocation = async move {
+            HandlerResponse::from(
+                handler(payload, stream)
+                    .await
+                    .map(|()| serde_json::Value::Null),
+            )
+        };
+        let response = with_tenant(
+            tenant.clone(),
+            catch_handler_panic(method, invocation, on_panic),
+        )
+        .await;
+        if !response.is_ok() {
+            record.record_error();
+        }
+
+        if let Some(tenant) = &tenant {
+            self.tenants.record(tenant, response.is_ok());
+        }
+        response
+    }
+
+    /// 再開可能なストリームハンドラーを登録
+    ///
+    /// ハンドラーはリクエストペイロードと再開オフセット（送信済みアイテム数）を受け取り、
+    /// そのオフセット以降のアイテムを生成するストリームを返します。
+    pub async fn register_resumable_stream_handler<F, Fut, S>(&self, method: &str, handler: F)
+    where
+        F: Fn(Value, u64) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = Result<S>> + Send + 'static,
+        S: Stream<Item = Result<Value>> + Send + 'static,
+    {
+        self.insert_resumable_stream_handler(method, handler);
+    }
+
+    /// 再開可能なストリームハンドラーをその場で登録
+    fn insert_resumable_stream_handler<F, Fut, S>(&self, method: &str, handler: F)
+    where
+        F: Fn(Value, u64) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = Result<S>> + Send + 'static,
+        S: Stream<Item = Result<Value>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let wrapped_handler = Arc::new(move |value: Value, offset: u64| {
+            let handler = Arc::clone(&handler);
+            Box::pin(async move {
+                let stream = handler(value, offset).await?;
+                Ok(Box::pin(stream) as Pin<Box<dyn Stream<Item = Result<Value>> + Send>>)
+            })
+                as Pin<
+                    Box<
+                        dyn futures_util::Future<
+                                Output = Result<Pin<Box<dyn Stream<Item = Result<Value>> + Send>>>,
+                            > + Send,
+                    >,
+                >
+        });
+
+        self.resumable_handlers
+            .write()
+            .unwrap()
+            .insert(method.to_string(), wrapped_handler);
+    }
+
+    /// ストリーム要求（`Stream` / `StreamResume`）を処理し、送出するイベントのストリームを返す
+    ///
+    /// 再開可能なハンドラーが登録されたメソッドでは、データに加えてレジュームトークンが
+    /// 定期的に送出されます。
+    ///
+    /// 停止中の拒否、テナント・接続ごとのレート制限、利用量の上限とアドミッション制御は
+    /// 単項の呼び出しと同じく適用し、拒否した場合は[`ProtocolError`]を返します。
+    /// アドミッション制御の枠は返したストリームを破棄するまで保持します。
+    ///
+    /// 返したストリームを破棄するまで、開いているストリームとして[`Self::streams`]に記録し、
+    /// 送出したアイテムを[`Self::stats`]のストリームの統計に数えます。
+    /// ハンドラーがパニックした場合は、相関ID付きの内部エラーを送出してストリームを終えます。
+    pub async fn open_stream(
+        &self,
+        connection_id: ConnectionId,
+        request: &ProtocolMessage,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>>> {
+        if self.shutdown.is_stopping() {
+            return Err(
+                ProtocolError::new(ProtocolError::UNAVAILABLE, "Server is shutting down").into(),
+            );
+        }
+
+        let method = request.method.as_str();
+        let tenant = self.tenants.tenant_of(connection_id);
+        if let Some(tenant) = &tenant {
+            self.tenants.check_rate(tenant).map_err(tenant_error)?;
+        }
+        self.rate_limiter
+            .check(connection_id, method)
+            .map_err(rate_limit_rejection)?;
+        let usage_key = UsageKey::for_connection(tenant.as_ref(), connection_id);
+        let bytes = if self.usage.counts_bytes(&usage_key) {
+            request.payload.len() as u64
+        } else {
+            0
+        };
+        self.usage.record(&usage_key, bytes).map_err(quota_error)?;
+        // 枠はストリームを破棄するまで保持する
+        let permit = self
+            .admission
+            .admit(method)
+            .await
+            .map_err(admission_rejection)?;
+
+        let open = self.streams.open(method);
+        let mut record = self.stats.open_stream(method);
+        let opened = AssertUnwindSafe(with_tenant(
+            tenant.clone(),
+            self.open_stream_events(request),
+        ))
+        .catch_unwind()
+        .await;
+        if let Some(tenant) = &tenant {
+            self.tenants.record(tenant, matches!(opened, Ok(Ok(_))));
+        }
+        let events = match opened {
+            Ok(Ok(events)) => events,
+            Ok(Err(e)) => {
+                record.record_error();
+                return Err(e);
+            }
+            Err(panic) => {
+                record.record_error();
+                let on_panic = |correlation_id: &str| self.handler_panicked(method, correlation_id);
+                return Err(panic_error(method, panic.as_ref(), on_panic).into());
+            }
+        };
+        // アイテムの生成中のパニックは内部エラーとして送り、ストリームを終える
+        let metrics = Arc::clone(&self.handler_metrics);
+        let server_events = self.events.clone();
+        let method = request.method.clone();
+        let events = AssertUnwindSafe(events)
+            .catch_unwind()
+            .map(move |event| match event {
+                Ok(event) => event,
+                Err(panic) => {
+                    let on_panic = |correlation_id: &str| {
+                        report_panic(&metrics, &server_events, &method, correlation_id)
+                    };
+                    Err(panic_error(&method, panic.as_ref(), on_panic).into())
+                }
+            });
+        Ok(Box::pin(events.map(move |event| {
+            let _open = &open;
+            let _permit = &permit;
+            match &event {
+                Ok(StreamEvent::Data(_)) => record.record_item(),
+                Err(_) => record.record_error(),
+                Ok(_) => {}
+            }
+            event
+        })))
+    }
+
+    async fn open_stream_events(
+        &self,
+        request: &ProtocolMessage,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>>> {
+        self.retired_methods.check(&request.method)?;
+        let payload = request
+            .payload_as_value()
+            .map_err(|e| anyhow::anyhow!("Failed to parse payload: {}", e))?;
+
+        match request.msg_type {
+            MessageType::Stream => {
+                let resumable = self
+                    .resumable_handlers
+                    .read()
+                    .unwrap()
+                    .get(&request.method)
+                    .cloned();
+                match resumable {
+                    Some(handler) => {
+                        let stream_id = self
+                            .resume_registry
+                            .register(&request.method, payload.clone());
+                        let inner = handler(payload, 0).await?;
+                        Ok(self
+                            .resume_registry
+                            .wrap(stream_id, request.method.clone(), 0, inner))
+                    }
+                    None => {
+                        let stream = self.handle_stream(&request.method, payload).await?;
+                        Ok(Box::pin(stream.map(|item| item.map(StreamEvent::Data))))
+                    }
+                }
+            }
+            MessageType::StreamResume => {
+                let token: ResumeToken = serde_json::from_value(payload)
+                    .map_err(|e| anyhow::anyhow!("Invalid resume token: {}", e))?;
+                let handler = self
+                    .resumable_handlers
+                    .read()
+                    .unwrap()
+                    .get(&token.method)
+                    .cloned()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Resumable stream method not found: {}", token.method)
+                    })?;
+
+                let original_payload = self.resume_registry.resume(&token)?;
+                tracing::debug!(
+                    "Resuming stream {} ({}) at sequence {}",
+                    token.stream_id,
+                    token.method,
+                    token.sequence
+                );
+                let inner = handler(original_payload, token.sequence).await?;
+                Ok(self
+                    .resume_registry
+                    .wrap(token.stream_id, token.method, token.sequence, inner))
+            }
+            other => Err(anyhow::anyhow!("Not a stream request: {:?}", other)),
+        }
     }
 
     /// 入力メッセージを処理
     pub async fn process_message(&self, message: ProtocolMessage) -> Result<ProtocolMessage> {
         match message.msg_type {
             MessageType::Request => {
-                let handlers = self.call_handlers.read().await;
-                if let Some(handler) = handlers.get(&message.method) {
+                let handler = self
+                    .call_handlers
+                    .read()
+                    .unwrap()
+                    .get(&message.method)
+                    .cloned();
+                if let Some(handler) = handler {
                     let payload_value = message
                         .payload_as_value()
                         .map_err(|e| anyhow::anyhow!("Failed to parse payload: {}", e))?;
-                    match handler(payload_value).await {
-                        Ok(response) => ProtocolMessage::new_with_json(
-                            message.id,
-                            message.method,
-                            MessageType::Response,
-                            response,
-                        )
-                        .map_err(|e| anyhow::anyhow!("Failed to create response: {}", e)),
-                        Err(e) => ProtocolMessage::new_with_json(
-                            message.id,
-                            message.method,
-                            MessageType::Error,
-                            serde_json::json!({
-                                "message": e.to_string(),
-                            }),
-                        )
-                        .map_err(|e| anyhow::anyhow!("Failed to create error response: {}", e)),
-                    }
+                    handler(payload_value)
+                        .await
+                        .into_message(message.id, message.method)
+                        .map_err(|e| anyhow::anyhow!("Failed to create response: {}", e))
                 } else {
                     ProtocolMessage::new_with_json(
                         message.id,
@@ -169,21 +2557,25 @@ impl ProtocolServer {
                 }
             }
             MessageType::Stream => {
-                // Stream handling would be more complex in a real implementation
-                // This is a simplified version
-                let handlers = self.stream_handlers.read().await;
-                if let Some(_handler) = handlers.get(&message.method) {
-                    // 実際の実装では：
-                    // 1. ストリームを開始
-                    // 2. 各アイテムに対してStreamDataメッセージを送信
-                    // 3. 完了時にStreamEndを送信
+                // ストリームは複数のメッセージを返すため、1つのレスポンスでは表せない
+                if self
+                    .stream_handlers
+                    .read()
+                    .unwrap()
+                    .contains_key(&message.method)
+                {
                     ProtocolMessage::new_with_json(
                         message.id,
-                        message.method,
-                        MessageType::StreamEnd,
-                        serde_json::json!({}),
+                        message.method.clone(),
+                        MessageType::Error,
+                        serde_json::json!({
+                            "message": format!(
+                                "Stream method {} must be served with open_stream",
+                                message.method
+                            ),
+                        }),
                     )
-                    .map_err(|e| anyhow::anyhow!("Failed to create stream end message: {}", e))
+                    .map_err(|e| anyhow::anyhow!("Failed to create error message: {}", e))
                 } else {
                     ProtocolMessage::new_with_json(
                         message.id,
@@ -215,36 +2607,129 @@ impl ProtocolServerTrait for ProtocolServer {
         method: &str,
         payload: serde_json::Value,
     ) -> Result<serde_json::Value> {
-        // まずunison_handlers（register_handlerで登録）を試行
-        let unison_handlers = self.unison_handlers.read().await;
-        if let Some(handler) = unison_handlers.get(method) {
-            match handler(payload) {
-                Ok(result) => Ok(result),
-                Err(e) => Err(anyhow::anyhow!("Handler error: {}", e)),
-            }
-        } else {
-            // call_handlersへフォールバック
-            drop(unison_handlers);
-            let handlers = self.call_handlers.read().await;
-            if let Some(handler) = handlers.get(method) {
-                handler(payload).await
-            } else {
-                Err(anyhow::anyhow!("Method not found: {}", method))
-            }
+        self.handle_call_response(method, payload)
+            .await
+            .into_result()
+    }
+
+    async fn handle_stream(
+        &self,
+        method: &str,
+        payload: serde_json::Value,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<serde_json::Value>> + Send>>> {
+        let handler = self.stream_handlers.read().unwrap().get(method).cloned();
+        match handler {
+            Some(handler) => handler(payload).await,
+            None => Err(anyhow::anyhow!("Stream method not found: {}", method)),
+        }
+    }
+}
+
+/// クライアントストリームで届いたメッセージをハンドラーへ渡すリクエストに変換
+///
+/// `StreamEnd`の場合は`None`を返します。エラーを返した後は列を終えてください。
+pub(crate) fn client_stream_item(message: &ProtocolMessage) -> Option<Result<Value, NetworkError>> {
+    match message.msg_type {
+        MessageType::StreamData => Some(message.payload_as_value()),
+        MessageType::StreamEnd => None,
+        MessageType::StreamError => Some(Err(super::client::response_error(message))),
+        other => Some(Err(NetworkError::Protocol(format!(
+            "Unexpected {:?} message in client stream",
+            other
+        )))),
+    }
+}
+
+/// テナントのエラーをワイヤー上のエラーへ変換
+fn tenant_error(error: TenantError) -> ProtocolError {
+    match &error {
+        TenantError::InvalidId(_) => {
+            ProtocolError::new(ProtocolError::INVALID_REQUEST, error.to_string())
+        }
+        TenantError::Mismatch { .. } => {
+            ProtocolError::new(ProtocolError::PERMISSION_DENIED, error.to_string())
+        }
+        TenantError::RateLimited { retry_after, .. } => {
+            let retry_after_ms = retry_after.as_millis().min(u64::MAX as u128) as u64;
+            ProtocolError::new(ProtocolError::RATE_LIMITED, error.to_string())
+                .with_details(serde_json::json!({ "retry_after_ms": retry_after_ms }))
         }
     }
+}
+
+/// ハンドラーのパニックを数え、`HandlerPanicked`を発行
+fn report_panic(
+    metrics: &HandlerMetrics,
+    events: &ServerEvents,
+    method: &str,
+    correlation_id: &str,
+) {
+    metrics.record_panic();
+    events.emit(|timestamp_ms| ServerEvent::HandlerPanicked {
+        method: method.to_string(),
+        correlation_id: correlation_id.to_string(),
+        timestamp_ms,
+    });
+}
+
+/// レート制限の超過を再試行までの時間を含むレスポンスへ変換
+fn rate_limit_error(error: RateLimitExceeded) -> HandlerResponse {
+    let retry_after_ms = error.retry_after_millis();
+    HandlerResponse::rejected(rate_limit_rejection(error))
+        .with_metadata(RETRY_AFTER_METADATA_KEY, retry_after_ms.to_string())
+}
+
+fn rate_limit_rejection(error: RateLimitExceeded) -> ProtocolError {
+    ProtocolError::new(ProtocolError::RESOURCE_EXHAUSTED, error.to_string()).with_details(
+        serde_json::json!({
+            "scope": error.scope.to_string(),
+            "retry_after_ms": error.retry_after_millis(),
+        }),
+    )
+}
+
+/// アドミッション制御による拒否をワイヤー上のエラーへ変換
+fn admission_error(error: AdmissionRejected) -> HandlerResponse {
+    HandlerResponse::rejected(admission_rejection(error))
+}
+
+fn admission_rejection(error: AdmissionRejected) -> ProtocolError {
+    ProtocolError::new(ProtocolError::UNAVAILABLE, error.to_string()).with_details(
+        serde_json::json!({
+            "scope": error.scope.to_string(),
+            "reason": error.reason.to_string(),
+        }),
+    )
+}
+
+/// 利用量の上限超過をワイヤー上のエラーへ変換
+fn quota_error(error: QuotaExceeded) -> ProtocolError {
+    ProtocolError::new(ProtocolError::QUOTA_EXCEEDED, error.to_string()).with_details(
+        serde_json::json!({
+            "resource": error.resource,
+            "limit": error.limit,
+            "reset_at_ms": error.reset_at_millis(),
+        }),
+    )
+}
 
-    async fn handle_stream(
-        &self,
-        method: &str,
-        payload: serde_json::Value,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<serde_json::Value>> + Send>>> {
-        let handlers = self.stream_handlers.read().await;
-        if let Some(handler) = handlers.get(method) {
-            handler(payload).await
-        } else {
-            Err(anyhow::anyhow!("Stream method not found: {}", method))
-        }
+/// QUIC接続の相手がmTLSで提示したクライアント証明書の公開鍵のハッシュ
+fn client_certificate_pin(connection: &quinn::Connection) -> Option<CertificatePin> {
+    let certificates = connection
+        .peer_identity()?
+        .downcast::<Vec<rustls::pki_types::CertificateDer<'static>>>()
+        .ok()?;
+    CertificatePin::public_key(certificates.first()?).ok()
+}
+
+/// ストリーム要求の拒否・失敗をワイヤー上のエラーのペイロードへ変換
+///
+/// [`ProtocolError`]はコードと詳細を含めて送り、それ以外はメッセージだけを送ります。
+pub(crate) fn stream_error_payload(error: &anyhow::Error) -> Value {
+    match error.downcast_ref::<ProtocolError>() {
+        Some(error) => serde_json::to_value(error)
+            .unwrap_or_else(|_| serde_json::json!({ "message": error.to_string() })),
+        None => serde_json::json!({ "message": error.to_string() }),
     }
 }
 
@@ -254,10 +2739,13 @@ impl Default for ProtocolServer {
     }
 }
 
-impl UnisonServer for ProtocolServer {
-    async fn listen(&mut self, addr: &str) -> Result<(), NetworkError> {
-        use super::quic::QuicServer;
-
+impl ProtocolServer {
+    /// 実行状態にして`serve`で接続を受け付け、`serve`が戻ったら監視タスクを止める
+    pub(crate) async fn run<F, Fut>(&self, serve: F) -> Result<(), NetworkError>
+    where
+        F: FnOnce(Arc<ProtocolServer>) -> Fut,
+        Fut: Future<Output = Result<(), NetworkError>>,
+    {
         // 実行状態を設定
         {
             let mut running = self.running.write().await;
@@ -268,12 +2756,91 @@ impl UnisonServer for ProtocolServer {
         let protocol_server = Arc::new(ProtocolServer {
             call_handlers: Arc::clone(&self.call_handlers),
             stream_handlers: Arc::clone(&self.stream_handlers),
+            client_stream_handlers: Arc::clone(&self.client_stream_handlers),
+            system_stream_handlers: Arc::clone(&self.system_stream_handlers),
+            resumable_handlers: Arc::clone(&self.resumable_handlers),
+            resume_registry: self.resume_registry.clone(),
             unison_handlers: Arc::clone(&self.unison_handlers),
             services: Arc::clone(&self.services),
+            lifecycle: self.lifecycle.clone(),
+            connections: self.connections.clone(),
+            pubsub: self.pubsub.clone(),
+            presence: self.presence.clone(),
+            tenants: self.tenants.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            admission: self.admission.clone(),
+            usage: self.usage.clone(),
+            slo: self.slo.clone(),
+            streams: self.streams.clone(),
+            events: self.events.clone(),
+            stats: self.stats.clone(),
+            handler_timeout: self.handler_timeout,
+            method_timeouts: Arc::clone(&self.method_timeouts),
+            method_encodings: Arc::clone(&self.method_encodings),
+            handler_metrics: Arc::clone(&self.handler_metrics),
             running: Arc::clone(&self.running),
+            shutdown: self.shutdown.clone(),
+            shutdown_grace: self.shutdown_grace,
+            decode: self.decode,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+            schema_validator: Arc::clone(&self.schema_validator),
+            runtime: self.runtime.clone(),
+            udp_backend: self.udp_backend.clone(),
+            udp_config: self.udp_config.clone(),
+            tls: self.tls.clone(),
+            certificate_reload: self.certificate_reload,
+            certificate_reloader: self.certificate_reloader.clone(),
+            heartbeat: self.heartbeat,
+            wire_format: self.wire_format,
+            schema: Arc::clone(&self.schema),
+            retired_methods: self.retired_methods.clone(),
+            capabilities: Arc::clone(&self.capabilities),
+            error_catalog: self.error_catalog.clone(),
+            peer_addresses: Arc::clone(&self.peer_addresses),
+            peer_certificates: Arc::clone(&self.peer_certificates),
+            connection_meters: Arc::clone(&self.connection_meters),
+            frame_history: self.frame_history.clone(),
+            frame_history_authorizer: self.frame_history_authorizer.clone(),
+            dedup: self.dedup.clone(),
+            stream_ids: self.stream_ids.clone(),
+        });
+
+        // プレゼンスのタイムアウト監視
+        let sweeper = Arc::clone(&protocol_server);
+        let background = protocol_server.task_supervisor("server background");
+        background.spawn(async move {
+            let mut interval = tokio::time::interval(sweeper.presence.config().sweep_interval);
+            loop {
+                interval.tick().await;
+                if !*sweeper.running.read().await {
+                    break;
+                }
+                sweeper.sweep_presence();
+            }
+        });
+
+        // サービスの状態の変化をトピックへ発行
+        let publisher = Arc::clone(&protocol_server);
+        background.spawn(async move {
+            let mut events = publisher.lifecycle.events();
+            while let Some(event) = events.next().await {
+                publisher.publish_service_event(&event);
+            }
         });
 
-        let mut quic_server = QuicServer::new(protocol_server);
+        let result = serve(protocol_server).await;
+
+        // 監視タスクを止めてから戻る
+        background.shutdown().await;
+        result
+    }
+
+    async fn listen_quic(
+        protocol_server: Arc<ProtocolServer>,
+        addr: &str,
+    ) -> Result<(), NetworkError> {
+        let mut quic_server = super::quic::QuicServer::new(protocol_server);
         quic_server
             .bind(addr)
             .await
@@ -284,22 +2851,39 @@ impl UnisonServer for ProtocolServer {
         quic_server
             .start()
             .await
-            .map_err(|e| NetworkError::Quic(e.to_string()))?;
+            .map_err(|e| NetworkError::Quic(e.to_string()))
+    }
+}
 
-        Ok(())
+impl UnisonServer for ProtocolServer {
+    async fn listen(&mut self, addr: &str) -> Result<(), NetworkError> {
+        self.run(|protocol_server| async move {
+            if addr.starts_with(super::pipe::PIPE_SCHEME) {
+                super::pipe::PipeServer::new(protocol_server)
+                    .listen(addr)
+                    .await
+            } else if super::websocket::is_websocket_url(addr) {
+                super::websocket::listen(protocol_server, addr).await
+            } else if super::memory::is_memory_url(addr) {
+                super::memory::listen(protocol_server, addr).await
+            } else {
+                Self::listen_quic(protocol_server, addr).await
+            }
+        })
+        .await
     }
 
+    /// 停止を要求し、`listen`は処理中のハンドラーを待ってから戻る
     async fn stop(&mut self) -> Result<(), NetworkError> {
         let mut running = self.running.write().await;
         *running = false;
-        tracing::info!("🎵 Unison Protocol server stopped");
+        self.shutdown.trigger();
+        tracing::info!("🎵 Unison Protocol server stopping");
         Ok(())
     }
 
     fn is_running(&self) -> bool {
-        // For now, return false for simplicity in tests
-        // In production, this would check the actual running state
-        false
+        !self.shutdown.is_stopping() && self.running.try_read().is_ok_and(|running| *running)
     }
 }
 
@@ -308,18 +2892,22 @@ impl UnisonServerExt for ProtocolServer {
     where
         F: Fn(serde_json::Value) -> Result<serde_json::Value, NetworkError> + Send + Sync + 'static,
     {
-        let handler = Arc::new(handler);
-        let method = method.to_string();
-        let handlers_arc = Arc::clone(&self.unison_handlers);
+        self.unison_handlers
+            .write()
+            .unwrap()
+            .insert(method.to_string(), Arc::new(handler));
+    }
 
-        // Use tokio spawn for async registration to avoid blocking
-        let _handle = tokio::spawn(async move {
-            let mut handlers = handlers_arc.write().await;
-            handlers.insert(method, handler);
-        });
+    fn register_async_handler<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut:
+            futures_util::Future<Output = Result<serde_json::Value, NetworkError>> + Send + 'static,
+    {
+        self.insert_call_handler(method, handler);
     }
 
-    fn register_stream_handler<F>(&mut self, method: &str, _handler: F)
+    fn register_stream_handler<F>(&mut self, method: &str, handler: F)
     where
         F: Fn(
                 serde_json::Value,
@@ -329,9 +2917,20 @@ impl UnisonServerExt for ProtocolServer {
             + Sync
             + 'static,
     {
-        // Simplified implementation for now - just log registration
-        tracing::info!("Stream handler registered for method: {}", method);
-        // TODO: Implement proper stream handler storage
+        let handler = Arc::new(handler);
+        self.insert_stream_handler(method, move |payload| {
+            let stream = handler(payload).map(|item| item.map_err(anyhow::Error::from));
+            async move { Ok(stream) }
+        });
+    }
+
+    fn register_client_stream_handler<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(ClientStreamRequests) -> Fut + Send + Sync + 'static,
+        Fut:
+            futures_util::Future<Output = Result<serde_json::Value, NetworkError>> + Send + 'static,
+    {
+        self.insert_client_stream_handler(method, handler);
     }
 
     fn register_system_stream_handler<F>(&mut self, method: &str, handler: F)
@@ -345,11 +2944,21 @@ impl UnisonServerExt for ProtocolServer {
             + Sync
             + 'static,
     {
-        // For now, we'll store this as a placeholder until we implement SystemStream handling
-        // This is a complex operation that requires significant changes to the server architecture
-        let _handler = Arc::new(handler);
-        tracing::info!("SystemStream handler registered for method: {}", method);
-        // TODO: Implement SystemStream handler storage and execution
+        self.insert_system_stream_handler(method, handler);
+    }
+
+    fn register_typed_handler<Req, Res, E, F, Fut>(&mut self, method: &str, handler: F)
+    where
+        Req: DeserializeOwned + Send + 'static,
+        Res: Serialize + Send + 'static,
+        E: Into<ProtocolError> + Send + 'static,
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<Output = Result<Res, E>> + Send + 'static,
+    {
+        self.insert_request_handler(method, move |request: Request<Req>| {
+            let response = handler(request.body);
+            async move { response.await.map(Response::new) }
+        });
     }
 }
 
@@ -372,18 +2981,98 @@ impl ProtocolServer {
         Ok(service_name)
     }
 
+    /// 監督下でサービスを起動
+    ///
+    /// `factory`で作成したサービスを`name`で登録し、健全性を定期的に確認します。
+    /// 起動・確認に失敗したりストリームが閉じたりした場合は、`config`の再起動のポリシーに
+    /// 従ってバックオフ後に`factory`から作成し直します。
+    pub fn supervise_service<F, Fut>(
+        &self,
+        name: impl Into<String>,
+        config: SupervisionConfig,
+        factory: F,
+    ) -> Result<(), NetworkError>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: futures_util::Future<
+                Output = Result<crate::network::service::UnisonService, NetworkError>,
+            > + Send
+            + 'static,
+    {
+        let name = name.into();
+        match self.lifecycle.state(&name) {
+            None | Some(ServiceState::Stopped) => {}
+            Some(state) => {
+                return Err(NetworkError::Protocol(format!(
+                    "Service {} is already {}",
+                    name, state
+                )));
+            }
+        }
+        let stop = self.lifecycle.register(&name, &config.restart, true);
+        let task = supervise(
+            Arc::clone(&self.services),
+            self.lifecycle.clone(),
+            name,
+            config,
+            factory,
+            stop,
+        );
+        match &self.runtime {
+            Some(runtime) => drop(runtime.spawn(task)),
+            None => drop(tokio::spawn(task)),
+        }
+        Ok(())
+    }
+
+    /// サービスを停止
+    ///
+    /// 処理中のリクエストを待ってから終了通知を送り、`Stopped`になるまで待機します。
+    /// 監督下のサービスは再起動されません。
+    pub async fn stop_service(&self, name: &str) -> Result<(), NetworkError> {
+        match self.lifecycle.state(name) {
+            None => {
+                return Err(NetworkError::Protocol(format!(
+                    "Service not found: {}",
+                    name
+                )));
+            }
+            Some(ServiceState::Stopped) => return Ok(()),
+            Some(_) => {}
+        }
+        tracing::info!("🛑 Shutting down service: {}", name);
+        if self.lifecycle.is_supervised(name) {
+            self.lifecycle.request_stop(name);
+            self.lifecycle.wait_for(name, ServiceState::Stopped).await;
+        } else {
+            drain_service(&self.services, &self.lifecycle, name).await;
+        }
+        Ok(())
+    }
+
+    /// サービスの状態と状態遷移イベント
+    pub fn service_lifecycle(&self) -> &ServiceLifecycle {
+        &self.lifecycle
+    }
+
     /// すべてのサービスを正常に停止
     pub async fn shutdown_all_services(&self) -> Result<(), NetworkError> {
-        let mut services = self.services.write().await;
-
-        for (name, service) in services.iter_mut() {
-            tracing::info!("🛑 Shutting down service: {}", name);
-            if let Err(e) = service.shutdown().await {
+        let names: Vec<String> = self
+            .lifecycle
+            .statuses()
+            .into_iter()
+            .filter(|status| status.state != ServiceState::Stopped)
+            .map(|status| status.service)
+            .collect();
+        let results =
+            futures_util::future::join_all(names.iter().map(|name| self.stop_service(name))).await;
+        for (name, result) in names.iter().zip(results) {
+            if let Err(e) = result {
                 tracing::error!("Error shutting down service {}: {}", name, e);
             }
         }
 
-        services.clear();
+        self.services.write().await.clear();
         Ok(())
     }
 }
@@ -409,10 +3098,872 @@ mod tests {
             Ok(serde_json::json!({"message": "pong"}))
         });
 
-        // Test handler registration without actually starting the server
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await; // Wait for registration to complete
+        // 登録直後から呼び出せる
+        let pong = server.handle_call("ping", Value::Null).await.unwrap();
+        assert_eq!(pong["message"], "pong");
 
         // Test that server can be stopped
         assert!(server.stop().await.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_async_handlers_registered_synchronously() {
+        let mut server = ProtocolServer::new().with_call_handler("double", |payload| async move {
+            Ok::<_, NetworkError>(serde_json::json!(payload.as_i64().unwrap_or(0) * 2))
+        });
+        server.register_async_handler("greet", |payload| async move {
+            tokio::task::yield_now().await;
+            Ok(serde_json::json!(format!(
+                "Hello, {}!",
+                payload.as_str().unwrap_or("?")
+            )))
+        });
+
+        let doubled = server.handle_call("double", serde_json::json!(21)).await;
+        assert_eq!(doubled.unwrap(), 42);
+        let greeting = server
+            .handle_call("greet", serde_json::json!("Unison"))
+            .await;
+        assert_eq!(greeting.unwrap(), "Hello, Unison!");
+    }
+
+    #[tokio::test]
+    async fn test_resumable_stream_resume() {
+        let server = ProtocolServer::new().with_resume_config(ResumeConfig {
+            token_interval: 2,
+            ..Default::default()
+        });
+        server
+            .register_resumable_stream_handler("count", |payload, offset| async move {
+                let to = payload["to"].as_u64().unwrap_or(0);
+                Ok(futures_util::stream::iter(
+                    (offset..to).map(|n| Ok(serde_json::json!(n))),
+                ))
+            })
+            .await;
+
+        let request = ProtocolMessage::new_with_json(
+            1,
+            "count".to_string(),
+            MessageType::Stream,
+            serde_json::json!({"to": 6}),
+        )
+        .unwrap();
+
+        // 3件受信した後に最後のトークンを保持して切断
+        let mut stream = server.open_stream(1, &request).await.unwrap();
+        let mut received = Vec::new();
+        let mut last_token = None;
+        while received.len() < 3 {
+            match stream.next().await.unwrap().unwrap() {
+                StreamEvent::Data(value) => received.push(value.as_u64().unwrap()),
+                StreamEvent::ResumeToken(token) => last_token = Some(token),
+            }
+        }
+        drop(stream);
+
+        let token = last_token.unwrap();
+        assert_eq!(token.sequence, 2);
+
+        let resume = ProtocolMessage::new_with_json(
+            2,
+            "count".to_string(),
+            MessageType::StreamResume,
+            serde_json::to_value(&token).unwrap(),
+        )
+        .unwrap();
+        let resumed: Vec<u64> = server
+            .open_stream(1, &resume)
+            .await
+            .unwrap()
+            .filter_map(|event| async move {
+                match event.unwrap() {
+                    StreamEvent::Data(value) => value.as_u64(),
+                    StreamEvent::ResumeToken(_) => None,
+                }
+            })
+            .collect()
+            .await;
+        assert_eq!(resumed, vec![2, 3, 4, 5]);
+    }
+
+    /// 受信したフレーム数を数えるテスト用シンク
+    #[derive(Default)]
+    struct CountingSink {
+        frames: std::sync::atomic::AtomicUsize,
+    }
+
+    impl super::super::MessageSink for CountingSink {
+        fn send_frame(
+            &self,
+            _frame: bytes::Bytes,
+        ) -> Pin<Box<dyn futures_util::Future<Output = Result<(), NetworkError>> + Send + '_>>
+        {
+            self.frames
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn is_closed(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropping_server_stops_broadcast_workers() {
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let server = ProtocolServer::new();
+        let before = metrics.num_alive_tasks();
+        let sink = Arc::new(CountingSink::default());
+        server.connections().register(sink.clone());
+        server
+            .broadcast("news", serde_json::json!({}))
+            .unwrap()
+            .wait()
+            .await;
+        assert!(metrics.num_alive_tasks() > before);
+
+        drop(server);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while metrics.num_alive_tasks() > before {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("broadcast workers should stop with the server");
+        assert_eq!(sink.frames.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_publish_with_filter() {
+        let server = ProtocolServer::new();
+        let all = Arc::new(CountingSink::default());
+        let hot = Arc::new(CountingSink::default());
+        let all_id = server.connections().register(all.clone());
+        let hot_id = server.connections().register(hot.clone());
+
+        server
+            .handle_connection_call(
+                all_id,
+                super::super::pubsub::SUBSCRIBE_METHOD,
+                serde_json::json!({"topic": "sensors.#"}),
+            )
+            .await
+            .unwrap();
+        server
+            .handle_connection_call(
+                hot_id,
+                super::super::pubsub::SUBSCRIBE_METHOD,
+                serde_json::json!({"topic": "sensors.*", "filter": "$.temp > 30"}),
+            )
+            .await
+            .unwrap();
+
+        for temp in [20, 35] {
+            server
+                .publish("sensors.room1", serde_json::json!({"temp": temp}))
+                .unwrap()
+                .wait()
+                .await;
+        }
+
+        let sent = |sink: &CountingSink| sink.frames.load(std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(sent(&all), 2);
+        assert_eq!(sent(&hot), 1);
+        assert_eq!(server.pubsub().stats().filtered, 1);
+
+        server.connection_closed(hot_id, CloseReason::ClientClosed);
+        assert_eq!(server.pubsub().stats().subscriptions, 1);
+    }
+
+    #[tokio::test]
+    async fn test_slo_violation_is_published() {
+        let violations = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&violations);
+        let server = ProtocolServer::new()
+            .with_call_handler("slow", |payload| async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok::<_, NetworkError>(payload)
+            })
+            .with_latency_slo(
+                "slow",
+                LatencySlo::p99(Duration::from_millis(5)).with_min_samples(3),
+            )
+            .on_slo_violation(move |violation| {
+                recorded.lock().unwrap().push(violation.method.clone());
+            });
+        let monitor = Arc::new(CountingSink::default());
+        let monitor_id = server.connections().register(monitor.clone());
+        server
+            .handle_connection_call(
+                monitor_id,
+                super::super::pubsub::SUBSCRIBE_METHOD,
+                serde_json::json!({ "topic": SLO_VIOLATION_TOPIC }),
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..4 {
+            server
+                .handle_connection_call(monitor_id, "slow", Value::Null)
+                .await
+                .unwrap();
+        }
+        assert_eq!(*violations.lock().unwrap(), vec!["slow".to_string()]);
+        assert!(server.slo().status("slow").unwrap().violating);
+
+        // 発行したイベントが購読者へ届くまで待つ
+        for _ in 0..50 {
+            if monitor.frames.load(std::sync::atomic::Ordering::SeqCst) > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(monitor.frames.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_presence_changes_published_to_watchers() {
+        let server = ProtocolServer::new();
+        let watcher = Arc::new(CountingSink::default());
+        let member = Arc::new(CountingSink::default());
+        let watcher_id = server.connections().register(watcher.clone());
+        let member_id = server.connections().register(member.clone());
+
+        server
+            .handle_connection_call(
+                watcher_id,
+                super::super::pubsub::SUBSCRIBE_METHOD,
+                serde_json::json!({"topic": "presence.alice"}),
+            )
+            .await
+            .unwrap();
+        server
+            .handle_connection_call(
+                member_id,
+                super::super::presence::PRESENCE_SET_METHOD,
+                serde_json::json!({"id": "alice", "status": "online"}),
+            )
+            .await
+            .unwrap();
+
+        let roster = server
+            .handle_connection_call(
+                watcher_id,
+                super::super::presence::PRESENCE_QUERY_METHOD,
+                serde_json::json!({}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(roster[0]["id"], "alice");
+        assert_eq!(roster[0]["status"], "online");
+
+        // 切断でオフラインへの遷移が発行される
+        server.connection_closed(member_id, CloseReason::ClientClosed);
+        assert!(server.presence().online().is_empty());
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert_eq!(watcher.frames.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_is_isolated() {
+        let server = ProtocolServer::new();
+        server
+            .register_call_handler("explode", |_payload| async move {
+                panic!("handler bug");
+                #[allow(unreachable_code)]
+                Ok::<_, NetworkError>(serde_json::json!({}))
+            })
+            .await;
+        server
+            .register_call_handler(
+                "echo",
+                |payload| async move { Ok::<_, NetworkError>(payload) },
+            )
+            .await;
+        let mut events = server.events();
+
+        let response = server
+            .handle_call_response("explode", serde_json::json!({}))
+            .await;
+        let error = response.outcome.unwrap_err();
+        assert_eq!(error.code, ProtocolError::INTERNAL);
+        // パニックの内容はクライアントへ返さず、相関IDのみを返す
+        assert!(!error.message.contains("handler bug"));
+        let correlation_id = error.details.unwrap()["correlation_id"].clone();
+        assert!(error.message.contains(correlation_id.as_str().unwrap()));
+        assert_eq!(server.handler_metrics().panics(), 1);
+        match events.next().await.unwrap() {
+            ServerEvent::HandlerPanicked {
+                method,
+                correlation_id: event_id,
+                ..
+            } => {
+                assert_eq!(method, "explode");
+                assert_eq!(event_id, correlation_id.as_str().unwrap());
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        // パニック後も他のハンドラーは処理できる
+        let echoed = server
+            .handle_call_response("echo", serde_json::json!({"ok": true}))
+            .await;
+        assert_eq!(echoed.outcome.unwrap()["ok"], true);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_isolation() {
+        let server = ProtocolServer::new();
+        server
+            .register_call_handler("whoami", |_payload| async move {
+                let tenant = super::super::tenant::current_tenant();
+                Ok::<_, NetworkError>(serde_json::json!({
+                    "tenant": tenant.map(|t| t.to_string()),
+                }))
+            })
+            .await;
+
+        let acme = Arc::new(CountingSink::default());
+        let globex = Arc::new(CountingSink::default());
+        let acme_id = server.connections().register(acme.clone());
+        let globex_id = server.connections().register(globex.clone());
+        let request = |tenant: &str| {
+            ProtocolMessage::new_with_json(1, "whoami".into(), MessageType::Request, Value::Null)
+                .unwrap()
+                .with_metadata(&HashMap::from([(
+                    super::super::tenant::TENANT_METADATA_KEY.to_string(),
+                    tenant.to_string(),
+                )]))
+                .unwrap()
+        };
+        server.bind_tenant(acme_id, &request("acme")).unwrap();
+        server.bind_tenant(globex_id, &request("globex")).unwrap();
+        // 接続のテナントは切り替えられない
+        let error = server.bind_tenant(acme_id, &request("globex")).unwrap_err();
+        assert_eq!(error.code, ProtocolError::PERMISSION_DENIED);
+
+        let whoami = server
+            .handle_connection_call(acme_id, "whoami", Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(whoami["tenant"], "acme");
+
+        // 同じトピック名でもテナントごとに分離される
+        for id in [acme_id, globex_id] {
+            server
+                .handle_connection_call(
+                    id,
+                    super::super::pubsub::SUBSCRIBE_METHOD,
+                    serde_json::json!({"topic": "orders.#"}),
+                )
+                .await
+                .unwrap();
+        }
+        let acme_tenant = server.tenants().tenant_of(acme_id).unwrap();
+        server
+            .publish_to_tenant(&acme_tenant, "orders.created", serde_json::json!({}))
+            .unwrap()
+            .wait()
+            .await;
+        let sent = |sink: &CountingSink| sink.frames.load(std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(sent(&acme), 1);
+        assert_eq!(sent(&globex), 0);
+
+        let presence = server
+            .handle_connection_request(
+                globex_id,
+                super::super::presence::PRESENCE_QUERY_METHOD,
+                serde_json::json!({}),
+            )
+            .await;
+        assert_eq!(
+            presence.outcome.unwrap_err().code,
+            ProtocolError::PERMISSION_DENIED
+        );
+
+        let stats = server.tenants().stats(&acme_tenant).unwrap();
+        assert_eq!((stats.requests, stats.errors), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_handler_timeout_per_method() {
+        let server = ProtocolServer::new().with_handler_timeout(Some(Duration::from_secs(5)));
+        let slow = |_payload| async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok::<_, NetworkError>(serde_json::json!({}))
+        };
+        server
+            .register_call_handler_with_options(
+                "slow",
+                HandlerOptions::default().with_timeout(Duration::from_millis(10)),
+                slow,
+            )
+            .await;
+        server.register_call_handler("patient", slow).await;
+
+        let error = server
+            .handle_call_response("slow", serde_json::json!({}))
+            .await
+            .outcome
+            .unwrap_err();
+        assert_eq!(error.code, ProtocolError::DEADLINE_EXCEEDED);
+        assert_eq!(server.handler_metrics().timeouts(), 1);
+
+        // 既定値の範囲内であれば完了する
+        let response = server
+            .handle_call_response("patient", serde_json::json!({}))
+            .await;
+        assert!(response.is_ok());
+        assert_eq!(server.handler_metrics().timeouts(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_caller_deadline() {
+        let server = ProtocolServer::new();
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        server
+            .register_call_handler("slow", move |_payload| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    Ok::<_, NetworkError>(serde_json::json!({}))
+                }
+            })
+            .await;
+        let connection_id: ConnectionId = 1;
+
+        // 期限切れのリクエストはハンドラーを実行しない
+        let expired = SystemTime::now() - Duration::from_secs(1);
+        let error = server
+            .handle_connection_request_with_deadline(
+                connection_id,
+                "slow",
+                serde_json::json!({}),
+                Some(expired),
+            )
+            .await
+            .outcome
+            .unwrap_err();
+        assert_eq!(error.code, ProtocolError::DEADLINE_EXCEEDED);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        // 残り時間を超えたハンドラーは中断する
+        let soon = SystemTime::now() + Duration::from_millis(20);
+        let error = server
+            .handle_connection_request_with_deadline(
+                connection_id,
+                "slow",
+                serde_json::json!({}),
+                Some(soon),
+            )
+            .await
+            .outcome
+            .unwrap_err();
+        assert_eq!(error.code, ProtocolError::DEADLINE_EXCEEDED);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_requests_rejected_while_stopping() {
+        let mut server = ProtocolServer::new();
+        server
+            .register_call_handler(
+                "echo",
+                |payload| async move { Ok::<_, NetworkError>(payload) },
+            )
+            .await;
+        let connection_id: ConnectionId = 1;
+        let response = server
+            .handle_connection_request(connection_id, "echo", serde_json::json!({}))
+            .await;
+        assert!(response.is_ok());
+
+        UnisonServer::stop(&mut server).await.unwrap();
+        assert!(server.shutdown_controller().is_stopping());
+        assert!(!server.is_running());
+        let response = server
+            .handle_connection_request(connection_id, "echo", serde_json::json!({}))
+            .await;
+        // 冪等キーの結果として保持しないよう、実行前の拒否として印を付ける
+        assert!(response.is_rejected());
+        assert_eq!(
+            response.outcome.unwrap_err().code,
+            ProtocolError::UNAVAILABLE
+        );
+
+        // ストリーム要求も停止中は拒否する
+        let request = ProtocolMessage::new_with_json(
+            1,
+            "echo".into(),
+            MessageType::Stream,
+            serde_json::json!({}),
+        )
+        .unwrap();
+        let Err(error) = server.open_stream(connection_id, &request).await else {
+            panic!("stream opened while stopping");
+        };
+        let error = error.downcast::<ProtocolError>().unwrap();
+        assert_eq!(error.code, ProtocolError::UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_open_stream_holds_admission_permit() {
+        use super::super::admission::ConcurrencyLimit;
+
+        let server = ProtocolServer::new().with_admission_config(
+            AdmissionConfig::default().with_method_limit("feed", ConcurrencyLimit::new(1)),
+        );
+        server
+            .register_stream_handler("feed", |_| async move {
+                Ok(futures_util::stream::pending::<Result<Value>>())
+            })
+            .await;
+        let request = ProtocolMessage::new_with_json(
+            1,
+            "feed".into(),
+            MessageType::Stream,
+            serde_json::json!({}),
+        )
+        .unwrap();
+
+        // 開いているストリームが枠を保持するため、次の要求は拒否される
+        let stream = server.open_stream(1, &request).await.unwrap();
+        let Err(error) = server.open_stream(2, &request).await else {
+            panic!("stream admitted beyond the limit");
+        };
+        let error = error.downcast::<ProtocolError>().unwrap();
+        assert_eq!(error.code, ProtocolError::UNAVAILABLE);
+
+        drop(stream);
+        assert!(server.open_stream(2, &request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_drain_status_tracks_open_streams() {
+        let mut server = ProtocolServer::new();
+        server
+            .register_stream_handler("feed", |_| async move {
+                Ok(futures_util::stream::pending::<Result<Value>>())
+            })
+            .await;
+        let request = ProtocolMessage::new_with_json(
+            1,
+            "feed".into(),
+            MessageType::Stream,
+            serde_json::json!({}),
+        )
+        .unwrap();
+        let stream = server.open_stream(1, &request).await.unwrap();
+        let status = server.drain_status();
+        assert_eq!(status.active_streams, 1);
+        assert_eq!(status.streams_by_method["feed"], 1);
+        assert!(status.drain.is_none());
+
+        // 停止中も組み込みメソッドでドレインの状態を確認できる
+        UnisonServer::stop(&mut server).await.unwrap();
+        let status = server
+            .handle_connection_request(1, DRAIN_STATUS_METHOD, Value::Null)
+            .await
+            .outcome
+            .unwrap();
+        assert_eq!(status["stopping"], true);
+        assert_eq!(status["active_streams"], 1);
+        assert_eq!(status["safe_to_restart"], false);
+
+        drop(stream);
+        let status = server.drain_status();
+        assert_eq!(status.active_streams, 0);
+        assert!(status.safe_to_restart);
+        assert_eq!(status.drain.unwrap().completed_ratio, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_usage_quota_exceeded() {
+        use super::super::usage::{QUOTA_USAGE_METHOD, UsageConfig, UsageQuota};
+
+        let server = ProtocolServer::new().with_usage_config(
+            UsageConfig::default().with_peer_quota(UsageQuota::daily().with_max_requests(2)),
+        );
+        server
+            .register_call_handler(
+                "echo",
+                |payload| async move { Ok::<_, NetworkError>(payload) },
+            )
+            .await;
+        let id = server
+            .connections()
+            .register(Arc::new(CountingSink::default()));
+
+        for _ in 0..2 {
+            assert!(
+                server
+                    .handle_connection_request(id, "echo", Value::Null)
+                    .await
+                    .is_ok()
+            );
+        }
+        let response = server
+            .handle_connection_request(id, "echo", Value::Null)
+            .await;
+        let reset_at = response.metadata[super::super::usage::QUOTA_RESET_METADATA_KEY].clone();
+        let error = response.outcome.unwrap_err();
+        assert_eq!(error.code, ProtocolError::QUOTA_EXCEEDED);
+        let details = error.details.unwrap();
+        assert_eq!(details["resource"], "requests");
+        assert_eq!(details["reset_at_ms"].to_string(), reset_at);
+
+        // 上限を超えても利用量は取得できる
+        let usage = server
+            .handle_connection_call(id, QUOTA_USAGE_METHOD, Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(usage["requests"], 2);
+        assert_eq!(usage["max_requests"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_rejects_noisy_connection_only() {
+        use super::super::rate_limit::{RateLimit, RateLimitConfig};
+
+        let server = ProtocolServer::new().with_rate_limit_config(
+            RateLimitConfig::default().with_method_limit("echo", RateLimit::new(1.0, 2)),
+        );
+        server
+            .register_call_handler(
+                "echo",
+                |payload| async move { Ok::<_, NetworkError>(payload) },
+            )
+            .await;
+        let noisy = server
+            .connections()
+            .register(Arc::new(CountingSink::default()));
+        let quiet = server
+            .connections()
+            .register(Arc::new(CountingSink::default()));
+
+        for _ in 0..2 {
+            assert!(
+                server
+                    .handle_connection_request(noisy, "echo", Value::Null)
+                    .await
+                    .is_ok()
+            );
+        }
+        let response = server
+            .handle_connection_request(noisy, "echo", Value::Null)
+            .await;
+        let retry_after = response.metadata[RETRY_AFTER_METADATA_KEY].clone();
+        let error = response.outcome.unwrap_err();
+        assert_eq!(error.code, ProtocolError::RESOURCE_EXHAUSTED);
+        let details = error.details.unwrap();
+        assert_eq!(details["scope"], "method 'echo'");
+        assert_eq!(details["retry_after_ms"].to_string(), retry_after);
+        assert!(details["retry_after_ms"].as_u64().unwrap() <= 1000);
+
+        // 他の接続は上限の影響を受けない
+        assert!(
+            server
+                .handle_connection_request(quiet, "echo", Value::Null)
+                .await
+                .is_ok()
+        );
+        assert_eq!(server.rate_limiter().rejected(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_schema_services_are_introspectable() {
+        use crate::parser::{Field, Method, MethodMessage, ParsedSchema, Protocol, Service};
+
+        let field = |name: &str, ty: &str| Field {
+            name: name.into(),
+            field_type_str: ty.into(),
+            required: true,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        };
+        let schema = ParsedSchema {
+            protocol: Some(Protocol {
+                name: "ping_pong".into(),
+                version: "1.2.0".into(),
+                namespace: None,
+                description: None,
+                services: vec![Service {
+                    name: "PingPong".into(),
+                    description: None,
+                    methods: vec![Method {
+                        name: "ping".into(),
+                        description: None,
+                        timeout_ms: None,
+                        idempotent: false,
+                        paged: false,
+                        encodings: Vec::new(),
+                        request: Some(MethodMessage {
+                            fields: vec![field("message", "string")],
+                        }),
+                        response: None,
+                    }],
+                    streams: vec![],
+                    client_streams: vec![],
+                }],
+                messages: vec![],
+                enums: vec![],
+            }),
+            ..Default::default()
+        };
+
+        let server = ProtocolServer::new().with_schema_services(&schema);
+        let ping = server
+            .handle_call("PingPong.ping", Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(ping["version"], "1.2.0");
+        assert_eq!(ping["methods"], serde_json::json!(["ping"]));
+        assert_eq!(ping["fingerprint"], schema.fingerprint());
+
+        let describe = server
+            .handle_call("PingPong.describe", Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(describe["methods"][0]["request"][0]["type"], "string");
+
+        // フィールドの型が変われば指紋も変わる
+        let mut changed = schema.clone();
+        changed.protocol.as_mut().unwrap().services[0].methods[0]
+            .request
+            .as_mut()
+            .unwrap()
+            .fields[0]
+            .field_type_str = "int".into();
+        assert_ne!(changed.fingerprint(), schema.fingerprint());
+    }
+
+    #[tokio::test]
+    async fn test_typed_handler_validates_request() {
+        use crate::parser::{Field, Method, MethodMessage, ParsedSchema, Protocol, Service};
+        use serde::Deserialize;
+
+        #[derive(Deserialize)]
+        struct Greet {
+            name: String,
+        }
+        #[derive(Serialize)]
+        struct Greeting {
+            message: String,
+        }
+
+        let mut name = Field {
+            name: "name".into(),
+            field_type_str: "string".into(),
+            required: true,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        };
+        name.min_length = Some(1);
+        let schema = ParsedSchema {
+            protocol: Some(Protocol {
+                name: "greeter".into(),
+                version: "1.0.0".into(),
+                namespace: None,
+                description: None,
+                services: vec![Service {
+                    name: "Greeter".into(),
+                    description: None,
+                    methods: vec![Method {
+                        name: "greet".into(),
+                        description: None,
+                        timeout_ms: None,
+                        idempotent: false,
+                        paged: false,
+                        encodings: Vec::new(),
+                        request: Some(MethodMessage { fields: vec![name] }),
+                        response: None,
+                    }],
+                    streams: vec![],
+                    client_streams: vec![],
+                }],
+                messages: vec![],
+                enums: vec![],
+            }),
+            ..Default::default()
+        };
+
+        let mut server = ProtocolServer::new()
+            .with_schema_validation(&schema)
+            .unwrap();
+        server.register_typed_handler("greet", |request: Greet| async move {
+            Ok::<_, NetworkError>(Greeting {
+                message: format!("Hello, {}!", request.name),
+            })
+        });
+
+        let response = server
+            .handle_call_response("greet", serde_json::json!({ "name": "Unison" }))
+            .await;
+        assert_eq!(response.outcome.unwrap()["message"], "Hello, Unison!");
+
+        // スキーマの制約を満たさないリクエストはハンドラーに渡さない
+        let error = server
+            .handle_call_response("greet", serde_json::json!({ "name": "" }))
+            .await
+            .outcome
+            .unwrap_err();
+        assert_eq!(error.code, ProtocolError::INVALID_REQUEST);
+        assert_eq!(error.details.unwrap()["field"], "name");
+
+        let error = server
+            .handle_call_response("greet", serde_json::json!({}))
+            .await
+            .outcome
+            .unwrap_err();
+        assert_eq!(error.code, ProtocolError::INVALID_REQUEST);
+
+        // 独自の検証関数は登録済みの検証器でのみ使用できる
+        let mut schema = schema;
+        schema.protocol.as_mut().unwrap().services[0].methods[0]
+            .request
+            .as_mut()
+            .unwrap()
+            .fields[0]
+            .validator = Some("capitalized".into());
+        assert!(matches!(
+            ProtocolServer::new().with_schema_validation(&schema),
+            Err(ValidationError::UnknownValidator { .. })
+        ));
+        let validator = SchemaValidator::new(&schema).unwrap().with_validator(
+            "capitalized",
+            |value| match value.as_str().and_then(|s| s.chars().next()) {
+                Some(c) if c.is_uppercase() => Ok(()),
+                _ => Err("must start with an uppercase letter".into()),
+            },
+        );
+        let mut server = ProtocolServer::new()
+            .with_schema_validator(validator)
+            .unwrap();
+        server.register_typed_handler("greet", |request: Greet| async move {
+            Ok::<_, NetworkError>(Greeting {
+                message: format!("Hello, {}!", request.name),
+            })
+        });
+        let error = server
+            .handle_call_response("greet", serde_json::json!({ "name": "unison" }))
+            .await
+            .outcome
+            .unwrap_err();
+        assert_eq!(error.code, ProtocolError::INVALID_REQUEST);
+        assert_eq!(error.details.unwrap()["field"], "name");
+    }
 }
@@ -1,5 +1,10 @@
+use super::admission::AdmissionStats;
+use super::connection_stats::{ConnectionMeter, ConnectionStats};
+use super::reconnect::random_unit;
+use super::stats::{MethodStats, StatsRecorder, StreamStats};
 use super::{NetworkError, StreamHandle, SystemStream};
 use std::collections::HashMap;
+use std::time::Duration;
 
 /// Unisonサービストレイト - SystemStreamをベースとした高レベルサービスインターフェース
 #[allow(async_fn_in_trait)]
@@ -155,7 +160,7 @@ pub trait RealtimeService: Service {
 }
 
 /// リアルタイム通信のためのサービス優先度レベル
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ServicePriority {
     Low = 0,
     Normal = 1,
@@ -164,6 +169,9 @@ pub enum ServicePriority {
 }
 
 /// サービスパフォーマンス統計
+///
+/// 全体の集計に加えて、メソッドごとの呼び出しとストリームの内訳を持ちます
+/// （[`stats`](super::stats)を参照）。
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ServiceStats {
     pub avg_latency_ms: f64,
@@ -174,6 +182,15 @@ pub struct ServiceStats {
     pub requests_processed: u64,
     pub errors_count: u64,
     pub uptime_seconds: u64,
+    /// メソッドごとの呼び出しの統計
+    #[serde(default)]
+    pub methods: HashMap<String, MethodStats>,
+    /// メソッドごとのストリームの統計
+    #[serde(default)]
+    pub streams: HashMap<String, StreamStats>,
+    /// 処理中・待機中のリクエストの数（サーバーのみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admission: Option<AdmissionStats>,
 }
 
 impl Default for ServiceStats {
@@ -187,6 +204,93 @@ impl Default for ServiceStats {
             requests_processed: 0,
             errors_count: 0,
             uptime_seconds: 0,
+            methods: HashMap::new(),
+            streams: HashMap::new(),
+            admission: None,
+        }
+    }
+}
+
+/// テスト用にサービスの処理へ遅延を注入する設定
+///
+/// 優先度やQoSの設定が、処理の遅延や優先度の逆転が起きたときに期待どおり働くかを
+/// 制御された条件で確認するためのものです。遅延は[`Service::handle_request`]の処理前と、
+/// [`RealtimeService::send_realtime`]の送信前に加わります。本番環境では設定しないでください。
+#[derive(Debug, Clone, Default)]
+pub struct LatencyInjection {
+    /// 全てのリクエストの処理に加える遅延
+    pub processing_delay: Duration,
+    /// メソッドごとにリクエストの処理へ加える遅延（`processing_delay`に加算）
+    pub method_delays: HashMap<String, Duration>,
+    /// 優先度ごとにリアルタイム送信へ加える遅延
+    pub priority_delays: HashMap<ServicePriority, Duration>,
+    /// 遅延に加えるランダムな揺らぎの最大値
+    pub jitter: Duration,
+}
+
+impl LatencyInjection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 全てのリクエストの処理を遅らせる
+    pub fn with_processing_delay(mut self, delay: Duration) -> Self {
+        self.processing_delay = delay;
+        self
+    }
+
+    /// 指定のメソッドの処理を遅らせる
+    pub fn with_method_delay(mut self, method: impl Into<String>, delay: Duration) -> Self {
+        self.method_delays.insert(method.into(), delay);
+        self
+    }
+
+    /// 指定の優先度のリアルタイム送信を遅らせる
+    pub fn with_priority_delay(mut self, priority: ServicePriority, delay: Duration) -> Self {
+        self.priority_delays.insert(priority, delay);
+        self
+    }
+
+    /// 優先度が高いほど長く遅らせ、優先度の逆転を再現する
+    ///
+    /// `Low`は遅延なし、`Normal`は`step`、`High`は`step`の2倍、`Critical`は3倍遅れます。
+    pub fn with_priority_inversion(mut self, step: Duration) -> Self {
+        for priority in [
+            ServicePriority::Low,
+            ServicePriority::Normal,
+            ServicePriority::High,
+            ServicePriority::Critical,
+        ] {
+            self.priority_delays
+                .insert(priority, step * priority as u32);
+        }
+        self
+    }
+
+    /// 遅延にランダムな揺らぎを加える
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// リクエストの処理前に加える遅延（揺らぎを含まない）
+    pub fn request_delay(&self, method: &str) -> Duration {
+        self.processing_delay + self.method_delays.get(method).copied().unwrap_or_default()
+    }
+
+    /// リアルタイム送信前に加える遅延（揺らぎを含まない）
+    pub fn priority_delay(&self, priority: ServicePriority) -> Duration {
+        self.priority_delays
+            .get(&priority)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// 揺らぎを加えて待機する（遅延がない場合は待たない）
+    async fn sleep(&self, delay: Duration) {
+        let delay = delay + self.jitter.mul_f64(random_unit());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
         }
     }
 }
@@ -228,25 +332,71 @@ pub struct UnisonService {
     config: ServiceConfig,
     stream: Box<crate::network::quic::UnisonStream>,
     stats: ServiceStats,
+    /// メソッドごとの内訳
+    recorder: StatsRecorder,
+    /// ストリームが使っている接続の統計
+    meter: ConnectionMeter,
     start_time: std::time::Instant,
+    latency_injection: Option<LatencyInjection>,
 }
 
 impl UnisonService {
     pub fn new(config: ServiceConfig, stream: crate::network::quic::UnisonStream) -> Self {
         Self {
             config,
+            meter: ConnectionMeter::new(Some(stream.quic_connection())),
             stream: Box::new(stream),
             stats: ServiceStats::default(),
+            recorder: StatsRecorder::default(),
             start_time: std::time::Instant::now(),
+            latency_injection: None,
         }
     }
 
+    /// テスト用に処理の遅延を注入する
+    pub fn with_latency_injection(mut self, injection: LatencyInjection) -> Self {
+        self.latency_injection = Some(injection);
+        self
+    }
+
+    /// 遅延の注入を変更する（`None`で解除）
+    pub fn set_latency_injection(&mut self, injection: Option<LatencyInjection>) {
+        self.latency_injection = injection;
+    }
+
+    pub fn latency_injection(&self) -> Option<&LatencyInjection> {
+        self.latency_injection.as_ref()
+    }
+
     pub fn get_config(&self) -> &ServiceConfig {
         &self.config
     }
 
-    pub fn get_stats(&self) -> &ServiceStats {
-        &self.stats
+    /// 全体の集計とメソッドごとの内訳
+    ///
+    /// 処理時間は記録した呼び出しから、パケットロス率とジッターはストリームの接続から求めます。
+    pub fn get_stats(&self) -> ServiceStats {
+        let recorded = self.recorder.snapshot();
+        let mut stats = self.stats.clone();
+        stats.avg_latency_ms = recorded.avg_latency_ms;
+        stats.min_latency_ms = recorded.min_latency_ms;
+        stats.max_latency_ms = recorded.max_latency_ms;
+        stats.packet_loss_rate = self.meter.stats().packet_loss_rate();
+        stats.jitter_ms = self.meter.jitter_ms();
+        stats.uptime_seconds = self.start_time.elapsed().as_secs();
+        stats.methods = self.recorder.methods();
+        stats.streams = self.recorder.streams();
+        stats
+    }
+
+    /// ストリームが使っている接続の往復時間・送受信量など
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.meter.stats()
+    }
+
+    /// メソッドごとの内訳の集計（ストリームの記録などに使う）
+    pub fn stats_recorder(&self) -> &StatsRecorder {
+        &self.recorder
     }
 
     pub fn update_stats<F>(&mut self, updater: F)
@@ -313,10 +463,40 @@ impl Service for UnisonService {
     async fn handle_request(
         &mut self,
         method: &str,
-        _payload: serde_json::Value,
+        payload: serde_json::Value,
     ) -> Result<serde_json::Value, NetworkError> {
+        let started = std::time::Instant::now();
+        if let Some(injection) = &self.latency_injection {
+            injection.sleep(injection.request_delay(method)).await;
+        }
         self.stats.requests_processed += 1;
 
+        let result = self.dispatch_request(method);
+        self.recorder
+            .record_call(method, started.elapsed(), result.is_ok());
+        let bytes_out = result.as_ref().map_or(0, json_size);
+        self.recorder
+            .record_bytes(method, json_size(&payload), bytes_out);
+        result
+    }
+
+    fn get_capabilities(&self) -> Vec<String> {
+        vec![
+            "ping".to_string(),
+            "heartbeat".to_string(),
+            "get_stats".to_string(),
+            "get_capabilities".to_string(),
+        ]
+    }
+}
+
+/// JSON値をシリアライズしたときのバイト数
+fn json_size(value: &serde_json::Value) -> u64 {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len() as u64)
+}
+
+impl UnisonService {
+    fn dispatch_request(&mut self, method: &str) -> Result<serde_json::Value, NetworkError> {
         match method {
             "ping" => Ok(serde_json::json!({
                 "service": self.service_name(),
@@ -324,10 +504,7 @@ impl Service for UnisonService {
                 "status": "healthy",
                 "timestamp": chrono::Utc::now().to_rfc3339()
             })),
-            "get_stats" => {
-                self.stats.uptime_seconds = self.start_time.elapsed().as_secs();
-                Ok(serde_json::to_value(&self.stats).unwrap())
-            }
+            "get_stats" => Ok(serde_json::to_value(self.get_stats())?),
             "get_capabilities" => Ok(serde_json::json!({
                 "capabilities": self.get_capabilities(),
                 "service": self.service_name()
@@ -340,15 +517,6 @@ impl Service for UnisonService {
             }
         }
     }
-
-    fn get_capabilities(&self) -> Vec<String> {
-        vec![
-            "ping".to_string(),
-            "heartbeat".to_string(),
-            "get_stats".to_string(),
-            "get_capabilities".to_string(),
-        ]
-    }
 }
 
 impl RealtimeService for UnisonService {
@@ -358,6 +526,10 @@ impl RealtimeService for UnisonService {
         data: serde_json::Value,
         priority: ServicePriority,
     ) -> Result<(), NetworkError> {
+        if let Some(injection) = &self.latency_injection {
+            injection.sleep(injection.priority_delay(priority)).await;
+        }
+
         let realtime_data = serde_json::json!({
             "method": method,
             "data": data,
@@ -384,8 +556,42 @@ impl RealtimeService for UnisonService {
     }
 
     async fn get_performance_stats(&self) -> Result<ServiceStats, NetworkError> {
-        let mut stats = self.stats.clone();
-        stats.uptime_seconds = self.start_time.elapsed().as_secs();
-        Ok(stats)
+        Ok(self.get_stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_delay_adds_method_delay() {
+        let injection = LatencyInjection::new()
+            .with_processing_delay(Duration::from_millis(10))
+            .with_method_delay("slow", Duration::from_millis(40));
+        assert_eq!(injection.request_delay("slow"), Duration::from_millis(50));
+        assert_eq!(injection.request_delay("ping"), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_priority_inversion_delays_higher_priorities_more() {
+        let injection = LatencyInjection::new().with_priority_inversion(Duration::from_millis(5));
+        assert!(injection.priority_delay(ServicePriority::Low).is_zero());
+        assert_eq!(
+            injection.priority_delay(ServicePriority::Critical),
+            Duration::from_millis(15)
+        );
+        assert!(
+            injection.priority_delay(ServicePriority::High)
+                > injection.priority_delay(ServicePriority::Normal)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sleep_waits_for_delay() {
+        let injection = LatencyInjection::new().with_jitter(Duration::from_millis(5));
+        let started = std::time::Instant::now();
+        injection.sleep(Duration::from_millis(30)).await;
+        assert!(started.elapsed() >= Duration::from_millis(30));
     }
 }
@@ -0,0 +1,214 @@
+//! サーバーの段階的な停止
+//!
+//! [`ShutdownController`]で停止を要求すると、サーバーは次の順に停止します。
+//!
+//! 1. 新しいQUIC接続の受け付けを停止し、以降のリクエストを`UNAVAILABLE`で拒否
+//! 2. 接続中のクライアントへ[`GOAWAY_EVENT_METHOD`]の制御パケットを送信
+//! 3. 処理中のハンドラーの完了を猶予期間まで待機
+//! 4. すべての接続を閉じて`listen`から戻る
+
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, watch};
+
+/// サーバーが停止を通知するイベントのメソッド名
+pub const GOAWAY_EVENT_METHOD: &str = "unison.goaway";
+
+/// 停止時に接続を閉じるQUICのアプリケーションエラーコード
+pub const GOAWAY_CLOSE_CODE: u32 = 0x474f;
+
+/// 処理中のハンドラーの完了を待つ期間の既定値
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+/// 停止要求からの進捗
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DrainProgress {
+    /// 停止を要求した時点で処理中だったリクエスト数
+    pub initial_in_flight: usize,
+    /// 現在処理中のリクエスト数
+    pub in_flight: usize,
+    /// 停止要求からの経過時間（ミリ秒）
+    pub elapsed_ms: u64,
+    /// 停止要求時のリクエストのうち完了した割合（0.0〜1.0）
+    pub completed_ratio: f64,
+}
+
+/// サーバーの停止要求と処理中のリクエスト数を共有するハンドル
+///
+/// クライアントの[`ProtocolClient::shutdown`](super::ProtocolClient::shutdown)でも、
+/// レスポンスを待っている呼び出しの記録に使います。
+#[derive(Clone)]
+pub struct ShutdownController {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    stopping: watch::Sender<bool>,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    /// 停止を要求した時刻とその時点の処理中のリクエスト数
+    drain_started: OnceLock<(Instant, usize)>,
+}
+
+impl ShutdownController {
+    pub fn new() -> Self {
+        let (stopping, _) = watch::channel(false);
+        Self {
+            inner: Arc::new(Inner {
+                stopping,
+                in_flight: AtomicUsize::new(0),
+                idle: Notify::new(),
+                drain_started: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// 停止を要求
+    pub fn trigger(&self) {
+        self.inner
+            .drain_started
+            .get_or_init(|| (Instant::now(), self.in_flight()));
+        self.inner.stopping.send_replace(true);
+    }
+
+    /// 停止が要求されているか
+    pub fn is_stopping(&self) -> bool {
+        *self.inner.stopping.borrow()
+    }
+
+    /// 停止が要求されるまで待機
+    pub fn stopped(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut rx = self.inner.stopping.subscribe();
+        async move {
+            let _ = rx.wait_for(|stopping| *stopping).await;
+        }
+    }
+
+    /// 処理中のリクエスト数
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// 停止要求からの進捗（停止を要求していない場合は`None`）
+    pub fn drain_progress(&self) -> Option<DrainProgress> {
+        let (started, initial) = *self.inner.drain_started.get()?;
+        let in_flight = self.in_flight();
+        let completed_ratio = if initial == 0 {
+            1.0
+        } else {
+            initial.saturating_sub(in_flight) as f64 / initial as f64
+        };
+        Some(DrainProgress {
+            initial_in_flight: initial,
+            in_flight,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            completed_ratio,
+        })
+    }
+
+    /// リクエストの処理中であることを記録（ガードの破棄で完了）
+    pub fn track(&self) -> InFlightGuard {
+        self.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// 処理中のリクエストがなくなるまで最大`grace`待機
+    ///
+    /// 期間内にすべて完了した場合は`true`を返します。
+    pub async fn wait_idle(&self, grace: Duration) -> bool {
+        let wait = async {
+            loop {
+                let notified = self.inner.idle.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(grace, wait).await.is_ok()
+    }
+}
+
+impl Default for ShutdownController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 処理中のリクエストを表すガード
+pub struct InFlightGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_idle_after_requests_finish() {
+        let shutdown = ShutdownController::new();
+        assert!(shutdown.wait_idle(Duration::from_millis(10)).await);
+
+        let guard = shutdown.track();
+        let second = shutdown.track();
+        assert_eq!(shutdown.in_flight(), 2);
+        drop(second);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(guard);
+        });
+        assert!(shutdown.wait_idle(Duration::from_secs(5)).await);
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle_gives_up_after_grace() {
+        let shutdown = ShutdownController::new();
+        let _guard = shutdown.track();
+        assert!(!shutdown.wait_idle(Duration::from_millis(20)).await);
+    }
+
+    #[test]
+    fn test_drain_progress_after_trigger() {
+        let shutdown = ShutdownController::new();
+        assert!(shutdown.drain_progress().is_none());
+
+        let first = shutdown.track();
+        let _second = shutdown.track();
+        shutdown.trigger();
+        drop(first);
+        let progress = shutdown.drain_progress().unwrap();
+        assert_eq!(progress.initial_in_flight, 2);
+        assert_eq!(progress.in_flight, 1);
+        assert_eq!(progress.completed_ratio, 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_stopped_resolves_after_trigger() {
+        let shutdown = ShutdownController::new();
+        let stopped = tokio::spawn(shutdown.stopped());
+        assert!(!shutdown.is_stopping());
+
+        shutdown.trigger();
+        stopped.await.unwrap();
+        assert!(shutdown.is_stopping());
+        // 停止要求後の待機はすぐに戻る
+        shutdown.stopped().await;
+    }
+}
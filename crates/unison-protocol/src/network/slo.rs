@@ -0,0 +1,289 @@
+//! メソッドごとのレイテンシSLO
+//!
+//! メソッドごとに「直近の期間でp99が50ms未満」のような目標（[`LatencySlo`]）を宣言すると、
+//! サーバーは処理時間をローリング期間で集計し、目標を満たさなくなったときに通知します。
+//!
+//! - [`ProtocolServer::on_slo_violation`](super::ProtocolServer::on_slo_violation)で登録した
+//!   コールバックを呼び出します
+//! - トピック[`SLO_VIOLATION_TOPIC`]へ[`SloViolation`]をイベントとして発行するため、
+//!   監視用のクライアントが購読して自己監視できます
+//!
+//! 通知は目標を満たさなくなった時点で1回だけ行い、回復した後に再び違反すると再度通知します。
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// SLO違反を発行するトピック
+pub const SLO_VIOLATION_TOPIC: &str = "unison.admin.slo";
+
+/// 1メソッドで保持する処理時間の最大数（超えた分は古いものから破棄）
+const MAX_SAMPLES: usize = 10_000;
+
+/// レイテンシの目標
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySlo {
+    /// 対象のパーセンタイル（0.0〜100.0）
+    pub percentile: f64,
+    /// パーセンタイルの上限
+    pub threshold: Duration,
+    /// 集計するローリング期間
+    pub window: Duration,
+    /// 判定に必要な最小の処理数（少ない処理数での誤検知を防ぐ）
+    pub min_samples: usize,
+}
+
+impl LatencySlo {
+    /// 指定のパーセンタイルが`threshold`未満であることを目標にする
+    pub fn new(percentile: f64, threshold: Duration) -> Self {
+        Self {
+            percentile: percentile.clamp(0.0, 100.0),
+            threshold,
+            window: Duration::from_secs(60),
+            min_samples: 20,
+        }
+    }
+
+    /// p99が`threshold`未満であることを目標にする
+    pub fn p99(threshold: Duration) -> Self {
+        Self::new(99.0, threshold)
+    }
+
+    /// 中央値が`threshold`未満であることを目標にする
+    pub fn p50(threshold: Duration) -> Self {
+        Self::new(50.0, threshold)
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples.max(1);
+        self
+    }
+}
+
+/// SLO違反の通知
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SloViolation {
+    pub method: String,
+    pub percentile: f64,
+    pub threshold_ms: f64,
+    /// 期間内で観測したパーセンタイル
+    pub observed_ms: f64,
+    /// 期間内の処理数
+    pub samples: usize,
+    pub window_ms: u64,
+}
+
+/// メソッドのSLOの現在の状態
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SloStatus {
+    pub method: String,
+    /// 期間内で観測したパーセンタイル（処理数が足りない場合は`None`）
+    pub observed_ms: Option<f64>,
+    pub samples: usize,
+    pub violating: bool,
+}
+
+type ViolationCallback = Arc<dyn Fn(&SloViolation) + Send + Sync>;
+
+/// メソッドごとの処理時間
+struct MethodWindow {
+    slo: LatencySlo,
+    samples: VecDeque<(Instant, Duration)>,
+    violating: bool,
+}
+
+impl MethodWindow {
+    fn evict(&mut self, now: Instant) {
+        while let Some((at, _)) = self.samples.front() {
+            if now.duration_since(*at) <= self.slo.window && self.samples.len() <= MAX_SAMPLES {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    /// 期間内のパーセンタイル（処理数が足りない場合は`None`）
+    fn observed(&self) -> Option<Duration> {
+        if self.samples.len() < self.slo.min_samples {
+            return None;
+        }
+        let mut latencies: Vec<Duration> = self.samples.iter().map(|(_, l)| *l).collect();
+        latencies.sort_unstable();
+        let rank = (self.slo.percentile / 100.0 * latencies.len() as f64).ceil() as usize;
+        Some(latencies[rank.clamp(1, latencies.len()) - 1])
+    }
+}
+
+/// メソッドごとのSLOの集計
+#[derive(Clone, Default)]
+pub struct SloTracker {
+    methods: Arc<Mutex<HashMap<String, MethodWindow>>>,
+    callbacks: Arc<Mutex<Vec<ViolationCallback>>>,
+}
+
+impl SloTracker {
+    /// メソッドのSLOを宣言（宣言済みの場合は置き換え、集計をやり直す）
+    pub fn set(&self, method: &str, slo: LatencySlo) {
+        self.methods.lock().unwrap().insert(
+            method.to_string(),
+            MethodWindow {
+                slo,
+                samples: VecDeque::new(),
+                violating: false,
+            },
+        );
+    }
+
+    /// 違反時に呼び出すコールバックを追加
+    pub fn on_violation<F>(&self, callback: F)
+    where
+        F: Fn(&SloViolation) + Send + Sync + 'static,
+    {
+        self.callbacks.lock().unwrap().push(Arc::new(callback));
+    }
+
+    /// SLOを宣言したメソッドか
+    pub fn is_tracked(&self, method: &str) -> bool {
+        self.methods.lock().unwrap().contains_key(method)
+    }
+
+    /// 処理時間を記録し、新たに違反した場合は通知を返す
+    ///
+    /// コールバックはこの呼び出しの中で実行されます。
+    pub fn record(&self, method: &str, latency: Duration) -> Option<SloViolation> {
+        let violation = {
+            let mut methods = self.methods.lock().unwrap();
+            let window = methods.get_mut(method)?;
+            let now = Instant::now();
+            window.samples.push_back((now, latency));
+            window.evict(now);
+
+            let observed = window.observed()?;
+            let violating = observed >= window.slo.threshold;
+            let started = violating && !window.violating;
+            window.violating = violating;
+            if !started {
+                return None;
+            }
+            SloViolation {
+                method: method.to_string(),
+                percentile: window.slo.percentile,
+                threshold_ms: millis(window.slo.threshold),
+                observed_ms: millis(observed),
+                samples: window.samples.len(),
+                window_ms: window.slo.window.as_millis() as u64,
+            }
+        };
+
+        tracing::warn!(
+            "SLO violated for {}: p{} is {:.1}ms (threshold {:.1}ms)",
+            violation.method,
+            violation.percentile,
+            violation.observed_ms,
+            violation.threshold_ms
+        );
+        let callbacks = self.callbacks.lock().unwrap().clone();
+        for callback in callbacks {
+            callback(&violation);
+        }
+        Some(violation)
+    }
+
+    /// メソッドのSLOの現在の状態
+    pub fn status(&self, method: &str) -> Option<SloStatus> {
+        let mut methods = self.methods.lock().unwrap();
+        let window = methods.get_mut(method)?;
+        window.evict(Instant::now());
+        Some(SloStatus {
+            method: method.to_string(),
+            observed_ms: window.observed().map(millis),
+            samples: window.samples.len(),
+            violating: window.violating,
+        })
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_violation_is_reported_once_until_recovered() {
+        let tracker = SloTracker::default();
+        tracker.set(
+            "search",
+            LatencySlo::p99(Duration::from_millis(50)).with_min_samples(10),
+        );
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        tracker.on_violation(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        for _ in 0..9 {
+            assert!(
+                tracker
+                    .record("search", Duration::from_millis(100))
+                    .is_none()
+            );
+        }
+        let violation = tracker
+            .record("search", Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(violation.samples, 10);
+        assert_eq!(violation.observed_ms, 100.0);
+        assert!(
+            tracker
+                .record("search", Duration::from_millis(100))
+                .is_none()
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(tracker.status("search").unwrap().violating);
+    }
+
+    #[test]
+    fn test_percentile_ignores_outliers_below_rank() {
+        let tracker = SloTracker::default();
+        tracker.set(
+            "get",
+            LatencySlo::p50(Duration::from_millis(20)).with_min_samples(4),
+        );
+        for latency in [5, 5, 5, 500] {
+            assert!(
+                tracker
+                    .record("get", Duration::from_millis(latency))
+                    .is_none()
+            );
+        }
+        let status = tracker.status("get").unwrap();
+        assert_eq!(status.observed_ms, Some(5.0));
+        assert!(!status.violating);
+        assert!(tracker.record("other", Duration::from_secs(1)).is_none());
+    }
+
+    #[test]
+    fn test_samples_outside_window_are_dropped() {
+        let tracker = SloTracker::default();
+        tracker.set(
+            "slow",
+            LatencySlo::p99(Duration::from_millis(1))
+                .with_window(Duration::from_millis(20))
+                .with_min_samples(2),
+        );
+        tracker.record("slow", Duration::from_millis(5));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(tracker.record("slow", Duration::from_millis(5)).is_none());
+        assert_eq!(tracker.status("slow").unwrap().samples, 1);
+    }
+}
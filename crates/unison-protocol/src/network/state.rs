@@ -0,0 +1,222 @@
+//! クライアント接続の状態機械
+//!
+//! ```text
+//! Idle ──▶ Connecting ──▶ Handshaking ──▶ Ready
+//!  ▲           │               │            │
+//!  └───────────┴───────────────┴────────────┤ (失敗・切断)
+//!                                           ▼
+//!              Connecting ◀──────────── Reconnecting
+//!
+//! 任意の状態 ──▶ Closed（明示的な切断）
+//! ```
+//!
+//! 状態の変化は[`StateEvent`]として購読でき、UIへの接続状態の反映などに使えます。
+
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::{broadcast, watch};
+use tracing::warn;
+
+/// 状態イベントのバッファ数（遅い購読者はこれを超えた古いイベントを取りこぼす）
+const STATE_EVENT_CAPACITY: usize = 64;
+
+/// 接続状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// 未接続
+    Idle,
+    /// 接続先の解決・接続開始中
+    Connecting,
+    /// QUIC/TLSハンドシェイク中
+    Handshaking,
+    /// 通信可能
+    Ready,
+    /// 再接続中
+    Reconnecting,
+    /// 明示的に切断済み
+    Closed,
+}
+
+impl ConnectionState {
+    /// 状態遷移が許可されているか
+    pub fn can_transition_to(self, next: ConnectionState) -> bool {
+        use ConnectionState::*;
+        match (self, next) {
+            (_, Closed) => self != Closed,
+            (Idle | Closed | Reconnecting, Connecting) => true,
+            (Connecting, Handshaking) => true,
+            (Handshaking, Ready) => true,
+            (Ready | Idle, Reconnecting) => true,
+            (Connecting | Handshaking | Ready | Reconnecting, Idle) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConnectionState::Idle => "idle",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Handshaking => "handshaking",
+            ConnectionState::Ready => "ready",
+            ConnectionState::Reconnecting => "reconnecting",
+            ConnectionState::Closed => "closed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 状態遷移イベント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateEvent {
+    pub previous: ConnectionState,
+    pub current: ConnectionState,
+    /// 遷移の理由（接続失敗のエラーメッセージ等）
+    pub reason: Option<String>,
+    pub at: SystemTime,
+}
+
+/// 接続状態を保持し、遷移を通知する状態機械
+#[derive(Clone)]
+pub struct ConnectionStateMachine {
+    state: Arc<watch::Sender<ConnectionState>>,
+    events: broadcast::Sender<StateEvent>,
+    /// 遷移の判定と更新を直列化する
+    transition_lock: Arc<Mutex<()>>,
+}
+
+impl ConnectionStateMachine {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(ConnectionState::Idle);
+        let (events, _) = broadcast::channel(STATE_EVENT_CAPACITY);
+        Self {
+            state: Arc::new(state),
+            events,
+            transition_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// 現在の状態
+    pub fn current(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// 状態を遷移させる
+    ///
+    /// 許可されていない遷移は無視され、`false`を返します。
+    pub fn transition(&self, next: ConnectionState, reason: Option<String>) -> bool {
+        self.transition_if(|_| true, next, reason)
+    }
+
+    /// 現在の状態が条件を満たす場合のみ遷移させる
+    pub fn transition_if(
+        &self,
+        condition: impl FnOnce(ConnectionState) -> bool,
+        next: ConnectionState,
+        reason: Option<String>,
+    ) -> bool {
+        let _guard = self.transition_lock.lock().unwrap();
+        let previous = self.current();
+        if !condition(previous) {
+            return false;
+        }
+        if !previous.can_transition_to(next) {
+            warn!("Ignoring invalid connection state transition {previous} -> {next}");
+            return false;
+        }
+
+        self.state.send_replace(next);
+        // 購読者がいない場合の送信エラーは無視
+        let _ = self.events.send(StateEvent {
+            previous,
+            current: next,
+            reason,
+            at: SystemTime::now(),
+        });
+        true
+    }
+
+    /// 状態遷移イベントのストリーム
+    ///
+    /// 購読開始以降の遷移を受け取ります。処理が遅れて取りこぼしたイベントはスキップされます。
+    pub fn events(&self) -> Pin<Box<dyn Stream<Item = StateEvent> + Send>> {
+        let mut rx = self.events.subscribe();
+        Box::pin(async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => yield event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// 指定の状態になるまで待機
+    pub async fn wait_for(&self, target: ConnectionState) {
+        let mut rx = self.state.subscribe();
+        let _ = rx.wait_for(|state| *state == target).await;
+    }
+}
+
+impl Default for ConnectionStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ConnectionState::*;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn test_transition_rules() {
+        assert!(Idle.can_transition_to(Connecting));
+        assert!(Connecting.can_transition_to(Handshaking));
+        assert!(Handshaking.can_transition_to(Ready));
+        assert!(Ready.can_transition_to(Reconnecting));
+        assert!(Reconnecting.can_transition_to(Connecting));
+        assert!(Ready.can_transition_to(Closed));
+        assert!(!Idle.can_transition_to(Ready));
+        assert!(!Connecting.can_transition_to(Connecting));
+        assert!(!Closed.can_transition_to(Closed));
+    }
+
+    #[tokio::test]
+    async fn test_events_stream() {
+        let machine = ConnectionStateMachine::new();
+        let mut events = machine.events();
+
+        assert!(machine.transition(Connecting, None));
+        assert!(machine.transition(Handshaking, None));
+        assert!(!machine.transition(Connecting, None));
+        assert!(machine.transition(Ready, None));
+        assert!(!machine.transition_if(|s| s == Handshaking, Idle, None));
+        assert!(machine.transition(Closed, Some("client disconnect".to_string())));
+        assert_eq!(machine.current(), Closed);
+
+        let observed: Vec<(ConnectionState, ConnectionState)> = events
+            .by_ref()
+            .take(4)
+            .map(|e| (e.previous, e.current))
+            .collect()
+            .await;
+        assert_eq!(
+            observed,
+            vec![
+                (Idle, Connecting),
+                (Connecting, Handshaking),
+                (Handshaking, Ready),
+                (Ready, Closed),
+            ]
+        );
+    }
+}
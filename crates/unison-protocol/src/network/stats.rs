@@ -0,0 +1,299 @@
+//! メソッド・ストリームごとの統計
+//!
+//! [`StatsRecorder`]は呼び出しの回数・エラー・バイト数・処理時間をメソッドごとに、
+//! ストリームの開始数・開いている数・送出したアイテム数をメソッドごとに集計します。
+//! 集計結果は[`ServiceStats`]の`methods`・`streams`として取得できます。
+//!
+//! - サーバー: [`ProtocolServer::stats`](super::ProtocolServer::stats)、または
+//!   組み込みメソッド[`STATS_METHOD`]（テナントに紐づく接続からは使用できません）
+//! - サービス: [`UnisonService`](super::UnisonService)の`get_stats`
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::service::ServiceStats;
+
+/// サーバー全体の統計を返す組み込みメソッド
+pub const STATS_METHOD: &str = "unison.admin.stats";
+
+/// パーセンタイルの計算に使う直近の処理時間の数（メソッドごと）
+const MAX_LATENCY_SAMPLES: usize = 1024;
+
+/// メソッドごとの呼び出しの統計
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MethodStats {
+    pub calls: u64,
+    pub errors: u64,
+    /// 受信したリクエストのペイロードのバイト数
+    pub bytes_in: u64,
+    /// 送信したレスポンスのペイロードのバイト数
+    pub bytes_out: u64,
+    /// 起動からの平均
+    pub avg_latency_ms: f64,
+    pub min_latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// 直近の処理時間でのパーセンタイル
+    pub p50_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub p99_latency_ms: f64,
+}
+
+/// メソッドごとのストリームの統計
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamStats {
+    /// 開いたストリームの数
+    pub opened: u64,
+    /// 現在開いているストリームの数
+    pub active: u64,
+    /// 送出したアイテムの数
+    pub items: u64,
+    /// エラーで終わったストリームの数
+    pub errors: u64,
+}
+
+#[derive(Default)]
+struct MethodCounters {
+    calls: u64,
+    errors: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    total_latency: Duration,
+    min_latency: Option<Duration>,
+    max_latency: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl MethodCounters {
+    fn stats(&self) -> MethodStats {
+        let mut recent: Vec<Duration> = self.recent.iter().copied().collect();
+        recent.sort_unstable();
+        let percentile = |p: f64| {
+            if recent.is_empty() {
+                return 0.0;
+            }
+            let rank = (p / 100.0 * recent.len() as f64).ceil() as usize;
+            millis(recent[rank.clamp(1, recent.len()) - 1])
+        };
+        MethodStats {
+            calls: self.calls,
+            errors: self.errors,
+            bytes_in: self.bytes_in,
+            bytes_out: self.bytes_out,
+            avg_latency_ms: if self.calls == 0 {
+                0.0
+            } else {
+                millis(self.total_latency) / self.calls as f64
+            },
+            min_latency_ms: self.min_latency.map_or(0.0, millis),
+            max_latency_ms: millis(self.max_latency),
+            p50_latency_ms: percentile(50.0),
+            p95_latency_ms: percentile(95.0),
+            p99_latency_ms: percentile(99.0),
+        }
+    }
+}
+
+struct RecorderInner {
+    started: Instant,
+    methods: Mutex<HashMap<String, MethodCounters>>,
+    streams: Mutex<HashMap<String, StreamStats>>,
+}
+
+impl Default for RecorderInner {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            methods: Mutex::default(),
+            streams: Mutex::default(),
+        }
+    }
+}
+
+/// メソッド・ストリームごとの統計の集計
+#[derive(Clone, Default)]
+pub struct StatsRecorder {
+    inner: Arc<RecorderInner>,
+}
+
+impl StatsRecorder {
+    /// 呼び出しの結果と処理時間を記録
+    pub fn record_call(&self, method: &str, latency: Duration, ok: bool) {
+        let mut methods = self.inner.methods.lock().unwrap();
+        let counters = methods.entry(method.to_string()).or_default();
+        counters.calls += 1;
+        if !ok {
+            counters.errors += 1;
+        }
+        counters.total_latency += latency;
+        counters.min_latency = Some(counters.min_latency.map_or(latency, |min| min.min(latency)));
+        counters.max_latency = counters.max_latency.max(latency);
+        if counters.recent.len() == MAX_LATENCY_SAMPLES {
+            counters.recent.pop_front();
+        }
+        counters.recent.push_back(latency);
+    }
+
+    /// 呼び出しで送受信したペイロードのバイト数を記録
+    pub fn record_bytes(&self, method: &str, bytes_in: u64, bytes_out: u64) {
+        let mut methods = self.inner.methods.lock().unwrap();
+        let counters = methods.entry(method.to_string()).or_default();
+        counters.bytes_in += bytes_in;
+        counters.bytes_out += bytes_out;
+    }
+
+    /// ストリームを開いたことを記録（ガードの破棄で閉じたとみなす）
+    pub fn open_stream(&self, method: &str) -> StreamRecord {
+        self.update_stream(method, |stats| {
+            stats.opened += 1;
+            stats.active += 1;
+        });
+        StreamRecord {
+            recorder: self.clone(),
+            method: method.to_string(),
+            failed: false,
+        }
+    }
+
+    fn update_stream(&self, method: &str, update: impl FnOnce(&mut StreamStats)) {
+        let mut streams = self.inner.streams.lock().unwrap();
+        update(streams.entry(method.to_string()).or_default());
+    }
+
+    /// メソッドの呼び出しの統計
+    pub fn method(&self, method: &str) -> Option<MethodStats> {
+        let methods = self.inner.methods.lock().unwrap();
+        methods.get(method).map(MethodCounters::stats)
+    }
+
+    /// すべてのメソッドの呼び出しの統計
+    pub fn methods(&self) -> HashMap<String, MethodStats> {
+        let methods = self.inner.methods.lock().unwrap();
+        methods
+            .iter()
+            .map(|(method, counters)| (method.clone(), counters.stats()))
+            .collect()
+    }
+
+    /// すべてのメソッドのストリームの統計
+    pub fn streams(&self) -> HashMap<String, StreamStats> {
+        self.inner.streams.lock().unwrap().clone()
+    }
+
+    /// 全体の集計とメソッド・ストリームごとの内訳
+    pub fn snapshot(&self) -> ServiceStats {
+        let methods = self.methods();
+        let calls: u64 = methods.values().map(|stats| stats.calls).sum();
+        let weighted: f64 = methods
+            .values()
+            .map(|stats| stats.avg_latency_ms * stats.calls as f64)
+            .sum();
+        let called = || methods.values().filter(|stats| stats.calls > 0);
+        ServiceStats {
+            avg_latency_ms: if calls == 0 {
+                0.0
+            } else {
+                weighted / calls as f64
+            },
+            min_latency_ms: called()
+                .map(|stats| stats.min_latency_ms)
+                .reduce(f64::min)
+                .unwrap_or(0.0),
+            max_latency_ms: called()
+                .map(|stats| stats.max_latency_ms)
+                .fold(0.0, f64::max),
+            requests_processed: calls,
+            errors_count: methods.values().map(|stats| stats.errors).sum(),
+            uptime_seconds: self.inner.started.elapsed().as_secs(),
+            streams: self.streams(),
+            methods,
+            ..ServiceStats::default()
+        }
+    }
+}
+
+/// 開いているストリームの記録
+pub struct StreamRecord {
+    recorder: StatsRecorder,
+    method: String,
+    failed: bool,
+}
+
+impl StreamRecord {
+    /// アイテムを送出したことを記録
+    pub fn record_item(&self) {
+        self.recorder
+            .update_stream(&self.method, |stats| stats.items += 1);
+    }
+
+    /// ストリームがエラーで終わったことを記録（閉じたときに数える）
+    pub fn record_error(&mut self) {
+        self.failed = true;
+    }
+}
+
+impl Drop for StreamRecord {
+    fn drop(&mut self) {
+        let failed = self.failed;
+        self.recorder.update_stream(&self.method, |stats| {
+            stats.active = stats.active.saturating_sub(1);
+            if failed {
+                stats.errors += 1;
+            }
+        });
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_stats_track_calls_bytes_and_percentiles() {
+        let recorder = StatsRecorder::default();
+        for ms in 1..=100 {
+            recorder.record_call("get", Duration::from_millis(ms), ms % 10 != 0);
+        }
+        recorder.record_bytes("get", 10, 200);
+        recorder.record_call("put", Duration::from_millis(300), true);
+
+        let get = recorder.method("get").unwrap();
+        assert_eq!((get.calls, get.errors), (100, 10));
+        assert_eq!((get.bytes_in, get.bytes_out), (10, 200));
+        assert_eq!(get.min_latency_ms, 1.0);
+        assert_eq!(get.p50_latency_ms, 50.0);
+        assert_eq!(get.p95_latency_ms, 95.0);
+        assert_eq!(get.p99_latency_ms, 99.0);
+        assert!((get.avg_latency_ms - 50.5).abs() < 1e-9);
+
+        let total = recorder.snapshot();
+        assert_eq!((total.requests_processed, total.errors_count), (101, 10));
+        assert_eq!(total.min_latency_ms, 1.0);
+        assert_eq!(total.max_latency_ms, 300.0);
+        assert_eq!(total.methods.len(), 2);
+    }
+
+    #[test]
+    fn test_stream_records_close_on_drop() {
+        let recorder = StatsRecorder::default();
+        let first = recorder.open_stream("feed");
+        let mut second = recorder.open_stream("feed");
+        first.record_item();
+        first.record_item();
+        second.record_error();
+        assert_eq!(recorder.streams()["feed"].active, 2);
+
+        drop(first);
+        drop(second);
+        let feed = &recorder.streams()["feed"];
+        assert_eq!(
+            (feed.opened, feed.active, feed.items, feed.errors),
+            (2, 0, 2, 1)
+        );
+    }
+}
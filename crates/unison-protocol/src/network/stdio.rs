@@ -0,0 +1,881 @@
+//! 標準入出力のトランスポート
+//!
+//! Unisonのサービスを子プロセスとして起動し、LSPサーバーのように標準入出力で操作できるため、
+//! エディタやエージェントとの連携に使えます。既定ではQUICと同じ[`ProtocolFrame`]を
+//! 長さ接頭辞付きで読み書きします（[`framing`](super::framing)を参照）。
+//!
+//! [`WireFormat::Json`]を指定すると、1行に1つの[`ProtocolMessage`]をJSONで読み書きします
+//! （JSON Lines）。パケット層に対応していない古い相手や、人が読み書きする場合に使います。
+//!
+//! ```text
+//! → {"id":1,"method":"echo","type":"request","payload":{"text":"hi"}}
+//! ← {"id":1,"method":"echo","type":"response","payload":{"text":"hi"}}
+//! ```
+//!
+//! QUICのフレームと異なり、`payload`はJSON文字列ではなくJSON値のまま格納します。
+//! ブロードキャストやPub/Subのイベントも`type: "event"`の行として送られます。
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{Instrument, debug, error, warn};
+
+use super::broadcast::{ConnectionId, MessageSink};
+use super::client::response_error;
+use super::events::CloseReason;
+use super::framing::{Framing, WireFormat, is_malformed};
+use super::handler::HandlerResponse;
+use super::resume::StreamEvent;
+use super::server::{ProtocolServer, client_stream_item, stream_error_payload};
+use super::trace;
+use super::{MessageType, NetworkError, ProtocolError, ProtocolFrame, ProtocolMessage};
+
+/// 1行分のメッセージ
+#[derive(Serialize, Deserialize)]
+struct JsonLine {
+    id: u64,
+    method: String,
+    #[serde(rename = "type")]
+    msg_type: MessageType,
+    #[serde(default)]
+    payload: Value,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
+}
+
+/// メッセージを1行のJSON（改行を含まない）に変換
+pub fn encode_line(message: &ProtocolMessage) -> Result<String, NetworkError> {
+    let line = JsonLine {
+        id: message.id,
+        method: message.method.clone(),
+        msg_type: message.msg_type,
+        payload: message.payload_as_value()?,
+        metadata: message.metadata()?,
+    };
+    Ok(serde_json::to_string(&line)?)
+}
+
+/// 1行のJSONからメッセージを復元
+pub fn decode_line(line: &str) -> Result<ProtocolMessage, NetworkError> {
+    let line: JsonLine = serde_json::from_str(line)?;
+    ProtocolMessage::new_with_json(line.id, line.method, line.msg_type, line.payload)?
+        .with_metadata(&line.metadata)
+}
+
+/// 送信するメッセージを書き込むタスクを開始
+pub(crate) fn spawn_writer<W>(
+    writer: W,
+    framing: Framing,
+) -> (mpsc::UnboundedSender<ProtocolMessage>, JoinHandle<()>)
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<ProtocolMessage>();
+    let task = tokio::spawn(async move {
+        let mut writer = writer;
+        while let Some(message) = rx.recv().await {
+            match framing.write(&mut writer, &message).await {
+                Ok(()) => {}
+                Err(NetworkError::Connection(e)) => {
+                    error!("Failed to write message: {}", e);
+                    break;
+                }
+                Err(e) => error!("Failed to encode message: {}", e),
+            }
+        }
+    });
+    (tx, task)
+}
+
+/// ブロードキャストのフレームをメッセージとして書き込みタスクへ送る配信先
+pub(crate) struct ChannelSink {
+    pub(crate) tx: mpsc::UnboundedSender<ProtocolMessage>,
+}
+
+impl MessageSink for ChannelSink {
+    fn send_frame(
+        &self,
+        frame: Bytes,
+    ) -> Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send + '_>> {
+        Box::pin(async move {
+            let frame = ProtocolFrame::from_bytes(&frame)?;
+            let message = ProtocolMessage::from_frame(&frame)?;
+            self.tx
+                .send(message)
+                .map_err(|_| NetworkError::NotConnected)
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// 標準入出力でリクエストを処理するサーバー
+pub struct StdioServer {
+    server: Arc<ProtocolServer>,
+}
+
+impl StdioServer {
+    pub fn new(server: Arc<ProtocolServer>) -> Self {
+        Self { server }
+    }
+
+    /// 標準入力が閉じるか停止が要求されるまでリクエストを処理
+    pub async fn serve_stdio(&self) -> Result<(), NetworkError> {
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// 任意の入出力でリクエストを処理
+    ///
+    /// リクエストは並行して処理され、レスポンスは完了した順に書き込まれます。
+    /// 入力が閉じた後は処理中のリクエストの完了を待ってから戻ります。
+    pub async fn serve<R, W>(&self, reader: R, writer: W) -> Result<(), NetworkError>
+    where
+        R: AsyncRead + Unpin + Send,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let framing = Framing::from(self.server.wire_format());
+        serve_connection(&self.server, reader, writer, framing).await
+    }
+}
+
+/// バイトストリームから読み込んだメッセージの列
+///
+/// 内容を解釈できないメッセージはエラーとして返して読み込みを続け、
+/// 接続のエラーを返した後は終了します。
+pub(crate) fn framed_messages<R>(
+    reader: R,
+    framing: Framing,
+) -> impl Stream<Item = Result<ProtocolMessage, NetworkError>> + Send
+where
+    R: AsyncRead + Unpin + Send,
+{
+    futures_util::stream::unfold(Some(BufReader::new(reader)), move |reader| async move {
+        let mut reader = reader?;
+        match framing.read(&mut reader).await {
+            Ok(Some(message)) => Some((Ok(message), Some(reader))),
+            Ok(None) => None,
+            Err(e) if is_malformed(&e) => Some((Err(e), Some(reader))),
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+/// 1本のバイトストリームで接続したクライアントのリクエストを処理
+///
+/// 入力が閉じるか停止が要求されるまで処理し、処理中のリクエストの完了を待ってから戻ります。
+pub(crate) async fn serve_connection<R, W>(
+    server: &Arc<ProtocolServer>,
+    reader: R,
+    writer: W,
+    framing: Framing,
+) -> Result<(), NetworkError>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, writer_task) = spawn_writer(writer, framing);
+    let result = serve_messages(server, None, framed_messages(reader, framing), tx).await;
+    let _ = writer_task.await;
+    result
+}
+
+/// 受信したメッセージの列を処理し、レスポンスやイベントを`tx`へ送る
+///
+/// トランスポートに依存しない共通部分です。列が終わるか停止が要求されるまで処理し、
+/// 処理中のリクエストの完了を待ってから戻ります。
+pub(crate) async fn serve_messages<S>(
+    server: &Arc<ProtocolServer>,
+    peer: Option<SocketAddr>,
+    incoming: S,
+    tx: mpsc::UnboundedSender<ProtocolMessage>,
+) -> Result<(), NetworkError>
+where
+    S: Stream<Item = Result<ProtocolMessage, NetworkError>> + Send,
+{
+    let connection_id =
+        server.open_connection(Arc::new(ChannelSink { tx: tx.clone() }), peer, None);
+
+    let mut incoming = std::pin::pin!(incoming);
+    let stopped = server.shutdown_controller().stopped();
+    tokio::pin!(stopped);
+    let mut requests = JoinSet::new();
+    // 受信中のクライアントストリーム（リクエストIDからハンドラーへの送信側）
+    let mut client_streams: HashMap<u64, mpsc::UnboundedSender<ProtocolMessage>> = HashMap::new();
+    let result = loop {
+        let request = tokio::select! {
+            request = incoming.next() => request,
+            _ = &mut stopped => break Ok(()),
+        };
+        let request = match request {
+            Some(Ok(request)) => request,
+            None => break Ok(()),
+            Some(Err(e)) if is_malformed(&e) => {
+                warn!("Ignoring malformed message: {}", e);
+                let error = HandlerResponse::error(ProtocolError::new(
+                    ProtocolError::INVALID_REQUEST,
+                    e.to_string(),
+                ));
+                if let Ok(message) = error.into_message(0, String::new()) {
+                    let _ = tx.send(message);
+                }
+                continue;
+            }
+            Some(Err(e)) => break Err(e),
+        };
+
+        // 受信中のクライアントストリームへのメッセージは処理中のハンドラーへ渡す
+        let continues_client_stream = matches!(
+            request.msg_type,
+            MessageType::StreamData | MessageType::StreamEnd | MessageType::StreamError
+        );
+        if continues_client_stream && let Some(sender) = client_streams.get(&request.id) {
+            let finished = request.msg_type != MessageType::StreamData;
+            if sender.send(request).is_err() || finished {
+                client_streams.retain(|_, sender| !sender.is_closed());
+            }
+            continue;
+        }
+
+        let server = Arc::clone(server);
+        let tx = tx.clone();
+        let in_flight = server.shutdown_controller().track();
+        if request.msg_type == MessageType::ClientStream {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            client_streams.insert(request.id, sender);
+            requests.spawn(async move {
+                let _in_flight = in_flight;
+                dispatch_client_stream(&server, connection_id, request, receiver, &tx).await;
+            });
+            continue;
+        }
+        requests.spawn(async move {
+            let _in_flight = in_flight;
+            dispatch(&server, connection_id, request, &tx).await;
+        });
+    };
+    // 終わりを受け取っていないクライアントストリームは切断として終える
+    client_streams.clear();
+
+    while requests.join_next().await.is_some() {}
+    let reason = match &result {
+        Ok(()) if server.shutdown_controller().is_stopping() => CloseReason::ServerClosed,
+        Ok(()) => CloseReason::ClientClosed,
+        Err(e) => CloseReason::Error(e.to_string()),
+    };
+    server.connection_closed(connection_id, reason);
+    result
+}
+
+/// 1件のメッセージを処理してレスポンスを送信
+async fn dispatch(
+    server: &ProtocolServer,
+    connection_id: ConnectionId,
+    request: ProtocolMessage,
+    tx: &mpsc::UnboundedSender<ProtocolMessage>,
+) {
+    match request.msg_type {
+        MessageType::Request => {
+            let response = match server.bind_tenant(connection_id, &request) {
+                Ok(_) => {
+                    server
+                        .handle_request_message(connection_id, &request, None)
+                        .await
+                }
+                Err(e) => HandlerResponse::error(e),
+            };
+            match response.into_message(request.id, request.method) {
+                Ok(message) => {
+                    let _ = tx.send(message);
+                }
+                Err(e) => error!("Failed to create response: {}", e),
+            }
+        }
+        MessageType::Stream | MessageType::StreamResume => {
+            let send = |msg_type, payload| match ProtocolMessage::new_with_json(
+                request.id,
+                request.method.clone(),
+                msg_type,
+                payload,
+            ) {
+                Ok(message) => tx.send(message).is_ok(),
+                Err(e) => {
+                    error!("Failed to create stream message: {}", e);
+                    false
+                }
+            };
+            let opened = match server.bind_tenant(connection_id, &request) {
+                Ok(_) => server.open_stream(connection_id, &request).await,
+                Err(e) => Err(e.into()),
+            };
+            match opened {
+                Ok(mut stream) => {
+                    while let Some(item) = stream.next().await {
+                        let sent = match item {
+                            Ok(StreamEvent::Data(payload)) => {
+                                send(MessageType::StreamData, payload)
+                            }
+                            Ok(StreamEvent::ResumeToken(token)) => send(
+                                MessageType::StreamResumeToken,
+                                serde_json::to_value(token).unwrap_or_default(),
+                            ),
+                            Err(e) => {
+                                // エラーでストリームを終える
+                                send(
+                                    MessageType::StreamError,
+                                    serde_json::json!({ "message": e.to_string() }),
+                                );
+                                return;
+                            }
+                        };
+                        if !sent {
+                            return;
+                        }
+                    }
+                    send(MessageType::StreamEnd, serde_json::json!({}));
+                }
+                Err(e) => {
+                    send(MessageType::Error, stream_error_payload(&e));
+                }
+            }
+        }
+        other => debug!("Ignoring message of type {:?}", other),
+    }
+}
+
+/// クライアントストリームを処理し、1つのレスポンスを送信
+///
+/// 同じIDで届く`StreamData`を`StreamEnd`までハンドラーへ渡します。
+async fn dispatch_client_stream(
+    server: &ProtocolServer,
+    connection_id: ConnectionId,
+    request: ProtocolMessage,
+    receiver: mpsc::UnboundedReceiver<ProtocolMessage>,
+    tx: &mpsc::UnboundedSender<ProtocolMessage>,
+) {
+    let response = match server.bind_tenant(connection_id, &request) {
+        Ok(_) => {
+            let requests = futures_util::stream::unfold(Some(receiver), |receiver| async move {
+                let mut receiver = receiver?;
+                let item = match receiver.recv().await {
+                    Some(message) => client_stream_item(&message)?,
+                    None => Err(NetworkError::Connection(
+                        "Client stream ended before StreamEnd".to_string(),
+                    )),
+                };
+                let next = item.is_ok().then_some(receiver);
+                Some((item, next))
+            });
+            server
+                .handle_client_stream(connection_id, &request.method, Box::pin(requests))
+                .await
+        }
+        Err(e) => HandlerResponse::error(e),
+    };
+    match response.into_message(request.id, request.method) {
+        Ok(message) => {
+            let _ = tx.send(message);
+        }
+        Err(e) => error!("Failed to create response: {}", e),
+    }
+}
+
+type PendingCalls = Arc<StdMutex<HashMap<u64, oneshot::Sender<ProtocolMessage>>>>;
+type OpenStreams = Arc<StdMutex<HashMap<u64, mpsc::UnboundedSender<ProtocolMessage>>>>;
+
+/// 1本の接続でリクエストを送るクライアントの共通部分
+///
+/// レスポンスは呼び出しへ、[`Self::stream`]で開いたストリームのメッセージは
+/// そのストリームへ振り分け、それ以外はイベントとして[`Self::next_event`]で受信します。
+pub(crate) struct MessageClient {
+    outgoing: mpsc::UnboundedSender<ProtocolMessage>,
+    pending: PendingCalls,
+    streams: OpenStreams,
+    events: Mutex<mpsc::UnboundedReceiver<ProtocolMessage>>,
+    next_id: AtomicU64,
+    writer_task: JoinHandle<()>,
+    reader_task: JoinHandle<()>,
+}
+
+impl MessageClient {
+    pub(crate) fn new<R, W>(reader: R, writer: W, framing: Framing) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (outgoing, writer_task) = spawn_writer(writer, framing);
+        Self::from_parts(framed_messages(reader, framing), outgoing, writer_task)
+    }
+
+    /// 受信するメッセージの列と、送信するメッセージを書き込むタスクから作成
+    pub(crate) fn from_parts<S>(
+        incoming: S,
+        outgoing: mpsc::UnboundedSender<ProtocolMessage>,
+        writer_task: JoinHandle<()>,
+    ) -> Self
+    where
+        S: Stream<Item = Result<ProtocolMessage, NetworkError>> + Send + 'static,
+    {
+        let pending: PendingCalls = Arc::default();
+        let streams: OpenStreams = Arc::default();
+        let (event_tx, events) = mpsc::unbounded_channel();
+
+        let responses = Arc::clone(&pending);
+        let open_streams = Arc::clone(&streams);
+        let reader_task = tokio::spawn(async move {
+            let mut incoming = std::pin::pin!(incoming);
+            while let Some(message) = incoming.next().await {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) if is_malformed(&e) => {
+                        warn!("Ignoring malformed message: {}", e);
+                        continue;
+                    }
+                    Err(e) => {
+                        debug!("Connection closed: {}", e);
+                        break;
+                    }
+                };
+                if let Some(message) = route_response(&responses, message)
+                    && let Some(message) = route_stream(&open_streams, message)
+                {
+                    let _ = event_tx.send(message);
+                }
+            }
+            // 入力が閉じたら待機中の呼び出しとストリームを失敗させる
+            responses.lock().unwrap().clear();
+            open_streams.lock().unwrap().clear();
+        });
+
+        Self {
+            outgoing,
+            pending,
+            streams,
+            events: Mutex::new(events),
+            next_id: AtomicU64::new(1),
+            writer_task,
+            reader_task,
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub(crate) async fn call(&self, method: &str, payload: Value) -> Result<Value, NetworkError> {
+        let message =
+            ProtocolMessage::new_with_json(0, method.to_string(), MessageType::Request, payload)?;
+        let response = self.request(message).await?;
+        if response.msg_type == MessageType::Error {
+            return Err(response_error(&response));
+        }
+        response.payload_as_value()
+    }
+
+    /// リクエストのメッセージにIDを割り当てて送信し、レスポンス（エラーを含む）を待機
+    pub(crate) async fn request(
+        &self,
+        mut message: ProtocolMessage,
+    ) -> Result<ProtocolMessage, NetworkError> {
+        let id = self.next_id();
+        message.id = id;
+        let span = trace::client_span(&mut message);
+
+        let (waiter, response) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, waiter);
+        if self.outgoing.send(message).is_err() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(NetworkError::NotConnected);
+        }
+
+        response
+            .instrument(span)
+            .await
+            .map_err(|_| NetworkError::NotConnected)
+    }
+
+    /// ストリーミング呼び出しを開始し、受信したデータを順に返す
+    ///
+    /// 終了の通知を受ける前に接続が閉じた場合は`NotConnected`で終わります。
+    pub(crate) fn stream(
+        &self,
+        method: &str,
+        payload: Value,
+    ) -> Result<impl Stream<Item = Result<Value, NetworkError>> + Send + 'static, NetworkError>
+    {
+        let id = self.next_id();
+        let message =
+            ProtocolMessage::new_with_json(id, method.to_string(), MessageType::Stream, payload)?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.streams.lock().unwrap().insert(id, tx);
+        if self.outgoing.send(message).is_err() {
+            self.streams.lock().unwrap().remove(&id);
+            return Err(NetworkError::NotConnected);
+        }
+
+        Ok(futures_util::stream::unfold(Some(rx), |rx| async move {
+            let mut rx = rx?;
+            loop {
+                let Some(message) = rx.recv().await else {
+                    return Some((Err(NetworkError::NotConnected), None));
+                };
+                match message.msg_type {
+                    MessageType::StreamData => return Some((message.payload_as_value(), Some(rx))),
+                    MessageType::StreamEnd => return None,
+                    MessageType::Error | MessageType::StreamError => {
+                        return Some((Err(response_error(&message)), None));
+                    }
+                    _ => continue,
+                }
+            }
+        }))
+    }
+
+    /// クライアントストリームを送信し、サーバーからの1つのレスポンスを受信
+    ///
+    /// アイテムがエラーの場合は`StreamError`を送って中断し、そのエラーを返します。
+    /// サーバーが送信を終える前に応答した場合は、残りのアイテムを送らずに応答を返します。
+    pub(crate) async fn client_stream<S>(
+        &self,
+        method: &str,
+        requests: S,
+    ) -> Result<Value, NetworkError>
+    where
+        S: Stream<Item = Result<Value, NetworkError>> + Send,
+    {
+        let id = self.next_id();
+        let (waiter, response) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, waiter);
+
+        let send = |msg_type, payload| {
+            let message =
+                ProtocolMessage::new_with_json(id, method.to_string(), msg_type, payload)?;
+            self.outgoing
+                .send(message)
+                .map_err(|_| NetworkError::NotConnected)
+        };
+        let sending = async {
+            send(MessageType::ClientStream, Value::Null)?;
+            let mut requests = std::pin::pin!(requests);
+            while let Some(item) = requests.next().await {
+                match item {
+                    Ok(payload) => {
+                        send(MessageType::StreamData, payload)?;
+                        // 送信キューは待機しないため、応答の受信と書き込みに順番を譲る
+                        tokio::task::yield_now().await;
+                    }
+                    Err(e) => {
+                        send(
+                            MessageType::StreamError,
+                            serde_json::json!({ "message": e.to_string() }),
+                        )?;
+                        return Err(e);
+                    }
+                }
+            }
+            send(MessageType::StreamEnd, serde_json::json!({}))
+        };
+        tokio::pin!(sending, response);
+
+        let response = tokio::select! {
+            response = &mut response => response,
+            sent = &mut sending => match sent {
+                Ok(()) => response.await,
+                Err(e) => {
+                    self.pending.lock().unwrap().remove(&id);
+                    return Err(e);
+                }
+            },
+        };
+        let response = response.map_err(|_| NetworkError::NotConnected)?;
+        if response.msg_type == MessageType::Error {
+            return Err(response_error(&response));
+        }
+        response.payload_as_value()
+    }
+
+    pub(crate) async fn next_event(&self) -> Option<ProtocolMessage> {
+        self.events.lock().await.recv().await
+    }
+
+    /// 受信側のタスクが動作中か（接続が閉じると`false`）
+    pub(crate) fn is_open(&self) -> bool {
+        !self.reader_task.is_finished() && !self.outgoing.is_closed()
+    }
+
+    /// 送信側を閉じてサーバーに終了を促す
+    pub(crate) fn close_writer(&self) {
+        self.writer_task.abort();
+    }
+
+    /// 接続を閉じ、待機中の呼び出しとストリームを失敗させる
+    pub(crate) fn close(&self) {
+        self.writer_task.abort();
+        self.reader_task.abort();
+        self.pending.lock().unwrap().clear();
+        self.streams.lock().unwrap().clear();
+    }
+}
+
+/// 待機中の呼び出しへのレスポンスを渡す（該当しなければメッセージを返す）
+fn route_response(pending: &PendingCalls, message: ProtocolMessage) -> Option<ProtocolMessage> {
+    if !matches!(message.msg_type, MessageType::Response | MessageType::Error) {
+        return Some(message);
+    }
+    match pending.lock().unwrap().remove(&message.id) {
+        Some(waiter) => {
+            let _ = waiter.send(message);
+            None
+        }
+        None => Some(message),
+    }
+}
+
+/// 開いているストリームへメッセージを渡す（該当しなければメッセージを返す）
+fn route_stream(streams: &OpenStreams, message: ProtocolMessage) -> Option<ProtocolMessage> {
+    let finished = match message.msg_type {
+        MessageType::StreamData | MessageType::StreamResumeToken => false,
+        MessageType::StreamEnd | MessageType::StreamError | MessageType::Error => true,
+        _ => return Some(message),
+    };
+    let id = message.id;
+    let mut streams = streams.lock().unwrap();
+    let Some(tx) = streams.get(&id) else {
+        return Some(message);
+    };
+    if tx.send(message).is_err() || finished {
+        streams.remove(&id);
+    }
+    None
+}
+
+impl Drop for MessageClient {
+    fn drop(&mut self) {
+        self.writer_task.abort();
+        self.reader_task.abort();
+    }
+}
+
+/// 標準入出力で接続するクライアント
+///
+/// [`StdioClient::spawn`]でサービスを子プロセスとして起動するか、
+/// [`StdioClient::new`]で任意の入出力に接続します。
+pub struct StdioClient {
+    inner: MessageClient,
+    child: Option<tokio::process::Child>,
+}
+
+impl StdioClient {
+    /// 読み込み側（サーバーの出力）と書き込み側（サーバーの入力）を指定して接続
+    pub fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        Self::new_with_wire_format(reader, writer, WireFormat::default())
+    }
+
+    /// メッセージの形式を指定して接続
+    ///
+    /// JSON Linesのみに対応した古いサービスへは[`WireFormat::Json`]で接続します。
+    pub fn new_with_wire_format<R, W>(reader: R, writer: W, format: WireFormat) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        Self {
+            inner: MessageClient::new(reader, writer, format.into()),
+            child: None,
+        }
+    }
+
+    /// サービスを子プロセスとして起動して接続
+    ///
+    /// 子プロセスの標準入出力はクライアントが使うため上書きされます。
+    /// クライアントを破棄すると子プロセスも終了します。
+    pub fn spawn(command: &mut tokio::process::Command) -> Result<Self, NetworkError> {
+        let mut child = command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| NetworkError::Connection(format!("Failed to spawn service: {}", e)))?;
+        let stdin = child.stdin.take().ok_or(NetworkError::NotConnected)?;
+        let stdout = child.stdout.take().ok_or(NetworkError::NotConnected)?;
+
+        let mut client = Self::new(stdout, stdin);
+        client.child = Some(child);
+        Ok(client)
+    }
+
+    /// リクエストを送信してレスポンスを待機
+    pub async fn call(&self, method: &str, payload: Value) -> Result<Value, NetworkError> {
+        self.inner.call(method, payload).await
+    }
+
+    /// レスポンス以外のメッセージ（イベント・ストリームデータ）を受信
+    ///
+    /// 接続が閉じると`None`を返します。
+    pub async fn next_event(&self) -> Option<ProtocolMessage> {
+        self.inner.next_event().await
+    }
+
+    /// 子プロセスの終了を待機（`spawn`で起動した場合）
+    pub async fn wait(&mut self) -> Result<Option<std::process::ExitStatus>, NetworkError> {
+        // 入力を閉じてサーバーに終了を促す
+        self.inner.close_writer();
+        match &mut self.child {
+            Some(child) => child
+                .wait()
+                .await
+                .map(Some)
+                .map_err(|e| NetworkError::Connection(e.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(server: ProtocolServer) -> (StdioClient, JoinHandle<Result<(), NetworkError>>) {
+        let format = server.wire_format();
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        let (client_read, client_write) = tokio::io::split(client_io);
+
+        let server = StdioServer::new(Arc::new(server));
+        let serving = tokio::spawn(async move { server.serve(server_read, server_write).await });
+        let client = StdioClient::new_with_wire_format(client_read, client_write, format);
+        (client, serving)
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        let mut metadata = HashMap::new();
+        metadata.insert("tenant".to_string(), "acme".to_string());
+        let message = ProtocolMessage::new_with_json(
+            7,
+            "echo".into(),
+            MessageType::Request,
+            serde_json::json!({ "text": "hi" }),
+        )
+        .unwrap()
+        .with_metadata(&metadata)
+        .unwrap();
+
+        let line = encode_line(&message).unwrap();
+        assert!(!line.contains('\n'));
+        assert!(line.contains(r#""payload":{"text":"hi"}"#));
+        assert!(line.contains(r#""type":"request""#));
+
+        let restored = decode_line(&line).unwrap();
+        assert_eq!(restored.id, 7);
+        assert_eq!(restored.payload, message.payload);
+        assert_eq!(restored.metadata().unwrap(), metadata);
+    }
+
+    #[tokio::test]
+    async fn test_call_over_stdio() {
+        let server = ProtocolServer::new();
+        server
+            .register_call_handler(
+                "echo",
+                |payload| async move { Ok::<_, NetworkError>(payload) },
+            )
+            .await;
+        let (client, _serving) = connect(server);
+
+        let calls = (0..8).map(|n| client.call("echo", serde_json::json!({ "n": n })));
+        for (n, response) in futures_util::future::join_all(calls)
+            .await
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(response.unwrap()["n"], n);
+        }
+
+        let error = client.call("missing", serde_json::json!({})).await;
+        assert!(matches!(error, Err(NetworkError::Remote(e)) if e.code == "404"));
+    }
+
+    #[tokio::test]
+    async fn test_wire_formats() {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        let (client_read, mut client_write) = tokio::io::split(client_io);
+        let server = StdioServer::new(Arc::new(ProtocolServer::new()));
+        tokio::spawn(async move { server.serve(server_read, server_write).await });
+
+        // 既定では長さ接頭辞付きのパケットで応答する
+        let request = ProtocolMessage::new_with_json(
+            3,
+            "missing".into(),
+            MessageType::Request,
+            serde_json::json!({}),
+        )
+        .unwrap();
+        super::super::framing::write_frame(&mut client_write, &request)
+            .await
+            .unwrap();
+        let mut reader = BufReader::new(client_read);
+        let response = super::super::framing::read_frame(&mut reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((response.id, response.msg_type), (3, MessageType::Error));
+
+        // 互換用のJSON Linesでも同じハンドラーを呼び出せる
+        let server = ProtocolServer::new()
+            .with_wire_format(WireFormat::Json)
+            .with_call_handler(
+                "echo",
+                |payload| async move { Ok::<_, NetworkError>(payload) },
+            );
+        let (client, _serving) = connect(server);
+        let response = client.call("echo", serde_json::json!({ "n": 1 })).await;
+        assert_eq!(response.unwrap()["n"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_and_close() {
+        let server = ProtocolServer::new();
+        let registry = server.connections().clone();
+        let (client, serving) = connect(server);
+        // 接続の登録を待つ
+        while registry.is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let frame = ProtocolMessage::new_with_json(
+            0,
+            "notice".into(),
+            MessageType::Event,
+            serde_json::json!({ "text": "hello" }),
+        )
+        .unwrap()
+        .into_frame()
+        .unwrap();
+        registry.broadcast_frame(frame.to_bytes()).wait().await;
+        let event = client.next_event().await.unwrap();
+        assert_eq!(event.method, "notice");
+        assert_eq!(event.msg_type, MessageType::Event);
+
+        // クライアントを破棄すると入力が閉じてサーバーが戻る
+        drop(client);
+        serving.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+//! ストリームIDの割り当てと衝突の検出
+//!
+//! [`StreamIdScheme::Partitioned`]（既定）では、64ビットのストリームIDの上位32ビットに
+//! セッションのエポック、下位32ビットに開始した側ごとの連番を入れます。最下位ビットは
+//! 開始した側を表し、クライアントが開始したストリームは奇数、サーバーが開始した
+//! ストリームは偶数になります。
+//!
+//! エポックは接続ごとに割り当てるため、再起動や再接続の前後、別のピアとの間で同じIDが
+//! 使われることはありません。サーバーは受信したIDの偶奇とエポックを検証し、同じ接続で
+//! 使用中のIDと衝突した場合は[`ProtocolError::STREAM_ID_CONFLICT`]で拒否します。
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+use super::ProtocolError;
+use super::broadcast::ConnectionId;
+
+/// エポックごとに割り当てられる連番の上限
+pub const MAX_STREAM_SEQUENCE: u64 = (1 << 31) - 1;
+
+/// ストリームを開始した側
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamInitiator {
+    Client,
+    Server,
+}
+
+impl StreamInitiator {
+    /// IDの最下位ビット（クライアントは1、サーバーは0）
+    fn bit(self) -> u64 {
+        match self {
+            Self::Client => 1,
+            Self::Server => 0,
+        }
+    }
+
+    /// IDからストリームを開始した側を取り出す
+    pub fn of(stream_id: u64) -> Self {
+        if stream_id & 1 == 1 {
+            Self::Client
+        } else {
+            Self::Server
+        }
+    }
+}
+
+/// ストリームIDの割り当て方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamIdScheme {
+    /// 開始した側の偶奇とセッションのエポックで区切る
+    #[default]
+    Partitioned,
+    /// 1から始まる連番（旧来の割り当て。受信したIDは衝突だけを検出する）
+    Sequential,
+}
+
+/// ストリームIDの設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StreamIdConfig {
+    pub scheme: StreamIdScheme,
+    /// 割り当てに使うエポック（`None`の場合は接続ごとにランダムに決める）
+    pub epoch: Option<u32>,
+}
+
+impl StreamIdConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 旧来の連番で割り当てる
+    pub fn sequential() -> Self {
+        Self {
+            scheme: StreamIdScheme::Sequential,
+            epoch: None,
+        }
+    }
+
+    /// 再起動の回数などから決めたエポックを使う
+    pub fn with_epoch(mut self, epoch: u32) -> Self {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    /// 新しいセッションの割り当てを作成
+    pub fn allocator(&self, initiator: StreamInitiator) -> StreamIdAllocator {
+        let epoch = self.epoch.unwrap_or_else(random_epoch);
+        StreamIdAllocator::new(self.scheme, epoch, initiator)
+    }
+}
+
+/// 0以外のランダムなエポック（エポック0は旧来の連番と区別できないため使わない）
+fn random_epoch() -> u32 {
+    (uuid::Uuid::new_v4().as_u128() as u32).max(1)
+}
+
+/// IDのエポック
+pub fn stream_epoch(stream_id: u64) -> u32 {
+    (stream_id >> 32) as u32
+}
+
+/// ストリームIDの検証・割り当てのエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StreamIdError {
+    /// 受信したIDの偶奇が、開始した側と一致しない
+    #[error("Stream ID {stream_id} was not allocated by the {expected:?}")]
+    WrongInitiator {
+        stream_id: u64,
+        expected: StreamInitiator,
+    },
+    /// 受信したIDのエポックが、同じ接続の他のストリームと異なる
+    #[error("Stream ID {stream_id} belongs to epoch {actual}, expected {expected}")]
+    EpochMismatch {
+        stream_id: u64,
+        expected: u32,
+        actual: u32,
+    },
+    /// 同じ接続で使用中のIDと衝突した
+    #[error("Stream ID {0} is already in use")]
+    Collision(u64),
+    /// エポックの連番を使い切った
+    #[error("Stream IDs are exhausted for epoch {0}")]
+    Exhausted(u32),
+}
+
+impl From<StreamIdError> for ProtocolError {
+    fn from(error: StreamIdError) -> Self {
+        let details = match &error {
+            StreamIdError::WrongInitiator { stream_id, .. } => {
+                serde_json::json!({ "reason": "initiator", "stream_id": stream_id })
+            }
+            StreamIdError::EpochMismatch {
+                stream_id,
+                expected,
+                actual,
+            } => serde_json::json!({
+                "reason": "epoch",
+                "stream_id": stream_id,
+                "expected": expected,
+                "actual": actual,
+            }),
+            StreamIdError::Collision(stream_id) => {
+                serde_json::json!({ "reason": "collision", "stream_id": stream_id })
+            }
+            StreamIdError::Exhausted(epoch) => {
+                serde_json::json!({ "reason": "exhausted", "epoch": epoch })
+            }
+        };
+        ProtocolError::new(ProtocolError::STREAM_ID_CONFLICT, error.to_string())
+            .with_details(details)
+    }
+}
+
+/// 1つのセッションで自分が開始するストリームのIDを割り当てる
+#[derive(Debug)]
+pub struct StreamIdAllocator {
+    scheme: StreamIdScheme,
+    epoch: u32,
+    initiator: StreamInitiator,
+    next: AtomicU64,
+}
+
+impl StreamIdAllocator {
+    pub fn new(scheme: StreamIdScheme, epoch: u32, initiator: StreamInitiator) -> Self {
+        Self {
+            scheme,
+            epoch,
+            initiator,
+            next: AtomicU64::new(match scheme {
+                StreamIdScheme::Partitioned => 0,
+                StreamIdScheme::Sequential => 1,
+            }),
+        }
+    }
+
+    /// このセッションのエポック
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// 次のIDを割り当てる
+    pub fn allocate(&self) -> Result<u64, StreamIdError> {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        match self.scheme {
+            StreamIdScheme::Sequential => Ok(seq),
+            StreamIdScheme::Partitioned if seq > MAX_STREAM_SEQUENCE => {
+                Err(StreamIdError::Exhausted(self.epoch))
+            }
+            StreamIdScheme::Partitioned => {
+                Ok((u64::from(self.epoch) << 32) | (seq << 1) | self.initiator.bit())
+            }
+        }
+    }
+}
+
+/// 接続ごとに相手が開始したストリームのIDを検証し、使用中のIDを記録する
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StreamIdRegistry {
+    config: StreamIdConfig,
+    peers: Arc<Mutex<HashMap<ConnectionId, PeerStreams>>>,
+}
+
+#[derive(Debug, Default)]
+struct PeerStreams {
+    /// 最初に受信したストリームのエポック
+    epoch: Option<u32>,
+    active: HashSet<u64>,
+}
+
+impl StreamIdRegistry {
+    pub(crate) fn new(config: StreamIdConfig) -> Self {
+        Self {
+            config,
+            peers: Arc::default(),
+        }
+    }
+
+    pub(crate) fn config(&self) -> StreamIdConfig {
+        self.config
+    }
+
+    /// 相手が開始したストリームのIDを検証し、返した値を破棄するまで使用中として記録
+    pub(crate) fn claim(
+        &self,
+        connection_id: ConnectionId,
+        stream_id: u64,
+        initiator: StreamInitiator,
+    ) -> Result<StreamIdClaim, StreamIdError> {
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(connection_id).or_default();
+        if self.config.scheme == StreamIdScheme::Partitioned {
+            if StreamInitiator::of(stream_id) != initiator {
+                return Err(StreamIdError::WrongInitiator {
+                    stream_id,
+                    expected: initiator,
+                });
+            }
+            let actual = stream_epoch(stream_id);
+            let expected = *peer.epoch.get_or_insert(actual);
+            if actual != expected {
+                return Err(StreamIdError::EpochMismatch {
+                    stream_id,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        if !peer.active.insert(stream_id) {
+            return Err(StreamIdError::Collision(stream_id));
+        }
+        Ok(StreamIdClaim {
+            peers: Arc::clone(&self.peers),
+            connection_id,
+            stream_id,
+        })
+    }
+
+    /// 切断した接続の記録を削除
+    pub(crate) fn remove_connection(&self, connection_id: ConnectionId) {
+        self.peers.lock().unwrap().remove(&connection_id);
+    }
+}
+
+/// 使用中のストリームID（破棄すると解放される）
+#[derive(Debug)]
+pub(crate) struct StreamIdClaim {
+    peers: Arc<Mutex<HashMap<ConnectionId, PeerStreams>>>,
+    connection_id: ConnectionId,
+    stream_id: u64,
+}
+
+impl Drop for StreamIdClaim {
+    fn drop(&mut self) {
+        if let Some(peer) = self.peers.lock().unwrap().get_mut(&self.connection_id) {
+            peer.active.remove(&self.stream_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partitioned_ids_carry_epoch_and_initiator() {
+        let client = StreamIdConfig::new()
+            .with_epoch(7)
+            .allocator(StreamInitiator::Client);
+        let server = StreamIdConfig::new()
+            .with_epoch(7)
+            .allocator(StreamInitiator::Server);
+
+        let ids: Vec<u64> = (0..3).map(|_| client.allocate().unwrap()).collect();
+        assert_eq!(ids, vec![(7 << 32) | 1, (7 << 32) | 3, (7 << 32) | 5]);
+        assert!(ids.iter().all(|id| stream_epoch(*id) == 7));
+        assert!(
+            ids.iter()
+                .all(|id| StreamInitiator::of(*id) == StreamInitiator::Client)
+        );
+
+        let id = server.allocate().unwrap();
+        assert_eq!(StreamInitiator::of(id), StreamInitiator::Server);
+        assert!(!ids.contains(&id));
+    }
+
+    #[test]
+    fn test_random_epochs_differ_per_session() {
+        let config = StreamIdConfig::new();
+        let first = config.allocator(StreamInitiator::Client);
+        let second = config.allocator(StreamInitiator::Client);
+        assert_ne!(first.epoch(), 0);
+        assert_ne!(first.allocate().unwrap(), second.allocate().unwrap());
+    }
+
+    #[test]
+    fn test_exhausted_epoch_is_an_error() {
+        let allocator =
+            StreamIdAllocator::new(StreamIdScheme::Partitioned, 1, StreamInitiator::Client);
+        allocator.next.store(MAX_STREAM_SEQUENCE, Ordering::Relaxed);
+        assert!(allocator.allocate().is_ok());
+        assert_eq!(allocator.allocate(), Err(StreamIdError::Exhausted(1)));
+    }
+
+    #[test]
+    fn test_registry_rejects_invalid_and_colliding_ids() {
+        let registry = StreamIdRegistry::default();
+        let id = (3 << 32) | 1;
+
+        let claim = registry.claim(1, id, StreamInitiator::Client).unwrap();
+        assert_eq!(
+            registry.claim(1, id, StreamInitiator::Client).unwrap_err(),
+            StreamIdError::Collision(id)
+        );
+        // 別の接続では同じIDを使える
+        let _other = registry.claim(2, id, StreamInitiator::Client).unwrap();
+        // 解放した後は再び使える
+        drop(claim);
+        let _again = registry.claim(1, id, StreamInitiator::Client).unwrap();
+
+        assert!(matches!(
+            registry.claim(1, (3 << 32) | 2, StreamInitiator::Client),
+            Err(StreamIdError::WrongInitiator { .. })
+        ));
+        let error = registry
+            .claim(1, (4 << 32) | 1, StreamInitiator::Client)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            StreamIdError::EpochMismatch {
+                stream_id: (4 << 32) | 1,
+                expected: 3,
+                actual: 4,
+            }
+        );
+        let error = ProtocolError::from(error);
+        assert_eq!(error.code, ProtocolError::STREAM_ID_CONFLICT);
+        assert_eq!(error.details.unwrap()["reason"], "epoch");
+    }
+
+    #[test]
+    fn test_sequential_scheme_only_detects_collisions() {
+        let registry = StreamIdRegistry::new(StreamIdConfig::sequential());
+        let allocator = StreamIdConfig::sequential().allocator(StreamInitiator::Client);
+        let first = allocator.allocate().unwrap();
+        let second = allocator.allocate().unwrap();
+        assert_eq!((first, second), (1, 2));
+
+        let _first = registry.claim(1, first, StreamInitiator::Client).unwrap();
+        let _second = registry.claim(1, second, StreamInitiator::Client).unwrap();
+        assert_eq!(
+            registry
+                .claim(1, first, StreamInitiator::Client)
+                .unwrap_err(),
+            StreamIdError::Collision(first)
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! 子タスクの監督
+//!
+//! 接続ごとの受信タスクやリクエストの処理タスクを[`TaskSupervisor`]から起動すると、
+//! 切断・停止時にまとめて中断し、終了を待ってから戻れます。
+//! 起動したタスクが所有者より長く動き続けることはありません。
+//!
+//! [`TaskSupervisor::with_runtime`]で作成すると、タスクは指定したランタイムで実行されます。
+//!
+//! デバッグビルドでは、`shutdown`せずに所有者が破棄された時点で監督下のタスクが
+//! 残っている場合に警告を出力します（残ったタスクは中断されます）。
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::{AbortHandle, JoinSet};
+use tracing::warn;
+
+/// 子タスクをまとめて起動・中断・待機するハンドル
+///
+/// 複製したハンドルは同じタスクの集合を共有します。
+#[derive(Clone)]
+pub struct TaskSupervisor {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    name: &'static str,
+    /// タスクを実行するランタイム（`None`の場合は呼び出し元のランタイム）
+    runtime: Option<Handle>,
+    tasks: Mutex<JoinSet<()>>,
+}
+
+impl TaskSupervisor {
+    /// `name`はログで監督対象を識別するための名前
+    pub fn new(name: &'static str) -> Self {
+        Self::build(name, None)
+    }
+
+    /// タスクを指定のランタイムで実行する監督を作成
+    pub fn with_runtime(name: &'static str, runtime: Handle) -> Self {
+        Self::build(name, Some(runtime))
+    }
+
+    fn build(name: &'static str, runtime: Option<Handle>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                name,
+                runtime,
+                tasks: Mutex::new(JoinSet::new()),
+            }),
+        }
+    }
+
+    /// タスクを実行するランタイム（`None`の場合は呼び出し元のランタイム）
+    pub fn runtime(&self) -> Option<&Handle> {
+        self.inner.runtime.as_ref()
+    }
+
+    /// 監督下でタスクを起動
+    pub fn spawn<F>(&self, future: F) -> AbortHandle
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut tasks = self.inner.tasks.lock().unwrap();
+        // 終了済みのタスクを回収して集合が増え続けないようにする
+        while tasks.try_join_next().is_some() {}
+        match &self.inner.runtime {
+            Some(runtime) => tasks.spawn_on(future, runtime),
+            None => tasks.spawn(future),
+        }
+    }
+
+    /// 動作中のタスク数
+    pub fn len(&self) -> usize {
+        let mut tasks = self.inner.tasks.lock().unwrap();
+        while tasks.try_join_next().is_some() {}
+        tasks.len()
+    }
+
+    /// 動作中のタスクがないか
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// すべてのタスクを中断し、終了するまで待機
+    ///
+    /// 戻った時点で、それまでに起動したタスクはすべて終了しています。
+    /// 以降も新しいタスクを起動できます。
+    pub async fn shutdown(&self) {
+        let mut tasks = self.take();
+        tasks.abort_all();
+        while tasks.join_next().await.is_some() {}
+    }
+
+    /// タスクが自然に終了するまで最大`grace`待機し、残ったタスクを中断
+    ///
+    /// 期間内にすべて終了した場合は`true`を返します。
+    pub async fn join(&self, grace: Duration) -> bool {
+        let mut tasks = self.take();
+        let finished =
+            tokio::time::timeout(grace, async { while tasks.join_next().await.is_some() {} })
+                .await
+                .is_ok();
+        if !finished {
+            warn!(
+                "{} {} task(s) still running after {:?}, aborting",
+                tasks.len(),
+                self.inner.name,
+                grace
+            );
+            tasks.abort_all();
+            while tasks.join_next().await.is_some() {}
+        }
+        finished
+    }
+
+    /// すべてのタスクの中断を要求（終了は待たない）
+    pub fn abort_all(&self) {
+        self.inner.tasks.lock().unwrap().abort_all();
+    }
+
+    /// 所有者の破棄時に残っているタスクを中断
+    ///
+    /// タスクが監督のハンドルを保持している場合でも、中断によって解放されます。
+    pub(crate) fn release(&self) {
+        let mut tasks = self.inner.tasks.lock().unwrap();
+        while tasks.try_join_next().is_some() {}
+        if tasks.is_empty() {
+            return;
+        }
+        #[cfg(debug_assertions)]
+        warn!(
+            "{} {} task(s) leaked without shutdown, aborting",
+            tasks.len(),
+            self.inner.name
+        );
+        tasks.abort_all();
+    }
+
+    fn take(&self) -> JoinSet<()> {
+        std::mem::take(&mut *self.inner.tasks.lock().unwrap())
+    }
+}
+
+impl std::fmt::Debug for TaskSupervisor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskSupervisor")
+            .field("name", &self.inner.name)
+            .field("tasks", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 破棄されたら数を記録するガード
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_aborts_and_awaits_tasks() {
+        let supervisor = TaskSupervisor::new("test");
+        let dropped = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let guard = DropCounter(Arc::clone(&dropped));
+            supervisor.spawn(async move {
+                let _guard = guard;
+                std::future::pending::<()>().await;
+            });
+        }
+        assert_eq!(supervisor.len(), 3);
+
+        supervisor.shutdown().await;
+        assert_eq!(dropped.load(Ordering::SeqCst), 3);
+        assert!(supervisor.is_empty());
+
+        // 停止後も再び起動できる
+        supervisor.spawn(async {});
+        supervisor.shutdown().await;
+        assert!(supervisor.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_join_waits_then_aborts_stragglers() {
+        let supervisor = TaskSupervisor::new("test");
+        supervisor.spawn(tokio::time::sleep(Duration::from_millis(10)));
+        assert!(supervisor.join(Duration::from_secs(5)).await);
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let guard = DropCounter(Arc::clone(&dropped));
+        supervisor.spawn(async move {
+            let _guard = guard;
+            std::future::pending::<()>().await;
+        });
+        assert!(!supervisor.join(Duration::from_millis(10)).await);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_release_breaks_reference_cycles() {
+        let supervisor = TaskSupervisor::new("test");
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let guard = DropCounter(Arc::clone(&dropped));
+        let child = supervisor.clone();
+        supervisor.spawn(async move {
+            let _guard = guard;
+            let _child = child;
+            std::future::pending::<()>().await;
+        });
+
+        let weak = Arc::downgrade(&supervisor.inner);
+        supervisor.release();
+        drop(supervisor);
+        tokio::task::yield_now().await;
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_tasks_run_on_given_runtime() {
+        let io = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("unison-test-io")
+            .enable_all()
+            .build()
+            .unwrap();
+        let main = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let supervisor = TaskSupervisor::with_runtime("test", io.handle().clone());
+        let thread = main.block_on(async {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            supervisor.spawn(async move {
+                let _ = tx.send(std::thread::current().name().map(str::to_string));
+            });
+            rx.await.unwrap()
+        });
+        assert_eq!(thread.as_deref(), Some("unison-test-io"));
+        main.block_on(supervisor.shutdown());
+    }
+
+    #[tokio::test]
+    async fn test_finished_tasks_are_reaped() {
+        let supervisor = TaskSupervisor::new("test");
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        supervisor.spawn(async move {
+            let _ = tx.send(());
+        });
+        rx.await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(supervisor.is_empty());
+    }
+}
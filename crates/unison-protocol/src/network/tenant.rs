@@ -0,0 +1,372 @@
+//! マルチテナント（テナントの分離）
+//!
+//! 1つのサーバーで複数のテナントを扱うため、接続をテナントに紐づけて分離します。
+//!
+//! - テナントIDはリクエストのメタデータ（[`TENANT_METADATA_KEY`]）で指定し、
+//!   最初に指定したテナントに接続が固定されます（別のテナントへの切り替えは拒否）
+//! - ハンドラーは[`current_tenant`]で処理中のリクエストのテナントを取得できます
+//! - Pub/Subのトピックはテナントごとの名前空間（`tenant.<id>.<topic>`）に分離されます
+//! - テナントごとにリクエスト数・エラー数を集計し、レート制限を適用します
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use super::broadcast::ConnectionId;
+use super::pubsub::topic::TOPIC_SEPARATOR;
+use super::rate_limit::{RateLimit, TokenBucket};
+
+/// テナントIDを指定するメタデータのキー
+pub const TENANT_METADATA_KEY: &str = "tenant-id";
+
+/// テナントのトピックの名前空間の接頭辞
+pub const TENANT_TOPIC_PREFIX: &str = "tenant";
+
+/// テナントのエラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TenantError {
+    #[error("Invalid tenant id: '{0}'")]
+    InvalidId(String),
+    #[error("Connection is bound to tenant '{bound}' and cannot act as '{requested}'")]
+    Mismatch {
+        bound: TenantId,
+        requested: TenantId,
+    },
+    #[error("Rate limit exceeded for tenant '{tenant}', retry after {retry_after:?}")]
+    RateLimited {
+        tenant: TenantId,
+        retry_after: Duration,
+    },
+}
+
+/// テナントID
+///
+/// トピックの階層名に含めるため、英数字・`-`・`_`のみ使用できます。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn parse(id: &str) -> Result<Self, TenantError> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(TenantError::InvalidId(id.to_string()));
+        }
+        Ok(Self(id.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// トピックをテナントの名前空間へ変換
+    pub fn scope_topic(&self, topic: &str) -> String {
+        format!(
+            "{}{sep}{}{sep}{}",
+            TENANT_TOPIC_PREFIX,
+            self.0,
+            topic,
+            sep = TOPIC_SEPARATOR
+        )
+    }
+
+    /// テナントの名前空間のトピックから元のトピックを取り出す
+    pub fn unscope_topic<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        topic
+            .strip_prefix(TENANT_TOPIC_PREFIX)?
+            .strip_prefix(TOPIC_SEPARATOR)?
+            .strip_prefix(self.0.as_str())?
+            .strip_prefix(TOPIC_SEPARATOR)
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_TENANT: Option<TenantId>;
+}
+
+/// 処理中のリクエストのテナント
+///
+/// ハンドラーの外や、テナントに紐づかない接続からのリクエストでは`None`です。
+pub fn current_tenant() -> Option<TenantId> {
+    CURRENT_TENANT.try_with(Clone::clone).ok().flatten()
+}
+
+/// テナントを設定してFutureを実行
+pub async fn with_tenant<F: Future>(tenant: Option<TenantId>, future: F) -> F::Output {
+    CURRENT_TENANT.scope(tenant, future).await
+}
+
+/// テナントごとのレート制限（トークンバケット）
+pub type TenantRateLimit = RateLimit;
+
+/// マルチテナントの設定
+#[derive(Debug, Clone, Default)]
+pub struct TenantConfig {
+    /// 全テナントに適用するレート制限
+    pub rate_limit: Option<TenantRateLimit>,
+    /// テナントごとのレート制限（`rate_limit`より優先）
+    pub tenant_rate_limits: HashMap<TenantId, TenantRateLimit>,
+}
+
+impl TenantConfig {
+    pub fn with_rate_limit(mut self, rate_limit: TenantRateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn with_tenant_rate_limit(mut self, tenant: TenantId, rate_limit: TenantRateLimit) -> Self {
+        self.tenant_rate_limits.insert(tenant, rate_limit);
+        self
+    }
+
+    fn rate_limit_for(&self, tenant: &TenantId) -> Option<TenantRateLimit> {
+        self.tenant_rate_limits
+            .get(tenant)
+            .copied()
+            .or(self.rate_limit)
+    }
+}
+
+/// テナントごとの集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantStats {
+    /// 紐づいている接続数
+    pub connections: usize,
+    pub requests: u64,
+    pub errors: u64,
+    /// レート制限で拒否した数
+    pub rate_limited: u64,
+}
+
+#[derive(Default)]
+struct TenantState {
+    stats: TenantStats,
+    /// レート制限のトークン残量
+    bucket: Option<TokenBucket>,
+}
+
+#[derive(Default)]
+struct TenantsInner {
+    config: TenantConfig,
+    bindings: Mutex<HashMap<ConnectionId, TenantId>>,
+    states: Mutex<HashMap<TenantId, TenantState>>,
+}
+
+/// 接続とテナントの紐づけ・集計・レート制限
+#[derive(Clone, Default)]
+pub struct Tenants {
+    inner: Arc<TenantsInner>,
+}
+
+impl Tenants {
+    pub fn new(config: TenantConfig) -> Self {
+        Self {
+            inner: Arc::new(TenantsInner {
+                config,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &TenantConfig {
+        &self.inner.config
+    }
+
+    /// リクエストのメタデータから接続のテナントを決定
+    ///
+    /// 初めてテナントが指定された場合は接続をそのテナントに固定します。
+    /// メタデータにテナントがなければ、固定済みのテナント（なければ`None`）を返します。
+    pub fn bind(
+        &self,
+        connection_id: ConnectionId,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Option<TenantId>, TenantError> {
+        let requested = metadata
+            .get(TENANT_METADATA_KEY)
+            .map(|id| TenantId::parse(id))
+            .transpose()?;
+
+        let mut bindings = self.inner.bindings.lock().unwrap();
+        match (bindings.get(&connection_id), requested) {
+            (Some(bound), Some(requested)) if *bound != requested => Err(TenantError::Mismatch {
+                bound: bound.clone(),
+                requested,
+            }),
+            (Some(bound), _) => Ok(Some(bound.clone())),
+            (None, Some(requested)) => {
+                bindings.insert(connection_id, requested.clone());
+                self.update(&requested, |state| state.stats.connections += 1);
+                Ok(Some(requested))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// 接続のテナント
+    pub fn tenant_of(&self, connection_id: ConnectionId) -> Option<TenantId> {
+        self.inner
+            .bindings
+            .lock()
+            .unwrap()
+            .get(&connection_id)
+            .cloned()
+    }
+
+    /// 接続の紐づけを解除
+    pub fn unbind(&self, connection_id: ConnectionId) {
+        let removed = self.inner.bindings.lock().unwrap().remove(&connection_id);
+        if let Some(tenant) = removed {
+            self.update(&tenant, |state| {
+                state.stats.connections = state.stats.connections.saturating_sub(1)
+            });
+        }
+    }
+
+    /// レート制限を確認し、受け付ける場合はトークンを消費
+    pub fn check_rate(&self, tenant: &TenantId) -> Result<(), TenantError> {
+        let Some(limit) = self.inner.config.rate_limit_for(tenant) else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let mut states = self.inner.states.lock().unwrap();
+        let state = states.entry(tenant.clone()).or_default();
+        let bucket = state
+            .bucket
+            .get_or_insert_with(|| TokenBucket::full(&limit, now));
+        let Err(retry_after) = bucket.try_acquire(&limit, now) else {
+            return Ok(());
+        };
+        state.stats.rate_limited += 1;
+        Err(TenantError::RateLimited {
+            tenant: tenant.clone(),
+            retry_after,
+        })
+    }
+
+    /// リクエストの結果を集計
+    pub fn record(&self, tenant: &TenantId, ok: bool) {
+        self.update(tenant, |state| {
+            state.stats.requests += 1;
+            if !ok {
+                state.stats.errors += 1;
+            }
+        });
+    }
+
+    pub fn stats(&self, tenant: &TenantId) -> Option<TenantStats> {
+        self.inner
+            .states
+            .lock()
+            .unwrap()
+            .get(tenant)
+            .map(|state| state.stats)
+    }
+
+    /// 全テナントの集計（テナントID順）
+    pub fn all_stats(&self) -> Vec<(TenantId, TenantStats)> {
+        let mut stats: Vec<_> = self
+            .inner
+            .states
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tenant, state)| (tenant.clone(), state.stats))
+            .collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+
+    fn update(&self, tenant: &TenantId, f: impl FnOnce(&mut TenantState)) {
+        f(self
+            .inner
+            .states
+            .lock()
+            .unwrap()
+            .entry(tenant.clone())
+            .or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(tenant: &str) -> HashMap<String, String> {
+        HashMap::from([(TENANT_METADATA_KEY.to_string(), tenant.to_string())])
+    }
+
+    #[test]
+    fn test_bind_pins_connection_to_tenant() {
+        let tenants = Tenants::default();
+        assert_eq!(tenants.bind(1, &HashMap::new()).unwrap(), None);
+
+        let acme = TenantId::parse("acme").unwrap();
+        assert_eq!(
+            tenants.bind(1, &metadata("acme")).unwrap(),
+            Some(acme.clone())
+        );
+        // 以降はメタデータがなくても固定済みのテナント
+        assert_eq!(
+            tenants.bind(1, &HashMap::new()).unwrap(),
+            Some(acme.clone())
+        );
+        assert!(matches!(
+            tenants.bind(1, &metadata("globex")),
+            Err(TenantError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            tenants.bind(2, &metadata("acme.evil")),
+            Err(TenantError::InvalidId(_))
+        ));
+        assert_eq!(tenants.stats(&acme).unwrap().connections, 1);
+
+        tenants.unbind(1);
+        assert_eq!(tenants.tenant_of(1), None);
+        assert_eq!(tenants.stats(&acme).unwrap().connections, 0);
+    }
+
+    #[test]
+    fn test_topic_scoping() {
+        let acme = TenantId::parse("acme").unwrap();
+        let scoped = acme.scope_topic("sensors.room1");
+        assert_eq!(scoped, "tenant.acme.sensors.room1");
+        assert_eq!(acme.unscope_topic(&scoped), Some("sensors.room1"));
+
+        let globex = TenantId::parse("globex").unwrap();
+        assert_eq!(globex.unscope_topic(&scoped), None);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_and_context() {
+        let acme = TenantId::parse("acme").unwrap();
+        let tenants = Tenants::new(TenantConfig::default().with_rate_limit(TenantRateLimit {
+            requests_per_second: 1.0,
+            burst: 2,
+        }));
+        assert!(tenants.check_rate(&acme).is_ok());
+        assert!(tenants.check_rate(&acme).is_ok());
+        match tenants.check_rate(&acme) {
+            Err(TenantError::RateLimited { retry_after, .. }) => {
+                assert!(retry_after <= Duration::from_secs(1));
+            }
+            other => panic!("expected rate limit, got {:?}", other),
+        }
+        assert_eq!(tenants.stats(&acme).unwrap().rate_limited, 1);
+
+        assert_eq!(current_tenant(), None);
+        let seen = with_tenant(Some(acme.clone()), async { current_tenant() }).await;
+        assert_eq!(seen, Some(acme));
+    }
+}
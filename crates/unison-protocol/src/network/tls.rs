@@ -0,0 +1,954 @@
+//! QUIC接続のTLS設定
+//!
+//! [`TlsConfig`]は自身の証明書、相手の証明書の検証方法、クライアント証明書（mTLS）の要求、
+//! 接続先のサーバー名（SNI）をまとめたものです。サーバーは
+//! [`ProtocolServer::with_tls`](super::ProtocolServer::with_tls)、クライアントは
+//! [`QuicClient::with_tls`](super::QuicClient::with_tls)で指定します。指定しない場合は従来どおり
+//! 開発用の証明書を使い、クライアントはサーバーの証明書を検証しません。
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), unison::network::TlsError> {
+//! use unison::network::{ClientAuth, TlsConfig};
+//!
+//! // CAで署名したクライアント証明書を必須にするサーバー
+//! let server = TlsConfig::new()
+//!     .with_identity_files("certs/server.pem", "certs/server.key")?
+//!     .with_root_certificates_file("certs/ca.pem")?
+//!     .with_client_auth(ClientAuth::Required);
+//!
+//! // サーバーの証明書をCAで検証し、クライアント証明書を提示するクライアント
+//! let client = TlsConfig::new()
+//!     .with_identity_files("certs/client.pem", "certs/client.key")?
+//!     .with_root_certificates_file("certs/ca.pem")?
+//!     .with_server_name("api.example.com");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 公開CAやOSに登録済みの社内CAで発行された証明書は、[`TlsConfig::system_roots`]
+//! （[`Verification::SystemRoots`]）でOSの証明書ストアを使って検証できます。
+//!
+//! クライアントでは[`ClientTlsConfig`]で、サーバーの証明書を信頼する方法（ルート証明書・OSの証明書ストア・
+//! 証明書の固定・検証しない）を明示的に選べます。自己署名の証明書を使うサーバーには
+//! [`CertificatePin`]で証明書または公開鍵のハッシュを固定します。
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), unison::network::TlsError> {
+//! use unison::network::ClientTlsConfig;
+//!
+//! // 公開鍵のSHA-256（`sha256/`＋Base64）が一致するサーバーのみ信頼する
+//! let client = ClientTlsConfig::pinned([
+//!     "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".parse()?,
+//! ]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ファイルまたはコールバックから読み込んだ証明書は[`TlsConfig::reload_identity`]で読み込み直せます。
+//! 以降の新しい接続から新しい証明書を使い、既存の接続はそのまま維持されます。
+//! 稼働中のサーバーでの自動的な読み込み直しは
+//! [`ProtocolServer::with_certificate_reload`](super::ProtocolServer::with_certificate_reload)を参照してください。
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rustls::client::ResolvesClientCert;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{
+    CertificateError, ClientConfig as RustlsClientConfig, DigitallySignedStruct, RootCertStore,
+    ServerConfig as RustlsServerConfig, SignatureScheme,
+};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::SystemTime;
+use thiserror::Error;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use super::quic::SkipServerVerification;
+
+/// TLS設定のエラー
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("Invalid private key: {0}")]
+    InvalidKey(String),
+    #[error("No certificate is configured")]
+    MissingIdentity,
+    #[error("No trusted root certificates are configured")]
+    MissingRoots,
+    #[error("No certificate pins are configured")]
+    MissingPins,
+    #[error("Invalid certificate pin: {0}")]
+    InvalidPin(String),
+    #[error("TLS error: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// 相手の証明書の検証方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    /// 登録したルート証明書で検証する
+    #[default]
+    Roots,
+    /// OSの証明書ストア（Windows・macOS・Linuxのシステムの証明書）と登録したルート証明書で検証する
+    ///
+    /// 企業のCAなど、OSに登録済みの証明書をそのまま信頼できます。
+    SystemRoots,
+    /// 登録した[`CertificatePin`]のいずれかとサーバーの証明書が一致するか検証する（クライアントのみ）
+    ///
+    /// 自己署名の証明書を使うサーバー向けです。証明書チェーン・サーバー名・有効期限は検証しません。
+    Pinned,
+    /// 検証しない（開発・テスト専用、クライアントのみ）
+    Insecure,
+}
+
+/// サーバーがクライアント証明書を要求するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuth {
+    /// 要求しない
+    #[default]
+    None,
+    /// 提示された場合のみ検証する
+    Optional,
+    /// 必須（提示されない・検証できない接続は拒否する）
+    Required,
+}
+
+/// QUIC接続のTLS設定
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    identity: Option<Arc<Identity>>,
+    roots: Vec<CertificateDer<'static>>,
+    pins: Vec<CertificatePin>,
+    verification: Verification,
+    client_auth: ClientAuth,
+    server_name: Option<String>,
+}
+
+impl TlsConfig {
+    /// 相手の証明書をルート証明書で検証する設定
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 相手の証明書をOSの証明書ストアで検証する設定
+    pub fn system_roots() -> Self {
+        Self {
+            verification: Verification::SystemRoots,
+            ..Self::default()
+        }
+    }
+
+    /// 相手の証明書を検証しない設定（開発・テスト専用）
+    pub fn insecure() -> Self {
+        Self {
+            verification: Verification::Insecure,
+            ..Self::default()
+        }
+    }
+
+    /// 自身の証明書チェーンと秘密鍵（サーバー証明書、またはmTLSのクライアント証明書）
+    pub fn with_identity(
+        mut self,
+        certs: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, TlsError> {
+        self.identity = Some(Arc::new(Identity::new(certified_key(certs, &key)?, None)));
+        Ok(self)
+    }
+
+    /// 自身の証明書をファイルから読み込む（証明書はPEM、秘密鍵はPEMまたはDER）
+    ///
+    /// [`reload_identity`](Self::reload_identity)で同じファイルから読み込み直せます。
+    pub fn with_identity_files(
+        mut self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, TlsError> {
+        let source = IdentitySource::Files {
+            cert_path: cert_path.as_ref().to_path_buf(),
+            key_path: key_path.as_ref().to_path_buf(),
+        };
+        self.identity = Some(Arc::new(Identity::new(source.load()?, Some(source))));
+        Ok(self)
+    }
+
+    /// 自身の証明書をコールバックで読み込む（シークレットストアなどから取得する場合）
+    ///
+    /// [`reload_identity`](Self::reload_identity)を呼び出すたびにコールバックで読み込み直します。
+    pub fn with_identity_loader<F>(mut self, loader: F) -> Result<Self, TlsError>
+    where
+        F: Fn() -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), TlsError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let source = IdentitySource::Loader(Arc::new(loader));
+        self.identity = Some(Arc::new(Identity::new(source.load()?, Some(source))));
+        Ok(self)
+    }
+
+    /// 相手の証明書の検証に使うルート証明書を追加
+    pub fn with_root_certificate(mut self, cert: CertificateDer<'static>) -> Self {
+        self.roots.push(cert);
+        self
+    }
+
+    /// PEMファイルのルート証明書をすべて追加
+    pub fn with_root_certificates_file(mut self, path: impl AsRef<Path>) -> Result<Self, TlsError> {
+        self.roots.extend(read_certs(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// [`Verification::Pinned`]で受け入れるサーバーの証明書を追加
+    pub fn with_pinned_certificate(mut self, pin: CertificatePin) -> Self {
+        self.pins.push(pin);
+        self
+    }
+
+    pub fn with_verification(mut self, verification: Verification) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// クライアント証明書の要求（サーバーのみ）
+    ///
+    /// 要求する場合は、クライアント証明書の検証に使うルート証明書が必要です。
+    pub fn with_client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    /// 証明書の検証とSNIに使うサーバー名（クライアントのみ）
+    ///
+    /// 指定しない場合は接続先のホスト名（IPアドレスの場合は`localhost`）を使います。
+    pub fn with_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
+    pub fn verification(&self) -> Verification {
+        self.verification
+    }
+
+    pub fn pins(&self) -> &[CertificatePin] {
+        &self.pins
+    }
+
+    pub fn client_auth(&self) -> ClientAuth {
+        self.client_auth
+    }
+
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// 自身の証明書チェーン（未設定の場合は空）
+    pub fn certificates(&self) -> Vec<CertificateDer<'static>> {
+        self.identity
+            .as_ref()
+            .map(|identity| identity.current().cert.clone())
+            .unwrap_or_default()
+    }
+
+    /// ファイルまたはコールバックから証明書を読み込み直す
+    ///
+    /// 読み込みに失敗した場合は以前の証明書を使い続けます。
+    /// 証明書を直接指定した場合は何もせず`Ok(false)`を返します。
+    pub fn reload_identity(&self) -> Result<bool, TlsError> {
+        let Some(identity) = &self.identity else {
+            return Ok(false);
+        };
+        let Some(source) = &identity.source else {
+            return Ok(false);
+        };
+        *identity.current.write().unwrap() = Arc::new(source.load()?);
+        Ok(true)
+    }
+
+    /// 証明書をコールバックで読み込むか
+    pub fn has_identity_loader(&self) -> bool {
+        self.identity
+            .as_ref()
+            .is_some_and(|identity| matches!(identity.source, Some(IdentitySource::Loader(_))))
+    }
+
+    /// 証明書の読み込み元のファイル（ファイルから読み込んでいない場合は空）
+    pub fn identity_files(&self) -> Vec<PathBuf> {
+        match self
+            .identity
+            .as_ref()
+            .and_then(|identity| identity.source.as_ref())
+        {
+            Some(IdentitySource::Files {
+                cert_path,
+                key_path,
+            }) => vec![cert_path.clone(), key_path.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn root_store(&self) -> Result<RootCertStore, TlsError> {
+        let mut store = RootCertStore::empty();
+        if self.verification == Verification::SystemRoots {
+            // 読み込めない証明書はOSのストアにあっても無視する
+            store.add_parsable_certificates(system_roots().iter().cloned());
+        }
+        for cert in &self.roots {
+            store.add(cert.clone())?;
+        }
+        if store.is_empty() {
+            return Err(TlsError::MissingRoots);
+        }
+        Ok(store)
+    }
+
+    /// サーバー用のrustlsの設定
+    pub fn server_crypto(&self) -> Result<RustlsServerConfig, TlsError> {
+        let identity = self.identity.clone().ok_or(TlsError::MissingIdentity)?;
+        let builder = RustlsServerConfig::builder();
+        let builder = match self.client_auth {
+            ClientAuth::None => builder.with_no_client_auth(),
+            client_auth => {
+                let verifier = WebPkiClientVerifier::builder(Arc::new(self.root_store()?));
+                let verifier = match client_auth {
+                    ClientAuth::Optional => verifier.allow_unauthenticated(),
+                    _ => verifier,
+                };
+                let verifier = verifier
+                    .build()
+                    .map_err(|e| TlsError::InvalidCertificate(e.to_string()))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+        Ok(builder.with_cert_resolver(identity))
+    }
+
+    /// クライアント用のrustlsの設定
+    pub fn client_crypto(&self) -> Result<RustlsClientConfig, TlsError> {
+        let builder = match self.verification {
+            Verification::Roots | Verification::SystemRoots => {
+                RustlsClientConfig::builder().with_root_certificates(self.root_store()?)
+            }
+            Verification::Pinned => {
+                if self.pins.is_empty() {
+                    return Err(TlsError::MissingPins);
+                }
+                RustlsClientConfig::builder()
+                    .dangerous()
+                    .with_custom_certificate_verifier(Arc::new(PinnedServerVerifier {
+                        pins: self.pins.clone(),
+                        algorithms: rustls::crypto::ring::default_provider()
+                            .signature_verification_algorithms,
+                    }))
+            }
+            Verification::Insecure => RustlsClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(SkipServerVerification)),
+        };
+        Ok(match self.identity.clone() {
+            Some(identity) => builder.with_client_cert_resolver(identity),
+            None => builder.with_no_client_auth(),
+        })
+    }
+}
+
+/// サーバーの証明書を信頼する方法を明示的に選んだクライアントのTLS設定
+///
+/// [`TlsConfig`]と異なり既定値がなく、コンストラクタで検証方法を選びます。
+/// [`QuicClient::with_tls`](super::QuicClient::with_tls)と
+/// [`ProtocolClient::with_tls`](super::ProtocolClient::with_tls)に[`TlsConfig`]の代わりに渡せます。
+#[derive(Debug, Clone)]
+pub struct ClientTlsConfig {
+    tls: TlsConfig,
+}
+
+impl ClientTlsConfig {
+    /// 指定したルート証明書（トラストアンカー）で検証する
+    pub fn trust_anchors(roots: impl IntoIterator<Item = CertificateDer<'static>>) -> Self {
+        Self {
+            tls: TlsConfig {
+                roots: roots.into_iter().collect(),
+                ..TlsConfig::default()
+            },
+        }
+    }
+
+    /// PEMファイルのルート証明書で検証する
+    pub fn trust_anchors_file(path: impl AsRef<Path>) -> Result<Self, TlsError> {
+        Ok(Self {
+            tls: TlsConfig::new().with_root_certificates_file(path)?,
+        })
+    }
+
+    /// OSの証明書ストアで検証する
+    pub fn system_roots() -> Self {
+        Self {
+            tls: TlsConfig::system_roots(),
+        }
+    }
+
+    /// 証明書または公開鍵のハッシュがいずれかと一致するサーバーのみ信頼する
+    pub fn pinned(pins: impl IntoIterator<Item = CertificatePin>) -> Self {
+        Self {
+            tls: TlsConfig {
+                pins: pins.into_iter().collect(),
+                verification: Verification::Pinned,
+                ..TlsConfig::default()
+            },
+        }
+    }
+
+    /// 検証しない（開発・テスト専用）
+    pub fn insecure() -> Self {
+        Self {
+            tls: TlsConfig::insecure(),
+        }
+    }
+
+    /// mTLSで提示するクライアント証明書と秘密鍵
+    pub fn with_client_certificate(
+        mut self,
+        certs: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, TlsError> {
+        self.tls = self.tls.with_identity(certs, key)?;
+        Ok(self)
+    }
+
+    /// mTLSで提示するクライアント証明書をファイルから読み込む
+    pub fn with_client_certificate_files(
+        mut self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, TlsError> {
+        self.tls = self.tls.with_identity_files(cert_path, key_path)?;
+        Ok(self)
+    }
+
+    /// 証明書の検証とSNIに使うサーバー名
+    pub fn with_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.tls = self.tls.with_server_name(server_name);
+        self
+    }
+
+    pub fn verification(&self) -> Verification {
+        self.tls.verification()
+    }
+
+    pub fn tls(&self) -> &TlsConfig {
+        &self.tls
+    }
+}
+
+impl From<ClientTlsConfig> for TlsConfig {
+    fn from(config: ClientTlsConfig) -> Self {
+        config.tls
+    }
+}
+
+/// 固定するサーバーの証明書（SHA-256ハッシュ）
+///
+/// 文字列からは`sha256/`＋Base64（HPKPやcurlの`--pinnedpubkey`と同じ公開鍵のハッシュ）、
+/// またはコロン区切りの16進数（`openssl x509 -fingerprint -sha256`と同じ証明書のハッシュ）で作成できます。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CertificatePin {
+    /// 証明書（DER）全体のハッシュ
+    Certificate([u8; 32]),
+    /// 公開鍵（SubjectPublicKeyInfo）のハッシュ（同じ鍵で証明書を更新しても一致する）
+    PublicKey([u8; 32]),
+}
+
+impl CertificatePin {
+    /// 証明書全体のハッシュで固定する
+    pub fn certificate(cert: &CertificateDer<'_>) -> Self {
+        Self::Certificate(sha256(cert))
+    }
+
+    /// 証明書の公開鍵のハッシュで固定する
+    pub fn public_key(cert: &CertificateDer<'_>) -> Result<Self, TlsError> {
+        Ok(Self::PublicKey(sha256(&subject_public_key_info(cert)?)))
+    }
+
+    /// 証明書がこのハッシュと一致するか
+    pub fn matches(&self, cert: &CertificateDer<'_>) -> bool {
+        match self {
+            Self::Certificate(hash) => sha256(cert) == *hash,
+            Self::PublicKey(hash) => {
+                subject_public_key_info(cert).is_ok_and(|spki| sha256(&spki) == *hash)
+            }
+        }
+    }
+}
+
+impl FromStr for CertificatePin {
+    type Err = TlsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TlsError::InvalidPin(s.to_string());
+        if let Some(encoded) = s.strip_prefix("sha256/") {
+            let hash = BASE64.decode(encoded.trim()).map_err(|_| invalid())?;
+            return hash.try_into().map(Self::PublicKey).map_err(|_| invalid());
+        }
+        let hash = s
+            .trim()
+            .split(':')
+            .map(|byte| match byte.len() {
+                2 => u8::from_str_radix(byte, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        hash.try_into()
+            .map(Self::Certificate)
+            .map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for CertificatePin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PublicKey(hash) => write!(f, "sha256/{}", BASE64.encode(hash)),
+            Self::Certificate(hash) => {
+                for (i, byte) in hash.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(":")?;
+                    }
+                    write!(f, "{:02X}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let digest = ring::digest::digest(&ring::digest::SHA256, data);
+    digest
+        .as_ref()
+        .try_into()
+        .expect("SHA-256 digest is 32 bytes")
+}
+
+fn subject_public_key_info(cert: &CertificateDer<'_>) -> Result<Vec<u8>, TlsError> {
+    let cert = webpki::EndEntityCert::try_from(cert)
+        .map_err(|e| TlsError::InvalidCertificate(e.to_string()))?;
+    Ok(cert.subject_public_key_info().as_ref().to_vec())
+}
+
+/// サーバーの証明書を固定したハッシュで検証する
+///
+/// 中間証明書はサーバーが自由に提示できるため、照合するのはサーバー自身の証明書のみです。
+/// ハンドシェイクの署名は通常どおり検証し、証明書の秘密鍵を持っていることを確認します。
+#[derive(Debug)]
+struct PinnedServerVerifier {
+    pins: Vec<CertificatePin>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if self.pins.iter().any(|pin| pin.matches(end_entity)) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// 証明書を読み込むコールバック
+type IdentityLoader = Arc<
+    dyn Fn() -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), TlsError>
+        + Send
+        + Sync,
+>;
+
+/// 読み込み直せる証明書の読み込み元
+#[derive(Clone)]
+enum IdentitySource {
+    Files {
+        cert_path: PathBuf,
+        key_path: PathBuf,
+    },
+    Loader(IdentityLoader),
+}
+
+impl IdentitySource {
+    fn load(&self) -> Result<CertifiedKey, TlsError> {
+        let (certs, key) = match self {
+            Self::Files {
+                cert_path,
+                key_path,
+            } => (read_certs(cert_path)?, read_key(key_path)?),
+            Self::Loader(loader) => loader()?,
+        };
+        certified_key(certs, &key)
+    }
+}
+
+impl fmt::Debug for IdentitySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Files {
+                cert_path,
+                key_path,
+            } => f
+                .debug_struct("Files")
+                .field("cert_path", cert_path)
+                .field("key_path", key_path)
+                .finish(),
+            Self::Loader(_) => f.write_str("Loader"),
+        }
+    }
+}
+
+/// 入れ替え可能な自身の証明書
+struct Identity {
+    current: RwLock<Arc<CertifiedKey>>,
+    source: Option<IdentitySource>,
+}
+
+impl Identity {
+    fn new(key: CertifiedKey, source: Option<IdentitySource>) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(key)),
+            source,
+        }
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("certificates", &self.current().cert.len())
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl ResolvesServerCert for Identity {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+impl ResolvesClientCert for Identity {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// OSの証明書ストアのルート証明書（初回のみ読み込み、以降は同じものを使う）
+fn system_roots() -> &'static [CertificateDer<'static>] {
+    static ROOTS: OnceLock<Vec<CertificateDer<'static>>> = OnceLock::new();
+    ROOTS.get_or_init(|| {
+        let result = rustls_native_certs::load_native_certs();
+        for error in &result.errors {
+            warn!("Failed to load a system root certificate: {}", error);
+        }
+        result.certs
+    })
+}
+
+/// 稼働中のサーバーに証明書の読み込み直しを要求するハンドル
+///
+/// [`ProtocolServer::certificate_reloader`](super::ProtocolServer::certificate_reloader)で取得し、
+/// サーバーを`listen`に渡した後も使えます（シグナルを受けたときに読み込み直す場合など）。
+#[derive(Debug, Clone, Default)]
+pub struct CertificateReloader {
+    requests: Arc<Notify>,
+}
+
+impl CertificateReloader {
+    /// 証明書の読み込み直しを要求
+    pub fn reload(&self) {
+        self.requests.notify_one();
+    }
+
+    pub(crate) async fn requested(&self) {
+        self.requests.notified().await;
+    }
+}
+
+/// ファイルの更新を更新時刻とサイズで検出する
+#[derive(Debug)]
+pub(crate) struct FileWatcher {
+    files: Vec<PathBuf>,
+    last: Vec<Option<(SystemTime, u64)>>,
+}
+
+impl FileWatcher {
+    pub(crate) fn new(files: Vec<PathBuf>) -> Self {
+        let last = files.iter().map(|path| file_version(path)).collect();
+        Self { files, last }
+    }
+
+    /// 前回から更新されたファイルがあるか
+    pub(crate) fn changed(&mut self) -> bool {
+        let current: Vec<_> = self.files.iter().map(|path| file_version(path)).collect();
+        let changed = current != self.last;
+        self.last = current;
+        changed
+    }
+}
+
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn certified_key(
+    certs: Vec<CertificateDer<'static>>,
+    key: &PrivateKeyDer<'static>,
+) -> Result<CertifiedKey, TlsError> {
+    if certs.is_empty() {
+        return Err(TlsError::InvalidCertificate("no certificates found".into()));
+    }
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(key)
+        .map_err(|e| TlsError::InvalidKey(e.to_string()))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path).map_err(|source| TlsError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let pem = read_file(path)?;
+    rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::InvalidCertificate(format!("{}: {}", path.display(), e)))
+}
+
+/// PEM（PKCS#8・PKCS#1・SEC1）またはDERの秘密鍵を読み込む
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let data = read_file(path)?;
+    if let Ok(Some(key)) = rustls_pemfile::private_key(&mut data.as_slice()) {
+        return Ok(key);
+    }
+    PrivateKeyDer::try_from(data)
+        .map_err(|e| TlsError::InvalidKey(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed(name: &str) -> (String, String) {
+        let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        (cert.cert.pem(), cert.key_pair.serialize_pem())
+    }
+
+    #[test]
+    fn test_reload_identity_from_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        let (cert, key) = self_signed("first.example");
+        std::fs::write(&cert_path, &cert).unwrap();
+        std::fs::write(&key_path, &key).unwrap();
+
+        let config = TlsConfig::new()
+            .with_identity_files(&cert_path, &key_path)
+            .unwrap();
+        let first = config.certificates();
+        assert_eq!(first.len(), 1);
+        assert_eq!(
+            config.identity_files(),
+            vec![cert_path.clone(), key_path.clone()]
+        );
+        let mut watcher = FileWatcher::new(config.identity_files());
+        assert!(!watcher.changed());
+        assert!(config.server_crypto().is_ok());
+
+        let (cert, key) = self_signed("second.example");
+        std::fs::write(&cert_path, &cert).unwrap();
+        std::fs::write(&key_path, &key).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        assert!(config.reload_identity().unwrap());
+        assert_ne!(config.certificates(), first);
+
+        // 壊れたファイルでは以前の証明書を使い続ける
+        let reloaded = config.certificates();
+        std::fs::write(&key_path, b"broken").unwrap();
+        assert!(config.reload_identity().is_err());
+        assert_eq!(config.certificates(), reloaded);
+    }
+
+    #[test]
+    fn test_missing_identity_and_roots() {
+        assert!(matches!(
+            TlsConfig::new().server_crypto(),
+            Err(TlsError::MissingIdentity)
+        ));
+        assert!(matches!(
+            TlsConfig::new().client_crypto(),
+            Err(TlsError::MissingRoots)
+        ));
+        assert!(TlsConfig::insecure().client_crypto().is_ok());
+
+        // クライアント証明書を要求するサーバーには検証用のルート証明書が必要
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let identity = TlsConfig::new()
+            .with_identity(
+                vec![cert.cert.der().clone()],
+                PrivateKeyDer::try_from(cert.key_pair.serialize_der()).unwrap(),
+            )
+            .unwrap();
+        let required = identity.clone().with_client_auth(ClientAuth::Required);
+        assert!(matches!(
+            required.server_crypto(),
+            Err(TlsError::MissingRoots)
+        ));
+        assert!(
+            required
+                .with_root_certificate(cert.cert.der().clone())
+                .server_crypto()
+                .is_ok()
+        );
+        assert!(!identity.reload_identity().unwrap());
+        assert!(identity.identity_files().is_empty());
+    }
+
+    #[test]
+    fn test_system_roots_include_configured_roots() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let config = TlsConfig::new()
+            .with_verification(Verification::SystemRoots)
+            .with_root_certificate(cert.cert.der().clone());
+        let store = config.root_store().unwrap();
+        assert_eq!(store.len(), system_roots().len() + 1);
+        assert!(config.client_crypto().is_ok());
+    }
+
+    #[test]
+    fn test_certificate_pins() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let other = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let (der, other_der) = (cert.cert.der(), other.cert.der());
+
+        let by_cert = CertificatePin::certificate(der);
+        let by_key = CertificatePin::public_key(der).unwrap();
+        assert!(by_cert.matches(der) && by_key.matches(der));
+        assert!(!by_cert.matches(other_der) && !by_key.matches(other_der));
+
+        // 同じ鍵で発行し直した証明書は公開鍵のハッシュのみ一致する
+        let reissued = rcgen::CertificateParams::new(vec!["example.com".to_string()])
+            .unwrap()
+            .self_signed(&cert.key_pair)
+            .unwrap();
+        assert!(by_key.matches(reissued.der()));
+        assert!(!by_cert.matches(reissued.der()));
+
+        for pin in [by_cert, by_key] {
+            assert_eq!(pin.to_string().parse::<CertificatePin>().unwrap(), pin);
+        }
+        assert!(matches!(
+            "sha256/AAAA".parse::<CertificatePin>(),
+            Err(TlsError::InvalidPin(_))
+        ));
+        assert!("AB:CD".parse::<CertificatePin>().is_err());
+    }
+
+    #[test]
+    fn test_client_tls_config_selects_verification() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let pinned = ClientTlsConfig::pinned([CertificatePin::certificate(cert.cert.der())]);
+        assert_eq!(pinned.verification(), Verification::Pinned);
+        assert!(TlsConfig::from(pinned).client_crypto().is_ok());
+        assert!(matches!(
+            ClientTlsConfig::pinned([]).tls().client_crypto(),
+            Err(TlsError::MissingPins)
+        ));
+
+        let anchors = ClientTlsConfig::trust_anchors([cert.cert.der().clone()]);
+        assert_eq!(anchors.verification(), Verification::Roots);
+        assert!(anchors.tls().client_crypto().is_ok());
+        assert_eq!(
+            ClientTlsConfig::insecure().verification(),
+            Verification::Insecure
+        );
+    }
+
+    #[test]
+    fn test_reload_identity_from_loader() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let config = TlsConfig::new()
+            .with_identity_loader(move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+                Ok((
+                    vec![cert.cert.der().clone()],
+                    PrivateKeyDer::try_from(cert.key_pair.serialize_der()).unwrap(),
+                ))
+            })
+            .unwrap();
+        assert!(config.has_identity_loader());
+        let first = config.certificates();
+        assert!(config.reload_identity().unwrap());
+        assert_ne!(config.certificates(), first);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+}
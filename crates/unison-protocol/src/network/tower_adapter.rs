@@ -0,0 +1,145 @@
+//! `tower::Service`へのアダプター（`tower`フィーチャー）
+//!
+//! towerのミドルウェア（リトライ・バッファ・同時実行数の制限・タイムアウト・トレースなど）を
+//! そのままUnisonの呼び出しとハンドラーに重ねるためのアダプターです。
+//!
+//! - クライアント: [`ClientService`]は[`Request<T>`]を送って[`Response<T>`]を返す
+//!   `tower::Service`です（[`ProtocolClient::request`]と同じ呼び出し）。
+//! - サーバー: [`ProtocolServer::with_tower_service`](super::ProtocolServer::with_tower_service)で、
+//!   [`Request<T>`]を受け取って[`Response<T>`]を返す`tower::Service`をハンドラーとして登録できます。
+//!
+//! ミドルウェアが返したエラーは、[`ProtocolError`]・[`NetworkError`]であればそのまま、
+//! それ以外は`INTERNAL`のエラーとしてクライアントへ返します。
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower::{BoxError, Service};
+
+use super::client::ProtocolClient;
+use super::envelope::{Request, Response};
+use super::{NetworkError, ProtocolError};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// クライアントの型付きの呼び出しを`tower::Service`として扱う
+pub struct ClientService<Req = serde_json::Value, Res = serde_json::Value> {
+    client: Arc<ProtocolClient>,
+    _types: PhantomData<fn(Req) -> Res>,
+}
+
+impl<Req, Res> ClientService<Req, Res> {
+    pub fn new(client: Arc<ProtocolClient>) -> Self {
+        Self {
+            client,
+            _types: PhantomData,
+        }
+    }
+
+    pub fn client(&self) -> &Arc<ProtocolClient> {
+        &self.client
+    }
+}
+
+impl<Req, Res> Clone for ClientService<Req, Res> {
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.client))
+    }
+}
+
+impl<Req, Res> Service<Request<Req>> for ClientService<Req, Res>
+where
+    Req: Serialize + Send + 'static,
+    Res: DeserializeOwned + Send + 'static,
+{
+    type Response = Response<Res>;
+    type Error = NetworkError;
+    type Future = BoxFuture<Result<Response<Res>, NetworkError>>;
+
+    /// 接続の状態は呼び出し時に確認するため、常に準備できている
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), NetworkError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        let client = Arc::clone(&self.client);
+        Box::pin(async move { client.request(request).await })
+    }
+}
+
+/// `tower::Service`をサーバーの型付きハンドラーとして呼び出す関数に変換
+///
+/// 呼び出しごとにサービスを複製し、準備ができるのを待ってから呼び出します。
+/// 複製するときだけロックするため、サービスは`Sync`でなくても構いません。
+pub(crate) fn request_handler<S, Req, Res>(
+    service: S,
+) -> impl Fn(Request<Req>) -> BoxFuture<Result<Response<Res>, ProtocolError>> + Send + Sync + 'static
+where
+    S: Service<Request<Req>, Response = Response<Res>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    Req: Send + 'static,
+    Res: Send + 'static,
+{
+    let service = Mutex::new(service);
+    move |request| {
+        let mut service = service.lock().unwrap().clone();
+        Box::pin(async move {
+            futures_util::future::poll_fn(|cx| service.poll_ready(cx))
+                .await
+                .map_err(tower_error)?;
+            service.call(request).await.map_err(tower_error)
+        })
+    }
+}
+
+/// ミドルウェアやサービスが返したエラーをプロトコルエラーに変換
+pub fn tower_error(error: impl Into<BoxError>) -> ProtocolError {
+    let error = error.into();
+    let error = match error.downcast::<ProtocolError>() {
+        Ok(error) => return *error,
+        Err(error) => error,
+    };
+    match error.downcast::<NetworkError>() {
+        Ok(error) => (*error).into(),
+        Err(error) => ProtocolError::internal(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tower_error_keeps_protocol_and_network_errors() {
+        let error = tower_error(ProtocolError::new(ProtocolError::NOT_FOUND, "missing"));
+        assert_eq!(error.code, ProtocolError::NOT_FOUND);
+
+        let error = tower_error(NetworkError::Timeout);
+        assert_eq!(error, ProtocolError::from(NetworkError::Timeout));
+
+        let error = tower_error("overloaded");
+        assert_eq!(error.code, ProtocolError::INTERNAL);
+        assert_eq!(error.message, "overloaded");
+    }
+
+    #[tokio::test]
+    async fn test_request_handler_calls_service() {
+        let service = tower::service_fn(|request: Request<u32>| async move {
+            if request.body == 0 {
+                return Err(ProtocolError::new(ProtocolError::INVALID_REQUEST, "zero"));
+            }
+            Ok(Response::new(request.body * 2))
+        });
+        let handler = request_handler(service);
+
+        let response = handler(Request::new("double", 21)).await.unwrap();
+        assert_eq!(response.body, 42);
+        let error = handler(Request::new("double", 0)).await.unwrap_err();
+        assert_eq!(error.code, ProtocolError::INVALID_REQUEST);
+    }
+}
@@ -0,0 +1,323 @@
+//! RPCをまたぐトレース（W3C Trace Context）
+//!
+//! クライアントは呼び出しごとに`unison.client`のスパンを作り、トレースIDとスパンIDを
+//! メタデータの[`TRACEPARENT_METADATA_KEY`]（W3Cの`traceparent`形式）でサーバーへ送ります。
+//! サーバーは受け取った[`TraceContext`]を親として`unison.server`のスパンを作り、
+//! ハンドラーの中からの呼び出しは同じトレースを引き継ぎます。
+//! QUICの接続・シリアライズ・圧縮・ハンドラーの実行は、それぞれ子スパンとして記録されます。
+//!
+//! `opentelemetry`フィーチャーを有効にすると、`tracing-opentelemetry`のレイヤーを設定した
+//! アプリケーションでは、スパンがOpenTelemetryのスパンとして呼び出し元と親子関係で出力されます。
+
+use std::fmt;
+use std::future::Future;
+use tracing::Span;
+use tracing::field::Empty;
+
+use super::ProtocolMessage;
+
+/// トレースコンテキストを送るメタデータのキー（W3C Trace Contextの`traceparent`）
+pub const TRACEPARENT_METADATA_KEY: &str = "traceparent";
+
+/// トレースの中の1つのスパンの識別子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    /// 呼び出し元がトレースを記録しているか
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// 新しいトレースの最初のスパン
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: *uuid::Uuid::new_v4().as_bytes(),
+            span_id: new_span_id(),
+            sampled: true,
+        }
+    }
+
+    /// 同じトレースの子スパン
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: new_span_id(),
+            ..*self
+        }
+    }
+
+    /// `traceparent`の値を解析（形式が正しくないか、IDがすべて0なら`None`）
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let [version] = decode_hex::<1>(parts.next()?)?;
+        let trace_id = decode_hex::<16>(parts.next()?)?;
+        let span_id = decode_hex::<8>(parts.next()?)?;
+        let [flags] = decode_hex::<1>(parts.next()?)?;
+        // 未知のバージョンは後ろにフィールドが続いてもよい
+        if version == 0xff || (version == 0 && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 0x01 != 0,
+        })
+    }
+
+    /// メッセージのメタデータのトレースコンテキスト
+    pub fn from_message(message: &ProtocolMessage) -> Option<Self> {
+        let metadata = message.metadata().ok()?;
+        Self::parse(metadata.get(TRACEPARENT_METADATA_KEY)?)
+    }
+
+    /// 処理中のリクエストのトレースコンテキスト
+    ///
+    /// サーバーのハンドラーの中ではリクエストのスパンを返します。`opentelemetry`フィーチャーが
+    /// 有効であれば、それ以外の場所でも現在のOpenTelemetryのスパンを返します。
+    pub fn current() -> Option<Self> {
+        CURRENT_TRACE
+            .try_with(|trace| *trace)
+            .ok()
+            .or_else(|| otel::context_of(&Span::current()))
+    }
+
+    /// トレースIDの16進数表記
+    pub fn trace_id_hex(&self) -> String {
+        encode_hex(&self.trace_id)
+    }
+
+    /// スパンIDの16進数表記
+    pub fn span_id_hex(&self) -> String {
+        encode_hex(&self.span_id)
+    }
+}
+
+/// `traceparent`の形式（`00-<trace-id>-<span-id>-<flags>`）
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            self.trace_id_hex(),
+            self.span_id_hex(),
+            u8::from(self.sampled)
+        )
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_TRACE: TraceContext;
+}
+
+/// トレースコンテキストを設定してFutureを実行
+pub(crate) async fn with_trace<F: Future>(trace: TraceContext, future: F) -> F::Output {
+    CURRENT_TRACE.scope(trace, future).await
+}
+
+/// 呼び出しのクライアント側のスパンを作り、サーバーへ送るトレースコンテキストをメタデータに付ける
+///
+/// メタデータに`traceparent`があればそれを、なければ処理中のリクエストのトレースを親にします。
+pub(crate) fn client_span(message: &mut ProtocolMessage) -> Span {
+    let span = tracing::info_span!(
+        "unison.client",
+        otel.name = %message.method,
+        otel.kind = "client",
+        rpc.system = "unison",
+        rpc.method = %message.method,
+        trace_id = Empty,
+        span_id = Empty,
+    );
+    let mut metadata = match message.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return span,
+    };
+    let parent = metadata
+        .get(TRACEPARENT_METADATA_KEY)
+        .and_then(|value| TraceContext::parse(value))
+        .or_else(TraceContext::current);
+    let trace = link(&span, parent);
+    metadata.insert(TRACEPARENT_METADATA_KEY.to_string(), trace.to_string());
+    if let Ok(traced) = message.clone().with_metadata(&metadata) {
+        *message = traced;
+    }
+    span
+}
+
+/// リクエストのサーバー側のスパンと、ハンドラーへ引き継ぐトレースコンテキスト
+pub(crate) fn server_span(request: &ProtocolMessage) -> (Span, TraceContext) {
+    let span = tracing::info_span!(
+        "unison.server",
+        otel.name = %request.method,
+        otel.kind = "server",
+        rpc.system = "unison",
+        rpc.method = %request.method,
+        trace_id = Empty,
+        span_id = Empty,
+        parent_span_id = Empty,
+    );
+    let parent = TraceContext::from_message(request);
+    if let Some(parent) = &parent {
+        span.record("parent_span_id", parent.span_id_hex());
+    }
+    let trace = link(&span, parent);
+    (span, trace)
+}
+
+/// スパンを親に結びつけ、スパンのトレースコンテキストを記録して返す
+fn link(span: &Span, parent: Option<TraceContext>) -> TraceContext {
+    if let Some(parent) = &parent {
+        otel::set_parent(span, parent);
+    }
+    let trace = otel::context_of(span).unwrap_or_else(|| {
+        parent
+            .as_ref()
+            .map_or_else(TraceContext::new_root, TraceContext::child)
+    });
+    span.record("trace_id", trace.trace_id_hex());
+    span.record("span_id", trace.span_id_hex());
+    trace
+}
+
+fn new_span_id() -> [u8; 8] {
+    let bits = uuid::Uuid::new_v4().as_u64_pair().0;
+    // すべて0のスパンIDは無効
+    bits.max(1).to_be_bytes()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != N * 2 || !text.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// `tracing-opentelemetry`のスパンとの変換
+#[cfg(feature = "opentelemetry")]
+mod otel {
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    use super::TraceContext;
+
+    /// スパンのOpenTelemetryのコンテキスト（レイヤーが設定されていなければ`None`）
+    pub(super) fn context_of(span: &Span) -> Option<TraceContext> {
+        let context = span.context();
+        let otel_span = context.span();
+        let span_context = otel_span.span_context();
+        span_context.is_valid().then(|| TraceContext {
+            trace_id: span_context.trace_id().to_bytes(),
+            span_id: span_context.span_id().to_bytes(),
+            sampled: span_context.is_sampled(),
+        })
+    }
+
+    /// リモートのスパンをスパンの親に設定
+    pub(super) fn set_parent(span: &Span, parent: &TraceContext) {
+        let flags = if parent.sampled {
+            TraceFlags::SAMPLED
+        } else {
+            TraceFlags::default()
+        };
+        let remote = SpanContext::new(
+            TraceId::from_bytes(parent.trace_id),
+            SpanId::from_bytes(parent.span_id),
+            flags,
+            true,
+            TraceState::default(),
+        );
+        span.set_parent(opentelemetry::Context::new().with_remote_span_context(remote));
+    }
+}
+
+/// `opentelemetry`フィーチャーが無効な場合は、スパンにトレースコンテキストを持たない
+#[cfg(not(feature = "opentelemetry"))]
+mod otel {
+    use tracing::Span;
+
+    use super::TraceContext;
+
+    pub(super) fn context_of(_span: &Span) -> Option<TraceContext> {
+        None
+    }
+
+    pub(super) fn set_parent(_span: &Span, _parent: &TraceContext) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_traceparent_round_trip() {
+        let text = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let trace = TraceContext::parse(text).unwrap();
+        assert_eq!(trace.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.span_id_hex(), "00f067aa0ba902b7");
+        assert!(trace.sampled);
+        assert_eq!(trace.to_string(), text);
+
+        let child = trace.child();
+        assert_eq!(child.trace_id, trace.trace_id);
+        assert_ne!(child.span_id, trace.span_id);
+    }
+
+    #[test]
+    fn test_invalid_traceparent_is_rejected() {
+        for text in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert_eq!(TraceContext::parse(text), None, "{text}");
+        }
+        // 未知のバージョンは後ろのフィールドを無視する
+        assert!(
+            TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_client_span_continues_trace_from_metadata() {
+        let parent = TraceContext::new_root();
+        let metadata = HashMap::from([(TRACEPARENT_METADATA_KEY.to_string(), parent.to_string())]);
+        let mut message = ProtocolMessage::new_with_json(
+            1,
+            "echo".to_string(),
+            MessageType::Request,
+            serde_json::Value::Null,
+        )
+        .unwrap()
+        .with_metadata(&metadata)
+        .unwrap();
+
+        let _span = client_span(&mut message);
+        let sent = TraceContext::from_message(&message).unwrap();
+        assert_eq!(sent.trace_id, parent.trace_id);
+        assert_ne!(sent.span_id, parent.span_id);
+
+        let (_span, server) = server_span(&message);
+        assert_eq!(server.trace_id, parent.trace_id);
+        assert_ne!(server.span_id, sent.span_id);
+    }
+}
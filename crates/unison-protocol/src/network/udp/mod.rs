@@ -0,0 +1,291 @@
+//! QUICが使うUDPソケット
+//!
+//! 既定ではquinnの標準ソケットで送受信します。Linuxで`io-uring`フィーチャーを有効にすると、
+//! [`UdpBackend::IoUring`]でio_uringを使ったソケットを選べます。受信バッファを常にカーネルへ
+//! 渡しておき、[`IoUringConfig::sqpoll_idle`]を指定すると送信もシステムコールなしで行えるため、
+//! 多数の接続を扱うサーバー向けです。ループバックの1接続のように負荷が低い場合は標準ソケットの方が
+//! 速いこともあるため、`benches/udp_backend.rs`で比較してから選んでください。
+//!
+//! io_uringを初期化できない環境（古いカーネルやseccompで禁止されている場合など）や、
+//! フィーチャーを有効にしていないビルドでは、警告を出力して標準ソケットにフォールバックします。
+//! AF_XDPには対応していません。
+//!
+//! 10GbEのような高速なリンクでは、OSの既定のソケットバッファがスループットの上限になります。
+//! [`UdpSocketConfig`]で送受信バッファの大きさとGSO（送信時のセグメンテーションオフロード）を
+//! 指定できます。GROは対応している環境では常に有効です。
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use uring::IoUringUdpSocket;
+
+use quinn::udp::{RecvMeta, Transmit};
+use quinn::{AsyncUdpSocket, Endpoint, EndpointConfig, Runtime, ServerConfig, UdpPoller};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{self, IoSliceMut};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// UDPソケットの実装
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum UdpBackend {
+    /// quinnの標準ソケット
+    #[default]
+    Standard,
+    /// io_uringによる送受信（Linuxで`io-uring`フィーチャーが有効な場合のみ）
+    IoUring(IoUringConfig),
+}
+
+impl UdpBackend {
+    /// 既定の設定でio_uringを使う
+    pub fn io_uring() -> Self {
+        Self::IoUring(IoUringConfig::default())
+    }
+}
+
+/// io_uringソケットの設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoUringConfig {
+    /// カーネルへ渡しておく受信バッファの数
+    pub recv_depth: u32,
+    /// 同時に送信中にできるデータグラムの数
+    pub send_depth: u32,
+    /// 送信キューを監視するカーネルスレッドを使う場合のアイドル時間（`None`の場合は使わない）
+    ///
+    /// システムコールなしで送信できますが、CPUを1つ占有します。
+    pub sqpoll_idle: Option<Duration>,
+}
+
+impl Default for IoUringConfig {
+    fn default() -> Self {
+        Self {
+            recv_depth: 64,
+            send_depth: 64,
+            sqpoll_idle: None,
+        }
+    }
+}
+
+/// UDPソケットのチューニング
+///
+/// バッファの大きさはカーネルの上限（Linuxでは`net.core.wmem_max`・`net.core.rmem_max`）で
+/// 制限されます。指定した大きさに届かない場合は警告を出力します。
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UdpSocketConfig {
+    /// 送信バッファの大きさ（`None`の場合はOSの既定値）
+    pub send_buffer_size: Option<usize>,
+    /// 受信バッファの大きさ（`None`の場合はOSの既定値）
+    pub recv_buffer_size: Option<usize>,
+    /// 対応している環境でGSOを使い、複数のデータグラムを1回のシステムコールで送信する
+    ///
+    /// GSOに対応していないネットワークドライバーでパケットの損失が起きる場合は無効にします。
+    pub segmentation_offload: bool,
+}
+
+impl Default for UdpSocketConfig {
+    fn default() -> Self {
+        Self {
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            segmentation_offload: true,
+        }
+    }
+}
+
+impl UdpSocketConfig {
+    /// 送受信バッファを同じ大きさにする
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// GSOを使うかを指定
+    pub fn with_segmentation_offload(mut self, enabled: bool) -> Self {
+        self.segmentation_offload = enabled;
+        self
+    }
+}
+
+/// この環境でio_uringソケットを使えるか
+pub fn io_uring_supported() -> bool {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    {
+        uring::supported()
+    }
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    {
+        false
+    }
+}
+
+/// 指定のバックエンドと設定でQUICのエンドポイントを作成
+///
+/// `server_config`を指定するとサーバーとして接続を受け付けます。
+/// Tokioランタイムのコンテキスト内で呼び出す必要があります。
+pub(crate) fn bind_endpoint(
+    backend: &UdpBackend,
+    config: &UdpSocketConfig,
+    addr: SocketAddr,
+    server_config: Option<ServerConfig>,
+) -> io::Result<Endpoint> {
+    let runtime = Arc::new(quinn::TokioRuntime);
+    let socket = bind_socket(addr, config)?;
+    let mut socket: Arc<dyn AsyncUdpSocket> = match backend {
+        UdpBackend::Standard => runtime.wrap_udp_socket(socket)?,
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        UdpBackend::IoUring(io_uring) => match IoUringUdpSocket::from_std(socket, io_uring) {
+            Ok(socket) => Arc::new(socket),
+            Err(e) => {
+                warn!(
+                    "io_uring is unavailable ({}), using the standard UDP socket",
+                    e
+                );
+                runtime.wrap_udp_socket(bind_socket(addr, config)?)?
+            }
+        },
+        #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+        UdpBackend::IoUring(_) => {
+            warn!("io_uring support is not enabled in this build, using the standard UDP socket");
+            runtime.wrap_udp_socket(socket)?
+        }
+    };
+    if !config.segmentation_offload {
+        socket = Arc::new(WithoutSegmentationOffload(socket));
+    }
+    debug!(
+        "UDP socket bound to {} (GSO segments: {}, GRO segments: {})",
+        socket.local_addr()?,
+        socket.max_transmit_segments(),
+        socket.max_receive_segments()
+    );
+
+    Endpoint::new_with_abstract_socket(EndpointConfig::default(), server_config, socket, runtime)
+}
+
+/// ソケットを作成し、バッファの大きさを設定してからバインド
+fn bind_socket(addr: SocketAddr, config: &UdpSocketConfig) -> io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() {
+        // IPv4射影アドレスでIPv4の通信も扱う
+        if let Err(e) = socket.set_only_v6(false) {
+            debug!("Unable to make UDP socket dual-stack: {}", e);
+        }
+    }
+    if let Some(size) = config.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+        warn_if_limited(
+            "send",
+            size,
+            socket.send_buffer_size()?,
+            "net.core.wmem_max",
+        );
+    }
+    if let Some(size) = config.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+        warn_if_limited(
+            "receive",
+            size,
+            socket.recv_buffer_size()?,
+            "net.core.rmem_max",
+        );
+    }
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+fn warn_if_limited(direction: &str, requested: usize, actual: usize, sysctl: &str) {
+    // Linuxは管理領域を含めて要求の2倍を確保するため、要求を下回る場合は上限で制限されている
+    if actual < requested {
+        warn!(
+            "UDP {} buffer is limited to {} bytes (requested {}); raise {} to allow larger buffers",
+            direction, actual, requested, sysctl
+        );
+    }
+}
+
+/// GSOを使わずに1データグラムずつ送信させるソケット
+#[derive(Debug)]
+struct WithoutSegmentationOffload(Arc<dyn AsyncUdpSocket>);
+
+impl AsyncUdpSocket for WithoutSegmentationOffload {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        Arc::clone(&self.0).create_io_poller()
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        self.0.try_send(transmit)
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        self.0.poll_recv(cx, bufs, meta)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    fn max_transmit_segments(&self) -> usize {
+        1
+    }
+
+    fn max_receive_segments(&self) -> usize {
+        self.0.max_receive_segments()
+    }
+
+    fn may_fragment(&self) -> bool {
+        self.0.may_fragment()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_io_uring_backend_falls_back_when_unavailable() {
+        let backend = UdpBackend::io_uring();
+        let endpoint = bind_endpoint(
+            &backend,
+            &UdpSocketConfig::default(),
+            "127.0.0.1:0".parse().unwrap(),
+            None,
+        )
+        .unwrap();
+        assert!(endpoint.local_addr().unwrap().port() > 0);
+        assert_eq!(UdpBackend::default(), UdpBackend::Standard);
+    }
+
+    #[test]
+    fn test_buffer_sizes_are_applied() {
+        // 多くの環境の上限を下回る大きさで確認する
+        let config = UdpSocketConfig::default().with_buffer_size(64 * 1024);
+        let socket = bind_socket("[::1]:0".parse().unwrap(), &config).unwrap();
+        let socket = socket2::SockRef::from(&socket);
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_segmentation_offload_can_be_disabled() {
+        let runtime = quinn::TokioRuntime;
+        let socket = runtime
+            .wrap_udp_socket(std::net::UdpSocket::bind("127.0.0.1:0").unwrap())
+            .unwrap();
+        let receive_segments = socket.max_receive_segments();
+        let socket = WithoutSegmentationOffload(socket);
+        assert_eq!(socket.max_transmit_segments(), 1);
+        assert_eq!(socket.max_receive_segments(), receive_segments);
+    }
+}
@@ -0,0 +1,520 @@
+//! io_uringによるUDPソケット
+//!
+//! 受信用のバッファを[`IoUringConfig::recv_depth`]個カーネルへ渡しておき、
+//! 完了をeventfd経由でTokioに通知します。送信はバッファへコピーしてから投入するため、
+//! 呼び出し元はカーネルの処理を待ちません。
+
+use io_uring::{IoUring, opcode, squeue, types};
+use quinn::udp::{RecvMeta, Transmit};
+use quinn::{AsyncUdpSocket, UdpPoller};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, IoSliceMut};
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll, Waker, ready};
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+use tracing::debug;
+
+use super::IoUringConfig;
+
+/// 受信バッファの大きさ（UDPのデータグラムの最大長）
+const RECV_BUFFER_SIZE: usize = u16::MAX as usize;
+/// 送信操作を示す`user_data`のビット（下位ビットはスロットの番号）
+const SEND_FLAG: u64 = 1 << 32;
+/// 取り消し操作の`user_data`
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+/// この環境でio_uringを初期化できるか
+pub(super) fn supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| IoUring::new(2).is_ok())
+}
+
+/// カーネルへ渡すメッセージの領域
+///
+/// 作成後に要素を増減しない`Vec`で保持し、操作の完了を回収するまでアドレスを変えません。
+struct Slot {
+    buf: Vec<u8>,
+    iov: libc::iovec,
+    addr: libc::sockaddr_storage,
+    msg: libc::msghdr,
+}
+
+impl Slot {
+    fn new(buf: Vec<u8>) -> Self {
+        // SAFETY: いずれもすべてのビットが0の値が有効なCの構造体
+        unsafe {
+            Self {
+                buf,
+                iov: mem::zeroed(),
+                addr: mem::zeroed(),
+                msg: mem::zeroed(),
+            }
+        }
+    }
+
+    fn prepare(&mut self, addr_len: libc::socklen_t) -> *mut libc::msghdr {
+        self.iov = libc::iovec {
+            iov_base: self.buf.as_mut_ptr().cast(),
+            iov_len: self.buf.len(),
+        };
+        // SAFETY: すべてのビットが0の値が有効なCの構造体
+        self.msg = unsafe { mem::zeroed() };
+        self.msg.msg_name = (&raw mut self.addr).cast();
+        self.msg.msg_namelen = addr_len;
+        self.msg.msg_iov = &raw mut self.iov;
+        self.msg.msg_iovlen = 1;
+        &raw mut self.msg
+    }
+
+    fn prepare_recv(&mut self) -> *mut libc::msghdr {
+        self.prepare(mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t)
+    }
+
+    fn prepare_send(&mut self, destination: SocketAddr, contents: &[u8]) -> *const libc::msghdr {
+        self.buf.clear();
+        self.buf.extend_from_slice(contents);
+        let addr_len = write_sockaddr(destination, &mut self.addr);
+        self.prepare(addr_len)
+    }
+}
+
+fn write_sockaddr(addr: SocketAddr, storage: &mut libc::sockaddr_storage) -> libc::socklen_t {
+    match addr {
+        SocketAddr::V4(addr) => {
+            // SAFETY: sockaddr_storageはすべてのソケットアドレスを格納できる大きさと整列を持つ
+            let sin = unsafe {
+                &mut *(storage as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in>()
+            };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr = libc::in_addr {
+                s_addr: u32::from(*addr.ip()).to_be(),
+            };
+            mem::size_of::<libc::sockaddr_in>() as libc::socklen_t
+        }
+        SocketAddr::V6(addr) => {
+            // SAFETY: sockaddr_storageはすべてのソケットアドレスを格納できる大きさと整列を持つ
+            let sin6 = unsafe {
+                &mut *(storage as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+            };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr = libc::in6_addr {
+                s6_addr: addr.ip().octets(),
+            };
+            sin6.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t
+        }
+    }
+}
+
+fn read_sockaddr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match i32::from(storage.ss_family) {
+        libc::AF_INET => {
+            // SAFETY: アドレスファミリーがAF_INETの場合はsockaddr_inとして書き込まれている
+            let sin =
+                unsafe { &*(storage as *const libc::sockaddr_storage).cast::<libc::sockaddr_in>() };
+            Some(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)),
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: アドレスファミリーがAF_INET6の場合はsockaddr_in6として書き込まれている
+            let sin6 = unsafe {
+                &*(storage as *const libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+            };
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// リングとカーネルへ渡したバッファ
+struct State {
+    ring: IoUring,
+    fd: types::Fd,
+    recv: Vec<Slot>,
+    /// 受信が完了し、まだ読み出していないスロットと結果
+    received: VecDeque<(usize, i32)>,
+    send: Vec<Slot>,
+    free_send: Vec<usize>,
+    /// カーネルが処理中の操作の数
+    in_flight: usize,
+    /// 送信スロットの空きを待っているタスク
+    send_waiters: Vec<Waker>,
+}
+
+// SAFETY: スロットの生ポインタは同じ`State`が所有するヒープ領域を指し、
+// `Mutex`で保護された状態でのみ操作する
+unsafe impl Send for State {}
+
+impl State {
+    fn push(&mut self, entry: &squeue::Entry) -> io::Result<()> {
+        // SAFETY: エントリが参照するスロットは、完了を回収するまで解放も移動もしない
+        if unsafe { self.ring.submission().push(entry) }.is_err() {
+            self.ring.submit()?;
+            unsafe { self.ring.submission().push(entry) }
+                .map_err(|_| io::Error::from(io::ErrorKind::WouldBlock))?;
+        }
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    fn submit_recv(&mut self, index: usize) -> io::Result<()> {
+        let msg = self.recv[index].prepare_recv();
+        let entry = opcode::RecvMsg::new(self.fd, msg)
+            .build()
+            .user_data(index as u64);
+        self.push(&entry)
+    }
+
+    /// 完了した操作を回収
+    fn reap(&mut self) {
+        let mut sent = false;
+        for entry in self.ring.completion() {
+            self.in_flight -= 1;
+            let user_data = entry.user_data();
+            if user_data == CANCEL_USER_DATA {
+                continue;
+            }
+            if user_data & SEND_FLAG != 0 {
+                if entry.result() < 0 {
+                    debug!(
+                        "io_uring sendmsg failed: {}",
+                        io::Error::from_raw_os_error(-entry.result())
+                    );
+                }
+                self.free_send.push((user_data & !SEND_FLAG) as usize);
+                sent = true;
+            } else {
+                self.received
+                    .push_back((user_data as usize, entry.result()));
+            }
+        }
+        if sent {
+            self.send_waiters.drain(..).for_each(Waker::wake);
+        }
+    }
+
+    /// 受信したデータグラムを`bufs`へ移し、スロットを再びカーネルへ渡す
+    fn fill(&mut self, bufs: &mut [IoSliceMut<'_>], meta: &mut [RecvMeta]) -> io::Result<usize> {
+        let capacity = bufs.len().min(meta.len());
+        let mut count = 0;
+        let mut resubmitted = false;
+        while count < capacity {
+            let Some((index, result)) = self.received.pop_front() else {
+                break;
+            };
+            if result >= 0 {
+                let slot = &self.recv[index];
+                let len = (result as usize).min(bufs[count].len());
+                if let Some(addr) = read_sockaddr(&slot.addr) {
+                    bufs[count][..len].copy_from_slice(&slot.buf[..len]);
+                    meta[count] = RecvMeta {
+                        addr,
+                        len,
+                        stride: len,
+                        ecn: None,
+                        dst_ip: None,
+                    };
+                    count += 1;
+                }
+            } else if result != -libc::ECANCELED {
+                debug!(
+                    "io_uring recvmsg failed: {}",
+                    io::Error::from_raw_os_error(-result)
+                );
+            }
+            self.submit_recv(index)?;
+            resubmitted = true;
+        }
+        if resubmitted {
+            self.ring.submit()?;
+        }
+        Ok(count)
+    }
+
+    /// 処理中の操作を取り消し、すべて完了するまで待機
+    fn cancel_all(&mut self) {
+        self.reap();
+        let received: Vec<usize> = self.received.iter().map(|(index, _)| *index).collect();
+        for index in 0..self.recv.len() {
+            if received.contains(&index) {
+                continue;
+            }
+            let entry = opcode::AsyncCancel::new(index as u64)
+                .build()
+                .user_data(CANCEL_USER_DATA);
+            if self.push(&entry).is_err() {
+                break;
+            }
+        }
+        while self.in_flight > 0 {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => self.reap(),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    // 完了を確認できないバッファはカーネルが書き込む可能性があるため解放しない
+                    debug!("Failed to wait for io_uring completions: {}", e);
+                    mem::forget(mem::take(&mut self.recv));
+                    mem::forget(mem::take(&mut self.send));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// io_uringで送受信するUDPソケット
+///
+/// [`quinn::Endpoint::new_with_abstract_socket`]に渡して使います。
+/// 通常は[`UdpBackend::IoUring`](super::UdpBackend::IoUring)を指定すれば十分です。
+pub struct IoUringUdpSocket {
+    socket: UdpSocket,
+    ipv6: bool,
+    state: Mutex<State>,
+    /// 完了の通知を受けるeventfd
+    completions: AsyncFd<OwnedFd>,
+}
+
+impl IoUringUdpSocket {
+    /// `addr`にバインドし、受信を開始
+    ///
+    /// Tokioランタイムのコンテキスト内で呼び出す必要があります。
+    pub fn bind(addr: SocketAddr, config: &IoUringConfig) -> io::Result<Self> {
+        Self::from_std(UdpSocket::bind(addr)?, config)
+    }
+
+    /// バインド済みのソケットで受信を開始
+    ///
+    /// io_uringが受信を待つため、ソケットはブロッキングモードである必要があります。
+    pub fn from_std(socket: UdpSocket, config: &IoUringConfig) -> io::Result<Self> {
+        let recv_depth = config.recv_depth.max(1);
+        let send_depth = config.send_depth.max(1);
+        let mut builder = IoUring::builder();
+        if let Some(idle) = config.sqpoll_idle {
+            builder.setup_sqpoll(idle.as_millis() as u32);
+        }
+        let ring = builder.build((recv_depth + send_depth + 8).next_power_of_two())?;
+
+        // SAFETY: 引数はフラグのみで、成功時は所有権を持つファイルディスクリプタを返す
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if eventfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: 作成したばかりで他に所有者のいないファイルディスクリプタ
+        let eventfd = unsafe { OwnedFd::from_raw_fd(eventfd) };
+        ring.submitter().register_eventfd(eventfd.as_raw_fd())?;
+        let completions = AsyncFd::with_interest(eventfd, Interest::READABLE)?;
+
+        let ipv6 = socket.local_addr()?.is_ipv6();
+        let mut state = State {
+            ring,
+            fd: types::Fd(socket.as_raw_fd()),
+            recv: (0..recv_depth)
+                .map(|_| Slot::new(vec![0; RECV_BUFFER_SIZE]))
+                .collect(),
+            received: VecDeque::new(),
+            send: (0..send_depth).map(|_| Slot::new(Vec::new())).collect(),
+            free_send: (0..send_depth as usize).rev().collect(),
+            in_flight: 0,
+            send_waiters: Vec::new(),
+        };
+        for index in 0..state.recv.len() {
+            if let Err(e) = state.submit_recv(index) {
+                state.cancel_all();
+                return Err(e);
+            }
+        }
+        if let Err(e) = state.ring.submit() {
+            state.cancel_all();
+            return Err(e);
+        }
+
+        Ok(Self {
+            ipv6,
+            socket,
+            state: Mutex::new(state),
+            completions,
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// eventfdのカウンターを読み捨てる
+    fn drain_completions(&self) {
+        let mut counter = [0u8; 8];
+        // SAFETY: 8バイトのバッファへの非ブロッキングの読み出し
+        unsafe {
+            libc::read(
+                self.completions.as_raw_fd(),
+                counter.as_mut_ptr().cast(),
+                counter.len(),
+            );
+        }
+    }
+}
+
+impl Drop for IoUringUdpSocket {
+    fn drop(&mut self) {
+        self.state
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cancel_all();
+    }
+}
+
+impl fmt::Debug for IoUringUdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IoUringUdpSocket")
+            .field("local_addr", &self.socket.local_addr().ok())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncUdpSocket for IoUringUdpSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        Box::pin(IoUringUdpPoller { socket: self })
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        // IPv6のソケットからIPv4の宛先へはマップされたアドレスで送る
+        let destination = match transmit.destination {
+            SocketAddr::V4(addr) if self.ipv6 => SocketAddr::V6(SocketAddrV6::new(
+                addr.ip().to_ipv6_mapped(),
+                addr.port(),
+                0,
+                0,
+            )),
+            destination => destination,
+        };
+
+        let mut state = self.lock();
+        state.reap();
+        let Some(index) = state.free_send.pop() else {
+            return Err(io::ErrorKind::WouldBlock.into());
+        };
+        let msg = state.send[index].prepare_send(destination, transmit.contents);
+        let entry = opcode::SendMsg::new(state.fd, msg)
+            .build()
+            .user_data(SEND_FLAG | index as u64);
+        if let Err(e) = state.push(&entry) {
+            state.free_send.push(index);
+            return Err(e);
+        }
+        state.ring.submit()?;
+        Ok(())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            {
+                let mut state = self.lock();
+                state.reap();
+                let count = state.fill(bufs, meta)?;
+                if count > 0 {
+                    return Poll::Ready(Ok(count));
+                }
+            }
+            // カウンターを読んでから完了を確認するため、その後の完了は次の通知で分かる
+            let mut guard = ready!(self.completions.poll_read_ready(cx))?;
+            self.drain_completions();
+            guard.clear_ready();
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+#[derive(Debug)]
+struct IoUringUdpPoller {
+    socket: Arc<IoUringUdpSocket>,
+}
+
+impl UdpPoller for IoUringUdpPoller {
+    fn poll_writable(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let mut state = self.socket.lock();
+        state.reap();
+        if !state.free_send.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        // 送信の完了は受信側の`poll_recv`が回収し、待っているタスクを起こす
+        if !state.send_waiters.iter().any(|w| w.will_wake(cx.waker())) {
+            state.send_waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_datagram_round_trip() {
+        if !supported() {
+            eprintln!("io_uring is not available, skipping");
+            return;
+        }
+        let config = IoUringConfig::default();
+        let a = Arc::new(IoUringUdpSocket::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap());
+        let b = IoUringUdpSocket::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let to = b.local_addr().unwrap();
+
+        let mut poller = Arc::clone(&a).create_io_poller();
+        std::future::poll_fn(|cx| poller.as_mut().poll_writable(cx))
+            .await
+            .unwrap();
+        a.try_send(&Transmit {
+            destination: to,
+            ecn: None,
+            contents: b"unison",
+            segment_size: None,
+            src_ip: None,
+        })
+        .unwrap();
+
+        let mut buf = [0u8; 64];
+        let mut meta = [RecvMeta::default()];
+        let count =
+            std::future::poll_fn(|cx| b.poll_recv(cx, &mut [IoSliceMut::new(&mut buf)], &mut meta))
+                .await
+                .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(&buf[..meta[0].len], b"unison");
+        assert_eq!(meta[0].addr, a.local_addr().unwrap());
+    }
+
+    #[test]
+    fn test_sockaddr_round_trip() {
+        // SAFETY: すべてのビットが0の値が有効なCの構造体
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        for addr in ["127.0.0.1:4433", "[::1]:4433", "[::ffff:10.0.0.1]:53"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            write_sockaddr(addr, &mut storage);
+            assert_eq!(read_sockaddr(&storage), Some(addr));
+        }
+    }
+}
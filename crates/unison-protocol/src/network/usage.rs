@@ -0,0 +1,408 @@
+//! テナント・ピアごとの利用量の集計と上限
+//!
+//! リクエスト数とリクエストのバイト数をテナントごと（テナントに紐づかない接続は接続ごと）に
+//! 集計し、期間内の上限（[`UsageQuota`]）を超えたリクエストを拒否します。
+//!
+//! - 期間は日単位（UTCの0時にリセット）または直近の一定時間（ローリング）を選べます
+//! - 上限を超えた場合は`QUOTA_EXCEEDED`を返し、メタデータ[`QUOTA_RESET_METADATA_KEY`]に
+//!   利用量がリセットされる時刻（Unix時刻、ミリ秒）を設定します
+//! - 組み込みメソッド[`QUOTA_USAGE_METHOD`]で自身の利用量を取得できます
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use super::broadcast::ConnectionId;
+use super::tenant::TenantId;
+
+/// 自身の利用量を取得する組み込みメソッド
+pub const QUOTA_USAGE_METHOD: &str = "unison.quota.usage";
+
+/// 利用量がリセットされる時刻（Unix時刻、ミリ秒）を格納するメタデータのキー
+pub const QUOTA_RESET_METADATA_KEY: &str = "quota-reset";
+
+/// ローリング期間を分割する区間数
+const ROLLING_SLOTS: u64 = 60;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// 上限を超えた資源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaResource {
+    Requests,
+    Bytes,
+}
+
+/// 利用量の上限超過
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Quota exceeded for {key}: {resource:?} limit {limit} reached")]
+pub struct QuotaExceeded {
+    pub key: UsageKey,
+    pub resource: QuotaResource,
+    pub limit: u64,
+    /// 利用量がリセットされる時刻
+    pub reset_at: SystemTime,
+}
+
+impl QuotaExceeded {
+    /// リセット時刻（Unix時刻、ミリ秒）
+    pub fn reset_at_millis(&self) -> u64 {
+        unix_millis(self.reset_at)
+    }
+}
+
+/// 集計期間
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaWindow {
+    /// UTCの0時にリセット
+    Daily,
+    /// 直近の指定時間
+    Rolling(Duration),
+}
+
+/// 期間内の利用量の上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageQuota {
+    pub window: QuotaWindow,
+    pub max_requests: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl UsageQuota {
+    pub fn daily() -> Self {
+        Self::with_window(QuotaWindow::Daily)
+    }
+
+    pub fn rolling(window: Duration) -> Self {
+        Self::with_window(QuotaWindow::Rolling(window))
+    }
+
+    fn with_window(window: QuotaWindow) -> Self {
+        Self {
+            window,
+            max_requests: None,
+            max_bytes: None,
+        }
+    }
+
+    pub fn with_max_requests(mut self, max_requests: u64) -> Self {
+        self.max_requests = Some(max_requests);
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+}
+
+/// 利用量の上限の設定
+#[derive(Debug, Clone, Default)]
+pub struct UsageConfig {
+    /// 全テナントに適用する上限
+    pub tenant_quota: Option<UsageQuota>,
+    /// テナントごとの上限（`tenant_quota`より優先）
+    pub tenant_quotas: HashMap<TenantId, UsageQuota>,
+    /// テナントに紐づかない接続ごとの上限
+    pub peer_quota: Option<UsageQuota>,
+}
+
+impl UsageConfig {
+    pub fn with_tenant_quota(mut self, quota: UsageQuota) -> Self {
+        self.tenant_quota = Some(quota);
+        self
+    }
+
+    pub fn with_quota_for_tenant(mut self, tenant: TenantId, quota: UsageQuota) -> Self {
+        self.tenant_quotas.insert(tenant, quota);
+        self
+    }
+
+    pub fn with_peer_quota(mut self, quota: UsageQuota) -> Self {
+        self.peer_quota = Some(quota);
+        self
+    }
+
+    fn quota_for(&self, key: &UsageKey) -> Option<UsageQuota> {
+        match key {
+            UsageKey::Tenant(tenant) => self
+                .tenant_quotas
+                .get(tenant)
+                .copied()
+                .or(self.tenant_quota),
+            UsageKey::Peer(_) => self.peer_quota,
+        }
+    }
+}
+
+/// 利用量の集計単位
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UsageKey {
+    Tenant(TenantId),
+    /// テナントに紐づかない接続
+    Peer(ConnectionId),
+}
+
+impl UsageKey {
+    /// 接続の集計単位（テナントに紐づいていればテナント単位）
+    pub fn for_connection(tenant: Option<&TenantId>, connection_id: ConnectionId) -> Self {
+        match tenant {
+            Some(tenant) => Self::Tenant(tenant.clone()),
+            None => Self::Peer(connection_id),
+        }
+    }
+}
+
+impl std::fmt::Display for UsageKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsageKey::Tenant(tenant) => write!(f, "tenant '{}'", tenant),
+            UsageKey::Peer(id) => write!(f, "connection {}", id),
+        }
+    }
+}
+
+/// 期間内の利用量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub bytes: u64,
+    pub max_requests: Option<u64>,
+    pub max_bytes: Option<u64>,
+    /// 利用量がリセットされる時刻（Unix時刻、ミリ秒）
+    pub reset_at_ms: u64,
+}
+
+/// 区間ごとの集計（日単位では1区間）
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    index: u64,
+    requests: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counter {
+    slots: VecDeque<Slot>,
+}
+
+impl Counter {
+    /// 期間外の区間を破棄し、期間内の合計とリセット時刻を返す
+    fn refresh(&mut self, window: QuotaWindow, now_ms: u64) -> (u64, u64, u64) {
+        let (slot_ms, slots) = slot_layout(window);
+        let current = now_ms / slot_ms;
+        while self
+            .slots
+            .front()
+            .is_some_and(|slot| slot.index + slots <= current)
+        {
+            self.slots.pop_front();
+        }
+
+        let requests = self.slots.iter().map(|s| s.requests).sum();
+        let bytes = self.slots.iter().map(|s| s.bytes).sum();
+        let oldest = self.slots.front().map_or(current, |s| s.index);
+        (requests, bytes, (oldest + slots) * slot_ms)
+    }
+
+    fn add(&mut self, window: QuotaWindow, now_ms: u64, bytes: u64) {
+        let (slot_ms, _) = slot_layout(window);
+        let index = now_ms / slot_ms;
+        match self.slots.back_mut() {
+            Some(slot) if slot.index == index => {
+                slot.requests += 1;
+                slot.bytes += bytes;
+            }
+            _ => self.slots.push_back(Slot {
+                index,
+                requests: 1,
+                bytes,
+            }),
+        }
+    }
+}
+
+/// 区間の長さ（ミリ秒）と期間内の区間数
+fn slot_layout(window: QuotaWindow) -> (u64, u64) {
+    match window {
+        QuotaWindow::Daily => (DAY_MILLIS, 1),
+        QuotaWindow::Rolling(window) => {
+            let window_ms = (window.as_millis() as u64).max(ROLLING_SLOTS);
+            (window_ms / ROLLING_SLOTS, ROLLING_SLOTS)
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// テナント・ピアごとの利用量の集計
+#[derive(Clone, Default)]
+pub struct UsageTracker {
+    config: Arc<UsageConfig>,
+    counters: Arc<Mutex<HashMap<UsageKey, Counter>>>,
+}
+
+impl UsageTracker {
+    pub fn new(config: UsageConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn config(&self) -> &UsageConfig {
+        &self.config
+    }
+
+    /// バイト数の上限があるか（リクエストのサイズの計算を省略するため）
+    pub fn counts_bytes(&self, key: &UsageKey) -> bool {
+        self.config
+            .quota_for(key)
+            .is_some_and(|quota| quota.max_bytes.is_some())
+    }
+
+    /// 上限を確認し、受け付ける場合は利用量に加算
+    pub fn record(&self, key: &UsageKey, bytes: u64) -> Result<(), QuotaExceeded> {
+        self.record_at(key, bytes, SystemTime::now())
+    }
+
+    fn record_at(&self, key: &UsageKey, bytes: u64, now: SystemTime) -> Result<(), QuotaExceeded> {
+        let Some(quota) = self.config.quota_for(key) else {
+            return Ok(());
+        };
+
+        let now_ms = unix_millis(now);
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters.entry(key.clone()).or_default();
+        let (requests, used_bytes, reset_at_ms) = counter.refresh(quota.window, now_ms);
+
+        let exceeded = |resource, limit| QuotaExceeded {
+            key: key.clone(),
+            resource,
+            limit,
+            reset_at: UNIX_EPOCH + Duration::from_millis(reset_at_ms),
+        };
+        if let Some(limit) = quota.max_requests {
+            if requests >= limit {
+                return Err(exceeded(QuotaResource::Requests, limit));
+            }
+        }
+        if let Some(limit) = quota.max_bytes {
+            if used_bytes + bytes > limit {
+                return Err(exceeded(QuotaResource::Bytes, limit));
+            }
+        }
+
+        counter.add(quota.window, now_ms, bytes);
+        Ok(())
+    }
+
+    /// 期間内の利用量（上限が設定されていなければ`None`）
+    pub fn usage(&self, key: &UsageKey) -> Option<Usage> {
+        let quota = self.config.quota_for(key)?;
+        let now_ms = unix_millis(SystemTime::now());
+        let mut counters = self.counters.lock().unwrap();
+        let (requests, bytes, reset_at_ms) = counters
+            .entry(key.clone())
+            .or_default()
+            .refresh(quota.window, now_ms);
+        Some(Usage {
+            requests,
+            bytes,
+            max_requests: quota.max_requests,
+            max_bytes: quota.max_bytes,
+            reset_at_ms,
+        })
+    }
+
+    /// 集計中の全テナントの利用量（テナントID順）
+    pub fn tenant_usage(&self) -> Vec<(TenantId, Usage)> {
+        let keys: Vec<UsageKey> = self.counters.lock().unwrap().keys().cloned().collect();
+        let mut usage: Vec<_> = keys
+            .into_iter()
+            .filter_map(|key| match &key {
+                UsageKey::Tenant(tenant) => Some((tenant.clone(), self.usage(&key)?)),
+                UsageKey::Peer(_) => None,
+            })
+            .collect();
+        usage.sort_by(|a, b| a.0.cmp(&b.0));
+        usage
+    }
+
+    /// 切断した接続の集計を破棄
+    pub fn remove_peer(&self, connection_id: ConnectionId) {
+        self.counters
+            .lock()
+            .unwrap()
+            .remove(&UsageKey::Peer(connection_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acme() -> UsageKey {
+        UsageKey::Tenant(TenantId::parse("acme").unwrap())
+    }
+
+    #[test]
+    fn test_daily_quota_resets_at_utc_midnight() {
+        let tracker = UsageTracker::new(
+            UsageConfig::default().with_tenant_quota(UsageQuota::daily().with_max_requests(2)),
+        );
+        // 2024-01-01 12:00:00 UTC
+        let noon = UNIX_EPOCH + Duration::from_secs(1_704_110_400);
+        tracker.record_at(&acme(), 0, noon).unwrap();
+        tracker.record_at(&acme(), 0, noon).unwrap();
+
+        let exceeded = tracker.record_at(&acme(), 0, noon).unwrap_err();
+        assert_eq!(exceeded.resource, QuotaResource::Requests);
+        // 翌日0時（2024-01-02 00:00:00 UTC）にリセット
+        assert_eq!(exceeded.reset_at_millis(), 1_704_153_600_000);
+
+        let next_day = UNIX_EPOCH + Duration::from_secs(1_704_153_600);
+        assert!(tracker.record_at(&acme(), 0, next_day).is_ok());
+    }
+
+    #[test]
+    fn test_rolling_byte_quota() {
+        let tracker = UsageTracker::new(
+            UsageConfig::default()
+                .with_peer_quota(UsageQuota::rolling(Duration::from_secs(60)).with_max_bytes(100)),
+        );
+        let peer = UsageKey::Peer(7);
+        let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        tracker.record_at(&peer, 60, start).unwrap();
+        tracker
+            .record_at(&peer, 30, start + Duration::from_secs(30))
+            .unwrap();
+
+        let exceeded = tracker
+            .record_at(&peer, 20, start + Duration::from_secs(40))
+            .unwrap_err();
+        assert_eq!(exceeded.resource, QuotaResource::Bytes);
+        // 最初の区間が期間外になる時刻にリセット
+        assert_eq!(
+            exceeded.reset_at,
+            start + Duration::from_secs(60),
+            "reset when the oldest slot leaves the window"
+        );
+
+        // 最初の60バイトが期間外になれば受け付ける
+        assert!(
+            tracker
+                .record_at(&peer, 20, start + Duration::from_secs(61))
+                .is_ok()
+        );
+        // テナントには上限が設定されていない
+        assert!(tracker.usage(&acme()).is_none());
+    }
+}
@@ -0,0 +1,474 @@
+//! WebSocketトランスポート
+//!
+//! `ws://`・`wss://`のURLで、QUICを使えないブラウザ等からUnisonのサービスへ接続します。
+//! 各メッセージは1つのバイナリフレームに[`ProtocolFrame`](super::ProtocolFrame)として格納します。
+//! [`WireFormat::Json`]を指定すると、パケット層に対応していない古い相手との互換用に
+//! テキストフレームへ[`stdio`](super::stdio)と同じJSON形式で格納します。
+//! 受信はどちらの形式のフレームも解釈します。
+//!
+//! ハンドラー・ストリーム・Pub/Sub・ブロードキャストはQUICの接続と共通のため、
+//! 同じ[`ProtocolServer`]をQUICとWebSocketの両方で公開できます。
+//!
+//! - サーバー: [`UnisonServer::listen`]に`ws://`・`wss://`のURLを渡すか、[`WebSocketServer`]を使います。
+//!   `wss://`はQUICと同じ証明書で待ち受けます。
+//! - クライアント: [`ProtocolClient::connect`](super::ProtocolClient::connect)に
+//!   `ws://`・`wss://`のURLを渡すか、[`WebSocketClient`]を使います。
+
+use bytes::Bytes;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tracing::{debug, error, info};
+
+use super::decode::{DecodeConfig, decode_frame};
+use super::framing::WireFormat;
+use super::server::{ClientStreamRequests, ProtocolServer};
+use super::stdio::{MessageClient, decode_line, encode_line, serve_messages};
+use super::{NetworkError, ProtocolError, ProtocolMessage, UnisonServer, UnisonServerExt};
+
+/// WebSocketのURLスキーム
+pub const WS_SCHEME: &str = "ws://";
+/// TLSを使うWebSocketのURLスキーム
+pub const WSS_SCHEME: &str = "wss://";
+
+/// WebSocketのURLか
+pub fn is_websocket_url(url: &str) -> bool {
+    url.starts_with(WS_SCHEME) || url.starts_with(WSS_SCHEME)
+}
+
+/// `ws://host:port/path`から待ち受けアドレスとTLSの有無を取り出す
+fn listen_addr(url: &str) -> Result<(&str, bool), NetworkError> {
+    let (rest, tls) = if let Some(rest) = url.strip_prefix(WSS_SCHEME) {
+        (rest, true)
+    } else if let Some(rest) = url.strip_prefix(WS_SCHEME) {
+        (rest, false)
+    } else {
+        return Err(NetworkError::UnsupportedTransport(url.to_string()));
+    };
+    let addr = rest.split('/').next().unwrap_or_default();
+    if addr.is_empty() {
+        return Err(NetworkError::Connection(format!(
+            "Missing address in {}",
+            url
+        )));
+    }
+    Ok((addr, tls))
+}
+
+fn ws_error(e: tungstenite::Error) -> NetworkError {
+    NetworkError::Connection(format!("WebSocket error: {}", e))
+}
+
+/// 受信したフレームをメッセージの列に変換
+///
+/// 制御フレームは読み飛ばし、接続が閉じると終了します。
+fn decode_frames<S>(incoming: S) -> impl Stream<Item = Result<ProtocolMessage, NetworkError>> + Send
+where
+    S: Stream<Item = Result<Message, tungstenite::Error>> + Send,
+{
+    incoming
+        .take_while(|frame| {
+            std::future::ready(!matches!(
+                frame,
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed)
+            ))
+        })
+        .filter_map(|frame| async move {
+            match frame {
+                Ok(Message::Text(text)) => Some(decode_line(&text)),
+                Ok(Message::Binary(bytes)) => Some(
+                    decode_frame(Bytes::from(bytes), DecodeConfig::default())
+                        .await
+                        .map(|(_, message)| message)
+                        .map_err(NetworkError::from),
+                ),
+                Ok(_) => None,
+                Err(e) => Some(Err(ws_error(e))),
+            }
+        })
+}
+
+/// メッセージを指定の形式のフレームに変換
+fn encode_frame(message: ProtocolMessage, format: WireFormat) -> Result<Message, NetworkError> {
+    match format {
+        WireFormat::Packet => Ok(Message::Binary(message.into_frame()?.to_bytes().to_vec())),
+        WireFormat::Json => Ok(Message::Text(encode_line(&message)?)),
+    }
+}
+
+/// 送信するメッセージをフレームとして書き込むタスクを開始
+///
+/// 送信側がすべて閉じるとクローズフレームを送って終了します。
+fn spawn_socket_writer<S>(
+    sink: S,
+    format: WireFormat,
+) -> (mpsc::UnboundedSender<ProtocolMessage>, JoinHandle<()>)
+where
+    S: Sink<Message, Error = tungstenite::Error> + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<ProtocolMessage>();
+    let task = tokio::spawn(async move {
+        let mut sink = sink;
+        while let Some(message) = rx.recv().await {
+            let frame = match encode_frame(message, format) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    error!("Failed to encode message: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = sink.send(frame).await {
+                debug!("Failed to write WebSocket frame: {}", e);
+                return;
+            }
+        }
+        let _ = sink.close().await;
+    });
+    (tx, task)
+}
+
+/// WebSocketのハンドシェイクを受け付けてリクエストを処理
+async fn serve_socket<S>(
+    server: &Arc<ProtocolServer>,
+    peer: SocketAddr,
+    stream: S,
+) -> Result<(), NetworkError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let socket = tokio_tungstenite::accept_async(stream)
+        .await
+        .map_err(ws_error)?;
+    let (sink, incoming) = socket.split();
+    let (tx, writer_task) = spawn_socket_writer(sink, server.wire_format());
+    let result = serve_messages(server, Some(peer), decode_frames(incoming), tx).await;
+    let _ = writer_task.await;
+    result
+}
+
+/// `wss://`で使うTLSの設定（QUICと同じ証明書）
+fn tls_acceptor() -> Result<tokio_rustls::TlsAcceptor, NetworkError> {
+    let (certs, key) = super::quic::QuicServer::load_cert_auto()
+        .map_err(|e| NetworkError::Connection(format!("Failed to load certificate: {:#}", e)))?;
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| NetworkError::Connection(format!("Invalid certificate: {}", e)))?;
+    Ok(tokio_rustls::TlsAcceptor::from(Arc::new(config)))
+}
+
+/// `ws://`・`wss://`で待ち受け、停止が要求されるまで接続を処理
+///
+/// 停止後は猶予期間まで処理中のリクエストの完了を待ってから戻ります。
+pub(crate) async fn listen(server: Arc<ProtocolServer>, url: &str) -> Result<(), NetworkError> {
+    let (addr, tls) = listen_addr(url)?;
+    let acceptor = tls.then(tls_acceptor).transpose()?;
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| NetworkError::Connection(format!("Failed to bind {}: {}", addr, e)))?;
+    info!(
+        "🎵 Unison Protocol server listening on {} via WebSocket",
+        url
+    );
+
+    let stopped = server.shutdown_controller().stopped();
+    tokio::pin!(stopped);
+    let connections = server.task_supervisor("WebSocket connections");
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut stopped => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("Failed to accept WebSocket connection: {}", e);
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        let acceptor = acceptor.clone();
+        connections.spawn(async move {
+            let result = match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => serve_socket(&server, peer, stream).await,
+                    Err(e) => Err(NetworkError::Connection(format!("TLS error: {}", e))),
+                },
+                None => serve_socket(&server, peer, stream).await,
+            };
+            if let Err(e) = result {
+                debug!("WebSocket connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+
+    drop(listener);
+    connections.join(server.shutdown_grace()).await;
+    Ok(())
+}
+
+/// WebSocketで待ち受けるサーバー
+///
+/// [`ProtocolServer`]のハンドラーをそのまま使い、`listen`ではアドレスを
+/// `ws://`のURLとして扱います（`host:port`のみでも可）。
+pub struct WebSocketServer {
+    server: ProtocolServer,
+}
+
+impl WebSocketServer {
+    pub fn new(server: ProtocolServer) -> Self {
+        Self { server }
+    }
+
+    /// ハンドラーの登録や接続の管理に使う内部のサーバー
+    pub fn server(&self) -> &ProtocolServer {
+        &self.server
+    }
+}
+
+impl UnisonServer for WebSocketServer {
+    async fn listen(&mut self, addr: &str) -> Result<(), NetworkError> {
+        if is_websocket_url(addr) {
+            self.server.listen(addr).await
+        } else {
+            self.server.listen(&format!("{}{}", WS_SCHEME, addr)).await
+        }
+    }
+
+    async fn stop(&mut self) -> Result<(), NetworkError> {
+        self.server.stop().await
+    }
+
+    fn is_running(&self) -> bool {
+        self.server.is_running()
+    }
+}
+
+impl UnisonServerExt for WebSocketServer {
+    fn register_handler<F>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(Value) -> Result<Value, NetworkError> + Send + Sync + 'static,
+    {
+        UnisonServerExt::register_handler(&mut self.server, method, handler);
+    }
+
+    fn register_async_handler<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, NetworkError>> + Send + 'static,
+    {
+        UnisonServerExt::register_async_handler(&mut self.server, method, handler);
+    }
+
+    fn register_stream_handler<F>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(Value) -> Pin<Box<dyn Stream<Item = Result<Value, NetworkError>> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        UnisonServerExt::register_stream_handler(&mut self.server, method, handler);
+    }
+
+    fn register_client_stream_handler<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(ClientStreamRequests) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, NetworkError>> + Send + 'static,
+    {
+        UnisonServerExt::register_client_stream_handler(&mut self.server, method, handler);
+    }
+
+    fn register_system_stream_handler<F>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(
+                Value,
+                super::quic::UnisonStream,
+            ) -> Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        UnisonServerExt::register_system_stream_handler(&mut self.server, method, handler);
+    }
+
+    fn register_typed_handler<Req, Res, E, F, Fut>(&mut self, method: &str, handler: F)
+    where
+        Req: serde::de::DeserializeOwned + Send + 'static,
+        Res: serde::Serialize + Send + 'static,
+        E: Into<ProtocolError> + Send + 'static,
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Res, E>> + Send + 'static,
+    {
+        UnisonServerExt::register_typed_handler(&mut self.server, method, handler);
+    }
+}
+
+/// WebSocketで接続するクライアント
+pub struct WebSocketClient {
+    inner: MessageClient,
+}
+
+impl WebSocketClient {
+    /// `ws://`・`wss://`のURLで接続
+    ///
+    /// `wss://`のサーバー証明書はWeb PKIのルート証明書で検証します。
+    pub async fn connect(url: &str) -> Result<Self, NetworkError> {
+        Self::connect_with_wire_format(url, WireFormat::default()).await
+    }
+
+    /// 送信するメッセージの形式を指定して接続
+    ///
+    /// パケット層に対応していない古いサーバーへは[`WireFormat::Json`]で接続します。
+    pub async fn connect_with_wire_format(
+        url: &str,
+        format: WireFormat,
+    ) -> Result<Self, NetworkError> {
+        if !is_websocket_url(url) {
+            return Err(NetworkError::UnsupportedTransport(url.to_string()));
+        }
+        let (socket, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(ws_error)?;
+        let (sink, incoming) = socket.split();
+        let (outgoing, writer_task) = spawn_socket_writer(sink, format);
+        Ok(Self {
+            inner: MessageClient::from_parts(decode_frames(incoming), outgoing, writer_task),
+        })
+    }
+
+    /// リクエストを送信してレスポンスを待機
+    pub async fn call(&self, method: &str, payload: Value) -> Result<Value, NetworkError> {
+        self.inner.call(method, payload).await
+    }
+
+    /// ストリーミング呼び出しを開始
+    pub fn stream(
+        &self,
+        method: &str,
+        payload: Value,
+    ) -> Result<impl Stream<Item = Result<Value, NetworkError>> + Send + 'static, NetworkError>
+    {
+        self.inner.stream(method, payload)
+    }
+
+    /// クライアントストリームを送信してレスポンスを待機
+    pub async fn client_stream<S>(&self, method: &str, requests: S) -> Result<Value, NetworkError>
+    where
+        S: Stream<Item = Result<Value, NetworkError>> + Send,
+    {
+        self.inner.client_stream(method, requests).await
+    }
+
+    /// レスポンス・ストリーム以外のメッセージ（イベント）を受信
+    ///
+    /// 接続が閉じると`None`を返します。
+    pub async fn next_event(&self) -> Option<ProtocolMessage> {
+        self.inner.next_event().await
+    }
+
+    /// 接続中か
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_open()
+    }
+
+    pub(crate) fn into_inner(self) -> MessageClient {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+
+    #[test]
+    fn test_listen_addr() {
+        assert_eq!(
+            listen_addr("ws://127.0.0.1:80").unwrap(),
+            ("127.0.0.1:80", false)
+        );
+        assert_eq!(
+            listen_addr("wss://[::1]:443/unison").unwrap(),
+            ("[::1]:443", true)
+        );
+        assert!(matches!(
+            listen_addr("quic://localhost:8080"),
+            Err(NetworkError::UnsupportedTransport(_))
+        ));
+        assert!(listen_addr("ws:///path").is_err());
+        assert!(is_websocket_url("wss://example.com"));
+        assert!(!is_websocket_url("[::1]:8080"));
+    }
+
+    #[tokio::test]
+    async fn test_call_stream_and_events_over_websocket() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let server = ProtocolServer::new().with_call_handler("echo", |payload| async move {
+            Ok::<_, NetworkError>(payload)
+        });
+        server
+            .register_stream_handler("count", |payload| async move {
+                let n = payload["n"].as_u64().unwrap_or(0);
+                Ok(futures_util::stream::iter(
+                    (0..n).map(|i| Ok(serde_json::json!({ "i": i }))),
+                ))
+            })
+            .await;
+        let mut server = WebSocketServer::new(server);
+        let shutdown = server.server().shutdown_controller().clone();
+        let registry = server.server().connections().clone();
+        let listen_url = url.clone();
+        let listening = tokio::spawn(async move { server.listen(&listen_url).await });
+
+        let client = loop {
+            match WebSocketClient::connect(&url).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        assert!(client.is_connected());
+        let response = client
+            .call("echo", serde_json::json!({ "text": "hi" }))
+            .await
+            .unwrap();
+        assert_eq!(response["text"], "hi");
+        assert!(matches!(
+            client.call("missing", Value::Null).await,
+            Err(NetworkError::Remote(e)) if e.code == "404"
+        ));
+
+        let items: Vec<Value> = client
+            .stream("count", serde_json::json!({ "n": 3 }))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[2]["i"], 2);
+
+        let event = ProtocolMessage::new_with_json(
+            0,
+            "notice".into(),
+            MessageType::Event,
+            serde_json::json!({}),
+        )
+        .unwrap()
+        .into_frame()
+        .unwrap();
+        registry.broadcast_frame(event.to_bytes()).wait().await;
+        assert_eq!(client.next_event().await.unwrap().method, "notice");
+
+        shutdown.trigger();
+        listening.await.unwrap().unwrap();
+    }
+}
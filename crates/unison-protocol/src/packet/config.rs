@@ -97,7 +97,7 @@ pub struct PacketConfig {
     /// 最大ペイロードサイズ（バイト）
     pub max_payload_size: usize,
 
-    /// フレームバージョン
+    /// 送信するヘッダーのバージョン（v2で拡張領域が使える）
     pub version: u8,
 }
 
@@ -119,6 +119,12 @@ impl PacketConfig {
         self
     }
 
+    /// ヘッダーのバージョンを設定（ハンドシェイクでネゴシエーションしたバージョン）
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
     /// 高性能設定（圧縮無効）
     pub fn high_performance() -> Self {
         Self {
@@ -1,11 +1,52 @@
 //! フレームヘッダーの定義
 //!
 //! UnisonPacketのヘッダー構造を定義します。
-//! rkyvによるゼロコピーシリアライゼーションをサポートします。
+//!
+//! ## ワイヤー形式
+//!
+//! 先頭1バイトのバージョンで形式を判別し、両方の形式を読み取れます。
+//!
+//! - **v1**: rkyvでアーカイブしたヘッダー（固定長[`V1_HEADER_SIZE`]バイト）
+//! - **v2**: リトルエンディアンの固定部（[`V2_FIXED_HEADER_SIZE`]バイト）に続いて
+//!   TLV形式の拡張領域を持つヘッダー
+//!
+//! v2の拡張領域には`type: u16, length: u16, value`の形式で拡張を並べ、
+//! 8バイト境界までゼロで埋めます（タイプ0はパディング）。未知の拡張タイプは
+//! 読み飛ばされるため、新しいフィールドを追加しても古いピアとの互換性を保てます。
+//!
+//! どちらの形式で送信するかはハンドシェイクで交換したバージョンから
+//! [`UnisonPacketHeader::negotiate_version`]で決定します。
 
 use super::flags::PacketFlags;
 use rkyv::{Archive, Deserialize, Serialize};
 
+/// v1ヘッダー（rkyvアーカイブ）のバイト数
+pub const V1_HEADER_SIZE: usize = std::mem::size_of::<ArchivedUnisonPacketHeader>();
+
+/// v2ヘッダーの固定部のバイト数（拡張領域を除く）
+pub const V2_FIXED_HEADER_SIZE: usize = 56;
+
+/// ヘッダー拡張のタイプ
+pub mod extension_type {
+    /// パディング（拡張領域の終端）
+    pub const PADDING: u16 = 0x0000;
+    /// トレースID
+    pub const TRACE_ID: u16 = 0x0001;
+    /// テナントID
+    pub const TENANT_ID: u16 = 0x0002;
+    /// QoSクラス
+    pub const QOS: u16 = 0x0003;
+    /// 呼び出しの期限（UNIXエポックからのミリ秒、u64リトルエンディアン）
+    pub const DEADLINE: u16 = 0x0004;
+}
+
+/// ヘッダー拡張（TLV）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderExtension {
+    pub ext_type: u16,
+    pub value: Vec<u8>,
+}
+
 /// フレームタイプを定義する列挙型
 #[derive(Archive, Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[archive(check_bytes)]
@@ -49,9 +90,11 @@ impl From<PacketType> for u8 {
 
 /// UnisonPacketのヘッダー構造
 ///
-/// 固定長48バイトのヘッダーで、パケットのメタデータを格納します。
+/// パケットのメタデータを格納します。拡張はv2形式でのみ送信されます。
+/// v1でも先頭バイトでバージョンを判別できるよう、アーカイブのフィールド順を固定しています。
 #[derive(Archive, Deserialize, Serialize, Debug, Clone)]
 #[archive(check_bytes)]
+#[archive_attr(repr(C))]
 pub struct UnisonPacketHeader {
     /// プロトコルバージョン（現在: 0x01）
     pub version: u8,
@@ -82,11 +125,21 @@ pub struct UnisonPacketHeader {
 
     /// 応答先メッセージID（0=Request/Oneway, >0=Response）
     pub response_to: u64,
+
+    /// ヘッダー拡張（v2のみ、v1のアーカイブには含まれない）
+    #[with(rkyv::with::Skip)]
+    pub extensions: Vec<HeaderExtension>,
 }
 
 impl UnisonPacketHeader {
-    /// 現在のプロトコルバージョン
-    pub const CURRENT_VERSION: u8 = 0x01;
+    /// v1形式（rkyvアーカイブ）
+    pub const V1: u8 = 0x01;
+    /// v2形式（固定部＋TLV拡張領域）
+    pub const V2: u8 = 0x02;
+    /// 既定で送信するバージョン（ネゴシエーション前でも古いピアが読めるv1）
+    pub const CURRENT_VERSION: u8 = Self::V1;
+    /// 読み書きできるバージョン
+    pub const SUPPORTED_VERSIONS: [u8; 2] = [Self::V1, Self::V2];
 
     /// 新しいヘッダーを作成
     pub fn new(packet_type: PacketType) -> Self {
@@ -104,9 +157,19 @@ impl UnisonPacketHeader {
             stream_id: 0,
             message_id: 0,
             response_to: 0,
+            extensions: Vec::new(),
         }
     }
 
+    /// 相手の対応バージョンとの共通で最新のバージョンを選択
+    pub fn negotiate_version(remote_versions: &[u8]) -> Option<u8> {
+        Self::SUPPORTED_VERSIONS
+            .iter()
+            .rev()
+            .find(|v| remote_versions.contains(v))
+            .copied()
+    }
+
     /// フレームタイプを取得
     pub fn packet_type(&self) -> PacketType {
         PacketType::from(self.packet_type)
@@ -134,7 +197,35 @@ impl UnisonPacketHeader {
 
     /// バージョンの互換性をチェック
     pub fn is_compatible(&self) -> bool {
-        self.version == Self::CURRENT_VERSION
+        Self::SUPPORTED_VERSIONS.contains(&self.version)
+    }
+
+    /// 送信するバージョンを設定
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// 拡張を追加（同じタイプの拡張は置き換え）
+    pub fn with_extension(mut self, ext_type: u16, value: impl Into<Vec<u8>>) -> Self {
+        self.set_extension(ext_type, value);
+        self
+    }
+
+    pub fn set_extension(&mut self, ext_type: u16, value: impl Into<Vec<u8>>) {
+        let value = value.into();
+        match self.extensions.iter_mut().find(|e| e.ext_type == ext_type) {
+            Some(extension) => extension.value = value,
+            None => self.extensions.push(HeaderExtension { ext_type, value }),
+        }
+    }
+
+    /// 拡張の値を取得
+    pub fn extension(&self, ext_type: u16) -> Option<&[u8]> {
+        self.extensions
+            .iter()
+            .find(|e| e.ext_type == ext_type)
+            .map(|e| e.value.as_slice())
     }
 
     /// ペイロードの実際のサイズを取得（圧縮されている場合は圧縮後のサイズ）
@@ -266,10 +357,32 @@ mod tests {
 
     #[test]
     fn test_header_size() {
-        // ヘッダーサイズが48バイトであることを確認
-        use std::mem::size_of;
-        let header_size = size_of::<UnisonPacketHeader>();
-        assert_eq!(header_size, 64, "Header size should be exactly 64 bytes");
+        // ワイヤー上の固定部はv1・v2とも56バイト
+        assert_eq!(V1_HEADER_SIZE, 56, "v1 header should be exactly 56 bytes");
+        assert_eq!(V2_FIXED_HEADER_SIZE, 56);
+    }
+
+    #[test]
+    fn test_version_negotiation_and_extensions() {
+        assert_eq!(
+            UnisonPacketHeader::negotiate_version(&[0x01, 0x02, 0x03]),
+            Some(UnisonPacketHeader::V2)
+        );
+        assert_eq!(
+            UnisonPacketHeader::negotiate_version(&[0x01]),
+            Some(UnisonPacketHeader::V1)
+        );
+        assert_eq!(UnisonPacketHeader::negotiate_version(&[0x09]), None);
+
+        let header = UnisonPacketHeader::new(PacketType::Data)
+            .with_extension(extension_type::TENANT_ID, b"acme".to_vec())
+            .with_extension(extension_type::TENANT_ID, b"globex".to_vec());
+        assert_eq!(header.extensions.len(), 1);
+        assert_eq!(
+            header.extension(extension_type::TENANT_ID),
+            Some(&b"globex"[..])
+        );
+        assert_eq!(header.extension(extension_type::TRACE_ID), None);
     }
 
     #[test]
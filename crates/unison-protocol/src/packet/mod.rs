@@ -37,7 +37,10 @@ pub mod serialization;
 // 主要な型を再エクスポート
 pub use config::{CompressionConfig, PacketConfig};
 pub use flags::PacketFlags;
-pub use header::{PacketType, UnisonPacketHeader};
+pub use header::{
+    HeaderExtension, PacketType, UnisonPacketHeader, V1_HEADER_SIZE, V2_FIXED_HEADER_SIZE,
+    extension_type,
+};
 pub use payload::{
     BytesPayload, EmptyPayload, JsonPayload, PayloadError, Payloadable, RkyvPayload, StringPayload,
 };
@@ -88,11 +91,14 @@ where
     }
 
     /// ヘッダーとペイロードを指定してフレームを作成（カスタム設定）
+    ///
+    /// ヘッダーは設定のバージョン（ネゴシエーション済みのバージョン）でエンコードされます。
     pub fn with_header_and_config(
         mut header: UnisonPacketHeader,
         payload: T,
         config: &PacketConfig,
     ) -> Result<Self, SerializationError> {
+        header.version = config.version;
         let raw_data = PacketSerializer::serialize_with_config(&mut header, &payload, config)?;
         Ok(Self {
             raw_data,
@@ -165,11 +171,9 @@ where
     where
         for<'b> T::Archived: rkyv::CheckBytes<rkyv::validation::validators::DefaultValidator<'b>>,
     {
-        let (header, _) = PacketDeserializer::deserialize_header(&self.raw_data)?;
-
         // ヘッダーサイズをスキップしてペイロード部分を取得
-        let payload_start = 48; // ヘッダーサイズ
-        let payload_bytes = &self.raw_data[payload_start..];
+        let (header, header_length) = PacketDeserializer::split_header(&self.raw_data)?;
+        let payload_bytes = &self.raw_data[header_length..];
 
         PacketDeserializer::deserialize_payload_zero_copy::<T>(&header, payload_bytes, buffer)
     }
@@ -183,6 +187,7 @@ where
     T: Payloadable,
 {
     header: UnisonPacketHeader,
+    compression: CompressionConfig,
     _phantom: PhantomData<T>,
 }
 
@@ -194,6 +199,7 @@ where
     pub fn new() -> Self {
         Self {
             header: UnisonPacketHeader::new(PacketType::Data),
+            compression: CompressionConfig::default(),
             _phantom: PhantomData,
         }
     }
@@ -228,6 +234,24 @@ where
         self
     }
 
+    /// ヘッダーのバージョンを設定（拡張を送るにはv2以降が必要）
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.header.version = version;
+        self
+    }
+
+    /// ヘッダー拡張を追加
+    pub fn with_extension(mut self, ext_type: u16, value: impl Into<Vec<u8>>) -> Self {
+        self.header.set_extension(ext_type, value);
+        self
+    }
+
+    /// ペイロードの圧縮設定を変更
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
     /// 高優先度フラグを設定
     pub fn with_high_priority(mut self) -> Self {
         let mut flags = self.header.flags();
@@ -255,7 +279,12 @@ where
         // タイムスタンプを更新
         self.header.update_timestamp();
 
-        UnisonPacket::with_header(self.header, payload)
+        let config = PacketConfig {
+            compression: self.compression,
+            version: self.header.version,
+            ..PacketConfig::default()
+        };
+        UnisonPacket::with_header_and_config(self.header, payload, &config)
     }
 }
 
@@ -280,20 +309,11 @@ pub struct UnisonPacketView<'a> {
 impl<'a> UnisonPacketView<'a> {
     /// Bytesからビューを作成
     pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, SerializationError> {
-        if bytes.len() < 48 {
-            return Err(SerializationError::InvalidHeader);
-        }
-
         // ヘッダーをパース
-        let header_bytes = &bytes[..48];
-        let archived_header = rkyv::check_archived_root::<UnisonPacketHeader>(header_bytes)
-            .map_err(|e| SerializationError::DeserializationFailed(e.to_string()))?;
-        let header: UnisonPacketHeader = archived_header
-            .deserialize(&mut rkyv::Infallible)
-            .map_err(|_| SerializationError::InvalidHeader)?;
+        let (header, header_length) = PacketDeserializer::split_header(bytes)?;
 
         // ペイロード部分を取得
-        let payload_bytes = &bytes[48..];
+        let payload_bytes = &bytes[header_length..];
         let is_compressed = header.is_compressed();
 
         Ok(Self {
@@ -333,7 +353,7 @@ mod tests {
         let payload = StringPayload::from_string("Test packet");
         let packet = UnisonPacket::new(payload.clone()).unwrap();
 
-        assert!(packet.size() > 48);
+        assert!(packet.size() > V1_HEADER_SIZE);
 
         let header = packet.header().unwrap();
         assert_eq!(header.packet_type(), PacketType::Data);
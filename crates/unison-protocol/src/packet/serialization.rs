@@ -1,16 +1,22 @@
 //! フレームのシリアライゼーション/デシリアライゼーション
 //!
 //! UnisonPacketとBytesの相互変換、圧縮/解凍処理を実装します。
+//! ヘッダーはバージョンに応じてv1（rkyv）またはv2（固定部＋TLV拡張）形式で読み書きします。
 
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use rkyv::Deserialize;
+use std::io::Read;
 use thiserror::Error;
-use zstd::stream::{decode_all, encode_all};
+use zstd::stream::{encode_all, read::Decoder};
+
+use crate::allocation::{self, Subsystem};
 
 use super::{
     config::PacketConfig,
     flags::PacketFlags,
-    header::UnisonPacketHeader,
+    header::{
+        HeaderExtension, UnisonPacketHeader, V1_HEADER_SIZE, V2_FIXED_HEADER_SIZE, extension_type,
+    },
     payload::{PayloadError, Payloadable},
 };
 
@@ -35,6 +41,12 @@ pub enum SerializationError {
     #[error("Incompatible protocol version: {version}")]
     IncompatibleVersion { version: u8 },
 
+    #[error("Header extensions require protocol version 2 or later (version: {version})")]
+    ExtensionsUnsupported { version: u8 },
+
+    #[error("Header extension area too large: {size} bytes")]
+    ExtensionsTooLarge { size: usize },
+
     #[error("Serialization failed: {0}")]
     SerializationFailed(String),
 
@@ -58,13 +70,19 @@ impl PacketSerializer {
     }
 
     /// ヘッダーとペイロードをBytesに変換（カスタム設定）
+    ///
+    /// ヘッダーは`header.version`の形式でエンコードされます。
     pub fn serialize_with_config<T: Payloadable>(
         header: &mut UnisonPacketHeader,
         payload: &T,
         config: &PacketConfig,
     ) -> Result<Bytes, SerializationError> {
+        let _alloc = allocation::enter(Subsystem::Packet);
         // ペイロードをシリアライズ
-        let payload_bytes = payload.to_bytes()?;
+        let payload_bytes = {
+            let _span = tracing::debug_span!("unison.serialize").entered();
+            payload.to_bytes()?
+        };
         let payload_size = payload_bytes.len();
 
         // ペイロードサイズを設定
@@ -72,7 +90,10 @@ impl PacketSerializer {
 
         // 圧縮判定と処理
         let (final_payload, is_compressed) = if config.compression.should_compress(payload_size) {
-            let compressed = Self::compress(&payload_bytes, config.compression.level)?;
+            let compressed = {
+                let _span = tracing::debug_span!("unison.compress", bytes = payload_size).entered();
+                Self::compress(&payload_bytes, config.compression.level)?
+            };
             let compressed_size = compressed.len();
 
             // 圧縮が効果的な場合のみ使用
@@ -116,11 +137,59 @@ impl PacketSerializer {
         Ok(packet.freeze())
     }
 
-    /// ヘッダーをシリアライズ
-    fn serialize_header(header: &UnisonPacketHeader) -> Result<Bytes, SerializationError> {
-        let bytes = rkyv::to_bytes::<_, 256>(header)
-            .map_err(|e| SerializationError::SerializationFailed(e.to_string()))?;
-        Ok(Bytes::from(bytes.to_vec()))
+    /// ヘッダーをバージョンに応じた形式でシリアライズ
+    pub fn serialize_header(header: &UnisonPacketHeader) -> Result<Bytes, SerializationError> {
+        match header.version {
+            UnisonPacketHeader::V1 => {
+                if !header.extensions.is_empty() {
+                    return Err(SerializationError::ExtensionsUnsupported {
+                        version: header.version,
+                    });
+                }
+                let bytes = rkyv::to_bytes::<_, 256>(header)
+                    .map_err(|e| SerializationError::SerializationFailed(e.to_string()))?;
+                Ok(Bytes::from(bytes.to_vec()))
+            }
+            UnisonPacketHeader::V2 => Self::serialize_header_v2(header),
+            version => Err(SerializationError::IncompatibleVersion { version }),
+        }
+    }
+
+    fn serialize_header_v2(header: &UnisonPacketHeader) -> Result<Bytes, SerializationError> {
+        let mut extensions = BytesMut::new();
+        for extension in &header.extensions {
+            let length = u16::try_from(extension.value.len()).map_err(|_| {
+                SerializationError::ExtensionsTooLarge {
+                    size: extension.value.len(),
+                }
+            })?;
+            extensions.put_u16_le(extension.ext_type);
+            extensions.put_u16_le(length);
+            extensions.put_slice(&extension.value);
+        }
+        // ペイロードのアラインメントを保つため8バイト境界までパディング
+        extensions.resize(extensions.len().next_multiple_of(8), 0);
+        let extensions_length = u16::try_from(extensions.len()).map_err(|_| {
+            SerializationError::ExtensionsTooLarge {
+                size: extensions.len(),
+            }
+        })?;
+
+        let mut bytes = BytesMut::with_capacity(V2_FIXED_HEADER_SIZE + extensions.len());
+        bytes.put_u8(header.version);
+        bytes.put_u8(header.packet_type);
+        bytes.put_u16_le(header.flags);
+        bytes.put_u32_le(header.payload_length);
+        bytes.put_u32_le(header.compressed_length);
+        bytes.put_u16_le(extensions_length);
+        bytes.put_u16_le(0); // 予約
+        bytes.put_u64_le(header.sequence_number);
+        bytes.put_u64_le(header.timestamp);
+        bytes.put_u64_le(header.stream_id);
+        bytes.put_u64_le(header.message_id);
+        bytes.put_u64_le(header.response_to);
+        bytes.put(extensions);
+        Ok(bytes.freeze())
     }
 
     /// ペイロードを圧縮
@@ -139,25 +208,26 @@ impl PacketDeserializer {
     pub fn deserialize_header(
         bytes: &Bytes,
     ) -> Result<(UnisonPacketHeader, Bytes), SerializationError> {
-        if bytes.len() < 48 {
-            return Err(SerializationError::InvalidHeader);
-        }
-
-        // ヘッダー部分を取得（最初の48バイト）
-        let header_bytes = &bytes[..48];
-        let header = Self::parse_header(header_bytes)?;
+        let (header, header_length) = Self::split_header(bytes)?;
+        Ok((header, bytes.slice(header_length..)))
+    }
 
-        // バージョンチェック
-        if !header.is_compatible() {
-            return Err(SerializationError::IncompatibleVersion {
-                version: header.version,
-            });
+    /// 先頭のヘッダーをパースし、ヘッダーとそのバイト数を返す
+    ///
+    /// 先頭1バイトのバージョンでv1・v2の形式を判別します。
+    pub fn split_header(bytes: &[u8]) -> Result<(UnisonPacketHeader, usize), SerializationError> {
+        match bytes.first() {
+            Some(&UnisonPacketHeader::V1) => {
+                if bytes.len() < V1_HEADER_SIZE {
+                    return Err(SerializationError::InvalidHeader);
+                }
+                let header = Self::parse_header(&bytes[..V1_HEADER_SIZE])?;
+                Ok((header, V1_HEADER_SIZE))
+            }
+            Some(&UnisonPacketHeader::V2) => Self::parse_header_v2(bytes),
+            Some(&version) => Err(SerializationError::IncompatibleVersion { version }),
+            None => Err(SerializationError::InvalidHeader),
         }
-
-        // ペイロード部分を取得
-        let payload_bytes = bytes.slice(48..);
-
-        Ok((header, payload_bytes))
     }
 
     /// ペイロードをデシリアライズ（デフォルト設定）
@@ -176,12 +246,13 @@ impl PacketDeserializer {
     pub fn deserialize_payload_with_config<T: Payloadable>(
         header: &UnisonPacketHeader,
         payload_bytes: &Bytes,
-        _config: &PacketConfig,
+        config: &PacketConfig,
     ) -> Result<T, SerializationError>
     where
         T::Archived: Deserialize<T, rkyv::Infallible>,
         for<'a> T::Archived: rkyv::CheckBytes<rkyv::validation::validators::DefaultValidator<'a>>,
     {
+        let _alloc = allocation::enter(Subsystem::Packet);
         // サイズチェック
         let expected_size = header.actual_payload_size() as usize;
         if payload_bytes.len() != expected_size {
@@ -190,12 +261,16 @@ impl PacketDeserializer {
 
         // 解凍（必要な場合）
         let decompressed = if header.is_compressed() {
-            Self::decompress(payload_bytes)?
+            let _span =
+                tracing::debug_span!("unison.decompress", bytes = payload_bytes.len()).entered();
+            let limit = Self::decompressed_limit(header, config)?;
+            Bytes::from(Self::decompress(payload_bytes, limit)?)
         } else {
             payload_bytes.clone()
         };
 
         // ペイロードをデシリアライズ
+        let _span = tracing::debug_span!("unison.deserialize").entered();
         T::from_bytes(&decompressed).map_err(Into::into)
     }
 
@@ -210,7 +285,8 @@ impl PacketDeserializer {
     {
         // 解凍が必要な場合はバッファを使用
         if header.is_compressed() {
-            *buffer = Self::decompress_to_vec(payload_bytes)?;
+            let limit = Self::decompressed_limit(header, &PacketConfig::default())?;
+            *buffer = Self::decompress(payload_bytes, limit)?;
             T::from_bytes_zero_copy(buffer).map_err(Into::into)
         } else {
             // 圧縮されていない場合は直接ゼロコピー
@@ -218,9 +294,12 @@ impl PacketDeserializer {
         }
     }
 
-    /// ヘッダーをパース
+    /// v1ヘッダーをパース
     fn parse_header(bytes: &[u8]) -> Result<UnisonPacketHeader, SerializationError> {
-        let archived = rkyv::check_archived_root::<UnisonPacketHeader>(bytes)
+        // 受信バッファのアラインメントは保証されないため、アラインされた領域へコピー
+        let mut aligned = rkyv::AlignedVec::with_capacity(bytes.len());
+        aligned.extend_from_slice(bytes);
+        let archived = rkyv::check_archived_root::<UnisonPacketHeader>(&aligned)
             .map_err(|e| SerializationError::DeserializationFailed(e.to_string()))?;
 
         archived
@@ -228,16 +307,91 @@ impl PacketDeserializer {
             .map_err(|_| SerializationError::InvalidHeader)
     }
 
-    /// データを解凍
-    fn decompress(data: &[u8]) -> Result<Bytes, SerializationError> {
-        decode_all(data)
-            .map(Bytes::from)
-            .map_err(|e| SerializationError::DecompressionFailed(e.to_string()))
+    /// v2ヘッダーをパース
+    fn parse_header_v2(bytes: &[u8]) -> Result<(UnisonPacketHeader, usize), SerializationError> {
+        if bytes.len() < V2_FIXED_HEADER_SIZE {
+            return Err(SerializationError::InvalidHeader);
+        }
+
+        let mut fixed = &bytes[..V2_FIXED_HEADER_SIZE];
+        let version = fixed.get_u8();
+        let packet_type = fixed.get_u8();
+        let flags = fixed.get_u16_le();
+        let payload_length = fixed.get_u32_le();
+        let compressed_length = fixed.get_u32_le();
+        let extensions_length = fixed.get_u16_le() as usize;
+        let _reserved = fixed.get_u16_le();
+        let mut header = UnisonPacketHeader {
+            version,
+            packet_type,
+            flags,
+            payload_length,
+            compressed_length,
+            sequence_number: fixed.get_u64_le(),
+            timestamp: fixed.get_u64_le(),
+            stream_id: fixed.get_u64_le(),
+            message_id: fixed.get_u64_le(),
+            response_to: fixed.get_u64_le(),
+            extensions: Vec::new(),
+        };
+
+        let header_length = V2_FIXED_HEADER_SIZE + extensions_length;
+        let mut area = bytes
+            .get(V2_FIXED_HEADER_SIZE..header_length)
+            .ok_or(SerializationError::InvalidHeader)?;
+        while area.len() >= 4 {
+            let ext_type = area.get_u16_le();
+            if ext_type == extension_type::PADDING {
+                break;
+            }
+            let length = area.get_u16_le() as usize;
+            if area.len() < length {
+                return Err(SerializationError::InvalidHeader);
+            }
+            header.extensions.push(HeaderExtension {
+                ext_type,
+                value: area[..length].to_vec(),
+            });
+            area.advance(length);
+        }
+
+        Ok((header, header_length))
     }
 
-    /// データを解凍（Vec<u8>として）
-    fn decompress_to_vec(data: &[u8]) -> Result<Vec<u8>, SerializationError> {
-        decode_all(data).map_err(|e| SerializationError::DecompressionFailed(e.to_string()))
+    /// 解凍後の大きさの上限（ヘッダーの`payload_length`が設定の上限を超える場合はエラー）
+    fn decompressed_limit(
+        header: &UnisonPacketHeader,
+        config: &PacketConfig,
+    ) -> Result<usize, SerializationError> {
+        let size = header.payload_length as usize;
+        if size > config.max_payload_size {
+            return Err(SerializationError::PacketTooLarge {
+                size,
+                max_size: config.max_payload_size,
+            });
+        }
+        Ok(size)
+    }
+
+    /// データを最大`limit`バイトまで解凍
+    ///
+    /// ヘッダーの`payload_length`と異なる大きさに展開されるデータは拒否します。
+    fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, SerializationError> {
+        let decoder = Decoder::new(data)
+            .map_err(|e| SerializationError::DecompressionFailed(e.to_string()))?;
+        let mut decompressed = Vec::new();
+        decoder
+            .take(limit as u64 + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| SerializationError::DecompressionFailed(e.to_string()))?;
+        if decompressed.len() != limit {
+            return Err(SerializationError::DecompressionFailed(format!(
+                "Decompressed {} bytes, expected {}",
+                decompressed.len(),
+                limit
+            )));
+        }
+        Ok(decompressed)
     }
 }
 
@@ -316,6 +470,48 @@ mod tests {
         assert_eq!(archived.data.as_slice(), &[1, 2, 3, 4, 5]);
     }
 
+    #[test]
+    fn test_v2_header_round_trip_with_extensions() {
+        let mut header = UnisonPacketHeader::new(PacketType::Data)
+            .with_version(UnisonPacketHeader::V2)
+            .with_sequence(7)
+            .with_message_id(42)
+            .with_extension(extension_type::TRACE_ID, vec![0xAB; 16])
+            .with_extension(extension_type::TENANT_ID, b"acme".to_vec())
+            .with_extension(0x7FFF, b"future".to_vec());
+        let payload = StringPayload::from_string("v2 payload");
+
+        let packet = PacketSerializer::serialize(&mut header, &payload).unwrap();
+        let (restored_header, payload_bytes) =
+            PacketDeserializer::deserialize_header(&packet).unwrap();
+        assert_eq!(restored_header.version, UnisonPacketHeader::V2);
+        assert_eq!(restored_header.sequence_number, 7);
+        assert_eq!(restored_header.message_id, 42);
+        // 未知の拡張タイプも保持される
+        assert_eq!(restored_header.extensions, header.extensions);
+
+        let restored_payload: StringPayload =
+            PacketDeserializer::deserialize_payload(&restored_header, &payload_bytes).unwrap();
+        assert_eq!(restored_payload.data, "v2 payload");
+    }
+
+    #[test]
+    fn test_extensions_require_v2() {
+        let mut header = UnisonPacketHeader::new(PacketType::Data)
+            .with_extension(extension_type::TENANT_ID, b"acme".to_vec());
+        let payload = StringPayload::from_string("v1 payload");
+        assert!(matches!(
+            PacketSerializer::serialize(&mut header, &payload),
+            Err(SerializationError::ExtensionsUnsupported { version: 1 })
+        ));
+
+        let unknown = Bytes::from_static(&[0x09; 64]);
+        assert!(matches!(
+            PacketDeserializer::deserialize_header(&unknown),
+            Err(SerializationError::IncompatibleVersion { version: 0x09 })
+        ));
+    }
+
     #[test]
     fn test_compression_effectiveness() {
         // 圧縮が効果的なデータ
@@ -328,4 +524,36 @@ mod tests {
         assert!(header.is_compressed());
         assert!(header.compressed_length < header.payload_length / 2);
     }
+
+    #[test]
+    fn test_decompression_is_bounded_by_header() {
+        let mut header = UnisonPacketHeader::new(PacketType::Data);
+        let payload = StringPayload::new("a".repeat(64 * 1024));
+        let packet = PacketSerializer::serialize(&mut header, &payload).unwrap();
+        let (header, payload_bytes) = PacketDeserializer::deserialize_header(&packet).unwrap();
+        assert!(header.is_compressed());
+
+        // 実際より小さい大きさを示すヘッダーでは上限を超えて展開しない
+        let mut forged = header.clone();
+        forged.payload_length = 1024;
+        assert!(matches!(
+            PacketDeserializer::deserialize_payload::<StringPayload>(&forged, &payload_bytes),
+            Err(SerializationError::DecompressionFailed(_))
+        ));
+
+        // 設定の上限を超える大きさは展開する前に拒否する
+        let config = PacketConfig::default().with_max_payload_size(32 * 1024);
+        assert!(matches!(
+            PacketDeserializer::deserialize_payload_with_config::<StringPayload>(
+                &header,
+                &payload_bytes,
+                &config
+            ),
+            Err(SerializationError::PacketTooLarge { .. })
+        ));
+
+        let restored: StringPayload =
+            PacketDeserializer::deserialize_payload(&header, &payload_bytes).unwrap();
+        assert_eq!(restored.data.len(), 64 * 1024);
+    }
 }
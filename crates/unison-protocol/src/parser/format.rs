@@ -0,0 +1,141 @@
+//! KDLスキーマの整形
+//!
+//! スキーマを解析して標準のスタイルで出力し直します。チームでスキーマの書式を揃え、
+//! 差分を意味のある変更だけに保つために使います。
+//!
+//! - インデントは4スペース（コメントは保持）
+//! - ノードの引数をプロパティより前に置く
+//! - プロパティは`type`・`required`などの定義順、それ以外は名前順に並べる
+//! - 子ノードのうち`namespace`・`description`を先頭に置く
+
+use kdl::{KdlDocument, KdlEntry, KdlNode};
+
+use super::ParseError;
+
+/// 標準のプロパティの順序（ここにないプロパティは後ろに名前順で並べる）
+const PROPERTY_ORDER: &[&str] = &[
+    "version",
+    "type",
+    "required",
+    "default",
+    "min",
+    "max",
+    "min_length",
+    "max_length",
+    "pattern",
+    "validator",
+    "timeout_ms",
+    "idempotent",
+    "paged",
+    "description",
+];
+
+/// 他の子ノードより前に置く子ノード
+const LEADING_CHILDREN: &[&str] = &["namespace", "description"];
+
+/// KDLスキーマを標準のスタイルに整形
+pub fn format_schema(input: &str) -> Result<String, ParseError> {
+    let mut document: KdlDocument = input.parse()?;
+    canonicalize(&mut document);
+    document.autoformat();
+    Ok(document.to_string())
+}
+
+/// 整形済みか（`unison fmt --check`用）
+pub fn is_formatted(input: &str) -> Result<bool, ParseError> {
+    Ok(format_schema(input)? == input)
+}
+
+fn canonicalize(document: &mut KdlDocument) {
+    // 並べ替えは安定なので、同じ種類のノードの順序は保たれる
+    document
+        .nodes_mut()
+        .sort_by_key(|node| leading_rank(node.name().value()));
+    for node in document.nodes_mut() {
+        canonicalize_node(node);
+    }
+}
+
+fn canonicalize_node(node: &mut KdlNode) {
+    node.entries_mut().sort_by_key(entry_rank);
+    if let Some(children) = node.children_mut() {
+        canonicalize(children);
+    }
+}
+
+fn leading_rank(name: &str) -> usize {
+    LEADING_CHILDREN
+        .iter()
+        .position(|leading| *leading == name)
+        .unwrap_or(LEADING_CHILDREN.len())
+}
+
+/// 引数（順序を保持）→定義済みのプロパティ→その他のプロパティ（名前順）
+fn entry_rank(entry: &KdlEntry) -> (usize, String) {
+    match entry.name() {
+        None => (0, String::new()),
+        Some(name) => {
+            let name = name.value();
+            match PROPERTY_ORDER.iter().position(|known| *known == name) {
+                Some(position) => (1 + position, String::new()),
+                None => (1 + PROPERTY_ORDER.len(), name.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_orders_entries_and_indents() {
+        let input = r#"protocol "chat" version="1.0.0" {
+service "Chat" {
+  method "send" {
+      description "Send a message"
+      request { field "body" required=#true type="string" }
+  }
+}
+}
+"#;
+        let formatted = format_schema(input).unwrap();
+        assert_eq!(
+            formatted,
+            r#"protocol "chat" version="1.0.0" {
+    service "Chat" {
+        method "send" {
+            description "Send a message"
+            request {
+                field "body" type="string" required=#true
+            }
+        }
+    }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        let input = r#"// チャットのスキーマ
+protocol "chat" version="1.0.0" {
+    service "Chat" {
+        method "history" timeout_ms=500 {
+            response { field "messages" type="json" max=10 description="最新順" min=1 }
+        }
+        description "Chat service"
+    }
+}
+"#;
+        let formatted = format_schema(input).unwrap();
+        assert!(formatted.starts_with("// チャットのスキーマ"));
+        // descriptionは子ノードの先頭へ
+        assert!(formatted.find("description").unwrap() < formatted.find("method").unwrap());
+        assert!(formatted.contains(r#"type="json" min=1 max=10 description="最新順""#));
+
+        assert_eq!(format_schema(&formatted).unwrap(), formatted);
+        assert!(is_formatted(&formatted).unwrap());
+        assert!(!is_formatted(input).unwrap());
+    }
+}
@@ -1,11 +1,15 @@
 use anyhow::Result;
 use thiserror::Error;
 
+pub mod format;
 pub mod schema;
 pub mod types;
+pub mod validate;
 
+pub use format::{format_schema, is_formatted};
 pub use schema::*;
 pub use types::*;
+pub use validate::{SchemaValidator, ValidationError, ValidatorFn};
 
 /// Parser errors for Unison Protocol
 #[derive(Error, Debug)]
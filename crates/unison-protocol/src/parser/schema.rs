@@ -20,6 +20,103 @@ pub struct ParsedSchema {
     pub typedefs: Vec<TypeDef>,
 }
 
+impl ParsedSchema {
+    /// スキーマで定義された全メソッド・ストリームの名前
+    pub fn method_names(&self) -> impl Iterator<Item = &str> {
+        self.protocol
+            .iter()
+            .flat_map(|p| &p.services)
+            .flat_map(|s| {
+                let methods = s.methods.iter().map(|m| m.name.as_str());
+                let streams = s.streams.iter().chain(&s.client_streams);
+                methods.chain(streams.map(|s| s.name.as_str()))
+            })
+    }
+
+    /// スキーマで定義されたメソッド・ストリームの名前か
+    pub fn has_method(&self, name: &str) -> bool {
+        self.method_names().any(|method| method == name)
+    }
+
+    /// スキーマの構造（サービス・メソッド・フィールドの名前と型）から計算する指紋
+    ///
+    /// 説明文や書式の違いには影響されず、同じ構造であればビルドをまたいで同じ値になります。
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Fingerprint::default();
+        if let Some(protocol) = &self.protocol {
+            hasher.write("protocol", &protocol.name);
+            hasher.write("version", &protocol.version);
+            for service in &protocol.services {
+                hasher.write("service", &service.name);
+                for method in &service.methods {
+                    hasher.write("method", &method.name);
+                    hasher.write_message("request", method.request.as_ref());
+                    hasher.write_message("response", method.response.as_ref());
+                }
+                for stream in &service.streams {
+                    hasher.write("stream", &stream.name);
+                    hasher.write_message("request", stream.request.as_ref());
+                    hasher.write_message("response", stream.response.as_ref());
+                }
+                for stream in &service.client_streams {
+                    hasher.write("client_stream", &stream.name);
+                    hasher.write_message("request", stream.request.as_ref());
+                    hasher.write_message("response", stream.response.as_ref());
+                }
+            }
+            for message in &protocol.messages {
+                hasher.write_fields(&message.name, &message.fields);
+            }
+        }
+        for message in &self.messages {
+            hasher.write_fields(&message.name, &message.fields);
+        }
+        format!("{:016x}", hasher.0)
+    }
+}
+
+/// FNV-1a（64ビット）による指紋の計算
+struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fingerprint {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// 種類と値を区切り付きで書き込む（連結による衝突を避けるため）
+    fn write(&mut self, kind: &str, value: &str) {
+        self.write_bytes(kind.as_bytes());
+        self.write_bytes(&[0]);
+        self.write_bytes(value.as_bytes());
+        self.write_bytes(&[0]);
+    }
+
+    fn write_message(&mut self, kind: &str, message: Option<&MethodMessage>) {
+        match message {
+            Some(message) => self.write_fields(kind, &message.fields),
+            None => self.write(kind, ""),
+        }
+    }
+
+    fn write_fields(&mut self, name: &str, fields: &[Field]) {
+        self.write("message", name);
+        for field in fields {
+            self.write("field", &field.name);
+            self.write("type", &field.field_type_str);
+            self.write("required", if field.required { "1" } else { "0" });
+        }
+    }
+}
+
 /// Import definition
 #[derive(Debug, Clone, knuffel::Decode)]
 pub struct Import {
@@ -66,6 +163,10 @@ pub struct Service {
 
     #[knuffel(children(name = "stream"))]
     pub streams: Vec<Stream>,
+
+    /// クライアントストリーム（`request`の列を受け取り`response`を1つ返す）
+    #[knuffel(children(name = "client_stream"))]
+    pub client_streams: Vec<Stream>,
 }
 
 /// RPC Method definition
@@ -77,6 +178,22 @@ pub struct Method {
     #[knuffel(child, unwrap(argument))]
     pub description: Option<String>,
 
+    /// ハンドラーの実行期限（ミリ秒）
+    #[knuffel(property)]
+    pub timeout_ms: Option<u64>,
+
+    /// 何度実行しても結果が変わらないメソッド（接続断・タイムアウト後も自動で再試行できる）
+    #[knuffel(property, default = false)]
+    pub idempotent: bool,
+
+    /// 一覧をページに分けて返すメソッド（カーソルはメタデータで受け渡す）
+    #[knuffel(property, default = false)]
+    pub paged: bool,
+
+    /// 受け付けるペイロードのエンコーディング（優先順、空の場合は`json`のみ）
+    #[knuffel(child, unwrap(arguments), default)]
+    pub encodings: Vec<String>,
+
     #[knuffel(child)]
     pub request: Option<MethodMessage>,
 
@@ -84,6 +201,13 @@ pub struct Method {
     pub response: Option<MethodMessage>,
 }
 
+impl Method {
+    /// スキーマで指定されたハンドラーの実行期限
+    pub fn timeout(&self) -> Option<std::time::Duration> {
+        self.timeout_ms.map(std::time::Duration::from_millis)
+    }
+}
+
 /// Method request/response definition (without name argument)
 #[derive(Debug, Clone, knuffel::Decode)]
 pub struct MethodMessage {
@@ -91,7 +215,7 @@ pub struct MethodMessage {
     pub fields: Vec<Field>,
 }
 
-/// Streaming endpoint definition
+/// Streaming endpoint definition (`stream` / `client_stream`)
 #[derive(Debug, Clone, knuffel::Decode)]
 pub struct Stream {
     #[knuffel(argument)]
@@ -147,6 +271,10 @@ pub struct Field {
     #[knuffel(property)]
     pub pattern: Option<String>,
 
+    /// [`SchemaValidator`](super::SchemaValidator)に登録した独自の検証関数の名前
+    #[knuffel(property)]
+    pub validator: Option<String>,
+
     #[knuffel(property)]
     pub description: Option<String>,
 }
@@ -154,7 +282,7 @@ pub struct Field {
 impl Field {
     /// フィールド型を取得
     pub fn field_type(&self) -> FieldType {
-        self.parse_field_type(&self.field_type_str)
+        Self::parse_type(&self.field_type_str)
     }
 
     /// デフォルト値を取得
@@ -172,10 +300,12 @@ impl Field {
             min_length: self.min_length,
             max_length: self.max_length,
             pattern: self.pattern.clone(),
+            validator: self.validator.clone(),
         }
     }
 
-    fn parse_field_type(&self, type_str: &str) -> FieldType {
+    /// 型名をフィールド型に変換
+    pub(crate) fn parse_type(type_str: &str) -> FieldType {
         match type_str {
             "string" => FieldType::String,
             "int" => FieldType::Int,
@@ -272,6 +402,7 @@ pub struct Constraints {
     pub min_length: Option<usize>,
     pub max_length: Option<usize>,
     pub pattern: Option<String>,
+    pub validator: Option<String>,
 }
 
 impl FieldType {
@@ -0,0 +1,601 @@
+//! スキーマによるペイロードの検証
+//!
+//! メソッドのリクエスト・レスポンスに定義されたフィールドの型と制約
+//! （`required`・`min`/`max`・`min_length`/`max_length`・`pattern`）をJSON値に適用します。
+//! スキーマで定義されたメッセージ・列挙型・typedefは参照先の定義で検証し、
+//! 未知の型やスキーマにないフィールドは検証しません。
+//!
+//! `validator="iban"`のように名前を指定したフィールドは、
+//! [`SchemaValidator::with_validator`]で登録した関数で検証します。
+//! 登録されていない名前のフィールドは[`ValidationError::UnknownValidator`]で拒否します。
+
+use regex::Regex;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+use super::{Field, FieldType, ParsedSchema};
+
+/// 入れ子のメッセージを検証する最大の深さ
+const MAX_DEPTH: usize = 32;
+
+/// ペイロードの検証エラー
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("Missing required field: {path}")]
+    MissingField { path: String },
+    #[error("Field {path} must be {expected}")]
+    TypeMismatch { path: String, expected: String },
+    #[error("Field {path} violates constraint: {constraint}")]
+    Constraint { path: String, constraint: String },
+    #[error("Invalid pattern {pattern}: {reason}")]
+    InvalidPattern { pattern: String, reason: String },
+    #[error("Unknown validator: {name}")]
+    UnknownValidator { name: String },
+}
+
+impl ValidationError {
+    /// エラーの対象フィールドのパス（`user.tags[0]`形式）
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            ValidationError::MissingField { path }
+            | ValidationError::TypeMismatch { path, .. }
+            | ValidationError::Constraint { path, .. } => Some(path),
+            ValidationError::InvalidPattern { .. } | ValidationError::UnknownValidator { .. } => {
+                None
+            }
+        }
+    }
+
+    /// エラーレスポンスの`details`に格納する値
+    pub fn details(&self) -> Value {
+        json!({ "field": self.path(), "reason": self.to_string() })
+    }
+}
+
+/// 独自の検証関数（違反した場合は理由を返す）
+pub type ValidatorFn = Arc<dyn Fn(&Value) -> Result<(), String> + Send + Sync>;
+
+#[derive(Clone, Default)]
+struct Validators(HashMap<String, ValidatorFn>);
+
+impl fmt::Debug for Validators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// スキーマから構築したペイロードの検証器
+#[derive(Debug, Clone, Default)]
+pub struct SchemaValidator {
+    requests: HashMap<String, Vec<Field>>,
+    responses: HashMap<String, Vec<Field>>,
+    messages: HashMap<String, Vec<Field>>,
+    enums: HashMap<String, Vec<String>>,
+    typedefs: HashMap<String, (String, Option<String>)>,
+    patterns: HashMap<String, Regex>,
+    validators: Validators,
+}
+
+impl SchemaValidator {
+    /// スキーマの定義から検証器を構築
+    ///
+    /// `pattern`が正規表現として不正な場合はエラーを返します。
+    pub fn new(schema: &ParsedSchema) -> Result<Self, ValidationError> {
+        let mut validator = Self::default();
+        let protocol = schema.protocol.as_ref();
+
+        let messages = schema
+            .messages
+            .iter()
+            .chain(protocol.into_iter().flat_map(|p| &p.messages));
+        for message in messages {
+            validator
+                .messages
+                .insert(message.name.clone(), message.fields.clone());
+        }
+        let enums = schema
+            .enums
+            .iter()
+            .chain(protocol.into_iter().flat_map(|p| &p.enums));
+        for definition in enums {
+            validator
+                .enums
+                .insert(definition.name.clone(), definition.values.clone());
+        }
+        for typedef in &schema.typedefs {
+            validator.typedefs.insert(
+                typedef.name.clone(),
+                (typedef.base_type.clone(), typedef.pattern.clone()),
+            );
+        }
+
+        for service in protocol.into_iter().flat_map(|p| &p.services) {
+            let methods = service
+                .methods
+                .iter()
+                .map(|m| (&m.name, &m.request, &m.response));
+            let streams = service
+                .streams
+                .iter()
+                .chain(&service.client_streams)
+                .map(|s| (&s.name, &s.request, &s.response));
+            for (name, request, response) in methods.chain(streams) {
+                if let Some(request) = request {
+                    validator
+                        .requests
+                        .insert(name.clone(), request.fields.clone());
+                }
+                if let Some(response) = response {
+                    validator
+                        .responses
+                        .insert(name.clone(), response.fields.clone());
+                }
+            }
+        }
+
+        let patterns: Vec<String> = validator
+            .requests
+            .values()
+            .chain(validator.responses.values())
+            .chain(validator.messages.values())
+            .flatten()
+            .filter_map(|field| field.pattern.clone())
+            .chain(validator.typedefs.values().filter_map(|(_, p)| p.clone()))
+            .collect();
+        for pattern in patterns {
+            if validator.patterns.contains_key(&pattern) {
+                continue;
+            }
+            // パターンは値全体に一致させる
+            let regex = Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| {
+                ValidationError::InvalidPattern {
+                    pattern: pattern.clone(),
+                    reason: e.to_string(),
+                }
+            })?;
+            validator.patterns.insert(pattern, regex);
+        }
+
+        Ok(validator)
+    }
+
+    /// 名前付きの検証関数を登録
+    ///
+    /// スキーマで`validator="<name>"`を指定したフィールドの値に適用されます。
+    /// 同じ名前で登録済みの場合は置き換えます。
+    pub fn with_validator<F>(mut self, name: impl Into<String>, validator: F) -> Self
+    where
+        F: Fn(&Value) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.register_validator(name, validator);
+        self
+    }
+
+    /// 名前付きの検証関数を登録
+    pub fn register_validator<F>(&mut self, name: impl Into<String>, validator: F)
+    where
+        F: Fn(&Value) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validators.0.insert(name.into(), Arc::new(validator));
+    }
+
+    /// 別の検証器に登録済みの検証関数を引き継ぐ（同じ名前で登録済みのものは残す）
+    pub(crate) fn inherit_validators(&mut self, other: &SchemaValidator) {
+        for (name, validator) in &other.validators.0 {
+            self.validators
+                .0
+                .entry(name.clone())
+                .or_insert_with(|| Arc::clone(validator));
+        }
+    }
+
+    /// スキーマで参照されているが登録されていない検証関数の名前
+    pub fn missing_validators(&self) -> Vec<String> {
+        let referenced: BTreeSet<&String> = self
+            .requests
+            .values()
+            .chain(self.responses.values())
+            .chain(self.messages.values())
+            .flatten()
+            .filter_map(|field| field.validator.as_ref())
+            .collect();
+        referenced
+            .into_iter()
+            .filter(|name| !self.validators.0.contains_key(*name))
+            .cloned()
+            .collect()
+    }
+
+    /// リクエストの定義があるメソッドか
+    pub fn has_request(&self, method: &str) -> bool {
+        self.requests.contains_key(method)
+    }
+
+    /// メソッドのリクエストを検証（定義がなければ常に成功）
+    pub fn validate_request(&self, method: &str, payload: &Value) -> Result<(), ValidationError> {
+        match self.requests.get(method) {
+            Some(fields) => self.validate_fields(fields, payload, "", 0),
+            None => Ok(()),
+        }
+    }
+
+    /// メソッドのレスポンスを検証（定義がなければ常に成功）
+    pub fn validate_response(&self, method: &str, payload: &Value) -> Result<(), ValidationError> {
+        match self.responses.get(method) {
+            Some(fields) => self.validate_fields(fields, payload, "", 0),
+            None => Ok(()),
+        }
+    }
+
+    /// フィールド定義の一覧でオブジェクトを検証
+    pub fn validate_fields(
+        &self,
+        fields: &[Field],
+        value: &Value,
+        path: &str,
+        depth: usize,
+    ) -> Result<(), ValidationError> {
+        let Some(object) = value.as_object() else {
+            return Err(mismatch(path_or_root(path), "an object"));
+        };
+        for field in fields {
+            let path = join(path, &field.name);
+            match object.get(&field.name) {
+                None | Some(Value::Null) => {
+                    if field.required && field.default_str.is_none() {
+                        return Err(ValidationError::MissingField { path });
+                    }
+                }
+                Some(value) => self.validate_field(field, value, &path, depth)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_field(
+        &self,
+        field: &Field,
+        value: &Value,
+        path: &str,
+        depth: usize,
+    ) -> Result<(), ValidationError> {
+        self.validate_type(&field.field_type(), value, path, depth)?;
+
+        let constraints = field.constraints();
+        if let Some(number) = value.as_f64() {
+            if let Some(min) = constraints.min.filter(|min| number < *min as f64) {
+                return Err(constraint(path, format!("must be >= {}", min)));
+            }
+            if let Some(max) = constraints.max.filter(|max| number > *max as f64) {
+                return Err(constraint(path, format!("must be <= {}", max)));
+            }
+        }
+        let length = match value {
+            Value::String(s) => Some(s.chars().count()),
+            Value::Array(items) => Some(items.len()),
+            _ => None,
+        };
+        if let Some(length) = length {
+            if let Some(min) = constraints.min_length.filter(|min| length < *min) {
+                return Err(constraint(path, format!("length must be >= {}", min)));
+            }
+            if let Some(max) = constraints.max_length.filter(|max| length > *max) {
+                return Err(constraint(path, format!("length must be <= {}", max)));
+            }
+        }
+        if let (Some(pattern), Some(s)) = (&constraints.pattern, value.as_str()) {
+            self.check_pattern(pattern, s, path)?;
+        }
+        if let Some(name) = &constraints.validator {
+            let Some(validator) = self.validators.0.get(name) else {
+                return Err(ValidationError::UnknownValidator { name: name.clone() });
+            };
+            validator(value).map_err(|reason| constraint(path, format!("{}: {}", name, reason)))?;
+        }
+        Ok(())
+    }
+
+    fn validate_type(
+        &self,
+        field_type: &FieldType,
+        value: &Value,
+        path: &str,
+        depth: usize,
+    ) -> Result<(), ValidationError> {
+        let ok = match field_type {
+            FieldType::String => value.is_string(),
+            FieldType::Int => value.is_i64() || value.is_u64(),
+            FieldType::Float => value.is_number(),
+            FieldType::Bool => value.is_boolean(),
+            FieldType::Json => true,
+            FieldType::Object | FieldType::Map(..) => value.is_object(),
+            FieldType::Array(inner) => {
+                let Some(items) = value.as_array() else {
+                    return Err(mismatch(path, "an array"));
+                };
+                for (index, item) in items.iter().enumerate() {
+                    self.validate_type(inner, item, &format!("{}[{}]", path, index), depth)?;
+                }
+                true
+            }
+            FieldType::Enum(values) => value
+                .as_str()
+                .is_some_and(|v| values.iter().any(|x| x == v)),
+            FieldType::Custom(name) => return self.validate_custom(name, value, path, depth),
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(mismatch(path, expected(field_type)))
+        }
+    }
+
+    fn validate_custom(
+        &self,
+        name: &str,
+        value: &Value,
+        path: &str,
+        depth: usize,
+    ) -> Result<(), ValidationError> {
+        if let Some(fields) = self.messages.get(name) {
+            if depth >= MAX_DEPTH {
+                return Ok(());
+            }
+            return self.validate_fields(fields, value, path, depth + 1);
+        }
+        if let Some(values) = self.enums.get(name) {
+            return match value.as_str() {
+                Some(v) if values.iter().any(|x| x == v) => Ok(()),
+                _ => Err(mismatch(path, format!("one of {}", values.join(", ")))),
+            };
+        }
+        if let Some((base_type, pattern)) = self.typedefs.get(name) {
+            if depth >= MAX_DEPTH {
+                return Ok(());
+            }
+            let base = Field::parse_type(base_type);
+            self.validate_type(&base, value, path, depth + 1)?;
+            if let (Some(pattern), Some(s)) = (pattern, value.as_str()) {
+                self.check_pattern(pattern, s, path)?;
+            }
+            return Ok(());
+        }
+        match name {
+            // スキーマで使われる組み込みの型
+            "number" => self.validate_type(&FieldType::Float, value, path, depth),
+            "array" => self.validate_type(
+                &FieldType::Array(Box::new(FieldType::Json)),
+                value,
+                path,
+                depth,
+            ),
+            "timestamp" if !(value.is_string() || value.is_number()) => {
+                Err(mismatch(path, "a timestamp"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_pattern(&self, pattern: &str, value: &str, path: &str) -> Result<(), ValidationError> {
+        match self.patterns.get(pattern) {
+            Some(regex) if !regex.is_match(value) => {
+                Err(constraint(path, format!("must match {}", pattern)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    }
+}
+
+fn path_or_root(path: &str) -> &str {
+    if path.is_empty() { "$" } else { path }
+}
+
+fn mismatch(path: &str, expected: impl Into<String>) -> ValidationError {
+    ValidationError::TypeMismatch {
+        path: path.to_string(),
+        expected: expected.into(),
+    }
+}
+
+fn constraint(path: &str, constraint: String) -> ValidationError {
+    ValidationError::Constraint {
+        path: path.to_string(),
+        constraint,
+    }
+}
+
+fn expected(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::String => "a string".into(),
+        FieldType::Int => "an integer".into(),
+        FieldType::Float => "a number".into(),
+        FieldType::Bool => "a boolean".into(),
+        FieldType::Object | FieldType::Map(..) => "an object".into(),
+        FieldType::Enum(values) => format!("one of {}", values.join(", ")),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Enum, Message, Method, MethodMessage, Protocol, Service};
+
+    fn field(name: &str, ty: &str) -> Field {
+        Field {
+            name: name.into(),
+            field_type_str: ty.into(),
+            required: true,
+            default_str: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            validator: None,
+            description: None,
+        }
+    }
+
+    fn schema(request: Vec<Field>) -> ParsedSchema {
+        ParsedSchema {
+            protocol: Some(Protocol {
+                name: "test".into(),
+                version: "1.0.0".into(),
+                namespace: None,
+                description: None,
+                services: vec![Service {
+                    name: "Users".into(),
+                    description: None,
+                    methods: vec![Method {
+                        name: "create_user".into(),
+                        description: None,
+                        timeout_ms: None,
+                        idempotent: false,
+                        paged: false,
+                        encodings: Vec::new(),
+                        request: Some(MethodMessage { fields: request }),
+                        response: None,
+                    }],
+                    streams: vec![],
+                    client_streams: vec![],
+                }],
+                messages: vec![Message {
+                    name: "Address".into(),
+                    description: None,
+                    fields: vec![field("city", "string")],
+                }],
+                enums: vec![Enum {
+                    name: "Role".into(),
+                    values: vec!["admin".into(), "member".into()],
+                }],
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_required_fields_and_types() {
+        let mut age = field("age", "int");
+        age.required = false;
+        let validator = SchemaValidator::new(&schema(vec![
+            field("name", "string"),
+            age,
+            field("role", "Role"),
+            field("address", "Address"),
+        ]))
+        .unwrap();
+
+        let valid = json!({ "name": "a", "role": "admin", "address": { "city": "Tokyo" } });
+        assert!(validator.validate_request("create_user", &valid).is_ok());
+        // 定義のないメソッドは検証しない
+        assert!(validator.validate_request("other", &json!(1)).is_ok());
+
+        let missing = json!({ "role": "admin", "address": { "city": "Tokyo" } });
+        assert_eq!(
+            validator.validate_request("create_user", &missing),
+            Err(ValidationError::MissingField {
+                path: "name".into()
+            })
+        );
+
+        let wrong = json!({ "name": "a", "age": "old", "role": "admin", "address": {} });
+        let error = validator
+            .validate_request("create_user", &wrong)
+            .unwrap_err();
+        assert_eq!(error.path(), Some("age"));
+
+        let nested = json!({ "name": "a", "role": "guest", "address": { "city": 1 } });
+        let error = validator
+            .validate_request("create_user", &nested)
+            .unwrap_err();
+        assert_eq!(error.path(), Some("role"));
+
+        let nested = json!({ "name": "a", "role": "admin", "address": { "city": 1 } });
+        let error = validator
+            .validate_request("create_user", &nested)
+            .unwrap_err();
+        assert_eq!(error.path(), Some("address.city"));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let mut name = field("name", "string");
+        name.min_length = Some(2);
+        name.pattern = Some("[a-z]+".into());
+        let mut count = field("count", "int");
+        count.min = Some(1);
+        count.max = Some(10);
+        let validator = SchemaValidator::new(&schema(vec![name, count])).unwrap();
+
+        let check = |payload| validator.validate_request("create_user", &payload);
+        assert!(check(json!({ "name": "abc", "count": 5 })).is_ok());
+        assert!(matches!(
+            check(json!({ "name": "a", "count": 5 })),
+            Err(ValidationError::Constraint { .. })
+        ));
+        // パターンは値全体に一致させる
+        assert!(check(json!({ "name": "abc1", "count": 5 })).is_err());
+        assert!(check(json!({ "name": "abc", "count": 11 })).is_err());
+        assert!(check(json!({ "name": "abc", "count": 0 })).is_err());
+    }
+
+    #[test]
+    fn test_custom_validators() {
+        let mut iban = field("iban", "string");
+        iban.validator = Some("iban".into());
+        let mut code = field("code", "string");
+        code.required = false;
+        code.validator = Some("country".into());
+        let validator = SchemaValidator::new(&schema(vec![iban, code])).unwrap();
+        assert_eq!(validator.missing_validators(), vec!["country", "iban"]);
+
+        // 登録されていない検証関数は拒否する
+        let payload = json!({ "iban": "DE89370400440532013000" });
+        assert_eq!(
+            validator.validate_request("create_user", &payload),
+            Err(ValidationError::UnknownValidator {
+                name: "iban".into()
+            })
+        );
+
+        let validator = validator.with_validator("iban", |value| {
+            if value.as_str().is_some_and(|s| s.starts_with("DE")) {
+                Ok(())
+            } else {
+                Err("unsupported country".into())
+            }
+        });
+        assert_eq!(validator.missing_validators(), vec!["country"]);
+        assert!(validator.validate_request("create_user", &payload).is_ok());
+        let error = validator
+            .validate_request(
+                "create_user",
+                &json!({ "iban": "FR7630006000011234567890189" }),
+            )
+            .unwrap_err();
+        assert_eq!(error.path(), Some("iban"));
+        assert!(error.to_string().contains("iban: unsupported country"));
+    }
+
+    #[test]
+    fn test_invalid_pattern_rejected() {
+        let mut name = field("name", "string");
+        name.pattern = Some("[unclosed".into());
+        assert!(matches!(
+            SchemaValidator::new(&schema(vec![name])),
+            Err(ValidationError::InvalidPattern { .. })
+        ));
+    }
+}
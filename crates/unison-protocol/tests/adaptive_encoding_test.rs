@@ -0,0 +1,61 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{
+    AdaptiveEncodingConfig, EncodingProfile, MemoryTransport, NetworkError, ProtocolClient,
+    ProtocolServer, UnisonClient, UnisonServer,
+};
+
+async fn build_server() -> ProtocolServer {
+    let server = ProtocolServer::new();
+    server
+        .register_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        )
+        .await;
+    server
+}
+
+/// 往復時間が常に「遅い」と判定される設定では、最も強い圧縮まで段階的に切り替わる
+#[tokio::test]
+async fn test_slow_link_switches_to_stronger_compression() -> Result<()> {
+    let addr = "[::1]:18476";
+    let mut server = build_server().await;
+    tokio::spawn(async move { server.listen(addr).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let config = AdaptiveEncodingConfig {
+        interval: Duration::from_millis(50),
+        degrade_rtt: Duration::ZERO,
+        recover_rtt: Duration::ZERO,
+        switch_after: 1,
+    };
+    let mut client = ProtocolClient::new_default()?.with_adaptive_encoding(config);
+    assert_eq!(client.encoding(), Some(EncodingProfile::Standard));
+    UnisonClient::connect(&mut client, addr).await?;
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while client.encoding() != Some(EncodingProfile::Minimal) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await?;
+
+    // 切り替え後も大きなペイロードを圧縮して送受信できる
+    let text = "unison ".repeat(2048);
+    let echoed = UnisonClient::call(&client, "echo", json!({ "text": text })).await?;
+    assert_eq!(echoed["text"], text);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encoding_is_not_reported_for_memory_transport() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server().await)?;
+    let client = transport.connect().await?;
+    assert_eq!(client.encoding(), None);
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    AdmissionConfig, ConcurrencyLimit, NetworkError, ProtocolClient, ProtocolError, ProtocolServer,
+    STATS_METHOD, ServiceStats, UnisonClient, UnisonServer,
+};
+
+/// 上限と待ち行列を超えた呼び出しは`UNAVAILABLE`で拒否され、処理中の数は統計で確認できる
+#[tokio::test]
+async fn test_burst_beyond_queue_is_shed() -> Result<()> {
+    let addr = "[::1]:18508";
+    let mut server = ProtocolServer::new()
+        .with_admission_config(
+            AdmissionConfig::default()
+                .with_method_limit("slow", ConcurrencyLimit::new(1).with_queue(1)),
+        )
+        .with_call_handler("slow", |_| async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            Ok::<_, ProtocolError>(json!("done"))
+        });
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let calls: Vec<_> = (0..3)
+        .map(|_| UnisonClient::call(&client, "slow", Value::Null))
+        .collect();
+    let stats = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // 組み込みメソッドは上限の影響を受けない
+        let stats: ServiceStats =
+            serde_json::from_value(UnisonClient::call(&client, STATS_METHOD, Value::Null).await?)?;
+        Ok::<_, anyhow::Error>(stats)
+    };
+    let (results, stats) = tokio::join!(futures_util::future::join_all(calls), stats);
+
+    let admission = stats?.admission.unwrap();
+    assert_eq!(admission.methods["slow"].in_flight, 1);
+    assert_eq!(admission.methods["slow"].queued, 1);
+    assert_eq!(admission.methods["slow"].max_in_flight, Some(1));
+
+    let mut rejected = 0;
+    for result in results {
+        match result {
+            Ok(response) => assert_eq!(response, "done"),
+            Err(NetworkError::Remote(error)) => {
+                assert_eq!(error.code, ProtocolError::UNAVAILABLE.to_string());
+                assert_eq!(error.details.unwrap()["reason"], "queue_full");
+                rejected += 1;
+            }
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+    assert_eq!(rejected, 1);
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::allocation::{ALLOC_STATS_METHOD, AllocStats, Subsystem, TrackingAllocator};
+use unison::network::{NetworkError, ProtocolClient, ProtocolServer, UnisonClient, UnisonServer};
+
+#[global_allocator]
+static GLOBAL: TrackingAllocator = TrackingAllocator::system();
+
+/// 登録したアロケーターの集計をサブシステムごとに取得できる
+#[tokio::test]
+async fn test_alloc_stats_over_quic() -> Result<()> {
+    let addr = "[::1]:18489";
+    let mut server = ProtocolServer::new().with_call_handler("fill", |payload: Value| async move {
+        let size = payload["size"].as_u64().unwrap_or(0) as usize;
+        let buffer = std::hint::black_box(vec![1u8; size]);
+        Ok::<_, NetworkError>(json!({ "len": buffer.len() }))
+    });
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let baseline: AllocStats = serde_json::from_value(
+        UnisonClient::call(&client, ALLOC_STATS_METHOD, Value::Null).await?,
+    )?;
+    assert!(baseline.enabled);
+
+    for _ in 0..4 {
+        UnisonClient::call(&client, "fill", json!({ "size": 64 * 1024 })).await?;
+    }
+    let stats: AllocStats = serde_json::from_value(
+        UnisonClient::call(&client, ALLOC_STATS_METHOD, Value::Null).await?,
+    )?;
+
+    let handlers = stats.subsystem(Subsystem::Handlers);
+    let before = baseline.subsystem(Subsystem::Handlers);
+    assert!(handlers.allocated_bytes - before.allocated_bytes >= 4 * 64 * 1024);
+    assert!(stats.subsystem(Subsystem::Packet).allocations > 0);
+    assert!(stats.subsystem(Subsystem::Quic).allocations > 0);
+    assert!(stats.peak_bytes >= stats.live_bytes);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use rust_embed::RustEmbed;
+use std::time::Duration;
+use unison::network::{
+    AssetClient, AssetService, DirectoryAssets, EmbeddedAssets, ProtocolClient, ProtocolServer,
+    UnisonClient, UnisonServer,
+};
+
+#[derive(RustEmbed)]
+#[folder = "assets/certs"]
+#[include = "*.pem"]
+struct Certs;
+
+/// 埋め込みアセットとディレクトリのアセットをチャンクに分けて取得し、ETagで再取得を省く
+#[tokio::test]
+async fn test_fetch_assets_over_quic() -> Result<()> {
+    let addr = "[::1]:18498";
+    let dir = tempfile::tempdir()?;
+    let schema = "service Ping {}\n".repeat(200);
+    std::fs::create_dir(dir.path().join("v1"))?;
+    std::fs::write(dir.path().join("v1").join("ping.kdl"), &schema)?;
+
+    let mut server = ProtocolServer::new()
+        .with_assets(AssetService::new(EmbeddedAssets::<Certs>::new()).with_chunk_size(256))
+        .with_assets(
+            AssetService::new(DirectoryAssets::new(dir.path()))
+                .with_prefix("schemas")
+                .with_chunk_size(1000),
+        );
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let assets = AssetClient::new(&client);
+    let mut paths = assets.list().await?;
+    paths.sort();
+    assert_eq!(paths, vec!["cert.pem", "private_key.pem"]);
+
+    let cert = assets.fetch("/cert.pem", None).await?.unwrap();
+    assert_eq!(cert.data.as_ref(), Certs::get("cert.pem").unwrap().data.as_ref());
+    assert_eq!(assets.stat("cert.pem").await?, cert.info);
+    assert!(assets.fetch("cert.pem", Some(&cert.info.etag)).await?.is_none());
+    assert!(assets.fetch("../Cargo.toml", None).await.is_err());
+    assert!(assets.stat("missing.pem").await.is_err());
+
+    let schemas = AssetClient::new(&client).with_prefix("schemas");
+    let ping = schemas.fetch("v1/ping.kdl", None).await?.unwrap();
+    assert_eq!(ping.data.as_ref(), schema.as_bytes());
+    assert_eq!(ping.info.content_type, "text/x-kdl; charset=utf-8");
+    // 内容が変わるとETagも変わる
+    std::fs::write(dir.path().join("v1").join("ping.kdl"), "service Ping2 {}\n")?;
+    let updated = schemas.fetch("v1/ping.kdl", Some(&ping.info.etag)).await?;
+    assert_eq!(updated.unwrap().data.as_ref(), b"service Ping2 {}\n");
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{
+    FlowControlConfig, MemoryTransport, NetworkError, ProtocolClient, ProtocolServer, SystemStream,
+    UnisonClient, UnisonClientExt, UnisonServer,
+};
+
+fn build_server() -> ProtocolServer {
+    ProtocolServer::new()
+        // クライアントが閉じるまで、受け取った値に接頭辞を付けて返す
+        .with_system_stream_handler("echo", |payload, mut stream| {
+            Box::pin(async move {
+                let prefix = payload["prefix"].as_str().unwrap_or_default().to_string();
+                while let Ok(value) = stream.receive().await {
+                    stream.send(json!(format!("{prefix}{value}"))).await?;
+                }
+                Ok(())
+            })
+        })
+        // 1つ送って終わる（クライアントには終わりが届く）
+        .with_system_stream_handler("once", |_, mut stream| {
+            Box::pin(async move { stream.send(json!("only")).await })
+        })
+        // しばらく読まずに放置してから読み始める（遅い受信側）
+        .with_system_stream_handler("slow", |_, mut stream| {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                while stream.receive().await.is_ok() {}
+                Ok(())
+            })
+        })
+        .with_system_stream_handler("fail", |_, _| {
+            Box::pin(async move { Err(NetworkError::Protocol("boom".into())) })
+        })
+}
+
+#[tokio::test]
+async fn test_bidirectional_stream_over_quic() -> Result<()> {
+    let addr = "[::1]:18471";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let mut stream = client
+        .start_system_stream("echo", json!({ "prefix": "> " }))
+        .await?;
+    for n in 0..3 {
+        stream.send(json!(n)).await?;
+        let echoed = tokio::time::timeout(Duration::from_secs(5), stream.receive()).await??;
+        assert_eq!(echoed, json!(format!("> {n}")));
+    }
+    stream.close().await?;
+    assert!(!stream.is_active());
+
+    let mut once = client.start_system_stream("once", json!({})).await?;
+    assert_eq!(once.receive().await?, json!("only"));
+    let ended = once.receive().await.unwrap_err();
+    assert!(ended.to_string().contains("ended by peer"), "{ended}");
+    assert!(!once.is_active());
+
+    let mut failing = client.start_system_stream("fail", json!({})).await?;
+    let error = failing.receive().await.unwrap_err();
+    assert!(error.to_string().contains("boom"), "{error}");
+
+    let mut missing = client.start_system_stream("missing", json!({})).await?;
+    let error = missing.receive().await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("System stream method not found: missing"),
+        "{error}"
+    );
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// 受信側が遅い間は書き込み待ちが上限で止まり、読み始めると送信が再開する
+#[tokio::test]
+async fn test_send_applies_backpressure() -> Result<()> {
+    let addr = "[::1]:18472";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let config = FlowControlConfig {
+        high_watermark: 64 * 1024,
+        low_watermark: 16 * 1024,
+    };
+    let mut stream = client
+        .start_system_stream("slow", json!({}))
+        .await?
+        .with_flow_control(config);
+
+    let chunk = json!("x".repeat(1024));
+    let mut sent = 0;
+    let blocked = loop {
+        match stream.try_send(chunk.clone()) {
+            Ok(()) => sent += 1,
+            Err(e) => break e,
+        }
+        assert!(sent < 100_000, "try_send never reported backpressure");
+        tokio::task::yield_now().await;
+    };
+    assert!(matches!(blocked, NetworkError::WouldBlock), "{blocked}");
+    assert!(stream.buffered_bytes() >= config.high_watermark);
+
+    // サーバーが読み始めると書き込み待ちが下限まで減り、待機していた送信が完了する
+    tokio::time::timeout(Duration::from_secs(10), stream.send(chunk)).await??;
+    assert!(stream.buffered_bytes() < config.high_watermark);
+    stream.close().await?;
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_system_stream_requires_quic() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server())?;
+    let mut client = transport.connect().await?;
+
+    let error = client
+        .start_system_stream("echo", json!({}))
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(error, NetworkError::UnsupportedTransport(_)));
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{CallOptions, NetworkError, ProtocolClient, ProtocolServer, UnisonServer};
+
+/// 呼び出しの期限を過ぎるとクライアントは待機を打ち切り、
+/// 期限内に完了する呼び出しには影響しないことを確認
+#[tokio::test]
+async fn test_call_with_timeout() -> Result<()> {
+    let addr = "[::1]:18453";
+
+    let mut server = ProtocolServer::new();
+    server
+        .register_call_handler("sleep", |payload| async move {
+            let millis = payload["millis"].as_u64().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok::<_, NetworkError>(payload)
+        })
+        .await;
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let options = CallOptions::default().with_timeout(Duration::from_millis(200));
+    let result = client
+        .call_with_options("sleep", json!({ "millis": 2000 }), options.clone())
+        .await;
+    assert!(matches!(result, Err(NetworkError::Timeout)), "{:?}", result);
+
+    let response = client
+        .call_with_options("sleep", json!({ "millis": 10 }), options)
+        .await?;
+    assert_eq!(response["millis"], 10);
+    Ok(())
+}
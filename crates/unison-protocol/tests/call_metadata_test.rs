@@ -0,0 +1,70 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::Duration;
+use unison::network::{
+    CallOptions, MemoryTransport, NetworkError, ProtocolClient, ProtocolServer, UnisonClient,
+    UnisonServer, request_metadata,
+};
+
+fn build_server() -> ProtocolServer {
+    ProtocolServer::new().with_call_handler("whoami", |_payload: Value| async move {
+        let metadata = request_metadata();
+        Ok::<_, NetworkError>(json!({
+            "token": metadata.get("authorization"),
+            "trace": metadata.get("x-trace-id"),
+        }))
+    })
+}
+
+/// 呼び出しごとのメタデータがハンドラーに届く
+async fn assert_metadata(client: &ProtocolClient) -> Result<()> {
+    let metadata = HashMap::from([
+        ("authorization".to_string(), "Bearer abc".to_string()),
+        ("x-trace-id".to_string(), "trace-1".to_string()),
+    ]);
+    let response = client
+        .call_with_metadata("whoami", Value::Null, metadata)
+        .await?;
+    assert_eq!(
+        response,
+        json!({ "token": "Bearer abc", "trace": "trace-1" })
+    );
+
+    let options = CallOptions::default()
+        .with_timeout(Duration::from_secs(5))
+        .with_metadata("x-trace-id", "trace-2");
+    let response = client
+        .call_with_options("whoami", Value::Null, options)
+        .await?;
+    assert_eq!(response["trace"], "trace-2");
+    assert_eq!(response["token"], Value::Null);
+
+    // メタデータなしの呼び出しでは空
+    let response = UnisonClient::call(client, "whoami", Value::Null).await?;
+    assert_eq!(response, json!({ "token": null, "trace": null }));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_call_metadata_over_quic() -> Result<()> {
+    let addr = "[::1]:18487";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    assert_metadata(&client).await?;
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_call_metadata_over_memory() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server())?;
+    let client = transport.connect().await?;
+    assert_metadata(&client).await
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::Duration;
+use unison::network::{
+    ClientPool, NetworkError, PoolConfig, ProtocolServer, ShutdownController, UnisonClient,
+    UnisonServer,
+};
+
+/// 呼び出されたサーバーの番号を返すサーバーを起動する
+fn start_server(addr: &'static str, id: u64) -> ShutdownController {
+    let mut server = ProtocolServer::new()
+        .with_shutdown_grace(Duration::from_millis(100))
+        .with_call_handler("whoami", move |_| async move {
+            Ok::<_, NetworkError>(json!({ "id": id }))
+        });
+    let shutdown = server.shutdown_controller().clone();
+    tokio::spawn(async move { server.listen(addr).await });
+    shutdown
+}
+
+async fn call_counts(pool: &ClientPool, calls: usize) -> Result<HashMap<u64, usize>> {
+    let mut counts = HashMap::new();
+    for _ in 0..calls {
+        let response = pool.call("whoami", Value::Null).await?;
+        *counts.entry(response["id"].as_u64().unwrap()).or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// ラウンドロビンで両方のサーバーに振り分け、停止したサーバーへの接続は切り離される
+#[tokio::test]
+async fn test_pool_balances_and_evicts_dead_connections() -> Result<()> {
+    let (first, second) = ("[::1]:18496", "[::1]:18497");
+    let first_shutdown = start_server(first, 1);
+    let _second_shutdown = start_server(second, 2);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut pool = ClientPool::new(
+        PoolConfig::default()
+            .with_connections_per_endpoint(2)
+            .with_health_check(Duration::from_secs(60), Duration::from_millis(500)),
+    );
+    pool.connect(&format!("{first},{second}")).await?;
+    assert_eq!(pool.status().len(), 4);
+    assert!(pool.is_connected());
+
+    let counts = call_counts(&pool, 8).await?;
+    assert_eq!(counts.get(&1), Some(&4));
+    assert_eq!(counts.get(&2), Some(&4));
+
+    first_shutdown.trigger();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    pool.check_health().await;
+
+    let status = pool.status();
+    for member in &status {
+        assert_eq!(member.connected, member.endpoint == second, "{member:?}");
+    }
+    let counts = call_counts(&pool, 4).await?;
+    assert_eq!(counts.get(&2), Some(&4));
+
+    pool.disconnect().await?;
+    assert!(!pool.is_connected());
+    Ok(())
+}
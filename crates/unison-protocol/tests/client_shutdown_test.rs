@@ -0,0 +1,83 @@
+use anyhow::Result;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use unison::network::{
+    MemoryTransport, NetworkError, ProtocolClient, ProtocolServer, UnisonClient, UnisonServer,
+};
+
+async fn build_server() -> ProtocolServer {
+    let server = ProtocolServer::new();
+    server
+        .register_call_handler("sleep", |payload| async move {
+            let ms = payload["ms"].as_u64().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok::<_, NetworkError>(payload)
+        })
+        .await;
+    server
+}
+
+fn spawn_call(
+    client: &Arc<ProtocolClient>,
+    ms: u64,
+) -> tokio::task::JoinHandle<Result<serde_json::Value, NetworkError>> {
+    let client = Arc::clone(client);
+    tokio::spawn(async move { UnisonClient::call(&*client, "sleep", json!({ "ms": ms })).await })
+}
+
+/// 停止を要求すると待っている呼び出しは完了まで待たれ、新しい呼び出しは拒否される
+async fn assert_drains_pending_calls(client: ProtocolClient) -> Result<()> {
+    let client = Arc::new(client);
+    let pending: Vec<_> = (0..3).map(|_| spawn_call(&client, 300)).collect();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let shutdown = {
+        let client = Arc::clone(&client);
+        tokio::spawn(async move { client.shutdown(Duration::from_secs(5)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let rejected = UnisonClient::call(&*client, "sleep", json!({})).await;
+    assert!(matches!(rejected, Err(NetworkError::ShuttingDown)));
+
+    for call in pending {
+        assert_eq!(call.await??["ms"], 300);
+    }
+    assert_eq!(shutdown.await??, 0);
+    assert!(!client.is_connected().await);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_pending_calls() -> Result<()> {
+    let addr = "[::1]:18473";
+    let mut server = build_server().await;
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    assert_drains_pending_calls(client).await?;
+
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server().await)?;
+    assert_drains_pending_calls(transport.connect().await?).await
+}
+
+/// 期限までに終わらない呼び出しは打ち切られ、その数が返る
+#[tokio::test]
+async fn test_shutdown_timeout_abandons_slow_calls() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server().await)?;
+    let client = Arc::new(transport.connect().await?);
+
+    let slow = spawn_call(&client, 10_000);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let abandoned = client.shutdown(Duration::from_millis(200)).await?;
+    assert_eq!(abandoned, 1);
+    let result = tokio::time::timeout(Duration::from_secs(5), slow).await??;
+    assert!(result.is_err());
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    ClientStreamRequests, MemoryTransport, NetworkError, ProtocolClient, ProtocolClientTrait,
+    ProtocolServer, UnisonClient, UnisonServer, UnisonServerExt,
+};
+
+/// 受け取った数値の合計と件数を返すハンドラー
+async fn sum(mut requests: ClientStreamRequests) -> Result<Value, NetworkError> {
+    let (mut total, mut count) = (0, 0);
+    while let Some(request) = requests.next().await {
+        total += request?["n"].as_i64().unwrap_or(0);
+        count += 1;
+    }
+    Ok(json!({ "total": total, "count": count }))
+}
+
+fn build_server() -> ProtocolServer {
+    let mut server = ProtocolServer::new().with_client_stream_handler("sum", sum);
+    // 最初のリクエストだけを読んで応答する
+    UnisonServerExt::register_client_stream_handler(
+        &mut server,
+        "first",
+        |mut requests| async move {
+            match requests.next().await {
+                Some(request) => request,
+                None => Err(NetworkError::Protocol("empty".into())),
+            }
+        },
+    );
+    server
+}
+
+/// 同じ呼び出しを行い、結果を比較できる形で返す
+async fn run_calls(client: &ProtocolClient) -> Result<Vec<String>> {
+    let mut results = Vec::new();
+
+    let requests = futures_util::stream::iter((1..=100).map(|n| json!({ "n": n })));
+    let summed: Value = client.client_stream("sum", requests).await?;
+    results.push(summed.to_string());
+
+    let empty: Value = client
+        .client_stream("sum", futures_util::stream::empty::<Value>())
+        .await?;
+    results.push(empty.to_string());
+
+    // サーバーは残りを読まずに応答する（終わらないストリームでも待たない）
+    let endless = futures_util::stream::iter(0..).map(|n| json!(n));
+    let first: Value = tokio::time::timeout(
+        Duration::from_secs(5),
+        client.client_stream("first", endless),
+    )
+    .await??;
+    results.push(first.to_string());
+
+    let missing = client
+        .client_stream::<Value, Value, _>("missing", futures_util::stream::iter([json!(1)]))
+        .await
+        .unwrap_err();
+    results.push(missing.to_string());
+
+    Ok(results)
+}
+
+/// QUICとメモリ内のトランスポートで同じ結果になることを確認
+#[tokio::test]
+async fn test_client_streaming_parity() -> Result<()> {
+    let addr = "[::1]:18470";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let quic = run_calls(&client).await?;
+    UnisonClient::disconnect(&mut client).await?;
+
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server())?;
+    let client = transport.connect().await?;
+    let memory = run_calls(&client).await?;
+
+    assert_eq!(quic[0], json!({ "total": 5050, "count": 100 }).to_string());
+    assert_eq!(quic[1], json!({ "total": 0, "count": 0 }).to_string());
+    assert_eq!(quic[2], "0");
+    assert!(quic[3].contains("Client stream method not found: missing"));
+    assert_eq!(quic, memory);
+    Ok(())
+}
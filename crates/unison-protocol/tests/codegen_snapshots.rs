@@ -0,0 +1,63 @@
+//! コード生成のスナップショットテスト
+//!
+//! `spec/schemas/`の全スキーマについてRust・TypeScriptの生成結果を記録し、
+//! ジェネレーターの変更で出力が意図せず変わらないことを確認します。
+//!
+//! 出力を意図して変更した場合は`cargo insta review`で差分を確認して更新してください。
+
+use std::path::Path;
+use unison::codegen::{CodeGenerator, RustGenerator, TypeScriptGenerator};
+use unison::parser::{ParsedSchema, SchemaParser, TypeRegistry};
+
+/// `spec/schemas/`のスキーマを名前順に読み込む
+fn schemas() -> Vec<(String, ParsedSchema)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../spec/schemas");
+    let mut paths: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "kdl"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No schemas found in {}", dir.display());
+
+    let parser = SchemaParser::new();
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let source = std::fs::read_to_string(&path).unwrap();
+            let schema = parser
+                .parse(&source)
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+            (name, schema)
+        })
+        .collect()
+}
+
+fn type_registry(schema: &ParsedSchema) -> TypeRegistry {
+    let mut registry = TypeRegistry::new();
+    registry.update_from_typedefs(&schema.typedefs);
+    registry
+}
+
+#[test]
+fn test_rust_codegen_snapshots() {
+    let generator = RustGenerator::new();
+    for (name, schema) in schemas() {
+        let code = generator
+            .generate(&schema, &type_registry(&schema))
+            .unwrap();
+        insta::assert_snapshot!(format!("rust__{}", name), code);
+    }
+}
+
+#[test]
+fn test_typescript_codegen_snapshots() {
+    let generator = TypeScriptGenerator::new();
+    for (name, schema) in schemas() {
+        let code = generator
+            .generate(&schema, &type_registry(&schema))
+            .unwrap();
+        insta::assert_snapshot!(format!("typescript__{}", name), code);
+    }
+}
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use unison::network::{NetworkError, ProtocolClient, ProtocolServer, UnisonClient, UnisonServer};
+
+/// `Arc`で共有したクライアントから複数のタスクが同時に呼び出しても、
+/// それぞれのリクエストに対応するレスポンスを受け取れることを確認
+#[tokio::test]
+async fn test_concurrent_calls_from_shared_client() -> Result<()> {
+    let addr = "[::1]:18452";
+
+    let mut server = ProtocolServer::new();
+    server
+        .register_call_handler("echo", |payload| async move {
+            // 到着順と完了順が入れ替わるよう、値に応じて待機時間を変える
+            let index = payload["index"].as_u64().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(50 - index)).await;
+            Ok::<_, NetworkError>(payload)
+        })
+        .await;
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let client = Arc::new(client);
+
+    let calls = (0..32u64).map(|index| {
+        let client = Arc::clone(&client);
+        tokio::spawn(async move {
+            let response = UnisonClient::call(&*client, "echo", json!({ "index": index })).await?;
+            Ok::<_, NetworkError>((index, response))
+        })
+    });
+    for call in futures_util::future::join_all(calls).await {
+        let (index, response) = call??;
+        assert_eq!(response["index"], index);
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::config::{ConfigLoader, UnisonConfig};
+use unison::network::{CertificatePin, NetworkError, ProtocolServer, UnisonClient, UnisonServer};
+
+/// 設定ファイルからTLSを含むサーバーとクライアントを作成して通信する
+#[tokio::test]
+async fn test_server_and_client_from_config() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let (cert_path, key_path) = (dir.path().join("cert.pem"), dir.path().join("key.pem"));
+    std::fs::write(&cert_path, cert.cert.pem())?;
+    std::fs::write(&key_path, cert.key_pair.serialize_pem())?;
+    let pin = CertificatePin::public_key(cert.cert.der())?;
+
+    let config_path = dir.path().join("unison.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            r#"
+[server]
+listen = "[::1]:8080"
+handler_timeout = "50ms"
+
+[server.tls]
+certificate = {cert:?}
+private_key = {key:?}
+
+[client]
+endpoint = "[::1]:8080"
+
+[client.tls]
+verification = "pinned"
+pins = ["{pin}"]
+"#,
+            cert = cert_path.display().to_string(),
+            key = key_path.display().to_string(),
+        ),
+    )?;
+
+    // 接続先のポートは環境変数の形式で上書きする
+    let addr = "[::1]:18495";
+    let config = ConfigLoader::new()
+        .with_file(&config_path)
+        .with_env_vars(
+            "UNISON",
+            [
+                ("UNISON_SERVER__LISTEN".to_string(), addr.to_string()),
+                ("UNISON_CLIENT__ENDPOINT".to_string(), addr.to_string()),
+            ],
+        )
+        .load()?;
+
+    let server = ProtocolServer::new()
+        .with_call_handler("ping", |_| async move {
+            Ok::<_, NetworkError>(json!({ "pong": true }))
+        })
+        .with_call_handler("slow", |_| async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok::<_, NetworkError>(Value::Null)
+        });
+    let mut server = config.server.apply(server)?;
+    let listen = config.server.listen.clone();
+    tokio::spawn(async move { server.listen(&listen).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut client = config.client.build()?;
+    UnisonClient::connect(&mut client, config.client.endpoint.as_deref().unwrap()).await?;
+    let response = UnisonClient::call(&client, "ping", Value::Null).await?;
+    assert_eq!(response, json!({ "pong": true }));
+    // 設定した実行期限でハンドラーが打ち切られる
+    assert!(
+        UnisonClient::call(&client, "slow", Value::Null)
+            .await
+            .is_err()
+    );
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// 必須のファイルが存在しない場合と、任意のファイルが存在しない場合
+#[test]
+fn test_missing_config_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.toml");
+    assert!(ConfigLoader::new().with_file(&missing).load().is_err());
+    assert_eq!(
+        ConfigLoader::new()
+            .with_optional_file(&missing)
+            .load()
+            .unwrap(),
+        UnisonConfig::default()
+    );
+}
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolServer, QuicServer, ServerEvent, SystemStream,
+    UnisonClient, UnisonClientExt,
+};
+
+/// クライアント・サーバーの接続とストリームの統計が送受信に応じて増える
+#[tokio::test]
+async fn test_connection_and_stream_stats() -> Result<()> {
+    let addr = "[::1]:18522";
+    let server = ProtocolServer::new()
+        .with_call_handler("echo", |payload: Value| async move {
+            Ok::<_, NetworkError>(payload)
+        })
+        .with_system_stream_handler("echo", |_, mut stream| {
+            Box::pin(async move {
+                while let Ok(value) = stream.receive().await {
+                    stream.send(value).await?;
+                }
+                Ok(())
+            })
+        });
+    let mut events = server.events();
+    let server = Arc::new(server);
+    let mut quic_server = QuicServer::new(Arc::clone(&server));
+    quic_server.bind(addr).await?;
+    tokio::spawn(async move { quic_server.start().await });
+
+    let mut client = ProtocolClient::new_default()?;
+    assert!(client.connection_stats().is_none());
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    for n in 0..3 {
+        UnisonClient::call(&client, "echo", json!({ "n": n })).await?;
+    }
+
+    let mut stream = client.start_system_stream("echo", json!({})).await?;
+    for n in 0..2 {
+        stream.send(json!(n)).await?;
+        assert_eq!(stream.receive().await?, json!(n));
+    }
+    let stream_stats = stream.stats();
+    assert_eq!(stream_stats.messages_sent, 2);
+    assert_eq!(stream_stats.messages_received, 2);
+    assert!(stream_stats.bytes_sent > 0 && stream_stats.bytes_received > 0);
+
+    let stats = client.connection_stats().unwrap();
+    assert!(stats.rtt_ms > 0.0);
+    assert!(stats.bytes_out > 0 && stats.bytes_in > 0);
+    assert!(stats.packets_out > 0 && stats.packets_in > 0);
+    assert!(stats.streams_opened >= 4, "{stats:?}");
+
+    let connection_id = loop {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await?
+            .unwrap();
+        if let ServerEvent::ConnectionOpened { connection_id, .. } = event {
+            break connection_id;
+        }
+    };
+    let server_stats = server.connection_stats(connection_id).unwrap();
+    assert!(server_stats.bytes_in > 0 && server_stats.bytes_out > 0);
+    assert!(server_stats.streams_opened >= 4, "{server_stats:?}");
+
+    stream.close().await?;
+    UnisonClient::disconnect(&mut client).await?;
+    assert!(client.connection_stats().is_none());
+    Ok(())
+}
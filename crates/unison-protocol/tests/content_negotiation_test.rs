@@ -0,0 +1,71 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    CallOptions, NetworkError, PayloadEncoding, ProtocolClient, ProtocolClientTrait, ProtocolError,
+    ProtocolServer, UnisonServer,
+};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Upload {
+    name: String,
+    chunks: Vec<u32>,
+}
+
+/// エンコーディングを指定したメソッドはMessagePackで呼び出せ、指定のないメソッドはJSONのみ受け付ける
+#[tokio::test]
+async fn test_methods_accept_declared_encodings() -> Result<()> {
+    let addr = "[::1]:18509";
+    let mut server = ProtocolServer::new()
+        .with_call_handler("upload", |payload: Value| async move {
+            Ok::<_, ProtocolError>(json!({ "name": payload["name"], "count": payload["chunks"].as_array().map_or(0, Vec::len) }))
+        })
+        .with_call_handler("echo", |payload: Value| async move {
+            Ok::<_, ProtocolError>(payload)
+        });
+    server
+        .set_method_encodings(
+            "upload",
+            vec![PayloadEncoding::Msgpack, PayloadEncoding::Json],
+        )
+        .await;
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    // 生成したクライアントと同じ呼び出しでMessagePackが選ばれ、レスポンスも復元できる
+    let upload = Upload {
+        name: "report".into(),
+        chunks: vec![1, 2, 3],
+    };
+    let response: Value = client
+        .call_negotiated("upload", &upload, &["msgpack", "json"], false)
+        .await?;
+    assert_eq!(response, json!({ "name": "report", "count": 3 }));
+
+    let options = CallOptions::default().with_encoding(PayloadEncoding::Msgpack);
+    let response = client
+        .call_with_options("upload", json!({ "name": "a", "chunks": [] }), options)
+        .await?;
+    assert_eq!(response["count"], 0);
+
+    // エンコーディングを指定していないメソッドはJSON以外を拒否する
+    let options = CallOptions::default().with_encoding(PayloadEncoding::Msgpack);
+    match client
+        .call_with_options("echo", json!({ "x": 1 }), options)
+        .await
+    {
+        Err(NetworkError::Remote(error)) => {
+            assert_eq!(error.code, ProtocolError::UNSUPPORTED_ENCODING.to_string());
+            assert_eq!(error.details.unwrap()["accepted"], json!(["json"]));
+        }
+        other => panic!("expected UNSUPPORTED_ENCODING, got {other:?}"),
+    }
+    let response: Value = client.call("echo", json!({ "x": 1 })).await?;
+    assert_eq!(response, json!({ "x": 1 }));
+    Ok(())
+}
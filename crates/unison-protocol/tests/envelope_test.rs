@@ -0,0 +1,99 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use unison::network::{
+    MemoryTransport, NetworkError, ProtocolClient, ProtocolError, ProtocolServer, Request,
+    Response, UnisonClient, UnisonServer,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Greet {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Greeting {
+    message: String,
+}
+
+fn build_server() -> ProtocolServer {
+    ProtocolServer::new().with_request_handler("greet", |request: Request<Greet>| async move {
+        let locale = request.metadata("locale").unwrap_or("en").to_string();
+        let message = match locale.as_str() {
+            "ja" => format!("こんにちは、{}", request.body.name),
+            _ => format!("Hello, {}", request.body.name),
+        };
+        Ok::<_, ProtocolError>(
+            Response::new(Greeting { message })
+                .with_metadata("x-request-id", request.id.to_string())
+                .with_metadata("x-has-deadline", request.deadline.is_some().to_string()),
+        )
+    })
+}
+
+/// メタデータと期限がハンドラーへ届き、レスポンスのメタデータが呼び出し元へ返る
+async fn assert_round_trip(client: &ProtocolClient) -> Result<()> {
+    let request = Request::new(
+        "greet",
+        Greet {
+            name: "Unison".into(),
+        },
+    )
+    .with_metadata("locale", "ja")
+    .with_timeout(Duration::from_secs(5));
+    let response: Response<Greeting> = client.request(request).await?;
+    assert_eq!(response.body.message, "こんにちは、Unison");
+    assert_eq!(
+        response.metadata("x-request-id"),
+        Some(response.id.to_string().as_str())
+    );
+    assert_ne!(response.id, 0);
+
+    let invalid = client
+        .request::<_, Greeting>(Request::new("greet", serde_json::json!({ "nickname": 1 })))
+        .await
+        .unwrap_err();
+    assert!(invalid.to_string().contains("Invalid request"), "{invalid}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_typed_request_over_quic() -> Result<()> {
+    let addr = "[::1]:18479";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    assert_round_trip(&client).await?;
+
+    // QUICでは期限もヘッダーで届く
+    let response: Response<Greeting> = client
+        .request(
+            Request::new("greet", Greet { name: "a".into() }).with_timeout(Duration::from_secs(5)),
+        )
+        .await?;
+    assert_eq!(response.metadata("x-has-deadline"), Some("true"));
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_typed_request_over_memory_transport() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server())?;
+    let client = transport.connect().await?;
+    assert_round_trip(&client).await?;
+
+    let expired = client
+        .request::<_, Greeting>(
+            Request::new("greet", Greet { name: "a".into() })
+                .with_deadline(std::time::SystemTime::now() - Duration::from_secs(1)),
+        )
+        .await;
+    assert!(matches!(expired, Err(NetworkError::Timeout)));
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    CloseReason, ProtocolClient, ProtocolClientTrait, ProtocolServer, ServerEvent, UnisonClient,
+    UnisonServer,
+};
+
+/// 接続の開始からハンドシェイク・ストリーム・切断までを順にイベントとして受け取る
+#[tokio::test]
+async fn test_connection_lifecycle_events() -> Result<()> {
+    let addr = "[::1]:18515";
+    let mut server = ProtocolServer::new();
+    server
+        .register_stream_handler("count", |payload| async move {
+            let n = payload["n"].as_u64().unwrap_or(0);
+            Ok(futures_util::stream::iter((0..n).map(|i| Ok(json!(i)))))
+        })
+        .await;
+    let mut events = server.events();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let items: Vec<Result<Value>> =
+        ProtocolClientTrait::stream::<Value, Value>(&client, "count", json!({ "n": 2 }))
+            .await?
+            .collect()
+            .await;
+    assert_eq!(items.len(), 2);
+    // ストリームはクライアントが受信し終えた後にサーバー側で閉じられる
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    UnisonClient::disconnect(&mut client).await?;
+
+    let mut received = Vec::new();
+    while let Ok(Some(event)) = tokio::time::timeout(Duration::from_secs(5), events.next()).await {
+        let closed = matches!(event, ServerEvent::ConnectionClosed { .. });
+        received.push(event);
+        if closed {
+            break;
+        }
+    }
+
+    let connection = match &received[0] {
+        ServerEvent::ConnectionOpened {
+            connection_id,
+            remote,
+            ..
+        } => {
+            assert!(remote.is_some());
+            *connection_id
+        }
+        other => panic!("unexpected event: {other:?}"),
+    };
+    assert!(matches!(
+        &received[1],
+        ServerEvent::HandshakeCompleted { connection_id, .. } if *connection_id == connection
+    ));
+    let opened = received.iter().find_map(|event| match event {
+        ServerEvent::StreamOpened {
+            stream_id, method, ..
+        } if method == "count" => Some(*stream_id),
+        _ => None,
+    });
+    assert!(received.iter().any(|event| matches!(
+        event,
+        ServerEvent::StreamClosed { stream_id, .. } if Some(*stream_id) == opened
+    )));
+    assert!(matches!(
+        received.last(),
+        Some(ServerEvent::ConnectionClosed {
+            connection_id,
+            reason: CloseReason::ClientClosed,
+            ..
+        }) if *connection_id == connection
+    ));
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use unison::network::{
+    CallOptions, ExactlyOnceConfig, IDEMPOTENCY_CREDENTIAL_METADATA_KEY,
+    IDEMPOTENCY_KEY_METADATA_KEY, NetworkError, ProtocolClient, ProtocolServer, UnisonClient,
+    UnisonServer, request_metadata,
+};
+
+/// 実行回数を数え、`delay_ms`だけ待ってから実行回数を返すサーバー
+fn charge_server(runs: Arc<AtomicUsize>) -> ProtocolServer {
+    ProtocolServer::new()
+        .with_exactly_once(ExactlyOnceConfig::new().with_method("charge"))
+        .with_call_handler("charge", move |payload: Value| {
+            let runs = Arc::clone(&runs);
+            async move {
+                let delay = payload["delay_ms"].as_u64().unwrap_or(0);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+                let key = request_metadata().remove(IDEMPOTENCY_KEY_METADATA_KEY);
+                Ok::<_, NetworkError>(json!({ "run": run, "key": key }))
+            }
+        })
+}
+
+/// 同じ冪等キーの重複配信はハンドラーを実行せずに最初の結果を返す
+#[tokio::test]
+async fn test_duplicate_delivery_runs_handler_once() -> Result<()> {
+    let addr = "[::1]:18516";
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut server = charge_server(Arc::clone(&runs));
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let options = CallOptions::default().with_metadata(IDEMPOTENCY_KEY_METADATA_KEY, "order-1");
+    let (first, second) = tokio::join!(
+        client.call_with_options("charge", json!({ "delay_ms": 50 }), options.clone()),
+        client.call_with_options("charge", json!({ "delay_ms": 50 }), options.clone()),
+    );
+    let third = client
+        .call_with_options("charge", json!({ "delay_ms": 50 }), options.clone())
+        .await?;
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(first?, third);
+    assert_eq!(second?, third);
+    assert_eq!(third["key"], "order-1");
+
+    // 同じキーで異なるペイロードは拒否される
+    let reused = client
+        .call_with_options("charge", json!({ "delay_ms": 0 }), options)
+        .await;
+    assert!(matches!(reused, Err(NetworkError::Remote(e)) if e.code == "400"));
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// 資格情報を付けたクライアント
+fn credentialed_client(credential: &str) -> Result<ProtocolClient> {
+    Ok(ProtocolClient::new_default()?
+        .with_handshake_metadata(IDEMPOTENCY_CREDENTIAL_METADATA_KEY, credential))
+}
+
+/// 応答を待たずに切断した呼び出しを、再接続後に同じキーで送っても実行は1回
+#[tokio::test]
+async fn test_retry_after_reconnect_returns_recorded_result() -> Result<()> {
+    let addr = "[::1]:18517";
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut server = charge_server(Arc::clone(&runs));
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let options = CallOptions::default().with_metadata(IDEMPOTENCY_KEY_METADATA_KEY, "order-2");
+    let mut client = credentialed_client("client-secret")?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let timed_out = client
+        .call_with_options(
+            "charge",
+            json!({ "delay_ms": 200 }),
+            options.clone().with_timeout(Duration::from_millis(50)),
+        )
+        .await;
+    assert!(matches!(timed_out, Err(NetworkError::Timeout)));
+    UnisonClient::disconnect(&mut client).await?;
+
+    // 最初の送信の処理中でも完了後でも、再送は同じ結果を受け取る
+    let mut client = credentialed_client("client-secret")?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let retried = client
+        .call_with_options("charge", json!({ "delay_ms": 200 }), options.clone())
+        .await?;
+    assert_eq!(retried["run"], 1);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    UnisonClient::disconnect(&mut client).await?;
+
+    // 同じアドレス・アプリケーション名でも、資格情報が異なるクライアントとは共有しない
+    for mut other in [
+        credentialed_client("other-secret")?,
+        ProtocolClient::new_default()?,
+    ] {
+        other.wait_until_ready(addr, Duration::from_secs(5)).await?;
+        let result = other
+            .call_with_options("charge", json!({ "delay_ms": 200 }), options.clone())
+            .await?;
+        assert_ne!(result["run"], 1);
+        UnisonClient::disconnect(&mut other).await?;
+    }
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+/// 指定したメソッドはクライアントが呼び出しごとに冪等キーを割り当てる
+#[tokio::test]
+async fn test_client_assigns_key_per_call() -> Result<()> {
+    let addr = "[::1]:18518";
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut server = charge_server(Arc::clone(&runs));
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?
+        .with_exactly_once(ExactlyOnceConfig::new().with_method("charge"));
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let first = UnisonClient::call(&client, "charge", json!({})).await?;
+    let second = UnisonClient::call(&client, "charge", json!({})).await?;
+    assert!(first["key"].is_string());
+    assert_ne!(first["key"], second["key"]);
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
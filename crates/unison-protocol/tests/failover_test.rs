@@ -0,0 +1,82 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use unison::network::{
+    FailoverConfig, FnDiscovery, ProtocolClient, ProtocolError, ProtocolServer, RoundRobinBalance,
+    UnisonClient, UnisonServer,
+};
+
+/// 呼び出されたサーバーの番号を返すサーバーを起動する（`available`が偽なら`UNAVAILABLE`で拒否）
+fn start_server(addr: &'static str, id: u64, available: bool) {
+    let mut server = ProtocolServer::new().with_call_handler("whoami", move |_| async move {
+        if available {
+            Ok(json!({ "id": id }))
+        } else {
+            Err(ProtocolError::new(
+                ProtocolError::UNAVAILABLE,
+                "Under maintenance",
+            ))
+        }
+    });
+    tokio::spawn(async move { server.listen(addr).await });
+}
+
+async fn whoami(client: &ProtocolClient) -> Result<u64> {
+    let response = UnisonClient::call(client, "whoami", Value::Null).await?;
+    Ok(response["id"].as_u64().unwrap())
+}
+
+/// `UNAVAILABLE`で拒否された呼び出しは、次の接続先へ切り替えて再送される
+#[tokio::test]
+async fn test_connect_multi_fails_over_on_unavailable() -> Result<()> {
+    let (first, second) = ("[::1]:18499", "[::1]:18500");
+    start_server(first, 1, false);
+    start_server(second, 2, true);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut client = ProtocolClient::new_default()?;
+    client.connect_multi(&[first, second]).await?;
+    assert_eq!(client.current_endpoint().as_deref(), Some(first));
+
+    // 冪等と指定していないメソッドも、サーバーが処理していないため再送される
+    assert_eq!(whoami(&client).await?, 2);
+    assert_eq!(client.current_endpoint().as_deref(), Some(second));
+    assert_eq!(whoami(&client).await?, 2);
+    Ok(())
+}
+
+/// 検出した接続先のリストから現在の接続先が消えると、新しい接続先へ切り替える
+#[tokio::test]
+async fn test_discovery_moves_client_to_new_endpoint() -> Result<()> {
+    let (first, second) = ("[::1]:18501", "[::1]:18502");
+    start_server(first, 1, true);
+    start_server(second, 2, true);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let discovered = Arc::new(Mutex::new(vec![first.to_string()]));
+    let registry = Arc::clone(&discovered);
+    let config = FailoverConfig::default()
+        .with_discovery(FnDiscovery::new(move || {
+            let endpoints = registry.lock().unwrap().clone();
+            async move { Ok(endpoints) }
+        }))
+        .with_refresh_interval(Duration::from_millis(100))
+        .with_balance(RoundRobinBalance::default());
+
+    let mut client = ProtocolClient::new_default()?;
+    client.connect_with_failover(config).await?;
+    assert_eq!(whoami(&client).await?, 1);
+
+    *discovered.lock().unwrap() = vec![second.to_string()];
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while client.current_endpoint().as_deref() != Some(second) {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "client did not switch"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(whoami(&client).await?, 2);
+    Ok(())
+}
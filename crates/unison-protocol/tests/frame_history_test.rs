@@ -0,0 +1,116 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use unison::network::{
+    FRAME_HISTORY_METHOD, FrameDirection, FrameHistoryConfig, MemoryTransport, MessageType,
+    NetworkError, ProtocolClient, ProtocolServer, RecordedFrame, UnisonClient,
+};
+
+/// エラーの前のやり取りを組み込みメソッドで確認できる
+#[tokio::test]
+async fn test_frame_history_dump() -> Result<()> {
+    let server = ProtocolServer::new()
+        .with_frame_history(FrameHistoryConfig {
+            capacity: 4,
+            max_payload_bytes: 16,
+            ..FrameHistoryConfig::default()
+        })
+        .with_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        )
+        .with_call_handler("fail", |_| async move {
+            Err::<Value, _>(NetworkError::Protocol("boom".into()))
+        });
+    let history = server.frame_history().unwrap().clone();
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(server)?;
+    let client = transport.connect().await?;
+
+    UnisonClient::call(&client, "echo", json!({ "text": "a long message body" })).await?;
+    assert!(
+        UnisonClient::call(&client, "fail", Value::Null)
+            .await
+            .is_err()
+    );
+
+    let dump: HashMap<String, Vec<RecordedFrame>> = serde_json::from_value(
+        UnisonClient::call(&client, FRAME_HISTORY_METHOD, Value::Null).await?,
+    )?;
+    assert_eq!(dump.len(), 1);
+    let (connection_id, frames) = dump.into_iter().next().unwrap();
+    // 記録は直近の4件（組み込みメソッドのリクエストを含む）
+    let kinds: Vec<(FrameDirection, &str, MessageType)> = frames
+        .iter()
+        .map(|frame| (frame.direction, frame.method.as_str(), frame.msg_type))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (FrameDirection::Outbound, "echo", MessageType::Response),
+            (FrameDirection::Inbound, "fail", MessageType::Request),
+            (FrameDirection::Outbound, "fail", MessageType::Error),
+            (
+                FrameDirection::Inbound,
+                FRAME_HISTORY_METHOD,
+                MessageType::Request
+            ),
+        ]
+    );
+    assert!(frames[0].truncated);
+    assert_eq!(frames[0].payload.len(), 16);
+
+    // 他の接続の記録は取得できない
+    let error = UnisonClient::call(
+        &client,
+        FRAME_HISTORY_METHOD,
+        json!({ "connection_id": connection_id.parse::<u64>()? + 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(error, NetworkError::Remote(e) if e.code == "403"));
+    assert_eq!(history.frames(connection_id.parse()?).len(), 4);
+    Ok(())
+}
+
+async fn dump(client: &ProtocolClient) -> Result<HashMap<String, Vec<RecordedFrame>>> {
+    Ok(serde_json::from_value(
+        UnisonClient::call(client, FRAME_HISTORY_METHOD, Value::Null).await?,
+    )?)
+}
+
+/// 許可した接続以外には、他の接続の記録（メタデータを含む）を返さない
+#[tokio::test]
+async fn test_frame_history_of_other_connections_requires_authorization() -> Result<()> {
+    let echo = |payload: Value| async move { Ok::<_, NetworkError>(payload) };
+
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(
+        ProtocolServer::new()
+            .with_frame_history(FrameHistoryConfig::default())
+            .with_call_handler("echo", echo),
+    )?;
+    let (first, second) = (transport.connect().await?, transport.connect().await?);
+    UnisonClient::call(&first, "echo", json!({ "secret": 1 })).await?;
+    UnisonClient::call(&second, "echo", json!({})).await?;
+    let frames = dump(&second).await?;
+    assert_eq!(frames.len(), 1);
+    assert!(
+        frames
+            .values()
+            .flatten()
+            .all(|frame| !frame.payload.contains("secret"))
+    );
+
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(
+        ProtocolServer::new()
+            .with_frame_history(FrameHistoryConfig::default())
+            .with_frame_history_authorizer(|_, _| true)
+            .with_call_handler("echo", echo),
+    )?;
+    let (first, second) = (transport.connect().await?, transport.connect().await?);
+    UnisonClient::call(&first, "echo", json!({ "secret": 1 })).await?;
+    assert_eq!(dump(&second).await?.len(), 2);
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{NetworkError, ProtocolClient, ProtocolServer, UnisonClient, UnisonServer};
+
+/// 停止を要求すると処理中の呼び出しは完了まで待たれ、その後`listen`が戻ることを確認
+#[tokio::test]
+async fn test_listen_returns_after_draining_in_flight_calls() -> Result<()> {
+    let addr = "[::1]:18454";
+
+    let mut server = ProtocolServer::new().with_shutdown_grace(Duration::from_secs(5));
+    server
+        .register_call_handler("slow", |payload| async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok::<_, NetworkError>(payload)
+        })
+        .await;
+    let shutdown = server.shutdown_controller().clone();
+    let listening = tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let client = std::sync::Arc::new(client);
+
+    let in_flight = {
+        let client = std::sync::Arc::clone(&client);
+        tokio::spawn(async move { UnisonClient::call(&*client, "slow", json!({ "n": 1 })).await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.trigger();
+
+    // 処理中の呼び出しは完了する
+    let response = in_flight.await??;
+    assert_eq!(response["n"], 1);
+
+    // 処理中のリクエストがなくなれば猶予期間を待たずに戻る
+    tokio::time::timeout(Duration::from_secs(3), listening).await???;
+    assert_eq!(shutdown.in_flight(), 0);
+
+    // 停止後の呼び出しは失敗する
+    assert!(
+        UnisonClient::call(&*client, "slow", json!({}))
+            .await
+            .is_err()
+    );
+    Ok(())
+}
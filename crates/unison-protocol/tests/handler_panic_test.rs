@@ -0,0 +1,91 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolClientTrait, ProtocolError, ProtocolServer, ServerEvent,
+    UnisonClient, UnisonServer,
+};
+
+/// パニックしたハンドラーは内部エラーを返し、接続とサーバーは処理を続ける
+#[tokio::test]
+async fn test_handler_panics_are_reported_and_isolated() -> Result<()> {
+    let addr = "[::1]:18519";
+    let mut server = ProtocolServer::new()
+        .with_call_handler("explode", |_: Value| async move {
+            panic!("call handler bug");
+            #[allow(unreachable_code)]
+            Ok::<_, NetworkError>(Value::Null)
+        })
+        .with_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        );
+    server
+        .register_stream_handler("feed", |_| async move {
+            // 1件送った後にパニックする
+            Ok(futures_util::stream::iter(0..2).map(|i| {
+                if i > 0 {
+                    panic!("stream handler bug");
+                }
+                Ok(json!(i))
+            }))
+        })
+        .await;
+    let mut events = server.events();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let error = UnisonClient::call(&client, "explode", Value::Null)
+        .await
+        .unwrap_err();
+    let correlation_id = match error {
+        NetworkError::Remote(remote) => {
+            assert_eq!(remote.code, ProtocolError::INTERNAL.to_string());
+            assert!(!remote.message.contains("call handler bug"));
+            remote.message
+        }
+        other => panic!("unexpected error: {other}"),
+    };
+
+    let items: Vec<Result<Value>> =
+        ProtocolClientTrait::stream::<Value, Value>(&client, "feed", Value::Null)
+            .await?
+            .collect()
+            .await;
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].as_ref().unwrap(), &json!(0));
+    let stream_error = items[1].as_ref().unwrap_err().to_string();
+    assert!(stream_error.contains("correlation id"), "{stream_error}");
+    assert!(!stream_error.contains("stream handler bug"));
+
+    // パニック後も同じ接続で呼び出せる
+    let echoed = UnisonClient::call(&client, "echo", json!({ "ok": true })).await?;
+    assert_eq!(echoed["ok"], true);
+
+    let mut panicked = Vec::new();
+    while panicked.len() < 2 {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await?
+            .unwrap();
+        if let ServerEvent::HandlerPanicked {
+            method,
+            correlation_id: id,
+            ..
+        } = event
+        {
+            panicked.push((method, id));
+        }
+    }
+    assert_eq!(panicked[0].0, "explode");
+    assert!(correlation_id.contains(&panicked[0].1));
+    assert_eq!(panicked[1].0, "feed");
+    assert!(stream_error.contains(&panicked[1].1));
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{
+    HandshakeError, MemoryTransport, NetworkError, PROTOCOL_VERSION, ProtocolClient,
+    ProtocolServer, UnisonClient, UnisonServer, handshake::feature,
+};
+use unison::parser::{Method, ParsedSchema, Protocol, Service};
+
+async fn spawn_server(addr: &'static str, server: ProtocolServer) {
+    server
+        .register_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        )
+        .await;
+    let mut server = server;
+    tokio::spawn(async move { server.listen(addr).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+}
+
+#[tokio::test]
+async fn test_handshake_exposes_negotiated_capabilities() -> Result<()> {
+    let addr = "[::1]:18477";
+    spawn_server(addr, ProtocolServer::new().with_schema_hash("abc")).await;
+
+    let mut client = ProtocolClient::new_default()?.with_schema_hash("abc");
+    assert_eq!(client.capabilities(), None);
+    UnisonClient::connect(&mut client, addr).await?;
+
+    let capabilities = client.capabilities().expect("handshake completed");
+    assert_eq!(capabilities.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(capabilities.schema_hash.as_deref(), Some("abc"));
+    assert!(capabilities.supports(feature::COMPRESSION));
+    assert!(capabilities.supports(feature::BINARY_FRAMING));
+    assert!(!capabilities.session_id.is_empty());
+
+    let echoed = UnisonClient::call(&client, "echo", json!({ "n": 1 })).await?;
+    assert_eq!(echoed["n"], 1);
+
+    UnisonClient::disconnect(&mut client).await?;
+    assert_eq!(client.capabilities(), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_schema_mismatch_is_rejected() -> Result<()> {
+    let addr = "[::1]:18478";
+    spawn_server(addr, ProtocolServer::new().with_schema_hash("new")).await;
+
+    let mut client = ProtocolClient::new_default()?.with_schema_hash("old");
+    let error = UnisonClient::connect(&mut client, addr).await.unwrap_err();
+    let NetworkError::Handshake(HandshakeError::SchemaMismatch {
+        expected, actual, ..
+    }) = error
+    else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!((expected.as_str(), actual.as_str()), ("old", "new"));
+    assert!(!client.is_connected().await);
+
+    // ハッシュを指定しないクライアントは照合せずに接続できる
+    let mut client = ProtocolClient::new_default()?;
+    UnisonClient::connect(&mut client, addr).await?;
+    let capabilities = client.capabilities().unwrap();
+    assert_eq!(capabilities.schema_hash.as_deref(), Some("new"));
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+fn schema(version: &str, methods: &[&str]) -> ParsedSchema {
+    ParsedSchema {
+        protocol: Some(Protocol {
+            name: "greeter".into(),
+            version: version.into(),
+            namespace: None,
+            description: None,
+            services: vec![Service {
+                name: "Greeter".into(),
+                description: None,
+                methods: methods
+                    .iter()
+                    .map(|name| Method {
+                        name: name.to_string(),
+                        description: None,
+                        timeout_ms: None,
+                        idempotent: false,
+                        paged: false,
+                        encodings: Vec::new(),
+                        request: None,
+                        response: None,
+                    })
+                    .collect(),
+                streams: vec![],
+                client_streams: vec![],
+            }],
+            messages: vec![],
+            enums: vec![],
+        }),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_outdated_schema_reports_missing_methods() -> Result<()> {
+    let addr = "[::1]:18480";
+    let current = schema("2.0.0", &["greet", "status"]);
+    spawn_server(
+        addr,
+        ProtocolServer::new().with_schema_fingerprint(&current),
+    )
+    .await;
+
+    let outdated = schema("1.0.0", &["greet", "legacy_ping"]);
+    let mut client = ProtocolClient::new_default()?.with_schema_fingerprint(&outdated);
+    let error = UnisonClient::connect(&mut client, addr).await.unwrap_err();
+    let NetworkError::Handshake(HandshakeError::SchemaMismatch {
+        expected,
+        actual,
+        missing_methods,
+    }) = error
+    else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!(expected, outdated.fingerprint());
+    assert_eq!(actual, current.fingerprint());
+    assert_eq!(missing_methods, ["legacy_ping"]);
+
+    // 同じスキーマでビルドしたクライアントは接続できる
+    let mut client = ProtocolClient::new_default()?.with_schema_fingerprint(&current);
+    UnisonClient::connect(&mut client, addr).await?;
+    let capabilities = client.capabilities().unwrap();
+    assert_eq!(capabilities.schema_hash, Some(current.fingerprint()));
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_channel_transports_skip_handshake() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(ProtocolServer::new())?;
+    let client = transport.connect().await?;
+    assert_eq!(client.capabilities(), None);
+    Ok(())
+}
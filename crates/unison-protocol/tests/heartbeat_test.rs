@@ -0,0 +1,66 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{
+    ConnectionState, HeartbeatConfig, NetworkError, ProtocolClient, ProtocolServer, UnisonClient,
+    UnisonServer,
+};
+
+/// 両側がハートビートを送っていても、応答し合う接続は閉じられず呼び出しも通ることを確認
+#[tokio::test]
+async fn test_heartbeat_keeps_healthy_connection_open() -> Result<()> {
+    let addr = "[::1]:18474";
+    let config = HeartbeatConfig {
+        interval: Duration::from_millis(100),
+        miss_threshold: 2,
+    };
+
+    let mut server = ProtocolServer::new().with_heartbeat(config);
+    server
+        .register_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        )
+        .await;
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?.with_heartbeat(config);
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    // 何回分もの間隔を空けても接続は保たれる
+    tokio::time::sleep(config.dead_after() * 3).await;
+    assert_eq!(client.state(), ConnectionState::Ready);
+    let echoed = UnisonClient::call(&client, "echo", json!({ "n": 1 })).await?;
+    assert_eq!(echoed["n"], 1);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// サーバーがハートビートを送らない設定でも、pingに応答して往復時間を計測できる
+#[tokio::test]
+async fn test_measure_latency() -> Result<()> {
+    let addr = "[::1]:18475";
+    let mut server = ProtocolServer::new();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let stats = client.measure_latency(10).await?;
+    assert_eq!(stats.samples, 10);
+    assert!(stats.min <= stats.avg && stats.avg <= stats.max);
+    assert!(stats.min <= stats.p95 && stats.p95 <= stats.max);
+    assert!(stats.max < Duration::from_secs(1), "{stats:?}");
+    assert!(matches!(
+        client.measure_latency(0).await,
+        Err(NetworkError::Protocol(_))
+    ));
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::core::UnisonError;
+use unison::network::{
+    ErrorCatalog, LOCALE_METADATA_KEY, MemoryTransport, NetworkError, ProtocolClient,
+    ProtocolServer, Request, Response, UnisonClient, UnisonServer,
+};
+
+fn build_server() -> ProtocolServer {
+    let catalog = ErrorCatalog::new()
+        .with_message("OUT_OF_STOCK", "en", "Item {item} is out of stock")
+        .with_message("OUT_OF_STOCK", "ja", "{item}は在庫切れです")
+        .with_default_locale("en");
+    ProtocolServer::new()
+        .with_error_catalog(catalog)
+        .with_call_handler("order", |payload| async move {
+            Err::<Value, _>(NetworkError::Remote(UnisonError::with_details(
+                "OUT_OF_STOCK",
+                "out of stock",
+                json!({ "item": payload["item"] }),
+            )))
+        })
+}
+
+fn remote(error: NetworkError) -> UnisonError {
+    match error {
+        NetworkError::Remote(error) => error,
+        other => panic!("unexpected error: {other}"),
+    }
+}
+
+/// ハンドシェイクで伝えたロケールでメッセージが返り、コードは変わらない
+#[tokio::test]
+async fn test_localized_errors_over_quic() -> Result<()> {
+    let addr = "[::1]:18486";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?.with_locale("ja-JP");
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let error = remote(
+        UnisonClient::call(&client, "order", json!({ "item": "りんご" }))
+            .await
+            .unwrap_err(),
+    );
+    assert_eq!(error.code, "OUT_OF_STOCK");
+    assert_eq!(error.message, "りんごは在庫切れです");
+    assert_eq!(error.details.unwrap()["item"], "りんご");
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// ハンドシェイクのない接続ではリクエストのメタデータでロケールを指定する
+#[tokio::test]
+async fn test_request_locale_over_memory() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server())?;
+    let client = transport.connect().await?;
+
+    let error = remote(
+        UnisonClient::call(&client, "order", json!({ "item": "apple" }))
+            .await
+            .unwrap_err(),
+    );
+    assert_eq!(error.message, "Item apple is out of stock");
+
+    let request = Request::new("order", json!({ "item": "みかん" }))
+        .with_metadata(LOCALE_METADATA_KEY, "ja");
+    let error = remote(
+        client
+            .request::<_, Value>(request)
+            .await
+            .map(Response::into_body)
+            .unwrap_err(),
+    );
+    assert_eq!(error.message, "みかんは在庫切れです");
+    Ok(())
+}
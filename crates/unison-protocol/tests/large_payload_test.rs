@@ -0,0 +1,49 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+use unison::network::{
+    DecodeConfig, ProtocolClient, ProtocolError, ProtocolServer, UnisonClient, UnisonServer,
+};
+
+/// ブロッキング用のスレッドで復元する大きなリクエストの処理中も、小さなリクエストは待たされない
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_large_payloads_do_not_stall_small_requests() -> Result<()> {
+    let addr = "[::1]:18507";
+    let mut server = ProtocolServer::new()
+        .with_decode_config(DecodeConfig::default().with_blocking_threshold(64 * 1024))
+        .with_call_handler("size", |payload: Value| async move {
+            let size = payload["data"].as_str().map_or(0, str::len);
+            Ok::<_, ProtocolError>(json!({ "size": size }))
+        })
+        .with_call_handler(
+            "ping",
+            |_| async move { Ok::<_, ProtocolError>(json!("pong")) },
+        );
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let data = "unison ".repeat(512 * 1024);
+    let large = {
+        let client = &client;
+        let data = data.clone();
+        async move { UnisonClient::call(client, "size", json!({ "data": data })).await }
+    };
+    let small = async {
+        let started = Instant::now();
+        for _ in 0..5 {
+            assert_eq!(
+                UnisonClient::call(&client, "ping", Value::Null).await?,
+                "pong"
+            );
+        }
+        Ok::<_, anyhow::Error>(started.elapsed())
+    };
+    let (large, small) = tokio::join!(large, small);
+    assert_eq!(large?["size"], data.len());
+    assert!(small? < Duration::from_secs(5));
+    Ok(())
+}
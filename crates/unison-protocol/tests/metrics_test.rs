@@ -0,0 +1,59 @@
+#![cfg(feature = "metrics")]
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use unison::network::{
+    ProtocolClient, ProtocolError, ProtocolServer, QuicServer, STATUS_OK, UnisonClient,
+};
+
+/// サーバーとクライアントの両方で呼び出しが集計され、Prometheusの形式で取得できる
+#[tokio::test]
+async fn test_server_and_client_metrics() -> Result<()> {
+    let addr = "[::1]:18510";
+    let server = ProtocolServer::new()
+        .with_call_handler("echo", |payload: Value| async move {
+            Ok::<_, ProtocolError>(payload)
+        })
+        .with_call_handler("fail", |_| async move {
+            Err::<Value, _>(ProtocolError::new(ProtocolError::NOT_FOUND, "missing"))
+        });
+    let server = Arc::new(server);
+    let mut quic_server = QuicServer::new(Arc::clone(&server));
+    quic_server.bind(addr).await?;
+    tokio::spawn(async move { quic_server.start().await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client.connect(addr).await?;
+    // 圧縮される大きさのペイロード
+    let text = "metrics ".repeat(1024);
+    for _ in 0..3 {
+        client.call("echo", json!({ "text": text })).await?;
+    }
+    assert!(client.call("fail", Value::Null).await.is_err());
+
+    let metrics = client.metrics();
+    assert_eq!(metrics.active_connections, 1);
+    assert_eq!(metrics.requests["echo"][STATUS_OK], 3);
+    assert_eq!(metrics.requests["fail"]["404"], 1);
+    assert_eq!(metrics.latency["echo"].count, 3);
+    assert!(metrics.bytes_received["echo"] > 3 * text.len() as u64);
+
+    let text = client.metrics().to_prometheus();
+    assert!(text.contains("unison_client_requests_total{method=\"echo\",status=\"ok\"} 3\n"));
+    assert!(text.contains("# TYPE unison_client_request_duration_seconds histogram\n"));
+
+    let metrics = server.metrics();
+    assert_eq!(metrics.active_connections, 1);
+    assert_eq!(metrics.requests["echo"][STATUS_OK], 3);
+    assert_eq!(metrics.requests["fail"]["404"], 1);
+    // 大きなリクエストとレスポンスのフレームは圧縮される
+    assert!(metrics.compression.compressed_frames >= 6);
+    assert!(metrics.compression.ratio() < 1.0);
+    assert!(
+        metrics
+            .to_prometheus()
+            .contains("unison_server_active_connections 1\n")
+    );
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use anyhow::Result;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use serde_json::{Value, json};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use unison::network::{
+    CertificatePin, ClientAuth, ClientTlsConfig, NetworkError, ProtocolClient, ProtocolServer,
+    QuicClient, TlsConfig, TlsError, UnisonClient, UnisonServer,
+};
+
+struct Ca {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl Ca {
+    fn new() -> Result<Self> {
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        Ok(Self {
+            cert: params.self_signed(&key)?,
+            key,
+        })
+    }
+
+    fn root(&self) -> CertificateDer<'static> {
+        self.cert.der().clone()
+    }
+
+    fn sign(&self, name: &str, usage: ExtendedKeyUsagePurpose) -> Result<(Certificate, KeyPair)> {
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(vec![name.to_string()])?;
+        params.extended_key_usages = vec![usage];
+        Ok((params.signed_by(&key, &self.cert, &self.key)?, key))
+    }
+
+    /// CAで署名した証明書でTLSの設定を作成
+    fn issue(&self, name: &str, usage: ExtendedKeyUsagePurpose) -> Result<TlsConfig> {
+        let (cert, key) = self.sign(name, usage)?;
+        Ok(TlsConfig::new()
+            .with_identity(vec![cert.der().clone()], private_key(&key)?)?
+            .with_root_certificate(self.root()))
+    }
+
+    /// CAで署名したサーバー証明書と秘密鍵をPEMファイルに書き込む
+    fn write_server_pem(&self, cert_path: &Path, key_path: &Path) -> Result<()> {
+        let (cert, key) = self.sign("localhost", ExtendedKeyUsagePurpose::ServerAuth)?;
+        std::fs::write(cert_path, cert.pem())?;
+        std::fs::write(key_path, key.serialize_pem())?;
+        Ok(())
+    }
+}
+
+fn private_key(key: &KeyPair) -> Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::try_from(key.serialize_der()).map_err(anyhow::Error::msg)
+}
+
+fn client(tls: impl Into<TlsConfig>) -> ProtocolClient {
+    ProtocolClient::new(QuicClient::new().unwrap()).with_tls(tls)
+}
+
+/// `ca`を信頼するクライアントで接続して呼び出す
+async fn connect_trusting(ca: &Ca, addr: &str) -> Result<ProtocolClient> {
+    let mut client = client(TlsConfig::new().with_root_certificate(ca.root()));
+    UnisonClient::connect(&mut client, addr).await?;
+    UnisonClient::call(&client, "ping", Value::Null).await?;
+    Ok(client)
+}
+
+fn ping_server() -> ProtocolServer {
+    ProtocolServer::new().with_call_handler("ping", |_| async move {
+        Ok::<_, NetworkError>(json!({ "pong": true }))
+    })
+}
+
+/// CAで署名したクライアント証明書を持つクライアントのみ接続できる
+#[tokio::test]
+async fn test_mutual_tls() -> Result<()> {
+    let addr = "[::1]:18490";
+    let ca = Ca::new()?;
+    let server_tls = ca
+        .issue("localhost", ExtendedKeyUsagePurpose::ServerAuth)?
+        .with_client_auth(ClientAuth::Required);
+    let mut server = ProtocolServer::new()
+        .with_tls(server_tls)
+        .with_call_handler("ping", |_| async move {
+            Ok::<_, NetworkError>(json!({ "pong": true }))
+        });
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut trusted = client(ca.issue("client-1", ExtendedKeyUsagePurpose::ClientAuth)?);
+    trusted
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let response = UnisonClient::call(&trusted, "ping", Value::Null).await?;
+    assert_eq!(response, json!({ "pong": true }));
+    UnisonClient::disconnect(&mut trusted).await?;
+
+    // クライアント証明書なし
+    let mut anonymous = client(TlsConfig::new().with_root_certificate(ca.root()));
+    let rejected = match UnisonClient::connect(&mut anonymous, addr).await {
+        Err(_) => true,
+        Ok(()) => UnisonClient::call(&anonymous, "ping", Value::Null)
+            .await
+            .is_err(),
+    };
+    assert!(rejected);
+
+    // 別のCAで署名したクライアント証明書
+    let other = Ca::new()?;
+    let untrusted_tls = other
+        .issue("client-2", ExtendedKeyUsagePurpose::ClientAuth)?
+        .with_root_certificate(ca.root());
+    let mut untrusted = client(untrusted_tls);
+    let rejected = match UnisonClient::connect(&mut untrusted, addr).await {
+        Err(_) => true,
+        Ok(()) => UnisonClient::call(&untrusted, "ping", Value::Null)
+            .await
+            .is_err(),
+    };
+    assert!(rejected);
+    Ok(())
+}
+
+/// サーバーの証明書を信頼していないCA・異なるサーバー名では接続できない
+#[tokio::test]
+async fn test_server_certificate_verification() -> Result<()> {
+    let addr = "[::1]:18491";
+    let ca = Ca::new()?;
+    let mut server = ProtocolServer::new()
+        .with_tls(ca.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth)?)
+        .with_call_handler("ping", |_| async move {
+            Ok::<_, NetworkError>(json!({ "pong": true }))
+        });
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut verified = client(TlsConfig::new().with_root_certificate(ca.root()));
+    verified
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    UnisonClient::call(&verified, "ping", Value::Null).await?;
+    UnisonClient::disconnect(&mut verified).await?;
+
+    let other = Ca::new()?;
+    let mut untrusted = client(TlsConfig::new().with_root_certificate(other.root()));
+    assert!(UnisonClient::connect(&mut untrusted, addr).await.is_err());
+
+    let mut wrong_name = client(
+        TlsConfig::new()
+            .with_root_certificate(ca.root())
+            .with_server_name("api.example.com"),
+    );
+    assert!(UnisonClient::connect(&mut wrong_name, addr).await.is_err());
+
+    // 検証しない設定では接続できる
+    let mut insecure = client(TlsConfig::insecure());
+    UnisonClient::connect(&mut insecure, addr).await?;
+    UnisonClient::disconnect(&mut insecure).await?;
+    Ok(())
+}
+
+/// 自己署名の証明書を固定したハッシュで検証する
+#[tokio::test]
+async fn test_pinned_server_certificate() -> Result<()> {
+    let addr = "[::1]:18494";
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let der = cert.cert.der().clone();
+    let server_tls =
+        TlsConfig::new().with_identity(vec![der.clone()], private_key(&cert.key_pair)?)?;
+    let mut server = ping_server().with_tls(server_tls);
+    tokio::spawn(async move { server.listen(addr).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // 証明書・公開鍵のいずれのハッシュでも接続でき、サーバー名は検証しない
+    let pins = [
+        CertificatePin::certificate(&der),
+        CertificatePin::public_key(&der)?.to_string().parse()?,
+    ];
+    for pin in pins {
+        let mut pinned = client(ClientTlsConfig::pinned([pin]).with_server_name("api.example.com"));
+        UnisonClient::connect(&mut pinned, addr).await?;
+        UnisonClient::call(&pinned, "ping", Value::Null).await?;
+        UnisonClient::disconnect(&mut pinned).await?;
+    }
+
+    let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let mut mismatched = client(ClientTlsConfig::pinned([CertificatePin::public_key(
+        other.cert.der(),
+    )?]));
+    assert!(UnisonClient::connect(&mut mismatched, addr).await.is_err());
+
+    // 自己署名の証明書はルート証明書としては信頼されない
+    let mut anchored = client(ClientTlsConfig::trust_anchors([other.cert.der().clone()]));
+    assert!(UnisonClient::connect(&mut anchored, addr).await.is_err());
+    Ok(())
+}
+
+/// 証明書ファイルを書き換えると、既存の接続を維持したまま新しい接続から新しい証明書を使う
+#[tokio::test]
+async fn test_certificate_file_reload() -> Result<()> {
+    let addr = "[::1]:18492";
+    let dir = tempfile::tempdir()?;
+    let (cert_path, key_path) = (dir.path().join("cert.pem"), dir.path().join("key.pem"));
+    let (old_ca, new_ca) = (Ca::new()?, Ca::new()?);
+    old_ca.write_server_pem(&cert_path, &key_path)?;
+
+    let mut server = ping_server()
+        .with_tls(TlsConfig::new().with_identity_files(&cert_path, &key_path)?)
+        .with_certificate_reload(Duration::from_millis(50));
+    tokio::spawn(async move { server.listen(addr).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let existing = connect_trusting(&old_ca, addr).await?;
+
+    new_ca.write_server_pem(&cert_path, &key_path)?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let mut rotated = connect_trusting(&new_ca, addr).await?;
+    assert!(connect_trusting(&old_ca, addr).await.is_err());
+    // 更新前の接続はそのまま使える
+    UnisonClient::call(&existing, "ping", Value::Null).await?;
+    UnisonClient::disconnect(&mut rotated).await?;
+
+    // 壊れたファイルでは以前の証明書を使い続ける
+    std::fs::write(&key_path, b"broken")?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    connect_trusting(&new_ca, addr).await?;
+    Ok(())
+}
+
+/// コールバックで読み込む証明書をハンドルから読み込み直させる
+#[tokio::test]
+async fn test_certificate_reload_on_request() -> Result<()> {
+    let addr = "[::1]:18493";
+    let (old_ca, new_ca) = (Ca::new()?, Ca::new()?);
+    let current = Arc::new(Mutex::new(
+        old_ca.sign("localhost", ExtendedKeyUsagePurpose::ServerAuth)?,
+    ));
+    let source = Arc::clone(&current);
+    let tls = TlsConfig::new().with_identity_loader(move || {
+        let (cert, key) = &*source.lock().unwrap();
+        let key = PrivateKeyDer::try_from(key.serialize_der())
+            .map_err(|e| TlsError::InvalidKey(e.to_string()))?;
+        Ok((vec![cert.der().clone()], key))
+    })?;
+
+    let mut server = ping_server().with_tls(tls);
+    let reloader = server.certificate_reloader().clone();
+    tokio::spawn(async move { server.listen(addr).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    connect_trusting(&old_ca, addr).await?;
+
+    *current.lock().unwrap() = new_ca.sign("localhost", ExtendedKeyUsagePurpose::ServerAuth)?;
+    // 要求するまでは以前の証明書
+    connect_trusting(&old_ca, addr).await?;
+    reloader.reload();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    connect_trusting(&new_ca, addr).await?;
+    assert!(connect_trusting(&old_ca, addr).await.is_err());
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    Page, ProtocolClient, ProtocolClientTrait, ProtocolServer, UnisonClient, UnisonServer,
+    page_cursor,
+};
+
+/// カーソルをたどって全ページを順に受け取る
+#[tokio::test]
+async fn test_call_paged_follows_cursor() -> Result<()> {
+    let addr = "[::1]:18514";
+    let mut server = ProtocolServer::new().with_call_handler("list", |request: Value| async move {
+        let size = request["size"].as_u64().unwrap_or(2);
+        let start: u64 = page_cursor().map_or(0, |cursor| cursor.parse().unwrap());
+        let end = (start + size).min(5);
+        let items: Vec<u64> = (start..end).collect();
+        if end < 5 {
+            Page::new(json!(items), end.to_string())
+        } else {
+            Page::last(json!(items))
+        }
+    });
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let pages: Vec<Vec<u64>> = client
+        .call_paged("list", json!({ "size": 2 }))
+        .map(|page| page.unwrap())
+        .collect()
+        .await;
+    assert_eq!(pages, vec![vec![0, 1], vec![2, 3], vec![4]]);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{
+    MessageType, NetworkError, ProtocolClient, ProtocolFrame, ProtocolMessage, ProtocolServer,
+    QuicClient, UnisonClient, UnisonServer,
+};
+
+fn echo_server() -> ProtocolServer {
+    ProtocolServer::new().with_call_handler("echo", |payload| async move {
+        Ok::<_, NetworkError>(payload)
+    })
+}
+
+/// 数MBのペイロードを長さ接頭辞付きのフレームで往復できることを確認
+#[tokio::test]
+async fn test_large_payload_round_trip() -> Result<()> {
+    let addr = "[::1]:18467";
+    let mut server = echo_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new(QuicClient::new()?);
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let payload = json!({ "data": "x".repeat(4 * 1024 * 1024) });
+    let response = UnisonClient::call(&client, "echo", payload.clone()).await?;
+    assert_eq!(response, payload);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// 長さ接頭辞を付けない以前の形式のリクエストには、同じ形式でレスポンスを返すことを確認
+#[tokio::test]
+async fn test_legacy_request_without_length_prefix() -> Result<()> {
+    let addr = "[::1]:18468";
+    let mut server = echo_server();
+    tokio::spawn(async move { server.listen(addr).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut endpoint = quinn::Endpoint::client("[::]:0".parse()?)?;
+    endpoint.set_default_client_config(QuicClient::configure_client().await?);
+    let connection = endpoint.connect(addr.parse()?, "localhost")?.await?;
+
+    let request =
+        ProtocolMessage::new_with_json(1, "echo".into(), MessageType::Request, json!("old"))?;
+    let (mut send_stream, mut recv_stream) = connection.open_bi().await?;
+    send_stream
+        .write_all(&request.into_frame()?.to_bytes())
+        .await?;
+    send_stream.finish()?;
+
+    let data = recv_stream.read_to_end(1024 * 1024).await?;
+    let frame = ProtocolFrame::from_bytes(&bytes::Bytes::from(data))?;
+    let response = ProtocolMessage::from_frame(&frame)?;
+    assert_eq!(response.msg_type, MessageType::Response);
+    assert_eq!(response.payload_as_value()?, json!("old"));
+
+    connection.close(0u32.into(), b"done");
+    Ok(())
+}
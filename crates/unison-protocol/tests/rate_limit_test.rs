@@ -0,0 +1,101 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolError, ProtocolServer, RateLimit, RateLimitConfig,
+    RetryPolicy, UnisonClient, UnisonServer,
+};
+
+/// 上限を超えた接続だけが拒否され、待機時間の後に再試行すれば受け付けられる
+#[tokio::test]
+async fn test_noisy_connection_is_limited_without_starving_others() -> Result<()> {
+    let addr = "[::1]:18505";
+    let mut server = ProtocolServer::new()
+        .with_rate_limit_config(
+            RateLimitConfig::default().with_connection_limit(RateLimit::new(5.0, 2)),
+        )
+        .with_call_handler("echo", |payload| async move {
+            Ok::<_, ProtocolError>(json!({ "echo": payload }))
+        });
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut noisy = ProtocolClient::new_default()?;
+    noisy.wait_until_ready(addr, Duration::from_secs(5)).await?;
+    let mut quiet = ProtocolClient::new_default()?;
+    quiet.connect(addr).await?;
+
+    for _ in 0..2 {
+        UnisonClient::call(&noisy, "echo", Value::Null).await?;
+    }
+    let error = UnisonClient::call(&noisy, "echo", Value::Null)
+        .await
+        .unwrap_err();
+    let NetworkError::Remote(error) = error else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!(error.code, ProtocolError::RESOURCE_EXHAUSTED.to_string());
+    let retry_after_ms = error.details.unwrap()["retry_after_ms"].as_u64().unwrap();
+    assert!(retry_after_ms > 0 && retry_after_ms <= 200);
+
+    // 他の接続は上限の影響を受けない
+    UnisonClient::call(&quiet, "echo", Value::Null).await?;
+
+    // 再試行のポリシーはサーバーが指定した待機時間の後に再送する
+    let mut retrying = ProtocolClient::new_default()?.with_retry_policy(
+        RetryPolicy::new()
+            .with_initial_backoff(Duration::from_millis(1))
+            .with_jitter(0.0),
+    );
+    retrying.connect(addr).await?;
+    for _ in 0..4 {
+        UnisonClient::call(&retrying, "echo", Value::Null).await?;
+    }
+    Ok(())
+}
+
+/// サーバーからのストリームの要求にもメソッドごとの上限を適用する
+#[tokio::test]
+async fn test_stream_requests_are_rate_limited() -> Result<()> {
+    use futures_util::StreamExt;
+    use unison::network::ProtocolClientTrait;
+
+    let addr = "[::1]:18525";
+    let mut server = ProtocolServer::new().with_rate_limit_config(
+        RateLimitConfig::default().with_method_limit("feed", RateLimit::new(0.0, 1)),
+    );
+    server
+        .register_stream_handler("feed", |_| async move {
+            Ok(futures_util::stream::iter([Ok(json!(1))]))
+        })
+        .await;
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let items: Vec<Result<Value>> =
+        ProtocolClientTrait::stream::<Value, Value>(&client, "feed", Value::Null)
+            .await?
+            .collect()
+            .await;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].as_ref().unwrap(), &json!(1));
+
+    let items: Vec<Result<Value>> =
+        ProtocolClientTrait::stream::<Value, Value>(&client, "feed", Value::Null)
+            .await?
+            .collect()
+            .await;
+    assert_eq!(items.len(), 1);
+    let error = items[0]
+        .as_ref()
+        .unwrap_err()
+        .downcast_ref::<NetworkError>();
+    let Some(NetworkError::Remote(error)) = error else {
+        panic!("unexpected error: {:?}", items[0]);
+    };
+    assert_eq!(error.code, ProtocolError::RESOURCE_EXHAUSTED.to_string());
+    Ok(())
+}
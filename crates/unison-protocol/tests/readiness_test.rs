@@ -0,0 +1,41 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+use unison::network::{NetworkError, ProtocolClient, ProtocolServer, UnisonClient, UnisonServer};
+
+/// サーバーより先に待機を始めても、起動した時点で接続して呼び出せる
+#[tokio::test]
+async fn test_wait_until_ready_before_server_starts() -> Result<()> {
+    let addr = "[::1]:18511";
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let mut server = ProtocolServer::new()
+            .with_call_handler("echo", |payload: Value| async move {
+                Ok::<_, NetworkError>(payload)
+            });
+        server.listen(addr).await
+    });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    assert!(UnisonClient::is_connected(&client));
+    let echoed = UnisonClient::call(&client, "echo", json!({ "n": 1 })).await?;
+    assert_eq!(echoed["n"], 1);
+    Ok(())
+}
+
+/// サーバーが起動しなければ期限で`Timeout`を返す
+#[tokio::test]
+async fn test_wait_until_ready_times_out() -> Result<()> {
+    let mut client = ProtocolClient::new_default()?;
+    let started = Instant::now();
+    let error = client
+        .wait_until_ready("[::1]:18512", Duration::from_millis(300))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, NetworkError::Timeout), "{error}");
+    assert!(started.elapsed() < Duration::from_secs(2));
+    Ok(())
+}
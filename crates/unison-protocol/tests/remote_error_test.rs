@@ -0,0 +1,92 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::core::UnisonError;
+use unison::network::{
+    MemoryTransport, NetworkError, ProtocolClient, ProtocolClientTrait, ProtocolServer,
+    UnisonClient, UnisonServer, UnisonServerExt,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GetUser {
+    id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct User {
+    name: String,
+}
+
+fn build_server() -> ProtocolServer {
+    let mut server = ProtocolServer::new().with_call_handler("withdraw", |payload| async move {
+        let amount = payload["amount"].as_u64().unwrap_or(0);
+        Err::<Value, _>(NetworkError::Remote(UnisonError::with_details(
+            "INSUFFICIENT_FUNDS",
+            "balance is too low",
+            json!({ "requested": amount, "available": 10 }),
+        )))
+    });
+    server.register_typed_handler("get_user", |request: GetUser| async move {
+        match request.id {
+            1 => Ok(User {
+                name: "alice".into(),
+            }),
+            _ => Err(UnisonError::new("USER_NOT_FOUND", "no such user")),
+        }
+    });
+    server
+}
+
+/// ハンドラーが返したエラーコードで分岐できる
+async fn assert_remote_errors(client: &ProtocolClient) -> Result<()> {
+    let error = UnisonClient::call(client, "withdraw", json!({ "amount": 50 }))
+        .await
+        .unwrap_err();
+    let NetworkError::Remote(error) = error else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!(error.code, "INSUFFICIENT_FUNDS");
+    assert_eq!(error.message, "balance is too low");
+    assert_eq!(error.details.unwrap()["requested"], 50);
+
+    let user: User = ProtocolClientTrait::call(client, "get_user", GetUser { id: 1 }).await?;
+    assert_eq!(user.name, "alice");
+    let error = ProtocolClientTrait::call::<_, User>(client, "get_user", GetUser { id: 2 })
+        .await
+        .unwrap_err();
+    match error.downcast_ref::<NetworkError>() {
+        Some(NetworkError::Remote(error)) => assert_eq!(error.code, "USER_NOT_FOUND"),
+        _ => panic!("unexpected error: {error}"),
+    }
+
+    // プロトコルのエラーは数値のコードで届く
+    let error = UnisonClient::call(client, "missing", Value::Null)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, NetworkError::Remote(e) if e.code == "404"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_remote_error_codes_over_quic() -> Result<()> {
+    let addr = "[::1]:18484";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    assert_remote_errors(&client).await?;
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_remote_error_codes_over_memory() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server())?;
+    let client = transport.connect().await?;
+    assert_remote_errors(&client).await
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    CallOptions, MemoryTransport, NetworkError, ProtocolClient, ProtocolServer, RequestContext,
+    UnisonClient, UnisonServer,
+};
+
+fn build_server() -> ProtocolServer {
+    ProtocolServer::new()
+        .with_context_handler("inspect", |context: RequestContext, payload| async move {
+            Ok::<_, NetworkError>(json!({
+                "method": context.method,
+                "connection": context.connection_id,
+                "remote": context.remote_address.map(|addr| addr.to_string()),
+                "stream": context.stream_id,
+                "features": context.features,
+                "trace": context.metadata("x-trace-id"),
+                "has_deadline": context.deadline.is_some(),
+                "payload": payload,
+            }))
+        })
+        // 従来のハンドラーからも同じ情報を参照できる
+        .with_call_handler("legacy", |_payload| async move {
+            let context = RequestContext::current("legacy");
+            Ok::<_, NetworkError>(json!({ "connection": context.connection_id }))
+        })
+}
+
+fn options() -> CallOptions {
+    CallOptions::default()
+        .with_timeout(Duration::from_secs(5))
+        .with_metadata("x-trace-id", "trace-1")
+}
+
+#[tokio::test]
+async fn test_request_context_over_quic() -> Result<()> {
+    let addr = "[::1]:18488";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let context = client
+        .call_with_options("inspect", json!({ "n": 1 }), options())
+        .await?;
+    assert_eq!(context["method"], "inspect");
+    assert!(context["connection"].is_u64());
+    assert!(context["remote"].as_str().unwrap().starts_with("[::1]:"));
+    assert!(context["stream"].is_u64());
+    assert!(!context["features"].as_array().unwrap().is_empty());
+    assert_eq!(context["trace"], "trace-1");
+    assert_eq!(context["has_deadline"], true);
+    assert_eq!(context["payload"], json!({ "n": 1 }));
+
+    let legacy = UnisonClient::call(&client, "legacy", Value::Null).await?;
+    assert_eq!(legacy["connection"], context["connection"]);
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// ハンドシェイクのない接続では接続IDとメタデータのみ
+#[tokio::test]
+async fn test_request_context_over_memory() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server())?;
+    let client = transport.connect().await?;
+
+    let context = client
+        .call_with_options("inspect", Value::Null, options())
+        .await?;
+    assert!(context["connection"].is_u64());
+    assert_eq!(context["remote"], Value::Null);
+    assert_eq!(context["stream"], Value::Null);
+    assert_eq!(context["features"], json!([]));
+    assert_eq!(context["trace"], "trace-1");
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use unison::network::{
+    CallOptions, NetworkError, ProtocolClient, ProtocolClientTrait, ProtocolError, ProtocolServer,
+    RetryPolicy, UnisonClient, UnisonServer,
+};
+
+/// `failures`回まで`code`で失敗し、その後は呼び出された回数を返すハンドラーを登録する
+fn flaky(
+    server: ProtocolServer,
+    method: &str,
+    code: i32,
+    failures: u32,
+    calls: Arc<AtomicU32>,
+) -> ProtocolServer {
+    server.with_call_handler(method, move |_| {
+        let calls = Arc::clone(&calls);
+        async move {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if call <= failures {
+                Err(ProtocolError::new(code, "Try again"))
+            } else {
+                Ok(json!({ "calls": call }))
+            }
+        }
+    })
+}
+
+fn policy() -> RetryPolicy {
+    RetryPolicy::new()
+        .with_max_attempts(3)
+        .with_initial_backoff(Duration::from_millis(10))
+}
+
+/// サーバーが`UNAVAILABLE`で拒否した呼び出しは、冪等でないメソッドも再送される
+#[tokio::test]
+async fn test_rejected_calls_are_retried_until_max_attempts() -> Result<()> {
+    let addr = "[::1]:18503";
+    let recovering = Arc::new(AtomicU32::new(0));
+    let down = Arc::new(AtomicU32::new(0));
+    let server = flaky(
+        ProtocolServer::new(),
+        "recovering",
+        ProtocolError::UNAVAILABLE,
+        2,
+        Arc::clone(&recovering),
+    );
+    let mut server = flaky(
+        server,
+        "down",
+        ProtocolError::UNAVAILABLE,
+        u32::MAX,
+        Arc::clone(&down),
+    );
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?.with_retry_policy(policy());
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let response = UnisonClient::call(&client, "recovering", Value::Null).await?;
+    assert_eq!(response["calls"], 3);
+
+    // 最大試行回数に達すると最後のエラーを返す
+    let error = UnisonClient::call(&client, "down", Value::Null)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, NetworkError::Remote(ref remote) if remote.code == "503"));
+    assert_eq!(down.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+/// 処理されたか分からないタイムアウトは、冪等と指定したメソッドだけ再送される
+#[tokio::test]
+async fn test_timeouts_are_retried_only_for_idempotent_methods() -> Result<()> {
+    let addr = "[::1]:18504";
+    let create = Arc::new(AtomicU32::new(0));
+    let get = Arc::new(AtomicU32::new(0));
+    let lookup = Arc::new(AtomicU32::new(0));
+    let server = flaky(
+        ProtocolServer::new(),
+        "create",
+        ProtocolError::DEADLINE_EXCEEDED,
+        1,
+        Arc::clone(&create),
+    );
+    let server = flaky(
+        server,
+        "get",
+        ProtocolError::DEADLINE_EXCEEDED,
+        1,
+        Arc::clone(&get),
+    );
+    let mut server = flaky(
+        server,
+        "lookup",
+        ProtocolError::DEADLINE_EXCEEDED,
+        1,
+        Arc::clone(&lookup),
+    );
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client =
+        ProtocolClient::new_default()?.with_retry_policy(policy().with_idempotent_method("lookup"));
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    assert!(
+        ProtocolClientTrait::call::<_, Value>(&client, "create", ())
+            .await
+            .is_err()
+    );
+    assert_eq!(create.load(Ordering::SeqCst), 1);
+
+    // 生成したクライアントは`idempotent=#true`のメソッドを`call_idempotent`で呼び出す
+    let response: Value = client.call_idempotent("get", ()).await?;
+    assert_eq!(response["calls"], 2);
+
+    // ポリシーで冪等と指定したメソッド
+    let response: Value = ProtocolClientTrait::call(&client, "lookup", ()).await?;
+    assert_eq!(response["calls"], 2);
+
+    // 呼び出しごとに冪等と指定する
+    let response = client
+        .call_with_options(
+            "create",
+            Value::Null,
+            CallOptions::default().with_idempotent(true),
+        )
+        .await?;
+    assert_eq!(response["calls"], 2);
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{NetworkError, ProtocolClient, ProtocolServer, UnisonClient, UnisonServer};
+
+fn current_thread_name() -> Value {
+    json!(std::thread::current().name().unwrap_or_default())
+}
+
+/// IO専用のランタイムを指定すると、接続とハンドラーの処理がそのランタイムで実行されることを確認
+#[test]
+fn test_server_and_client_run_on_injected_runtime() -> Result<()> {
+    let io = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .thread_name("unison-io")
+        .enable_all()
+        .build()?;
+    let app = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("app")
+        .enable_all()
+        .build()?;
+
+    let addr = "[::1]:18464";
+    let result: Result<()> = app.block_on(async {
+        let mut server = ProtocolServer::new()
+            .with_runtime(io.handle().clone())
+            .with_call_handler("thread", |_| async move {
+                Ok::<_, NetworkError>(current_thread_name())
+            });
+        tokio::spawn(async move { server.listen(addr).await });
+
+        let mut client = ProtocolClient::new_default()?.with_runtime(io.handle().clone());
+        assert!(client.runtime().is_some());
+        client
+            .wait_until_ready(addr, Duration::from_secs(5))
+            .await?;
+
+        let thread = UnisonClient::call(&client, "thread", Value::Null).await?;
+        assert!(
+            thread.as_str().unwrap().starts_with("unison-io"),
+            "handler ran on {}",
+            thread
+        );
+        UnisonClient::disconnect(&mut client).await?;
+        Ok(())
+    });
+    result?;
+
+    app.shutdown_timeout(Duration::from_secs(1));
+    io.shutdown_timeout(Duration::from_secs(1));
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolServer, QuicServer, SCHEMA_CHANGED_EVENT_METHOD,
+    SchemaChange, SchemaReloadError, ServerEvent, UnisonClient,
+};
+
+fn schema(version: &str, methods: &[&str]) -> String {
+    let methods: String = methods
+        .iter()
+        .map(|method| format!("        method \"{method}\"\n"))
+        .collect();
+    format!(
+        "protocol \"Greeter\" version=\"{version}\" {{\n    service \"Greeter\" {{\n{methods}    }}\n}}\n"
+    )
+}
+
+/// 稼働中のサーバーのスキーマを読み込み直すと、削除されたメソッドを拒否して接続中のクライアントへ通知する
+#[tokio::test]
+async fn test_reload_schema_on_running_server() -> Result<()> {
+    let addr = "[::1]:18524";
+    let v1 = unison::parser::SchemaParser::new().parse(&schema("1.0.0", &["greet", "legacy"]))?;
+    let echo = |payload: Value| async move { Ok::<_, NetworkError>(payload) };
+    let server = ProtocolServer::new()
+        .with_schema_fingerprint(&v1)
+        .with_call_handler("greet", echo)
+        .with_call_handler("legacy", echo);
+    let mut events = server.events();
+    let server = Arc::new(server);
+    let mut quic_server = QuicServer::new(Arc::clone(&server));
+    quic_server.bind(addr).await?;
+    tokio::spawn(async move { quic_server.start().await });
+
+    let mut client = ProtocolClient::new_default()?.with_schema_fingerprint(&v1);
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    UnisonClient::call(&client, "legacy", json!({})).await?;
+
+    // 解析できないスキーマでは何も変わらない
+    let error = server.reload_schema("protocol {").await.unwrap_err();
+    assert!(matches!(error, SchemaReloadError::Parse(_)), "{error}");
+    assert_eq!(server.schema_hash(), Some(v1.fingerprint()));
+
+    let change = server
+        .reload_schema(&schema("2.0.0", &["greet", "status"]))
+        .await?;
+    assert_eq!(change.previous, Some(v1.fingerprint()));
+    assert_eq!(change.version.as_deref(), Some("2.0.0"));
+    assert_eq!(change.added_methods, ["status"]);
+    assert_eq!(change.removed_methods, ["legacy"]);
+    assert_eq!(server.schema_hash(), Some(change.fingerprint.clone()));
+
+    // 接続中のクライアントに変更が届く
+    let event = tokio::time::timeout(Duration::from_secs(5), client.receive_event()).await??;
+    assert_eq!(event.method, SCHEMA_CHANGED_EVENT_METHOD);
+    let announced: SchemaChange = serde_json::from_value(event.payload_as_value()?)?;
+    assert_eq!(announced, change);
+
+    // 削除されたメソッドはハンドラーが残っていても拒否される
+    let error = UnisonClient::call(&client, "legacy", json!({}))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, NetworkError::Remote(e) if e.code == "404"));
+    assert_eq!(
+        UnisonClient::call(&client, "greet", json!({ "n": 1 })).await?,
+        json!({ "n": 1 })
+    );
+
+    let reloaded = loop {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await?
+            .unwrap();
+        if let ServerEvent::SchemaReloaded {
+            removed_methods, ..
+        } = event
+        {
+            break removed_methods;
+        }
+    };
+    assert_eq!(reloaded, ["legacy"]);
+
+    // 古いスキーマのクライアントは新しく接続できない
+    UnisonClient::disconnect(&mut client).await?;
+    let mut outdated = ProtocolClient::new_default()?.with_schema_fingerprint(&v1);
+    assert!(UnisonClient::connect(&mut outdated, addr).await.is_err());
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolClientTrait, ProtocolServer, UnisonClient, UnisonServer,
+    UnisonServerExt,
+};
+
+/// QUICでサーバーからのストリームを受信し、並行するストリームが混ざらないことを確認
+#[tokio::test]
+async fn test_server_streaming_over_quic() -> Result<()> {
+    let addr = "[::1]:18469";
+    let mut server = ProtocolServer::new();
+    // トレイト経由で登録したハンドラーも呼び出せる
+    UnisonServerExt::register_stream_handler(&mut server, "count", |payload| {
+        let n = payload["n"].as_u64().unwrap_or(0);
+        Box::pin(futures_util::stream::iter(
+            (0..n).map(|i| Ok(json!({ "i": i }))),
+        ))
+    });
+    server
+        .register_stream_handler("fail_after", |payload| async move {
+            let n = payload["n"].as_u64().unwrap_or(0);
+            Ok(futures_util::stream::iter((0..=n).map(move |i| {
+                if i < n {
+                    Ok(json!(i))
+                } else {
+                    Err(anyhow::anyhow!("boom"))
+                }
+            })))
+        })
+        .await;
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let (first, second) = tokio::join!(
+        ProtocolClientTrait::stream::<Value, Value>(&client, "count", json!({ "n": 50 })),
+        ProtocolClientTrait::stream::<Value, Value>(&client, "count", json!({ "n": 3 })),
+    );
+    let (first, second): (Vec<Value>, Vec<Value>) = tokio::join!(
+        first?.map(Result::unwrap).collect(),
+        second?.map(Result::unwrap).collect(),
+    );
+    assert_eq!(first.len(), 50);
+    assert_eq!(first[49], json!({ "i": 49 }));
+    assert_eq!(
+        second,
+        vec![json!({ "i": 0 }), json!({ "i": 1 }), json!({ "i": 2 })]
+    );
+
+    // エラーはStreamErrorとして届き、ストリームが終わる
+    let items: Vec<Result<Value>> =
+        ProtocolClientTrait::stream::<Value, Value>(&client, "fail_after", json!({ "n": 2 }))
+            .await?
+            .collect()
+            .await;
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[1].as_ref().unwrap(), &json!(1));
+    assert!(items[2].as_ref().unwrap_err().to_string().contains("boom"));
+
+    let missing: Vec<Result<Value>> =
+        ProtocolClientTrait::stream::<Value, Value>(&client, "missing", Value::Null)
+            .await?
+            .collect()
+            .await;
+    assert_eq!(missing.len(), 1);
+    assert!(missing[0].is_err());
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// `process_message`はストリーム要求に空の`StreamEnd`を返さない
+#[tokio::test]
+async fn test_process_message_rejects_stream_requests() -> Result<()> {
+    let mut server = ProtocolServer::new();
+    UnisonServerExt::register_stream_handler(&mut server, "count", |_| {
+        Box::pin(futures_util::stream::empty::<Result<Value, NetworkError>>())
+    });
+    let request = unison::network::ProtocolMessage::new_with_json(
+        1,
+        "count".into(),
+        unison::network::MessageType::Stream,
+        Value::Null,
+    )?;
+    let response = server.process_message(request).await?;
+    assert_eq!(response.msg_type, unison::network::MessageType::Error);
+    Ok(())
+}
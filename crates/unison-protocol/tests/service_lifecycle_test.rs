@@ -0,0 +1,149 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolServer, QuicServer, ReconnectPolicy, RestartPolicy,
+    SERVICES_METHOD, ServiceConfig, ServiceState, ServiceStatus, SupervisionConfig, SystemStream,
+    UnisonClient, UnisonClientExt, UnisonService,
+};
+
+/// 起動に失敗したサービスはバックオフ後に再起動され、停止の要求で処理を終えて`Stopped`になる
+#[tokio::test]
+async fn test_supervised_service_restarts_and_drains() -> Result<()> {
+    let addr = "[::1]:18506";
+    // クライアントが開いたストリームをサービスの作成に渡す
+    let (streams_tx, streams_rx) = mpsc::unbounded_channel();
+    let server = Arc::new(ProtocolServer::new().with_system_stream_handler(
+        "attach",
+        move |_, stream| {
+            let streams_tx = streams_tx.clone();
+            Box::pin(async move {
+                let _ = streams_tx.send(stream);
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+                Ok(())
+            })
+        },
+    ));
+    let lifecycle = server.service_lifecycle().clone();
+    let mut events = lifecycle.events();
+
+    let streams_rx = Arc::new(Mutex::new(streams_rx));
+    let attempts = Arc::new(AtomicU32::new(0));
+    let backoff = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(10),
+        jitter: 0.0,
+        ..ReconnectPolicy::default()
+    };
+    server.supervise_service(
+        "chat",
+        SupervisionConfig::new(RestartPolicy::OnFailure(backoff))
+            .with_health_check_interval(Duration::from_millis(100)),
+        move || {
+            let streams_rx = Arc::clone(&streams_rx);
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if attempt == 0 {
+                    return Err(NetworkError::Protocol("not ready".into()));
+                }
+                let stream = streams_rx
+                    .lock()
+                    .await
+                    .recv()
+                    .await
+                    .ok_or_else(|| NetworkError::Protocol("server closed".into()))?;
+                let config = ServiceConfig {
+                    service_name: "chat".into(),
+                    heartbeat_interval: None,
+                    ..ServiceConfig::default()
+                };
+                Ok(UnisonService::new(config, stream))
+            }
+        },
+    )?;
+    // 動作中のサービスと同じ名前では監督を始められない
+    assert!(
+        server
+            .supervise_service("chat", SupervisionConfig::default(), || async {
+                Err(NetworkError::Protocol("unused".into()))
+            })
+            .is_err()
+    );
+
+    let mut quic_server = QuicServer::new(Arc::clone(&server));
+    quic_server.bind(addr).await?;
+    tokio::spawn(async move { quic_server.start().await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let mut stream = client.start_system_stream("attach", json!({})).await?;
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        lifecycle.wait_for("chat", ServiceState::Running),
+    )
+    .await?;
+
+    // 管理用のメソッドで状態と再起動の回数を確認できる
+    let statuses: Vec<ServiceStatus> =
+        serde_json::from_value(UnisonClient::call(&client, SERVICES_METHOD, Value::Null).await?)?;
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].state, ServiceState::Running);
+    assert_eq!(statuses[0].restart_policy, "on_failure");
+    assert_eq!(statuses[0].restarts, 1);
+    assert!(
+        statuses[0]
+            .last_error
+            .as_deref()
+            .unwrap()
+            .contains("not ready")
+    );
+    server
+        .handle_service_request("chat", "ping", Value::Null)
+        .await?;
+
+    server.stop_service("chat").await?;
+    assert_eq!(lifecycle.state("chat"), Some(ServiceState::Stopped));
+    assert!(
+        server
+            .handle_service_request("chat", "ping", Value::Null)
+            .await
+            .is_err()
+    );
+    assert!(server.stop_service("missing").await.is_err());
+
+    // クライアントには健全性の確認の後に終了通知が届く
+    let shutdown = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let message = stream.receive().await?;
+            if message["data"]["type"] == "service_shutdown" {
+                return Ok::<_, NetworkError>(message);
+            }
+        }
+    })
+    .await??;
+    assert_eq!(shutdown["data"]["service"], "chat");
+
+    let transitions: Vec<_> = events
+        .by_ref()
+        .take(5)
+        .map(|event| (event.previous, event.current))
+        .collect()
+        .await;
+    use ServiceState::*;
+    assert_eq!(
+        transitions,
+        vec![
+            (None, Starting),
+            (Some(Starting), Starting),
+            (Some(Starting), Running),
+            (Some(Running), Draining),
+            (Some(Draining), Stopped),
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolClientTrait, ProtocolServer, STATS_METHOD,
+    ServiceStats, UnisonClient, UnisonServer,
+};
+
+/// メソッド・ストリームごとの統計をサーバーと組み込みメソッドの両方から取得できる
+#[tokio::test]
+async fn test_per_method_and_stream_stats() -> Result<()> {
+    let addr = "[::1]:18485";
+    let mut server = ProtocolServer::new()
+        .with_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        )
+        .with_call_handler("fail", |_| async move {
+            Err::<Value, _>(NetworkError::Protocol("boom".into()))
+        });
+    server
+        .register_stream_handler("count", |payload| async move {
+            let n = payload["n"].as_u64().unwrap_or(0);
+            Ok(futures_util::stream::iter((0..n).map(|i| Ok(json!(i)))))
+        })
+        .await;
+    let recorder = server.stats_recorder().clone();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    for _ in 0..3 {
+        UnisonClient::call(&client, "echo", json!({ "text": "hello" })).await?;
+    }
+    assert!(UnisonClient::call(&client, "fail", Value::Null).await.is_err());
+    let items: Vec<Result<Value>> =
+        ProtocolClientTrait::stream::<Value, Value>(&client, "count", json!({ "n": 4 }))
+            .await?
+            .collect()
+            .await;
+    assert_eq!(items.len(), 4);
+
+    let echo = recorder.method("echo").unwrap();
+    assert_eq!((echo.calls, echo.errors), (3, 0));
+    assert!(echo.bytes_in > 0 && echo.bytes_out > 0);
+    assert!(echo.p99_latency_ms >= echo.p50_latency_ms);
+    assert_eq!(recorder.method("fail").unwrap().errors, 1);
+
+    // ストリームはクライアントが受信し終えた後にサーバー側で閉じられる
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let stats: ServiceStats =
+        serde_json::from_value(UnisonClient::call(&client, STATS_METHOD, Value::Null).await?)?;
+    assert_eq!(stats.methods["echo"].calls, 3);
+    assert_eq!(stats.errors_count, 1);
+    let count = &stats.streams["count"];
+    assert_eq!((count.opened, count.active, count.items), (1, 0, 4));
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
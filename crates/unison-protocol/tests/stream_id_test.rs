@@ -0,0 +1,107 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{
+    ProtocolClient, ProtocolServer, StreamIdConfig, StreamInitiator, SystemStream, UnisonClient,
+    UnisonClientExt, UnisonServer, UnisonServerExt, stream_epoch,
+};
+
+/// サーバー側で見えたストリームIDを返す
+fn build_server() -> ProtocolServer {
+    let mut server = ProtocolServer::new().with_system_stream_handler("id", |_, mut stream| {
+        Box::pin(async move {
+            let stream_id = stream.get_handle().stream_id;
+            stream.send(json!(stream_id)).await
+        })
+    });
+    server.register_handler("echo", Ok);
+    server
+}
+
+/// クライアントが開始したストリームは奇数で、接続ごとに別のエポックを持つ
+#[tokio::test]
+async fn test_stream_ids_are_partitioned_per_session() -> Result<()> {
+    let addr = "[::1]:18520";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let mut stream = client.start_system_stream("id", json!({})).await?;
+        let seen = stream.receive().await?;
+        assert_eq!(seen, json!(stream.get_handle().stream_id));
+        ids.push(stream.get_handle().stream_id);
+    }
+    assert!(
+        ids.iter()
+            .all(|id| StreamInitiator::of(*id) == StreamInitiator::Client)
+    );
+    assert_ne!(ids[0], ids[1]);
+    assert_eq!(stream_epoch(ids[0]), stream_epoch(ids[1]));
+    UnisonClient::disconnect(&mut client).await?;
+
+    // 再接続後は別のエポックから割り当てる
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let stream = client.start_system_stream("id", json!({})).await?;
+    assert_ne!(
+        stream_epoch(stream.get_handle().stream_id),
+        stream_epoch(ids[0])
+    );
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// 規則に反するIDのストリームはプロトコルエラーで拒否される
+#[tokio::test]
+async fn test_invalid_stream_id_is_rejected() -> Result<()> {
+    let addr = "[::1]:18521";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    // 旧来の連番は1, 2, ...と割り当てるため、2つ目が偶数になる
+    let mut client = ProtocolClient::new_default()?.with_stream_ids(StreamIdConfig::sequential());
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let mut first = client.start_system_stream("id", json!({})).await?;
+    assert_eq!(first.receive().await?, json!(1));
+
+    let mut second = client.start_system_stream("id", json!({})).await?;
+    let error = second.receive().await.unwrap_err();
+    assert!(error.to_string().contains("code 409"), "{error}");
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// 単発のリクエストのIDも同じ規則で検証される
+#[tokio::test]
+async fn test_invalid_request_id_is_rejected() -> Result<()> {
+    let addr = "[::1]:18526";
+    let mut server = build_server();
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?.with_stream_ids(StreamIdConfig::sequential());
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    assert_eq!(
+        UnisonClient::call(&client, "echo", json!(1)).await?,
+        json!(1)
+    );
+
+    let error = UnisonClient::call(&client, "echo", json!(2))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("code 409"), "{error}");
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::json;
+use unison::network::{MemoryTransport, ProtocolServer};
+
+async fn build_server() -> ProtocolServer {
+    let server = ProtocolServer::new();
+    server
+        .register_resumable_stream_handler("count", |payload, offset| async move {
+            let to = payload["to"].as_u64().unwrap_or(0);
+            Ok(futures_util::stream::iter(
+                (offset..to).map(|n| Ok(json!(n))),
+            ))
+        })
+        .await;
+    server
+}
+
+/// トークンの発行間隔の途中で中断しても、重複なく続きから受信できる
+#[tokio::test]
+async fn test_resume_between_token_intervals() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server().await)?;
+    let client = transport.connect().await?;
+
+    // 既定の発行間隔（16件）に満たない5件で中断する
+    let mut stream = client
+        .stream_resumable::<_, u64>("count", json!({ "to": 40 }))
+        .await?;
+    let mut received = Vec::new();
+    while received.len() < 5 {
+        received.push(stream.next().await.unwrap()?);
+    }
+    let token = stream.resume_token().unwrap();
+    assert_eq!(token.sequence, 5);
+    drop(stream);
+
+    let mut resumed = client.resume_stream::<u64>(token).await?;
+    while let Some(item) = resumed.next().await {
+        received.push(item?);
+        // 発行済みのトークンを受け取った後も受信位置に追従する
+        assert_eq!(
+            resumed.resume_token().unwrap().sequence,
+            received.len() as u64
+        );
+    }
+    assert_eq!(received, (0..40).collect::<Vec<_>>());
+    Ok(())
+}
@@ -89,3 +89,28 @@ enum "Status" {
     assert_eq!(enum_def.values.len(), 4);
     assert_eq!(enum_def.values[0], "pending");
 }
+
+#[test]
+fn test_method_timeout() {
+    let schema_str = r#"
+protocol "TestProtocol" version="1.0.0" {
+    service "TestService" {
+        method "slowMethod" timeout_ms=1500 {
+            request {
+                field "id" type="int"
+            }
+        }
+        method "defaultMethod" {
+        }
+    }
+}
+"#;
+
+    let schema = SchemaParser::new().parse(schema_str).unwrap();
+    let methods = &schema.protocol.unwrap().services[0].methods;
+    assert_eq!(
+        methods[0].timeout(),
+        Some(std::time::Duration::from_millis(1500))
+    );
+    assert_eq!(methods[1].timeout(), None);
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "tower")]
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use tower::{ServiceBuilder, ServiceExt};
+use unison::network::{
+    ClientService, NetworkError, ProtocolClient, ProtocolError, ProtocolServer, Request, Response,
+    UnisonClient, UnisonServer,
+};
+
+/// towerのミドルウェアを重ねたサービスをハンドラーとして登録し、クライアントもtower経由で呼び出す
+#[tokio::test]
+async fn test_tower_services_on_both_sides() -> Result<()> {
+    let addr = "[::1]:18523";
+    let handler = ServiceBuilder::new().concurrency_limit(4).service_fn(
+        |request: Request<Value>| async move {
+            let delay = request.body["delay_ms"].as_u64().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            match request.body["n"].as_i64() {
+                Some(n) => Ok(Response::new(json!({ "doubled": n * 2 }))),
+                None => Err(ProtocolError::new(
+                    ProtocolError::INVALID_REQUEST,
+                    "n is required",
+                )),
+            }
+        },
+    );
+    let mut server = ProtocolServer::new().with_tower_service("double", handler);
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    let client = Arc::new(client);
+    let service = ServiceBuilder::new()
+        .timeout(Duration::from_millis(200))
+        .service(ClientService::<Value, Value>::new(Arc::clone(&client)));
+
+    let response = service
+        .clone()
+        .oneshot(Request::new("double", json!({ "n": 21 })))
+        .await
+        .unwrap();
+    assert_eq!(response.body["doubled"], 42);
+
+    // サービスのエラーはリモートのエラーとして届く
+    let error = service
+        .clone()
+        .oneshot(Request::new("double", json!({})))
+        .await
+        .unwrap_err();
+    match error.downcast::<NetworkError>() {
+        Ok(error) => match *error {
+            NetworkError::Remote(remote) => {
+                assert_eq!(remote.code, ProtocolError::INVALID_REQUEST.to_string())
+            }
+            other => panic!("unexpected error: {other}"),
+        },
+        Err(other) => panic!("unexpected error: {other}"),
+    }
+
+    // クライアント側のタイムアウトはtowerのエラーとして返る
+    let error = service
+        .oneshot(Request::new("double", json!({ "n": 1, "delay_ms": 1000 })))
+        .await
+        .unwrap_err();
+    assert!(error.is::<tower::timeout::error::Elapsed>(), "{error}");
+
+    let mut client = Arc::try_unwrap(client).ok().unwrap();
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
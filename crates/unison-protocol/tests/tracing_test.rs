@@ -0,0 +1,50 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolServer, TRACEPARENT_METADATA_KEY, TraceContext,
+    UnisonClient, UnisonServer, request_metadata,
+};
+
+/// クライアントのトレースコンテキストがサーバーへ伝わり、ハンドラーは同じトレースの子スパンで動く
+#[tokio::test]
+async fn test_trace_context_propagates_to_handler() -> Result<()> {
+    let addr = "[::1]:18513";
+    let mut server = ProtocolServer::new().with_call_handler("whoami", |_: Value| async move {
+        let received = request_metadata()
+            .get(TRACEPARENT_METADATA_KEY)
+            .cloned()
+            .unwrap_or_default();
+        let current = TraceContext::current().map(|trace| trace.to_string());
+        Ok::<_, NetworkError>(json!({ "received": received, "current": current }))
+    });
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    // 呼び出し元のトレースを指定しない場合も新しいトレースが送られる
+    let response = UnisonClient::call(&client, "whoami", Value::Null).await?;
+    let sent = TraceContext::parse(response["received"].as_str().unwrap()).unwrap();
+    let current = TraceContext::parse(response["current"].as_str().unwrap()).unwrap();
+    assert_eq!(current.trace_id, sent.trace_id);
+    assert_ne!(current.span_id, sent.span_id);
+
+    // 呼び出し元のトレースを引き継ぐ
+    let parent = TraceContext::new_root();
+    let metadata = HashMap::from([(TRACEPARENT_METADATA_KEY.to_string(), parent.to_string())]);
+    let response = client
+        .call_with_metadata("whoami", Value::Null, metadata)
+        .await?;
+    let sent = TraceContext::parse(response["received"].as_str().unwrap()).unwrap();
+    let current = TraceContext::parse(response["current"].as_str().unwrap()).unwrap();
+    assert_eq!(sent.trace_id, parent.trace_id);
+    assert_ne!(sent.span_id, parent.span_id);
+    assert_eq!(current.trace_id, parent.trace_id);
+
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
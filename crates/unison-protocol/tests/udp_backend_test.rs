@@ -0,0 +1,64 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolServer, QuicClient, UdpBackend, UdpSocketConfig,
+    UnisonClient, UnisonServer,
+};
+
+/// io_uringのソケットで待ち受けたサーバーと通信できることを確認
+///
+/// io_uringを使えない環境では標準ソケットにフォールバックするため、同じ結果になります。
+#[tokio::test]
+async fn test_quic_over_io_uring_backend() -> Result<()> {
+    let addr = "[::1]:18465";
+    let mut server = ProtocolServer::new()
+        .with_udp_backend(UdpBackend::io_uring())
+        .with_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        );
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let quic = QuicClient::new()?.with_udp_backend(UdpBackend::io_uring());
+    let mut client = ProtocolClient::new(quic);
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let payload = json!({ "data": "x".repeat(16 * 1024) });
+    for _ in 0..20 {
+        let response = UnisonClient::call(&client, "echo", payload.clone()).await?;
+        assert_eq!(response, payload);
+    }
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
+
+/// 送受信バッファを広げ、GSOを無効にしたソケットでも通信できることを確認
+#[tokio::test]
+async fn test_quic_with_tuned_socket() -> Result<()> {
+    let addr = "[::1]:18466";
+    let config = UdpSocketConfig::default()
+        .with_buffer_size(4 * 1024 * 1024)
+        .with_segmentation_offload(false);
+    let mut server = ProtocolServer::new()
+        .with_udp_config(config.clone())
+        .with_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        );
+    tokio::spawn(async move { server.listen(addr).await });
+
+    let quic = QuicClient::new()?.with_udp_config(config);
+    let mut client = ProtocolClient::new(quic);
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+
+    let payload = json!({ "data": "x".repeat(256 * 1024) });
+    let response = UnisonClient::call(&client, "echo", payload.clone()).await?;
+    assert_eq!(response, payload);
+    UnisonClient::disconnect(&mut client).await?;
+    Ok(())
+}
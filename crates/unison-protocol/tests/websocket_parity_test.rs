@@ -0,0 +1,81 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::network::{
+    NetworkError, ProtocolClient, ProtocolClientTrait, ProtocolServer, UnisonClient, UnisonServer,
+};
+
+/// QUICとWebSocketで共通のハンドラーを登録したサーバー
+async fn build_server() -> ProtocolServer {
+    let server = ProtocolServer::new()
+        .with_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        )
+        .with_call_handler("fail", |_| async move {
+            Err::<Value, _>(NetworkError::Protocol("boom".into()))
+        });
+    server
+        .register_stream_handler("count", |payload| async move {
+            let n = payload["n"].as_u64().unwrap_or(0);
+            Ok(futures_util::stream::iter(
+                (0..n).map(|i| Ok(json!({ "i": i }))),
+            ))
+        })
+        .await;
+    server
+}
+
+/// 同じ呼び出しを行い、結果を比較できる形で返す
+async fn run_calls(client: &ProtocolClient) -> Result<Vec<String>> {
+    let mut results = Vec::new();
+
+    let echoed = UnisonClient::call(client, "echo", json!({ "text": "hi", "n": [1, 2] })).await?;
+    results.push(echoed.to_string());
+
+    for method in ["fail", "missing"] {
+        let error = UnisonClient::call(client, method, Value::Null)
+            .await
+            .unwrap_err();
+        results.push(error.to_string());
+    }
+
+    let items: Vec<Value> =
+        ProtocolClientTrait::stream::<Value, Value>(client, "count", json!({ "n": 3 }))
+            .await?
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+    results.push(Value::Array(items).to_string());
+
+    Ok(results)
+}
+
+async fn connect_and_run(addr: &str) -> Result<Vec<String>> {
+    let mut server = build_server().await;
+    let listen_addr = addr.to_string();
+    tokio::spawn(async move { server.listen(&listen_addr).await });
+
+    let mut client = ProtocolClient::new_default()?;
+    client
+        .wait_until_ready(addr, Duration::from_secs(5))
+        .await?;
+    assert!(client.is_connected().await);
+    let results = run_calls(&client).await?;
+    client.disconnect().await?;
+    assert!(!client.is_connected().await);
+    Ok(results)
+}
+
+/// WebSocketで接続した場合もQUICと同じハンドラーが同じ結果を返すことを確認
+#[tokio::test]
+async fn test_websocket_matches_quic() -> Result<()> {
+    let quic = connect_and_run("[::1]:18462").await?;
+    let websocket = connect_and_run("ws://127.0.0.1:18463").await?;
+
+    assert_eq!(quic[0], r#"{"n":[1,2],"text":"hi"}"#);
+    assert_eq!(quic[3], r#"[{"i":0},{"i":1},{"i":2}]"#);
+    assert_eq!(quic, websocket);
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::time::Duration;
+use unison::core::UnisonMessage;
+use unison::network::{
+    MESSAGE_ID_METADATA_KEY, MemoryTransport, NetworkError, ProtocolClient, ProtocolError,
+    ProtocolServer, Request, Response, UnisonClient, UnisonServer, WireFormat,
+};
+
+fn build_server(format: WireFormat) -> ProtocolServer {
+    ProtocolServer::new()
+        .with_wire_format(format)
+        .with_call_handler(
+            "echo",
+            |payload| async move { Ok::<_, NetworkError>(payload) },
+        )
+        .with_request_handler("whoami", |request: Request<Value>| async move {
+            let id = request
+                .metadata(MESSAGE_ID_METADATA_KEY)
+                .unwrap_or_default();
+            Ok::<_, ProtocolError>(Response::new(json!({ "id": id })))
+        })
+}
+
+/// パケットとJSONのどちらの形式で送っても、相手の形式に関係なく呼び出せる
+#[tokio::test]
+async fn test_websocket_wire_formats_interoperate() -> Result<()> {
+    let formats = [
+        (WireFormat::Packet, WireFormat::Packet, 18481),
+        (WireFormat::Json, WireFormat::Packet, 18482),
+        (WireFormat::Packet, WireFormat::Json, 18483),
+    ];
+    for (server_format, client_format, port) in formats {
+        let url = format!("ws://127.0.0.1:{}", port);
+        let mut server = build_server(server_format);
+        let listen_url = url.clone();
+        tokio::spawn(async move { server.listen(&listen_url).await });
+
+        let mut client = ProtocolClient::new_default()?.with_wire_format(client_format);
+        client
+            .wait_until_ready(&url, Duration::from_secs(5))
+            .await?;
+        let echoed = UnisonClient::call(&client, "echo", json!({ "text": "hi" })).await?;
+        assert_eq!(
+            echoed["text"], "hi",
+            "{server_format:?} -> {client_format:?}"
+        );
+        client.disconnect().await?;
+    }
+    Ok(())
+}
+
+/// コアのUnisonMessageも他の呼び出しと同じ経路で送られる
+#[tokio::test]
+async fn test_send_core_message() -> Result<()> {
+    let transport = MemoryTransport::new();
+    let _server = transport.serve(build_server(WireFormat::Packet))?;
+    let client = transport.connect().await?;
+
+    let message = UnisonMessage::with_id("msg-1", "whoami", Value::Null);
+    let response = client.send_message(message).await?;
+    assert!(response.success);
+    assert_eq!(response.id, "msg-1");
+    assert_eq!(response.payload, Some(json!({ "id": "msg-1" })));
+
+    let response = client
+        .send_message(UnisonMessage::new("missing", Value::Null))
+        .await?;
+    assert!(!response.success);
+    assert!(response.error.is_some());
+    Ok(())
+}
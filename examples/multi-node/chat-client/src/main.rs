@@ -0,0 +1,52 @@
+use anyhow::Result;
+use chat_protocol::{ChatClient, HistoryRequest, PostRequest};
+use futures_util::StreamExt;
+use unison::network::{ProtocolClient, RetryPolicy};
+
+const DEFAULT_SERVER: &str = "[::1]:8080";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let server = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_SERVER.to_string());
+
+    // Rejected calls are retried; `stats` is also retried after connection errors
+    // because the schema marks it `idempotent=#true`
+    let mut client = ProtocolClient::new_default()?.with_retry_policy(RetryPolicy::new());
+    client.connect(&server).await?;
+    println!("🔗 Connected to {server}");
+
+    // Typed calls through the client generated from schema/chat.kdl
+    let chat = ChatClient::new(&client);
+    for text in ["Hello from Rust!", "Same schema, every node."] {
+        let posted = chat
+            .post(PostRequest {
+                room: "general".into(),
+                author: "chat-client".into(),
+                text: text.into(),
+            })
+            .await?;
+        println!(
+            "📤 #{} posted at {}",
+            posted.message.id, posted.message.posted_at
+        );
+    }
+
+    let stats = chat.stats(()).await?;
+    println!("📊 {} messages in {} rooms", stats.messages, stats.rooms);
+
+    let mut history = chat
+        .history(HistoryRequest {
+            room: "general".into(),
+            limit: Some(10),
+        })
+        .await?;
+    while let Some(item) = history.next().await {
+        let message = item?.message;
+        println!("📜 [{}] {}: {}", message.room, message.author, message.text);
+    }
+
+    client.disconnect().await?;
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use unison::codegen::{SchemaBuild, TypeScriptPackageOptions};
+
+fn main() -> anyhow::Result<()> {
+    // One schema, two outputs: Rust for the server and client crates (OUT_DIR),
+    // and an npm/JSR package for browsers and Deno (../ts-client).
+    SchemaBuild::new()
+        .with_schema("../schema/chat.kdl")
+        .with_typescript_package(
+            "../ts-client",
+            TypeScriptPackageOptions::new("@unison-examples/chat")
+                .with_description("Chat client generated from schema/chat.kdl"),
+        )
+        .run()
+}
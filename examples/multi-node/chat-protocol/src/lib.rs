@@ -0,0 +1,6 @@
+//! Types, method names and the `ChatClient` generated from `schema/chat.kdl`.
+//!
+//! Both `chat-server` and `chat-client` depend on this crate, so a change to the
+//! schema shows up as a compile error on whichever side falls out of date.
+
+unison::include_schema!();
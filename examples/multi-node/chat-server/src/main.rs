@@ -0,0 +1,124 @@
+use anyhow::{Result, bail};
+use chat_protocol::{
+    ChatMessage, ChatService, HistoryRequest, HistoryResponse, PostRequest, PostResponse,
+    StatsResponse, methods,
+};
+use futures_util::{Stream, StreamExt};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use unison::network::{ProtocolError, ProtocolServer, Request, Response, UnisonServer, WireFormat};
+
+const QUIC_ADDR: &str = "[::1]:8080";
+const WEBSOCKET_ADDR: &str = "ws://127.0.0.1:8081";
+
+/// In-memory chat rooms implementing the generated `ChatService` trait
+#[derive(Default)]
+struct ChatRooms {
+    messages: Mutex<Vec<ChatMessage>>,
+}
+
+impl ChatService for ChatRooms {
+    async fn post(&self, request: PostRequest) -> Result<PostResponse> {
+        if request.text.trim().is_empty() {
+            bail!("Message text must not be empty");
+        }
+        let mut messages = self.messages.lock().unwrap();
+        let message = ChatMessage {
+            id: messages.len() as i64 + 1,
+            room: request.room,
+            author: request.author,
+            text: request.text,
+            posted_at: chrono::Utc::now(),
+        };
+        messages.push(message.clone());
+        Ok(PostResponse { message })
+    }
+
+    async fn stats(&self, _request: ()) -> Result<StatsResponse> {
+        let messages = self.messages.lock().unwrap();
+        let rooms: HashSet<&str> = messages.iter().map(|m| m.room.as_str()).collect();
+        Ok(StatsResponse {
+            rooms: rooms.len() as i64,
+            messages: messages.len() as i64,
+        })
+    }
+
+    async fn history(
+        &self,
+        request: HistoryRequest,
+    ) -> Result<Box<dyn Stream<Item = Result<HistoryResponse>> + Send + Unpin>> {
+        let messages = self.messages.lock().unwrap();
+        let mut history: Vec<ChatMessage> = messages
+            .iter()
+            .filter(|m| m.room == request.room)
+            .cloned()
+            .collect();
+        if let Some(limit) = request.limit {
+            let skip = history.len().saturating_sub(limit.max(0) as usize);
+            history.drain(..skip);
+        }
+        Ok(Box::new(futures_util::stream::iter(
+            history
+                .into_iter()
+                .map(|message| Ok(HistoryResponse { message })),
+        )))
+    }
+}
+
+fn invalid_request(error: anyhow::Error) -> ProtocolError {
+    ProtocolError::new(ProtocolError::INVALID_REQUEST, error.to_string())
+}
+
+/// Wire the `ChatService` implementation to the method names generated from the schema
+async fn build_server(chat: Arc<ChatRooms>) -> ProtocolServer {
+    let post = Arc::clone(&chat);
+    let stats = Arc::clone(&chat);
+    let server = ProtocolServer::new()
+        .with_request_handler(methods::chat::POST, move |request: Request<PostRequest>| {
+            let chat = Arc::clone(&post);
+            async move {
+                chat.post(request.body)
+                    .await
+                    .map(Response::new)
+                    .map_err(invalid_request)
+            }
+        })
+        .with_request_handler(methods::chat::STATS, move |_: Request<()>| {
+            let chat = Arc::clone(&stats);
+            async move {
+                chat.stats(())
+                    .await
+                    .map(Response::new)
+                    .map_err(invalid_request)
+            }
+        });
+    server
+        .register_stream_handler(methods::chat::HISTORY, move |payload: Value| {
+            let chat = Arc::clone(&chat);
+            async move {
+                let request: HistoryRequest = serde_json::from_value(payload)?;
+                let history = chat.history(request).await?;
+                Ok(history.map(|item| Ok(serde_json::to_value(item?)?)))
+            }
+        })
+        .await;
+    server
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let chat = Arc::new(ChatRooms::default());
+
+    // The same handlers serve Rust clients over QUIC and the generated
+    // TypeScript package over WebSocket (one JSON text frame per message)
+    let mut quic = build_server(Arc::clone(&chat)).await;
+    let mut websocket = build_server(chat).await.with_wire_format(WireFormat::Json);
+
+    println!("💬 Chat server listening on {QUIC_ADDR} (QUIC) and {WEBSOCKET_ADDR} (WebSocket)");
+    println!("   Methods: {:?}", methods::chat::ALL);
+    tokio::try_join!(quic.listen(QUIC_ADDR), websocket.listen(WEBSOCKET_ADDR))?;
+
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
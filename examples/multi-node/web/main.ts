@@ -0,0 +1,25 @@
+// TypeScript node of the example, using the package generated into ../ts-client.
+//
+//   deno run --allow-net web/main.ts [ws://127.0.0.1:8081]
+
+import { ChatClient, WebSocketTransportImpl } from '../ts-client/src/index.ts';
+
+const transport = new WebSocketTransportImpl();
+await transport.connect(Deno.args[0] ?? 'ws://127.0.0.1:8081');
+const chat = new ChatClient(transport);
+
+const { message } = await chat.post({
+  room: 'general',
+  author: 'web',
+  text: 'Hello from TypeScript!',
+});
+console.log(`📤 #${message.id} posted at ${message.posted_at}`);
+
+const stats = await chat.stats();
+console.log(`📊 ${stats.messages} messages in ${stats.rooms} rooms`);
+
+for await (const { message } of chat.history({ room: 'general', limit: 10 })) {
+  console.log(`📜 [${message.room}] ${message.author}: ${message.text}`);
+}
+
+await transport.disconnect();